@@ -8,39 +8,81 @@ use std::{
         Arc,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use cpal::traits::StreamTrait;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use hashbrown::HashMap;
 use log::{debug, error, info, LevelFilter};
 use simplelog::{ColorChoice, CombinedLogger, Config, TermLogger, WriteLogger};
 use swyh_rs::{
+    dsp::chain::update_dsp_chain,
     enums::{
-        messages::MessageType,
+        messages::{MessageType, MqttCommand},
         streaming::{
             StreamingFormat::{Flac, Lpcm, Rf64, Wav},
             StreamingState,
         },
     },
-    globals::statics::{APP_VERSION, CLIENTS, CONFIG, MSGCHANNEL},
-    openhome::rendercontrol::{discover, Renderer, StreamInfo, WavData},
-    server::streaming_server::run_server,
+    globals::statics::{APP_VERSION, CLIENTS, CONFIG, MSGCHANNEL, RUN_RMS_MONITOR},
+    openhome::{
+        inventory::{export_renderers, known_renderers, record_renderer},
+        rendercontrol::{discover, Renderer, StreamInfo, WavData},
+        renderer_poller::{run_renderer_poller, PollIntervals},
+    },
+    server::{
+        client_api::request_disconnect,
+        pipe_output::start_pipe_output,
+        players_api::players_commands,
+        port_select::{select_server_port, start_igd_check},
+        query_params::stream_url,
+        rtp_sender::start_rtp_sender,
+        slimproto::start_slimproto_server,
+        ssdp_announcer::start_ssdp_announcer,
+        stream_check::{verify_stream, CHECK_SECS},
+        streaming_server::run_server,
+    },
     utils::{
+        announce::start_announcer,
         audiodevices::{
-            capture_output_audio, get_default_audio_output_device, get_output_audio_devices,
+            capture_network_audio, get_default_audio_output_device, get_output_audio_devices,
+            stream_channels,
+        },
+        bincommon::{
+            confirm_fallback_format, next_fallback_format, remember_pulled_format,
+            run_silence_injector, start_audio_capture, wait_for_ssdp_discovery, SsdpRefresh,
         },
-        bincommon::run_silence_injector,
         commandline::Args,
         configuration::Configuration,
-        local_ip_address::{get_interfaces, get_local_addr},
+        device_watcher::run_device_watcher,
+        fallback_audio::start_fallback_audio,
+        hooks::{hook_streaming_state, start_silence_hook},
+        local_ip_address::{get_interfaces, get_local_addr, get_serve_addr},
+        mixer::start_mix_source,
+        mqtt::{
+            publish_streaming_state, renderer_matches, run_mqtt_client, run_mqtt_rms_publisher,
+        },
+        network_source::open_network_source,
+        playback_monitor::start_playback_monitor,
         priority::raise_priority,
+        recorder::start_recorder,
+        renderer_stats::{record_play_result, record_reconnect, record_streaming},
+        resource_monitor::run_resource_monitor,
+        scripting::{script_renderer, start_script},
+        scrobble::start_scrobbler,
+        selftest::run_selftest,
+        sessions::{apply_session_loudness, find_session},
+        shutdown::graceful_shutdown,
+        track_detector::run_track_change_handler,
         ui_logger::ui_log,
+        update_check::start_update_check,
+        vu_trigger::{start_vu_trigger, vu_trigger_renderers, VuEvent},
     },
 };
 
 pub const APP_NAME: &str = "SWYH-RS-CLI";
+/// the old connection of a renderer that switches formats may end during this time
+const SWITCH_GRACE: Duration = Duration::from_secs(10);
 
 fn main() -> Result<(), i32> {
     let shutting_down = Arc::new(AtomicBool::new(false));
@@ -68,13 +110,22 @@ fn main() -> Result<(), i32> {
         }
         conf.clone()
     };
+    // print the effective config and exit
+    if let Some(command) = &args.config_command {
+        if command == "show" {
+            println!("{}", config.show_config());
+            std::process::exit(0);
+        }
+        eprintln!("Unknown config command {command}, use: swyh-rs-cli config show");
+        std::process::exit(1);
+    }
     if let Some(config_id) = &config.config_id {
         if !config_id.is_empty() {
-            println!("Loaded configuration -c {config_id}");
+            eprintln!("Loaded configuration -c {config_id}");
         }
     }
     config.monitor_rms = false;
-    println!("Current config: {config:?}");
+    eprintln!("Current config: {:?}", config.redacted());
     // set args loglevel
     if let Some(level) = args.log_level {
         config.log_level = level;
@@ -87,15 +138,26 @@ fn main() -> Result<(), i32> {
     let config_id = config.config_id.clone().unwrap();
     let logfilename = "log{}.txt".replace("{}", &config_id);
     let logfile = Path::new(&config.log_dir()).join(logfilename);
-    let _ = CombinedLogger::init(vec![
-        TermLogger::new(
+    // in a container only log to stdout, unless stdout is the stream output
+    let log_stdout = config.log_stdout && args.output.as_deref() != Some("-");
+    let _ = if log_stdout {
+        CombinedLogger::init(vec![TermLogger::new(
             loglevel,
             Config::default(),
-            simplelog::TerminalMode::Stderr,
-            ColorChoice::Auto,
-        ),
-        WriteLogger::new(loglevel, Config::default(), File::create(logfile).unwrap()),
-    ]);
+            simplelog::TerminalMode::Stdout,
+            ColorChoice::Never,
+        )])
+    } else {
+        CombinedLogger::init(vec![
+            TermLogger::new(
+                loglevel,
+                Config::default(),
+                simplelog::TerminalMode::Stderr,
+                ColorChoice::Auto,
+            ),
+            WriteLogger::new(loglevel, Config::default(), File::create(logfile).unwrap()),
+        ])
+    };
 
     info!(
         "{} V {} - Running on {}, {}, {} - Logging started.",
@@ -108,9 +170,26 @@ fn main() -> Result<(), i32> {
     if cfg!(debug_assertions) {
         ui_log("*W*W*>Running DEBUG build => log level set to DEBUG!");
     }
+    for message in &config.config_messages {
+        ui_log(message);
+    }
+    // report the problems of a config file that could not be read
+    for problem in &config.config_problems {
+        ui_log(&format!("*E*E*> {problem}"));
+    }
     if args.inject_silence.is_some() {
         config.inject_silence = args.inject_silence;
     }
+    // only use the audio devices of the selected audio host
+    if let Some(ref audio_host) = args.audio_host {
+        let audio_host = Some(audio_host.clone()).filter(|h| !h.eq_ignore_ascii_case("all"));
+        config.audio_host.clone_from(&audio_host);
+        CONFIG.write().audio_host = audio_host;
+    }
+    // check the installation without a renderer and exit
+    if args.selftest == Some(true) {
+        std::process::exit(run_selftest());
+    }
     // set soundsource index or name
     let audio_devices = get_output_audio_devices();
     if let Some(index) = args.sound_source_index {
@@ -204,12 +283,47 @@ fn main() -> Result<(), i32> {
             get_default_address(&mut config)
         }
     };
+    // the streaming server can be on another network than SSDP discovery
+    let serve_addr = get_serve_addr(local_addr, &networks);
+    // args: disconnect a streaming client of the running swyh-rs instance and exit
+    if let Some(client) = args.disconnect {
+        let port = args.server_port.or(config.server_port).unwrap_or(5901);
+        let server = format!("{serve_addr}:{port}");
+        return match request_disconnect(&server, &client) {
+            Ok(n) => {
+                ui_log(&format!(
+                    "Disconnected {n} connection(s) of {client} on {server}"
+                ));
+                Ok(())
+            }
+            Err(e) => {
+                ui_log(&format!(
+                    "*E*E*>Disconnecting {client} on {server} failed: {e}"
+                ));
+                Err(-3)
+            }
+        };
+    }
+    // use the stream of another swyh-rs instance as the audio source if configured
+    if args.network_source.is_some() {
+        config.network_source.clone_from(&args.network_source);
+    }
+    let network_source = config
+        .network_source
+        .as_deref()
+        .filter(|url| !url.is_empty())
+        .and_then(open_network_source);
+
     // we need to pass some audio config data to the play function
     let audio_cfg = audio_output_device.default_config().clone();
-    let wd = WavData {
-        sample_format: audio_cfg.sample_format(),
-        sample_rate: audio_cfg.sample_rate(),
-        channels: audio_cfg.channels(),
+    let wd = if let Some(source) = &network_source {
+        source.wav_data()
+    } else {
+        WavData {
+            sample_format: audio_cfg.sample_format(),
+            sample_rate: audio_cfg.sample_rate(),
+            channels: stream_channels(audio_cfg.channels()),
+        }
     };
 
     // raise process priority a bit to prevent audio stuttering under cpu load
@@ -218,15 +332,72 @@ fn main() -> Result<(), i32> {
     // the rms monitor channel
     let rms_channel: (Sender<Vec<f32>>, Receiver<Vec<f32>>) = unbounded();
 
-    // capture system audio
-    debug!("Try capturing system audio");
-    let stream: cpal::Stream;
-    if let Some(s) = capture_output_audio(&audio_output_device, rms_channel.0) {
-        stream = s;
-        stream.play().unwrap();
+    // capture system audio, unless capturing only when a client is connected
+    if args.capture_on_demand.is_some() {
+        config.capture_on_demand = args.capture_on_demand.unwrap();
+    }
+    if args.preroll_msec.is_some() {
+        config.preroll_msec = args.preroll_msec;
+        CONFIG.write().preroll_msec = args.preroll_msec;
+    }
+    if args.monitor_device.is_some() {
+        config.monitor_device.clone_from(&args.monitor_device);
+        CONFIG
+            .write()
+            .monitor_device
+            .clone_from(&args.monitor_device);
+    }
+    if args.rtp_destination.is_some() {
+        config.rtp_destination.clone_from(&args.rtp_destination);
+        CONFIG
+            .write()
+            .rtp_destination
+            .clone_from(&args.rtp_destination);
+    }
+    if args.record_dir.is_some() {
+        config.record_dir.clone_from(&args.record_dir);
+        CONFIG.write().record_dir.clone_from(&args.record_dir);
+    }
+    if args.track_gap_msec.is_some() {
+        config.track_gap_msec = args.track_gap_msec;
+        CONFIG.write().track_gap_msec = args.track_gap_msec;
+    }
+    if args.monitor_delay_msec.is_some() {
+        config.monitor_delay_msec = args.monitor_delay_msec;
+        CONFIG.write().monitor_delay_msec = args.monitor_delay_msec;
+    }
+    if args.balance.is_some() {
+        config.balance = args.balance;
+        CONFIG.write().balance = args.balance;
+    }
+    if args.trim_left_db.is_some() {
+        config.trim_left_db = args.trim_left_db;
+        CONFIG.write().trim_left_db = args.trim_left_db;
+    }
+    if args.trim_right_db.is_some() {
+        config.trim_right_db = args.trim_right_db;
+        CONFIG.write().trim_right_db = args.trim_right_db;
+    }
+    if let Some(bit_perfect) = args.bit_perfect {
+        config.bit_perfect = bit_perfect;
+        CONFIG.write().bit_perfect = bit_perfect;
+    }
+    if args.dither.is_some() {
+        config.dither = args.dither;
+        CONFIG.write().dither = args.dither;
+    }
+    let capture_on_demand = config.capture_on_demand && network_source.is_none();
+    let mut stream: Option<cpal::Stream> = None;
+    if let Some(source) = network_source {
+        capture_network_audio(source, rms_channel.0.clone());
+    } else if capture_on_demand {
+        ui_log("Audio capture will start when the first streaming client connects");
     } else {
-        ui_log("*E*E*> Could not capture audio ...Please check configuration.");
-        return Err(-2);
+        debug!("Try capturing system audio");
+        stream = start_audio_capture(&audio_output_device, rms_channel.0.clone());
+        if stream.is_none() {
+            return Err(-2);
+        }
     }
     // If silence injector is on, create a silence injector stream.
     let _silence_stream = if let Some(true) = CONFIG.read().inject_silence {
@@ -236,6 +407,42 @@ fn main() -> Result<(), i32> {
         None
     };
 
+    // play the outgoing audio on the local playback monitor device if configured
+    let _monitor_stream = start_playback_monitor(&wd);
+
+    // mix the secondary audio source into the captured audio if configured
+    let _mix_stream = start_mix_source(&wd);
+
+    // log the track boundaries detected from silence gaps and call the track change hook
+    run_track_change_handler();
+
+    // record to disk if a recording directory is configured
+    start_recorder(&wd);
+
+    // stream the fallback audio when the audio capture is lost, if configured
+    start_fallback_audio(&wd);
+
+    // mix the announcements of /api/announce into the stream
+    start_announcer(&wd);
+
+    // start and stop the renderers on audio activity if configured
+    start_vu_trigger(&wd);
+
+    // run the silence hooks on a prolonged silence if configured
+    start_silence_hook();
+
+    // run the automation script if configured
+    start_script();
+
+    // check for a newer release if configured
+    start_update_check();
+
+    // scrobble the pushed now-playing tracks if configured
+    start_scrobbler();
+
+    // send RTP to a unicast or multicast destination if configured
+    start_rtp_sender(&serve_addr, &wd);
+
     // set args ssdp_interval
     if let Some(mut minutes) = args.ssdp_interval_mins {
         if minutes < 0.5 {
@@ -243,6 +450,10 @@ fn main() -> Result<(), i32> {
         }
         config.ssdp_interval_mins = minutes;
     }
+    // set args known renderers
+    if args.import_renderers.is_some() {
+        config.known_renderers.clone_from(&args.import_renderers);
+    }
 
     // update config with new args
     let _ = config.update_config();
@@ -256,6 +467,68 @@ fn main() -> Result<(), i32> {
     let msg_tx = MSGCHANNEL.read().0.clone();
     let msg_rx = MSGCHANNEL.read().1.clone();
 
+    // connect to the MQTT broker if configured, it also gets the RMS levels
+    if let Some(mqtt) = &config.mqtt {
+        run_mqtt_client(mqtt, &msg_tx);
+        RUN_RMS_MONITOR.store(true, Ordering::Relaxed);
+        let rms_receiver = rms_channel.1.clone();
+        let _ = thread::Builder::new()
+            .name("mqtt_rms".into())
+            .stack_size(4 * 1024 * 1024)
+            .spawn(move || run_mqtt_rms_publisher(wd, &rms_receiver))
+            .unwrap();
+    }
+
+    // poll the transport state of the active renderers if enabled,
+    // the elapsed time and the volume are only shown in the GUI
+    let poll_intervals = PollIntervals::new(config.transport_poll_secs, None, None);
+    if poll_intervals.any() {
+        run_renderer_poller(poll_intervals, &msg_tx);
+    }
+
+    // measure the resource usage if enabled
+    if let Some(secs) = config.resource_monitor.interval_secs.filter(|s| *s > 0) {
+        run_resource_monitor(secs);
+    }
+
+    // watch for added or removed audio devices if enabled
+    if let Some(secs) = config.device_watch_secs.filter(|s| *s > 0) {
+        run_device_watcher(secs, &msg_tx);
+    }
+
+    // a session preset sets the renderers and the format, unless they are given as args
+    let session = if let Some(name) = &args.session {
+        let Some(session) = find_session(&config, name) else {
+            error!("Session {name} not found");
+            return Err(-4);
+        };
+        ui_log(&format!("Using session {}", session.name));
+        let members: Vec<String> = session
+            .renderers
+            .iter()
+            .map(|m| m.renderer.clone())
+            .collect();
+        if args.player_ip.is_none() {
+            args.player_ip = members.first().cloned();
+        }
+        if args.active_players.is_none() && !members.is_empty() {
+            args.active_players = Some(members);
+        }
+        if args.streaming_format.is_none() {
+            args.streaming_format = session.streaming_format;
+        }
+        if args.bits_per_sample.is_none() {
+            args.bits_per_sample = session.bits_per_sample;
+        }
+        // the capture is running, so the DSP chain is rebuilt
+        if apply_session_loudness(&mut CONFIG.write(), &session) {
+            update_dsp_chain();
+        }
+        Some(session)
+    } else {
+        None
+    };
+
     let mut renderers: Vec<Renderer> = Vec::new();
     let mut serve_only = args.serve_only.unwrap_or(false);
     // if only serving: no ssdp discovery
@@ -266,10 +539,13 @@ fn main() -> Result<(), i32> {
         ui_log("Starting SSDP discovery");
         let ssdp_int = config.ssdp_interval_mins;
         let ssdp_tx = msg_tx.clone();
+        let export_file = args.export_renderers.clone();
         let _ = thread::Builder::new()
             .name("ssdp_updater".into())
             .stack_size(4 * 1024 * 1024)
-            .spawn(move || run_ssdp_updater(&ssdp_tx, ssdp_int))
+            .spawn(move || {
+                run_ssdp_updater(&ssdp_tx, ssdp_int, export_file.as_deref().map(Path::new))
+            })
             .unwrap();
     }
     // set args autoresume
@@ -300,22 +576,49 @@ fn main() -> Result<(), i32> {
         config.buffering_delay_msec = args.upfront_buffer;
     }
 
-    // start the webserver
-    let server_port = config.server_port;
+    // start the webserver, on the next free port if the configured port is in use
+    let server_port = select_server_port(&serve_addr, config.server_port.unwrap_or_default());
     let feedback_tx = msg_tx.clone();
     let _ = thread::Builder::new()
         .name("swyh_rs_webserver".into())
         .stack_size(4 * 1024 * 1024)
         .spawn(move || {
-            run_server(
-                &local_addr,
-                server_port.unwrap_or_default(),
-                wd,
-                &feedback_tx,
-            );
+            run_server(&serve_addr, server_port, wd, &feedback_tx);
         })
         .unwrap();
 
+    // connect to the own stream and check it if asked
+    if args.verify_stream == Some(true) {
+        let url = stream_url(
+            &format!("{serve_addr}:{server_port}"),
+            config.streaming_format.unwrap_or(Flac),
+            config.bits_per_sample,
+            None,
+        );
+        let bits = config.bits_per_sample.unwrap_or(16);
+        let sample_rate = wd.sample_rate.0;
+        let _ = thread::Builder::new()
+            .name("stream_check".into())
+            .spawn(move || {
+                // give the webserver a chance to start
+                thread::sleep(Duration::from_secs(1));
+                ui_log(&format!("Verifying {url} for {CHECK_SECS} seconds"));
+                let steps = verify_stream(&url, sample_rate, bits, CHECK_SECS);
+                for step in &steps {
+                    ui_log(&format!("Stream check: {step}"));
+                }
+            });
+    }
+
+    // check the port forwarding of the router if enabled
+    start_igd_check(&serve_addr, server_port);
+
+    // announce the webserver with SSDP if enabled
+    start_ssdp_announcer(&local_addr, &serve_addr, server_port);
+
+    // let Squeezebox players connect with slimproto if enabled
+    start_slimproto_server(&serve_addr, server_port, &wd);
+
     // we may have to translate player names to IP addresses
     if !serve_only && (args.player_ip.is_some() || config.last_renderer.is_some()) {
         // give the webserver a chance to start and wait for ssdp to complete
@@ -325,21 +628,45 @@ fn main() -> Result<(), i32> {
         while let Ok(msg) = msg_rx.try_recv() {
             match msg {
                 MessageType::SsdpMessage(newr) => {
+                    script_renderer(&newr);
                     renderers.push(newr.clone());
-                    ui_log(&format!(
-                        "Available renderer #{n}: {} at {}",
-                        newr.dev_name, newr.remote_addr
-                    ));
-                    n += 1;
+                    if newr.is_group_member() {
+                        ui_log(&format!(
+                            "Grouped renderer: {} at {}, plays with its group coordinator",
+                            newr.dev_name, newr.remote_addr
+                        ));
+                    } else {
+                        ui_log(&format!(
+                            "Available renderer #{n}: {} at {}",
+                            newr.button_label(),
+                            newr.remote_addr
+                        ));
+                        n += 1;
+                    }
                 }
                 MessageType::PlayerMessage(_) => (),
                 MessageType::LogMessage(_) => (),
+                MessageType::MqttMessage(_) => (),
+                MessageType::TransportMessage(_)
+                | MessageType::PositionMessage(..)
+                | MessageType::AvailabilityMessage(..)
+                | MessageType::VolumeMessage(..)
+                | MessageType::PlayersMessage(_)
+                | MessageType::FormatMessage
+                | MessageType::AudioDevicesMessage(_)
+                | MessageType::RecallSessionMessage(_)
+                | MessageType::SaveSessionMessage(_)
+                | MessageType::DiagnosisMessage(..)
+                | MessageType::MediaBrowseMessage(_)
+                | MessageType::VuTriggerMessage(_)
+                | MessageType::ShutdownMessage => (),
             }
         }
         // now check for player names(s) instead of ip addresses
         if args.player_ip.is_some() {
             if let Some(r) = renderers
                 .iter()
+                .filter(|r| !r.is_group_member())
                 .find(|r| r.dev_name.contains(args.player_ip.as_ref().unwrap()))
             {
                 ui_log(&format!(
@@ -353,7 +680,10 @@ fn main() -> Result<(), i32> {
         if args.active_players.is_some() {
             let mut ip_players: Vec<String> = Vec::new();
             args.active_players.as_ref().unwrap().iter().for_each(|ap| {
-                if let Some(r) = renderers.iter().find(|r| r.dev_name.contains(ap)) {
+                if let Some(r) = renderers
+                    .iter()
+                    .find(|r| !r.is_group_member() && r.dev_name.contains(ap))
+                {
                     ip_players.push(r.remote_addr.clone());
                     ui_log(&format!("Active renderer: {ap} => {} ", r.remote_addr));
                 }
@@ -427,7 +757,12 @@ fn main() -> Result<(), i32> {
         *conf = config.clone();
     }
 
-    info!("New config: {config:?}");
+    info!("New config: {:?}", config.redacted());
+
+    // write the stream to a named pipe or stdout if requested
+    if let Some(output) = &args.output {
+        start_pipe_output(output, &wd, &msg_tx);
+    }
 
     // exit here if dry-run
     if args.dry_run.is_some() {
@@ -436,12 +771,7 @@ fn main() -> Result<(), i32> {
     }
 
     // prepare for playing
-    let wd = WavData {
-        sample_format: audio_cfg.sample_format(),
-        sample_rate: audio_cfg.sample_rate(),
-        channels: audio_cfg.channels(),
-    };
-    let streaminfo = StreamInfo {
+    let mut streaminfo = StreamInfo {
         sample_rate: wd.sample_rate.0,
         bits_per_sample: config.bits_per_sample.unwrap_or(16),
         streaming_format: config.streaming_format.unwrap_or(Lpcm),
@@ -450,8 +780,7 @@ fn main() -> Result<(), i32> {
     // start playing unless only serving
     let mut playing = Vec::new();
     if serve_only {
-        let port = config.server_port.unwrap_or(5901);
-        ui_log(&format!("Serving started on port {port}..."));
+        ui_log(&format!("Serving started on port {server_port}..."));
     } else {
         for ip in config.active_renderers {
             if let Some(pl) = renderers
@@ -459,16 +788,21 @@ fn main() -> Result<(), i32> {
                 .find(|&renderer| renderer.remote_addr == ip)
             {
                 let mut player = pl.clone();
-                if let Some(vol) = args.volume {
+                // the volume of the player in the session, if any
+                let session_volume = session.as_ref().and_then(|s| {
+                    s.renderers
+                        .iter()
+                        .find(|m| renderer_matches(&player, &m.renderer))
+                        .and_then(|m| m.volume)
+                });
+                if let Some(vol) = args.volume.or(session_volume) {
                     if player.get_volume(&ui_log) > -1 {
                         player.set_volume(&ui_log, vol.into());
                     }
                 }
-                let _ = player.play(
-                    &local_addr,
-                    config.server_port.unwrap_or(5901),
-                    &ui_log,
-                    streaminfo,
+                record_play_result(
+                    &player,
+                    player.play(&serve_addr, server_port, &ui_log, streaminfo),
                 );
                 let pl_name = &player.dev_url;
                 ui_log(&format!("Playing to {pl_name}"));
@@ -477,11 +811,19 @@ fn main() -> Result<(), i32> {
         }
     }
 
+    // the renderers being switched to a new format, with the time of the switch
+    let mut switching: HashMap<String, Instant> = HashMap::new();
+    // the locations of the renderers started by the VU trigger
+    let mut vu_started: Vec<String> = Vec::new();
+    // the ip addresses of the renderers that were stopped on purpose, they are not auto-resumed
+    let mut stopped: Vec<String> = Vec::new();
+
     loop {
         while let Ok(msg) = msg_rx.try_recv() {
             match msg {
                 MessageType::SsdpMessage(newr) => {
                     if !serve_only {
+                        script_renderer(&newr);
                         renderers.push(newr.clone());
                         ui_log(&format!(
                             "New renderer {} at {}",
@@ -490,18 +832,72 @@ fn main() -> Result<(), i32> {
                     }
                 }
                 MessageType::PlayerMessage(streamer_feedback) => {
+                    publish_streaming_state(&streamer_feedback);
+                    record_streaming(&renderers, &streamer_feedback);
+                    hook_streaming_state(&streamer_feedback, &renderers);
                     match streamer_feedback.streaming_state {
-                        StreamingState::Started => {}
+                        StreamingState::Started => {
+                            // start capturing audio on demand
+                            if capture_on_demand && stream.is_none() {
+                                ui_log("Streaming client connected, starting audio capture");
+                                stream = start_audio_capture(
+                                    &audio_output_device,
+                                    rms_channel.0.clone(),
+                                );
+                            }
+                            // remember the format if the renderer pulled another one
+                            let same_ip = renderers
+                                .iter()
+                                .filter(|r| r.remote_addr == streamer_feedback.remote_ip)
+                                .collect::<Vec<&Renderer>>();
+                            if let [renderer] = same_ip.as_slice() {
+                                remember_pulled_format(renderer, &streamer_feedback);
+                            }
+                        }
                         StreamingState::Ended => {
+                            // stop capturing audio if this was the last client
+                            if capture_on_demand && stream.is_some() && CLIENTS.read().is_empty() {
+                                ui_log("No streaming clients left, stopping audio capture");
+                                stream = None;
+                            }
                             if !serve_only {
+                                let renderer = playing
+                                    .iter()
+                                    .find(|r| r.remote_addr == streamer_feedback.remote_ip);
+                                // remember a fallback format that has played
+                                if let Some(r) = renderer {
+                                    confirm_fallback_format(r, &streamer_feedback);
+                                }
                                 // first check if the renderer has actually not started streaming again
                                 // as this can happen with Bubble/Nest Audio Openhome
+                                // or it is still switching to a new format
                                 let still_streaming = CLIENTS.read().values().any(|chanstrm| {
                                     chanstrm.remote_ip == streamer_feedback.remote_ip
-                                });
+                                }) || switching
+                                    .get(&streamer_feedback.remote_ip)
+                                    .is_some_and(|t| t.elapsed() < SWITCH_GRACE);
                                 if !still_streaming {
                                     let config = CONFIG.read().clone();
-                                    if config.auto_resume {
+                                    // retry with the next fallback format if it dropped
+                                    // the connection right after starting
+                                    let fallback = renderer
+                                        .and_then(|r| next_fallback_format(r, &streamer_feedback));
+                                    if let (Some(r), Some(_)) = (renderer, fallback) {
+                                        let streaminfo = StreamInfo {
+                                            sample_rate: wd.sample_rate.0,
+                                            bits_per_sample: config.bits_per_sample.unwrap_or(16),
+                                            streaming_format: config
+                                                .streaming_format
+                                                .unwrap_or(Flac),
+                                        };
+                                        record_reconnect(r, streamer_feedback.streaming_time);
+                                        record_play_result(
+                                            r,
+                                            r.play(&serve_addr, server_port, &ui_log, streaminfo),
+                                        );
+                                    } else if config.auto_resume
+                                        && !stopped.contains(&streamer_feedback.remote_ip)
+                                    {
                                         if let Some(r) = renderers
                                             .iter()
                                             .find(|r| r.remote_addr == streamer_feedback.remote_ip)
@@ -515,11 +911,15 @@ fn main() -> Result<(), i32> {
                                                     .streaming_format
                                                     .unwrap_or(Flac),
                                             };
-                                            let _ = r.play(
-                                                &local_addr,
-                                                server_port.unwrap_or_default(),
-                                                &ui_log,
-                                                streaminfo,
+                                            record_reconnect(r, streamer_feedback.streaming_time);
+                                            record_play_result(
+                                                r,
+                                                r.play(
+                                                    &serve_addr,
+                                                    server_port,
+                                                    &ui_log,
+                                                    streaminfo,
+                                                ),
                                             );
                                         }
                                     }
@@ -529,37 +929,150 @@ fn main() -> Result<(), i32> {
                     }
                 }
                 MessageType::LogMessage(msg) => ui_log(&msg),
-            }
-        }
-        // check the logchannel for new log messages to show in the logger textbox
-        thread::sleep(Duration::from_millis(100));
-        // handle CTL-C interrupt: shutdown the player(s)
-        if shutting_down.load(Ordering::Relaxed) {
-            println!("Received ^C -> exiting.");
-            if !serve_only && player.is_some() && CLIENTS.read().len() > 0 {
-                for pl in playing {
-                    if CLIENTS
-                        .read()
-                        .values()
-                        .any(|cs| cs.remote_ip == pl.remote_addr)
-                    {
-                        println!("^C: Stopping streaming to {}", pl.dev_name);
-                        pl.stop_play(&ui_log);
+                MessageType::MqttMessage(cmd) => match cmd {
+                    MqttCommand::Play(name) => {
+                        // without a name: the active renderers
+                        let active_renderers = CONFIG.read().active_renderers.clone();
+                        let new_players: Vec<Renderer> = renderers
+                            .iter()
+                            .filter(|r| {
+                                if name.is_empty() {
+                                    active_renderers.contains(&r.remote_addr)
+                                } else {
+                                    renderer_matches(r, &name)
+                                }
+                            })
+                            .filter(|r| !playing.iter().any(|p| p.location == r.location))
+                            .cloned()
+                            .collect();
+                        for r in new_players {
+                            stopped.retain(|ip| *ip != r.remote_addr);
+                            let _ = r.play(&serve_addr, server_port, &ui_log, streaminfo);
+                            ui_log(&format!("Playing to {}", r.dev_url));
+                            playing.push(r);
+                        }
+                    }
+                    MqttCommand::Stop(name) => {
+                        // without a name: all renderers
+                        playing.retain(|r| {
+                            if name.is_empty() || renderer_matches(r, &name) {
+                                r.stop_play(&ui_log);
+                                stopped.push(r.remote_addr.clone());
+                                false
+                            } else {
+                                true
+                            }
+                        });
+                    }
+                    MqttCommand::Volume(name, vol) => {
+                        for r in renderers.iter_mut().filter(|r| renderer_matches(r, &name)) {
+                            r.set_volume(&ui_log, vol.into());
+                        }
+                    }
+                },
+                MessageType::TransportMessage(remote_ip) => {
+                    // stopped outside swyh-rs: resume if auto-resume is set, else forget it
+                    if CONFIG.read().auto_resume {
+                        if let Some(r) = playing.iter().find(|r| r.remote_addr == remote_ip) {
+                            ui_log(&format!("Auto-resume playing on {}", r.dev_name));
+                            let _ = r.play(&serve_addr, server_port, &ui_log, streaminfo);
+                        }
+                    } else {
+                        playing.retain(|r| r.remote_addr != remote_ip);
                     }
                 }
-                // also wait some time for the player(s) to drop the HTTP streaming connection
-                for _ in 0..100 {
-                    if CLIENTS.read().len() == 0 {
-                        println!("^C: No HTTP streaming connections active");
-                        break;
+                // switch the playing renderers to the new format without stopping them
+                MessageType::FormatMessage => {
+                    switching.retain(|_, t| t.elapsed() < SWITCH_GRACE);
+                    let config = CONFIG.read().clone();
+                    streaminfo = StreamInfo {
+                        sample_rate: wd.sample_rate.0,
+                        bits_per_sample: config.bits_per_sample.unwrap_or(16),
+                        streaming_format: config.streaming_format.unwrap_or(Lpcm),
+                    };
+                    for r in &playing {
+                        ui_log(&format!("Switching {} to the new format", r.dev_name));
+                        switching.insert(r.remote_addr.clone(), Instant::now());
+                        let _ = r.switch_stream(&serve_addr, server_port, &ui_log, streaminfo);
                     }
-                    thread::sleep(Duration::from_millis(100));
                 }
-                if CLIENTS.read().len() > 0 {
-                    println!("^C: Time-out waiting for HTTP streaming shutdown - exiting.");
+                // change the players and the format at runtime
+                MessageType::PlayersMessage(request) => {
+                    let mut conf = CONFIG.write();
+                    let format_changed = conf
+                        .set_streaming_format(request.streaming_format, request.bits_per_sample);
+                    // the new active players are also used after a restart
+                    if let Some(active) = &request.active {
+                        conf.active_renderers = renderers
+                            .iter()
+                            .filter(|r| active.iter().any(|name| renderer_matches(r, name)))
+                            .map(|r| r.remote_addr.clone())
+                            .collect();
+                    }
+                    let _ = conf.update_config();
+                    drop(conf);
+                    if format_changed {
+                        let _ = msg_tx.send(MessageType::FormatMessage);
+                    }
+                    for cmd in players_commands(&request, &playing) {
+                        let _ = msg_tx.send(MessageType::MqttMessage(cmd));
+                    }
+                }
+                // the elapsed time, the availability and the volume are only shown in the GUI
+                MessageType::PositionMessage(..)
+                | MessageType::AvailabilityMessage(..)
+                | MessageType::VolumeMessage(..) => (),
+                // the device changes have been logged, the audio source is fixed
+                MessageType::AudioDevicesMessage(_) => (),
+                // the sessions are recalled with -Y, or in the GUI
+                MessageType::RecallSessionMessage(_) | MessageType::SaveSessionMessage(_) => (),
+                MessageType::DiagnosisMessage(..) | MessageType::MediaBrowseMessage(_) => (),
+                MessageType::VuTriggerMessage(VuEvent::Start) => {
+                    // without renderers: the active renderers
+                    let names = vu_trigger_renderers();
+                    let active_renderers = CONFIG.read().active_renderers.clone();
+                    let new_players: Vec<Renderer> = renderers
+                        .iter()
+                        .filter(|r| {
+                            if names.is_empty() {
+                                active_renderers.contains(&r.remote_addr)
+                            } else {
+                                names.iter().any(|name| renderer_matches(r, name))
+                            }
+                        })
+                        .filter(|r| !playing.iter().any(|p| p.location == r.location))
+                        .cloned()
+                        .collect();
+                    for r in new_players {
+                        stopped.retain(|ip| *ip != r.remote_addr);
+                        let _ = r.play(&serve_addr, server_port, &ui_log, streaminfo);
+                        ui_log(&format!("Playing to {}", r.dev_url));
+                        vu_started.push(r.location.clone());
+                        playing.push(r);
+                    }
                 }
+                MessageType::VuTriggerMessage(VuEvent::Stop) => {
+                    playing.retain(|r| {
+                        if vu_started.contains(&r.location) {
+                            r.stop_play(&ui_log);
+                            stopped.push(r.remote_addr.clone());
+                            false
+                        } else {
+                            true
+                        }
+                    });
+                    vu_started.clear();
+                }
+                // shut down orderly, like ^C
+                MessageType::ShutdownMessage => shutting_down.store(true, Ordering::Relaxed),
             }
-            std::process::exit(0);
+        }
+        // check the logchannel for new log messages to show in the logger textbox
+        thread::sleep(Duration::from_millis(100));
+        // handle CTL-C interrupt or /api/shutdown: shutdown the player(s)
+        if shutting_down.load(Ordering::Relaxed) {
+            eprintln!("Shutting down -> exiting.");
+            std::process::exit(graceful_shutdown(&playing));
         }
     }
 }
@@ -567,23 +1080,46 @@ fn main() -> Result<(), i32> {
 /// run the `ssdp_updater` - thread that periodically run ssdp discovery
 /// and detect new renderers
 /// send any new renderers to te main thread on the Crossbeam ssdp channel
-fn run_ssdp_updater(ssdp_tx: &Sender<MessageType>, ssdp_interval_mins: f64) {
+fn run_ssdp_updater(
+    ssdp_tx: &Sender<MessageType>,
+    ssdp_interval_mins: f64,
+    export_file: Option<&Path>,
+) {
     // the hashmap used to detect new renderers
     let mut rmap: HashMap<String, Renderer> = HashMap::new();
+    // the known renderers are added to the first discovery
+    let mut known = known_renderers(&ui_log);
+    // the known renderers are refreshed with unicast between the multicast discoveries
+    let mut refresh = SsdpRefresh::default();
     loop {
-        let renderers = discover(&rmap, &ui_log).unwrap_or_default();
+        let mut renderers = std::mem::take(&mut known);
+        if refresh.multicast_due() {
+            renderers.extend(discover(&rmap, &ui_log).unwrap_or_default());
+        } else {
+            refresh.refresh(rmap.values());
+        }
         for r in &renderers {
             rmap.entry(r.remote_addr.clone()).or_insert_with(|| {
                 info!(
                     "Found new renderer {} {}  at {}",
                     r.dev_name, r.dev_model, r.remote_addr
                 );
+                record_renderer(r);
                 ssdp_tx.send(MessageType::SsdpMessage(r.clone())).unwrap();
                 thread::yield_now();
                 r.clone()
             });
         }
-        thread::sleep(Duration::from_millis(
+        if let Some(file) = export_file {
+            match export_renderers(file) {
+                Ok(n) => ui_log(&format!("Exported {n} renderers to {}", file.display())),
+                Err(e) => ui_log(&format!(
+                    "*E*E*> Could not export the renderers to {}: {e}",
+                    file.display()
+                )),
+            }
+        }
+        wait_for_ssdp_discovery(Duration::from_millis(
             (ssdp_interval_mins * 60.0 * 1000.0) as u64,
         ));
     }
@@ -37,38 +37,94 @@ OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 SOFTWARE.
 */
 use swyh_rs::{
+    dsp::chain::{loudness, update_dsp_chain},
     enums::{
-        messages::MessageType,
+        messages::{MediaBrowse, MessageType, MqttCommand},
         streaming::{StreamingFormat::Flac, StreamingState},
     },
-    globals::statics::{APP_VERSION, CLIENTS, CONFIG, MSGCHANNEL, SERVER_PORT},
-    openhome::rendercontrol::{discover, Renderer, StreamInfo, WavData},
-    server::streaming_server::run_server,
-    ui::mainform::MainForm,
+    globals::statics::{
+        APP_VERSION, CLIENTS, CONFIG, MSGCHANNEL, RUN_RMS_MONITOR, RUN_SPECTRUM_ANALYZER,
+        SERVER_PORT,
+    },
+    openhome::{
+        diagnosis::under_test,
+        inventory::{known_renderers, record_renderer},
+        rendercontrol::{discover, Renderer, StreamInfo, WavData},
+        renderer_poller::{run_renderer_poller, PollIntervals},
+    },
+    server::{
+        players_api::players_commands,
+        port_select::{select_server_port, start_igd_check},
+        rtp_sender::start_rtp_sender,
+        slimproto::start_slimproto_server,
+        ssdp_announcer::start_ssdp_announcer,
+        streaming_server::run_server,
+    },
+    ui::mainform::{MainForm, RmsMonitorWidgets},
     utils::{
+        announce::start_announcer,
         audiodevices::{
-            capture_output_audio, get_default_audio_output_device, get_output_audio_devices,
+            capture_network_audio, get_default_audio_output_device, get_output_audio_devices,
+            stream_channels,
         },
-        bincommon::run_silence_injector,
-        local_ip_address::{get_interfaces, get_local_addr},
+        bincommon::{
+            confirm_fallback_format, next_fallback_format, remember_pulled_format,
+            run_silence_injector, start_audio_capture, wait_for_ssdp_discovery, SsdpRefresh,
+        },
+        configuration::{SessionMember, SessionPreset},
+        device_watcher::run_device_watcher,
+        fallback_audio::start_fallback_audio,
+        hooks::{hook_streaming_state, start_silence_hook},
+        local_ip_address::{get_interfaces, get_local_addr, get_serve_addr},
+        meter::{peak_and_clips, MeterSettings, RmsWindow},
+        mixer::start_mix_source,
+        mqtt::{publish_streaming_state, renderer_matches, run_mqtt_client, RmsPublisher},
+        network_source::open_network_source,
+        notify::{notify, notify_streaming_state, NotifyEvent},
+        playback_monitor::start_playback_monitor,
         priority::raise_priority,
+        recorder::start_recorder,
+        renderer_badge::BADGE_REFRESH,
+        renderer_stats::{record_reconnect, record_streaming},
+        resource_monitor::run_resource_monitor,
+        scripting::{script_renderer, start_script},
+        scrobble::start_scrobbler,
+        sessions::{
+            apply_session_format, apply_session_loudness, find_session, save_session,
+            session_commands,
+        },
+        shutdown::graceful_shutdown,
+        spectrum::SpectrumAnalyzer,
+        track_detector::run_track_change_handler,
         ui_logger::ui_log,
+        update_check::start_update_check,
+        vu_trigger::{start_vu_trigger, vu_trigger_renderers, VuEvent},
     },
 };
 
-use cpal::{traits::StreamTrait, Sample};
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use fltk::{
     app, dialog,
-    misc::Progress,
+    enums::Color,
     prelude::{ButtonExt, WidgetExt},
 };
 use hashbrown::HashMap;
 use log::{debug, info, LevelFilter};
 use simplelog::{ColorChoice, CombinedLogger, Config, TermLogger, WriteLogger};
-use std::{cell::Cell, fs::File, net::IpAddr, path::Path, rc::Rc, thread, time::Duration};
+use std::{
+    cell::Cell,
+    fs::File,
+    net::IpAddr,
+    path::Path,
+    rc::Rc,
+    sync::atomic::Ordering,
+    thread,
+    time::{Duration, Instant},
+};
 
 pub const APP_NAME: &str = "SWYH-RS";
+/// the old connection of a renderer that switches formats may end during this time
+const SWITCH_GRACE: Duration = Duration::from_secs(10);
 
 /// swyh-rs
 ///
@@ -131,15 +187,22 @@ fn main() {
     if cfg!(debug_assertions) {
         ui_log("*W*W*>Running DEBUG build => log level set to DEBUG!");
     }
+    for message in &config.config_messages {
+        ui_log(message);
+    }
+    // report the problems of a config file that could not be read
+    for problem in &config.config_problems {
+        ui_log(&format!("*E*E*> {problem}"));
+    }
 
     if let Some(config_id) = &config.config_id {
         if !config_id.is_empty() {
             ui_log(&format!("Loaded configuration -c {config_id}"));
         }
     }
-    ui_log(&format!("{config:?}"));
+    ui_log(&format!("{:?}", config.redacted()));
 
-    info!("Config: {:?}", config);
+    info!("Config: {:?}", config.redacted());
 
     // get the output device from the config and get all available audio source names
     let audio_devices = get_output_audio_devices();
@@ -182,22 +245,38 @@ fn main() {
             get_default_address()
         }
     };
+    // the streaming server can be on another network than SSDP discovery
+    let serve_addr = get_serve_addr(local_addr, &networks);
+
+    // use the stream of another swyh-rs instance as the audio source if configured
+    let network_source = config
+        .network_source
+        .as_deref()
+        .filter(|url| !url.is_empty())
+        .and_then(open_network_source);
 
     // we need to pass some audio config data to the play function
     let audio_cfg = audio_output_device.default_config();
-    let wd = WavData {
-        sample_format: audio_cfg.sample_format(),
-        sample_rate: audio_cfg.sample_rate(),
-        channels: audio_cfg.channels(),
+    let wd = if let Some(source) = &network_source {
+        source.wav_data()
+    } else {
+        WavData {
+            sample_format: audio_cfg.sample_format(),
+            sample_rate: audio_cfg.sample_rate(),
+            channels: stream_channels(audio_cfg.channels()),
+        }
     };
 
+    // use the next free HTTP port if the configured port is in use
+    let server_port = select_server_port(&serve_addr, config.server_port.unwrap_or(SERVER_PORT));
+
     // we now have enough information to create the GUI with meaningful data
     let mut mf = MainForm::create(
         &config,
         &config_changed,
         &source_names,
         &networks,
-        local_addr,
+        serve_addr,
         &wd,
         APP_VERSION,
     );
@@ -208,17 +287,16 @@ fn main() {
     // the rms monitor channel
     let rms_channel: (Sender<Vec<f32>>, Receiver<Vec<f32>>) = unbounded();
 
-    // capture system audio
-    debug!("Try capturing system audio");
-    let stream: cpal::Stream;
-    match capture_output_audio(&audio_output_device, rms_channel.0) {
-        Some(s) => {
-            stream = s;
-            stream.play().unwrap();
-        }
-        None => {
-            ui_log("*E*E*> Could not capture audio ...Please check configuration.");
-        }
+    // capture system audio, unless capturing only when a client is connected
+    let capture_on_demand = config.capture_on_demand && network_source.is_none();
+    let mut stream: Option<cpal::Stream> = None;
+    if let Some(source) = network_source {
+        capture_network_audio(source, rms_channel.0.clone());
+    } else if capture_on_demand {
+        ui_log("Audio capture will start when the first streaming client connects");
+    } else {
+        debug!("Try capturing system audio");
+        stream = start_audio_capture(&audio_output_device, rms_channel.0.clone());
     }
 
     // If silence injector is on, create a silence injector stream.
@@ -229,10 +307,73 @@ fn main() {
         None
     };
 
+    // play the outgoing audio on the local playback monitor device if configured
+    let _monitor_stream = start_playback_monitor(&wd);
+
+    // mix the secondary audio source into the captured audio if configured
+    let _mix_stream = start_mix_source(&wd);
+
+    // log the track boundaries detected from silence gaps and call the track change hook
+    run_track_change_handler();
+
+    // record to disk if a recording directory is configured
+    start_recorder(&wd);
+
+    // stream the fallback audio when the audio capture is lost, if configured
+    start_fallback_audio(&wd);
+
+    // mix the announcements of /api/announce into the stream
+    start_announcer(&wd);
+
+    // start and stop the renderers on audio activity if configured
+    start_vu_trigger(&wd);
+
+    // run the silence hooks on a prolonged silence if configured
+    start_silence_hook();
+
+    // run the automation script if configured
+    start_script();
+
+    // check for a newer release if configured
+    start_update_check();
+
+    // scrobble the pushed now-playing tracks if configured
+    start_scrobbler();
+
+    // send RTP to a unicast or multicast destination if configured
+    start_rtp_sender(&serve_addr, &wd);
+
     // get the message channel
     let msg_tx = MSGCHANNEL.read().0.clone();
     let msg_rx = MSGCHANNEL.read().1.clone();
 
+    // connect to the MQTT broker if configured, it also gets the RMS levels
+    if let Some(mqtt) = &config.mqtt {
+        run_mqtt_client(mqtt, &msg_tx);
+        RUN_RMS_MONITOR.store(true, Ordering::Relaxed);
+    }
+
+    // poll the transport state and the elapsed time of the active renderers
+    // and keep the volume sliders in sync with the renderers if enabled
+    let poll_intervals = PollIntervals::new(
+        config.transport_poll_secs,
+        config.position_poll_secs,
+        config.volume_poll_secs,
+    );
+    if poll_intervals.any() {
+        run_renderer_poller(poll_intervals, &msg_tx);
+    }
+
+    // measure the resource usage if enabled
+    if let Some(secs) = config.resource_monitor.interval_secs.filter(|s| *s > 0) {
+        run_resource_monitor(secs);
+    }
+
+    // watch for added or removed audio devices if enabled
+    if let Some(secs) = config.device_watch_secs.filter(|s| *s > 0) {
+        run_device_watcher(secs, &msg_tx);
+    }
+
     // now start the SSDP discovery update thread with a Crossbeam channel for renderer updates
     // the discovered renderers will be kept in this list
     let mut renderers: Vec<Renderer> = Vec::new();
@@ -250,36 +391,68 @@ fn main() {
     }
     // also start the "monitor_rms" thread
     let rms_receiver = rms_channel.1;
-    let mon_l = mf.rms_mon_l.clone();
-    let mon_r = mf.rms_mon_r.clone();
+    let rms_monitor = mf.rms_monitor.clone();
     let _ = thread::Builder::new()
         .name("rms_monitor".into())
         .stack_size(4 * 1024 * 1024)
         .spawn(move || {
-            run_rms_monitor(wd, &rms_receiver, mon_l, mon_r);
+            run_rms_monitor(wd, &rms_receiver, rms_monitor);
         })
         .unwrap();
 
     // finally start a webserver on the local address,
-    let server_port = config.server_port.unwrap_or(SERVER_PORT);
     let feedback_tx = msg_tx.clone();
     let _ = thread::Builder::new()
         .name("swyh_rs_webserver".into())
         .stack_size(4 * 1024 * 1024)
         .spawn(move || {
-            run_server(&local_addr, server_port, wd, &feedback_tx);
+            run_server(&serve_addr, server_port, wd, &feedback_tx);
         })
         .unwrap();
     // give the webserver a chance to start
     thread::yield_now();
 
+    // check the port forwarding of the router if enabled
+    start_igd_check(&serve_addr, server_port);
+
+    // announce the webserver with SSDP if enabled
+    start_ssdp_announcer(&local_addr, &serve_addr, server_port);
+
+    // let Squeezebox players connect with slimproto if enabled
+    start_slimproto_server(&serve_addr, server_port, &wd);
+
+    // the renderers being switched to a new format, with the time of the switch
+    let mut switching: HashMap<String, Instant> = HashMap::new();
+
     // and now we can run the GUI event loop, app::awake() is used by the various threads to
     // trigger updates when something has changed, some threads use Crossbeam channels
     // to signal what has changed
+    // wake up the event loop every second to refresh the status lines and the badges,
+    // not on every event
+    let refresh_due = Rc::new(Cell::new(true));
+    // the locations of the renderers started by the VU trigger
+    let mut vu_started: Vec<String> = Vec::new();
+    app::add_timeout3(BADGE_REFRESH.as_secs_f64(), {
+        let refresh_due = refresh_due.clone();
+        move |handle| {
+            refresh_due.set(true);
+            app::repeat_timeout3(BADGE_REFRESH.as_secs_f64(), handle);
+        }
+    });
     while app::wait() {
         if app::should_program_quit() {
             break;
         }
+        if refresh_due.replace(false) {
+            // show if the stream is bit-perfect
+            mf.show_bit_perfect();
+            // show the estimated bitrate of the streaming format
+            mf.show_bitrate();
+            // show the resource usage if it is monitored
+            mf.show_resources();
+            // show the live state of the renderers in the badges next to their buttons
+            mf.show_badges();
+        }
         // test for a configuration change that needs an app restart to take effect
         if config_changed.get() && app_restart(&mf) != 0 {
             config_changed.set(false);
@@ -292,6 +465,40 @@ fn main() {
                 // in that case we turn the button off as a visual feedback for the user
                 // but if auto_resume is set, we restart playing instead
                 MessageType::PlayerMessage(streamer_feedback) => {
+                    publish_streaming_state(&streamer_feedback);
+                    notify_streaming_state(&streamer_feedback, &renderers);
+                    record_streaming(&renderers, &streamer_feedback);
+                    hook_streaming_state(&streamer_feedback, &renderers);
+                    // start or stop capturing audio on demand
+                    if capture_on_demand {
+                        match streamer_feedback.streaming_state {
+                            StreamingState::Started => {
+                                if stream.is_none() {
+                                    ui_log("Streaming client connected, starting audio capture");
+                                    stream = start_audio_capture(
+                                        &audio_output_device,
+                                        rms_channel.0.clone(),
+                                    );
+                                }
+                            }
+                            StreamingState::Ended => {
+                                if stream.is_some() && CLIENTS.read().is_empty() {
+                                    ui_log("No streaming clients left, stopping audio capture");
+                                    stream = None;
+                                }
+                            }
+                        }
+                    }
+                    // show the stream parameters the renderer actually requested
+                    if let Some(negotiated) = streamer_feedback.negotiated {
+                        for r in renderers
+                            .iter_mut()
+                            .filter(|r| r.remote_addr == streamer_feedback.remote_ip)
+                        {
+                            r.negotiated = Some(negotiated);
+                            mf.show_negotiated_stream(r);
+                        }
+                    }
                     // check for multiple renderers at same ip address (Bubble UPNP)
                     let same_ip: Vec<&Renderer> = renderers
                         .iter()
@@ -300,6 +507,28 @@ fn main() {
                     if same_ip.len() == 1 {
                         // got the only renderer with this IP address
                         let renderer = same_ip[0];
+                        // remember the format if it pulled another one and show it
+                        match streamer_feedback.streaming_state {
+                            StreamingState::Started => {
+                                remember_pulled_format(renderer, &streamer_feedback);
+                                let bits = streamer_feedback.negotiated.map_or_else(
+                                    || CONFIG.read().bits_per_sample.unwrap_or(16),
+                                    |n| n.bits_per_sample,
+                                );
+                                mf.show_stream_started(
+                                    &renderer.location,
+                                    streamer_feedback.streaming_format,
+                                    bits,
+                                );
+                            }
+                            StreamingState::Ended => {
+                                if !CLIENTS.read().values().any(|chanstrm| {
+                                    chanstrm.remote_ip == streamer_feedback.remote_ip
+                                }) {
+                                    mf.show_stream_ended(&renderer.location);
+                                }
+                            }
+                        }
                         // get the button associated with this renderer
                         if let Some(button) = mf.buttons.get_mut(&renderer.location) {
                             match streamer_feedback.streaming_state {
@@ -309,16 +538,57 @@ fn main() {
                                     }
                                 }
                                 StreamingState::Ended => {
+                                    // remember a fallback format that has played
+                                    confirm_fallback_format(renderer, &streamer_feedback);
                                     // first check if the renderer has actually not started streaming again
                                     // as this can happen with Bubble/Nest Audio Openhome
+                                    // or it is still switching to a new format
                                     let still_streaming = CLIENTS.read().values().any(|chanstrm| {
                                         chanstrm.remote_ip == streamer_feedback.remote_ip
-                                    });
-                                    if !still_streaming {
-                                        if mf.auto_resume.is_set() && button.is_set() {
+                                    }) || switching
+                                        .get(&streamer_feedback.remote_ip)
+                                        .is_some_and(|t| t.elapsed() < SWITCH_GRACE);
+                                    // a renderer that was tested is only switched off
+                                    let tested = under_test(&streamer_feedback.remote_ip);
+                                    // retry with the next fallback format if it dropped
+                                    // the connection right after starting
+                                    let fallback = if !still_streaming && !tested && button.is_set()
+                                    {
+                                        next_fallback_format(renderer, &streamer_feedback)
+                                    } else {
+                                        None
+                                    };
+                                    if fallback.is_some() {
+                                        record_reconnect(
+                                            renderer,
+                                            streamer_feedback.streaming_time,
+                                        );
+                                        let config = CONFIG.read().clone();
+                                        let streaminfo = StreamInfo {
+                                            sample_rate: wd.sample_rate.0,
+                                            bits_per_sample: config.bits_per_sample.unwrap_or(16),
+                                            streaming_format: config
+                                                .streaming_format
+                                                .unwrap_or(Flac),
+                                        };
+                                        MainForm::start_playing(
+                                            renderer,
+                                            serve_addr,
+                                            server_port,
+                                            streaminfo,
+                                        );
+                                    } else if !still_streaming {
+                                        if mf.auto_resume.is_set() && !tested && button.is_set() {
                                             if let Some(r) = renderers.iter().find(|r| {
                                                 r.remote_addr == streamer_feedback.remote_ip
                                             }) {
+                                                notify(
+                                                    NotifyEvent::AutoResume,
+                                                    &format!(
+                                                        "Auto-resume playing on {}",
+                                                        r.dev_name
+                                                    ),
+                                                );
                                                 let config = CONFIG.read().clone();
                                                 let streaminfo = StreamInfo {
                                                     sample_rate: wd.sample_rate.0,
@@ -329,10 +599,14 @@ fn main() {
                                                         .streaming_format
                                                         .unwrap_or(Flac),
                                                 };
-                                                let _ = r.play(
-                                                    &local_addr,
+                                                record_reconnect(
+                                                    r,
+                                                    streamer_feedback.streaming_time,
+                                                );
+                                                MainForm::start_playing(
+                                                    r,
+                                                    serve_addr,
                                                     server_port,
-                                                    &ui_log,
                                                     streaminfo,
                                                 );
                                             }
@@ -354,44 +628,262 @@ fn main() {
                 MessageType::SsdpMessage(mut newr) => {
                     let vol = newr.get_volume(&ui_log);
                     debug!("Renderer {} Volume: {vol}", newr.dev_name);
-                    mf.add_renderer_button(&newr);
+                    // a grouped speaker plays what the coordinator of its group plays
+                    if newr.is_group_member() {
+                        ui_log(&format!(
+                            "No button for {} {}, it plays with its group coordinator",
+                            newr.dev_model, newr.dev_name
+                        ));
+                    } else {
+                        mf.add_renderer_button(&newr);
+                    }
+                    script_renderer(&newr);
                     renderers.push(newr.clone());
                 }
                 // check the logchannel for new log messages to show in the logger textbox
                 MessageType::LogMessage(msg) => {
                     mf.add_log_msg(&msg);
                 }
+                // MQTT commands push the renderer button, like the user would
+                MessageType::MqttMessage(cmd) => match cmd {
+                    MqttCommand::Play(name) => {
+                        // without a name: the last used renderer
+                        let last_renderer = CONFIG.read().last_renderer.clone().unwrap_or_default();
+                        for r in renderers.iter().filter(|r| {
+                            if name.is_empty() {
+                                r.button_label() == last_renderer
+                            } else {
+                                renderer_matches(r, &name)
+                            }
+                        }) {
+                            if let Some(button) = mf.buttons.get_mut(&r.location) {
+                                if !button.is_set() {
+                                    button.set(true);
+                                    button.do_callback();
+                                }
+                            }
+                        }
+                    }
+                    MqttCommand::Stop(name) => {
+                        // without a name: all renderers
+                        for r in renderers
+                            .iter()
+                            .filter(|r| name.is_empty() || renderer_matches(r, &name))
+                        {
+                            if let Some(button) = mf.buttons.get_mut(&r.location) {
+                                if button.is_set() {
+                                    button.set(false);
+                                    button.do_callback();
+                                }
+                            }
+                        }
+                    }
+                    MqttCommand::Volume(name, vol) => {
+                        for r in renderers.iter_mut().filter(|r| renderer_matches(r, &name)) {
+                            r.set_volume(&ui_log, vol.into());
+                            mf.show_volume(&r.location, vol.into());
+                        }
+                    }
+                },
+                MessageType::TransportMessage(remote_ip) => {
+                    // stopped outside swyh-rs: resume if auto-resume is set, else turn the button off
+                    if let Some(r) = renderers.iter().find(|r| r.remote_addr == remote_ip) {
+                        if let Some(button) = mf.buttons.get_mut(&r.location) {
+                            if button.is_set() {
+                                if mf.auto_resume.is_set() {
+                                    let msg = format!("Auto-resume playing on {}", r.dev_name);
+                                    ui_log(&msg);
+                                    notify(NotifyEvent::AutoResume, &msg);
+                                    let config = CONFIG.read().clone();
+                                    let streaminfo = StreamInfo {
+                                        sample_rate: wd.sample_rate.0,
+                                        bits_per_sample: config.bits_per_sample.unwrap_or(16),
+                                        streaming_format: config.streaming_format.unwrap_or(Flac),
+                                    };
+                                    MainForm::start_playing(r, serve_addr, server_port, streaminfo);
+                                } else {
+                                    button.set(false);
+                                }
+                            }
+                        }
+                    }
+                }
+                MessageType::FormatMessage => {
+                    // switch the playing renderers to the new format without stopping them
+                    switching.retain(|_, t| t.elapsed() < SWITCH_GRACE);
+                    let config = CONFIG.read().clone();
+                    for r in &renderers {
+                        if mf.buttons.get(&r.location).is_some_and(|b| b.is_set()) {
+                            ui_log(&format!("Switching {} to the new format", r.dev_name));
+                            switching.insert(r.remote_addr.clone(), Instant::now());
+                            let streaminfo = StreamInfo {
+                                sample_rate: wd.sample_rate.0,
+                                bits_per_sample: config.bits_per_sample.unwrap_or(16),
+                                streaming_format: config.streaming_format.unwrap_or(Flac),
+                            };
+                            let _ = r.switch_stream(&serve_addr, server_port, &ui_log, streaminfo);
+                        }
+                    }
+                }
+                // update the audio source menus with the added or removed devices
+                MessageType::AudioDevicesMessage(names) => {
+                    mf.update_audio_sources(names);
+                }
+                MessageType::PositionMessage(location, elapsed) => {
+                    mf.show_elapsed(&location, elapsed);
+                }
+                MessageType::AvailabilityMessage(location, available) => {
+                    mf.show_offline(&location, !available);
+                }
+                MessageType::VolumeMessage(location, vol) => {
+                    mf.show_volume(&location, vol);
+                    if let Some(r) = renderers.iter_mut().find(|r| r.location == location) {
+                        r.volume = vol;
+                    }
+                }
+                // apply the format of the session and start its renderers with their volume
+                MessageType::RecallSessionMessage(name) => {
+                    let session = find_session(&CONFIG.read(), &name);
+                    let Some(session) = session else {
+                        ui_log(&format!("*W*W*> Session {name} not found"));
+                        continue;
+                    };
+                    ui_log(&format!("Recalling session {}", session.name));
+                    let msg_tx = MSGCHANNEL.read().0.clone();
+                    let mut conf = CONFIG.write();
+                    if apply_session_format(&mut conf, &session) {
+                        let _ = conf.update_config();
+                        drop(conf);
+                        mf.show_streaming_format();
+                        // switch the renderers that are already playing
+                        let _ = msg_tx.send(MessageType::FormatMessage);
+                    } else {
+                        drop(conf);
+                    }
+                    let mut conf = CONFIG.write();
+                    if apply_session_loudness(&mut conf, &session) {
+                        let _ = conf.update_config();
+                        drop(conf);
+                        update_dsp_chain();
+                        mf.show_loudness();
+                    } else {
+                        drop(conf);
+                    }
+                    for cmd in session_commands(&session) {
+                        let _ = msg_tx.send(MessageType::MqttMessage(cmd));
+                    }
+                }
+                // change the players and the format at runtime
+                MessageType::PlayersMessage(request) => {
+                    let msg_tx = MSGCHANNEL.read().0.clone();
+                    let mut conf = CONFIG.write();
+                    if conf.set_streaming_format(request.streaming_format, request.bits_per_sample)
+                    {
+                        let _ = conf.update_config();
+                        drop(conf);
+                        mf.show_streaming_format();
+                        let _ = msg_tx.send(MessageType::FormatMessage);
+                    } else {
+                        drop(conf);
+                    }
+                    let playing: Vec<Renderer> = renderers
+                        .iter()
+                        .filter(|r| mf.buttons.get(&r.location).is_some_and(|b| b.is_set()))
+                        .cloned()
+                        .collect();
+                    for cmd in players_commands(&request, &playing) {
+                        let _ = msg_tx.send(MessageType::MqttMessage(cmd));
+                    }
+                }
+                MessageType::DiagnosisMessage(dev_name, steps) => {
+                    for step in &steps {
+                        ui_log(&format!("Test of {dev_name}: {step}"));
+                    }
+                    MainForm::show_diagnosis(&dev_name, &steps);
+                }
+                MessageType::VuTriggerMessage(event) => match event {
+                    VuEvent::Start => {
+                        // without renderers: the last used renderer
+                        let names = vu_trigger_renderers();
+                        let last_renderer = CONFIG.read().last_renderer.clone().unwrap_or_default();
+                        for r in renderers.iter().filter(|r| {
+                            if names.is_empty() {
+                                r.button_label() == last_renderer
+                            } else {
+                                names.iter().any(|name| renderer_matches(r, name))
+                            }
+                        }) {
+                            if let Some(button) = mf.buttons.get_mut(&r.location) {
+                                if !button.is_set() {
+                                    button.set(true);
+                                    button.do_callback();
+                                    vu_started.push(r.location.clone());
+                                }
+                            }
+                        }
+                    }
+                    VuEvent::Stop => {
+                        for location in vu_started.drain(..) {
+                            if let Some(button) = mf.buttons.get_mut(&location) {
+                                if button.is_set() {
+                                    button.set(false);
+                                    button.do_callback();
+                                }
+                            }
+                        }
+                    }
+                },
+                MessageType::MediaBrowseMessage(browse) => match browse {
+                    MediaBrowse::Servers(renderer, servers, again) => {
+                        MainForm::choose_media_server(&renderer, &servers, again);
+                    }
+                    MediaBrowse::Objects(renderer, server, path, objects) => {
+                        MainForm::choose_media_object(&renderer, &server, path, objects);
+                    }
+                },
+                // shut down orderly after the event loop
+                MessageType::ShutdownMessage => {
+                    app::program_should_quit(true);
+                }
+                MessageType::SaveSessionMessage(name) => {
+                    let members: Vec<SessionMember> = renderers
+                        .iter()
+                        .filter(|r| mf.buttons.get(&r.location).is_some_and(|b| b.is_set()))
+                        .map(|r| SessionMember {
+                            renderer: r.dev_name.clone(),
+                            volume: u8::try_from(r.volume).ok(),
+                        })
+                        .collect();
+                    if members.is_empty() {
+                        ui_log("*W*W*> No renderers playing, nothing to save");
+                        continue;
+                    }
+                    let mut conf = CONFIG.write();
+                    let session = SessionPreset {
+                        name: name.clone(),
+                        renderers: members,
+                        streaming_format: conf.streaming_format,
+                        bits_per_sample: conf.bits_per_sample,
+                        loudness: Some(loudness(&conf.dsp_chain)),
+                    };
+                    save_session(&mut conf, session);
+                    let _ = conf.update_config();
+                    ui_log(&format!("Saved session {name}"));
+                }
             }
         }
     } // while app::wait()
 
     // if anyone is still streaming: stop them first
-    let mut active_players: Vec<String> = Vec::new();
-    for button in &mf.buttons {
-        if button.1.is_set() {
-            if let Some(r) = renderers.iter().find(|r| r.location == *button.0) {
-                active_players.push(r.remote_addr.clone());
-                r.stop_play(&ui_log);
-            }
-        }
-    }
+    let playing: Vec<Renderer> = renderers
+        .iter()
+        .filter(|r| mf.buttons.get(&r.location).is_some_and(|b| b.is_set()))
+        .cloned()
+        .collect();
     // remember active players in config for auto_reconnect
-    {
-        let mut config = CONFIG.write();
-        config.active_renderers = active_players;
-        let _ = config.update_config();
-    }
-    // and now wait some time for them to stop the HTTP streaming connection too
-    for _ in 0..50 {
-        if CLIENTS.read().len() == 0 {
-            info!("No active HTTP streaming connections - exiting.");
-            break;
-        }
-        thread::sleep(Duration::from_millis(100));
-    }
-    if CLIENTS.read().len() > 0 {
-        info!("Time-out waiting for HTTP streaming shutdown - exiting.");
-    }
+    CONFIG.write().active_renderers = playing.iter().map(|r| r.remote_addr.clone()).collect();
+    // and wait some time for them to stop the HTTP streaming connection too
+    std::process::exit(graceful_shutdown(&playing));
 }
 
 fn app_restart(mf: &MainForm) -> i32 {
@@ -421,59 +913,104 @@ fn app_restart(mf: &MainForm) -> i32 {
 fn run_ssdp_updater(ssdp_tx: &Sender<MessageType>, ssdp_interval_mins: f64) {
     // the hashmap used to detect new renderers
     let mut rmap: HashMap<String, Renderer> = HashMap::new();
+    // the known renderers are added to the first discovery
+    let mut known = known_renderers(&ui_log);
+    // the known renderers are refreshed with unicast between the multicast discoveries
+    let mut refresh = SsdpRefresh::default();
     loop {
-        let renderers = discover(&rmap, &ui_log).unwrap_or_default();
+        let mut renderers = std::mem::take(&mut known);
+        if refresh.multicast_due() {
+            renderers.extend(discover(&rmap, &ui_log).unwrap_or_default());
+        } else {
+            for (location, available) in refresh.refresh(rmap.values()) {
+                ssdp_tx
+                    .send(MessageType::AvailabilityMessage(location, available))
+                    .unwrap();
+                app::awake();
+            }
+        }
         for r in &renderers {
             rmap.entry(r.location.clone()).or_insert_with(|| {
                 info!(
                     "Found new renderer {} {}  at {}",
                     r.dev_name, r.dev_model, r.remote_addr
                 );
+                record_renderer(r);
                 ssdp_tx.send(MessageType::SsdpMessage(r.clone())).unwrap();
                 app::awake();
                 thread::yield_now();
                 r.clone()
             });
         }
-        thread::sleep(Duration::from_millis(
+        wait_for_ssdp_discovery(Duration::from_millis(
             (ssdp_interval_mins * 60.0 * 1000.0) as u64,
         ));
     }
 }
 
-fn run_rms_monitor(
-    wd: WavData,
-    rms_receiver: &Receiver<Vec<f32>>,
-    mut rms_frame_l: Progress,
-    mut rms_frame_r: Progress,
-) {
-    // compute # of samples needed to get a 10 Hz refresh rate
-    let samples_per_update = ((wd.sample_rate.0 * u32::from(wd.channels)) / 10) as usize;
-    let mut total_samples = 0usize;
-    let mut sum_l = 0f64;
-    let mut sum_r = 0f64;
+fn run_rms_monitor(wd: WavData, rms_receiver: &Receiver<Vec<f32>>, monitor: RmsMonitorWidgets) {
+    let RmsMonitorWidgets {
+        mut rms_mon_l,
+        mut rms_mon_r,
+        mut clip_led,
+        meter_state,
+        mut spectrum,
+        spectrum_levels,
+    } = monitor;
+    let mut rms_window = RmsWindow::new();
+    let mut frames = 0usize;
+    let mut peak_l = 0f32;
+    let mut peak_r = 0f32;
+    let mut clips = 0u64;
+    let mut analyzer = SpectrumAnalyzer::new(wd.sample_rate.0);
+    let mut mqtt_rms = RmsPublisher::new(&wd);
     while let Ok(samples) = rms_receiver.recv() {
-        total_samples += samples.len();
-        // sum left channel samples
-        sum_l = samples.iter().step_by(2).fold(sum_l, |acc, x| {
-            let v = f64::from(i16::from_sample(*x));
-            acc + (v * v)
-        });
-        // sum right channel samples
-        sum_r = samples.iter().skip(1).step_by(2).fold(sum_r, |acc, x| {
-            let v = f64::from(i16::from_sample(*x));
-            acc + (v * v)
-        });
-        // compute and show current RMS values if enough samples collected
-        if total_samples >= samples_per_update {
-            let samples_per_channel = (total_samples / wd.channels as usize) as f64;
-            let rms_l = (sum_l / samples_per_channel).sqrt();
-            let rms_r = (sum_r / samples_per_channel).sqrt();
-            total_samples = 0;
-            sum_l = 0.0;
-            sum_r = 0.0;
-            rms_frame_l.set_value(rms_l);
-            rms_frame_r.set_value(rms_r);
+        // the spectrum analyzer has its own refresh rate
+        if RUN_SPECTRUM_ANALYZER.load(Ordering::Acquire) {
+            if let Some(levels) = analyzer.push(&samples) {
+                *spectrum_levels.lock() = levels;
+                spectrum.redraw();
+                app::awake();
+            }
+        }
+        // MQTT gets the RMS levels even when the RMS monitor is off
+        mqtt_rms.push(&samples);
+        if !CONFIG.read().monitor_rms {
+            continue;
+        }
+        // the configured ballistics and scale apply immediately
+        let settings = MeterSettings::from_config(&CONFIG.read());
+        let window_frames = (wd.sample_rate.0 * settings.window_msec / 1000) as usize;
+        rms_window.push(&samples, window_frames);
+        frames += samples.len() / 2;
+        // the sample peaks and the clipped samples
+        let (peak, clipped_l) = peak_and_clips(samples.iter().step_by(2));
+        peak_l = peak_l.max(peak);
+        let (peak, clipped_r) = peak_and_clips(samples.iter().skip(1).step_by(2));
+        peak_r = peak_r.max(peak);
+        clips += clipped_l + clipped_r;
+        // show the current RMS values at the refresh rate
+        if frames >= (wd.sample_rate.0 / settings.refresh_hz) as usize {
+            frames = 0;
+            let (rms_l, rms_r) = rms_window.rms();
+            let total_clips = {
+                let mut state = meter_state.lock();
+                state.peak_l.update(f64::from(peak_l), settings.refresh_hz);
+                state.peak_r.update(f64::from(peak_r), settings.refresh_hz);
+                state.clips += clips;
+                state.scale = settings.scale;
+                state.clips
+            };
+            if clips > 0 {
+                clip_led.set_color(Color::Red);
+                clip_led.set_label(&total_clips.to_string());
+                clip_led.redraw();
+            }
+            peak_l = 0.0;
+            peak_r = 0.0;
+            clips = 0;
+            rms_mon_l.set_value(settings.scale.fraction(rms_l));
+            rms_mon_r.set_value(settings.scale.fraction(rms_r));
             app::awake();
         }
     }
@@ -0,0 +1,350 @@
+//! The DSP chain: the ordered processors that are applied to the captured samples.
+//!
+//! The chain is declared in the config file as `[[configuration.dsp_chain]]` stages, each
+//! with the name of a processor and its parameters. The processors are created by name
+//! from the `REGISTRY`, so a new processor only needs an implementation of `DspProcessor`
+//! and an entry in the registry. The chain is applied by the `wave_reader`, so it affects
+//! every client, and it is rebuilt when it is changed while streaming, the processors of
+//! the unchanged stages are kept with their state. In bit-perfect mode it is not applied.
+//!
+//! Dither can only be applied when a stream is reduced to 16 bit, so a "Dither" stage is
+//! always the last stage, and it overrides the `dither` option.
+
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    dsp::{
+        compressor::{self, COMPRESSOR},
+        crossfeed, eq, gain, limiter,
+    },
+    enums::streaming::Dither,
+    globals::statics::{CONFIG, STREAM_PARAMS},
+    utils::{configuration::DspStage, ui_logger::ui_log},
+};
+
+/// a processor of the DSP chain
+pub trait DspProcessor: Send {
+    /// process the interleaved samples in place
+    fn process(&mut self, samples: &mut [f32]);
+    /// the processor and its settings, for the log
+    fn describe(&self) -> String;
+}
+
+/// creates a processor from the parameters of a stage, for the sample rate and channels
+type Factory = fn(&DspParams, u32, u16) -> Result<Box<dyn DspProcessor>, String>;
+
+/// the processors by name
+const REGISTRY: &[(&str, Factory)] = &[
+    ("Gain", gain::create),
+    ("Eq", eq::create),
+    ("Limiter", limiter::create),
+    ("Crossfeed", crossfeed::create),
+    (COMPRESSOR, compressor::create),
+];
+
+/// the name of the dither stage
+pub const DITHER: &str = "Dither";
+
+/// a processor of the chain and the stage it was created from
+type ChainEntry = (DspStage, Box<dyn DspProcessor>);
+
+/// the processors of the current chain, for the sample rate and channels of the stream
+struct Chain {
+    stream: Option<(u32, u16)>,
+    processors: Vec<ChainEntry>,
+}
+
+/// the current chain
+static DSP_CHAIN: Mutex<Chain> = Mutex::new(Chain {
+    stream: None,
+    processors: Vec::new(),
+});
+/// true if the chain has processors
+static DSP_ACTIVE: AtomicBool = AtomicBool::new(false);
+/// the dither of the dither stage, if any
+static DSP_DITHER: RwLock<Option<Dither>> = RwLock::new(None);
+
+/// the names of the processors, for the GUI
+#[must_use]
+pub fn processor_names() -> Vec<&'static str> {
+    REGISTRY
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(std::iter::once(DITHER))
+        .collect()
+}
+
+/// the parameters of a stage
+pub struct DspParams<'a>(&'a BTreeMap<String, toml::Value>);
+
+impl DspParams<'_> {
+    /// a number parameter, integers are accepted too
+    pub fn f32(&self, name: &str, default: f32) -> Result<f32, String> {
+        match self.0.get(name) {
+            None => Ok(default),
+            Some(toml::Value::Float(f)) => Ok(*f as f32),
+            Some(toml::Value::Integer(i)) => Ok(*i as f32),
+            Some(v) => Err(format!("{name} must be a number, not {v}")),
+        }
+    }
+
+    /// a text parameter
+    pub fn str(&self, name: &str, default: &str) -> Result<String, String> {
+        match self.0.get(name) {
+            None => Ok(default.to_string()),
+            Some(toml::Value::String(s)) => Ok(s.clone()),
+            Some(v) => Err(format!("{name} must be a text, not {v}")),
+        }
+    }
+}
+
+/// a stage with its parameters, for the log and the GUI
+#[must_use]
+pub fn describe_stage(stage: &DspStage) -> String {
+    let params: Vec<String> = stage
+        .params
+        .iter()
+        .map(|(name, value)| format!("{name} = {value}"))
+        .collect();
+    let mut description = stage.processor.clone();
+    if !params.is_empty() {
+        description.push_str(&format!(" ({})", params.join(", ")));
+    }
+    if !stage.enabled {
+        description.push_str(" [disabled]");
+    }
+    description
+}
+
+/// true if the loudness option is on: a compressor stage is enabled
+#[must_use]
+pub fn loudness(stages: &[DspStage]) -> bool {
+    stages
+        .iter()
+        .any(|s| s.enabled && s.processor.eq_ignore_ascii_case(COMPRESSOR))
+}
+
+/// switch the loudness option: enable or disable the compressor stages, a compressor
+/// with the default parameters is added before a limiter or dither stage if there is none,
+/// followed by a limiter if there is none, so that the make-up gain can't clip
+/// returns true if the chain changed
+pub fn set_loudness(stages: &mut Vec<DspStage>, on: bool) -> bool {
+    if loudness(stages) == on {
+        return false;
+    }
+    let is_compressor = |s: &DspStage| s.processor.eq_ignore_ascii_case(COMPRESSOR);
+    if stages.iter().any(is_compressor) {
+        stages
+            .iter_mut()
+            .filter(|s| is_compressor(s))
+            .for_each(|s| s.enabled = on);
+    } else {
+        let pos = stages
+            .iter()
+            .position(|s| {
+                s.processor.eq_ignore_ascii_case("Limiter")
+                    || s.processor.eq_ignore_ascii_case(DITHER)
+            })
+            .unwrap_or(stages.len());
+        stages.insert(
+            pos,
+            DspStage {
+                processor: COMPRESSOR.to_string(),
+                enabled: true,
+                params: BTreeMap::new(),
+            },
+        );
+        let limited = stages[pos + 1..]
+            .iter()
+            .any(|s| s.enabled && s.processor.eq_ignore_ascii_case("Limiter"));
+        if !limited {
+            stages.insert(
+                pos + 1,
+                DspStage {
+                    processor: "Limiter".to_string(),
+                    enabled: true,
+                    params: BTreeMap::new(),
+                },
+            );
+        }
+    }
+    true
+}
+
+/// the processors of the enabled stages, the dither of a dither stage, and the errors,
+/// the processors of the stages that are in `reuse` are taken from it instead of created
+fn build_chain(
+    stages: &[DspStage],
+    sample_rate: u32,
+    channels: u16,
+    mut reuse: Vec<ChainEntry>,
+) -> (Vec<ChainEntry>, Option<Dither>, Vec<String>) {
+    let mut processors = Vec::new();
+    let mut dither = None;
+    let mut errors = Vec::new();
+    let enabled: Vec<&DspStage> = stages.iter().filter(|s| s.enabled).collect();
+    for (n, stage) in enabled.iter().enumerate() {
+        let params = DspParams(&stage.params);
+        if stage.processor.eq_ignore_ascii_case(DITHER) {
+            match params.str("mode", "Tpdf").map(|m| Dither::from_str(&m)) {
+                Ok(Ok(mode)) => dither = Some(mode),
+                _ => errors.push(format!("{DITHER}: mode must be Off, Tpdf or Shaped")),
+            }
+            if n + 1 < enabled.len() {
+                errors.push(format!(
+                    "{DITHER} is always applied last, when the samples are reduced to 16 bit"
+                ));
+            }
+            continue;
+        }
+        if let Some(pos) = reuse.iter().position(|(s, _)| s == *stage) {
+            processors.push(reuse.remove(pos));
+            continue;
+        }
+        match REGISTRY
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&stage.processor))
+        {
+            Some((_, create)) => match create(&params, sample_rate, channels) {
+                Ok(processor) => processors.push(((*stage).clone(), processor)),
+                Err(e) => errors.push(format!("{}: {e}", stage.processor)),
+            },
+            None => errors.push(format!(
+                "unknown processor {}, use one of {}",
+                stage.processor,
+                processor_names().join(", ")
+            )),
+        }
+    }
+    (processors, dither, errors)
+}
+
+/// (re)build the DSP chain from the configuration, for the running capture
+pub fn update_dsp_chain() {
+    let (stages, bit_perfect) = {
+        let conf = CONFIG.read();
+        (conf.dsp_chain.clone(), conf.bit_perfect)
+    };
+    let Some((sample_rate, channels)) = *STREAM_PARAMS.read() else {
+        return;
+    };
+    let mut chain = DSP_CHAIN.lock();
+    // the processors of the unchanged stages keep their state, e.g. the filter history
+    let reuse = if chain.stream == Some((sample_rate, channels)) {
+        std::mem::take(&mut chain.processors)
+    } else {
+        Vec::new()
+    };
+    // no DSP in bit-perfect mode
+    let (processors, dither, errors) = if bit_perfect {
+        (Vec::new(), None, Vec::new())
+    } else {
+        build_chain(&stages, sample_rate, channels, reuse)
+    };
+    for e in &errors {
+        ui_log(&format!("*E*E*> DSP chain: {e}"));
+    }
+    if !processors.is_empty() || dither.is_some() {
+        let mut described: Vec<String> = processors.iter().map(|(_, p)| p.describe()).collect();
+        if let Some(dither) = dither {
+            described.push(format!("{DITHER} {dither}"));
+        }
+        ui_log(&format!("DSP chain: {}", described.join(" -> ")));
+    }
+    DSP_ACTIVE.store(!processors.is_empty(), Ordering::Relaxed);
+    *chain = Chain {
+        stream: Some((sample_rate, channels)),
+        processors,
+    };
+    *DSP_DITHER.write() = dither;
+}
+
+/// true if the DSP chain changes the samples
+#[must_use]
+pub fn dsp_chain_active() -> bool {
+    DSP_ACTIVE.load(Ordering::Relaxed)
+}
+
+/// the dither of the dither stage of the chain, if any
+#[must_use]
+pub fn dsp_dither() -> Option<Dither> {
+    *DSP_DITHER.read()
+}
+
+/// apply the DSP chain to the interleaved samples
+pub fn apply_dsp_chain(samples: &mut [f32]) {
+    if !dsp_chain_active() {
+        return;
+    }
+    for (_, processor) in &mut DSP_CHAIN.lock().processors {
+        processor.process(samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(processor: &str, params: &[(&str, toml::Value)]) -> DspStage {
+        DspStage {
+            processor: processor.to_string(),
+            enabled: true,
+            params: params
+                .iter()
+                .map(|(k, v)| ((*k).to_string(), v.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_build_chain() {
+        let mut stages = vec![
+            stage("gain", &[("gain_db", toml::Value::Integer(-6))]),
+            stage(DITHER, &[("mode", toml::Value::String("Shaped".into()))]),
+            stage("Limiter", &[]),
+            stage("Reverb", &[]),
+            stage("Gain", &[("gain_db", toml::Value::String("loud".into()))]),
+        ];
+        stages[2].enabled = false;
+        let (processors, dither, errors) = build_chain(&stages, 44100, 2, Vec::new());
+        assert_eq!(processors.len(), 1);
+        assert_eq!(dither, Some(Dither::Shaped));
+        // the dither is not last, the unknown processor, the invalid gain
+        assert_eq!(errors.len(), 3);
+        let mut samples = vec![0.5, -0.5];
+        let mut processors = processors;
+        processors[0].1.process(&mut samples);
+        assert!((samples[0] - 0.2506).abs() < 0.001);
+        // the processor of an unchanged stage is reused
+        let gain: *const dyn DspProcessor = &*processors[0].1;
+        let (processors, _, _) = build_chain(&stages, 44100, 2, processors);
+        assert!(std::ptr::addr_eq(gain, &*processors[0].1));
+        assert_eq!(describe_stage(&stages[2]), "Limiter [disabled]".to_string());
+    }
+
+    #[test]
+    fn test_loudness() {
+        let mut stages = vec![stage("Gain", &[]), stage("limiter", &[])];
+        assert!(!loudness(&stages));
+        // a compressor is added before the limiter, and disabled and enabled again
+        assert!(set_loudness(&mut stages, true));
+        assert_eq!(stages[1].processor, COMPRESSOR);
+        assert!(!set_loudness(&mut stages, true));
+        assert!(set_loudness(&mut stages, false));
+        assert!(!loudness(&stages));
+        assert_eq!(stages.len(), 3);
+        assert!(set_loudness(&mut stages, true));
+        assert_eq!(stages.len(), 3);
+        assert!(stages[1].enabled);
+        // a limiter is added after the compressor if there is none
+        let mut stages = vec![stage("Gain", &[])];
+        assert!(set_loudness(&mut stages, true));
+        assert_eq!(stages[1].processor, COMPRESSOR);
+        assert_eq!(stages[2].processor, "Limiter");
+    }
+}
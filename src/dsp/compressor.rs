@@ -0,0 +1,135 @@
+//! A dynamic range compressor for listening in a noisy environment (a kitchen, a
+//! workshop), where the quiet passages vanish under the background noise.
+//!
+//! The level above the threshold is reduced by the ratio, with the attack and release
+//! times, and the make-up gain raises everything again, so the quiet passages become
+//! louder. The gain follows the loudest channel, so the stereo image doesn't shift.
+
+use crate::dsp::chain::{DspParams, DspProcessor};
+
+/// the name of the compressor stage, it is switched with the loudness option
+pub const COMPRESSOR: &str = "Compressor";
+
+/// the lowest level that is followed, to avoid the log of 0
+const MIN_LEVEL_DB: f32 = -120.0;
+
+pub struct Compressor {
+    threshold_db: f32,
+    ratio: f32,
+    attack_msec: f32,
+    release_msec: f32,
+    makeup_db: f32,
+    // the part of the difference with the previous level that is kept per frame
+    attack: f32,
+    release: f32,
+    // the followed level in dB
+    level_db: f32,
+    channels: usize,
+}
+
+impl Compressor {
+    #[must_use]
+    pub fn new(
+        threshold_db: f32,
+        ratio: f32,
+        attack_msec: f32,
+        release_msec: f32,
+        makeup_db: f32,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Compressor {
+        let coefficient = |msec: f32| (-1000.0 / (msec.max(0.1) * sample_rate as f32)).exp();
+        Compressor {
+            threshold_db: threshold_db.min(0.0),
+            ratio: ratio.max(1.0),
+            attack_msec,
+            release_msec,
+            makeup_db,
+            attack: coefficient(attack_msec),
+            release: coefficient(release_msec),
+            level_db: MIN_LEVEL_DB,
+            channels: usize::from(channels.max(1)),
+        }
+    }
+
+    /// the make-up gain that brings the compressed level halfway back to full scale
+    #[must_use]
+    pub fn auto_makeup_db(threshold_db: f32, ratio: f32) -> f32 {
+        -threshold_db.min(0.0) * (1.0 - 1.0 / ratio.max(1.0)) / 2.0
+    }
+}
+
+impl DspProcessor for Compressor {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(self.channels) {
+            let peak = frame.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+            let peak_db = (20.0 * peak.log10()).max(MIN_LEVEL_DB);
+            let coefficient = if peak_db > self.level_db {
+                self.attack
+            } else {
+                self.release
+            };
+            self.level_db = peak_db + (self.level_db - peak_db) * coefficient;
+            let over_db = (self.level_db - self.threshold_db).max(0.0);
+            let gain_db = self.makeup_db - over_db * (1.0 - 1.0 / self.ratio);
+            let gain = 10f32.powf(gain_db / 20.0);
+            frame.iter_mut().for_each(|s| *s *= gain);
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "{COMPRESSOR} {:.1} dB {}:1, attack {} ms, release {} ms, make-up {:+.1} dB",
+            self.threshold_db, self.ratio, self.attack_msec, self.release_msec, self.makeup_db
+        )
+    }
+}
+
+/// `threshold_db`: the level above which the compression starts in dB (-30), `ratio`:
+/// the compression ratio (3), `attack_msec` (10) and `release_msec` (200) in
+/// milliseconds, `makeup_db`: the make-up gain in dB (halfway back to full scale)
+pub fn create(
+    params: &DspParams,
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Box<dyn DspProcessor>, String> {
+    let threshold_db = params.f32("threshold_db", -30.0)?;
+    let ratio = params.f32("ratio", 3.0)?;
+    if ratio < 1.0 {
+        return Err("ratio must be at least 1".to_string());
+    }
+    Ok(Box::new(Compressor::new(
+        threshold_db,
+        ratio,
+        params.f32("attack_msec", 10.0)?,
+        params.f32("release_msec", 200.0)?,
+        params.f32("makeup_db", Compressor::auto_makeup_db(threshold_db, ratio))?,
+        sample_rate,
+        channels,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the output level in dB of a constant input level after the attack
+    fn output_db(compressor: &mut Compressor, input_db: f32) -> f32 {
+        let level = 10f32.powf(input_db / 20.0);
+        let mut samples = vec![level; 2 * 4800];
+        compressor.process(&mut samples);
+        20.0 * samples[samples.len() - 1].log10()
+    }
+
+    #[test]
+    fn test_compressor() {
+        let mut compressor = Compressor::new(-30.0, 3.0, 1.0, 50.0, 0.0, 48000, 2);
+        // below the threshold nothing changes, above it the level is reduced by the ratio
+        assert!((output_db(&mut compressor, -40.0) + 40.0).abs() < 0.01);
+        assert!((output_db(&mut compressor, -6.0) + 22.0).abs() < 0.1);
+        // the make-up gain makes the quiet passages louder
+        assert!((Compressor::auto_makeup_db(-30.0, 3.0) - 10.0).abs() < 0.001);
+        let mut loud = Compressor::new(-30.0, 3.0, 1.0, 50.0, 10.0, 48000, 2);
+        assert!((output_db(&mut loud, -40.0) + 30.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,131 @@
+//! Headphone crossfeed: a low-passed, delayed and attenuated copy of each channel is
+//! mixed into the other channel, like the sound of a loudspeaker also reaches the
+//! opposite ear, so that hard panned recordings are less tiring on headphones.
+//!
+//! The output is scaled so that a centered (mono) signal keeps its level.
+
+use crate::dsp::chain::{DspParams, DspProcessor};
+
+/// the level range of the crossfed signal in dB
+const MIN_LEVEL_DB: f32 = -24.0;
+const MAX_LEVEL_DB: f32 = -1.0;
+/// the longest delay of the crossfed signal
+const MAX_DELAY_USEC: f32 = 2000.0;
+
+pub struct Crossfeed {
+    level_db: f32,
+    delay_usec: f32,
+    cutoff: f32,
+    level: f32,
+    // the scale that keeps the level of a centered signal
+    scale: f32,
+    // the one-pole low-pass coefficient and the low-passed left and right samples
+    lowpass: f32,
+    filtered: [f32; 2],
+    // the delay line of the low-passed frames, empty without a delay
+    delay: Vec<[f32; 2]>,
+    pos: usize,
+}
+
+impl Crossfeed {
+    #[must_use]
+    pub fn new(level_db: f32, delay_usec: f32, cutoff: f32, sample_rate: u32) -> Crossfeed {
+        let level_db = level_db.clamp(MIN_LEVEL_DB, MAX_LEVEL_DB);
+        let delay_usec = delay_usec.clamp(0.0, MAX_DELAY_USEC);
+        let level = 10f32.powf(level_db / 20.0);
+        let delay_frames = (delay_usec * sample_rate as f32 / 1_000_000.0).round() as usize;
+        Crossfeed {
+            level_db,
+            delay_usec,
+            cutoff,
+            level,
+            scale: 1.0 / (1.0 + level),
+            lowpass: 1.0 - (-2.0 * std::f32::consts::PI * cutoff / sample_rate as f32).exp(),
+            filtered: [0.0; 2],
+            delay: vec![[0.0; 2]; delay_frames],
+            pos: 0,
+        }
+    }
+}
+
+impl DspProcessor for Crossfeed {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(2) {
+            for (f, s) in self.filtered.iter_mut().zip(frame.iter()) {
+                *f += self.lowpass * (*s - *f);
+            }
+            // the delay line holds the low-passed frame of `delay_frames` ago
+            let [left, right] = if self.delay.is_empty() {
+                self.filtered
+            } else {
+                let delayed = std::mem::replace(&mut self.delay[self.pos], self.filtered);
+                self.pos = (self.pos + 1) % self.delay.len();
+                delayed
+            };
+            frame[0] = (frame[0] + self.level * right) * self.scale;
+            frame[1] = (frame[1] + self.level * left) * self.scale;
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "Crossfeed {:.1} dB, {} us, {} Hz",
+            self.level_db, self.delay_usec, self.cutoff
+        )
+    }
+}
+
+/// `level_db`: the level of the crossfed signal in dB (-6), `delay_usec`: its delay in
+/// microseconds (300), `cutoff`: the low-pass frequency of the crossfed signal in Hz (700),
+/// only for a stereo stream
+pub fn create(
+    params: &DspParams,
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Box<dyn DspProcessor>, String> {
+    if channels != 2 {
+        return Err(format!("only for stereo, not {channels} channels"));
+    }
+    let cutoff = params.f32("cutoff", 700.0)?;
+    if cutoff <= 0.0 || cutoff >= sample_rate as f32 / 2.0 {
+        return Err(format!(
+            "cutoff must be between 0 and {} Hz",
+            sample_rate / 2
+        ));
+    }
+    Ok(Box::new(Crossfeed::new(
+        params.f32("level_db", -6.0)?,
+        params.f32("delay_usec", 300.0)?,
+        cutoff,
+        sample_rate,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossfeed() {
+        // a centered signal keeps its level
+        let mut crossfeed = Crossfeed::new(-6.0, 300.0, 700.0, 44100);
+        let mut centered = vec![0.5f32; 2 * 4410];
+        crossfeed.process(&mut centered);
+        assert!((centered[centered.len() - 2] - 0.5).abs() < 0.001);
+        assert!((centered[centered.len() - 1] - 0.5).abs() < 0.001);
+        // a hard left signal reaches the right channel after the delay, at the level
+        let mut crossfeed = Crossfeed::new(-6.0, 300.0, 700.0, 44100);
+        let mut left: Vec<f32> = [0.5f32, 0.0].repeat(4410);
+        crossfeed.process(&mut left);
+        assert_eq!(left[1], 0.0);
+        let right = left[left.len() - 1];
+        let level = 10f32.powf(-6.0 / 20.0);
+        assert!((right - 0.5 * level / (1.0 + level)).abs() < 0.001);
+        assert!(left[left.len() - 2] > right);
+        // without a delay the right channel starts immediately
+        let mut crossfeed = Crossfeed::new(-6.0, 0.0, 700.0, 44100);
+        let mut left = [0.5f32, 0.0];
+        crossfeed.process(&mut left);
+        assert!(left[1] > 0.0);
+    }
+}
@@ -0,0 +1,197 @@
+//! A band of a parametric equalizer: a peaking, low shelf or high shelf biquad filter
+//! (after the Audio EQ Cookbook of Robert Bristow-Johnson). Every band is a stage of
+//! the DSP chain, so an EQ with more bands is a series of "Eq" stages.
+
+use std::{f64::consts::PI, fmt, str::FromStr};
+
+use crate::dsp::chain::{DspParams, DspProcessor};
+
+/// the gain range of a band in dB
+const MAX_GAIN_DB: f32 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EqType {
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+impl fmt::Display for EqType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EqType::Peak => write!(f, "Peak"),
+            EqType::LowShelf => write!(f, "LowShelf"),
+            EqType::HighShelf => write!(f, "HighShelf"),
+        }
+    }
+}
+
+impl FromStr for EqType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "peak" => Ok(EqType::Peak),
+            "lowshelf" => Ok(EqType::LowShelf),
+            "highshelf" => Ok(EqType::HighShelf),
+            _ => Err(format!("type must be Peak, LowShelf or HighShelf, not {s}")),
+        }
+    }
+}
+
+pub struct EqBand {
+    eq_type: EqType,
+    frequency: f32,
+    gain_db: f32,
+    q: f32,
+    // the normalized coefficients b0, b1, b2, a1, a2
+    coefs: [f64; 5],
+    // the transposed direct form II state per channel
+    state: Vec<[f64; 2]>,
+}
+
+impl EqBand {
+    #[must_use]
+    pub fn new(
+        eq_type: EqType,
+        frequency: f32,
+        gain_db: f32,
+        q: f32,
+        sample_rate: u32,
+        channels: u16,
+    ) -> EqBand {
+        let gain_db = gain_db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB);
+        EqBand {
+            eq_type,
+            frequency,
+            gain_db,
+            q,
+            coefs: coefficients(eq_type, frequency, gain_db, q, sample_rate),
+            state: vec![[0.0; 2]; usize::from(channels.max(1))],
+        }
+    }
+}
+
+/// the biquad coefficients, normalized by a0
+fn coefficients(
+    eq_type: EqType,
+    frequency: f32,
+    gain_db: f32,
+    q: f32,
+    sample_rate: u32,
+) -> [f64; 5] {
+    let a = 10f64.powf(f64::from(gain_db) / 40.0);
+    let w0 = 2.0 * PI * f64::from(frequency) / f64::from(sample_rate);
+    let (sin, cos) = w0.sin_cos();
+    let alpha = sin / (2.0 * f64::from(q));
+    let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+    let [b0, b1, b2, a0, a1, a2] = match eq_type {
+        EqType::Peak => [
+            1.0 + alpha * a,
+            -2.0 * cos,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        ],
+        EqType::LowShelf => [
+            a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+            (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+        ],
+        EqType::HighShelf => [
+            a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+            (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+        ],
+    };
+    [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0]
+}
+
+impl DspProcessor for EqBand {
+    fn process(&mut self, samples: &mut [f32]) {
+        let [b0, b1, b2, a1, a2] = self.coefs;
+        let channels = self.state.len();
+        for frame in samples.chunks_exact_mut(channels) {
+            for (sample, z) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let x = f64::from(*sample);
+                let y = b0 * x + z[0];
+                z[0] = b1 * x - a1 * y + z[1];
+                z[1] = b2 * x - a2 * y;
+                *sample = y as f32;
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "Eq {} {} Hz {:+.1} dB Q {}",
+            self.eq_type, self.frequency, self.gain_db, self.q
+        )
+    }
+}
+
+/// `type`: Peak, LowShelf or HighShelf (Peak), `frequency`: the center or corner
+/// frequency in Hz, `gain_db`: the gain in dB (0), `q`: the Q (0.707)
+pub fn create(
+    params: &DspParams,
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Box<dyn DspProcessor>, String> {
+    let eq_type = EqType::from_str(&params.str("type", "Peak")?)?;
+    let frequency = params.f32("frequency", 0.0)?;
+    if frequency <= 0.0 || frequency >= sample_rate as f32 / 2.0 {
+        return Err(format!(
+            "frequency must be between 0 and {} Hz",
+            sample_rate / 2
+        ));
+    }
+    let q = params.f32("q", std::f32::consts::FRAC_1_SQRT_2)?;
+    if q <= 0.0 {
+        return Err("q must be more than 0".to_string());
+    }
+    Ok(Box::new(EqBand::new(
+        eq_type,
+        frequency,
+        params.f32("gain_db", 0.0)?,
+        q,
+        sample_rate,
+        channels,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the gain of a band for a constant (DC) signal
+    fn dc_gain(band: &mut EqBand) -> f32 {
+        let mut samples = vec![0.25f32; 2 * 20000];
+        band.process(&mut samples);
+        samples[samples.len() - 1] / 0.25
+    }
+
+    #[test]
+    fn test_eq_band() {
+        // a peak of 0 dB doesn't change the signal
+        let mut flat = EqBand::new(EqType::Peak, 1000.0, 0.0, 1.0, 44100, 2);
+        let mut samples = vec![0.5, -0.25, 0.125, 0.0];
+        flat.process(&mut samples);
+        assert_eq!(samples, vec![0.5, -0.25, 0.125, 0.0]);
+        // a low shelf boosts DC by its gain, a high shelf or a peak doesn't
+        let mut low = EqBand::new(EqType::LowShelf, 100.0, 6.0, 0.707, 44100, 2);
+        assert!((dc_gain(&mut low) - 10f32.powf(6.0 / 20.0)).abs() < 0.01);
+        let mut high = EqBand::new(EqType::HighShelf, 8000.0, 6.0, 0.707, 44100, 2);
+        assert!((dc_gain(&mut high) - 1.0).abs() < 0.01);
+        let mut peak = EqBand::new(EqType::Peak, 1000.0, -6.0, 1.0, 48000, 2);
+        assert!((dc_gain(&mut peak) - 1.0).abs() < 0.01);
+        assert_eq!(EqType::from_str("HIGHSHELF"), Ok(EqType::HighShelf));
+        assert!(EqType::from_str("notch").is_err());
+    }
+}
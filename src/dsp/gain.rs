@@ -0,0 +1,42 @@
+//! A fixed gain, e.g. to make room for an EQ boost or to match the level of another source.
+
+use crate::dsp::chain::{DspParams, DspProcessor};
+
+/// the gain range in dB
+const MIN_GAIN_DB: f32 = -60.0;
+const MAX_GAIN_DB: f32 = 24.0;
+
+pub struct Gain {
+    gain_db: f32,
+    gain: f32,
+}
+
+impl Gain {
+    #[must_use]
+    pub fn new(gain_db: f32) -> Gain {
+        let gain_db = gain_db.clamp(MIN_GAIN_DB, MAX_GAIN_DB);
+        Gain {
+            gain_db,
+            gain: 10f32.powf(gain_db / 20.0),
+        }
+    }
+}
+
+impl DspProcessor for Gain {
+    fn process(&mut self, samples: &mut [f32]) {
+        samples.iter_mut().for_each(|s| *s *= self.gain);
+    }
+
+    fn describe(&self) -> String {
+        format!("Gain {:+.1} dB", self.gain_db)
+    }
+}
+
+/// `gain_db`: the gain in dB (0)
+pub fn create(
+    params: &DspParams,
+    _sample_rate: u32,
+    _channels: u16,
+) -> Result<Box<dyn DspProcessor>, String> {
+    Ok(Box::new(Gain::new(params.f32("gain_db", 0.0)?)))
+}
@@ -0,0 +1,92 @@
+//! A peak limiter, so that a gain or an EQ boost in the chain doesn't clip.
+//!
+//! The gain follows a peak above the threshold immediately, so the output never exceeds
+//! the threshold, and it recovers with the release time.
+
+use crate::dsp::chain::{DspParams, DspProcessor};
+
+pub struct Limiter {
+    threshold_db: f32,
+    release_msec: f32,
+    threshold: f32,
+    // the part of the remaining gain reduction that is kept per frame
+    release: f32,
+    gain: f32,
+    channels: usize,
+}
+
+impl Limiter {
+    #[must_use]
+    pub fn new(threshold_db: f32, release_msec: f32, sample_rate: u32, channels: u16) -> Limiter {
+        let threshold_db = threshold_db.min(0.0);
+        let release_frames = (release_msec.max(1.0) / 1000.0) * sample_rate as f32;
+        Limiter {
+            threshold_db,
+            release_msec,
+            threshold: 10f32.powf(threshold_db / 20.0),
+            release: (-1.0 / release_frames).exp(),
+            gain: 1.0,
+            channels: usize::from(channels.max(1)),
+        }
+    }
+}
+
+impl DspProcessor for Limiter {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(self.channels) {
+            let peak = frame.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+            let target = if peak > self.threshold {
+                self.threshold / peak
+            } else {
+                1.0
+            };
+            self.gain = if target < self.gain {
+                target
+            } else {
+                target + (self.gain - target) * self.release
+            };
+            frame.iter_mut().for_each(|s| *s *= self.gain);
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "Limiter {:.1} dB, release {} ms",
+            self.threshold_db, self.release_msec
+        )
+    }
+}
+
+/// `threshold_db`: the highest output level in dB (-1), `release_msec`: the release
+/// time in milliseconds (100)
+pub fn create(
+    params: &DspParams,
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Box<dyn DspProcessor>, String> {
+    Ok(Box::new(Limiter::new(
+        params.f32("threshold_db", -1.0)?,
+        params.f32("release_msec", 100.0)?,
+        sample_rate,
+        channels,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter() {
+        let mut limiter = Limiter::new(-6.0, 10.0, 1000, 2);
+        let threshold = 10f32.powf(-6.0 / 20.0);
+        let mut loud = vec![1.0f32, -0.9, 0.2, 0.1];
+        limiter.process(&mut loud);
+        assert!(loud.iter().all(|s| s.abs() <= threshold + 1e-6));
+        // the gain recovers after the peak
+        let mut quiet = vec![0.1f32; 2 * 100];
+        limiter.process(&mut quiet);
+        assert!(quiet[0] < 0.1);
+        assert!((quiet[quiet.len() - 1] - 0.1).abs() < 0.001);
+    }
+}
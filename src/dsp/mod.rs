@@ -0,0 +1,6 @@
+pub mod chain;
+pub mod compressor;
+pub mod crossfeed;
+pub mod eq;
+pub mod gain;
+pub mod limiter;
@@ -1,7 +1,61 @@
-use crate::{openhome::rendercontrol::Renderer, server::streaming_server::StreamerFeedBack};
+use crate::{
+    openhome::{
+        media_server::{MediaObject, MediaServer},
+        rendercontrol::Renderer,
+    },
+    server::{players_api::PlayersRequest, streaming_server::StreamerFeedBack},
+    utils::vu_trigger::VuEvent,
+};
 #[derive(Debug, Clone)]
 pub enum MessageType {
     SsdpMessage(Renderer),
     PlayerMessage(StreamerFeedBack),
     LogMessage(String),
+    MqttMessage(MqttCommand),
+    /// the ip address of a renderer that was stopped outside swyh-rs
+    TransportMessage(String),
+    /// the location of a renderer and the elapsed playing time it reports in seconds,
+    /// None when it stopped playing or does not report it
+    PositionMessage(String, Option<u64>),
+    /// the location of a renderer and if it responds, after it stopped or started responding
+    AvailabilityMessage(String, bool),
+    /// the location of a renderer and its volume, after it was changed outside swyh-rs
+    VolumeMessage(String, i32),
+    /// change the active players, their volume and the format at runtime (/api/players)
+    PlayersMessage(PlayersRequest),
+    /// the streaming format or bit depth was changed while playing
+    FormatMessage,
+    /// the names of the audio devices, after devices were added or removed
+    AudioDevicesMessage(Vec<String>),
+    /// recall the session preset with this name
+    RecallSessionMessage(String),
+    /// save the playing renderers and their volume as a session preset with this name
+    SaveSessionMessage(String),
+    /// the name of a tested renderer or stream and the outcome of the test steps
+    DiagnosisMessage(String, Vec<String>),
+    /// stop the renderers and exit (/api/shutdown)
+    ShutdownMessage,
+    /// the outcome of a media server search or browse for "Play from media server"
+    MediaBrowseMessage(MediaBrowse),
+    /// start the configured renderers on audio activity, or stop the ones it started
+    VuTriggerMessage(VuEvent),
+}
+
+/// the media servers or the children of a container, searched or browsed in the background
+#[derive(Debug, Clone)]
+pub enum MediaBrowse {
+    /// the media servers for the renderer, true if the user asked to search again
+    Servers(Renderer, Vec<MediaServer>, bool),
+    /// the children of the last container in the path, None if the server did not respond
+    Objects(Renderer, MediaServer, Vec<String>, Option<Vec<MediaObject>>),
+}
+
+/// a command received on one of the MQTT command topics,
+/// the renderer is identified by (part of) its name or its ip address,
+/// an empty renderer means the active renderer(s)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MqttCommand {
+    Play(String),
+    Stop(String),
+    Volume(String, u8),
 }
@@ -100,6 +100,63 @@ impl FromStr for StreamSize {
     }
 }
 
+/// the total size in the WAV and RF64 headers of an endless stream
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum HeaderSize {
+    /// the largest size the header can hold
+    #[default]
+    Max,
+    /// a number of bytes of audio data
+    Bytes(u64),
+    /// the size of a virtual duration in seconds
+    Secs(u32),
+}
+
+impl fmt::Display for HeaderSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderSize::Max => write!(f, "max"),
+            HeaderSize::Bytes(bytes) => write!(f, "{bytes}"),
+            HeaderSize::Secs(secs) => write!(f, "{secs}s"),
+        }
+    }
+}
+
+impl FromStr for HeaderSize {
+    type Err = String;
+
+    /// "max", a number of bytes or a number of seconds followed by s, e.g. "3600s"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        if s == "max" {
+            Ok(HeaderSize::Max)
+        } else if let Some(secs) = s.strip_suffix('s') {
+            secs.parse()
+                .map(HeaderSize::Secs)
+                .map_err(|_| format!("invalid header size {s}"))
+        } else {
+            s.parse()
+                .map(HeaderSize::Bytes)
+                .map_err(|_| format!("invalid header size {s}"))
+        }
+    }
+}
+
+impl TryFrom<String> for HeaderSize {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<HeaderSize> for String {
+    fn from(hs: HeaderSize) -> Self {
+        hs.to_string()
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum BitDepth {
     Bits24 = 24,
@@ -136,3 +193,96 @@ impl FromStr for BitDepth {
         }
     }
 }
+
+/// the channels sent to a renderer
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ChannelMode {
+    #[default]
+    Stereo,
+    /// left and right mixed down to mono on both channels
+    Mono,
+    /// the left channel on both channels
+    Left,
+    /// the right channel on both channels
+    Right,
+}
+
+impl fmt::Display for ChannelMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelMode::Stereo => write!(f, "Stereo"),
+            ChannelMode::Mono => write!(f, "Mono"),
+            ChannelMode::Left => write!(f, "Left"),
+            ChannelMode::Right => write!(f, "Right"),
+        }
+    }
+}
+
+impl FromStr for ChannelMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        match s.as_str() {
+            "stereo" => Ok(ChannelMode::Stereo),
+            "mono" => Ok(ChannelMode::Mono),
+            "left" => Ok(ChannelMode::Left),
+            "right" => Ok(ChannelMode::Right),
+            _ => Ok(ChannelMode::Stereo),
+        }
+    }
+}
+
+/// the dither used when reducing the captured samples to 16 bit
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Dither {
+    /// plain rounding
+    #[default]
+    Off,
+    /// triangular (TPDF) dither
+    Tpdf,
+    /// TPDF dither with first order noise shaping
+    Shaped,
+}
+
+impl fmt::Display for Dither {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dither::Off => write!(f, "Off"),
+            Dither::Tpdf => write!(f, "Tpdf"),
+            Dither::Shaped => write!(f, "Shaped"),
+        }
+    }
+}
+
+impl FromStr for Dither {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        match s.as_str() {
+            "tpdf" => Ok(Dither::Tpdf),
+            "shaped" => Ok(Dither::Shaped),
+            _ => Ok(Dither::Off),
+        }
+    }
+}
+
+/// the values of the STREAMINFO header of the FLAC stream
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FlacHeaderPreset {
+    /// the header written by the encoder: a fixed block size, unknown total samples and MD5
+    #[default]
+    Encoder,
+    /// the maximum total samples and a dummy MD5, for renderers that want a known length
+    MaxLength,
+}
+
+impl fmt::Display for FlacHeaderPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlacHeaderPreset::Encoder => write!(f, "Encoder"),
+            FlacHeaderPreset::MaxLength => write!(f, "MaxLength"),
+        }
+    }
+}
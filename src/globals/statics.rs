@@ -1,4 +1,10 @@
-use std::sync::{atomic::AtomicBool, LazyLock};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicU16},
+        LazyLock,
+    },
+};
 
 use crate::{
     enums::messages::MessageType,
@@ -7,13 +13,15 @@ use crate::{
 
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use hashbrown::HashMap;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
 /// app version
 pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 /// the HTTP server port
 pub const SERVER_PORT: u16 = 5901;
+// the port the HTTP server listens on, the next free port if the configured port is in use
+pub static SERVER_PORT_IN_USE: AtomicU16 = AtomicU16::new(SERVER_PORT);
 
 // streaming clients of the webserver
 pub static CLIENTS: LazyLock<RwLock<HashMap<String, ChannelStream>>> =
@@ -28,3 +36,18 @@ pub static CONFIG: LazyLock<RwLock<Configuration>> =
 pub static THEMES: [&str; 6] = ["Shake", "Gray", "Tan", "Dark", "Black", "None"];
 // the global "enable rms monitor" flag
 pub static RUN_RMS_MONITOR: AtomicBool = AtomicBool::new(false);
+// the global "enable spectrum analyzer" flag, the analyzer is part of the rms monitor
+pub static RUN_SPECTRUM_ANALYZER: AtomicBool = AtomicBool::new(false);
+// the rolling pre-roll buffer with the most recently captured samples
+pub static PREROLL: LazyLock<Mutex<VecDeque<f32>>> = LazyLock::new(|| Mutex::new(VecDeque::new()));
+// the global "enable playback monitor" flag
+pub static RUN_PLAYBACK_MONITOR: AtomicBool = AtomicBool::new(false);
+// the global "record to disk" flag
+pub static RUN_RECORDER: AtomicBool = AtomicBool::new(false);
+// the global "send RTP" flag
+pub static RUN_RTP_SENDER: AtomicBool = AtomicBool::new(false);
+// the samples waiting to be played by the local playback monitor
+pub static MONITOR_BUFFER: LazyLock<Mutex<VecDeque<f32>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::new()));
+// the sample rate and the number of channels of the stream from the running capture
+pub static STREAM_PARAMS: RwLock<Option<(u32, u16)>> = RwLock::new(None);
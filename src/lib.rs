@@ -1,3 +1,4 @@
+pub mod dsp;
 pub mod enums;
 pub mod globals;
 pub mod openhome;
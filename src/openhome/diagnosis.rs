@@ -0,0 +1,270 @@
+//! Step-by-step connection test of a renderer, for the "no sound" triage that otherwise
+//! needs the log files.
+//!
+//! The steps: fetch the device description, ask the `ConnectionManager` which protocols
+//! the renderer can play, check the stream url with a HEAD request, and play for
+//! 5 seconds and stop. Every step reports its outcome, and the play step checks that
+//! the renderer actually connected to the streaming server.
+
+use std::{
+    fmt,
+    net::IpAddr,
+    sync::LazyLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+use hashbrown::HashSet;
+use parking_lot::Mutex;
+
+use crate::{
+    enums::streaming::StreamingFormat,
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::rendercontrol::{protocol_info, renderer_available, Renderer, StreamInfo},
+    server::{query_params::stream_url, ssdp_announcer::upnp_user_agent},
+};
+
+/// how long the test plays
+const PLAY_SECS: u64 = 5;
+
+/// the ip addresses of the renderers being tested
+static TESTING: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// the outcome of a step of the test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagnosisStep {
+    pub step: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl DiagnosisStep {
+    #[must_use]
+    pub fn new(step: &'static str, ok: bool, detail: String) -> DiagnosisStep {
+        DiagnosisStep { step, ok, detail }
+    }
+}
+
+impl fmt::Display for DiagnosisStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = if self.ok { "OK" } else { "FAILED" };
+        write!(f, "[{outcome}] {}: {}", self.step, self.detail)
+    }
+}
+
+/// true if the renderer at this ip address is being tested, its streaming connection
+/// ending is not a reason to resume playing
+#[must_use]
+pub fn under_test(remote_ip: &str) -> bool {
+    TESTING.lock().contains(remote_ip)
+}
+
+/// the mime type of a protocol info, without parameters
+fn mime_type(protocol_info: &str) -> &str {
+    let mime_type = protocol_info.split(':').nth(2).unwrap_or_default();
+    mime_type.split(';').next().unwrap_or_default()
+}
+
+/// the mime type without the variations renderers use (audio/x-flac, audio/wave)
+fn normalized(mime_type: &str) -> String {
+    mime_type
+        .to_lowercase()
+        .replace("audio/x-", "audio/")
+        .replace("audio/wave", "audio/wav")
+}
+
+/// check the protocols the renderer reported for the streaming format
+fn check_protocols(sink: &str, format: StreamingFormat, bits_per_sample: u16) -> DiagnosisStep {
+    let wanted = mime_type(protocol_info(format, bits_per_sample));
+    let mut supported: Vec<&str> = sink
+        .split(',')
+        .map(mime_type)
+        .filter(|m| m.starts_with("audio/"))
+        .collect();
+    supported.sort_unstable();
+    supported.dedup();
+    let ok = supported
+        .iter()
+        .any(|m| normalized(m) == normalized(wanted));
+    let detail = if ok {
+        format!("{wanted} is supported")
+    } else {
+        format!(
+            "{wanted} is not in the protocols of the renderer ({}), try another format",
+            supported.join(", ")
+        )
+    };
+    DiagnosisStep::new("GetProtocolInfo", ok, detail)
+}
+
+/// check the stream url with a HEAD request
+fn check_stream_url(url: &str) -> DiagnosisStep {
+    let detail = |ok: &str| {
+        format!(
+            "{ok}. The renderer must be able to reach {url}, check the firewall of this \
+            computer if the next step fails"
+        )
+    };
+    match attohttpc::head(url)
+        .header("User-Agent", upnp_user_agent())
+        .timeout(Duration::from_secs(3))
+        .send()
+    {
+        Ok(resp) if resp.is_success() => {
+            let content_type = resp
+                .headers()
+                .get("Content-Type")
+                .and_then(|ct| ct.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            DiagnosisStep::new(
+                "HEAD stream url",
+                true,
+                detail(&format!("HTTP {}, {content_type}", resp.status())),
+            )
+        }
+        Ok(resp) => DiagnosisStep::new(
+            "HEAD stream url",
+            false,
+            format!("HTTP {} for {url}", resp.status()),
+        ),
+        Err(e) => DiagnosisStep::new(
+            "HEAD stream url",
+            false,
+            format!("the streaming server did not respond on {url}: {e}"),
+        ),
+    }
+}
+
+/// play for a few seconds, check that the renderer connects, and stop
+fn check_play(
+    renderer: &Renderer,
+    local_addr: &IpAddr,
+    server_port: u16,
+    streaminfo: StreamInfo,
+    log: &dyn Fn(&str),
+) -> DiagnosisStep {
+    if let Err(e) = renderer.play(local_addr, server_port, log, streaminfo) {
+        return DiagnosisStep::new("Play", false, format!("the renderer refused to play: {e}"));
+    }
+    let start = Instant::now();
+    let mut connected = None;
+    while start.elapsed() < Duration::from_secs(PLAY_SECS) {
+        if connected.is_none()
+            && CLIENTS
+                .read()
+                .values()
+                .any(|c| c.remote_ip == renderer.remote_addr)
+        {
+            connected = Some(start.elapsed());
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    let state = renderer.get_transport_state(log);
+    renderer.stop_play(log);
+    let state = state.map_or_else(|| "unknown".to_string(), |s| s.to_uppercase());
+    match connected {
+        Some(after) => DiagnosisStep::new(
+            "Play",
+            true,
+            format!(
+                "the renderer connected to the stream after {} ms, transport state {state}, \
+                stopped after {PLAY_SECS} seconds",
+                after.as_millis()
+            ),
+        ),
+        None => DiagnosisStep::new(
+            "Play",
+            false,
+            format!(
+                "the renderer accepted the play command but did not connect to the stream \
+                within {PLAY_SECS} seconds (transport state {state}), check the firewall"
+            ),
+        ),
+    }
+}
+
+/// test the connection with a renderer step by step, it takes at least 5 seconds
+pub fn diagnose(
+    renderer: &Renderer,
+    local_addr: &IpAddr,
+    server_port: u16,
+    streaminfo: StreamInfo,
+    log: &dyn Fn(&str),
+) -> Vec<DiagnosisStep> {
+    let mut steps = Vec::new();
+    // the device description, the other steps can't work without it
+    if renderer_available(&renderer.location) {
+        steps.push(DiagnosisStep::new(
+            "Device description",
+            true,
+            format!("{} responded", renderer.location),
+        ));
+    } else {
+        steps.push(DiagnosisStep::new(
+            "Device description",
+            false,
+            format!(
+                "{} did not respond, is the renderer switched on and on the same network?",
+                renderer.location
+            ),
+        ));
+        return steps;
+    }
+    // the format that play uses for this renderer
+    let configured = CONFIG
+        .read()
+        .streaming_format
+        .unwrap_or(StreamingFormat::Flac);
+    let format = renderer.streaming_format(configured);
+    steps.push(match renderer.get_protocol_info(log) {
+        Some(sink) => check_protocols(&sink, format, streaminfo.bits_per_sample),
+        None => DiagnosisStep::new(
+            "GetProtocolInfo",
+            false,
+            "no answer from the ConnectionManager, the renderer may still play".to_string(),
+        ),
+    });
+    let url = stream_url(
+        &format!("{local_addr}:{server_port}"),
+        format,
+        Some(streaminfo.bits_per_sample),
+        None,
+    );
+    steps.push(check_stream_url(&url));
+    TESTING.lock().insert(renderer.remote_addr.clone());
+    steps.push(check_play(
+        renderer,
+        local_addr,
+        server_port,
+        streaminfo,
+        log,
+    ));
+    // the streaming connection ends after the stop
+    thread::sleep(Duration::from_secs(2));
+    TESTING.lock().remove(&renderer.remote_addr);
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_protocols() {
+        let sink = "http-get:*:audio/L16;rate=44100;channels=2:*,http-get:*:audio/x-flac:*,\
+            http-get:*:audio/mpeg:*,http-get:*:video/mp4:*";
+        assert!(check_protocols(sink, StreamingFormat::Flac, 16).ok);
+        assert!(check_protocols(sink, StreamingFormat::Lpcm, 16).ok);
+        assert!(!check_protocols(sink, StreamingFormat::Lpcm, 24).ok);
+        let step = check_protocols(sink, StreamingFormat::Wav, 16);
+        assert!(!step.ok);
+        assert!(step
+            .detail
+            .contains("(audio/L16, audio/mpeg, audio/x-flac)"));
+        assert_eq!(
+            step.to_string(),
+            format!("[FAILED] GetProtocolInfo: {}", step.detail)
+        );
+    }
+}
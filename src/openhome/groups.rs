@@ -0,0 +1,164 @@
+//! Renderer groups: Sonos stereo pairs, home theater setups and groups of rooms.
+//!
+//! Only the coordinator of a group accepts a stream, the other speakers play what the
+//! coordinator plays. The `ZoneGroupTopology` service of a Sonos speaker reports all the
+//! groups of the household, so every speaker can tell if it is the coordinator of a group
+//! (it gets a button labeled with the group name) or a member (it gets no button).
+
+use log::{debug, error};
+use stringreader::StringReader;
+use xml::{
+    attribute::OwnedAttribute,
+    reader::{EventReader, XmlEvent},
+};
+
+use crate::openhome::rendercontrol::Renderer;
+
+/// the group of a renderer that is not on its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RendererGroup {
+    /// the room names of the group, e.g. "Living Room + Kitchen"
+    pub name: String,
+    /// true for the speaker that accepts the stream for the group
+    pub coordinator: bool,
+}
+
+/// a speaker in the zone group state
+#[derive(Debug, Default)]
+struct GroupMember {
+    uuid: String,
+    location: String,
+    zone_name: String,
+    invisible: bool,
+}
+
+/// a group in the zone group state
+#[derive(Debug, Default)]
+struct ZoneGroup {
+    coordinator: String,
+    members: Vec<GroupMember>,
+}
+
+impl ZoneGroup {
+    /// the distinct room names of the visible members, the coordinator first
+    fn name(&self) -> String {
+        let mut members: Vec<&GroupMember> = self.members.iter().filter(|m| !m.invisible).collect();
+        members.sort_by_key(|m| m.uuid != self.coordinator);
+        let mut names: Vec<&str> = Vec::new();
+        for m in members {
+            if !names.contains(&m.zone_name.as_str()) {
+                names.push(&m.zone_name);
+            }
+        }
+        names.join(" + ")
+    }
+}
+
+fn attribute(attributes: &[OwnedAttribute], name: &str) -> String {
+    attributes
+        .iter()
+        .find(|a| a.name.local_name == name)
+        .map(|a| a.value.clone())
+        .unwrap_or_default()
+}
+
+/// parse the zone groups of the `ZoneGroupState`, the satellites of a home theater
+/// setup are members too
+fn parse_zone_groups(xml: &str) -> Vec<ZoneGroup> {
+    let parser = EventReader::new(StringReader::new(xml));
+    let mut groups: Vec<ZoneGroup> = Vec::new();
+    for e in parser {
+        match e {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => match name.local_name.as_str() {
+                "ZoneGroup" => groups.push(ZoneGroup {
+                    coordinator: attribute(&attributes, "Coordinator"),
+                    members: Vec::new(),
+                }),
+                "ZoneGroupMember" | "Satellite" => {
+                    if let Some(group) = groups.last_mut() {
+                        group.members.push(GroupMember {
+                            uuid: attribute(&attributes, "UUID"),
+                            location: attribute(&attributes, "Location"),
+                            zone_name: attribute(&attributes, "ZoneName"),
+                            invisible: attribute(&attributes, "Invisible") == "1",
+                        });
+                    }
+                }
+                _ => (),
+            },
+            Err(e) => {
+                error!("Zone group state XML parse error: {e}");
+                return Vec::new();
+            }
+            _ => (),
+        }
+    }
+    groups
+}
+
+/// the group of the speaker with the description at `location`, None if it's on its own
+fn group_of(groups: &[ZoneGroup], location: &str) -> Option<RendererGroup> {
+    groups.iter().find_map(|group| {
+        let member = group
+            .members
+            .iter()
+            .find(|m| m.location.eq_ignore_ascii_case(location))?;
+        if group.members.len() < 2 {
+            return None;
+        }
+        Some(RendererGroup {
+            name: group.name(),
+            coordinator: member.uuid == group.coordinator,
+        })
+    })
+}
+
+/// ask a renderer with a `ZoneGroupTopology` service for its group
+pub fn renderer_group(renderer: &Renderer, log: &dyn Fn(&str)) -> Option<RendererGroup> {
+    let xml = renderer.get_zone_group_state(log)?;
+    debug!("Zone group state of {}: {xml}", renderer.dev_name);
+    group_of(&parse_zone_groups(&xml), &renderer.location)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zone_groups() {
+        // a stereo pair in the living room grouped with the kitchen, a single bathroom
+        // speaker, and a soundbar with a surround satellite
+        let xml = r#"<ZoneGroupState><ZoneGroups>
+            <ZoneGroup Coordinator="RINCON_A" ID="RINCON_A:1">
+                <ZoneGroupMember UUID="RINCON_A" Location="http://192.168.1.20:1400/xml/device_description.xml" ZoneName="Living Room"/>
+                <ZoneGroupMember UUID="RINCON_B" Location="http://192.168.1.21:1400/xml/device_description.xml" ZoneName="Living Room" Invisible="1"/>
+                <ZoneGroupMember UUID="RINCON_C" Location="http://192.168.1.22:1400/xml/device_description.xml" ZoneName="Kitchen"/>
+            </ZoneGroup>
+            <ZoneGroup Coordinator="RINCON_D" ID="RINCON_D:2">
+                <ZoneGroupMember UUID="RINCON_D" Location="http://192.168.1.23:1400/xml/device_description.xml" ZoneName="Bathroom"/>
+            </ZoneGroup>
+            <ZoneGroup Coordinator="RINCON_E" ID="RINCON_E:3">
+                <ZoneGroupMember UUID="RINCON_E" Location="http://192.168.1.24:1400/xml/device_description.xml" ZoneName="TV Room">
+                    <Satellite UUID="RINCON_F" Location="http://192.168.1.25:1400/xml/device_description.xml" ZoneName="TV Room" Invisible="1"/>
+                </ZoneGroupMember>
+            </ZoneGroup>
+        </ZoneGroups></ZoneGroupState>"#;
+        let groups = parse_zone_groups(xml);
+        assert_eq!(groups.len(), 3);
+        let location = |n: u8| format!("http://192.168.1.{n}:1400/xml/device_description.xml");
+        assert_eq!(
+            group_of(&groups, &location(20)),
+            Some(RendererGroup {
+                name: "Living Room + Kitchen".to_string(),
+                coordinator: true
+            })
+        );
+        assert!(!group_of(&groups, &location(21)).unwrap().coordinator);
+        assert!(!group_of(&groups, &location(22)).unwrap().coordinator);
+        assert_eq!(group_of(&groups, &location(23)), None);
+        assert_eq!(group_of(&groups, &location(24)).unwrap().name, "TV Room");
+        assert!(!group_of(&groups, &location(25)).unwrap().coordinator);
+    }
+}
@@ -0,0 +1,135 @@
+//! Export the renderers found by SSDP discovery to a JSON file, and import such a file as
+//! known renderers.
+//!
+//! The export lists the name, model, ip address, services and supported protocols of every
+//! renderer, which is useful for support requests. The renderers in the `known_renderers`
+//! file are added to the discovered renderers, e.g. on networks where multicast is blocked:
+//! only their description url (`location`) has to be reachable.
+
+use std::{fs, io, path::Path, sync::LazyLock};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    globals::statics::CONFIG,
+    openhome::rendercontrol::{renderer_from_location, Renderer, SupportedProtocols},
+};
+
+/// the renderer inventory file name in the config directory
+pub const INVENTORY_FILE: &str = "renderers.json";
+
+/// the renderers found so far
+static DISCOVERED: LazyLock<RwLock<Vec<RendererInfo>>> = LazyLock::new(|| RwLock::new(Vec::new()));
+
+/// the exported information of a renderer
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RendererInfo {
+    pub name: String,
+    pub model: String,
+    pub manufacturer: String,
+    pub remote_addr: String,
+    pub location: String,
+    pub dev_url: String,
+    pub openhome: bool,
+    pub avtransport: bool,
+    pub services: Vec<String>,
+}
+
+impl From<&Renderer> for RendererInfo {
+    fn from(r: &Renderer) -> Self {
+        RendererInfo {
+            name: r.dev_name.clone(),
+            model: r.dev_model.clone(),
+            manufacturer: r.dev_manufacturer.clone(),
+            remote_addr: r.remote_addr.clone(),
+            location: r.location.clone(),
+            dev_url: r.dev_url.clone(),
+            openhome: r.supported_protocols.contains(SupportedProtocols::OPENHOME),
+            avtransport: r
+                .supported_protocols
+                .contains(SupportedProtocols::AVTRANSPORT),
+            services: r
+                .services
+                .iter()
+                .map(|s| s.service_type().to_string())
+                .collect(),
+        }
+    }
+}
+
+/// remember a new renderer for the export
+pub fn record_renderer(r: &Renderer) {
+    let info = RendererInfo::from(r);
+    let mut discovered = DISCOVERED.write();
+    discovered.retain(|d| d.remote_addr != info.remote_addr);
+    discovered.push(info);
+}
+
+/// export the renderers found so far as JSON, returns the number of renderers
+pub fn export_renderers(path: &Path) -> io::Result<usize> {
+    let discovered = DISCOVERED.read();
+    let json = serde_json::to_string_pretty(&*discovered)?;
+    fs::write(path, json)?;
+    Ok(discovered.len())
+}
+
+/// read an exported renderer inventory
+pub fn read_inventory(path: &Path) -> io::Result<Vec<RendererInfo>> {
+    let json = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+/// the reachable renderers of the configured `known_renderers` inventory file
+#[must_use]
+pub fn known_renderers(logger: &dyn Fn(&str)) -> Vec<Renderer> {
+    let Some(file) = CONFIG.read().known_renderers.clone() else {
+        return Vec::new();
+    };
+    let infos = match read_inventory(Path::new(&file)) {
+        Ok(infos) => infos,
+        Err(e) => {
+            logger(&format!(
+                "*E*E*> Could not read the known renderers from {file}: {e}"
+            ));
+            return Vec::new();
+        }
+    };
+    infos
+        .iter()
+        .filter_map(|info| {
+            let rend = renderer_from_location(&info.location, &info.remote_addr, logger);
+            if rend.is_none() {
+                logger(&format!(
+                    "*W*W*> Known renderer {} at {} is not reachable",
+                    info.name, info.location
+                ));
+            }
+            rend
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inventory_json() {
+        let infos = vec![RendererInfo {
+            name: "Living Room".to_string(),
+            model: "WXAD-10".to_string(),
+            manufacturer: "Yamaha Corporation".to_string(),
+            remote_addr: "192.168.1.20".to_string(),
+            location: "http://192.168.1.20:49154/MediaRenderer/desc.xml".to_string(),
+            dev_url: "http://192.168.1.20:49154/".to_string(),
+            openhome: false,
+            avtransport: true,
+            services: vec!["urn:schemas-upnp-org:service:AVTransport:1".to_string()],
+        }];
+        let json = serde_json::to_string_pretty(&infos).unwrap();
+        assert!(json.contains("\"remote_addr\": \"192.168.1.20\""));
+        let read: Vec<RendererInfo> = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, infos);
+    }
+}
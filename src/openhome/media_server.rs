@@ -0,0 +1,353 @@
+//! Control point (DMC) mode: browse a UPnP media server on the network and let a renderer
+//! play one of its items, so that swyh-rs doubles as a minimal controller when it is not
+//! streaming the audio of the computer.
+//!
+//! The media servers are found with an SSDP search, their `ContentDirectory` service is
+//! browsed one container at a time, and the renderer gets the url and the DIDL-Lite
+//! metadata of the chosen item with the same `OpenHome` or `AVTransport` commands that
+//! start the swyh-rs stream.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use log::{debug, error};
+use parking_lot::Mutex;
+use strfmt::strfmt;
+use stringreader::StringReader;
+use url::Url;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{
+    globals::statics::CONFIG,
+    openhome::rendercontrol::{get_service_description, soap_response_value, SSDP_DISCOVER_MSG},
+    server::ssdp_announcer::{upnp_udn, upnp_user_agent},
+};
+
+const MEDIA_SERVER: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+/// the maximum number of children of a container that are listed
+const MAX_CHILDREN: u32 = 500;
+
+/// the media servers of the last discovery
+static MEDIA_SERVERS: Mutex<Vec<MediaServer>> = Mutex::new(Vec::new());
+
+/// `ContentDirectory` Browse template
+static CD_BROWSE_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+<s:Body>\
+<u:Browse xmlns:u=\"urn:schemas-upnp-org:service:ContentDirectory:1\">\
+<ObjectID>{object_id}</ObjectID>\
+<BrowseFlag>BrowseDirectChildren</BrowseFlag>\
+<Filter>*</Filter>\
+<StartingIndex>0</StartingIndex>\
+<RequestedCount>{count}</RequestedCount>\
+<SortCriteria></SortCriteria>\
+</u:Browse>\
+</s:Body>\
+</s:Envelope>";
+
+/// didl metadata template for an item of a media server
+static ITEM_DIDL_TEMPLATE: &str = "\
+<DIDL-Lite xmlns=\"urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/\" \
+xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+xmlns:upnp=\"urn:schemas-upnp-org:metadata-1-0/upnp/\">\
+<item id=\"{id}\" parentID=\"-1\" restricted=\"1\">\
+<dc:title>{title}</dc:title>\
+<res protocolInfo=\"{protocol_info}\">{url}</res>\
+<upnp:class>{class}</upnp:class>\
+</item>\
+</DIDL-Lite>";
+
+/// a media server with a `ContentDirectory` service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaServer {
+    pub name: String,
+    pub location: String,
+    /// the absolute url of the `ContentDirectory` control
+    control_url: String,
+}
+
+/// a container or an item of a media server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MediaObject {
+    pub id: String,
+    pub title: String,
+    pub container: bool,
+    /// the url of the first resource of an item
+    pub url: String,
+    pub protocol_info: String,
+    pub class: String,
+}
+
+impl MediaObject {
+    /// the DIDL-Lite metadata of an item, for the renderer
+    #[must_use]
+    pub fn metadata(&self) -> String {
+        let vars: HashMap<String, String> = [
+            ("id", &self.id),
+            ("title", &self.title),
+            ("protocol_info", &self.protocol_info),
+            ("url", &self.url),
+            ("class", &self.class),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), htmlescape::encode_minimal(value)))
+        .collect();
+        strfmt(ITEM_DIDL_TEMPLATE, &vars).unwrap_or_default()
+    }
+}
+
+impl MediaServer {
+    /// the children of a container, "0" is the root container
+    pub fn browse(&self, object_id: &str) -> Option<Vec<MediaObject>> {
+        let body = CD_BROWSE_TEMPLATE
+            .replace("{object_id}", &htmlescape::encode_minimal(object_id))
+            .replace("{count}", &MAX_CHILDREN.to_string());
+        debug!("Browse {object_id} on {}: {body}", self.name);
+        let xml = match attohttpc::post(&self.control_url)
+            .header("Connection", "close")
+            .header("User-Agent", upnp_user_agent())
+            .header(
+                "SOAPAction",
+                "\"urn:schemas-upnp-org:service:ContentDirectory:1#Browse\"",
+            )
+            .header("Content-Type", "text/xml; charset=\"utf-8\"")
+            .timeout(Duration::from_secs(10))
+            .text(body)
+            .send()
+        {
+            Ok(resp) => resp.text().unwrap_or_default(),
+            Err(e) => {
+                error!("Browse {object_id} on {} failed: {e}", self.name);
+                return None;
+            }
+        };
+        debug!("<=Browse response: {xml}");
+        let didl = soap_response_value(&xml, "BrowseResponse", "Result")?;
+        Some(parse_didl(&didl))
+    }
+}
+
+/// the containers and items of a DIDL-Lite browse result
+fn parse_didl(didl: &str) -> Vec<MediaObject> {
+    let parser = EventReader::new(StringReader::new(didl));
+    let mut objects = Vec::new();
+    let mut object: Option<MediaObject> = None;
+    let mut cur_elem = String::new();
+    for e in parser {
+        match e {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                cur_elem = name.local_name;
+                let attribute = |n: &str| {
+                    attributes
+                        .iter()
+                        .find(|a| a.name.local_name == n)
+                        .map(|a| a.value.clone())
+                        .unwrap_or_default()
+                };
+                match cur_elem.as_str() {
+                    "container" | "item" => {
+                        object = Some(MediaObject {
+                            id: attribute("id"),
+                            container: cur_elem == "container",
+                            ..MediaObject::default()
+                        });
+                    }
+                    "res" => {
+                        if let Some(o) = object.as_mut().filter(|o| o.url.is_empty()) {
+                            o.protocol_info = attribute("protocolInfo");
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            Ok(XmlEvent::Characters(value)) => {
+                if let Some(o) = object.as_mut() {
+                    match cur_elem.as_str() {
+                        "title" => o.title = value,
+                        "class" => o.class = value,
+                        "res" if o.url.is_empty() => o.url = value.trim().to_string(),
+                        _ => (),
+                    }
+                }
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                if name.local_name == "container" || name.local_name == "item" {
+                    objects.extend(object.take());
+                }
+                cur_elem.clear();
+            }
+            Err(e) => {
+                error!("DIDL-Lite parse error: {e}");
+                break;
+            }
+            _ => (),
+        }
+    }
+    // an item without a resource can't be played
+    objects.retain(|o| o.container || !o.url.is_empty());
+    objects
+}
+
+/// the media server of a device description, if it has a `ContentDirectory` service
+fn media_server(xml: &str, location: &str) -> Option<MediaServer> {
+    let parser = EventReader::new(StringReader::new(xml));
+    let mut cur_elem = String::new();
+    let mut name = String::new();
+    let mut url_base = String::new();
+    let mut service_type = String::new();
+    let mut control_url = None;
+    for e in parser {
+        match e {
+            Ok(XmlEvent::StartElement { name, .. }) => cur_elem = name.local_name,
+            Ok(XmlEvent::Characters(value)) => match cur_elem.as_str() {
+                "friendlyName" if name.is_empty() => name = value,
+                "URLBase" => url_base = value,
+                "serviceType" => service_type = value,
+                "controlURL" if service_type.contains(":ContentDirectory:") => {
+                    control_url = Some(value);
+                }
+                _ => (),
+            },
+            Err(e) => {
+                error!("Media server description error: {e}");
+                return None;
+            }
+            _ => (),
+        }
+    }
+    let base = if url_base.is_empty() {
+        location
+    } else {
+        &url_base
+    };
+    let control_url = Url::parse(base).ok()?.join(&control_url?).ok()?;
+    Some(MediaServer {
+        name,
+        location: location.to_string(),
+        control_url: control_url.to_string(),
+    })
+}
+
+/// the locations in the responses to an SSDP search for media servers
+fn search_media_servers(log: &dyn Fn(&str)) -> Vec<String> {
+    let Some(local_addr) = CONFIG
+        .read()
+        .last_network
+        .as_ref()
+        .and_then(|ip| ip.parse::<IpAddr>().ok())
+    else {
+        return Vec::new();
+    };
+    let socket = match UdpSocket::bind(SocketAddr::new(local_addr, 0)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            log(&format!("*E*E*> Media server search failed: {e}"));
+            return Vec::new();
+        }
+    };
+    let _ = socket.set_multicast_ttl_v4(2);
+    let udn = upnp_udn();
+    let msg = SSDP_DISCOVER_MSG
+        .replace("{device_type}", MEDIA_SERVER)
+        .replace("{user_agent}", &upnp_user_agent())
+        .replace("{cpuuid}", udn.trim_start_matches("uuid:"));
+    let broadcast_address: SocketAddr = ([239, 255, 255, 250], 1900).into();
+    if let Err(e) = socket.send_to(msg.as_bytes(), broadcast_address) {
+        log(&format!("*E*E*> Media server search failed: {e}"));
+        return Vec::new();
+    }
+    let mut locations: Vec<String> = Vec::new();
+    let start = Instant::now();
+    let mut buf = [0u8; 2048];
+    while let Some(wait) = Duration::from_millis(3100).checked_sub(start.elapsed()) {
+        if wait.is_zero() || socket.set_read_timeout(Some(wait)).is_err() {
+            break;
+        }
+        let Ok((received, _)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        let response = String::from_utf8_lossy(&buf[..received]);
+        let location = response.lines().find_map(|l| {
+            let (header, value) = l.split_once(':')?;
+            header
+                .eq_ignore_ascii_case("LOCATION")
+                .then(|| value.trim().to_string())
+        });
+        if let Some(location) = location.filter(|l| !locations.contains(l)) {
+            locations.push(location);
+        }
+    }
+    locations
+}
+
+/// Search the media servers on the network (about 3 seconds), or use those of the last
+/// search unless `refresh`.
+pub fn media_servers(refresh: bool, log: &dyn Fn(&str)) -> Vec<MediaServer> {
+    if !refresh && !MEDIA_SERVERS.lock().is_empty() {
+        return MEDIA_SERVERS.lock().clone();
+    }
+    let servers: Vec<MediaServer> = search_media_servers(log)
+        .iter()
+        .filter_map(|location| {
+            let xml = get_service_description(location)?;
+            media_server(&xml, location)
+        })
+        .collect();
+    log(&format!("Found {} media server(s)", servers.len()));
+    MEDIA_SERVERS.lock().clone_from(&servers);
+    servers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media_server() {
+        let xml = "<root><URLBase></URLBase><device>\
+            <friendlyName>NAS: music</friendlyName>\
+            <serviceList><service>\
+            <serviceType>urn:schemas-upnp-org:service:ConnectionManager:1</serviceType>\
+            <controlURL>/cm/control</controlURL></service><service>\
+            <serviceType>urn:schemas-upnp-org:service:ContentDirectory:1</serviceType>\
+            <controlURL>cd/control</controlURL></service></serviceList>\
+            </device></root>";
+        let server = media_server(xml, "http://192.168.1.5:8200/rootDesc.xml").unwrap();
+        assert_eq!(server.name, "NAS: music");
+        assert_eq!(server.control_url, "http://192.168.1.5:8200/cd/control");
+    }
+
+    #[test]
+    fn test_parse_didl() {
+        let didl = r#"<DIDL-Lite xmlns="urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/"
+            xmlns:dc="http://purl.org/dc/elements/1.1/"
+            xmlns:upnp="urn:schemas-upnp-org:metadata-1-0/upnp/">
+            <container id="1$4" parentID="1" childCount="12"><dc:title>Albums</dc:title>
+                <upnp:class>object.container.storageFolder</upnp:class></container>
+            <item id="64$0" parentID="64"><dc:title>Tom &amp; Jerry</dc:title>
+                <upnp:class>object.item.audioItem.musicTrack</upnp:class>
+                <res protocolInfo="http-get:*:audio/flac:*">http://192.168.1.5:8200/MediaItems/1.flac</res>
+                <res protocolInfo="http-get:*:audio/mpeg:*">http://192.168.1.5:8200/MediaItems/1.mp3</res>
+            </item>
+            <item id="64$1" parentID="64"><dc:title>No resource</dc:title></item>
+        </DIDL-Lite>"#;
+        let objects = parse_didl(didl);
+        assert_eq!(objects.len(), 2);
+        assert!(objects[0].container);
+        assert_eq!(objects[0].title, "Albums");
+        let item = &objects[1];
+        assert_eq!(item.title, "Tom & Jerry");
+        assert_eq!(item.url, "http://192.168.1.5:8200/MediaItems/1.flac");
+        assert_eq!(item.protocol_info, "http-get:*:audio/flac:*");
+        assert!(item.metadata().contains(
+            "<dc:title>Tom &amp; Jerry</dc:title><res protocolInfo=\"http-get:*:audio/flac:*\">"
+        ));
+    }
+}
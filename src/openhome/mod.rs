@@ -1 +1,8 @@
+pub mod diagnosis;
+pub mod groups;
+pub mod inventory;
+pub mod media_server;
+pub mod power_control;
+pub mod quirks;
 pub mod rendercontrol;
+pub mod renderer_poller;
@@ -0,0 +1,162 @@
+//! Power and input control of a renderer (or the amplifier behind it) with vendor HTTP APIs.
+//!
+//! The `power_on` requests of a renderer are sent before it starts playing, and the
+//! `power_off` requests after it was stopped. They are HTTP GET urls in the renderer
+//! settings, with `{host}` and `{input}` replaced by the `power_host` (default the renderer
+//! ip address) and the URL-encoded `power_input`. A `power_preset` provides the urls for
+//! Yamaha MusicCast and for the HTTP API of Denon/Marantz receivers, configured urls take
+//! precedence.
+//! `power_on` waits for the receiver to wake up, the GUI plays on a worker thread.
+
+use crate::{
+    globals::statics::CONFIG, openhome::rendercontrol::Renderer,
+    server::ssdp_announcer::http_user_agent, utils::configuration::RendererSettings,
+};
+use log::debug;
+use std::{thread, time::Duration};
+use url::form_urlencoded;
+
+/// the time a powered on receiver gets to wake up before playing starts
+const DEFAULT_POWER_ON_DELAY_MSEC: u32 = 2000;
+
+/// the power on and power off urls of the built-in presets
+fn preset_urls(preset: &str) -> Option<(&'static [&'static str], &'static [&'static str])> {
+    match preset.to_lowercase().as_str() {
+        "musiccast" | "yamaha" => Some((
+            &[
+                "http://{host}/YamahaExtendedControl/v1/main/setPower?power=on",
+                "http://{host}/YamahaExtendedControl/v1/main/setInput?input={input}",
+            ],
+            &["http://{host}/YamahaExtendedControl/v1/main/setPower?power=standby"],
+        )),
+        "denon" | "marantz" => Some((
+            &[
+                "http://{host}/goform/formiPhoneAppPower.xml?1+PowerOn",
+                "http://{host}/goform/formiPhoneAppDirect.xml?SI{input}",
+            ],
+            &["http://{host}/goform/formiPhoneAppPower.xml?1+PowerStandby"],
+        )),
+        _ => None,
+    }
+}
+
+/// the power on or power off urls for a renderer, with the variables replaced
+///
+/// urls with an `{input}` are skipped if there is no `power_input`
+#[must_use]
+pub fn power_urls(settings: &RendererSettings, remote_addr: &str, power_on: bool) -> Vec<String> {
+    let configured = if power_on {
+        settings.power_on.clone()
+    } else {
+        settings.power_off.clone()
+    };
+    let templates = configured.unwrap_or_else(|| {
+        settings
+            .power_preset
+            .as_deref()
+            .and_then(preset_urls)
+            .map(|(on, off)| if power_on { on } else { off })
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    });
+    let host = settings.power_host.as_deref().unwrap_or(remote_addr);
+    let input = settings
+        .power_input
+        .as_deref()
+        .map(|input| form_urlencoded::byte_serialize(input.as_bytes()).collect::<String>());
+    templates
+        .iter()
+        .filter(|t| input.is_some() || !t.contains("{input}"))
+        .map(|t| {
+            t.replace("{host}", host)
+                .replace("{input}", input.as_deref().unwrap_or_default())
+        })
+        .collect()
+}
+
+/// send the power requests
+fn send_requests(urls: &[String], log: &dyn Fn(&str)) {
+    for url in urls {
+        debug!("Power control request: {url}");
+        match attohttpc::get(url)
+            .header("Connection", "close")
+            .header("User-Agent", http_user_agent())
+            .timeout(Duration::from_secs(5))
+            .send()
+        {
+            Ok(resp) if resp.is_success() => (),
+            Ok(resp) => log(&format!(
+                "*W*W*> Power control request {url} failed: {}",
+                resp.status()
+            )),
+            Err(e) => log(&format!("*W*W*> Power control request {url} failed: {e}")),
+        }
+    }
+}
+
+/// power on a renderer and select its input before it starts playing
+pub fn power_on(renderer: &Renderer, log: &dyn Fn(&str)) {
+    let Some(settings) = CONFIG.read().renderers.get(&renderer.dev_name).cloned() else {
+        return;
+    };
+    let urls = power_urls(&settings, &renderer.remote_addr, true);
+    if urls.is_empty() {
+        return;
+    }
+    log(&format!("Powering on {}", renderer.dev_name));
+    send_requests(&urls, log);
+    let delay = settings
+        .power_on_delay_msec
+        .unwrap_or(DEFAULT_POWER_ON_DELAY_MSEC);
+    thread::sleep(Duration::from_millis(u64::from(delay)));
+}
+
+/// power off a renderer after it was stopped
+pub fn power_off(renderer: &Renderer, log: &dyn Fn(&str)) {
+    let Some(settings) = CONFIG.read().renderers.get(&renderer.dev_name).cloned() else {
+        return;
+    };
+    let urls = power_urls(&settings, &renderer.remote_addr, false);
+    if !urls.is_empty() {
+        log(&format!("Powering off {}", renderer.dev_name));
+        send_requests(&urls, log);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_power_urls() {
+        let mut settings = RendererSettings {
+            power_preset: Some("MusicCast".to_string()),
+            ..Default::default()
+        };
+        // no input: only power on
+        assert_eq!(
+            power_urls(&settings, "192.168.1.20", true),
+            vec!["http://192.168.1.20/YamahaExtendedControl/v1/main/setPower?power=on"]
+        );
+        settings.power_input = Some("server".to_string());
+        settings.power_host = Some("192.168.1.21".to_string());
+        assert_eq!(
+            power_urls(&settings, "192.168.1.20", true)[1],
+            "http://192.168.1.21/YamahaExtendedControl/v1/main/setInput?input=server"
+        );
+        assert_eq!(power_urls(&settings, "192.168.1.20", false).len(), 1);
+        // the input is URL-encoded
+        settings.power_preset = Some("Denon".to_string());
+        settings.power_input = Some("SAT/CBL & TV".to_string());
+        assert_eq!(
+            power_urls(&settings, "192.168.1.20", true)[1],
+            "http://192.168.1.21/goform/formiPhoneAppDirect.xml?SISAT%2FCBL+%26+TV"
+        );
+        // configured urls take precedence over the preset
+        settings.power_off = Some(vec![]);
+        assert!(power_urls(&settings, "192.168.1.20", false).is_empty());
+        assert!(power_urls(&RendererSettings::default(), "192.168.1.20", true).is_empty());
+    }
+}
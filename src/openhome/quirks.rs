@@ -0,0 +1,165 @@
+//! Known-good streaming settings per renderer family, selected by manufacturer and/or model name.
+//!
+//! The built-in table can be extended or overridden with `[[configuration.quirks]]` entries
+//! in the config file, these are checked first.
+
+use crate::{
+    enums::streaming::{StreamSize, StreamingFormat},
+    globals::statics::CONFIG,
+    server::query_params::to_query_string,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// the streaming settings for a renderer family
+///
+/// `manufacturer` and `model_name` are matched case insensitive as a substring,
+/// if both are present both have to match
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quirk {
+    #[serde(alias = "Name")]
+    pub name: String,
+    #[serde(alias = "Manufacturer", default)]
+    pub manufacturer: Option<String>,
+    #[serde(alias = "ModelName", default)]
+    pub model_name: Option<String>,
+    #[serde(alias = "StreamingFormat", default)]
+    pub streaming_format: Option<StreamingFormat>,
+    #[serde(alias = "BitsPerSample", default)]
+    pub bits_per_sample: Option<u16>,
+    #[serde(alias = "StreamSize", default)]
+    pub stream_size: Option<StreamSize>,
+    #[serde(alias = "InjectSilence", default)]
+    pub inject_silence: Option<bool>,
+}
+
+impl Quirk {
+    /// check if this quirk applies to a renderer with this manufacturer and model name
+    #[must_use]
+    pub fn matches(&self, manufacturer: &str, model_name: &str) -> bool {
+        fn contains(value: &str, pattern: Option<&String>) -> Option<bool> {
+            pattern.map(|p| value.to_lowercase().contains(&p.to_lowercase()))
+        }
+        match (
+            contains(manufacturer, self.manufacturer.as_ref()),
+            contains(model_name, self.model_name.as_ref()),
+        ) {
+            (Some(m), Some(n)) => m && n,
+            (Some(m), None) => m,
+            (None, Some(n)) => n,
+            (None, None) => false,
+        }
+    }
+
+    /// the query string for the streaming url with the settings that the server needs to know
+    #[must_use]
+    pub fn query_string(&self) -> String {
+        to_query_string(self.bits_per_sample, self.stream_size, None, None, None)
+    }
+}
+
+impl fmt::Display for Quirk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}':", self.name)?;
+        if let Some(format) = self.streaming_format {
+            write!(f, " format={format}")?;
+        }
+        if let Some(bps) = self.bits_per_sample {
+            write!(f, " bits={bps}")?;
+        }
+        if let Some(ss) = self.stream_size {
+            write!(f, " streamsize={ss}")?;
+        }
+        if let Some(inject) = self.inject_silence {
+            write!(f, " inject_silence={inject}")?;
+        }
+        Ok(())
+    }
+}
+
+/// the built-in quirks table
+#[must_use]
+pub fn builtin_quirks() -> Vec<Quirk> {
+    vec![
+        // Sonos drops FLAC and chunked streams, and disconnects when no audio is received
+        Quirk {
+            name: "Sonos".to_string(),
+            manufacturer: Some("Sonos".to_string()),
+            model_name: None,
+            streaming_format: Some(StreamingFormat::Wav),
+            bits_per_sample: Some(16),
+            stream_size: Some(StreamSize::U32maxNotChunked),
+            inject_silence: Some(true),
+        },
+        // Logitech Media Server based players (squeezelite, UPnP bridge)
+        Quirk {
+            name: "LMS".to_string(),
+            manufacturer: None,
+            model_name: Some("Squeeze".to_string()),
+            streaming_format: Some(StreamingFormat::Flac),
+            bits_per_sample: None,
+            stream_size: Some(StreamSize::NoneChunked),
+            inject_silence: None,
+        },
+    ]
+}
+
+/// find the quirk for a renderer, the configured quirks take precedence over the built-in ones
+#[must_use]
+pub fn find_quirk(manufacturer: &str, model_name: &str) -> Option<Quirk> {
+    let configured = CONFIG.read().quirks.clone();
+    configured
+        .into_iter()
+        .chain(builtin_quirks())
+        .find(|q| q.matches(manufacturer, model_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let quirks = builtin_quirks();
+        let sonos = &quirks[0];
+        assert!(sonos.matches("Sonos, Inc.", "Sonos One"));
+        assert!(sonos.matches("SONOS", ""));
+        assert!(!sonos.matches("Yamaha Corporation", "WXAD-10"));
+        let lms = &quirks[1];
+        assert!(lms.matches("", "SqueezeLite"));
+        assert!(!lms.matches("Logitech", "Harmony"));
+        let q = Quirk {
+            name: "both".to_string(),
+            manufacturer: Some("Denon".to_string()),
+            model_name: Some("HEOS".to_string()),
+            streaming_format: None,
+            bits_per_sample: None,
+            stream_size: None,
+            inject_silence: None,
+        };
+        assert!(q.matches("Denon", "HEOS 1"));
+        assert!(!q.matches("Denon", "AVR-X2700H"));
+    }
+
+    #[test]
+    fn test_sonos_inject_silence() {
+        let quirk = builtin_quirks()
+            .into_iter()
+            .find(|q| q.matches("Sonos, Inc.", "Sonos Play:1"));
+        assert_eq!(quirk.and_then(|q| q.inject_silence), Some(true));
+        let quirk = builtin_quirks()
+            .into_iter()
+            .find(|q| q.matches("", "SqueezeLite"));
+        assert_eq!(quirk.and_then(|q| q.inject_silence), None);
+    }
+
+    #[test]
+    fn test_query_string() {
+        let quirks = builtin_quirks();
+        assert_eq!(quirks[0].query_string(), "?bd=16&ss=u32maxnotchunked");
+        assert_eq!(quirks[1].query_string(), "?ss=nonechunked");
+        let mut q = quirks[1].clone();
+        q.stream_size = None;
+        assert_eq!(q.query_string(), "");
+    }
+}
@@ -5,15 +5,36 @@
 ///
 ///
 use crate::{
-    enums::streaming::StreamingFormat,
-    globals::statics::{APP_VERSION, CONFIG},
+    enums::streaming::{HeaderSize, StreamingFormat},
+    globals::statics::{CLIENTS, CONFIG, STREAM_PARAMS},
+    openhome::{
+        groups::{renderer_group, RendererGroup},
+        power_control::{power_off, power_on},
+        quirks::{find_quirk, Quirk},
+        renderer_poller::{set_paused, unwatch_renderer, volume_set, watch_renderer},
+    },
+    server::{
+        query_params::{stream_path, to_query_string},
+        ssdp_announcer::{upnp_udn, upnp_user_agent},
+        streaming_server::NegotiatedStream,
+    },
+    utils::{
+        bincommon::{start_silence_injector, trial_format},
+        rwstream::ChannelStream,
+    },
 };
+use attohttpc::{header::HeaderName, RequestBuilder};
 use bitflags::bitflags;
 use hashbrown::HashMap;
 use log::{debug, error, info};
+use parking_lot::RwLock;
 use std::collections::HashMap as StdHashMap;
 use std::{
     net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock,
+    },
     time::{Duration, Instant},
 };
 use strfmt::strfmt;
@@ -22,6 +43,9 @@ use url::Url;
 use xml::reader::{EventReader, XmlEvent};
 
 /// OH insert playlist template
+/// a remembered volume is not yet written to the config file
+static VOLUME_UNSAVED: AtomicBool = AtomicBool::new(false);
+
 static OH_INSERT_PL_TEMPLATE: &str = "\
 <?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
 <s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
@@ -51,14 +75,40 @@ s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
 
 /// didl protocolinfo
 /// rf64 seems to work with L16, do we need a specific one?
-static L16_PROT_INFO: &str = "http-get:*:audio/L16;rate={sample_rate};channels=2:DLNA.ORG_PN=LPCM";
-static L24_PROT_INFO: &str = "http-get:*:audio/L24;rate={sample_rate};channels=2:DLNA.ORG_PN=LPCM";
+static L16_PROT_INFO: &str =
+    "http-get:*:audio/L16;rate={sample_rate};channels={channels}:DLNA.ORG_PN=LPCM";
+static L24_PROT_INFO: &str =
+    "http-get:*:audio/L24;rate={sample_rate};channels={channels}:DLNA.ORG_PN=LPCM";
 static WAV_PROT_INFO: &str = "http-get:*:audio/wav:DLNA.ORG_PN=WAV;DLNA.ORG_OP=01;DLNA.ORG_CI=0;\
     DLNA.ORG_FLAGS=03700000000000000000000000000000";
 static FLAC_PROT_INFO: &str =
     "http-get:*:audio/flac:DLNA.ORG_PN=FLAC;DLNA.ORG_OP=01;DLNA.ORG_CI=0;\
     DLNA.ORG_FLAGS=01700000000000000000000000000000";
 
+/// the didl protocolinfo for a streaming format and bit depth
+#[must_use]
+pub fn protocol_info(streaming_format: StreamingFormat, bits_per_sample: u16) -> &'static str {
+    match streaming_format {
+        StreamingFormat::Flac => FLAC_PROT_INFO,
+        StreamingFormat::Wav | StreamingFormat::Rf64 => WAV_PROT_INFO,
+        StreamingFormat::Lpcm if bits_per_sample == 16 => L16_PROT_INFO,
+        StreamingFormat::Lpcm => L24_PROT_INFO,
+    }
+}
+
+/// the DLNA.ORG_PN/OP/FLAGS part of the protocolinfo,
+/// returned in the contentFeatures.dlna.org HTTP header
+#[must_use]
+pub fn dlna_content_features(
+    streaming_format: StreamingFormat,
+    bits_per_sample: u16,
+) -> &'static str {
+    protocol_info(streaming_format, bits_per_sample)
+        .splitn(4, ':')
+        .nth(3)
+        .unwrap_or_default()
+}
+
 /// didl metadata template
 static DIDL_TEMPLATE: &str = "\
 <DIDL-Lite xmlns=\"urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/\" \
@@ -67,7 +117,7 @@ xmlns:upnp=\"urn:schemas-upnp-org:metadata-1-0/upnp/\">\
 <item id=\"1\" parentID=\"0\" restricted=\"0\">\
 <dc:title>swyh-rs</dc:title>\
 <res bitsPerSample=\"{bits_per_sample}\" \
-nrAudioChannels=\"2\" \
+nrAudioChannels=\"{channels}\" \
 sampleFrequency=\"{sample_rate}\" \
 protocolInfo=\"{didl_prot_info}\" \
 duration=\"{duration}\" >{server_uri}</res>\
@@ -85,6 +135,18 @@ xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
 </s:Body>\
 </s:Envelope>";
 
+/// OH play the playlist track with id template
+static OH_SEEK_ID_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:SeekId xmlns:u=\"urn:av-openhome-org:service:Playlist:1\">\
+<Value>{new_id}</Value>\
+</u:SeekId>\
+</s:Body>\
+</s:Envelope>";
+
 /// AV Play template
 static AV_PLAY_TEMPLATE: &str = "\
 <?xml version=\"1.0\" encoding=\"utf-8\"?>\
@@ -120,6 +182,28 @@ xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
 </s:Body>\
 </s:Envelope>";
 
+/// OH Pause template, uses Playlist service
+static OH_PAUSE_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:Pause xmlns:u=\"urn:av-openhome-org:service:Playlist:1\"/>\
+</s:Body>\
+</s:Envelope>";
+
+/// AV Pause template
+static AV_PAUSE_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:Pause xmlns:u=\"urn:schemas-upnp-org:service:AVTransport:1\">\
+<InstanceID>0</InstanceID>\
+</u:Pause>\
+</s:Body>\
+</s:Envelope>";
+
 /// OH get volume template, uses Volume service
 static OH_GET_VOL_TEMPLATE: &str = "\
 <?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
@@ -170,9 +254,141 @@ xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
 </s:Body>\
 </s:Envelope>";
 
+/// OH set mute template, uses Volume service
+static OH_SET_MUTE_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:SetMute xmlns:u=\"urn:av-openhome-org:service:Volume:1\">\
+<Value>{mute}</Value>\
+</u:SetMute>\
+</s:Body>\
+</s:Envelope>";
+
+/// AV set Mute template, uses `RenderingControl` service
+static AV_SET_MUTE_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:SetMute xmlns:u=\"urn:schemas-upnp-org:service:RenderingControl:1\">\
+<InstanceID>0</InstanceID>\
+<Channel>Master</Channel>\
+<DesiredMute>{mute}</DesiredMute>\
+</u:SetMute>\
+</s:Body>\
+</s:Envelope>";
+
+/// OH get transport state template, uses Playlist service
+static OH_TRANSPORT_STATE_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:TransportState xmlns:u=\"urn:av-openhome-org:service:Playlist:1\"/>\
+</s:Body>\
+</s:Envelope>";
+
+/// AV get transport info template
+static AV_TRANSPORT_INFO_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:GetTransportInfo xmlns:u=\"urn:schemas-upnp-org:service:AVTransport:1\">\
+<InstanceID>0</InstanceID>\
+</u:GetTransportInfo>\
+</s:Body>\
+</s:Envelope>";
+
+/// OH get time template, uses Time service
+static OH_TIME_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:Time xmlns:u=\"urn:av-openhome-org:service:Time:1\"/>\
+</s:Body>\
+</s:Envelope>";
+
+/// AV get position info template
+static AV_POSITION_INFO_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:GetPositionInfo xmlns:u=\"urn:schemas-upnp-org:service:AVTransport:1\">\
+<InstanceID>0</InstanceID>\
+</u:GetPositionInfo>\
+</s:Body>\
+</s:Envelope>";
+
+/// CM get protocol info template
+static CM_PROTOCOL_INFO_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:GetProtocolInfo xmlns:u=\"urn:schemas-upnp-org:service:ConnectionManager:1\"/>\
+</s:Body>\
+</s:Envelope>";
+
+/// Sonos `GetZoneGroupState` template
+static ZGT_GET_ZONE_GROUP_STATE_TEMPLATE: &str = "\
+<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" \
+xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:GetZoneGroupState xmlns:u=\"urn:schemas-upnp-org:service:ZoneGroupTopology:1\"/>\
+</s:Body>\
+</s:Envelope>";
+
 /// Bad XML template error
 static BAD_TEMPL: &str = "Bad xml template (strfmt)";
 
+/// an authentication hook: adds the credentials of a renderer (by name) to a SOAP request,
+/// for renderers that need HTTP auth or a pairing token on their control endpoints
+pub type AuthHook = fn(&str, RequestBuilder) -> RequestBuilder;
+
+/// the authentication hooks, applied in order, the configured credentials first
+static AUTH_HOOKS: LazyLock<RwLock<Vec<AuthHook>>> =
+    LazyLock::new(|| RwLock::new(vec![config_auth]));
+
+/// add an authentication hook, e.g. for the pairing of another ecosystem
+pub fn register_auth_hook(hook: AuthHook) {
+    AUTH_HOOKS.write().push(hook);
+}
+
+/// the credentials in the `auth` renderer settings: HTTP basic auth with the username
+/// and password, and the token as a bearer token or in the configured header
+fn config_auth(renderer: &str, mut request: RequestBuilder) -> RequestBuilder {
+    // the keyring is not queried while the config is locked
+    let (auth, config_id) = {
+        let conf = CONFIG.read();
+        let Some(auth) = conf.renderers.get(renderer).and_then(|s| s.auth.clone()) else {
+            return request;
+        };
+        (auth, conf.config_id.clone().unwrap_or_default())
+    };
+    if let Some(username) = &auth.username {
+        request = request.basic_auth(username, auth.password(renderer, &config_id));
+    }
+    if let Some(token) = auth.token(renderer, &config_id) {
+        request = match &auth.token_header {
+            Some(header) => match HeaderName::from_bytes(header.as_bytes()) {
+                Ok(name) => request.header(name, token),
+                Err(_) => {
+                    error!("Invalid token header {header} for {renderer}");
+                    request
+                }
+            },
+            None => request.bearer_auth(token),
+        };
+    }
+    request
+}
+
 // some audio config info
 #[derive(Debug, Clone, Copy)]
 pub struct WavData {
@@ -204,6 +420,11 @@ impl AvService {
             control_url: String::new(),
         }
     }
+
+    #[must_use]
+    pub fn service_type(&self) -> &str {
+        &self.service_type
+    }
 }
 
 bitflags! {
@@ -223,17 +444,25 @@ pub struct SupportedProtocols: u32 {
 pub struct Renderer {
     pub dev_name: String,
     pub dev_model: String,
+    pub dev_manufacturer: String,
     pub dev_type: String,
     pub dev_url: String,
     pub oh_control_url: String,
     pub av_control_url: String,
     pub oh_volume_url: String,
     pub av_volume_url: String,
+    pub oh_time_url: String,
     pub volume: i32,
     pub supported_protocols: SupportedProtocols,
     pub remote_addr: String,
     pub location: String,
     pub services: Vec<AvService>,
+    pub quirk: Option<Quirk>,
+    /// the group of a Sonos speaker that is grouped or paired with other speakers
+    pub group: Option<RendererGroup>,
+    /// the stream parameters of its last streaming connection, which can differ from the
+    /// configured ones when the renderer requests another format in the url
+    pub negotiated: Option<NegotiatedStream>,
 }
 
 impl Renderer {
@@ -241,20 +470,41 @@ impl Renderer {
         Renderer {
             dev_name: String::new(),
             dev_model: String::new(),
+            dev_manufacturer: String::new(),
             dev_url: String::new(),
             dev_type: String::new(),
             oh_control_url: String::new(),
             av_control_url: String::new(),
             oh_volume_url: String::new(),
             av_volume_url: String::new(),
+            oh_time_url: String::new(),
             volume: -1,
             supported_protocols: SupportedProtocols::NONE,
             remote_addr: String::new(),
             location: String::new(),
             services: Vec::new(),
+            quirk: None,
+            group: None,
+            negotiated: None,
         }
     }
 
+    /// the label of the renderer button: the model and the name of the renderer,
+    /// or the group name for the coordinator of a group
+    #[must_use]
+    pub fn button_label(&self) -> String {
+        match &self.group {
+            Some(group) if group.coordinator => format!("{} {}", self.dev_model, group.name),
+            _ => format!("{} {}", self.dev_model, self.dev_name),
+        }
+    }
+
+    /// true for a grouped speaker that plays what the coordinator of its group plays
+    #[must_use]
+    pub fn is_group_member(&self) -> bool {
+        self.group.as_ref().is_some_and(|g| !g.coordinator)
+    }
+
     fn parse_url(dev_url: &str, log: &dyn Fn(&str)) -> (String, u16) {
         let host: String;
         let port: u16;
@@ -275,16 +525,21 @@ impl Renderer {
     }
 
     /// `oh_soap_request` - send an `OpenHome` SOAP message to a renderer
-    fn soap_request(url: &str, soap_action: &str, body: &str) -> Option<String> {
+    fn soap_request(&self, url: &str, soap_action: &str, body: &str) -> Option<String> {
         debug!(
             "url: {},\r\n=>SOAP Action: {},\r\n=>SOAP xml: \r\n{}",
             url.to_string(),
             soap_action,
             body
         );
-        match attohttpc::post(url)
+        let mut request = attohttpc::post(url);
+        for hook in AUTH_HOOKS.read().iter() {
+            request = hook(&self.dev_name, request);
+        }
+        match request
             .header("Connection", "close")
-            .header("User-Agent", format!("swyh-rs/{APP_VERSION}"))
+            .header("User-Agent", upnp_user_agent())
+            .header("CPUUID.UPNP.ORG", upnp_udn().trim_start_matches("uuid:"))
             .header("Accept", "*/*")
             .header("SOAPAction", format!("\"{soap_action}\""))
             .header("Content-Type", "text/xml; charset=\"utf-8\"")
@@ -319,8 +574,96 @@ impl Renderer {
         -1
     }
 
+    /// set volume, and remember it as the last volume of this renderer
     pub fn set_volume(&mut self, log: &dyn Fn(&str), vol: i32) {
         self.volume = vol;
+        self.send_volume(log);
+        volume_set(&self.location, vol);
+        self.remember_volume(vol);
+    }
+
+    /// remember the volume of this renderer if `restore_volume` is set, e.g. after it was
+    /// changed with the vendor remote
+    ///
+    /// only in memory, every step of a volume slider would rewrite the config file,
+    /// it is written when a renderer stops playing and at shutdown
+    pub fn remember_volume(&self, vol: i32) {
+        let mut conf = CONFIG.write();
+        if !conf.restore_volume {
+            return;
+        }
+        let settings = conf.renderers.entry(self.dev_name.clone()).or_default();
+        if settings.volume != Some(vol) {
+            settings.volume = Some(vol);
+            VOLUME_UNSAVED.store(true, Ordering::Release);
+        }
+    }
+
+    /// `restore_volume` - set the remembered volume of this renderer (if enabled)
+    /// and/or limit it to the configured cap before playback starts
+    fn restore_volume(&self, log: &dyn Fn(&str)) {
+        let (remembered, cap) = {
+            let conf = CONFIG.read();
+            let remembered = conf
+                .renderers
+                .get(&self.dev_name)
+                .and_then(|s| s.volume)
+                .filter(|_| conf.restore_volume);
+            (remembered, conf.start_volume_cap.map(i32::from))
+        };
+        if remembered.is_none() && cap.is_none() {
+            return;
+        }
+        // the volume may have been changed with the vendor app since
+        let mut r = self.clone();
+        let current = r.get_volume(log);
+        if let Some(vol) = start_volume(current, remembered, cap) {
+            log(&format!(
+                "Restoring volume of {} to {vol}% (was {current}%)",
+                self.dev_name
+            ));
+            r.volume = vol;
+            r.send_volume(log);
+        }
+    }
+
+    /// mute or unmute, the volume is kept
+    pub fn set_mute(&self, log: &dyn Fn(&str), mute: bool) {
+        let (url, action, tmpl) = if self
+            .supported_protocols
+            .contains(SupportedProtocols::OPENHOME)
+        {
+            (
+                &self.oh_volume_url,
+                "urn:av-openhome-org:service:Volume:1#SetMute",
+                OH_SET_MUTE_TEMPLATE.replace("{mute}", if mute { "true" } else { "false" }),
+            )
+        } else if self
+            .supported_protocols
+            .contains(SupportedProtocols::AVTRANSPORT)
+        {
+            (
+                &self.av_volume_url,
+                "urn:schemas-upnp-org:service:RenderingControl:1#SetMute",
+                AV_SET_MUTE_TEMPLATE.replace("{mute}", if mute { "1" } else { "0" }),
+            )
+        } else {
+            return;
+        };
+        let (host, port) = Self::parse_url(&self.dev_url, log);
+        let url = format!("http://{host}:{port}{url}");
+        log(&format!(
+            "Set Mute on {} host={host} port={port}: {}",
+            self.dev_name,
+            if mute { "on" } else { "off" }
+        ));
+        let mute_xml = self
+            .soap_request(&url, action, &tmpl)
+            .unwrap_or("<Error/>".to_string());
+        debug!("set_mute response: {mute_xml}");
+    }
+
+    fn send_volume(&mut self, log: &dyn Fn(&str)) {
         if self
             .supported_protocols
             .contains(SupportedProtocols::OPENHOME)
@@ -341,18 +684,156 @@ impl Renderer {
         server_port: u16,
         log: &dyn Fn(&str),
         streaminfo: StreamInfo,
+    ) -> Result<(), &str> {
+        self.start_play(local_addr, server_port, log, streaminfo, false)
+    }
+
+    /// `play_item` - let this renderer play an item of a media server (control point mode)
+    ///
+    /// `uri` is the url of the item and `metadata` its DIDL-Lite, the renderer gets them
+    /// with the same commands that start the swyh-rs stream
+    pub fn play_item(&self, uri: &str, metadata: &str, log: &dyn Fn(&str)) -> Result<(), &str> {
+        // not the swyh-rs stream, a stop is no reason to resume
+        unwatch_renderer(&self.remote_addr);
+        power_on(self, log);
+        let fmt_vars = StdHashMap::from([
+            ("server_uri".to_string(), htmlescape::encode_minimal(uri)),
+            (
+                "didl_data".to_string(),
+                htmlescape::encode_minimal(metadata),
+            ),
+        ]);
+        log(&format!("Playing {uri} on {}", self.dev_name));
+        if self
+            .supported_protocols
+            .contains(SupportedProtocols::OPENHOME)
+        {
+            self.oh_play(log, &fmt_vars, false)
+        } else if self
+            .supported_protocols
+            .contains(SupportedProtocols::AVTRANSPORT)
+        {
+            self.av_play(log, &fmt_vars, false)
+        } else {
+            log("ERROR: play_item: no supported renderer protocol found");
+            Ok(())
+        }
+    }
+
+    /// `streaming_format` - the streaming format that `play` uses for this renderer:
+    /// the fallback format that is being tried, else the remembered format for this renderer,
+    /// else the quirk format, else the configured format
+    #[must_use]
+    pub fn streaming_format(&self, configured: StreamingFormat) -> StreamingFormat {
+        if let Some(format) = trial_format(&self.dev_name) {
+            return format;
+        }
+        if let Some(format) = CONFIG
+            .read()
+            .renderers
+            .get(&self.dev_name)
+            .and_then(|s| s.streaming_format)
+        {
+            return format;
+        }
+        self.quirk
+            .as_ref()
+            .and_then(|q| q.streaming_format)
+            .unwrap_or(configured)
+    }
+
+    /// `bits_per_sample` - the bit depth that `play` uses for this renderer:
+    /// 16 bit for a fallback format that is being tried, else the remembered bit depth for this
+    /// renderer, else the quirk bit depth, else the configured one
+    #[must_use]
+    pub fn bits_per_sample(&self, configured: u16) -> u16 {
+        if trial_format(&self.dev_name).is_some() {
+            return 16;
+        }
+        if let Some(bps) = CONFIG
+            .read()
+            .renderers
+            .get(&self.dev_name)
+            .and_then(|s| s.bits_per_sample)
+        {
+            return bps;
+        }
+        self.quirk
+            .as_ref()
+            .and_then(|q| q.bits_per_sample)
+            .unwrap_or(configured)
+    }
+
+    /// `switch_stream` - switch this playing renderer to a new stream url (format or bit depth)
+    ///
+    /// the new url is handed to the renderer without stopping it first, so it can
+    /// connect to the new stream before it drops the old connection
+    pub fn switch_stream(
+        &self,
+        local_addr: &IpAddr,
+        server_port: u16,
+        log: &dyn Fn(&str),
+        streaminfo: StreamInfo,
+    ) -> Result<(), &str> {
+        self.start_play(local_addr, server_port, log, streaminfo, true)
+    }
+
+    fn start_play(
+        &self,
+        local_addr: &IpAddr,
+        server_port: u16,
+        log: &dyn Fn(&str),
+        mut streaminfo: StreamInfo,
+        warm: bool,
     ) -> Result<(), &str> {
         // build the hashmap with the formatting vars for the OH and AV play templates
         let mut fmt_vars = StdHashMap::new();
         let (host, port) = Self::parse_url(&self.dev_url, log);
         let addr = format!("{local_addr}:{server_port}");
-
-        let local_url = match streaminfo.streaming_format {
-            StreamingFormat::Wav => format!("http://{addr}/stream/swyh.wav"),
-            StreamingFormat::Lpcm => format!("http://{addr}/stream/swyh.raw"),
-            StreamingFormat::Flac => format!("http://{addr}/stream/swyh.flac"),
-            StreamingFormat::Rf64 => format!("http://{addr}/stream/swyh.rf64"),
+        // the quirk settings for this renderer family override the configured values
+        let mut bd = None;
+        let mut ss = None;
+        let mut cm = None;
+        let mut dl = None;
+        let mut hs = None;
+        streaminfo.streaming_format = self.streaming_format(streaminfo.streaming_format);
+        if let Some(quirk) = &self.quirk {
+            bd = quirk.bits_per_sample;
+            ss = quirk.stream_size;
+            // Sonos disconnects when it receives no audio
+            if quirk.inject_silence == Some(true) {
+                start_silence_injector();
+            }
+        }
+        // and a remembered working format for this renderer overrides them all
+        if let Some(settings) = CONFIG.read().renderers.get(&self.dev_name) {
+            if settings.bits_per_sample.is_some() {
+                bd = settings.bits_per_sample;
+            }
+            cm = settings.channel_mode;
+            dl = settings.delay_msec.filter(|d| *d > 0);
+            hs = settings.header_size.filter(|h| *h != HeaderSize::Max);
+        }
+        // a fallback format that is being tried streams 16 bit
+        if trial_format(&self.dev_name).is_some() {
+            bd = Some(16);
+        }
+        if let Some(bps) = bd {
+            streaminfo.bits_per_sample = bps;
+        }
+        let query_string = to_query_string(bd, ss, cm, dl, hs);
+        // the parameters of the running capture, they may differ from those at startup
+        let channels = if let Some((sample_rate, channels)) = *STREAM_PARAMS.read() {
+            streaminfo.sample_rate = sample_rate;
+            channels
+        } else {
+            2
         };
+
+        let local_url = format!(
+            "http://{addr}{}{query_string}",
+            stream_path(streaminfo.streaming_format)
+        );
         fmt_vars.insert("server_uri".to_string(), local_url);
         fmt_vars.insert(
             "bits_per_sample".to_string(),
@@ -362,19 +843,12 @@ impl Renderer {
             "sample_rate".to_string(),
             streaminfo.sample_rate.to_string(),
         );
+        fmt_vars.insert("channels".to_string(), channels.to_string());
         fmt_vars.insert("duration".to_string(), "00:00:00".to_string());
-        let mut didl_prot: String;
-        if streaminfo.streaming_format == StreamingFormat::Flac {
-            didl_prot = htmlescape::encode_minimal(FLAC_PROT_INFO);
-        } else if streaminfo.streaming_format == StreamingFormat::Wav
-            || streaminfo.streaming_format == StreamingFormat::Rf64
-        {
-            didl_prot = htmlescape::encode_minimal(WAV_PROT_INFO);
-        } else if streaminfo.bits_per_sample == 16 {
-            didl_prot = htmlescape::encode_minimal(L16_PROT_INFO);
-        } else {
-            didl_prot = htmlescape::encode_minimal(L24_PROT_INFO);
-        }
+        let mut didl_prot = htmlescape::encode_minimal(protocol_info(
+            streaminfo.streaming_format,
+            streaminfo.bits_per_sample,
+        ));
         match strfmt(&didl_prot, &fmt_vars) {
             Ok(s) => didl_prot = s,
             Err(e) => {
@@ -394,6 +868,14 @@ impl Renderer {
             }
         }
         fmt_vars.insert("didl_data".to_string(), didl_data);
+        // power on the receiver and select its input, and don't start at the volume
+        // someone left it at
+        if !warm {
+            power_on(self, log);
+            self.restore_volume(log);
+        }
+        // watch the transport state from now on
+        watch_renderer(self);
         // now send the start playing commands
         if self
             .supported_protocols
@@ -402,7 +884,7 @@ impl Renderer {
             log(&format!(
             "OH Start playing on {} host={host} port={port} from {local_addr} using OH Playlist",
             self.dev_name));
-            return self.oh_play(log, &fmt_vars);
+            return self.oh_play(log, &fmt_vars, warm);
         } else if self
             .supported_protocols
             .contains(SupportedProtocols::AVTRANSPORT)
@@ -411,7 +893,7 @@ impl Renderer {
                 "AV Start playing on {} host={host} port={port} from {local_addr} using AV Play",
                 self.dev_name
             ));
-            return self.av_play(log, &fmt_vars);
+            return self.av_play(log, &fmt_vars, warm);
         }
         log("ERROR: play: no supported renderer protocol found");
         Ok(())
@@ -421,13 +903,18 @@ impl Renderer {
     ///
     /// the renderer will then try to get the audio from our built-in webserver
     /// at http://{_`my_ip`_}:`{server_port}/stream/swyh.wav`
+    ///
+    /// with `warm` the current playlist is kept until the new track plays
     fn oh_play(
         &self,
         log: &dyn Fn(&str),
         fmt_vars: &StdHashMap<String, String>,
+        warm: bool,
     ) -> Result<(), &str> {
         // stop anything currently playing first, Moode needs it
-        self.oh_stop_play(log);
+        if !warm {
+            self.oh_stop_play(log);
+        }
         // Send the InsertPlayList command with metadate(DIDL-Lite)
         let (host, port) = Self::parse_url(&self.dev_url, log);
         log(&format!(
@@ -442,23 +929,48 @@ impl Renderer {
             }
         };
         let url = format!("http://{host}:{port}{}", self.oh_control_url);
-        let _resp = Self::soap_request(
-            &url,
-            "urn:av-openhome-org:service:Playlist:1#Insert",
-            &xmlbody,
-        )
-        .unwrap_or_default();
+        let resp = self
+            .soap_request(
+                &url,
+                "urn:av-openhome-org:service:Playlist:1#Insert",
+                &xmlbody,
+            )
+            .unwrap_or_default();
+        // switch to the new track while the old one is still playing
+        if warm {
+            if let Some(new_id) = soap_response_value(&resp, "InsertResponse", "NewId") {
+                log(&format!(
+                    "OH Switching {} to playlist track {new_id}",
+                    self.dev_name
+                ));
+                let vars = StdHashMap::from([("new_id".to_string(), new_id)]);
+                let xmlbody = strfmt(OH_SEEK_ID_TEMPLATE, &vars).map_err(|_| BAD_TEMPL)?;
+                if self
+                    .soap_request(
+                        &url,
+                        "urn:av-openhome-org:service:Playlist:1#SeekId",
+                        &xmlbody,
+                    )
+                    .is_some()
+                {
+                    return Ok(());
+                }
+            }
+            // no new track id, do a normal start
+            return self.oh_play(log, fmt_vars, false);
+        }
         // send the Play command
         log(&format!(
             "OH Play on {} host={host} port={port}",
             self.dev_name
         ));
-        let _resp = Self::soap_request(
-            &url,
-            "urn:av-openhome-org:service:Playlist:1#Play",
-            OH_PLAY_PL_TEMPLATE,
-        )
-        .unwrap_or_default();
+        let _resp = self
+            .soap_request(
+                &url,
+                "urn:av-openhome-org:service:Playlist:1#Play",
+                OH_PLAY_PL_TEMPLATE,
+            )
+            .unwrap_or_default();
         Ok(())
     }
 
@@ -466,14 +978,19 @@ impl Renderer {
     ///
     /// the renderer will then try to get the audio from our built-in webserver
     /// at http://{_`my_ip`_}:`{server_port}/stream/swyh.wav`
+    ///
+    /// with `warm` the renderer is not stopped first
     fn av_play(
         &self,
         log: &dyn Fn(&str),
         fmt_vars: &StdHashMap<String, String>,
+        warm: bool,
     ) -> Result<(), &str> {
         // to prevent error 705 (transport locked) on some devices
         // it's necessary to send a stop play request first
-        self.av_stop_play(log);
+        if !warm {
+            self.av_stop_play(log);
+        }
         // now send SetAVTransportURI with metadate(DIDL-Lite) and play requests
         let xmlbody = match strfmt(AV_SET_TRANSPORT_URI_TEMPLATE, fmt_vars) {
             Ok(s) => s,
@@ -484,26 +1001,48 @@ impl Renderer {
         };
         let (host, port) = Self::parse_url(&self.dev_url, log);
         let url = format!("http://{host}:{port}{}", self.av_control_url);
-        let _resp = Self::soap_request(
-            &url,
-            "urn:schemas-upnp-org:service:AVTransport:1#SetAVTransportURI",
-            &xmlbody,
-        )
-        .unwrap_or_default();
+        let resp = self
+            .soap_request(
+                &url,
+                "urn:schemas-upnp-org:service:AVTransport:1#SetAVTransportURI",
+                &xmlbody,
+            )
+            .unwrap_or_default();
+        // a renderer that refuses a new url while playing gets a normal start
+        if warm && !resp.contains("SetAVTransportURIResponse") {
+            log(&format!(
+                "AV {} refused the new url while playing, restarting",
+                self.dev_name
+            ));
+            return self.av_play(log, fmt_vars, false);
+        }
         // the renderer will now send a head request first, so wait a bit
         std::thread::sleep(Duration::from_millis(100));
         // send play command
-        let _resp = Self::soap_request(
-            &url,
-            "urn:schemas-upnp-org:service:AVTransport:1#Play",
-            AV_PLAY_TEMPLATE,
-        )
-        .unwrap_or_default();
+        let _resp = self
+            .soap_request(
+                &url,
+                "urn:schemas-upnp-org:service:AVTransport:1#Play",
+                AV_PLAY_TEMPLATE,
+            )
+            .unwrap_or_default();
         Ok(())
     }
 
     /// `stop_play` - stop playing on this renderer (`OpenHome` or `AvTransport`)
     pub fn stop_play(&self, log: &dyn Fn(&str)) {
+        unwatch_renderer(&self.remote_addr);
+        // fade out the streams of this renderer before it stops
+        let fade = CLIENTS
+            .read()
+            .values()
+            .filter(|chanstrm| chanstrm.remote_ip == self.remote_addr)
+            .map(ChannelStream::fade_out)
+            .max()
+            .unwrap_or_default();
+        if !fade.is_zero() {
+            std::thread::sleep(fade);
+        }
         if self
             .supported_protocols
             .contains(SupportedProtocols::OPENHOME)
@@ -517,6 +1056,146 @@ impl Renderer {
         } else {
             log("ERROR: stop_play: no supported renderer protocol found");
         }
+        power_off(self, log);
+        save_volumes();
+    }
+
+    /// `pause` - pause playing on this renderer (`OpenHome` or `AvTransport`)
+    pub fn pause(&self, log: &dyn Fn(&str)) {
+        let (host, port) = Self::parse_url(&self.dev_url, log);
+        let (url, action, tmpl) = if self
+            .supported_protocols
+            .contains(SupportedProtocols::OPENHOME)
+        {
+            (
+                &self.oh_control_url,
+                "urn:av-openhome-org:service:Playlist:1#Pause",
+                OH_PAUSE_TEMPLATE,
+            )
+        } else if self
+            .supported_protocols
+            .contains(SupportedProtocols::AVTRANSPORT)
+        {
+            (
+                &self.av_control_url,
+                "urn:schemas-upnp-org:service:AVTransport:1#Pause",
+                AV_PAUSE_TEMPLATE,
+            )
+        } else {
+            log("ERROR: pause: no supported renderer protocol found");
+            return;
+        };
+        let url = format!("http://{host}:{port}{url}");
+        log(&format!(
+            "Pause playing on {} host={host} port={port}",
+            self.dev_name
+        ));
+        let _resp = self.soap_request(&url, action, tmpl).unwrap_or_default();
+        set_paused(&self.remote_addr, true);
+    }
+
+    /// `get_transport_state` - get the current transport state (e.g. "Playing" or "STOPPED")
+    /// of this renderer (`OpenHome` or `AvTransport`), None if it did not respond
+    pub fn get_transport_state(&self, log: &dyn Fn(&str)) -> Option<String> {
+        let (host, port) = Self::parse_url(&self.dev_url, log);
+        let state = if self
+            .supported_protocols
+            .contains(SupportedProtocols::OPENHOME)
+        {
+            let url = format!("http://{host}:{port}{}", self.oh_control_url);
+            let xml = self.soap_request(
+                &url,
+                "urn:av-openhome-org:service:Playlist:1#TransportState",
+                OH_TRANSPORT_STATE_TEMPLATE,
+            )?;
+            soap_response_value(&xml, "TransportStateResponse", "Value")
+        } else if self
+            .supported_protocols
+            .contains(SupportedProtocols::AVTRANSPORT)
+        {
+            let url = format!("http://{host}:{port}{}", self.av_control_url);
+            let xml = self.soap_request(
+                &url,
+                "urn:schemas-upnp-org:service:AVTransport:1#GetTransportInfo",
+                AV_TRANSPORT_INFO_TEMPLATE,
+            )?;
+            soap_response_value(&xml, "GetTransportInfoResponse", "CurrentTransportState")
+        } else {
+            None
+        };
+        debug!("Transport state of {}: {state:?}", self.dev_name);
+        state
+    }
+
+    /// `get_elapsed` - get the elapsed playing time in seconds reported by this renderer,
+    /// with the `OpenHome` Time service or `AvTransport` `GetPositionInfo`,
+    /// None if it did not respond or does not know
+    pub fn get_elapsed(&self, log: &dyn Fn(&str)) -> Option<u64> {
+        let (host, port) = Self::parse_url(&self.dev_url, log);
+        let elapsed = if self
+            .supported_protocols
+            .contains(SupportedProtocols::OPENHOME)
+            && !self.oh_time_url.is_empty()
+        {
+            let url = format!("http://{host}:{port}{}", self.oh_time_url);
+            let xml = self.soap_request(
+                &url,
+                "urn:av-openhome-org:service:Time:1#Time",
+                OH_TIME_TEMPLATE,
+            )?;
+            soap_response_value(&xml, "TimeResponse", "Seconds")
+                .and_then(|secs| secs.trim().parse().ok())
+        } else if self
+            .supported_protocols
+            .contains(SupportedProtocols::AVTRANSPORT)
+        {
+            let url = format!("http://{host}:{port}{}", self.av_control_url);
+            let xml = self.soap_request(
+                &url,
+                "urn:schemas-upnp-org:service:AVTransport:1#GetPositionInfo",
+                AV_POSITION_INFO_TEMPLATE,
+            )?;
+            soap_response_value(&xml, "GetPositionInfoResponse", "RelTime")
+                .and_then(|time| parse_upnp_time(&time))
+        } else {
+            None
+        };
+        debug!("Elapsed time of {}: {elapsed:?}", self.dev_name);
+        elapsed
+    }
+
+    /// `get_protocol_info` - get the protocols that the renderer can play (the `Sink` of the
+    /// `ConnectionManager` `GetProtocolInfo`), None if it has no `ConnectionManager` or
+    /// did not respond
+    pub fn get_protocol_info(&self, log: &dyn Fn(&str)) -> Option<String> {
+        let service = self
+            .services
+            .iter()
+            .find(|s| s.service_type.contains(":ConnectionManager"))?;
+        let (host, port) = Self::parse_url(&self.dev_url, log);
+        let url = format!("http://{host}:{port}{}", service.control_url);
+        let xml = self.soap_request(
+            &url,
+            "urn:schemas-upnp-org:service:ConnectionManager:1#GetProtocolInfo",
+            CM_PROTOCOL_INFO_TEMPLATE,
+        )?;
+        soap_response_value(&xml, "GetProtocolInfoResponse", "Sink")
+    }
+
+    /// the zone group state of a Sonos speaker, None if it has no `ZoneGroupTopology`
+    pub fn get_zone_group_state(&self, log: &dyn Fn(&str)) -> Option<String> {
+        let service = self
+            .services
+            .iter()
+            .find(|s| s.service_type.contains(":ZoneGroupTopology"))?;
+        let (host, port) = Self::parse_url(&self.dev_url, log);
+        let url = format!("http://{host}:{port}{}", service.control_url);
+        let xml = self.soap_request(
+            &url,
+            "urn:schemas-upnp-org:service:ZoneGroupTopology:1#GetZoneGroupState",
+            ZGT_GET_ZONE_GROUP_STATE_TEMPLATE,
+        )?;
+        soap_response_value(&xml, "GetZoneGroupStateResponse", "ZoneGroupState")
     }
 
     /// `oh_stop_play` - delete the playlist on the `OpenHome` renderer, so that it stops playing
@@ -529,12 +1208,13 @@ impl Renderer {
         ));
 
         // delete current playlist
-        let _resp = Self::soap_request(
-            &url,
-            "urn:av-openhome-org:service:Playlist:1#DeleteAll",
-            OH_DELETE_PL_TEMPLATE,
-        )
-        .unwrap_or_default();
+        let _resp = self
+            .soap_request(
+                &url,
+                "urn:av-openhome-org:service:Playlist:1#DeleteAll",
+                OH_DELETE_PL_TEMPLATE,
+            )
+            .unwrap_or_default();
     }
 
     /// `av_stop_play` - stop playing on the AV renderer
@@ -547,12 +1227,13 @@ impl Renderer {
         ));
 
         // delete current playlist
-        let _resp = Self::soap_request(
-            &url,
-            "urn:schemas-upnp-org:service:AVTransport:1#Stop",
-            AV_STOP_PLAY_TEMPLATE,
-        )
-        .unwrap_or_default();
+        let _resp = self
+            .soap_request(
+                &url,
+                "urn:schemas-upnp-org:service:AVTransport:1#Stop",
+                AV_STOP_PLAY_TEMPLATE,
+            )
+            .unwrap_or_default();
     }
 
     fn oh_get_volume(&mut self, log: &dyn Fn(&str)) -> i32 {
@@ -560,12 +1241,13 @@ impl Renderer {
         let url = format!("http://{host}:{port}{}", self.oh_volume_url);
 
         // get current volume
-        let vol_xml = Self::soap_request(
-            &url,
-            "urn:av-openhome-org:service:Volume:1#Volume",
-            OH_GET_VOL_TEMPLATE,
-        )
-        .unwrap_or("<Error/>".to_string());
+        let vol_xml = self
+            .soap_request(
+                &url,
+                "urn:av-openhome-org:service:Volume:1#Volume",
+                OH_GET_VOL_TEMPLATE,
+            )
+            .unwrap_or("<Error/>".to_string());
         // parse response to extract volume
         debug!("oh_get_volume response: {vol_xml}");
         let xmlstream = StringReader::new(&vol_xml);
@@ -605,12 +1287,13 @@ impl Renderer {
         let url = format!("http://{host}:{port}{}", self.av_volume_url);
 
         // get current volume
-        let vol_xml = Self::soap_request(
-            &url,
-            "urn:schemas-upnp-org:service:RenderingControl:1#GetVolume",
-            AV_GET_VOL_TEMPLATE,
-        )
-        .unwrap_or("<Error/>".to_string());
+        let vol_xml = self
+            .soap_request(
+                &url,
+                "urn:schemas-upnp-org:service:RenderingControl:1#GetVolume",
+                AV_GET_VOL_TEMPLATE,
+            )
+            .unwrap_or("<Error/>".to_string());
         debug!("av_get_volume response: {vol_xml}");
         let xmlstream = StringReader::new(&vol_xml);
         let parser = EventReader::new(xmlstream);
@@ -654,12 +1337,13 @@ impl Renderer {
             self.dev_name
         ));
         // set new volume
-        let vol_xml = Self::soap_request(
-            &url,
-            "urn:av-openhome-org:service:Volume:1#SetVolume",
-            &tmpl,
-        )
-        .unwrap_or("<Error/>".to_string());
+        let vol_xml = self
+            .soap_request(
+                &url,
+                "urn:av-openhome-org:service:Volume:1#SetVolume",
+                &tmpl,
+            )
+            .unwrap_or("<Error/>".to_string());
         debug!("oh_set_volume response: {vol_xml}");
     }
 
@@ -673,22 +1357,78 @@ impl Renderer {
             self.dev_name
         ));
         // set new volume
-        let vol_xml = Self::soap_request(
-            &url,
-            "urn:schemas-upnp-org:service:RenderingControl:1#SetVolume",
-            &tmpl,
-        )
-        .unwrap_or("<Error/>".to_string());
+        let vol_xml = self
+            .soap_request(
+                &url,
+                "urn:schemas-upnp-org:service:RenderingControl:1#SetVolume",
+                &tmpl,
+            )
+            .unwrap_or("<Error/>".to_string());
         debug!("av_set_volume response: {vol_xml}");
     }
 }
 
-// SSDP UDP search message for media renderers with a 3.0 second MX response time
-static SSDP_DISCOVER_MSG: &str = "M-SEARCH * HTTP/1.1\r\n\
+/// the volume to set when playback starts: the remembered volume (if restored) or the
+/// current volume, limited to the cap, or None if it doesn't change
+#[must_use]
+pub fn start_volume(current: i32, remembered: Option<i32>, cap: Option<i32>) -> Option<i32> {
+    let vol = remembered.unwrap_or(current);
+    let vol = cap.map_or(vol, |cap| vol.min(cap));
+    (vol >= 0 && vol != current).then_some(vol)
+}
+
+/// parse a UPNP time (H+:MM:SS[.F+]) to seconds,
+/// None for NOT_IMPLEMENTED or an invalid time
+fn parse_upnp_time(time: &str) -> Option<u64> {
+    let time = time.trim().split('.').next()?;
+    let mut parts = time.split(':');
+    let (h, m, s) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let h: u64 = h.trim_start_matches('+').parse().ok()?;
+    let m: u64 = m.parse().ok()?;
+    let s: u64 = s.parse().ok()?;
+    (m < 60 && s < 60).then_some(h * 3600 + m * 60 + s)
+}
+
+/// get the value of an element in a SOAP response
+pub(crate) fn soap_response_value(xml: &str, response: &str, element: &str) -> Option<String> {
+    let xmlstream = StringReader::new(xml);
+    let parser = EventReader::new(xmlstream);
+    let mut cur_elem = String::new();
+    let mut have_response = false;
+    for e in parser {
+        match e {
+            Ok(XmlEvent::StartElement { name, .. }) => {
+                cur_elem = name.local_name;
+                if cur_elem == response {
+                    have_response = true;
+                }
+            }
+            Ok(XmlEvent::Characters(value)) => {
+                if have_response && cur_elem == element {
+                    return Some(value);
+                }
+            }
+            Err(e) => {
+                error!("SOAP response XML parse error: {e}");
+                return None;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// SSDP UDP search message for media renderers (and servers) with a 3.0 second MX response time
+pub(crate) static SSDP_DISCOVER_MSG: &str = "M-SEARCH * HTTP/1.1\r\n\
 Host: 239.255.255.250:1900\r\n\
 Man: \"ssdp:discover\"\r\n\
 ST: {device_type}\r\n\
-MX: 3\r\n\r\n";
+MX: 3\r\n\
+USER-AGENT: {user_agent}\r\n\
+CPUUID.UPNP.ORG: {cpuuid}\r\n\r\n";
 
 //
 // SSDP UPNP service discovery
@@ -717,9 +1457,13 @@ pub fn discover(rmap: &HashMap<String, Renderer>, logger: &dyn Fn(&str)) -> Opti
     let mut devices: Vec<(String, SocketAddr)> = Vec::new();
     //  SSDP UDP broadcast address
     let broadcast_address: SocketAddr = ([239, 255, 255, 250], 1900).into();
-    let msg = SSDP_DISCOVER_MSG.replace("{device_type}", OH_DEVICE);
+    let udn = upnp_udn();
+    let search_msg = SSDP_DISCOVER_MSG
+        .replace("{user_agent}", &upnp_user_agent())
+        .replace("{cpuuid}", udn.trim_start_matches("uuid:"));
+    let msg = search_msg.replace("{device_type}", OH_DEVICE);
     socket.send_to(msg.as_bytes(), broadcast_address).unwrap();
-    let msg = SSDP_DISCOVER_MSG.replace("{device_type}", AV_DEVICE);
+    let msg = search_msg.replace("{device_type}", AV_DEVICE);
     socket.send_to(msg.as_bytes(), broadcast_address).unwrap();
     // collect the responses and remeber all new renderers
     let start = Instant::now();
@@ -841,29 +1585,12 @@ pub fn discover(rmap: &HashMap<String, Renderer>, logger: &dyn Fn(&str)) -> Opti
     let mut renderers: Vec<Renderer> = Vec::new();
 
     for (location, from) in devices {
-        if let Some(xml) = get_service_description(&location) {
-            if let Some(mut rend) = get_renderer(&xml) {
-                rend.location = location.clone();
-                let mut s = from.to_string();
-                if let Some(i) = s.find(':') {
-                    s.truncate(i);
-                }
-                rend.remote_addr = s;
-                // check for an absent URLBase in the description
-                // or devices like Yamaha WXAD-10 with bad URLBase port number
-                if rend.dev_url.is_empty() || !location.contains(&rend.dev_url) {
-                    let mut url_base = location;
-                    if url_base.contains("http://") {
-                        url_base = url_base["http://".to_string().len()..].to_string();
-                        let pos = url_base.find('/').unwrap_or_default();
-                        if pos > 0 {
-                            url_base = url_base[0..pos].to_string();
-                        }
-                    }
-                    rend.dev_url = format!("http://{url_base}/");
-                }
-                renderers.push(rend);
-            }
+        let mut s = from.to_string();
+        if let Some(i) = s.find(':') {
+            s.truncate(i);
+        }
+        if let Some(rend) = renderer_from_location(&location, &s, logger) {
+            renderers.push(rend);
         }
     }
 
@@ -888,12 +1615,85 @@ pub fn discover(rmap: &HashMap<String, Renderer>, logger: &dyn Fn(&str)) -> Opti
     Some(renderers)
 }
 
+/// `renderer_from_location` - get a renderer from its device description at `location`
+pub fn renderer_from_location(
+    location: &str,
+    remote_addr: &str,
+    logger: &dyn Fn(&str),
+) -> Option<Renderer> {
+    let xml = get_service_description(location)?;
+    let mut rend = get_renderer(&xml)?;
+    rend.location = location.to_string();
+    rend.remote_addr = remote_addr.to_string();
+    // check for an absent URLBase in the description
+    // or devices like Yamaha WXAD-10 with bad URLBase port number
+    if rend.dev_url.is_empty() || !location.contains(&rend.dev_url) {
+        let mut url_base = location.to_string();
+        if url_base.contains("http://") {
+            url_base = url_base["http://".to_string().len()..].to_string();
+            let pos = url_base.find('/').unwrap_or_default();
+            if pos > 0 {
+                url_base = url_base[0..pos].to_string();
+            }
+        }
+        rend.dev_url = format!("http://{url_base}/");
+    }
+    // only the coordinator of a group of speakers accepts the stream
+    rend.group = renderer_group(&rend, logger);
+    if let Some(group) = &rend.group {
+        logger(&format!(
+            "{} {} is {} of the group {}",
+            rend.dev_model,
+            rend.dev_name,
+            if group.coordinator {
+                "the coordinator"
+            } else {
+                "a member"
+            },
+            group.name
+        ));
+    }
+    // apply the known-good settings for this renderer family
+    rend.quirk = find_quirk(&rend.dev_manufacturer, &rend.dev_model);
+    if let Some(quirk) = &rend.quirk {
+        logger(&format!(
+            "Applying quirks {quirk} to {} {}",
+            rend.dev_model, rend.dev_name
+        ));
+    }
+    Some(rend)
+}
+
+/// `save_volumes` - write the remembered volumes to the config file, if any changed
+pub fn save_volumes() {
+    if VOLUME_UNSAVED.swap(false, Ordering::AcqRel) {
+        let _ = CONFIG.read().update_config();
+    }
+}
+
+/// `renderer_available` - check with a unicast HTTP request that a known renderer still
+/// serves its device description at `location`
+#[must_use]
+pub fn renderer_available(location: &str) -> bool {
+    match attohttpc::get(location)
+        .header("User-Agent", upnp_user_agent())
+        .timeout(Duration::from_secs(3))
+        .send()
+    {
+        Ok(resp) => resp.is_success(),
+        Err(e) => {
+            debug!("Renderer at {location} not available: {e}");
+            false
+        }
+    }
+}
+
 /// `get_service_description` - get the upnp service description xml for a media renderer
-fn get_service_description(location: &str) -> Option<String> {
+pub(crate) fn get_service_description(location: &str) -> Option<String> {
     debug!("Get service description for {}", location.to_string());
     let url = location.to_string();
     match attohttpc::get(url.as_str())
-        .header("User-Agent", format!("swyh-rs/{APP_VERSION}"))
+        .header("User-Agent", upnp_user_agent())
         .header("Content-Type", "text/xml")
         .text("")
         .send()
@@ -946,6 +1746,13 @@ fn get_renderer(xml: &str) -> Option<Renderer> {
                         {
                             renderer.oh_volume_url.clone_from(&service.control_url);
                         }
+                        ref id
+                            if ["Time", "urn:av-openhome-org:service"]
+                                .iter()
+                                .all(|&p| id.contains(p)) =>
+                        {
+                            renderer.oh_time_url.clone_from(&service.control_url);
+                        }
                         ref id if id.contains(":AVTransport") => {
                             renderer.av_control_url.clone_from(&service.control_url);
                             renderer.supported_protocols |= SupportedProtocols::AVTRANSPORT;
@@ -964,6 +1771,7 @@ fn get_renderer(xml: &str) -> Option<Renderer> {
                 ref el if el.contains("serviceType") => service.service_type = value,
                 ref el if el.contains("serviceId") => service.service_id = value,
                 ref el if el.contains("modelName") => renderer.dev_model = value,
+                ref el if el == "manufacturer" => renderer.dev_manufacturer = value,
                 ref el if el.contains("friendlyName") => renderer.dev_name = value,
                 ref el if el.contains("deviceType") => renderer.dev_type = value,
                 ref el if el.contains("URLBase") => renderer.dev_url = value,
@@ -1063,6 +1871,83 @@ mod tests {
         assert!(req_bps == 16);
     }
 
+    #[test]
+    fn test_didl_metadata() {
+        let mut fmt_vars = StdHashMap::new();
+        for (k, v) in [
+            ("server_uri", "http://192.168.1.10:5901/stream/swyh.flac"),
+            ("bits_per_sample", "24"),
+            ("sample_rate", "48000"),
+            ("channels", "2"),
+            ("duration", "00:00:00"),
+            ("didl_prot_info", "http-get:*:audio/flac:*"),
+        ] {
+            fmt_vars.insert(k.to_string(), v.to_string());
+        }
+        let didl = strfmt(DIDL_TEMPLATE, &fmt_vars).unwrap();
+        assert!(didl.contains("sampleFrequency=\"48000\""));
+        assert!(didl.contains("nrAudioChannels=\"2\""));
+    }
+
+    #[test]
+    fn test_soap_response_value() {
+        let xml = r#"<?xml version="1.0"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/">
+<s:Body>
+<u:GetTransportInfoResponse xmlns:u="urn:schemas-upnp-org:service:AVTransport:1">
+<CurrentTransportState>STOPPED</CurrentTransportState>
+<CurrentTransportStatus>OK</CurrentTransportStatus>
+<CurrentSpeed>1</CurrentSpeed>
+</u:GetTransportInfoResponse>
+</s:Body>
+</s:Envelope>"#;
+        assert_eq!(
+            soap_response_value(xml, "GetTransportInfoResponse", "CurrentTransportState"),
+            Some("STOPPED".to_string())
+        );
+        assert_eq!(
+            soap_response_value(xml, "TransportStateResponse", "Value"),
+            None
+        );
+        assert_eq!(soap_response_value("<Error/>", "Response", "Value"), None);
+    }
+
+    #[test]
+    fn test_parse_upnp_time() {
+        assert_eq!(parse_upnp_time("0:00:00"), Some(0));
+        assert_eq!(parse_upnp_time("1:02:03"), Some(3723));
+        assert_eq!(parse_upnp_time("00:10:05.250"), Some(605));
+        assert_eq!(parse_upnp_time("NOT_IMPLEMENTED"), None);
+        assert_eq!(parse_upnp_time("0:61:00"), None);
+        assert_eq!(parse_upnp_time(""), None);
+    }
+
+    #[test]
+    fn test_start_volume() {
+        assert_eq!(start_volume(80, None, None), None);
+        assert_eq!(start_volume(80, Some(30), None), Some(30));
+        assert_eq!(start_volume(80, None, Some(50)), Some(50));
+        assert_eq!(start_volume(80, Some(60), Some(50)), Some(50));
+        assert_eq!(start_volume(30, None, Some(50)), None);
+        assert_eq!(start_volume(-1, None, Some(50)), None);
+    }
+
+    #[test]
+    fn test_content_features() {
+        assert_eq!(
+            dlna_content_features(StreamingFormat::Flac, 24),
+            "DLNA.ORG_PN=FLAC;DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS=01700000000000000000000000000000"
+        );
+        assert_eq!(
+            dlna_content_features(StreamingFormat::Rf64, 16),
+            dlna_content_features(StreamingFormat::Wav, 16)
+        );
+        assert_eq!(
+            dlna_content_features(StreamingFormat::Lpcm, 16),
+            "DLNA.ORG_PN=LPCM"
+        );
+    }
+
     #[test]
     fn test_normalize() {
         let mut url = "/ctl".to_string();
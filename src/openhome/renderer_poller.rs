@@ -0,0 +1,304 @@
+//! Poll the renderers: their transport state, elapsed playing time and volume.
+//!
+//! One thread polls all renderers, each kind of poll has its own interval:
+//! - the transport state of the active renderers, for renderers without usable GENA events:
+//!   a renderer that was stopped with its own app or remote is detected within a few seconds,
+//!   instead of waiting for the HTTP connection to die. The main loop then turns off the
+//!   renderer button, or resumes playing if auto-resume is enabled.
+//! - the elapsed playing time of the active renderers (`OpenHome` Time service or `AvTransport`
+//!   `GetPositionInfo`), shown next to their button as a visual confirmation that the
+//!   renderer is actually consuming the stream and not just holding the connection.
+//! - the volume of the renderers with a volume slider: when the volume is changed with the
+//!   vendor app or remote, the slider would show a volume that is no longer true, and the
+//!   next nudge of the slider would make the volume jump. The changed volume is sent to the
+//!   main loop to update the slider, and remembered as the last volume of the renderer.
+
+use crossbeam_channel::Sender;
+use hashbrown::{HashMap, HashSet};
+use log::debug;
+use parking_lot::Mutex;
+use std::{sync::LazyLock, thread, time::Duration};
+
+use super::rendercontrol::Renderer;
+use crate::{enums::messages::MessageType, utils::ui_logger::ui_log};
+
+/// a renderer that swyh-rs started playing
+struct Watched {
+    renderer: Renderer,
+    // only a renderer that was seen playing can be stopped externally
+    seen_playing: bool,
+    // a paused renderer stops reading its stream
+    paused: bool,
+}
+
+/// the active renderers by ip address
+static WATCHED: LazyLock<Mutex<HashMap<String, Watched>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// the renderers with a volume slider by location, with their last known volume
+static POLLED: LazyLock<Mutex<HashMap<String, Renderer>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// the simplified transport state of a renderer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportState {
+    Playing,
+    Stopped,
+    Other,
+}
+
+impl TransportState {
+    /// translate an `OpenHome` or AV transport state
+    #[must_use]
+    pub fn from_state(state: &str) -> TransportState {
+        match state.trim().to_uppercase().as_str() {
+            "PLAYING" | "TRANSITIONING" | "BUFFERING" => TransportState::Playing,
+            "STOPPED" | "NO_MEDIA_PRESENT" => TransportState::Stopped,
+            _ => TransportState::Other,
+        }
+    }
+}
+
+/// the polling intervals in seconds, None (or 0 in the config) disables that poll
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PollIntervals {
+    pub transport_secs: Option<u32>,
+    pub position_secs: Option<u32>,
+    pub volume_secs: Option<u32>,
+}
+
+impl PollIntervals {
+    #[must_use]
+    pub fn new(
+        transport_secs: Option<u32>,
+        position_secs: Option<u32>,
+        volume_secs: Option<u32>,
+    ) -> PollIntervals {
+        PollIntervals {
+            transport_secs: transport_secs.filter(|s| *s > 0),
+            position_secs: position_secs.filter(|s| *s > 0),
+            volume_secs: volume_secs.filter(|s| *s > 0),
+        }
+    }
+
+    /// true if anything is polled
+    #[must_use]
+    pub fn any(&self) -> bool {
+        self.transport_secs.is_some() || self.position_secs.is_some() || self.volume_secs.is_some()
+    }
+}
+
+/// true if a poll with this interval is due after `elapsed` seconds
+fn due(interval_secs: Option<u32>, elapsed: u64) -> bool {
+    interval_secs.is_some_and(|secs| elapsed % u64::from(secs) == 0)
+}
+
+/// start watching a renderer that swyh-rs starts playing
+pub fn watch_renderer(renderer: &Renderer) {
+    WATCHED.lock().insert(
+        renderer.remote_addr.clone(),
+        Watched {
+            renderer: renderer.clone(),
+            seen_playing: false,
+            paused: false,
+        },
+    );
+}
+
+/// stop watching a renderer that swyh-rs stops playing
+pub fn unwatch_renderer(remote_addr: &str) {
+    WATCHED.lock().remove(remote_addr);
+}
+
+/// mark a renderer that swyh-rs is playing to as paused
+pub fn set_paused(remote_addr: &str, paused: bool) {
+    if let Some(w) = WATCHED.lock().get_mut(remote_addr) {
+        w.paused = paused;
+    }
+}
+
+/// true if the renderer at this ip address was paused, by swyh-rs or as seen by the poller
+pub(crate) fn is_paused(remote_addr: &str) -> bool {
+    WATCHED.lock().get(remote_addr).is_some_and(|w| w.paused)
+}
+
+/// the renderers that swyh-rs is playing to
+pub(crate) fn watched_renderers() -> Vec<Renderer> {
+    WATCHED
+        .lock()
+        .values()
+        .map(|w| w.renderer.clone())
+        .collect()
+}
+
+/// poll the volume of a renderer that has a volume slider
+pub fn poll_volume(renderer: &Renderer) {
+    POLLED
+        .lock()
+        .insert(renderer.location.clone(), renderer.clone());
+}
+
+/// the volume of a renderer was set by swyh-rs, it is not a change with the vendor remote
+pub fn volume_set(location: &str, vol: i32) {
+    if let Some(r) = POLLED.lock().get_mut(location) {
+        r.volume = vol;
+    }
+}
+
+/// Start the thread that polls the renderers, each kind of poll at its own interval.
+///
+/// A renderer that stopped playing is sent to the main loop as a `MessageType::TransportMessage`,
+/// the elapsed time as a `MessageType::PositionMessage` (None when the renderer stopped playing
+/// or does not report its position) and a changed volume as a `MessageType::VolumeMessage`.
+pub fn run_renderer_poller(intervals: PollIntervals, msg_tx: &Sender<MessageType>) {
+    if let Some(secs) = intervals.transport_secs {
+        ui_log(&format!(
+            "Polling the transport state of active renderers every {secs} seconds"
+        ));
+    }
+    if let Some(secs) = intervals.position_secs {
+        ui_log(&format!(
+            "Polling the elapsed time of active renderers every {secs} seconds"
+        ));
+    }
+    if let Some(secs) = intervals.volume_secs {
+        ui_log(&format!(
+            "Polling the volume of the renderers every {secs} seconds"
+        ));
+    }
+    let msg_tx = msg_tx.clone();
+    let _ = thread::Builder::new()
+        .name("renderer_poller".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            // the renderers with an elapsed time shown, by location
+            let mut shown: HashSet<String> = HashSet::new();
+            let mut elapsed = 0u64;
+            loop {
+                thread::sleep(Duration::from_secs(1));
+                elapsed += 1;
+                if due(intervals.transport_secs, elapsed) {
+                    poll_transport_state(&msg_tx);
+                }
+                if due(intervals.position_secs, elapsed) {
+                    poll_elapsed_time(&mut shown, &msg_tx);
+                }
+                if due(intervals.volume_secs, elapsed) {
+                    poll_volumes(&msg_tx);
+                }
+            }
+        });
+}
+
+/// send the active renderers that were stopped outside swyh-rs to the main loop
+fn poll_transport_state(msg_tx: &Sender<MessageType>) {
+    for renderer in watched_renderers() {
+        // no response is no information
+        let Some(state) = renderer.get_transport_state(&ui_log) else {
+            continue;
+        };
+        let mut watched = WATCHED.lock();
+        // it may have been stopped in the meantime
+        let Some(w) = watched.get_mut(&renderer.remote_addr) else {
+            continue;
+        };
+        w.paused = state.trim().eq_ignore_ascii_case("PAUSED_PLAYBACK");
+        match TransportState::from_state(&state) {
+            TransportState::Playing => w.seen_playing = true,
+            TransportState::Stopped if w.seen_playing => {
+                watched.remove(&renderer.remote_addr);
+                ui_log(&format!(
+                    "*W*W*> {} was stopped outside swyh-rs (transport state {state})",
+                    renderer.dev_name
+                ));
+                let _ = msg_tx.send(MessageType::TransportMessage(renderer.remote_addr.clone()));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// send the elapsed time of the active renderers to the main loop
+fn poll_elapsed_time(shown: &mut HashSet<String>, msg_tx: &Sender<MessageType>) {
+    let renderers = watched_renderers();
+    // clear the elapsed time of the renderers that stopped playing
+    shown.retain(|location| {
+        let playing = renderers.iter().any(|r| r.location == *location);
+        if !playing {
+            let _ = msg_tx.send(MessageType::PositionMessage(location.clone(), None));
+        }
+        playing
+    });
+    for renderer in renderers {
+        let elapsed = renderer.get_elapsed(&ui_log);
+        if elapsed.is_some() {
+            shown.insert(renderer.location.clone());
+        } else if !shown.remove(&renderer.location) {
+            continue;
+        }
+        let _ = msg_tx.send(MessageType::PositionMessage(
+            renderer.location.clone(),
+            elapsed,
+        ));
+    }
+}
+
+/// send the changed volumes of the renderers to the main loop
+fn poll_volumes(msg_tx: &Sender<MessageType>) {
+    let renderers = POLLED.lock().values().cloned().collect::<Vec<Renderer>>();
+    for mut renderer in renderers {
+        let known = renderer.volume;
+        // only log the polling in the debug log
+        let vol = renderer.get_volume(&|msg| debug!("{msg}"));
+        // no response is no information
+        if vol < 0 || vol == known {
+            continue;
+        }
+        ui_log(&format!("Volume of {} changed to {vol}", renderer.dev_name));
+        renderer.remember_volume(vol);
+        let location = renderer.location.clone();
+        POLLED.lock().insert(location.clone(), renderer);
+        let _ = msg_tx.send(MessageType::VolumeMessage(location, vol));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_state() {
+        assert_eq!(
+            TransportState::from_state("PLAYING"),
+            TransportState::Playing
+        );
+        assert_eq!(
+            TransportState::from_state("Buffering"),
+            TransportState::Playing
+        );
+        assert_eq!(
+            TransportState::from_state("Stopped"),
+            TransportState::Stopped
+        );
+        assert_eq!(
+            TransportState::from_state("NO_MEDIA_PRESENT"),
+            TransportState::Stopped
+        );
+        assert_eq!(
+            TransportState::from_state("PAUSED_PLAYBACK"),
+            TransportState::Other
+        );
+    }
+
+    #[test]
+    fn test_poll_intervals() {
+        let intervals = PollIntervals::new(Some(5), Some(0), None);
+        assert_eq!(intervals.transport_secs, Some(5));
+        assert_eq!(intervals.position_secs, None);
+        assert!(intervals.any());
+        assert!(!PollIntervals::new(Some(0), None, Some(0)).any());
+        assert!(due(Some(5), 10));
+        assert!(!due(Some(5), 11));
+        assert!(!due(None, 10));
+    }
+}
@@ -0,0 +1,192 @@
+//! The optional HTTP access log, separate from the application log, so that you can audit
+//! which devices pulled the stream and when.
+//!
+//! The format is the Common Log Format with the negotiated audio format and the duration
+//! in seconds appended, the time is the end of the request in UTC:
+//!
+//! `192.168.1.20 - - [16/Oct/2026:20:15:02 +0000] "GET /stream/swyh.flac HTTP/1.1" 200 52428800 "Flac 24 bit 44100 Hz" 3600.250`
+
+use crate::{globals::statics::CONFIG, server::health::is_health_url, utils::ui_logger::ui_log};
+use parking_lot::{const_mutex, Mutex};
+use std::{
+    fs::OpenOptions,
+    io::{Read, Result as IoResult, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tiny_http::{Request, Response};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// serializes the writes of the streaming threads
+static ACCESS_LOG_LOCK: Mutex<()> = const_mutex(());
+
+/// an HTTP request that is logged when it's done
+pub struct AccessLog {
+    remote_ip: String,
+    request_line: String,
+    start: Instant,
+    // the health checks poll /healthz, they would flood the access log
+    polled: bool,
+}
+
+impl AccessLog {
+    #[must_use]
+    pub fn new(rq: &Request) -> AccessLog {
+        AccessLog {
+            remote_ip: rq
+                .remote_addr()
+                .map_or_else(|| "-".to_string(), |a| a.ip().to_string()),
+            request_line: format!("{} {} HTTP/{}", rq.method(), rq.url(), rq.http_version()),
+            start: Instant::now(),
+            polled: is_health_url(rq.url()),
+        }
+    }
+
+    /// log a response with a known length
+    pub fn log_response<R: Read>(&self, response: &Response<R>, format: &str) {
+        self.log(
+            response.status_code().0,
+            response.data_length().unwrap_or_default() as u64,
+            format,
+        );
+    }
+
+    /// log the request if the access log is enabled
+    pub fn log(&self, status: u16, bytes: u64, format: &str) {
+        if self.polled {
+            return;
+        }
+        let path = {
+            let conf = CONFIG.read();
+            if !conf.access_log {
+                return;
+            }
+            let config_id = conf.config_id.clone().unwrap_or_default();
+            conf.log_dir()
+                .join("access{}.log".replace("{}", &config_id))
+        };
+        let line = access_log_line(
+            &self.remote_ip,
+            SystemTime::now(),
+            &self.request_line,
+            status,
+            bytes,
+            format,
+            self.start.elapsed(),
+        );
+        let _lock = ACCESS_LOG_LOCK.lock();
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut f| writeln!(f, "{line}"));
+        if let Err(e) = result {
+            ui_log(&format!(
+                "*E*E*> Could not write the access log {}: {e}",
+                path.display()
+            ));
+        }
+    }
+}
+
+/// counts the bytes that a streaming response sends
+pub struct CountingReader<R: Read> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> CountingReader<R> {
+    /// the reader and its byte counter
+    pub fn new(inner: R) -> (CountingReader<R>, Arc<AtomicU64>) {
+        let count = Arc::new(AtomicU64::new(0));
+        (
+            CountingReader {
+                inner,
+                count: count.clone(),
+            },
+            count,
+        )
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// format an access log line
+fn access_log_line(
+    remote_ip: &str,
+    time: SystemTime,
+    request_line: &str,
+    status: u16,
+    bytes: u64,
+    format: &str,
+    duration: Duration,
+) -> String {
+    format!(
+        "{remote_ip} - - [{}] \"{request_line}\" {status} {bytes} \"{format}\" {:.3}",
+        clf_time(time),
+        duration.as_secs_f64()
+    )
+}
+
+/// the time in the Common Log Format, e.g. 16/Oct/2026:20:15:02 +0000
+fn clf_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // civil date from the days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + u64::from(month <= 2);
+    format!(
+        "{day:02}/{}/{year}:{:02}:{:02}:{:02} +0000",
+        MONTHS[(month - 1) as usize],
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_log_line() {
+        assert_eq!(clf_time(UNIX_EPOCH), "01/Jan/1970:00:00:00 +0000");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(clf_time(time), "29/Feb/2024:12:34:56 +0000");
+        assert_eq!(
+            access_log_line(
+                "192.168.1.20",
+                time,
+                "GET /stream/swyh.flac HTTP/1.1",
+                200,
+                1234,
+                "Flac 24 bit 44100 Hz",
+                Duration::from_millis(3_600_250)
+            ),
+            "192.168.1.20 - - [29/Feb/2024:12:34:56 +0000] \"GET /stream/swyh.flac HTTP/1.1\" \
+            200 1234 \"Flac 24 bit 44100 Hz\" 3600.250"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! The diagnostics of the connected streaming clients, served as JSON on /api/clients,
+//! so that you can see exactly which connections the renderers and scripts have open.
+//!
+//! DELETE /api/clients/{ip} force-disconnects the connections of a client, so that a stuck
+//! renderer can pull a fresh stream, if `control_api` is enabled.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::{
+    globals::statics::CLIENTS,
+    server::ssdp_announcer::http_user_agent,
+    utils::{rwstream::ChannelStream, ui_logger::ui_log},
+};
+
+/// the response of a disconnect request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Disconnected {
+    pub disconnected: usize,
+}
+
+/// the details of a streaming connection
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClientDetails {
+    pub remote_addr: String,
+    pub remote_ip: String,
+    pub user_agent: String,
+    pub streaming_format: String,
+    pub bits_per_sample: u16,
+    pub uptime_secs: f64,
+    /// the number of captured blocks waiting to be converted or encoded
+    pub queue_depth: usize,
+    pub bytes_sent: u64,
+    pub sending_silence: bool,
+    /// the periods of silence injected because no audio was captured, and their duration
+    pub silence_periods: u64,
+    pub silence_secs: f64,
+}
+
+impl ClientDetails {
+    #[must_use]
+    pub fn new(remote_addr: &str, chs: &ChannelStream, now: Instant) -> ClientDetails {
+        ClientDetails {
+            remote_addr: remote_addr.to_string(),
+            remote_ip: chs.remote_ip.clone(),
+            user_agent: chs.user_agent.clone(),
+            streaming_format: chs.streaming_format.to_string(),
+            bits_per_sample: chs.bits_per_sample(),
+            uptime_secs: now.duration_since(chs.started).as_secs_f64(),
+            queue_depth: chs.queue_depth(),
+            bytes_sent: chs.stats.bytes_sent(),
+            sending_silence: chs.stats.in_silence(),
+            silence_periods: chs.stats.silence().periods,
+            silence_secs: chs.stats.silence().secs(),
+        }
+    }
+}
+
+/// the details of all streaming connections, sorted by remote address
+#[must_use]
+pub fn client_details() -> Vec<ClientDetails> {
+    let now = Instant::now();
+    let mut details: Vec<ClientDetails> = CLIENTS
+        .read()
+        .iter()
+        .map(|(remote_addr, chs)| ClientDetails::new(remote_addr, chs, now))
+        .collect();
+    details.sort_by(|a, b| a.remote_addr.cmp(&b.remote_addr));
+    details
+}
+
+/// the details of all streaming connections as JSON
+#[must_use]
+pub fn clients_json() -> String {
+    serde_json::to_string_pretty(&client_details()).unwrap_or_default()
+}
+
+/// force-disconnect the connections of a client, by ip address or ip:port,
+/// returns the number of connections
+pub fn disconnect_client(client: &str) -> usize {
+    let clients = CLIENTS.read();
+    let mut n = 0;
+    for (remote_addr, chs) in clients.iter() {
+        if chs.remote_ip == client || remote_addr == client {
+            ui_log(&format!("Disconnecting streaming client {remote_addr}"));
+            chs.disconnect();
+            n += 1;
+        }
+    }
+    n
+}
+
+/// the JSON response of a disconnect request
+#[must_use]
+pub fn disconnect_json(client: &str) -> (usize, String) {
+    let disconnected = disconnect_client(client);
+    (
+        disconnected,
+        serde_json::to_string(&Disconnected { disconnected }).unwrap_or_default(),
+    )
+}
+
+/// ask a running swyh-rs to disconnect a client, returns the number of connections
+pub fn request_disconnect(server: &str, client: &str) -> Result<usize, String> {
+    let url = format!("http://{server}/api/clients/{client}");
+    let resp = attohttpc::delete(&url)
+        .header("User-Agent", http_user_agent())
+        .timeout(Duration::from_secs(5))
+        .send()
+        .map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let body = resp.text().map_err(|e| e.to_string())?;
+    serde_json::from_str::<Disconnected>(&body)
+        .map(|d| d.disconnected)
+        .map_err(|_| format!("{status}: {body}"))
+}
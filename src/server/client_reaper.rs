@@ -0,0 +1,115 @@
+//! A powered-off renderer can leave a half-open streaming connection: the HTTP response
+//! blocks on a full TCP send buffer until the operating system gives up, which can take
+//! many minutes, and meanwhile the client stays in CLIENTS and blocks the shutdown.
+//!
+//! With `stale_client_secs` in the config file, a client that doesn't consume any audio for
+//! that many seconds is dropped from CLIENTS with a log entry, a healthy client always
+//! consumes audio because silence is sent when there is no captured audio.
+//!
+//! The clients of a paused renderer are skipped, a paused renderer stops reading its stream.
+
+use std::{
+    collections::HashMap,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::renderer_poller::is_paused,
+    server::pipe_output::PIPE_CLIENT,
+    utils::ui_logger::ui_log,
+};
+
+/// how often the clients are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// the clients that made no progress for the timeout, the progress of the others is updated,
+/// progress has the bytes sent and when they last changed for each client
+fn stale_clients(
+    progress: &mut HashMap<String, (u64, Instant)>,
+    bytes_sent: &[(String, u64)],
+    now: Instant,
+    timeout: Duration,
+) -> Vec<String> {
+    progress.retain(|client, _| bytes_sent.iter().any(|(c, _)| c == client));
+    let mut stale = Vec::new();
+    for (client, bytes) in bytes_sent {
+        let (last_bytes, since) = progress.entry(client.clone()).or_insert((*bytes, now));
+        if *bytes != *last_bytes {
+            *last_bytes = *bytes;
+            *since = now;
+        } else if now.duration_since(*since) >= timeout {
+            stale.push(client.clone());
+        }
+    }
+    stale
+}
+
+/// drop a client from CLIENTS, its HTTP response ends if its write ever returns
+fn reap_client(remote_addr: &str, stale: Duration) {
+    let Some(chs) = CLIENTS.write().remove(remote_addr) else {
+        return;
+    };
+    chs.disconnect();
+    chs.stop_flac_encoder();
+    ui_log(&format!(
+        "*W*W*> Dropped streaming client {remote_addr}: no audio consumed for {} seconds, \
+        the connection is probably half-open (renderer powered off?)",
+        stale.as_secs()
+    ));
+}
+
+/// start the thread that drops the stale clients, if enabled
+pub fn start_client_reaper() {
+    let Some(stale_secs) = CONFIG.read().stale_client_secs.filter(|s| *s > 0) else {
+        return;
+    };
+    let timeout = Duration::from_secs(u64::from(stale_secs));
+    let _ = thread::Builder::new()
+        .name("client_reaper".into())
+        .spawn(move || {
+            let mut progress = HashMap::new();
+            loop {
+                thread::sleep(CHECK_INTERVAL);
+                let bytes_sent: Vec<(String, u64)> = CLIENTS
+                    .read()
+                    .iter()
+                    // the pipe output is not a network connection
+                    .filter(|(remote_addr, _)| *remote_addr != PIPE_CLIENT)
+                    .filter(|(_, chs)| !is_paused(&chs.remote_ip))
+                    .map(|(remote_addr, chs)| (remote_addr.clone(), chs.stats.bytes_sent()))
+                    .collect();
+                for client in stale_clients(&mut progress, &bytes_sent, Instant::now(), timeout) {
+                    reap_client(&client, timeout);
+                    progress.remove(&client);
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stale_clients() {
+        let timeout = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut progress = HashMap::new();
+        let clients = |a: u64, b: u64| vec![("a:1".to_string(), a), ("b:2".to_string(), b)];
+        assert!(stale_clients(&mut progress, &clients(0, 0), start, timeout).is_empty());
+        let later = start + Duration::from_secs(30);
+        assert!(stale_clients(&mut progress, &clients(1000, 0), later, timeout).is_empty());
+        // b made no progress for 60 seconds, a did 30 seconds ago
+        let later = start + Duration::from_secs(60);
+        assert_eq!(
+            stale_clients(&mut progress, &clients(1000, 0), later, timeout),
+            ["b:2"]
+        );
+        // gone clients are forgotten
+        let later = start + Duration::from_secs(95);
+        assert!(stale_clients(&mut progress, &clients(2000, 0)[..1], later, timeout).is_empty());
+        assert_eq!(progress.len(), 1);
+    }
+}
@@ -0,0 +1,97 @@
+//! /healthz serves the health of swyh-rs as JSON, for the health check of a container or
+//! a service monitor: is the audio capture alive, is the streaming server alive and how
+//! many streaming clients are connected.
+//!
+//! The status code is 200 when healthy, and 503 when the audio capture is lost.
+
+use serde::Serialize;
+use std::{sync::OnceLock, time::Instant};
+
+use crate::{
+    globals::statics::{APP_VERSION, CLIENTS},
+    utils::fallback_audio::capture_alive,
+};
+
+/// when the streaming server started
+static SERVER_STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// the health of swyh-rs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Health {
+    pub status: &'static str,
+    pub version: &'static str,
+    pub capture_alive: bool,
+    pub server_alive: bool,
+    pub server_uptime_secs: u64,
+    pub clients: usize,
+}
+
+impl Health {
+    #[must_use]
+    pub fn new(capture_alive: bool, server_uptime_secs: Option<u64>, clients: usize) -> Health {
+        let server_alive = server_uptime_secs.is_some();
+        Health {
+            status: if capture_alive && server_alive {
+                "ok"
+            } else {
+                "unhealthy"
+            },
+            version: APP_VERSION,
+            capture_alive,
+            server_alive,
+            server_uptime_secs: server_uptime_secs.unwrap_or_default(),
+            clients,
+        }
+    }
+
+    /// the HTTP status code of the health
+    #[must_use]
+    pub fn status_code(&self) -> u16 {
+        if self.status == "ok" {
+            200
+        } else {
+            503
+        }
+    }
+}
+
+/// the streaming server is started
+pub fn server_started() {
+    let _ = SERVER_STARTED.set(Instant::now());
+}
+
+/// is this the url of the health endpoint
+#[must_use]
+pub fn is_health_url(url: &str) -> bool {
+    let path = url.split('?').next().unwrap_or_default();
+    path.eq_ignore_ascii_case("/healthz")
+}
+
+/// the HTTP status code and the JSON of the current health
+#[must_use]
+pub fn health_json() -> (u16, String) {
+    let uptime = SERVER_STARTED.get().map(|s| s.elapsed().as_secs());
+    let health = Health::new(capture_alive(), uptime, CLIENTS.read().len());
+    (
+        health.status_code(),
+        serde_json::to_string(&health).unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let health = Health::new(true, Some(42), 2);
+        assert_eq!(health.status_code(), 200);
+        let json = serde_json::to_string(&health).unwrap();
+        assert!(json.contains(r#""status":"ok""#));
+        assert!(json.contains(r#""clients":2"#));
+        assert_eq!(Health::new(false, Some(42), 0).status_code(), 503);
+        assert_eq!(Health::new(true, None, 0).status, "unhealthy");
+        assert!(is_health_url("/healthz?verbose=1"));
+        assert!(!is_health_url("/healthz/x"));
+    }
+}
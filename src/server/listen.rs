@@ -0,0 +1,30 @@
+//! A tiny HTML page on /listen that plays the stream in the browser,
+//! so that a phone or a PC can listen without a DLNA app.
+
+use crate::globals::statics::APP_VERSION;
+
+static LISTEN_PAGE: &str = include_str!("../../assets/listen.html");
+
+/// get the listen page if this is the /listen url
+#[must_use]
+pub fn listen_page(url: &str) -> Option<String> {
+    let path = url.split('?').next().unwrap_or_default().to_lowercase();
+    if path == "/listen" || path == "/listen/" {
+        Some(LISTEN_PAGE.replace("{version}", APP_VERSION))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listen_page() {
+        let page = listen_page("/Listen").unwrap();
+        assert!(page.contains("/stream/swyh.wav"));
+        assert!(page.contains(APP_VERSION));
+        assert!(listen_page("/stream/swyh.wav").is_none());
+    }
+}
@@ -1,2 +1,18 @@
+pub mod access_log;
+pub mod client_api;
+pub mod client_reaper;
+pub mod health;
+pub mod listen;
+pub mod pipe_output;
+pub mod players_api;
+pub mod playlist;
+pub mod port_select;
 pub mod query_params;
+pub mod rtp_sender;
+pub mod silence_pause;
+pub mod slimproto;
+pub mod ssdp_announcer;
+pub mod stream_check;
 pub mod streaming_server;
+pub mod tcp_tuning;
+pub mod test_files;
@@ -0,0 +1,115 @@
+//! Write the encoded stream to a named pipe, a file or stdout ("-"), in addition to HTTP,
+//! to pipe it straight into other tools like ffmpeg, snapserver or squeezelite.
+//!
+//! The stream has the configured streaming format and bit depth, and is fed by the
+//! `wave_reader` like any other client.
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::{
+    fs::OpenOptions,
+    io::{self, Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    enums::{
+        messages::MessageType,
+        streaming::{ChannelMode, HeaderSize, StreamingFormat, StreamingState},
+    },
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::rendercontrol::WavData,
+    server::streaming_server::StreamerFeedBack,
+    utils::{bitperfect::enforce_channel_mode, rwstream::ChannelStream, ui_logger::ui_log},
+};
+
+/// the client name of the pipe output
+pub const PIPE_CLIENT: &str = "pipe";
+
+/// Start writing the stream to `output`, a named pipe or file, or stdout if it is "-".
+pub fn start_pipe_output(output: &str, wd: &WavData, feedback_tx: &Sender<MessageType>) {
+    let (format, bps, channel_mode) = {
+        let conf = CONFIG.read();
+        (
+            conf.streaming_format.unwrap_or(StreamingFormat::Flac),
+            conf.bits_per_sample.unwrap_or(16),
+            enforce_channel_mode(conf.channel_mode.unwrap_or(ChannelMode::Stereo)),
+        )
+    };
+    let output = output.to_string();
+    let sample_rate = wd.sample_rate.0;
+    let channels = wd.channels;
+    let feedback_tx = feedback_tx.clone();
+    let _ = thread::Builder::new()
+        .name("pipe_output".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            // opening a named pipe blocks until the reader has opened it too
+            let writer: Box<dyn Write> = if output == "-" {
+                Box::new(io::stdout().lock())
+            } else {
+                match OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&output)
+                {
+                    Ok(f) => Box::new(f),
+                    Err(e) => {
+                        ui_log(&format!("*E*E*> Could not open output {output}: {e}"));
+                        return;
+                    }
+                }
+            };
+            ui_log(&format!(
+                "Writing the {format} {bps} bit stream to {}",
+                if output == "-" { "stdout" } else { &output }
+            ));
+            let (tx, rx): (Sender<Vec<f32>>, Receiver<Vec<f32>>) = unbounded();
+            let use_wav_hdr = [StreamingFormat::Wav, StreamingFormat::Rf64].contains(&format);
+            let channel_stream = ChannelStream::new(
+                tx,
+                rx,
+                PIPE_CLIENT.to_string(),
+                use_wav_hdr,
+                sample_rate,
+                bps,
+                channels,
+                format,
+                channel_mode,
+                0,
+                HeaderSize::Max,
+            );
+            CLIENTS
+                .write()
+                .insert(PIPE_CLIENT.to_string(), channel_stream.clone());
+            let feedback = |streaming_state, streaming_time| {
+                let _ = feedback_tx.send(MessageType::PlayerMessage(StreamerFeedBack {
+                    remote_ip: PIPE_CLIENT.to_string(),
+                    streaming_state,
+                    streaming_format: format,
+                    streaming_time,
+                    negotiated: None,
+                    silence: channel_stream.stats.silence(),
+                }));
+            };
+            feedback(StreamingState::Started, Duration::ZERO);
+            let start = Instant::now();
+            if let Err(e) = copy_stream(channel_stream.clone(), writer) {
+                ui_log(&format!("*W*W*> Stopped writing to {output}: {e}"));
+            }
+            CLIENTS.write().remove(PIPE_CLIENT);
+            channel_stream.stop_flac_encoder();
+            feedback(StreamingState::Ended, start.elapsed());
+        });
+}
+
+/// copy the stream to the output until it is closed
+fn copy_stream(mut stream: ChannelStream, mut writer: Box<dyn Write>) -> io::Result<()> {
+    let mut buf = vec![0u8; 16 * 1024];
+    loop {
+        let n = stream.read(&mut buf)?;
+        writer.write_all(&buf[..n])?;
+        writer.flush()?;
+    }
+}
@@ -0,0 +1,140 @@
+//! Change the active players, their volume and the streaming format of a running swyh-rs
+//! without a restart that would interrupt all streaming renderers:
+//!
+//! `POST /api/players?active=Kitchen,Living%20Room&volume=30&format=Flac&bits=24`
+//!
+//! Only a POST is accepted, so that a link or an image on a web page can't change the players,
+//! and only with `control_api` enabled.
+//!
+//! The players are matched like the MQTT commands, by (part of) their name or their ip
+//! address, the players that are not in the `active` list are stopped, an empty list stops
+//! all players, and without `active` the volume applies to the playing renderers.
+
+use serde::Serialize;
+use url::form_urlencoded;
+
+use crate::{
+    enums::{messages::MqttCommand, streaming::StreamingFormat},
+    openhome::rendercontrol::Renderer,
+    utils::mqtt::renderer_matches,
+};
+
+/// a request to change the players at runtime
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PlayersRequest {
+    pub active: Option<Vec<String>>,
+    pub volume: Option<u8>,
+    pub streaming_format: Option<StreamingFormat>,
+    pub bits_per_sample: Option<u16>,
+}
+
+/// parse the query of a /api/players request
+pub fn parse_players_request(url: &str) -> Result<PlayersRequest, String> {
+    let mut request = PlayersRequest::default();
+    let query = url.split_once('?').map_or("", |(_, q)| q);
+    for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+        match k.as_ref() {
+            "active" => {
+                request.active = Some(
+                    v.split(',')
+                        .map(str::trim)
+                        .filter(|p| !p.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                );
+            }
+            "volume" => {
+                request.volume = Some(
+                    v.parse::<u8>()
+                        .ok()
+                        .filter(|vol| *vol <= 100)
+                        .ok_or_else(|| format!("invalid volume {v}"))?,
+                );
+            }
+            "format" => {
+                request.streaming_format = Some(
+                    [
+                        StreamingFormat::Lpcm,
+                        StreamingFormat::Wav,
+                        StreamingFormat::Flac,
+                        StreamingFormat::Rf64,
+                    ]
+                    .into_iter()
+                    .find(|f| f.to_string().eq_ignore_ascii_case(&v))
+                    .ok_or_else(|| format!("invalid format {v}"))?,
+                );
+            }
+            "bits" => {
+                request.bits_per_sample = Some(match v.as_ref() {
+                    "16" => 16,
+                    "24" => 24,
+                    _ => return Err(format!("invalid bits {v}")),
+                });
+            }
+            _ => (),
+        }
+    }
+    if request == PlayersRequest::default() {
+        return Err("nothing to change, use active, volume, format and/or bits".to_string());
+    }
+    Ok(request)
+}
+
+/// the commands that stop, start and set the volume of the players for a request,
+/// the format is changed separately
+#[must_use]
+pub fn players_commands(request: &PlayersRequest, playing: &[Renderer]) -> Vec<MqttCommand> {
+    let mut commands = Vec::new();
+    if let Some(active) = &request.active {
+        for r in playing {
+            if !active.iter().any(|name| renderer_matches(r, name)) {
+                commands.push(MqttCommand::Stop(r.remote_addr.clone()));
+            }
+        }
+        for name in active {
+            commands.push(MqttCommand::Play(name.clone()));
+        }
+        if let Some(vol) = request.volume {
+            for name in active {
+                commands.push(MqttCommand::Volume(name.clone(), vol));
+            }
+        }
+    } else if let Some(vol) = request.volume {
+        for r in playing {
+            commands.push(MqttCommand::Volume(r.remote_addr.clone(), vol));
+        }
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_players_request() {
+        let request = parse_players_request(
+            "/api/players?active=Kitchen,%20Living%20Room&volume=30&format=flac&bits=24",
+        )
+        .unwrap();
+        assert_eq!(
+            request,
+            PlayersRequest {
+                active: Some(vec!["Kitchen".to_string(), "Living Room".to_string()]),
+                volume: Some(30),
+                streaming_format: Some(StreamingFormat::Flac),
+                bits_per_sample: Some(24),
+            }
+        );
+        // an empty list stops all players
+        assert_eq!(
+            parse_players_request("/api/players?active=")
+                .unwrap()
+                .active,
+            Some(Vec::new())
+        );
+        assert!(parse_players_request("/api/players?volume=101").is_err());
+        assert!(parse_players_request("/api/players?bits=8").is_err());
+        assert!(parse_players_request("/api/players").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! /playlist.m3u and /playlist.pls list the streaming urls of all formats, for hardware
+//! internet radios and apps that only accept a playlist url.
+//!
+//! The configured format comes first, because most radios play the first entry, the urls
+//! use the host the client used to reach swyh-rs.
+
+use crate::{
+    enums::streaming::StreamingFormat, globals::statics::CONFIG, server::query_params::stream_url,
+};
+
+/// the streaming urls of all formats with the configured bit depth and streamsizes,
+/// the configured format first, as (title, url)
+fn playlist_entries(host: &str) -> Vec<(String, String)> {
+    let config = CONFIG.read();
+    let bd = config.bits_per_sample.unwrap_or(16);
+    let configured = config.streaming_format.unwrap_or(StreamingFormat::Flac);
+    let mut formats = vec![
+        (StreamingFormat::Flac, config.flac_stream_size),
+        (StreamingFormat::Wav, config.wav_stream_size),
+        (StreamingFormat::Lpcm, config.lpcm_stream_size),
+        (StreamingFormat::Rf64, config.rf64_stream_size),
+    ];
+    formats.sort_by_key(|(fmt, _)| *fmt != configured);
+    formats
+        .into_iter()
+        .map(|(fmt, ss)| {
+            (
+                format!("swyh-rs {} {bd} bit", fmt.to_string().to_uppercase()),
+                stream_url(host, fmt, Some(bd), ss),
+            )
+        })
+        .collect()
+}
+
+fn m3u(entries: &[(String, String)]) -> String {
+    let mut m3u = String::from("#EXTM3U\r\n");
+    for (title, url) in entries {
+        m3u.push_str(&format!("#EXTINF:-1,{title}\r\n{url}\r\n"));
+    }
+    m3u
+}
+
+fn pls(entries: &[(String, String)]) -> String {
+    let mut pls = String::from("[playlist]\r\n");
+    for (n, (title, url)) in entries.iter().enumerate() {
+        let n = n + 1;
+        pls.push_str(&format!(
+            "File{n}={url}\r\nTitle{n}={title}\r\nLength{n}=-1\r\n"
+        ));
+    }
+    pls.push_str(&format!(
+        "NumberOfEntries={}\r\nVersion=2\r\n",
+        entries.len()
+    ));
+    pls
+}
+
+/// get the content type and the playlist if this is a playlist url
+#[must_use]
+pub fn playlist(url: &str, host: &str) -> Option<(&'static str, String)> {
+    let path = url.split('?').next().unwrap_or_default().to_lowercase();
+    match path.as_str() {
+        "/playlist.m3u" => Some(("audio/x-mpegurl", m3u(&playlist_entries(host)))),
+        "/playlist.pls" => Some(("audio/x-scpls", pls(&playlist_entries(host)))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playlist() {
+        assert!(playlist("/listen", "192.168.1.10:5901").is_none());
+        let entries = vec![
+            (
+                "swyh-rs FLAC 16 bit".to_string(),
+                "http://h/stream/swyh.flac?bd=16".to_string(),
+            ),
+            (
+                "swyh-rs WAV 16 bit".to_string(),
+                "http://h/stream/swyh.wav?bd=16".to_string(),
+            ),
+        ];
+        assert_eq!(
+            m3u(&entries),
+            "#EXTM3U\r\n\
+            #EXTINF:-1,swyh-rs FLAC 16 bit\r\nhttp://h/stream/swyh.flac?bd=16\r\n\
+            #EXTINF:-1,swyh-rs WAV 16 bit\r\nhttp://h/stream/swyh.wav?bd=16\r\n"
+        );
+        assert_eq!(
+            pls(&entries),
+            "[playlist]\r\n\
+            File1=http://h/stream/swyh.flac?bd=16\r\nTitle1=swyh-rs FLAC 16 bit\r\nLength1=-1\r\n\
+            File2=http://h/stream/swyh.wav?bd=16\r\nTitle2=swyh-rs WAV 16 bit\r\nLength2=-1\r\n\
+            NumberOfEntries=2\r\nVersion=2\r\n"
+        );
+    }
+}
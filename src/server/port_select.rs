@@ -0,0 +1,320 @@
+//! Select the port of the streaming webserver: if the configured port is in use (e.g. by
+//! another swyh-rs instance), the next free port is used, and the play URLs use that port.
+//!
+//! Optionally the UPnP Internet Gateway Device (the router) is asked if it forwards the
+//! port to another host.
+
+use log::debug;
+use std::{
+    net::{IpAddr, SocketAddr, TcpListener, UdpSocket},
+    sync::atomic::Ordering,
+    thread,
+    time::{Duration, Instant},
+};
+use stringreader::StringReader;
+use url::Url;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{
+    globals::statics::{CONFIG, SERVER_PORT_IN_USE},
+    server::ssdp_announcer::{upnp_udn, upnp_user_agent},
+    utils::ui_logger::ui_log,
+};
+
+/// the number of ports after the configured port that are tried
+const PORT_SEARCH_RANGE: u16 = 20;
+
+const IGD_DEVICE: &str = "urn:schemas-upnp-org:device:InternetGatewayDevice:1";
+/// the WAN services that can forward ports
+const WAN_SERVICES: [&str; 2] = ["WANIPConnection", "WANPPPConnection"];
+/// the IGD's have 2 seconds to respond
+const IGD_SEARCH_TIME: Duration = Duration::from_millis(2100);
+
+static IGD_SEARCH_MSG: &str = "M-SEARCH * HTTP/1.1\r\n\
+Host: 239.255.255.250:1900\r\n\
+Man: \"ssdp:discover\"\r\n\
+ST: {device_type}\r\n\
+MX: 2\r\n\
+USER-AGENT: {user_agent}\r\n\
+CPUUID.UPNP.ORG: {cpuuid}\r\n\r\n";
+
+static GET_PORT_MAPPING_TEMPLATE: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<s:Envelope s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\" xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+<s:Body>\
+<u:GetSpecificPortMappingEntry xmlns:u=\"{service_type}\">\
+<NewRemoteHost></NewRemoteHost>\
+<NewExternalPort>{port}</NewExternalPort>\
+<NewProtocol>TCP</NewProtocol>\
+</u:GetSpecificPortMappingEntry>\
+</s:Body>\
+</s:Envelope>";
+
+/// the port the HTTP server listens on
+#[must_use]
+pub fn server_port() -> u16 {
+    SERVER_PORT_IN_USE.load(Ordering::Relaxed)
+}
+
+/// the configured port first, then the next ports
+fn candidate_ports(port: u16) -> impl Iterator<Item = u16> {
+    (0..=PORT_SEARCH_RANGE).filter_map(move |n| port.checked_add(n))
+}
+
+/// select the first free port, starting with the configured port, it is used for all
+/// play URLs, the SSDP announcements and slimproto
+pub fn select_server_port(local_addr: &IpAddr, port: u16) -> u16 {
+    let free =
+        candidate_ports(port).find(|p| TcpListener::bind(SocketAddr::new(*local_addr, *p)).is_ok());
+    let selected = match free {
+        Some(p) if p != port => {
+            ui_log(&format!(
+                "*W*W*> HTTP port {port} is in use, using HTTP port {p} instead!!"
+            ));
+            p
+        }
+        Some(p) => p,
+        None => {
+            ui_log(&format!(
+                "*E*E*> HTTP port {port} and the next {PORT_SEARCH_RANGE} ports are in use!!"
+            ));
+            port
+        }
+    };
+    SERVER_PORT_IN_USE.store(selected, Ordering::Relaxed);
+    selected
+}
+
+/// a port forwarding of the router
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PortMapping {
+    client: String,
+    port: u16,
+    description: String,
+}
+
+/// Check the port forwarding of the router in the background if enabled in the config.
+pub fn start_igd_check(local_addr: &IpAddr, server_port: u16) {
+    if !CONFIG.read().igd_port_check {
+        return;
+    }
+    if !local_addr.is_ipv4() {
+        ui_log("*W*W*> The router port check is only supported on IPv4");
+        return;
+    }
+    let local_addr = *local_addr;
+    let _ = thread::Builder::new()
+        .name("igd_check".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || check_igd(local_addr, server_port))
+        .unwrap();
+}
+
+fn check_igd(local_addr: IpAddr, port: u16) {
+    let Some(location) = find_igd(local_addr) else {
+        ui_log(&format!(
+            "No UPnP router found, forwarding of port {port} not checked"
+        ));
+        return;
+    };
+    let Some((service_type, control_url)) = get_wan_service(&location) else {
+        ui_log(&format!(
+            "The UPnP router at {location} has no WAN connection service, forwarding of port {port} not checked"
+        ));
+        return;
+    };
+    match get_port_mapping(&control_url, &service_type, port) {
+        Some(m) if m.client == local_addr.to_string() && m.port == port => {
+            ui_log(&format!(
+                "The router forwards TCP port {port} to swyh-rs ({})",
+                m.description
+            ));
+        }
+        Some(m) => {
+            ui_log(&format!(
+                "*W*W*> The router forwards TCP port {port} to {}:{} ({}), not to swyh-rs!!",
+                m.client, m.port, m.description
+            ));
+        }
+        None => ui_log(&format!("The router does not forward TCP port {port}")),
+    }
+}
+
+/// the location of the device description of the first IGD that responds
+fn find_igd(local_addr: IpAddr) -> Option<String> {
+    let socket = UdpSocket::bind(SocketAddr::new(local_addr, 0)).ok()?;
+    let _ = socket.set_multicast_ttl_v4(2);
+    let udn = upnp_udn();
+    let msg = IGD_SEARCH_MSG
+        .replace("{device_type}", IGD_DEVICE)
+        .replace("{user_agent}", &upnp_user_agent())
+        .replace("{cpuuid}", udn.trim_start_matches("uuid:"));
+    let ssdp_addr: SocketAddr = ([239, 255, 255, 250], 1900).into();
+    socket.send_to(msg.as_bytes(), ssdp_addr).ok()?;
+    let start = Instant::now();
+    let mut buf = [0u8; 2048];
+    while let Some(wait) = IGD_SEARCH_TIME.checked_sub(start.elapsed()) {
+        let _ = socket.set_read_timeout(Some(wait.max(Duration::from_millis(1))));
+        let Ok((received, from)) = socket.recv_from(&mut buf) else {
+            break;
+        };
+        let resp = String::from_utf8_lossy(&buf[0..received]);
+        debug!("IGD search response from {from}:\r\n{resp}");
+        let location = resp.split("\r\n").find_map(|l| {
+            let (header, value) = l.split_once(':')?;
+            header
+                .eq_ignore_ascii_case("LOCATION")
+                .then(|| value.trim().to_string())
+        });
+        if location.is_some() {
+            return location;
+        }
+    }
+    None
+}
+
+/// the service type and the absolute control URL of the WAN connection service of the IGD
+fn get_wan_service(location: &str) -> Option<(String, String)> {
+    let xml = attohttpc::get(location)
+        .header("User-Agent", upnp_user_agent())
+        .timeout(Duration::from_secs(3))
+        .send()
+        .ok()?
+        .text()
+        .ok()?;
+    let (service_type, control_url) = parse_wan_service(&xml)?;
+    let control_url = Url::parse(location).ok()?.join(&control_url).ok()?;
+    Some((service_type, control_url.to_string()))
+}
+
+/// the service type and control URL of the first WAN connection service in the description
+fn parse_wan_service(xml: &str) -> Option<(String, String)> {
+    let parser = EventReader::new(StringReader::new(xml));
+    let mut cur_elem = String::new();
+    let mut service_type = String::new();
+    let mut control_url = String::new();
+    for e in parser {
+        match e {
+            Ok(XmlEvent::StartElement { name, .. }) => cur_elem = name.local_name,
+            Ok(XmlEvent::Characters(value)) => match cur_elem.as_str() {
+                "serviceType" => service_type = value,
+                "controlURL" => control_url = value,
+                _ => (),
+            },
+            Ok(XmlEvent::EndElement { name }) if name.local_name == "service" => {
+                if WAN_SERVICES.iter().any(|s| service_type.contains(s)) {
+                    return Some((service_type, control_url));
+                }
+                service_type.clear();
+                control_url.clear();
+            }
+            Err(e) => {
+                debug!("IGD description XML parse error: {e}");
+                return None;
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// the forwarding of the external TCP port, None if there is none (or no answer)
+fn get_port_mapping(control_url: &str, service_type: &str, port: u16) -> Option<PortMapping> {
+    let body = GET_PORT_MAPPING_TEMPLATE
+        .replace("{service_type}", service_type)
+        .replace("{port}", &port.to_string());
+    let resp = attohttpc::post(control_url)
+        .header("Connection", "close")
+        .header("User-Agent", upnp_user_agent())
+        .header(
+            "SOAPAction",
+            format!("\"{service_type}#GetSpecificPortMappingEntry\""),
+        )
+        .header("Content-Type", "text/xml; charset=\"utf-8\"")
+        .timeout(Duration::from_secs(3))
+        .text(body)
+        .send()
+        .ok()?;
+    // NoSuchEntryInArray is a SOAP fault with status 500
+    if !resp.is_success() {
+        return None;
+    }
+    parse_port_mapping(&resp.text().ok()?)
+}
+
+fn parse_port_mapping(xml: &str) -> Option<PortMapping> {
+    let parser = EventReader::new(StringReader::new(xml));
+    let mut cur_elem = String::new();
+    let mut client = None;
+    let mut port = None;
+    let mut description = String::new();
+    for e in parser {
+        match e {
+            Ok(XmlEvent::StartElement { name, .. }) => cur_elem = name.local_name,
+            Ok(XmlEvent::Characters(value)) => match cur_elem.as_str() {
+                "NewInternalClient" => client = Some(value),
+                "NewInternalPort" => port = value.trim().parse().ok(),
+                "NewPortMappingDescription" => description = value,
+                _ => (),
+            },
+            Err(e) => {
+                debug!("IGD port mapping XML parse error: {e}");
+                return None;
+            }
+            _ => (),
+        }
+    }
+    Some(PortMapping {
+        client: client?,
+        port: port?,
+        description,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidate_ports() {
+        let ports: Vec<u16> = candidate_ports(5901).collect();
+        assert_eq!(ports.len(), usize::from(PORT_SEARCH_RANGE) + 1);
+        assert_eq!(ports[0], 5901);
+        assert_eq!(ports[1], 5902);
+        assert_eq!(candidate_ports(65534).collect::<Vec<u16>>(), [65534, 65535]);
+    }
+
+    #[test]
+    fn test_parse_igd() {
+        let description = "<root><device><serviceList>\
+            <service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+            <controlURL>/ctl/L3F</controlURL></service>\
+            <service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+            <controlURL>/ctl/IPConn</controlURL></service>\
+            </serviceList></device></root>";
+        assert_eq!(
+            parse_wan_service(description),
+            Some((
+                "urn:schemas-upnp-org:service:WANIPConnection:1".to_string(),
+                "/ctl/IPConn".to_string()
+            ))
+        );
+        let response = "<?xml version=\"1.0\"?>\
+            <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\"><s:Body>\
+            <u:GetSpecificPortMappingEntryResponse xmlns:u=\"urn:schemas-upnp-org:service:WANIPConnection:1\">\
+            <NewInternalPort>5901</NewInternalPort>\
+            <NewInternalClient>192.168.1.20</NewInternalClient>\
+            <NewEnabled>1</NewEnabled>\
+            <NewPortMappingDescription>NAS</NewPortMappingDescription>\
+            <NewLeaseDuration>0</NewLeaseDuration>\
+            </u:GetSpecificPortMappingEntryResponse></s:Body></s:Envelope>";
+        assert_eq!(
+            parse_port_mapping(response),
+            Some(PortMapping {
+                client: "192.168.1.20".to_string(),
+                port: 5901,
+                description: "NAS".to_string(),
+            })
+        );
+        assert_eq!(parse_port_mapping("<s:Envelope/>"), None);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::enums::streaming::{BitDepth, StreamSize, StreamingFormat};
+use crate::enums::streaming::{BitDepth, ChannelMode, HeaderSize, StreamSize, StreamingFormat};
 use std::str::FromStr;
 
 const VALID_URLS: [&str; 4] = [
@@ -13,6 +13,9 @@ pub struct StreamingParams {
     pub path: Option<String>,
     pub bd: Option<BitDepth>,
     pub ss: Option<StreamSize>,
+    pub cm: Option<ChannelMode>,
+    pub dl: Option<u32>,
+    pub hs: Option<HeaderSize>,
     pub fmt: Option<StreamingFormat>,
 }
 
@@ -23,6 +26,9 @@ impl StreamingParams {
             path: None,
             bd: None,
             ss: None,
+            cm: None,
+            dl: None,
+            hs: None,
             fmt: None,
         };
         if !url.contains('/') {
@@ -61,7 +67,8 @@ impl StreamingParams {
             return result;
         }
         // parse key=value pairs from querystring if present
-        // extract bd (bit depth) and ss (streamsize) if found
+        // extract bd (bit depth), ss (streamsize), cm (channel mode), dl (delay)
+        // and hs (WAV/RF64 header size) if found
         let query_string = parts[1];
         if !query_string.is_empty() {
             query_string
@@ -76,6 +83,9 @@ impl StreamingParams {
                 .for_each(|kv_pair| match kv_pair.0 {
                     "bd" => result.bd = Some(BitDepth::from_str(kv_pair.1).unwrap()),
                     "ss" => result.ss = Some(StreamSize::from_str(kv_pair.1).unwrap()),
+                    "cm" => result.cm = Some(ChannelMode::from_str(kv_pair.1).unwrap()),
+                    "dl" => result.dl = kv_pair.1.parse().ok(),
+                    "hs" => result.hs = kv_pair.1.parse().ok(),
                     _ => (),
                 });
         }
@@ -83,6 +93,66 @@ impl StreamingParams {
     }
 }
 
+/// build the query string for a streaming url with the optional bit depth, streamsize,
+/// channel mode, delay in msec and WAV/RF64 header size
+#[must_use]
+pub fn to_query_string(
+    bd: Option<u16>,
+    ss: Option<StreamSize>,
+    cm: Option<ChannelMode>,
+    dl: Option<u32>,
+    hs: Option<HeaderSize>,
+) -> String {
+    let mut params = Vec::new();
+    if let Some(bps) = bd {
+        params.push(format!("bd={bps}"));
+    }
+    if let Some(ss) = ss {
+        params.push(format!("ss={}", ss.to_string().to_lowercase()));
+    }
+    if let Some(cm) = cm {
+        params.push(format!("cm={}", cm.to_string().to_lowercase()));
+    }
+    if let Some(dl) = dl {
+        params.push(format!("dl={dl}"));
+    }
+    if let Some(hs) = hs {
+        params.push(format!("hs={hs}"));
+    }
+    if params.is_empty() {
+        String::new()
+    } else {
+        format!("?{}", params.join("&"))
+    }
+}
+
+/// the path of the streaming url of a format
+#[must_use]
+pub fn stream_path(format: StreamingFormat) -> &'static str {
+    match format {
+        StreamingFormat::Wav => "/stream/swyh.wav",
+        StreamingFormat::Lpcm => "/stream/swyh.raw",
+        StreamingFormat::Flac => "/stream/swyh.flac",
+        StreamingFormat::Rf64 => "/stream/swyh.rf64",
+    }
+}
+
+/// the streaming url of a format at `addr` (ip:port) with the optional bit depth and
+/// streamsize, e.g. to configure a third-party player by hand
+#[must_use]
+pub fn stream_url(
+    addr: &str,
+    format: StreamingFormat,
+    bd: Option<u16>,
+    ss: Option<StreamSize>,
+) -> String {
+    format!(
+        "http://{addr}{}{}",
+        stream_path(format),
+        to_query_string(bd, ss, None, None, None)
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use crate::server::query_params::*;
@@ -124,4 +194,51 @@ mod tests {
         assert_eq!(sp.ss, None);
         assert_eq!(sp.fmt, None);
     }
+
+    #[test]
+    fn test_to_query_string() {
+        assert_eq!(to_query_string(None, None, None, None, None), "");
+        assert_eq!(to_query_string(Some(24), None, None, None, None), "?bd=24");
+        let qs = to_query_string(
+            Some(16),
+            Some(StreamSize::U32maxNotChunked),
+            None,
+            None,
+            None,
+        );
+        assert_eq!(qs, "?bd=16&ss=u32maxnotchunked");
+        let sp = StreamingParams::from_query_string(&format!("/stream/swyh.wav{qs}"));
+        assert_eq!(sp.bd, Some(BitDepth::Bits16));
+        assert_eq!(sp.ss, Some(StreamSize::U32maxNotChunked));
+        assert_eq!(sp.cm, None);
+        let qs = to_query_string(None, None, Some(ChannelMode::Mono), Some(250), None);
+        assert_eq!(qs, "?cm=mono&dl=250");
+        let sp = StreamingParams::from_query_string(&format!("/stream/swyh.flac{qs}"));
+        assert_eq!(sp.cm, Some(ChannelMode::Mono));
+        assert_eq!(sp.dl, Some(250));
+        let qs = to_query_string(None, None, None, None, Some(HeaderSize::Secs(3600)));
+        assert_eq!(qs, "?hs=3600s");
+        let sp = StreamingParams::from_query_string(&format!("/stream/swyh.wav{qs}"));
+        assert_eq!(sp.hs, Some(HeaderSize::Secs(3600)));
+        let sp = StreamingParams::from_query_string("/stream/swyh.rf64?hs=1000000");
+        assert_eq!(sp.hs, Some(HeaderSize::Bytes(1_000_000)));
+    }
+
+    #[test]
+    fn test_stream_url() {
+        let url = stream_url(
+            "192.168.1.10:5901",
+            StreamingFormat::Lpcm,
+            Some(24),
+            Some(StreamSize::U64maxNotChunked),
+        );
+        assert_eq!(
+            url,
+            "http://192.168.1.10:5901/stream/swyh.raw?bd=24&ss=u64maxnotchunked"
+        );
+        let sp =
+            StreamingParams::from_query_string(url.trim_start_matches("http://192.168.1.10:5901"));
+        assert_eq!(sp.fmt, Some(StreamingFormat::Lpcm));
+        assert_eq!(sp.bd, Some(BitDepth::Bits24));
+    }
 }
@@ -0,0 +1,251 @@
+//! Send the captured audio as RTP (RFC 3550) L16/L24 packets (RFC 3190/3551) to a UDP
+//! unicast or multicast destination, as an alternative to HTTP streaming for renderers
+//! and pro-audio receivers that support RTP.
+//!
+//! The matching SDP file is written to the config directory and served on /rtp/swyh.sdp.
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use dasp_sample::Sample;
+use parking_lot::RwLock;
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{atomic::Ordering, LazyLock},
+    thread,
+};
+
+use crate::{
+    globals::statics::{CONFIG, RUN_RTP_SENDER},
+    openhome::rendercontrol::WavData,
+    utils::ui_logger::ui_log,
+};
+
+/// the maximum payload size that fits in an ethernet frame
+const MAX_PAYLOAD: usize = 1440;
+/// the preferred packet time
+const PACKET_MSEC: usize = 5;
+
+/// the channel from the audio capture to the RTP sender
+static RTP_CHANNEL: LazyLock<(Sender<Vec<f32>>, Receiver<Vec<f32>>)> = LazyLock::new(unbounded);
+/// the SDP description of the RTP stream, if it is running
+static SDP: LazyLock<RwLock<Option<String>>> = LazyLock::new(|| RwLock::new(None));
+
+/// send the captured samples to the RTP sender
+pub fn rtp_send_samples(samples: &[f32]) {
+    let _ = RTP_CHANNEL.0.send(samples.to_vec());
+}
+
+/// the SDP description of the running RTP stream, if any
+#[must_use]
+pub fn rtp_sdp() -> Option<String> {
+    SDP.read().clone()
+}
+
+/// the RTP stream parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RtpFormat {
+    payload_type: u8,
+    bits_per_sample: u16,
+    sample_rate: u32,
+    channels: u16,
+    frames_per_packet: usize,
+}
+
+impl RtpFormat {
+    fn new(sample_rate: u32, channels: u16, bits_per_sample: u16) -> RtpFormat {
+        // the static payload types 10 and 11 are L16 at 44.1 kHz stereo and mono,
+        // everything else uses a dynamic payload type
+        let payload_type = match (bits_per_sample, sample_rate, channels) {
+            (16, 44100, 2) => 10,
+            (16, 44100, 1) => 11,
+            _ => 96,
+        };
+        let frame_size = usize::from(channels) * usize::from(bits_per_sample / 8);
+        let frames_per_packet =
+            (sample_rate as usize * PACKET_MSEC / 1000).min(MAX_PAYLOAD / frame_size);
+        RtpFormat {
+            payload_type,
+            bits_per_sample,
+            sample_rate,
+            channels,
+            frames_per_packet,
+        }
+    }
+
+    fn ptime_msec(&self) -> f64 {
+        let ptime = self.frames_per_packet as f64 * 1000.0 / f64::from(self.sample_rate);
+        (ptime * 1000.0).round() / 1000.0
+    }
+}
+
+/// the 12 byte RTP header
+fn rtp_header(payload_type: u8, sequence: u16, timestamp: u32, ssrc: u32) -> [u8; 12] {
+    let mut hdr = [0u8; 12];
+    hdr[0] = 0x80; // version 2, no padding, no extension, no CSRC
+    hdr[1] = payload_type & 0x7f; // no marker
+    hdr[2..4].copy_from_slice(&sequence.to_be_bytes());
+    hdr[4..8].copy_from_slice(&timestamp.to_be_bytes());
+    hdr[8..12].copy_from_slice(&ssrc.to_be_bytes());
+    hdr
+}
+
+/// the SDP description of the RTP stream
+fn sdp(fmt: &RtpFormat, source: &IpAddr, dest: &SocketAddr, ttl: u32, ssrc: u32) -> String {
+    let ip_version = if dest.is_ipv4() { "IP4" } else { "IP6" };
+    let connection = if dest.ip().is_multicast() && dest.is_ipv4() {
+        format!("{}/{ttl}", dest.ip())
+    } else {
+        dest.ip().to_string()
+    };
+    format!(
+        "v=0\r\n\
+        o=- {ssrc} 0 IN {ip_version} {source}\r\n\
+        s=swyh-rs\r\n\
+        c=IN {ip_version} {connection}\r\n\
+        t=0 0\r\n\
+        m=audio {} RTP/AVP {}\r\n\
+        a=rtpmap:{} L{}/{}/{}\r\n\
+        a=ptime:{}\r\n\
+        a=recvonly\r\n",
+        dest.port(),
+        fmt.payload_type,
+        fmt.payload_type,
+        fmt.bits_per_sample,
+        fmt.sample_rate,
+        fmt.channels,
+        fmt.ptime_msec(),
+    )
+}
+
+/// Start the RTP sender if an RTP destination is configured.
+pub fn start_rtp_sender(local_addr: &IpAddr, wd: &WavData) {
+    let (destination, ttl, bits_per_sample, config_dir) = {
+        let conf = CONFIG.read();
+        let Some(destination) = conf.rtp_destination.clone() else {
+            return;
+        };
+        (
+            destination,
+            conf.rtp_ttl.unwrap_or(1),
+            conf.bits_per_sample.unwrap_or(16),
+            conf.config_dir(),
+        )
+    };
+    let dest: SocketAddr = match destination.parse() {
+        Ok(dest) => dest,
+        Err(e) => {
+            ui_log(&format!(
+                "*E*E*> Invalid RTP destination {destination} (ip:port): {e}"
+            ));
+            return;
+        }
+    };
+    let socket = match UdpSocket::bind(SocketAddr::new(*local_addr, 0)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            ui_log(&format!("*E*E*> Could not create the RTP socket: {e}"));
+            return;
+        }
+    };
+    if dest.ip().is_multicast() && dest.is_ipv4() {
+        let _ = socket.set_multicast_ttl_v4(ttl);
+    }
+    if let Err(e) = socket.connect(dest) {
+        ui_log(&format!("*E*E*> Could not connect to {dest}: {e}"));
+        return;
+    }
+    let fmt = RtpFormat::new(wd.sample_rate.0, wd.channels, bits_per_sample);
+    let ssrc = fastrand::u32(..);
+    let sdp = sdp(&fmt, local_addr, &dest, ttl, ssrc);
+    let sdp_file = config_dir.join("swyh-rs.sdp");
+    if let Err(e) = fs::write(&sdp_file, &sdp) {
+        ui_log(&format!(
+            "*W*W*> Could not write {}: {e}",
+            sdp_file.display()
+        ));
+    }
+    *SDP.write() = Some(sdp);
+    ui_log(&format!(
+        "Sending RTP L{} {} Hz to {dest}, SDP in {}",
+        fmt.bits_per_sample,
+        fmt.sample_rate,
+        sdp_file.display()
+    ));
+    RUN_RTP_SENDER.store(true, Ordering::Release);
+    let _ = thread::Builder::new()
+        .name("rtp_sender".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || run_rtp_sender(&socket, fmt, ssrc, &RTP_CHANNEL.1));
+}
+
+/// packetize the samples and send them
+fn run_rtp_sender(socket: &UdpSocket, fmt: RtpFormat, ssrc: u32, rx: &Receiver<Vec<f32>>) {
+    let samples_per_packet = fmt.frames_per_packet * usize::from(fmt.channels);
+    let bytes_per_sample = usize::from(fmt.bits_per_sample / 8);
+    let mut sequence = fastrand::u16(..);
+    let mut timestamp = fastrand::u32(..);
+    let mut pending: Vec<f32> = Vec::with_capacity(samples_per_packet * 2);
+    let mut packet: Vec<u8> = Vec::with_capacity(12 + samples_per_packet * bytes_per_sample);
+    let mut errors = 0;
+    while let Ok(samples) = rx.recv() {
+        pending.extend_from_slice(&samples);
+        while pending.len() >= samples_per_packet {
+            packet.clear();
+            packet.extend_from_slice(&rtp_header(fmt.payload_type, sequence, timestamp, ssrc));
+            // network byte order
+            for s in pending.drain(..samples_per_packet) {
+                if fmt.bits_per_sample == 24 {
+                    packet.extend_from_slice(&s.to_sample::<i32>().to_be_bytes()[..3]);
+                } else {
+                    packet.extend_from_slice(&s.to_sample::<i16>().to_be_bytes());
+                }
+            }
+            if let Err(e) = socket.send(&packet) {
+                // don't flood the log if the network is down
+                if errors == 0 {
+                    ui_log(&format!("*W*W*> RTP send error: {e}"));
+                }
+                errors = (errors + 1) % 1000;
+            }
+            sequence = sequence.wrapping_add(1);
+            timestamp = timestamp.wrapping_add(fmt.frames_per_packet as u32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rtp_format() {
+        let fmt = RtpFormat::new(44100, 2, 16);
+        assert_eq!(fmt.payload_type, 10);
+        assert_eq!(fmt.frames_per_packet, 220);
+        let fmt = RtpFormat::new(96000, 2, 24);
+        assert_eq!(fmt.payload_type, 96);
+        assert_eq!(fmt.frames_per_packet, 240); // limited by the MTU
+    }
+
+    #[test]
+    fn test_rtp_header() {
+        let hdr = rtp_header(96, 0x1234, 0x0102_0304, 0xdead_beef);
+        assert_eq!(
+            hdr,
+            [0x80, 96, 0x12, 0x34, 1, 2, 3, 4, 0xde, 0xad, 0xbe, 0xef]
+        );
+    }
+
+    #[test]
+    fn test_sdp() {
+        let fmt = RtpFormat::new(48000, 2, 24);
+        let source: IpAddr = "192.168.1.2".parse().unwrap();
+        let dest: SocketAddr = "239.255.77.77:5004".parse().unwrap();
+        let sdp = sdp(&fmt, &source, &dest, 4, 42);
+        assert!(sdp.contains("o=- 42 0 IN IP4 192.168.1.2\r\n"));
+        assert!(sdp.contains("c=IN IP4 239.255.77.77/4\r\n"));
+        assert!(sdp.contains("m=audio 5004 RTP/AVP 96\r\n"));
+        assert!(sdp.contains("a=rtpmap:96 L24/48000/2\r\n"));
+        assert!(sdp.contains("a=ptime:5\r\n"));
+    }
+}
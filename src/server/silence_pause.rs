@@ -0,0 +1,108 @@
+//! With `silence_pause_secs` in the config file, a renderer is paused when swyh-rs has been
+//! sending it injected silence (because no audio was captured) for that many seconds
+//! without a break, instead of letting it play silence indefinitely.
+//!
+//! A renderer is paused once per silence period, and only while swyh-rs plays to it.
+//!
+//! A paused renderer is not resumed when audio is captured again: it no longer reads its
+//! stream, so swyh-rs can't tell, it is resumed with its own app or restarted with its button,
+//! the stale client check skips its clients in the meantime.
+
+use std::{collections::HashSet, thread, time::Duration};
+
+use crate::{
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::renderer_poller::watched_renderers,
+    server::pipe_output::PIPE_CLIENT,
+    utils::ui_logger::ui_log,
+};
+
+/// how often the clients are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// the clients that are in silence for `after` and were not paused yet, the clients that
+/// stream audio again or are gone are forgotten
+fn prolonged_silence(
+    paused: &mut HashSet<String>,
+    silence_runs: &[(String, Duration)],
+    after: Duration,
+) -> Vec<String> {
+    paused.retain(|ip| silence_runs.iter().any(|(c, run)| c == ip && *run >= after));
+    let mut pause = Vec::new();
+    for (ip, run) in silence_runs {
+        if *run >= after && paused.insert(ip.clone()) {
+            pause.push(ip.clone());
+        }
+    }
+    pause
+}
+
+/// start the thread that pauses the renderers after a prolonged silence, if enabled
+pub fn start_silence_pause() {
+    let Some(secs) = CONFIG.read().silence_pause_secs.filter(|s| *s > 0) else {
+        return;
+    };
+    let after = Duration::from_secs(u64::from(secs));
+    ui_log(&format!(
+        "Pausing the renderers after {secs} seconds of injected silence"
+    ));
+    let _ = thread::Builder::new()
+        .name("silence_pause".into())
+        .spawn(move || {
+            let mut paused = HashSet::new();
+            loop {
+                thread::sleep(CHECK_INTERVAL);
+                let silence_runs: Vec<(String, Duration)> = CLIENTS
+                    .read()
+                    .iter()
+                    // the pipe output is not a renderer
+                    .filter(|(remote_addr, _)| *remote_addr != PIPE_CLIENT)
+                    .map(|(_, chs)| (chs.remote_ip.clone(), chs.stats.silence_run()))
+                    .collect();
+                for ip in prolonged_silence(&mut paused, &silence_runs, after) {
+                    let Some(renderer) = watched_renderers()
+                        .into_iter()
+                        .find(|r| r.remote_addr == ip)
+                    else {
+                        continue;
+                    };
+                    ui_log(&format!(
+                        "*W*W*> No audio captured for {secs} seconds, pausing {}",
+                        renderer.dev_name
+                    ));
+                    renderer.pause(&ui_log);
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prolonged_silence() {
+        let after = Duration::from_secs(30);
+        let mut paused = HashSet::new();
+        let runs = |a: u64, b: u64| {
+            vec![
+                ("10.0.0.1".to_string(), Duration::from_secs(a)),
+                ("10.0.0.2".to_string(), Duration::from_secs(b)),
+            ]
+        };
+        assert!(prolonged_silence(&mut paused, &runs(10, 0), after).is_empty());
+        assert_eq!(
+            prolonged_silence(&mut paused, &runs(30, 0), after),
+            ["10.0.0.1"]
+        );
+        // paused once per silence period
+        assert!(prolonged_silence(&mut paused, &runs(45, 0), after).is_empty());
+        // a new silence period after audio pauses again
+        assert!(prolonged_silence(&mut paused, &runs(0, 0), after).is_empty());
+        assert!(paused.is_empty());
+        assert_eq!(
+            prolonged_silence(&mut paused, &runs(31, 40), after),
+            ["10.0.0.1", "10.0.0.2"]
+        );
+    }
+}
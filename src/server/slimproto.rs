@@ -0,0 +1,326 @@
+//! A minimal slimproto server, so that squeezelite (and other Squeezebox) players can connect
+//! to swyh-rs as if it were a Logitech Media Server, and are told to play the HTTP stream.
+//!
+//! Players find the server with the UDP discovery on port 3483, or with `squeezelite -s {ip}`.
+
+use parking_lot::Mutex;
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    enums::streaming::StreamingFormat, globals::statics::CONFIG, openhome::rendercontrol::WavData,
+    utils::ui_logger::ui_log,
+};
+
+/// the slimproto TCP and discovery UDP port
+const SLIMPROTO_PORT: u16 = 3483;
+/// the players drop the connection after 35 seconds without a message from the server
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// the KB a player buffers before it starts playing
+const START_THRESHOLD_KB: u8 = 1;
+/// the maximum length of a player message, HELO and STAT are well below it
+const MAX_MESSAGE_LEN: usize = 4096;
+
+/// the streaming format for a player, squeezelite can't parse RF64 headers
+fn player_format(format: StreamingFormat) -> StreamingFormat {
+    match format {
+        StreamingFormat::Rf64 => StreamingFormat::Wav,
+        _ => format,
+    }
+}
+
+/// the slimproto sample rate code, '?' if the player has to get it from the stream
+fn sample_rate_code(sample_rate: u32) -> u8 {
+    match sample_rate {
+        11025 => b'0',
+        22050 => b'1',
+        32000 => b'2',
+        44100 => b'3',
+        48000 => b'4',
+        8000 => b'5',
+        12000 => b'6',
+        16000 => b'7',
+        24000 => b'8',
+        96000 => b'9',
+        88200 => b':',
+        176_400 => b';',
+        192_000 => b'<',
+        352_800 => b'=',
+        384_000 => b'>',
+        _ => b'?',
+    }
+}
+
+/// a server to player message: length, opcode and data
+fn server_msg(opcode: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let len = u16::try_from(opcode.len() + data.len()).unwrap_or(u16::MAX);
+    let mut msg = Vec::with_capacity(2 + usize::from(len));
+    msg.extend_from_slice(&len.to_be_bytes());
+    msg.extend_from_slice(opcode);
+    msg.extend_from_slice(data);
+    msg
+}
+
+/// the strm message, `command` 's' starts the stream with the HTTP request, 't' is a heartbeat
+fn strm_msg(
+    command: u8,
+    format: StreamingFormat,
+    sample_rate: u32,
+    bits_per_sample: u16,
+    server: &SocketAddr,
+    http_request: &str,
+) -> Vec<u8> {
+    // raw LPCM has no header, for the other formats the player reads the header
+    let (codec, size, rate, channels, endianness) = match format {
+        StreamingFormat::Flac => (b'f', b'?', b'?', b'?', b'?'),
+        StreamingFormat::Lpcm => (
+            b'p',
+            if bits_per_sample == 24 { b'2' } else { b'1' },
+            sample_rate_code(sample_rate),
+            b'2',
+            b'0',
+        ),
+        StreamingFormat::Wav | StreamingFormat::Rf64 => (b'p', b'?', b'?', b'?', b'?'),
+    };
+    let server_ip = match server.ip() {
+        IpAddr::V4(ip) => u32::from(ip),
+        IpAddr::V6(_) => 0,
+    };
+    let mut data = vec![
+        command,
+        b'1', // autostart when the threshold is reached
+        codec,
+        size,
+        rate,
+        channels,
+        endianness,
+        START_THRESHOLD_KB,
+        0,    // no spdif
+        0,    // transition period
+        b'0', // no transition
+        0,    // flags
+        0,    // output threshold
+        0,    // slaves
+    ];
+    data.extend_from_slice(&0u32.to_be_bytes()); // replay gain
+    data.extend_from_slice(&server.port().to_be_bytes());
+    data.extend_from_slice(&server_ip.to_be_bytes());
+    data.extend_from_slice(http_request.as_bytes());
+    server_msg(b"strm", &data)
+}
+
+/// Start the slimproto server for Squeezebox players if enabled in the config.
+pub fn start_slimproto_server(local_addr: &IpAddr, server_port: u16, wd: &WavData) {
+    if !CONFIG.read().slimproto {
+        return;
+    }
+    let listener = match TcpListener::bind(SocketAddr::new(*local_addr, SLIMPROTO_PORT)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            ui_log(&format!(
+                "*E*E*> Could not start the slimproto server on port {SLIMPROTO_PORT} (is LMS running?): {e}"
+            ));
+            return;
+        }
+    };
+    ui_log(&format!(
+        "Slimproto server for Squeezebox players listening on {local_addr}:{SLIMPROTO_PORT}"
+    ));
+    run_discovery_responder();
+    let http_server = SocketAddr::new(*local_addr, server_port);
+    let sample_rate = wd.sample_rate.0;
+    let _ = thread::Builder::new()
+        .name("slimproto_server".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = thread::Builder::new()
+                    .name("slimproto_player".into())
+                    .stack_size(4 * 1024 * 1024)
+                    .spawn(move || {
+                        let peer = stream
+                            .peer_addr()
+                            .map_or("?".to_string(), |a| a.ip().to_string());
+                        if let Err(e) = serve_player(stream, &http_server, sample_rate, &peer) {
+                            ui_log(&format!("Squeezebox player {peer} disconnected: {e}"));
+                        }
+                    });
+            }
+        });
+}
+
+/// answer the broadcast discovery of the players
+fn run_discovery_responder() {
+    let socket = match UdpSocket::bind(SocketAddr::new([0, 0, 0, 0].into(), SLIMPROTO_PORT)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            ui_log(&format!(
+                "*W*W*> No slimproto discovery, players need the server ip address: {e}"
+            ));
+            return;
+        }
+    };
+    let _ = thread::Builder::new()
+        .name("slimproto_discovery".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let mut buf = [0u8; 512];
+            while let Ok((n, src)) = socket.recv_from(&mut buf) {
+                if n > 0 && buf[0] == b'e' {
+                    let _ = socket.send_to(b"E", src);
+                }
+            }
+        });
+}
+
+/// read a player message: opcode, length and data, a message that is too long drops the connection
+fn read_message(stream: &mut impl Read) -> io::Result<([u8; 4], Vec<u8>)> {
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header)?;
+    let opcode = [header[0], header[1], header[2], header[3]];
+    let len = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {len} bytes is too long"),
+        ));
+    }
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data)?;
+    Ok((opcode, data))
+}
+
+/// start the stream on a player and keep the connection alive until it says goodbye
+fn serve_player(
+    stream: TcpStream,
+    http_server: &SocketAddr,
+    sample_rate: u32,
+    peer: &str,
+) -> io::Result<()> {
+    let result = player_messages(&stream, http_server, sample_rate, peer);
+    // this also ends the heartbeat
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
+
+/// handle the messages of a player until it says goodbye or the connection fails
+fn player_messages(
+    stream: &TcpStream,
+    http_server: &SocketAddr,
+    sample_rate: u32,
+    peer: &str,
+) -> io::Result<()> {
+    let (format, bps) = {
+        let conf = CONFIG.read();
+        (
+            player_format(conf.streaming_format.unwrap_or(StreamingFormat::Flac)),
+            conf.bits_per_sample.unwrap_or(16),
+        )
+    };
+    let path = match format {
+        StreamingFormat::Flac => "/stream/swyh.flac",
+        StreamingFormat::Lpcm => "/stream/swyh.raw",
+        StreamingFormat::Wav | StreamingFormat::Rf64 => "/stream/swyh.wav",
+    };
+    let mut reader = stream.try_clone()?;
+    // the heartbeat thread writes too, so the writes are serialized
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    let mut heartbeat_started = false;
+    loop {
+        let (opcode, data) = read_message(&mut reader)?;
+        match &opcode {
+            b"HELO" => {
+                let mac = data
+                    .get(2..8)
+                    .map(|m| {
+                        m.iter()
+                            .map(|b| format!("{b:02x}"))
+                            .collect::<Vec<String>>()
+                            .join(":")
+                    })
+                    .unwrap_or_default();
+                ui_log(&format!(
+                    "Squeezebox player {mac} at {peer} connected, streaming {format} {bps} bit"
+                ));
+                let request = format!("GET {path} HTTP/1.0\r\n\r\n");
+                {
+                    let mut w = writer.lock();
+                    w.write_all(&server_msg(b"aude", &[1, 1]))?;
+                    w.write_all(&strm_msg(
+                        b's',
+                        format,
+                        sample_rate,
+                        bps,
+                        http_server,
+                        &request,
+                    ))?;
+                }
+                // one heartbeat per connection keeps it alive until it's shut down
+                if !heartbeat_started {
+                    heartbeat_started = true;
+                    let heartbeat = strm_msg(b't', format, sample_rate, bps, http_server, "");
+                    let writer = writer.clone();
+                    let _ = thread::Builder::new()
+                        .name("slimproto_heartbeat".into())
+                        .stack_size(4 * 1024 * 1024)
+                        .spawn(move || loop {
+                            thread::sleep(HEARTBEAT_INTERVAL);
+                            if writer.lock().write_all(&heartbeat).is_err() {
+                                break;
+                            }
+                        });
+                }
+            }
+            b"BYE!" => {
+                ui_log(&format!("Squeezebox player at {peer} said goodbye"));
+                return Ok(());
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strm_msg() {
+        let server: SocketAddr = ([192, 168, 1, 2], 5901).into();
+        let request = "GET /stream/swyh.raw HTTP/1.0\r\n\r\n";
+        let msg = strm_msg(b's', StreamingFormat::Lpcm, 48000, 24, &server, request);
+        assert_eq!(
+            usize::from(u16::from_be_bytes([msg[0], msg[1]])),
+            msg.len() - 2
+        );
+        assert_eq!(&msg[2..6], b"strm");
+        assert_eq!(&msg[6..13], b"s1p2420");
+        assert_eq!(&msg[24..26], &5901u16.to_be_bytes());
+        assert_eq!(&msg[26..30], &[192, 168, 1, 2]);
+        assert_eq!(&msg[30..], request.as_bytes());
+        let msg = strm_msg(b's', StreamingFormat::Flac, 44100, 16, &server, "");
+        assert_eq!(&msg[6..13], b"s1f????");
+        assert_eq!(msg.len(), 30);
+        assert_eq!(player_format(StreamingFormat::Rf64), StreamingFormat::Wav);
+    }
+
+    #[test]
+    fn test_read_message() {
+        let mut msg = b"STAT".to_vec();
+        msg.extend_from_slice(&3u32.to_be_bytes());
+        msg.extend_from_slice(b"abc");
+        let (opcode, data) = read_message(&mut msg.as_slice()).unwrap();
+        assert_eq!(&opcode, b"STAT");
+        assert_eq!(data, b"abc");
+        // a huge length is refused before allocating
+        let mut msg = b"HELO".to_vec();
+        msg.extend_from_slice(&u32::MAX.to_be_bytes());
+        let e = read_message(&mut msg.as_slice()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,558 @@
+//! Announce the swyh-rs streaming server itself with periodic SSDP NOTIFY messages,
+//! and answer the SSDP M-SEARCH requests for it, so that UPnP control points and renderers
+//! can discover it and "pull" the stream.
+//!
+//! The device description is served on /upnp/device.xml, with the icons on
+//! /upnp/icon48.png and /upnp/icon128.png, its only service is a minimal ConnectionManager
+//! (/upnp/cm.xml) that lists the stream formats, there is no ContentDirectory to browse.
+
+use parking_lot::RwLock;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::LazyLock,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    globals::statics::{APP_VERSION, CONFIG},
+    utils::ui_logger::ui_log,
+};
+
+const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+const CM_SERVICE_TYPE: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+/// the ConnectionManager service description and control urls
+const CM_SCPD_URL: &str = "/upnp/cm.xml";
+pub const CM_CONTROL_URL: &str = "/upnp/cm/control";
+/// the formats of the streams, for GetProtocolInfo
+const SOURCE_PROTOCOLS: &str = "http-get:*:audio/flac:*,http-get:*:audio/wav:*,\
+http-get:*:audio/L16:*,http-get:*:audio/L24:*";
+const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+/// the announcements expire after 30 minutes
+const MAX_AGE_SECS: u64 = 1800;
+/// so they are repeated well before that
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// the device icons, (url, size, png)
+const ICONS: [(&str, u32, &[u8]); 2] = [
+    (
+        "/upnp/icon48.png",
+        48,
+        include_bytes!("../../assets/n48.png"),
+    ),
+    (
+        "/upnp/icon128.png",
+        128,
+        include_bytes!("../../assets/n128.png"),
+    ),
+];
+
+/// a new random (version 4) UUID as UDN
+#[must_use]
+pub fn random_udn() -> String {
+    let mut bytes = [0u8; 16];
+    bytes.iter_mut().for_each(|b| *b = fastrand::u8(..));
+    // random (version 4) UUID
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    format!(
+        "uuid:{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// the UDN for a read-only config without one, for the lifetime of the process
+static FALLBACK_UDN: LazyLock<String> = LazyLock::new(random_udn);
+
+/// the UDN of this installation, used for all UPnP interactions
+#[must_use]
+pub fn upnp_udn() -> String {
+    CONFIG
+        .read()
+        .udn
+        .clone()
+        .unwrap_or_else(|| FALLBACK_UDN.clone())
+}
+
+/// the User-Agent of the UPnP requests, with the UDN of this installation,
+/// unless another User-Agent is configured
+#[must_use]
+pub fn upnp_user_agent() -> String {
+    CONFIG
+        .read()
+        .identity
+        .user_agent
+        .clone()
+        .unwrap_or_else(|| user_agent(&upnp_udn()))
+}
+
+/// the User-Agent of the other HTTP requests
+#[must_use]
+pub fn http_user_agent() -> String {
+    CONFIG
+        .read()
+        .identity
+        .user_agent
+        .clone()
+        .unwrap_or_else(|| format!("swyh-rs/{APP_VERSION}"))
+}
+
+/// the Server header of the streaming server
+#[must_use]
+pub fn server_header() -> String {
+    CONFIG
+        .read()
+        .identity
+        .server
+        .clone()
+        .unwrap_or_else(|| "swyh-rs tiny-http".to_string())
+}
+
+/// the icy-name header of the streams, None if it is configured empty
+#[must_use]
+pub fn icy_name() -> Option<String> {
+    let name = CONFIG
+        .read()
+        .identity
+        .icy_name
+        .clone()
+        .unwrap_or_else(|| "swyh-rs".to_string());
+    (!name.is_empty()).then_some(name)
+}
+
+fn user_agent(udn: &str) -> String {
+    format!(
+        "{} UPnP/1.1 swyh-rs/{APP_VERSION} ({udn})",
+        std::env::consts::OS
+    )
+}
+
+/// the device description, if the announcer is running
+static DESCRIPTION: LazyLock<RwLock<Option<String>>> = LazyLock::new(|| RwLock::new(None));
+
+/// the ConnectionManager service description
+const CM_SCPD: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0">
+  <specVersion>
+    <major>1</major>
+    <minor>0</minor>
+  </specVersion>
+  <actionList>
+    <action>
+      <name>GetProtocolInfo</name>
+      <argumentList>
+        <argument><name>Source</name><direction>out</direction><relatedStateVariable>SourceProtocolInfo</relatedStateVariable></argument>
+        <argument><name>Sink</name><direction>out</direction><relatedStateVariable>SinkProtocolInfo</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+    <action>
+      <name>GetCurrentConnectionIDs</name>
+      <argumentList>
+        <argument><name>ConnectionIDs</name><direction>out</direction><relatedStateVariable>CurrentConnectionIDs</relatedStateVariable></argument>
+      </argumentList>
+    </action>
+  </actionList>
+  <serviceStateTable>
+    <stateVariable sendEvents="yes"><name>SourceProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="yes"><name>SinkProtocolInfo</name><dataType>string</dataType></stateVariable>
+    <stateVariable sendEvents="yes"><name>CurrentConnectionIDs</name><dataType>string</dataType></stateVariable>
+  </serviceStateTable>
+</scpd>
+"#;
+
+/// true if the announcer is running
+#[must_use]
+pub fn announcing() -> bool {
+    DESCRIPTION.read().is_some()
+}
+
+/// the UPnP device or service description for a request url, if the announcer is running
+#[must_use]
+pub fn upnp_document(url: &str) -> Option<String> {
+    let description = DESCRIPTION.read().clone()?;
+    if url.eq_ignore_ascii_case("/upnp/device.xml") {
+        Some(description)
+    } else if url.eq_ignore_ascii_case(CM_SCPD_URL) {
+        Some(CM_SCPD.to_string())
+    } else {
+        None
+    }
+}
+
+/// the SOAP response of the ConnectionManager to the action in a SOAPACTION header,
+/// None for an unknown action
+#[must_use]
+pub fn connection_manager_response(soap_action: &str) -> Option<String> {
+    let (_, action) = soap_action.trim().trim_matches('"').rsplit_once('#')?;
+    let args = match action {
+        "GetProtocolInfo" => format!("<Source>{SOURCE_PROTOCOLS}</Source><Sink></Sink>"),
+        "GetCurrentConnectionIDs" => "<ConnectionIDs>0</ConnectionIDs>".to_string(),
+        _ => return None,
+    };
+    Some(format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+<s:Body><u:{action}Response xmlns:u=\"{CM_SERVICE_TYPE}\">{args}</u:{action}Response>\
+</s:Body></s:Envelope>"
+    ))
+}
+
+/// the device icon for a request url, if any
+#[must_use]
+pub fn device_icon(url: &str) -> Option<&'static [u8]> {
+    ICONS
+        .iter()
+        .find(|(icon_url, _, _)| url.eq_ignore_ascii_case(icon_url))
+        .map(|(_, _, png)| *png)
+}
+
+/// the name of this computer, if it can be found
+#[cfg(windows)]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+/// the name of this computer, if it can be found
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if rc != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    let name = String::from_utf8_lossy(&buf[..len]).to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+/// the friendly name that control points show, e.g. "swyh-rs on desktop"
+fn friendly_name(hostname: Option<&str>, config_id: &str) -> String {
+    let name = match hostname {
+        Some(host) => format!("swyh-rs on {host}"),
+        None => "swyh-rs".to_string(),
+    };
+    if config_id.is_empty() {
+        name
+    } else {
+        format!("{name} ({})", config_id.trim_start_matches('_'))
+    }
+}
+
+/// the UPnP device description XML, pointing to the stream urls
+fn description_xml(base_url: &str, udn: &str, friendly_name: &str) -> String {
+    let friendly_name = htmlescape::encode_minimal(friendly_name);
+    let serial = udn.trim_start_matches("uuid:");
+    let os = std::env::consts::OS;
+    let icons = ICONS
+        .iter()
+        .map(|(url, size, _)| {
+            format!(
+                r#"      <icon>
+        <mimetype>image/png</mimetype>
+        <width>{size}</width>
+        <height>{size}</height>
+        <depth>24</depth>
+        <url>{url}</url>
+      </icon>
+"#
+            )
+        })
+        .collect::<String>();
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <specVersion>
+    <major>1</major>
+    <minor>0</minor>
+  </specVersion>
+  <device>
+    <deviceType>{DEVICE_TYPE}</deviceType>
+    <friendlyName>{friendly_name}</friendlyName>
+    <manufacturer>dheijl</manufacturer>
+    <manufacturerURL>https://github.com/dheijl/swyh-rs</manufacturerURL>
+    <modelDescription>Stream What You Hear audio streaming server ({os}), stream {base_url}/stream/swyh.flac</modelDescription>
+    <modelName>swyh-rs</modelName>
+    <modelNumber>{APP_VERSION}</modelNumber>
+    <modelURL>https://github.com/dheijl/swyh-rs</modelURL>
+    <serialNumber>{serial}</serialNumber>
+    <UDN>{udn}</UDN>
+    <iconList>
+{icons}    </iconList>
+    <serviceList>
+      <service>
+        <serviceType>{CM_SERVICE_TYPE}</serviceType>
+        <serviceId>urn:upnp-org:serviceId:ConnectionManager</serviceId>
+        <SCPDURL>{CM_SCPD_URL}</SCPDURL>
+        <controlURL>{CM_CONTROL_URL}</controlURL>
+        <eventSubURL>/upnp/cm/event</eventSubURL>
+      </service>
+    </serviceList>
+    <presentationURL>{base_url}/listen</presentationURL>
+  </device>
+</root>
+"#
+    )
+}
+
+/// the SERVER header of the SSDP NOTIFY messages
+fn ssdp_server() -> String {
+    CONFIG
+        .read()
+        .identity
+        .server
+        .clone()
+        .unwrap_or_else(|| format!("{} UPnP/1.0 swyh-rs/{APP_VERSION}", std::env::consts::OS))
+}
+
+/// the notification types and search targets of the device
+fn targets(udn: &str) -> [&str; 4] {
+    ["upnp:rootdevice", udn, DEVICE_TYPE, CM_SERVICE_TYPE]
+}
+
+/// the unique service name of a notification type or search target
+fn usn(nt: &str, udn: &str) -> String {
+    if nt == udn {
+        udn.to_string()
+    } else {
+        format!("{udn}::{nt}")
+    }
+}
+
+/// the SSDP NOTIFY ssdp:alive message for a notification type
+fn notify_msg(location: &str, nt: &str, udn: &str, server: &str) -> String {
+    let usn = usn(nt, udn);
+    format!(
+        "NOTIFY * HTTP/1.1\r\n\
+        HOST: 239.255.255.250:1900\r\n\
+        CACHE-CONTROL: max-age={MAX_AGE_SECS}\r\n\
+        LOCATION: {location}\r\n\
+        NT: {nt}\r\n\
+        NTS: ssdp:alive\r\n\
+        SERVER: {server}\r\n\
+        USN: {usn}\r\n\r\n",
+    )
+}
+
+/// the responses to an SSDP M-SEARCH request, one for each matching search target
+fn search_responses(request: &str, location: &str, udn: &str, server: &str) -> Vec<String> {
+    let mut lines = request.lines();
+    if !lines.next().is_some_and(|l| l.starts_with("M-SEARCH ")) {
+        return Vec::new();
+    }
+    let Some(st) = lines
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("ST"))
+        .map(|(_, st)| st.trim())
+    else {
+        return Vec::new();
+    };
+    targets(udn)
+        .into_iter()
+        .filter(|nt| st == "ssdp:all" || st.eq_ignore_ascii_case(nt))
+        .map(|nt| {
+            format!(
+                "HTTP/1.1 200 OK\r\n\
+                CACHE-CONTROL: max-age={MAX_AGE_SECS}\r\n\
+                EXT:\r\n\
+                LOCATION: {location}\r\n\
+                SERVER: {server}\r\n\
+                ST: {nt}\r\n\
+                USN: {}\r\n\r\n",
+                usn(nt, udn)
+            )
+        })
+        .collect()
+}
+
+/// the socket that receives the M-SEARCH requests on the SSDP port, shared with the
+/// other UPnP stacks of this computer
+fn search_socket(local_addr: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 1900)).into())?;
+    socket.join_multicast_v4(&SSDP_ADDR, &local_addr)?;
+    Ok(socket.into())
+}
+
+/// start the thread that answers the M-SEARCH requests for the device
+fn start_search_responder(local_addr: Ipv4Addr, location: String, udn: String, server: String) {
+    let socket = match search_socket(local_addr) {
+        Ok(socket) => socket,
+        Err(e) => {
+            ui_log(&format!(
+                "*W*W*> Could not listen for SSDP M-SEARCH requests, only announcing: {e}"
+            ));
+            return;
+        }
+    };
+    let _ = thread::Builder::new()
+        .name("ssdp_responder".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let mut buf = [0u8; 2048];
+            loop {
+                let (len, src) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(e) => {
+                        ui_log(&format!("*W*W*> SSDP M-SEARCH receive failed: {e}"));
+                        thread::sleep(Duration::from_secs(1));
+                        continue;
+                    }
+                };
+                let request = String::from_utf8_lossy(&buf[..len]);
+                for response in search_responses(&request, &location, &udn, &server) {
+                    let _ = socket.send_to(response.as_bytes(), src);
+                }
+            }
+        });
+}
+
+/// Start announcing the streaming server with SSDP if enabled in the config.
+/// The announcements are sent on the SSDP network, the device description points to the
+/// streaming server on the serve address.
+pub fn start_ssdp_announcer(local_addr: &IpAddr, serve_addr: &IpAddr, server_port: u16) {
+    let config_id = {
+        let conf = CONFIG.read();
+        if !conf.ssdp_announce {
+            return;
+        }
+        conf.config_id.clone().unwrap_or_default()
+    };
+    let udn = upnp_udn();
+    let IpAddr::V4(local_v4) = *local_addr else {
+        ui_log("*W*W*> SSDP announcements are only supported on IPv4");
+        return;
+    };
+    let socket = match UdpSocket::bind(SocketAddr::new(*local_addr, 0)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            ui_log(&format!(
+                "*E*E*> Could not create the SSDP NOTIFY socket: {e}"
+            ));
+            return;
+        }
+    };
+    let _ = socket.set_multicast_ttl_v4(2);
+    let base_url = format!("http://{serve_addr}:{server_port}");
+    let location = format!("{base_url}/upnp/device.xml");
+    let name = friendly_name(hostname().as_deref(), &config_id);
+    let server = ssdp_server();
+    *DESCRIPTION.write() = Some(description_xml(&base_url, &udn, &name));
+    ui_log(&format!(
+        "Announcing \"{name}\" ({udn}) with SSDP, device description {location}"
+    ));
+    start_search_responder(local_v4, location.clone(), udn.clone(), server.clone());
+    let _ = thread::Builder::new()
+        .name("ssdp_announcer".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let ssdp_addr: SocketAddr = (SSDP_ADDR, 1900).into();
+            let udn = udn.as_str();
+            loop {
+                // send everything twice, UDP is unreliable
+                for _ in 0..2 {
+                    for nt in targets(udn) {
+                        let msg = notify_msg(&location, nt, udn, &server);
+                        if let Err(e) = socket.send_to(msg.as_bytes(), ssdp_addr) {
+                            ui_log(&format!("*W*W*> SSDP NOTIFY failed: {e}"));
+                        }
+                    }
+                    thread::sleep(Duration::from_millis(200));
+                }
+                thread::sleep(ANNOUNCE_INTERVAL);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_udn() {
+        let udn = random_udn();
+        assert_eq!(udn.len(), 41);
+        assert!(udn.starts_with("uuid:"));
+        assert_eq!(&udn[19..20], "4");
+        assert_ne!(udn, random_udn());
+        assert!(user_agent(&udn).ends_with(&format!(" UPnP/1.1 swyh-rs/{APP_VERSION} ({udn})")));
+    }
+
+    #[test]
+    fn test_notify_msg() {
+        let udn = "uuid:01234567-89ab-4def-8123-456789abcdef";
+        let server = "Linux UPnP/1.0 swyh-rs";
+        let msg = notify_msg(
+            "http://192.168.1.2:5901/upnp/device.xml",
+            DEVICE_TYPE,
+            udn,
+            server,
+        );
+        assert!(msg.starts_with("NOTIFY * HTTP/1.1\r\n"));
+        assert!(msg.contains("\r\nLOCATION: http://192.168.1.2:5901/upnp/device.xml\r\n"));
+        assert!(msg.contains(&format!("\r\nUSN: {udn}::{DEVICE_TYPE}\r\n")));
+        assert!(msg.contains("\r\nSERVER: Linux UPnP/1.0 swyh-rs\r\n"));
+        assert!(msg.ends_with("\r\n\r\n"));
+        let msg = notify_msg("http://192.168.1.2:5901/upnp/device.xml", udn, udn, server);
+        assert!(msg.contains(&format!("\r\nUSN: {udn}\r\n")));
+        let xml = description_xml("http://192.168.1.2:5901", udn, "swyh-rs on a&b");
+        assert!(xml.contains(&format!("<UDN>{udn}</UDN>")));
+        assert!(xml.contains("<friendlyName>swyh-rs on a&amp;b</friendlyName>"));
+        assert!(xml.contains("<url>/upnp/icon48.png</url>"));
+        assert_eq!(
+            friendly_name(Some("desktop"), "_kitchen"),
+            "swyh-rs on desktop (kitchen)"
+        );
+        assert_eq!(friendly_name(None, ""), "swyh-rs");
+        assert!(device_icon("/upnp/icon128.png").is_some());
+        assert!(device_icon("/upnp/icon64.png").is_none());
+        assert!(xml.contains("<presentationURL>http://192.168.1.2:5901/listen</presentationURL>"));
+        assert!(xml.contains(&format!("<controlURL>{CM_CONTROL_URL}</controlURL>")));
+    }
+
+    #[test]
+    fn test_search_responses() {
+        let udn = "uuid:01234567-89ab-4def-8123-456789abcdef";
+        let location = "http://192.168.1.2:5901/upnp/device.xml";
+        let search = |st: &str| {
+            format!(
+                "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\
+                MAN: \"ssdp:discover\"\r\nMX: 1\r\nST: {st}\r\n\r\n"
+            )
+        };
+        let responses = search_responses(&search(DEVICE_TYPE), location, udn, "swyh-rs");
+        assert_eq!(responses.len(), 1);
+        assert!(responses[0].starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(responses[0].contains(&format!("\r\nLOCATION: {location}\r\n")));
+        assert!(responses[0].contains(&format!("\r\nUSN: {udn}::{DEVICE_TYPE}\r\n")));
+        assert_eq!(
+            search_responses(&search("ssdp:all"), location, udn, "swyh-rs").len(),
+            4
+        );
+        let renderers = search("urn:schemas-upnp-org:device:MediaRenderer:1");
+        assert!(search_responses(&renderers, location, udn, "swyh-rs").is_empty());
+        let notify = notify_msg(location, DEVICE_TYPE, udn, "swyh-rs");
+        assert!(search_responses(&notify, location, udn, "swyh-rs").is_empty());
+    }
+
+    #[test]
+    fn test_connection_manager() {
+        let response = connection_manager_response(
+            "\"urn:schemas-upnp-org:service:ConnectionManager:1#GetProtocolInfo\"",
+        )
+        .unwrap();
+        assert!(response.contains("<u:GetProtocolInfoResponse"));
+        assert!(response.contains("<Source>http-get:*:audio/flac:*,"));
+        assert!(connection_manager_response("urn:x#Browse").is_none());
+    }
+}
@@ -0,0 +1,533 @@
+//! Stream integrity self-check: swyh-rs connects to its own streaming url as a client,
+//! checks the headers and follows the audio for a number of seconds.
+//!
+//! For WAV and RF64 the RIFF header is checked, for LPCM the Content-Type. A FLAC stream
+//! is followed frame by frame: every frame header must have a correct CRC-8, every frame
+//! a correct CRC-16, and the frame numbers must be consecutive. The samples are not decoded.
+//! For all formats the amount of audio received must keep up with the time, without stalls. If the check
+//! passes and a renderer still has problems, the problem is in the renderer or in the
+//! network between the renderer and swyh-rs.
+
+use std::{
+    io::Read,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    enums::streaming::StreamingFormat,
+    openhome::diagnosis::DiagnosisStep,
+    server::{query_params::StreamingParams, ssdp_announcer::http_user_agent},
+    utils::network_source::{read_wav_header, WavFormat},
+};
+
+/// the default duration of the check
+pub const CHECK_SECS: u64 = 10;
+/// a pause between two reads longer than this is a stall
+const STALL: Duration = Duration::from_secs(1);
+
+/// the values of a FLAC frame header that are checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FrameHeader {
+    len: usize,
+    variable_blocksize: bool,
+    /// the frame number, or the first sample number with a variable block size
+    number: u64,
+    blocksize: u32,
+}
+
+/// CRC-8 (polynomial 0x07) of a FLAC frame header
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |crc, b| {
+        (0..8).fold(crc ^ b, |c, _| {
+            if c & 0x80 != 0 {
+                (c << 1) ^ 0x07
+            } else {
+                c << 1
+            }
+        })
+    })
+}
+
+/// CRC-16 (polynomial 0x8005) of a FLAC frame, 0 for a frame including its CRC
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, b| {
+        (0..8).fold(crc ^ (u16::from(*b) << 8), |c, _| {
+            if c & 0x8000 != 0 {
+                (c << 1) ^ 0x8005
+            } else {
+                c << 1
+            }
+        })
+    })
+}
+
+/// the UTF-8 like coded frame or sample number and its length
+fn coded_number(data: &[u8]) -> Option<(u64, usize)> {
+    let first = *data.first()?;
+    let len = match first.leading_ones() {
+        0 => return Some((u64::from(first), 1)),
+        n @ 2..=7 => n as usize,
+        _ => return None,
+    };
+    let mut number = u64::from(first & (0x7f >> len));
+    for b in data.get(1..len)? {
+        if b & 0xc0 != 0x80 {
+            return None;
+        }
+        number = (number << 6) | u64::from(b & 0x3f);
+    }
+    Some((number, len))
+}
+
+/// parse a FLAC frame header, None if there's no valid header at the start of `data`
+fn frame_header(data: &[u8]) -> Option<FrameHeader> {
+    if data.len() < 6 || data[0] != 0xff || data[1] & 0xfe != 0xf8 || data[3] & 0x01 != 0 {
+        return None;
+    }
+    let (number, number_len) = coded_number(&data[4..])?;
+    let mut len = 4 + number_len;
+    let blocksize = match data[2] >> 4 {
+        0 => return None,
+        1 => 192,
+        n @ 2..=5 => 576 << (n - 2),
+        6 => {
+            len += 1;
+            u32::from(*data.get(len - 1)?) + 1
+        }
+        7 => {
+            len += 2;
+            u32::from(u16::from_be_bytes([
+                *data.get(len - 2)?,
+                *data.get(len - 1)?,
+            ])) + 1
+        }
+        n => 256 << (n - 8),
+    };
+    len += match data[2] & 0x0f {
+        12 => 1,
+        13 | 14 => 2,
+        15 => return None,
+        _ => 0,
+    };
+    if crc8(data.get(..len)?) != *data.get(len)? {
+        return None;
+    }
+    Some(FrameHeader {
+        len: len + 1,
+        variable_blocksize: data[1] & 0x01 != 0,
+        number,
+        blocksize,
+    })
+}
+
+/// follows a FLAC stream frame by frame
+#[derive(Default)]
+struct FlacChecker {
+    buf: Vec<u8>,
+    format: Option<WavFormat>,
+    /// the header of the frame at the start of `buf`
+    current: Option<FrameHeader>,
+    /// where to continue the search for the next frame
+    scan: usize,
+    frames: u64,
+    samples: u64,
+    error: Option<String>,
+}
+
+impl FlacChecker {
+    /// add the received bytes, false after an error
+    fn add(&mut self, data: &[u8]) -> bool {
+        self.buf.extend_from_slice(data);
+        if self.format.is_none() && !self.metadata() {
+            return self.error.is_none();
+        }
+        while self.error.is_none() && self.next_frame() {}
+        self.error.is_none()
+    }
+
+    /// parse the metadata blocks, false if they're not complete yet
+    fn metadata(&mut self) -> bool {
+        if self.buf.len() < 4 {
+            return false;
+        }
+        if &self.buf[0..4] != b"fLaC" {
+            self.error = Some("the stream does not start with fLaC".to_string());
+            return false;
+        }
+        let mut pos = 4;
+        let mut format = None;
+        loop {
+            let Some(block) = self.buf.get(pos..pos + 4) else {
+                return false;
+            };
+            let last = block[0] & 0x80 != 0;
+            let len = u32::from_be_bytes([0, block[1], block[2], block[3]]) as usize;
+            if self.buf.len() < pos + 4 + len {
+                return false;
+            }
+            if block[0] & 0x7f == 0 && len >= 18 {
+                // STREAMINFO: 20 bits sample rate, 3 bits channels - 1, 5 bits bps - 1
+                let info = &self.buf[pos + 14..pos + 18];
+                let bits = u32::from_be_bytes([info[0], info[1], info[2], info[3]]);
+                format = Some(WavFormat {
+                    sample_rate: bits >> 12,
+                    channels: ((bits >> 9) & 0x07) as u16 + 1,
+                    bits_per_sample: ((bits >> 4) & 0x1f) as u16 + 1,
+                });
+            }
+            pos += 4 + len;
+            if last {
+                break;
+            }
+        }
+        if format.is_none() {
+            self.error = Some("there is no STREAMINFO metadata block".to_string());
+            return false;
+        }
+        self.format = format;
+        self.buf.drain(..pos);
+        true
+    }
+
+    /// find the end of the frame at the start of the buffer, false if it isn't received yet
+    fn next_frame(&mut self) -> bool {
+        let current = match self.current {
+            Some(header) => header,
+            None => {
+                if self.buf.len() < 16 {
+                    return false;
+                }
+                let Some(header) = frame_header(&self.buf) else {
+                    self.error = Some(format!("no valid frame header after frame {}", self.frames));
+                    return false;
+                };
+                self.current = Some(header);
+                self.scan = header.len;
+                header
+            }
+        };
+        let expected = if current.variable_blocksize {
+            current.number + u64::from(current.blocksize)
+        } else {
+            current.number + 1
+        };
+        // the unit of the numbers, and how far a number after missing frames can be
+        let (unit, gap) = if current.variable_blocksize {
+            ("sample", 8 * u64::from(current.blocksize))
+        } else {
+            ("frame", 8)
+        };
+        // the frame ends where the next frame header starts and the CRC-16 is correct,
+        // a header with the expected number but a wrong CRC-16 means a corrupted frame
+        while self.scan + 16 <= self.buf.len() {
+            let pos = self.scan;
+            self.scan += 1;
+            let Some(next) = frame_header(&self.buf[pos..]) else {
+                continue;
+            };
+            let crc_ok = crc16(&self.buf[..pos]) == 0;
+            if !crc_ok && next.number == expected {
+                self.error = Some(format!("{unit} {} has a wrong CRC-16", current.number));
+                return false;
+            }
+            if !crc_ok && !(expected..expected + gap).contains(&next.number) {
+                // a false frame sync in the audio data
+                continue;
+            }
+            if next.number != expected {
+                self.error = Some(format!(
+                    "{unit} {} follows {}, expected {expected}",
+                    next.number, current.number
+                ));
+                return false;
+            }
+            self.frames += 1;
+            self.samples += u64::from(current.blocksize);
+            self.buf.drain(..pos);
+            self.current = Some(next);
+            self.scan = next.len;
+            return true;
+        }
+        // a frame can't be longer than this, so the CRC-16 of the frame must be wrong
+        if self.buf.len() > 1 << 20 {
+            self.error = Some(format!("frame {} has no correct CRC-16", current.number));
+        }
+        false
+    }
+}
+
+/// the LPCM format in the Content-Type, e.g. audio/L16;rate=44100;channels=2
+fn lpcm_format(content_type: &str) -> Option<WavFormat> {
+    let mut parts = content_type.split(';').map(str::trim);
+    let bits_per_sample = match parts.next()?.to_lowercase().as_str() {
+        "audio/l16" => 16,
+        "audio/l24" => 24,
+        _ => return None,
+    };
+    let mut format = WavFormat {
+        sample_rate: 0,
+        channels: 1,
+        bits_per_sample,
+    };
+    for (key, value) in parts.filter_map(|p| p.split_once('=')) {
+        match key.to_lowercase().as_str() {
+            "rate" => format.sample_rate = value.parse().ok()?,
+            "channels" => format.channels = value.parse().ok()?,
+            _ => (),
+        }
+    }
+    (format.sample_rate > 0).then_some(format)
+}
+
+/// check the format in the header of the stream
+fn check_format(header: &str, format: WavFormat, sample_rate: u32, bits: u16) -> DiagnosisStep {
+    let detail = format!(
+        "{} Hz, {} channels, {} bits",
+        format.sample_rate, format.channels, format.bits_per_sample
+    );
+    if format.sample_rate == sample_rate && format.bits_per_sample == bits {
+        DiagnosisStep::new("Header", true, format!("{header}: {detail}"))
+    } else {
+        DiagnosisStep::new(
+            "Header",
+            false,
+            format!("{header}: {detail}, expected {sample_rate} Hz and {bits} bits"),
+        )
+    }
+}
+
+/// check that the audio received kept up with the time
+fn check_continuity(audio: Duration, elapsed: Duration, stalls: &[Duration]) -> DiagnosisStep {
+    let detail = format!(
+        "received {:.1} seconds of audio in {:.1} seconds",
+        audio.as_secs_f64(),
+        elapsed.as_secs_f64()
+    );
+    if let Some(longest) = stalls.iter().max() {
+        DiagnosisStep::new(
+            "Continuity",
+            false,
+            format!(
+                "{detail}, the stream stalled {} times for up to {} ms, is the audio \
+                source silent without inject silence?",
+                stalls.len(),
+                longest.as_millis()
+            ),
+        )
+    } else if audio.as_secs_f64() < elapsed.as_secs_f64() * 0.95 - 0.5 {
+        DiagnosisStep::new(
+            "Continuity",
+            false,
+            format!("{detail}, the stream does not keep up"),
+        )
+    } else {
+        DiagnosisStep::new("Continuity", true, detail)
+    }
+}
+
+/// connect to the streaming url and check the stream for `secs` seconds,
+/// with the sample rate and the bit depth that the stream should have
+pub fn verify_stream(url: &str, sample_rate: u32, bits: u16, secs: u64) -> Vec<DiagnosisStep> {
+    let mut steps = Vec::new();
+    let format = StreamingParams::from_query_string(url)
+        .fmt
+        .unwrap_or(StreamingFormat::Flac);
+    let resp = match attohttpc::get(url)
+        .header("User-Agent", http_user_agent())
+        .connect_timeout(Duration::from_secs(3))
+        .read_timeout(Duration::from_secs(5))
+        .send()
+    {
+        Ok(resp) if resp.is_success() => resp,
+        Ok(resp) => {
+            steps.push(DiagnosisStep::new(
+                "HTTP response",
+                false,
+                format!("HTTP {} for {url}", resp.status()),
+            ));
+            return steps;
+        }
+        Err(e) => {
+            steps.push(DiagnosisStep::new(
+                "HTTP response",
+                false,
+                format!("the streaming server did not respond on {url}: {e}"),
+            ));
+            return steps;
+        }
+    };
+    let content_type = resp
+        .headers()
+        .get("Content-Type")
+        .and_then(|ct| ct.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    steps.push(DiagnosisStep::new(
+        "HTTP response",
+        true,
+        format!("HTTP {}, {content_type}", resp.status()),
+    ));
+    let (_, _, mut reader) = resp.split();
+    let pcm = match format {
+        StreamingFormat::Lpcm => match lpcm_format(&content_type) {
+            Some(f) => Some(f),
+            None => {
+                steps.push(DiagnosisStep::new(
+                    "Header",
+                    false,
+                    format!("the Content-Type {content_type} has no LPCM rate"),
+                ));
+                return steps;
+            }
+        },
+        StreamingFormat::Wav | StreamingFormat::Rf64 => match read_wav_header(&mut reader) {
+            Ok(f) => Some(f),
+            Err(e) => {
+                steps.push(DiagnosisStep::new("Header", false, e.to_string()));
+                return steps;
+            }
+        },
+        StreamingFormat::Flac => None,
+    };
+    if let Some(f) = pcm {
+        let header = if format == StreamingFormat::Lpcm {
+            "Content-Type"
+        } else {
+            "WAV header"
+        };
+        steps.push(check_format(header, f, sample_rate, bits));
+    }
+    let mut flac = FlacChecker::default();
+    let mut audio_bytes = 0u64;
+    let mut stalls = Vec::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let start = Instant::now();
+    // the upfront buffering delays the first read, it's not a stall
+    let mut first_read = None;
+    let mut last_read = start;
+    while start.elapsed() < Duration::from_secs(secs) {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => {
+                steps.push(DiagnosisStep::new(
+                    "Continuity",
+                    false,
+                    format!(
+                        "the stream ended after {:.1} seconds",
+                        start.elapsed().as_secs_f64()
+                    ),
+                ));
+                return steps;
+            }
+            Ok(n) => n,
+            Err(e) => {
+                steps.push(DiagnosisStep::new(
+                    "Continuity",
+                    false,
+                    format!("reading the stream failed: {e}"),
+                ));
+                return steps;
+            }
+        };
+        if first_read.is_some() && last_read.elapsed() > STALL {
+            stalls.push(last_read.elapsed());
+        }
+        last_read = Instant::now();
+        first_read.get_or_insert(last_read);
+        let data = &buf[..n];
+        if format == StreamingFormat::Flac {
+            let had_format = flac.format.is_some();
+            if !flac.add(data) {
+                break;
+            }
+            if let (false, Some(f)) = (had_format, flac.format) {
+                steps.push(check_format("STREAMINFO", f, sample_rate, bits));
+            }
+        } else {
+            audio_bytes += n as u64;
+        }
+    }
+    let elapsed = first_read.map_or(Duration::ZERO, |t| t.elapsed());
+    let audio = if format == StreamingFormat::Flac {
+        if let Some(e) = flac.error {
+            steps.push(DiagnosisStep::new("Frames", false, e));
+            return steps;
+        }
+        let Some(f) = flac.format else {
+            steps.push(DiagnosisStep::new(
+                "Header",
+                false,
+                "no STREAMINFO received".to_string(),
+            ));
+            return steps;
+        };
+        steps.push(DiagnosisStep::new(
+            "Frames",
+            true,
+            format!(
+                "{} frames with correct CRCs and consecutive numbers",
+                flac.frames
+            ),
+        ));
+        Duration::from_secs_f64(flac.samples as f64 / f64::from(f.sample_rate.max(1)))
+    } else {
+        let Some(f) = pcm else {
+            return steps;
+        };
+        let frame_bytes = u64::from(f.channels * f.bits_per_sample / 8).max(1);
+        let byte_rate = frame_bytes * u64::from(f.sample_rate.max(1));
+        Duration::from_secs_f64(audio_bytes as f64 / byte_rate as f64)
+    };
+    steps.push(check_continuity(audio, elapsed, &stalls));
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::test_files::test_file;
+
+    #[test]
+    fn test_flac_checker() {
+        let (_, sine) = test_file("/test/sine.flac").unwrap();
+        let mut flac = FlacChecker::default();
+        for chunk in sine.chunks(1000) {
+            assert!(flac.add(chunk), "{:?}", flac.error);
+        }
+        assert_eq!(
+            flac.format,
+            Some(WavFormat {
+                sample_rate: 44100,
+                channels: 2,
+                bits_per_sample: 16
+            })
+        );
+        // all frames but the last, that has no next frame header
+        assert!(flac.frames > 0);
+        assert_eq!(
+            flac.samples + u64::from(flac.current.unwrap().blocksize),
+            441_000
+        );
+        // a corrupted frame is found
+        let mut corrupted = sine.to_vec();
+        let n = corrupted.len() / 2;
+        corrupted[n] ^= 0x55;
+        let mut flac = FlacChecker::default();
+        assert!(!flac.add(&corrupted));
+    }
+
+    #[test]
+    fn test_lpcm_and_continuity() {
+        assert_eq!(
+            lpcm_format("audio/L24;rate=48000;channels=2"),
+            Some(WavFormat {
+                sample_rate: 48000,
+                channels: 2,
+                bits_per_sample: 24
+            })
+        );
+        assert_eq!(lpcm_format("audio/flac"), None);
+        assert_eq!(coded_number(&[0xc2, 0x80]), Some((0x80, 2)));
+        assert!(!check_continuity(Duration::from_secs(5), Duration::from_secs(10), &[]).ok);
+        assert!(check_continuity(Duration::from_secs(10), Duration::from_secs(10), &[]).ok);
+    }
+}
@@ -2,26 +2,99 @@ use crate::{
     enums::{
         messages::MessageType,
         streaming::{
-            BitDepth,
+            BitDepth, ChannelMode, StreamSize,
             StreamingFormat::{self, Flac, Lpcm, Rf64, Wav},
             StreamingState,
         },
     },
-    globals::statics::{CLIENTS, CONFIG},
-    openhome::rendercontrol::WavData,
-    server::query_params::StreamingParams,
-    utils::{rwstream::ChannelStream, ui_logger::ui_log},
+    globals::statics::{CLIENTS, CONFIG, PREROLL},
+    openhome::rendercontrol::{dlna_content_features, WavData},
+    server::{
+        access_log::{AccessLog, CountingReader},
+        client_api::{clients_json, disconnect_json},
+        client_reaper::start_client_reaper,
+        health::{health_json, is_health_url, server_started},
+        listen::listen_page,
+        players_api::parse_players_request,
+        playlist::playlist,
+        query_params::StreamingParams,
+        rtp_sender::rtp_sdp,
+        silence_pause::start_silence_pause,
+        ssdp_announcer::{
+            announcing, connection_manager_response, device_icon, icy_name, server_header,
+            upnp_document, CM_CONTROL_URL,
+        },
+        tcp_tuning::tuned_listener,
+        test_files::test_file,
+    },
+    utils::{
+        announce::announce_request,
+        bitperfect::{enforce_channel_mode, stream_conversions},
+        replay::{is_replay_url, replay_flac},
+        resource_monitor::resources_json,
+        rwstream::ChannelStream,
+        scrobble::now_playing_request,
+        session_stats::{register_session, sessions_json, store_summary, SilenceCount},
+        shutdown::authorize_shutdown,
+        ui_logger::ui_log,
+    },
 };
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use log::debug;
-use std::{net::IpAddr, sync::Arc, thread, time::Duration};
-use tiny_http::{Header, Method, Response, Server};
+use std::{
+    io::{Cursor, Read},
+    net::{IpAddr, SocketAddr},
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// the maximum A/V delay for a renderer
+const MAX_DELAY_MSEC: u32 = 10_000;
+/// the maximum size of an uploaded announcement
+const MAX_ANNOUNCEMENT_SIZE: u64 = 4 * 1024 * 1024;
+/// the maximum size of a now-playing track
+const MAX_NOW_PLAYING_SIZE: u64 = 64 * 1024;
+/// the response to a control request while the control API is disabled
+const CONTROL_API_DISABLED: &str =
+    "The control API is disabled, set control_api = true in the config file";
+
+/// the stream parameters a client actually requested, from its url and the config
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct NegotiatedStream {
+    pub format: StreamingFormat,
+    pub bits_per_sample: u16,
+    pub sample_rate: u32,
+    pub channel_mode: ChannelMode,
+    pub stream_size: StreamSize,
+}
+
+impl std::fmt::Display for NegotiatedStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} bit {} Hz {}, {}",
+            self.format,
+            self.bits_per_sample,
+            self.sample_rate,
+            self.channel_mode,
+            self.stream_size
+        )
+    }
+}
 
 /// streaming state feedback for a client
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct StreamerFeedBack {
     pub remote_ip: String,
     pub streaming_state: StreamingState,
+    pub streaming_format: StreamingFormat,
+    pub streaming_time: Duration,
+    /// the negotiated stream parameters when the streaming started
+    pub negotiated: Option<NegotiatedStream>,
+    /// the silence injected into the stream when it ended
+    pub silence: SilenceCount,
 }
 
 /// `run_server` - run a tiny-http webserver to serve streaming requests from renderers
@@ -51,15 +124,45 @@ pub fn run_server(
         )
     };
     ui_log(&logmsg);
-    let server = Arc::new(Server::http(addr).unwrap());
+    // use a tuned listener if TCP options are configured
+    let tcp = CONFIG.read().tcp;
+    let server = if let Some(tcp) = tcp {
+        ui_log(&format!("Streaming connections use TCP options {tcp:?}"));
+        match tuned_listener(SocketAddr::new(*local_addr, server_port), &tcp) {
+            Ok(listener) => Server::from_listener(listener, None),
+            Err(e) => {
+                ui_log(&format!(
+                    "*E*E*> Could not set the TCP options, using the defaults: {e}"
+                ));
+                Server::http(&addr)
+            }
+        }
+    } else {
+        Server::http(&addr)
+    };
+    let server = match server {
+        Ok(server) => server,
+        Err(e) => {
+            ui_log(&format!(
+                "*E*E*> Could not start the HTTP server on {addr}: {e}, streaming is not possible!!"
+            ));
+            return;
+        }
+    };
+    server_started();
+    start_client_reaper();
+    start_silence_pause();
+    let server = Arc::new(server);
     let mut handles = Vec::new();
     // always have two threads ready to serve new requests
     for _ in 0..2 {
         let server = server.clone();
         let feedback_tx_c = feedback_tx.clone();
+        let addr_c = addr.clone();
         handles.push(std::thread::spawn(move || {
-            for rq in server.incoming_requests() {
+            for mut rq in server.incoming_requests() {
                 let feedback_tx_c = feedback_tx_c.clone();
+                let addr_c = addr_c.clone();
                 // start streaming in a new thread and continue serving new requests
                 std::thread::spawn(move || {
                     if cfg!(debug_assertions) {
@@ -71,40 +174,24 @@ pub fn run_server(
                             );
                         }
                     }
-                    // get remote ip
-                    let remote_addr = format!("{}", rq.remote_addr().unwrap());
-                    let mut remote_ip = remote_addr.clone();
-                    if let Some(i) = remote_ip.find(':') {
-                        remote_ip.truncate(i);
+                    let access = AccessLog::new(&rq);
+                    // serve the requests other than the streams
+                    let url = rq.url().to_string();
+                    if let Some(response) = dispatch(&mut rq, &addr_c, &feedback_tx_c) {
+                        respond(rq, response, &access, &url);
+                        return;
                     }
                     // default headers
-                    let srvr_hdr =
-                        Header::from_bytes(&b"Server"[..], &b"swyh-rs tiny-http"[..]).unwrap();
-                    let nm_hdr = Header::from_bytes(&b"icy-name"[..], &b"swyh-rs"[..]).unwrap();
+                    let srvr_hdr = server_hdr();
+                    let nm_hdr = icy_name().and_then(|name| {
+                        Header::from_bytes(&b"icy-name"[..], name.as_bytes()).ok()
+                    });
                     let cc_hdr = Header::from_bytes(&b"Connection"[..], &b"close"[..]).unwrap();
                     // don't accept range headers (Linn) until I know how to handle them
                     let acc_rng_hdr =
                         Header::from_bytes(&b"Accept-Ranges"[..], &b"none"[..]).unwrap();
                     // parse the GET request
                     let sp = StreamingParams::from_query_string(rq.url());
-                    // check url
-                    if sp.path.is_none() {
-                        ui_log(&format!(
-                            "Unrecognized request '{}' from {}'",
-                            rq.url(),
-                            rq.remote_addr().unwrap()
-                        ));
-                        let response = Response::empty(404)
-                            .with_header(cc_hdr)
-                            .with_header(srvr_hdr)
-                            .with_header(nm_hdr);
-                        if let Err(e) = rq.respond(response) {
-                            ui_log(&format!(
-                                "=>Http streaming request with {remote_addr} terminated [{e}]"
-                            ));
-                        }
-                        return;
-                    }
                     // get remote ip
                     let remote_addr = format!("{}", rq.remote_addr().unwrap());
                     let mut remote_ip = remote_addr.clone();
@@ -128,6 +215,12 @@ pub fn run_server(
                     } else {
                         BitDepth::from(cf_bps)
                     };
+                    // channel mode from GET query string or from config, stereo if bit-perfect
+                    let channel_mode = enforce_channel_mode(
+                        sp.cm.or(conf.channel_mode).unwrap_or(ChannelMode::Stereo),
+                    );
+                    // the A/V delay for this renderer from the GET query string
+                    let delay_msec = sp.dl.unwrap_or_default().min(MAX_DELAY_MSEC);
                     let ct_text = if format == StreamingFormat::Flac {
                         "audio/flac".to_string()
                     } else if format == StreamingFormat::Wav || format == StreamingFormat::Rf64 {
@@ -135,9 +228,15 @@ pub fn run_server(
                     } else {
                         // LPCM
                         if bps == BitDepth::Bits16 {
-                            format!("audio/L16;rate={};channels=2", wd.sample_rate.0)
+                            format!(
+                                "audio/L16;rate={};channels={}",
+                                wd.sample_rate.0, wd.channels
+                            )
                         } else {
-                            format!("audio/L24;rate={};channels=2", wd.sample_rate.0)
+                            format!(
+                                "audio/L24;rate={};channels={}",
+                                wd.sample_rate.0, wd.channels
+                            )
                         }
                     };
                     let ct_hdr =
@@ -145,6 +244,26 @@ pub fn run_server(
                     let tm_hdr =
                         Header::from_bytes(&b"TransferMode.dlna.org"[..], &b"Streaming"[..])
                             .unwrap();
+                    // echo the DLNA content features if the renderer asks for them
+                    let cf_hdr = if rq
+                        .headers()
+                        .iter()
+                        .any(|h| h.field.equiv("getcontentFeatures.dlna.org"))
+                    {
+                        let features = dlna_content_features(format, bps as u16);
+                        Some(
+                            Header::from_bytes(
+                                &b"contentFeatures.dlna.org"[..],
+                                features.as_bytes(),
+                            )
+                            .unwrap(),
+                        )
+                    } else {
+                        None
+                    };
+                    // the negotiated audio format for the access log
+                    let audio_format =
+                        format!("{format} {} bit {} Hz", bps as u16, wd.sample_rate.0);
                     // handle response, streaming if GET, headers only otherwise
                     if matches!(rq.method(), Method::Get) {
                         ui_log(&format!(
@@ -152,31 +271,83 @@ pub fn run_server(
                             rq.url(),
                             rq.remote_addr().unwrap()
                         ));
+                        let conversions = stream_conversions(bps as u16, channel_mode);
+                        if conversions.is_empty() {
+                            ui_log(&format!("Bit-perfect streaming to {remote_addr}"));
+                        } else if conf.bit_perfect {
+                            ui_log(&format!(
+                                "*W*W*> Streaming to {remote_addr} is not bit-perfect: {}",
+                                conversions.join(", ")
+                            ));
+                        }
                         let (tx, rx): (Sender<Vec<f32>>, Receiver<Vec<f32>>) = unbounded();
                         let use_wav_hdr =
                             [StreamingFormat::Wav, StreamingFormat::Rf64].contains(&format);
-                        let channel_stream = ChannelStream::new(
+                        let mut channel_stream = ChannelStream::new(
                             tx,
                             rx,
                             remote_ip.clone(),
                             use_wav_hdr,
                             wd.sample_rate.0,
                             bps as u16,
+                            wd.channels,
                             format,
+                            channel_mode,
+                            delay_msec,
+                            sp.hs.unwrap_or_default(),
                         );
+                        channel_stream.user_agent = rq
+                            .headers()
+                            .iter()
+                            .find(|h| h.field.equiv("User-Agent"))
+                            .map(|h| h.value.to_string())
+                            .unwrap_or_default();
+                        let stats = channel_stream.stats.clone();
+                        let reconnects = register_session(&remote_ip);
                         let nclients = {
+                            // first send the pre-roll audio, keeping it locked until the client
+                            // is registered so that the wave_reader can't add samples in between
+                            let preroll = PREROLL.lock();
+                            let (front, back) = preroll.as_slices();
+                            for samples in [front, back] {
+                                if !samples.is_empty() {
+                                    channel_stream.write(samples);
+                                }
+                            }
                             let mut clients = CLIENTS.write();
                             clients.insert(remote_addr.clone(), channel_stream.clone());
                             clients.len()
                         };
                         debug!("Now have {} streaming clients", nclients);
 
+                        // the configured content length and chunksize params,
+                        // unless overridden by the GET query string
+                        let stream_size = sp.ss.unwrap_or_else(|| {
+                            match format {
+                                Lpcm => conf.lpcm_stream_size,
+                                Wav => conf.wav_stream_size,
+                                Rf64 => conf.rf64_stream_size,
+                                Flac => conf.flac_stream_size,
+                            }
+                            .unwrap()
+                        });
                         feedback_tx_c
                             .send(MessageType::PlayerMessage(StreamerFeedBack {
                                 remote_ip: remote_ip.clone(),
                                 streaming_state: StreamingState::Started,
+                                streaming_format: format,
+                                streaming_time: Duration::ZERO,
+                                negotiated: Some(NegotiatedStream {
+                                    format,
+                                    bits_per_sample: bps as u16,
+                                    sample_rate: wd.sample_rate.0,
+                                    channel_mode,
+                                    stream_size,
+                                }),
+                                silence: SilenceCount::default(),
                             }))
                             .unwrap();
+                        let streaming_start = Instant::now();
 
                         // check for upfront audio buffering needed
                         if let Some(bufferdelay) = conf.buffering_delay_msec {
@@ -198,32 +369,29 @@ pub fn run_server(
                         };
                         ui_log(&format!(
                             "Streaming {streaming_format}, input sample format {:?}, \
-                            channels=2, rate={}, bps = {}, to {}",
+                            channels={}, rate={}, bps = {}, to {}",
                             wd.sample_format,
+                            wd.channels,
                             wd.sample_rate.0,
                             bps as u16,
                             rq.remote_addr().unwrap()
                         ));
-                        // use the configured content length and chunksize params
-                        let (mut streamsize, mut chunksize) = match format {
-                            Lpcm => conf.lpcm_stream_size.unwrap().values(),
-                            Wav => conf.wav_stream_size.unwrap().values(),
-                            Rf64 => conf.rf64_stream_size.unwrap().values(),
-                            Flac => conf.flac_stream_size.unwrap().values(),
-                        };
-                        // unless overridden by the GET query string
-                        if sp.ss.is_some() {
-                            (streamsize, chunksize) = sp.ss.unwrap().values();
-                        }
-                        let response = Response::empty(200)
-                            .with_data(channel_stream, streamsize)
+                        let (streamsize, chunksize) = stream_size.values();
+                        let (stream, bytes_sent) = CountingReader::new(channel_stream);
+                        let mut response = Response::empty(200)
+                            .with_data(stream, streamsize)
                             .with_chunked_threshold(chunksize)
                             .with_header(cc_hdr)
                             .with_header(ct_hdr)
                             .with_header(tm_hdr)
                             .with_header(srvr_hdr)
-                            .with_header(acc_rng_hdr)
-                            .with_header(nm_hdr);
+                            .with_header(acc_rng_hdr);
+                        if let Some(nm_hdr) = nm_hdr {
+                            response.add_header(nm_hdr);
+                        }
+                        if let Some(cf_hdr) = cf_hdr {
+                            response.add_header(cf_hdr);
+                        }
                         if cfg!(debug_assertions) {
                             debug!("==> Response:");
                             debug!(
@@ -240,6 +408,8 @@ pub fn run_server(
                                 "=>Http connection with {remote_addr} terminated [{e:?}]"
                             ));
                         }
+                        let bytes = bytes_sent.load(Ordering::Relaxed);
+                        access.log(200, bytes, &audio_format);
                         let nclients = {
                             let mut clients = CLIENTS.write();
                             if let Some(chs) = clients.remove(&remote_addr) {
@@ -255,18 +425,37 @@ pub fn run_server(
                             .send(MessageType::PlayerMessage(StreamerFeedBack {
                                 remote_ip,
                                 streaming_state: StreamingState::Ended,
+                                streaming_format: format,
+                                streaming_time: streaming_start.elapsed(),
+                                negotiated: None,
+                                silence: stats.silence(),
                             }))
                             .unwrap();
                         ui_log(&format!("Streaming to {remote_addr} has ended"));
+                        let summary = stats.summary(
+                            &remote_addr,
+                            &audio_format,
+                            streaming_start.elapsed(),
+                            bytes,
+                            reconnects,
+                        );
+                        ui_log(&summary.to_string());
+                        store_summary(summary);
                     } else if matches!(rq.method(), Method::Head) {
                         debug!("HEAD rq from {}", remote_addr);
-                        let response = Response::empty(200)
+                        let mut response = Response::empty(200)
                             .with_header(cc_hdr)
                             .with_header(ct_hdr)
                             .with_header(tm_hdr)
                             .with_header(srvr_hdr)
-                            .with_header(acc_rng_hdr)
-                            .with_header(nm_hdr);
+                            .with_header(acc_rng_hdr);
+                        if let Some(nm_hdr) = nm_hdr {
+                            response.add_header(nm_hdr);
+                        }
+                        if let Some(cf_hdr) = cf_hdr {
+                            response.add_header(cf_hdr);
+                        }
+                        access.log_response(&response, &audio_format);
                         if let Err(e) = rq.respond(response) {
                             ui_log(&format!(
                                 "=>Http HEAD connection with {remote_addr} terminated [{e}]"
@@ -274,10 +463,13 @@ pub fn run_server(
                         }
                     } else if matches!(rq.method(), Method::Post) {
                         debug!("POST rq from {}", remote_addr);
-                        let response = Response::empty(200)
+                        let mut response = Response::empty(200)
                             .with_header(cc_hdr)
-                            .with_header(srvr_hdr)
-                            .with_header(nm_hdr);
+                            .with_header(srvr_hdr);
+                        if let Some(nm_hdr) = nm_hdr {
+                            response.add_header(nm_hdr);
+                        }
+                        access.log_response(&response, "-");
                         if let Err(e) = rq.respond(response) {
                             ui_log(&format!(
                                 "=>Http POST connection with {remote_addr} terminated [{e}]"
@@ -293,3 +485,246 @@ pub fn run_server(
         h.join().unwrap();
     }
 }
+
+/// a response with its body in memory, for all requests except the streams
+type DataResponse = Response<Cursor<Vec<u8>>>;
+
+/// the Server header of all responses
+fn server_hdr() -> Header {
+    Header::from_bytes(&b"Server"[..], server_header().as_bytes())
+        .unwrap_or_else(|()| Header::from_bytes(&b"Server"[..], &b"swyh-rs tiny-http"[..]).unwrap())
+}
+
+/// a response with a body of the given content type
+fn content_response(content_type: &str, body: impl Into<Vec<u8>>) -> DataResponse {
+    let ct_hdr = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap();
+    Response::from_data(body).with_header(ct_hdr)
+}
+
+/// a plain text response with a status code
+fn text_response(status: u16, text: &str) -> DataResponse {
+    Response::from_string(text).with_status_code(status)
+}
+
+/// the paths that need `control_api` in the config
+fn is_control_path(path: &str) -> bool {
+    path == "/api/announce"
+        || path == "/api/players"
+        || path == "/api/nowplaying"
+        || path.starts_with("/api/clients/")
+}
+
+/// the value of a request header
+fn header_value(rq: &Request, field: &'static str) -> Option<String> {
+    rq.headers()
+        .iter()
+        .find(|h| h.field.equiv(field))
+        .map(|h| h.value.to_string())
+}
+
+/// `dispatch` - serve all requests except the streams on method and path
+///
+/// returns `None` for a streaming request
+fn dispatch(
+    rq: &mut Request,
+    server_addr: &str,
+    feedback_tx: &Sender<MessageType>,
+) -> Option<DataResponse> {
+    let url = rq.url().to_string();
+    let remote_addr = format!("{}", rq.remote_addr().unwrap());
+    let path = url.split('?').next().unwrap_or_default();
+    let lc_path = path.to_lowercase();
+    let method = rq.method().clone();
+    let response = match (method, lc_path.as_str()) {
+        (_, p) if is_control_path(p) && !CONFIG.read().control_api => {
+            text_response(403, CONTROL_API_DISABLED)
+        }
+        // the health, for the health check of a container or service manager
+        _ if is_health_url(&url) => {
+            let (status, json) = health_json();
+            content_response("application/json", json).with_status_code(status)
+        }
+        // the last seconds of the captured audio
+        _ if is_replay_url(&url) => {
+            if let Some(flac) = replay_flac() {
+                ui_log(&format!("Serving the replay buffer to {remote_addr}"));
+                content_response("audio/flac", flac)
+                    .with_header(Header::from_bytes(&b"Accept-Ranges"[..], &b"none"[..]).unwrap())
+            } else {
+                text_response(404, "No replay buffer")
+            }
+        }
+        // the SDP file of the RTP stream
+        (_, "/rtp/swyh.sdp") => rtp_sdp().map_or_else(
+            || text_response(404, "RTP is not enabled"),
+            |sdp| content_response("application/sdp", sdp),
+        ),
+        // the UPnP device and service descriptions
+        (_, p) if p == "/upnp/device.xml" || p.starts_with("/upnp/cm") => {
+            let control = url.eq_ignore_ascii_case(CM_CONTROL_URL);
+            let xml = if control {
+                header_value(rq, "SOAPACTION")
+                    .filter(|_| announcing())
+                    .and_then(|soap_action| connection_manager_response(&soap_action))
+            } else {
+                upnp_document(&url)
+            };
+            if let Some(xml) = xml {
+                content_response("text/xml; charset=\"utf-8\"", xml)
+            } else if control && announcing() {
+                text_response(500, "Invalid Action")
+            } else {
+                text_response(404, "SSDP announce is not enabled")
+            }
+        }
+        // mix an announcement into the stream
+        (Method::Post, "/api/announce") => {
+            let mut body = Vec::new();
+            let _ = rq
+                .as_reader()
+                .take(MAX_ANNOUNCEMENT_SIZE + 1)
+                .read_to_end(&mut body);
+            let announced = if body.len() as u64 > MAX_ANNOUNCEMENT_SIZE {
+                Err(format!(
+                    "An announcement can't be larger than {} MiB",
+                    MAX_ANNOUNCEMENT_SIZE / (1024 * 1024)
+                ))
+            } else {
+                announce_request(&url, &body)
+            };
+            match announced {
+                Ok(duration) => content_response(
+                    "application/json",
+                    format!("{{\"duration_secs\":{duration:.3}}}"),
+                ),
+                Err(e) => text_response(400, &e),
+            }
+        }
+        (_, "/api/announce") => text_response(405, "Use POST to announce"),
+        // change the active players, their volume and the format
+        (Method::Post, "/api/players") => match parse_players_request(&url) {
+            Ok(request) => {
+                ui_log(&format!("Players request from {remote_addr}"));
+                let json = serde_json::to_string(&request).unwrap_or_default();
+                let _ = feedback_tx.send(MessageType::PlayersMessage(request));
+                content_response("application/json", json)
+            }
+            Err(e) => text_response(400, &e),
+        },
+        (_, "/api/players") => text_response(405, "Use POST to change the players"),
+        // the now-playing track to scrobble
+        (Method::Post, "/api/nowplaying") => {
+            let mut body = Vec::new();
+            let _ = rq
+                .as_reader()
+                .take(MAX_NOW_PLAYING_SIZE)
+                .read_to_end(&mut body);
+            match now_playing_request(&body) {
+                Ok(json) => content_response("application/json", json),
+                Err(e) => text_response(400, &e),
+            }
+        }
+        (_, "/api/nowplaying") => text_response(405, "Use POST to push the now-playing track"),
+        // stop the renderers and exit
+        (method, "/api/shutdown") => {
+            let authorized = authorize_shutdown(
+                header_value(rq, "Authorization").as_deref(),
+                CONFIG.read().shutdown_token.as_deref(),
+            );
+            if let Err(refused) = authorized {
+                let (status, msg) = refused.response();
+                ui_log(&format!(
+                    "*W*W*> Refused shutdown request from {remote_addr}: {msg}"
+                ));
+                text_response(status, msg)
+            } else if method == Method::Post {
+                let _ = feedback_tx.send(MessageType::ShutdownMessage);
+                ui_log(&format!("Shutdown request from {remote_addr}"));
+                content_response(
+                    "application/json",
+                    format!("{{\"connections\":{}}}", CLIENTS.read().len()),
+                )
+                .with_status_code(202)
+            } else {
+                text_response(405, "Use POST to shut down")
+            }
+        }
+        // force-disconnect the streaming connections of a client
+        (Method::Delete, p) if p.starts_with("/api/clients/") => {
+            let (n, json) = disconnect_json(&path["/api/clients/".len()..]);
+            content_response("application/json", json).with_status_code(if n > 0 {
+                200
+            } else {
+                404
+            })
+        }
+        (_, p) if p.starts_with("/api/clients/") => {
+            text_response(405, "Use DELETE to disconnect a client")
+        }
+        // the details of the streaming connections
+        (_, "/api/clients") => content_response("application/json", clients_json()),
+        // the summaries of the last streaming sessions
+        (_, "/stats/sessions") => content_response("application/json", sessions_json()),
+        // the resource usage
+        (_, "/stats/resources") => content_response("application/json", resources_json()),
+        // the static test files, the listen page, the playlists and the device icons,
+        // or a 404 if it isn't a stream either
+        _ => test_file(&url)
+            .map(|(content_type, data)| {
+                ui_log(&format!("Serving test file {url} to {remote_addr}"));
+                content_response(content_type, data)
+                    .with_header(Header::from_bytes(&b"Accept-Ranges"[..], &b"none"[..]).unwrap())
+            })
+            .or_else(|| {
+                listen_page(&url).map(|page| content_response("text/html; charset=utf-8", page))
+            })
+            .or_else(|| {
+                let host = header_value(rq, "Host").unwrap_or_else(|| server_addr.to_string());
+                playlist(&url, &host).map(|(content_type, list)| {
+                    ui_log(&format!("Serving {url} to {remote_addr}"));
+                    content_response(content_type, list)
+                })
+            })
+            .or_else(|| device_icon(&url).map(|png| content_response("image/png", png)))
+            .or_else(|| {
+                StreamingParams::from_query_string(&url)
+                    .path
+                    .is_none()
+                    .then(|| {
+                        ui_log(&format!("Unrecognized request '{url}' from {remote_addr}'"));
+                        text_response(404, "")
+                    })
+            })?,
+    };
+    Some(response)
+}
+
+/// `respond` - send a response that is not a stream, and log it
+fn respond<R: Read>(rq: Request, response: Response<R>, access: &AccessLog, what: &str) {
+    let response = response.with_header(server_hdr());
+    access.log_response(&response, "-");
+    let remote_addr = rq
+        .remote_addr()
+        .map_or_else(|| "-".to_string(), ToString::to_string);
+    if let Err(e) = rq.respond(response) {
+        ui_log(&format!(
+            "=>Http request {what} with {remote_addr} terminated [{e}]"
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_control_path() {
+        assert!(is_control_path("/api/announce"));
+        assert!(is_control_path("/api/players"));
+        assert!(is_control_path("/api/nowplaying"));
+        assert!(is_control_path("/api/clients/192.168.1.20"));
+        assert!(!is_control_path("/api/clients"));
+        assert!(!is_control_path("/api/shutdown"));
+        assert!(!is_control_path("/stream/swyh.flac"));
+    }
+}
@@ -0,0 +1,67 @@
+//! The optional TCP options of the streaming connections: SO_KEEPALIVE with its timing,
+//! TCP_NODELAY and the send buffer size.
+//!
+//! `tiny_http` doesn't expose the sockets of its connections, so the options are set on the
+//! listening socket, and the accepted connections inherit them (Linux, Windows and macOS).
+
+use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+use std::{
+    io::Result as IoResult,
+    net::{SocketAddr, TcpListener},
+    time::Duration,
+};
+
+use crate::utils::configuration::TcpSettings;
+
+/// a listening socket with the configured TCP options
+pub fn tuned_listener(addr: SocketAddr, settings: &TcpSettings) -> IoResult<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // like std::net::TcpListener::bind
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    if let Some(idle_secs) = settings.keepalive_secs {
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(u64::from(idle_secs)));
+        let keepalive = match settings.keepalive_interval_secs {
+            Some(secs) => keepalive.with_interval(Duration::from_secs(u64::from(secs))),
+            None => keepalive,
+        };
+        // the number of probes can't be set on Windows
+        #[cfg(not(windows))]
+        let keepalive = match settings.keepalive_retries {
+            Some(retries) => keepalive.with_retries(retries),
+            None => keepalive,
+        };
+        socket.set_tcp_keepalive(&keepalive)?;
+    }
+    if settings.nodelay {
+        socket.set_nodelay(true)?;
+    }
+    if let Some(size) = settings.send_buffer_size {
+        socket.set_send_buffer_size(size as usize)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(128)?;
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket2::SockRef;
+
+    #[test]
+    fn test_tuned_listener() {
+        let settings = TcpSettings {
+            keepalive_secs: Some(60),
+            keepalive_interval_secs: Some(10),
+            keepalive_retries: Some(3),
+            nodelay: true,
+            send_buffer_size: Some(65536),
+        };
+        let listener = tuned_listener("127.0.0.1:0".parse().unwrap(), &settings).unwrap();
+        let socket = SockRef::from(&listener);
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.nodelay().unwrap());
+        assert!(socket.send_buffer_size().unwrap() >= 65536);
+    }
+}
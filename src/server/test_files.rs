@@ -0,0 +1,117 @@
+//! Static test files served on /test/sine.flac and /test/sine.wav.
+//!
+//! A 10 second 1 kHz sine tone (44.1 kHz, 16 bit stereo) with correct finite headers,
+//! so that a renderer problem with the "infinite" streaming headers can be told apart
+//! from network or DLNA problems.
+
+use flac_bound::{FlacEncoder, WriteWrapper};
+use log::error;
+use std::sync::LazyLock;
+
+use crate::{enums::streaming::HeaderSize, utils::rwstream::create_wav_hdr};
+
+const SAMPLE_RATE: u32 = 44_100;
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+const DURATION_SECS: u32 = 10;
+const FREQUENCY: f64 = 1000.0;
+const AMPLITUDE: f64 = 0.5; // -6 dB
+
+static SINE_WAV: LazyLock<Vec<u8>> = LazyLock::new(create_sine_wav);
+static SINE_FLAC: LazyLock<Vec<u8>> = LazyLock::new(create_sine_flac);
+
+/// get the content type and the contents of a test file for an url, if it is one
+#[must_use]
+pub fn test_file(url: &str) -> Option<(&'static str, &'static [u8])> {
+    let path = url.split('?').next().unwrap_or_default().to_lowercase();
+    match path.as_str() {
+        "/test/sine.wav" => Some(("audio/wav", SINE_WAV.as_slice())),
+        "/test/sine.flac" => Some(("audio/flac", SINE_FLAC.as_slice())),
+        _ => None,
+    }
+}
+
+/// the interleaved stereo 16 bit sine samples
+fn sine_samples() -> Vec<i16> {
+    let frames = SAMPLE_RATE * DURATION_SECS;
+    let mut samples = Vec::with_capacity((frames * u32::from(CHANNELS)) as usize);
+    for n in 0..frames {
+        let t = f64::from(n) / f64::from(SAMPLE_RATE);
+        let value = (2.0 * std::f64::consts::PI * FREQUENCY * t).sin() * AMPLITUDE;
+        let sample = (value * f64::from(i16::MAX)) as i16;
+        for _ in 0..CHANNELS {
+            samples.push(sample);
+        }
+    }
+    samples
+}
+
+/// a WAV file with a correct (finite) RIFF header
+fn create_sine_wav() -> Vec<u8> {
+    let samples = sine_samples();
+    let datachunksize = (samples.len() * 2) as u64;
+    let mut wav = create_wav_hdr(
+        SAMPLE_RATE,
+        BITS_PER_SAMPLE,
+        CHANNELS,
+        HeaderSize::Bytes(datachunksize),
+    );
+    wav.reserve(datachunksize as usize);
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+/// a FLAC file with the total number of samples in the STREAMINFO header
+fn create_sine_flac() -> Vec<u8> {
+    let samples = sine_samples()
+        .into_iter()
+        .map(i32::from)
+        .collect::<Vec<i32>>();
+    let frames = samples.len() / CHANNELS as usize;
+    let mut flac: Vec<u8> = Vec::new();
+    {
+        let mut outw = WriteWrapper(&mut flac);
+        let mut enc = FlacEncoder::new()
+            .unwrap()
+            .channels(u32::from(CHANNELS))
+            .bits_per_sample(u32::from(BITS_PER_SAMPLE))
+            .sample_rate(SAMPLE_RATE)
+            .compression_level(5)
+            .total_samples_estimate(frames as u64)
+            .init_write(&mut outw)
+            .unwrap();
+        if enc
+            .process_interleaved(samples.as_slice(), frames as u32)
+            .is_err()
+        {
+            error!("Encoding the FLAC test file failed");
+        }
+        let _ = enc.finish();
+    }
+    flac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sine_wav() {
+        let wav = create_sine_wav();
+        let datasize = SAMPLE_RATE * DURATION_SECS * 4;
+        assert_eq!(wav.len(), 44 + datasize as usize);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav[4..8], (datasize + 36).to_le_bytes());
+        assert_eq!(wav[40..44], datasize.to_le_bytes());
+    }
+
+    #[test]
+    fn test_test_file() {
+        assert!(test_file("/test/sine.wav").is_some());
+        assert!(test_file("/TEST/Sine.wav?x=1").is_some());
+        assert!(test_file("/test/sine.mp3").is_none());
+        assert!(test_file("/stream/swyh.wav").is_none());
+    }
+}
@@ -1,22 +1,61 @@
 #![cfg(feature = "gui")]
 use crate::{
-    enums::streaming::{
-        StreamSize,
-        StreamingFormat::{self, Flac},
+    dsp::chain::{describe_stage, loudness, set_loudness, update_dsp_chain},
+    enums::{
+        messages::{MediaBrowse, MessageType},
+        streaming::{
+            StreamSize,
+            StreamingFormat::{self, Flac},
+        },
+    },
+    globals::statics::{
+        CLIENTS, CONFIG, MSGCHANNEL, RUN_RMS_MONITOR, RUN_SPECTRUM_ANALYZER, THEMES,
+    },
+    openhome::{
+        diagnosis::diagnose,
+        inventory::{export_renderers, INVENTORY_FILE},
+        media_server::{media_servers, MediaObject, MediaServer},
+        rendercontrol::{Renderer, StreamInfo, WavData},
+        renderer_poller::poll_volume,
+    },
+    server::{
+        client_api::{client_details, disconnect_client},
+        port_select::server_port,
+        query_params::stream_url,
+        stream_check::{verify_stream, CHECK_SECS},
+    },
+    utils::{
+        audiodevices::{get_audio_hosts, stream_channels},
+        balance::{update_channel_gains, BALANCE_RANGE, MAX_TRIM_DB, MIN_TRIM_DB},
+        bandwidth::bitrate_advice,
+        bitperfect::{capture_format, enforce_channel_mode, stream_conversions},
+        button_gestures::{gesture_action, gestures_help, ButtonAction, Gesture},
+        configuration::{Configuration, DspStage, WindowGeometry},
+        meter::{
+            MeterSettings, MeterState, PeakHold, DEFAULT_METER_REFRESH_HZ, DEFAULT_RMS_WINDOW_MSEC,
+        },
+        mixer::{set_mix_gain, MAX_MIX_GAIN_DB, MIN_MIX_GAIN_DB},
+        renderer_badge::RendererBadge,
+        renderer_stats::{record_play_result, renderer_stats},
+        resource_monitor::{resource_usage, MAX_COMPRESSION_LEVEL},
+        session_stats::SilenceCount,
+        spectrum::SPECTRUM_BANDS,
+        traits::FwSlashPipeEscape,
+        ui_logger::ui_log,
+        volume_lock::{apply_lock_change, lock_choices, locked_volumes, LockChange},
     },
-    globals::statics::{CONFIG, RUN_RMS_MONITOR, THEMES},
-    openhome::rendercontrol::{Renderer, StreamInfo, WavData},
-    utils::{configuration::Configuration, traits::FwSlashPipeEscape, ui_logger::ui_log},
 };
 use fltk::{
     app,
-    button::{CheckButton, LightButton},
-    enums::{Align, Color, Event, FrameType},
+    browser::HoldBrowser,
+    button::{Button, CheckButton, LightButton},
+    dialog, draw,
+    enums::{Align, CallbackTrigger, Color, Event, FrameType, Key, Shortcut},
     frame::Frame,
     group::{Flex, FlexType, Pack, PackType},
     image::SvgImage,
     input::IntInput,
-    menu::MenuButton,
+    menu::{MenuButton, MenuFlag, MenuItem},
     misc::Progress,
     prelude::*,
     text::{TextBuffer, TextDisplay},
@@ -26,17 +65,130 @@ use fltk::{
 //use fltk_flow::Flow;
 use hashbrown::HashMap;
 use log::{debug, info, LevelFilter};
+use parking_lot::Mutex;
 
 use fltk_theme::{color_themes, ColorTheme};
 
 use std::{
-    cell::Cell,
+    cell::{Cell, RefCell},
     net::IpAddr,
     rc::Rc,
     str::FromStr,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, LazyLock,
+    },
+    time::Instant,
 };
 
+/// the widgets and the shared state that the rms monitor thread updates
+#[derive(Clone)]
+pub struct RmsMonitorWidgets {
+    pub rms_mon_l: Progress,
+    pub rms_mon_r: Progress,
+    pub clip_led: Button,
+    pub meter_state: Arc<Mutex<MeterState>>,
+    pub spectrum: Frame,
+    pub spectrum_levels: Arc<Mutex<Vec<f32>>>,
+}
+
+/// the volume slider of a renderer, with its name and the volume it was last set to
+#[derive(Clone)]
+struct VolumeSlider {
+    dev_name: String,
+    slider: HorNiceSlider,
+    volume: i32,
+}
+
+/// the volume sliders by renderer location, shared with the slider callbacks
+type VolumeSliders = Rc<RefCell<HashMap<String, VolumeSlider>>>;
+
+/// the rows of the renderer buttons by renderer name, to hide and show them
+type RendererRows = Rc<RefCell<HashMap<String, Flex>>>;
+
+/// what the mouse gestures and the menu of a renderer button act on
+#[derive(Clone)]
+struct ButtonContext {
+    renderer: Renderer,
+    local_addr: IpAddr,
+    sample_rate: u32,
+    rows: RendererRows,
+    volume_sliders: VolumeSliders,
+    muted: Rc<Cell<bool>>,
+}
+
+/// the badges next to a renderer button with the live state they show
+#[derive(Clone)]
+struct RendererBadges {
+    format: Frame,
+    state: Frame,
+    elapsed: Frame,
+    silence: Frame,
+    /// the address of the renderer, to find its streaming clients
+    remote_ip: String,
+    badge: RendererBadge,
+}
+
+/// the badges by renderer location, shared with the button callbacks
+type Badges = Rc<RefCell<HashMap<String, RendererBadges>>>;
+
+/// set while the sliders of a volume lock group follow a moved slider
+static FOLLOWING_VOLUME_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// the locks that run the play and stop actions of a renderer in order, by location
+static RENDERER_ACTIONS: LazyLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// the log pane, in the main window or popped out into its own window
+#[derive(Clone)]
+struct LogPane {
+    wind: DoubleWindow,
+    vpack: Pack,
+    pfeedback: Flex,
+    log_wind: DoubleWindow,
+    tb: TextDisplay,
+    min_w: i32,
+    min_h: i32,
+}
+
+impl LogPane {
+    /// the height of the log pane in the main window, including the spacing
+    const HEIGHT: i32 = 156 + 15;
+
+    fn is_separate(&self) -> bool {
+        !self.pfeedback.visible()
+    }
+
+    /// move the log into its own window (and show it) or back into the main window
+    fn pop_out(&mut self, separate: bool, show: bool) {
+        if separate {
+            if !self.is_separate() {
+                self.pfeedback.hide();
+                self.log_wind.add(&self.tb);
+                self.tb.resize(0, 0, self.log_wind.w(), self.log_wind.h());
+                self.log_wind.resizable(&self.tb);
+                // the main window can shrink to just the renderer buttons
+                self.wind
+                    .size_range(self.min_w, self.min_h - Self::HEIGHT, 0, 0);
+                let (x, y, w, h) = (self.wind.x(), self.wind.y(), self.wind.w(), self.wind.h());
+                self.wind.resize(x, y, w, h - Self::HEIGHT);
+            }
+            if show {
+                self.log_wind.show();
+            }
+        } else if self.is_separate() {
+            self.log_wind.hide();
+            self.pfeedback.add(&self.tb);
+            self.pfeedback.resizable(&self.tb);
+            self.pfeedback.show();
+            self.wind.size_range(self.min_w, self.min_h, 0, 0);
+            let (x, y, w, h) = (self.wind.x(), self.wind.y(), self.wind.w(), self.wind.h());
+            self.wind.resize(x, y, w, h + Self::HEIGHT);
+        }
+        self.vpack.redraw();
+    }
+}
+
 pub struct MainForm {
     pub wind: DoubleWindow,
     pub auto_resume: CheckButton,
@@ -46,11 +198,20 @@ pub struct MainForm {
     pub fmt_choice: MenuButton,
     pub b24_bit: CheckButton,
     pub show_rms: CheckButton,
-    pub rms_mon_l: Progress,
-    pub rms_mon_r: Progress,
+    pub rms_monitor: RmsMonitorWidgets,
     pub choose_audio_source_but: MenuButton,
+    choose_monitor_but: MenuButton,
+    choose_mix_but: MenuButton,
+    audio_sources: Rc<RefCell<Vec<String>>>,
+    pub bit_perfect_status: Frame,
+    loudness: CheckButton,
+    bitrate: Frame,
+    resources: Frame,
     pub tb: TextDisplay,
     pub buttons: HashMap<String, LightButton>,
+    badges: Badges,
+    rows: RendererRows,
+    volume_sliders: VolumeSliders,
     vpack: Pack,
     bwidth: i32,
     bheight: i32,
@@ -74,7 +235,7 @@ impl MainForm {
         const XPOS: i32 = 30;
         const YPOS: i32 = 5;
         const WW: i32 = 660;
-        const WH: i32 = 660;
+        const WH: i32 = 770;
 
         let title_color: Color = Color::from_u32(0x00e6_fff0);
         let app = app::App::default().with_scheme(app::Scheme::Gtk);
@@ -97,12 +258,28 @@ impl MainForm {
         wind.end();
         wind.show();
 
-        wind.handle(move |_, _ev| {
+        // the scale factor of the system, fltk reads it when the first window is shown
+        let system_scales: Vec<f32> = (0..app::screen_count()).map(app::screen_scale).collect();
+        if let Some(scale) = config.ui_scale {
+            Self::apply_ui_scale(&system_scales, Some(scale));
+        }
+
+        wind.handle(move |w, _ev| {
             // Event::Hide fires before Event::Close, hiding the Window and preventing the Close handler being called
             // eprintln!("_ev = {:?}, app_event = {:?}", _ev, app::event());
             let ev = app::event();
             match ev {
                 Event::Close => {
+                    // remember the window position and size
+                    let mut conf = CONFIG.write();
+                    conf.window_geometry = Some(WindowGeometry {
+                        x: w.x(),
+                        y: w.y(),
+                        w: w.w(),
+                        h: w.h(),
+                    });
+                    let _ = conf.update_config();
+                    drop(conf);
                     app.quit();
                     //std::process::exit(0);
                     true
@@ -137,7 +314,9 @@ impl MainForm {
         } else {
             "Choose Color Theme"
         };
-        let mut ptheme = Pack::new(0, 0, GW, 25, "");
+        let mut ptheme = Flex::new(0, 0, GW, 25, "");
+        ptheme.set_spacing(10);
+        ptheme.set_type(FlexType::Row);
         ptheme.end();
         let mut theme_button = MenuButton::new(0, 0, 0, 25, None).with_label(cur_theme);
         theme_button.add_choice(&THEMES.join("|"));
@@ -158,10 +337,52 @@ impl MainForm {
             rlock.store(false, Ordering::Release);
         });
         ptheme.add(&theme_button);
+
+        // UI scale, overrides the scale factor of the system
+        let ui_scale_label = |scale: Option<f32>| match scale {
+            Some(scale) => format!("UI scale: {:.0}%", scale * 100.0),
+            None => "UI scale: System".to_string(),
+        };
+        let mut ui_scale_button =
+            MenuButton::new(0, 0, 0, 25, None).with_label(&ui_scale_label(config.ui_scale));
+        ui_scale_button.set_tooltip("Scale all widgets and text, e.g. on a 4K display");
+        ui_scale_button.add_choice("System|100%|125%|150%|175%|200%");
+        ui_scale_button.set_callback(move |b| {
+            if b.value() < 0 {
+                return;
+            }
+            let scale = match b.value() {
+                0 => None,
+                i => Some(0.75 + i as f32 * 0.25),
+            };
+            Self::apply_ui_scale(&system_scales, scale);
+            b.set_label(&ui_scale_label(scale));
+            let mut conf = CONFIG.write();
+            conf.ui_scale = scale;
+            let _ = conf.update_config();
+        });
+        ptheme.add(&ui_scale_button);
+        ptheme.fixed(&ui_scale_button, 160);
+
+        // log window, the callbacks are set when the log pane exists
+        let mut log_window = CheckButton::new(0, 0, 0, 0, "Log window");
+        log_window.set_tooltip("Show the log in a separate window");
+        log_window.set(config.log_window);
+        ptheme.add(&log_window);
+        ptheme.fixed(&log_window, 110);
+        let mut show_log = Button::new(0, 0, 0, 0, "Show log");
+        show_log.set_tooltip("Show the log window");
+        if !config.log_window {
+            show_log.deactivate();
+        }
+        ptheme.add(&show_log);
+        ptheme.fixed(&show_log, 90);
         vpack.add(&ptheme);
 
         // network selection
         let mut pnw = Flex::new(0, 0, GW, 25, "");
+        pnw.set_spacing(10);
+        pnw.set_type(FlexType::Row);
         pnw.end();
         let cur_nw = {
             if config.last_network.is_none() {
@@ -206,11 +427,105 @@ impl MainForm {
             }
         });
         pnw.add(&choose_network_but);
+        // the streaming urls, to configure third-party players by hand
+        let mut urls_button = MenuButton::new(0, 0, 0, 25, None).with_label("Stream URLs");
+        urls_button.set_tooltip("Copy or open the streaming URLs with the current bit depth");
+        let sample_rate = wd.sample_rate.0;
+        urls_button.handle(move |b, ev| {
+            if ev == Event::Push {
+                b.clear();
+                let urls = Self::stream_urls(local_addr);
+                for (label, url) in &urls {
+                    let url = url.clone();
+                    b.add(
+                        &format!("Copy URL/{label}"),
+                        Shortcut::None,
+                        MenuFlag::Normal,
+                        move |_| {
+                            app::copy(&url);
+                            ui_log(&format!("Copied {url}"));
+                        },
+                    );
+                }
+                for (label, url) in &urls {
+                    let url = url.clone();
+                    b.add(
+                        &format!("Open in browser/{label}"),
+                        Shortcut::None,
+                        MenuFlag::Normal,
+                        move |_| Self::open_in_browser(&url),
+                    );
+                }
+                // the self-check of the audio streams, not of the listen page
+                for (label, url) in urls.iter().filter(|(_, url)| url.contains("/stream/")) {
+                    let (label, url) = (label.clone(), url.clone());
+                    b.add(
+                        &format!("Verify stream/{label}"),
+                        Shortcut::None,
+                        MenuFlag::Normal,
+                        move |_| Self::verify_own_stream(&label, &url, sample_rate),
+                    );
+                }
+                b.add(
+                    "Show all URLs",
+                    Shortcut::None,
+                    MenuFlag::Normal,
+                    move |_| Self::show_urls(&urls),
+                );
+            }
+            false
+        });
+        pnw.add(&urls_button);
+        pnw.fixed(&urls_button, 130);
         vpack.add(&pnw);
 
-        // setup audio source choice
+        // setup audio host and audio source choice
         let mut pas = Flex::new(0, 0, GW, 25, "");
+        pas.set_spacing(10);
+        pas.set_type(FlexType::Row);
         pas.end();
+        let cur_audio_host = format!(
+            "Audio Host: {}",
+            config.audio_host.as_deref().unwrap_or("All")
+        );
+        let mut choose_audio_host_but =
+            MenuButton::new(0, 0, 0, 25, None).with_label(&cur_audio_host);
+        // the device list of a host can differ a lot, e.g. ALSA and JACK on Linux
+        let audio_hosts = get_audio_hosts();
+        choose_audio_host_but.add_choice("All");
+        for name in &audio_hosts {
+            choose_audio_host_but.add_choice(&name.fw_slash_pipe_escape());
+        }
+        choose_audio_host_but.set_callback({
+            let config_changed = config_changed.clone();
+            move |b| {
+                let i = b.value();
+                if i < 0 {
+                    return;
+                }
+                // the first choice uses the devices of all audio hosts
+                let audio_host = if i > 0 {
+                    audio_hosts.get(i as usize - 1).cloned()
+                } else {
+                    None
+                };
+                ui_log(&format!(
+                    "*W*W*> Audio host changed to {}, restart required!!",
+                    audio_host.as_deref().unwrap_or("All")
+                ));
+                let mut conf = CONFIG.write();
+                conf.audio_host = audio_host;
+                let _ = conf.update_config();
+                b.set_label(&format!(
+                    "New Audio Host: {}",
+                    conf.audio_host.as_deref().unwrap_or("All")
+                ));
+                config_changed.set(true);
+                app::awake();
+            }
+        });
+        pas.add(&choose_audio_host_but);
+        pas.fixed(&choose_audio_host_but, 190);
         let cur_audio_src = format!("Audio Source: {}", config.sound_source.as_ref().unwrap());
         ui_log("Setup audio sources");
         let mut choose_audio_source_but =
@@ -219,9 +534,11 @@ impl MainForm {
             choose_audio_source_but.add_choice(&name.fw_slash_pipe_escape());
         }
         let rlock = AtomicBool::new(false);
-        let audio_sources_c = audio_sources.to_vec();
+        // the audio source names, updated when devices are added or removed
+        let sources = Rc::new(RefCell::new(audio_sources.to_vec()));
         choose_audio_source_but.set_callback({
             let config_changed = config_changed.clone();
+            let sources = sources.clone();
             move |b| {
                 if rlock.swap(true, Ordering::Acquire) {
                     return;
@@ -230,10 +547,11 @@ impl MainForm {
                 if i < 0 {
                     return;
                 }
-                if i as usize >= audio_sources_c.len() {
-                    i = (audio_sources_c.len() - 1) as i32;
+                let audio_sources = sources.borrow();
+                if i as usize >= audio_sources.len() {
+                    i = (audio_sources.len() - 1) as i32;
                 }
-                let name = &audio_sources_c[i as usize];
+                let name = &audio_sources[i as usize];
                 ui_log(&format!(
                     "*W*W*> Audio source changed to {name}, restart required!!"
                 ));
@@ -253,6 +571,315 @@ impl MainForm {
         pas.add(&choose_audio_source_but);
         vpack.add(&pas);
 
+        // setup playback monitor device choice and delay
+        let mut pmon = Flex::new(0, 0, GW, 25, "");
+        pmon.set_spacing(10);
+        pmon.set_type(FlexType::Row);
+        pmon.end();
+        let cur_monitor = format!(
+            "Playback Monitor: {}",
+            config.monitor_device.as_deref().unwrap_or("None")
+        );
+        let mut choose_monitor_but = MenuButton::new(0, 0, 0, 25, None).with_label(&cur_monitor);
+        choose_monitor_but.add_choice("None");
+        for name in audio_sources {
+            choose_monitor_but.add_choice(&name.fw_slash_pipe_escape());
+        }
+        let rlock = AtomicBool::new(false);
+        choose_monitor_but.set_callback({
+            let config_changed = config_changed.clone();
+            let sources = sources.clone();
+            move |b| {
+                if rlock.swap(true, Ordering::Acquire) {
+                    return;
+                }
+                let i = b.value();
+                if i < 0 {
+                    rlock.store(false, Ordering::Release);
+                    return;
+                }
+                // the first choice disables the monitor
+                let audio_sources = sources.borrow();
+                let name = if i > 0 {
+                    audio_sources.get(i as usize - 1)
+                } else {
+                    None
+                };
+                ui_log(&format!(
+                    "*W*W*> Playback monitor changed to {}, restart required!!",
+                    name.map_or("None", |n| n.as_str())
+                ));
+                let mut conf = CONFIG.write();
+                conf.monitor_device = name.cloned();
+                let _ = conf.update_config();
+                b.set_label(&format!(
+                    "New Playback Monitor: {}",
+                    conf.monitor_device.as_deref().unwrap_or("None")
+                ));
+                config_changed.set(true);
+                app::awake();
+                rlock.store(false, Ordering::Release);
+            }
+        });
+        pmon.add(&choose_monitor_but);
+        let label_delay = Frame::default().with_label("Delay (msec): ");
+        pmon.add(&label_delay);
+        pmon.fixed(&label_delay, 90);
+        let mut monitor_delay_ms = IntInput::new(0, 0, 50, 0, "");
+        monitor_delay_ms.set_maximum_size(5);
+        monitor_delay_ms.set_tooltip("Playback monitor delay in msec");
+        let d_config = config.monitor_delay_msec.unwrap_or_default();
+        monitor_delay_ms.set_value(&d_config.to_string());
+        monitor_delay_ms.set_callback({
+            let config_changed = config_changed.clone();
+            move |i| {
+                let mut d: i32 = i.value().parse().unwrap_or_default();
+                if d < 0 {
+                    i.set_value(&0i32.to_string());
+                    return;
+                }
+                if d > 5_000 {
+                    i.set_value(&5_000i32.to_string());
+                    d = 5_000;
+                }
+                let mut conf = CONFIG.write();
+                if Some(d as u32) != conf.monitor_delay_msec {
+                    conf.monitor_delay_msec = Some(d as u32);
+                    let _ = conf.update_config();
+                    config_changed.set(true);
+                }
+            }
+        });
+        pmon.add(&monitor_delay_ms);
+        pmon.fixed(&monitor_delay_ms, 60);
+        vpack.add(&pmon);
+
+        // setup the mix source choice and its gain, e.g. a microphone for commentary
+        let mut pmix = Flex::new(0, 0, GW, 25, "");
+        pmix.set_spacing(10);
+        pmix.set_type(FlexType::Row);
+        pmix.end();
+        let cur_mix = format!(
+            "Mix source: {}",
+            config.mix_source.as_deref().unwrap_or("None")
+        );
+        let mut choose_mix_but = MenuButton::new(0, 0, 0, 25, None).with_label(&cur_mix);
+        choose_mix_but.set_tooltip("A secondary audio source that is mixed into the stream");
+        choose_mix_but.add_choice("None");
+        for name in audio_sources {
+            choose_mix_but.add_choice(&name.fw_slash_pipe_escape());
+        }
+        let rlock = AtomicBool::new(false);
+        choose_mix_but.set_callback({
+            let config_changed = config_changed.clone();
+            let sources = sources.clone();
+            move |b| {
+                if rlock.swap(true, Ordering::Acquire) {
+                    return;
+                }
+                let i = b.value();
+                if i < 0 {
+                    rlock.store(false, Ordering::Release);
+                    return;
+                }
+                // the first choice disables the mixing
+                let audio_sources = sources.borrow();
+                let name = if i > 0 {
+                    audio_sources.get(i as usize - 1)
+                } else {
+                    None
+                };
+                ui_log(&format!(
+                    "*W*W*> Mix source changed to {}, restart required!!",
+                    name.map_or("None", |n| n.as_str())
+                ));
+                let mut conf = CONFIG.write();
+                conf.mix_source = name.cloned();
+                let _ = conf.update_config();
+                b.set_label(&format!(
+                    "New Mix source: {}",
+                    conf.mix_source.as_deref().unwrap_or("None")
+                ));
+                config_changed.set(true);
+                app::awake();
+                rlock.store(false, Ordering::Release);
+            }
+        });
+        pmix.add(&choose_mix_but);
+        let mix_gain = config.mix_gain_db.unwrap_or_default();
+        let mut label_mix_gain =
+            Frame::default().with_label(&format!("Mix gain: {mix_gain:+.1} dB"));
+        pmix.add(&label_mix_gain);
+        pmix.fixed(&label_mix_gain, 120);
+        let mut mix_gain_slider = HorNiceSlider::default();
+        mix_gain_slider.set_minimum(f64::from(MIN_MIX_GAIN_DB));
+        mix_gain_slider.set_maximum(f64::from(MAX_MIX_GAIN_DB));
+        mix_gain_slider.set_step(0.5, 1);
+        mix_gain_slider.set_value(f64::from(mix_gain));
+        mix_gain_slider.set_trigger(CallbackTrigger::Release);
+        mix_gain_slider.set_tooltip("Gain of the mix source in dB");
+        mix_gain_slider.set_callback(move |s| {
+            let g = s.value() as f32;
+            label_mix_gain.set_label(&format!("Mix gain: {g:+.1} dB"));
+            set_mix_gain(g);
+            let mut conf = CONFIG.write();
+            conf.mix_gain_db = Some(g);
+            let _ = conf.update_config();
+        });
+        pmix.add(&mix_gain_slider);
+        pmix.fixed(&mix_gain_slider, 160);
+        vpack.add(&pmix);
+
+        // balance and left/right trim sliders, applied immediately
+        let mut pbal = Flex::new(0, 0, GW, 25, "");
+        pbal.set_spacing(5);
+        pbal.set_type(FlexType::Row);
+        pbal.end();
+        let balance = config.balance.unwrap_or_default();
+        let mut label_balance = Frame::default().with_label(&format!("Balance: {balance}"));
+        pbal.add(&label_balance);
+        pbal.fixed(&label_balance, 85);
+        let mut balance_slider = HorNiceSlider::default();
+        balance_slider.set_minimum(f64::from(-BALANCE_RANGE));
+        balance_slider.set_maximum(f64::from(BALANCE_RANGE));
+        balance_slider.set_step(1.0, 1);
+        balance_slider.set_value(f64::from(balance));
+        balance_slider.set_trigger(CallbackTrigger::Release);
+        balance_slider.set_tooltip("Balance between -100 (left) and 100 (right)");
+        balance_slider.set_callback(move |s| {
+            let b = s.value() as i32;
+            label_balance.set_label(&format!("Balance: {b}"));
+            let mut conf = CONFIG.write();
+            if conf.balance.unwrap_or_default() != b {
+                conf.balance = Some(b);
+                let _ = conf.update_config();
+                drop(conf);
+                update_channel_gains();
+            }
+        });
+        pbal.add(&balance_slider);
+        for (channel, trim) in [("L", config.trim_left_db), ("R", config.trim_right_db)] {
+            let trim = trim.unwrap_or_default();
+            let mut label_trim =
+                Frame::default().with_label(&format!("{channel} trim: {trim:+.1} dB"));
+            pbal.add(&label_trim);
+            pbal.fixed(&label_trim, 105);
+            let mut trim_slider = HorNiceSlider::default();
+            trim_slider.set_minimum(f64::from(MIN_TRIM_DB));
+            trim_slider.set_maximum(f64::from(MAX_TRIM_DB));
+            trim_slider.set_step(0.5, 1);
+            trim_slider.set_value(f64::from(trim));
+            trim_slider.set_trigger(CallbackTrigger::Release);
+            trim_slider.set_tooltip(&format!("{channel} channel gain trim in dB"));
+            trim_slider.set_callback(move |s| {
+                let t = s.value() as f32;
+                label_trim.set_label(&format!("{channel} trim: {t:+.1} dB"));
+                let mut conf = CONFIG.write();
+                if channel == "L" {
+                    conf.trim_left_db = Some(t);
+                } else {
+                    conf.trim_right_db = Some(t);
+                }
+                let _ = conf.update_config();
+                drop(conf);
+                update_channel_gains();
+            });
+            pbal.add(&trim_slider);
+        }
+        vpack.add(&pbal);
+
+        // bit-perfect mode checkbox and indicator
+        let mut pbp = Flex::new(0, 0, GW, 25, "");
+        pbp.set_spacing(10);
+        pbp.set_type(FlexType::Row);
+        pbp.end();
+        let mut bit_perfect = CheckButton::new(0, 0, 0, 0, "Bit-perfect mode");
+        bit_perfect.set_tooltip("No balance, trim, channel mode or DSP chain is applied");
+        if config.bit_perfect {
+            bit_perfect.set(true);
+        }
+        bit_perfect.set_callback(move |b| {
+            let mut conf = CONFIG.write();
+            conf.bit_perfect = b.is_set();
+            let _ = conf.update_config();
+            drop(conf);
+            update_channel_gains();
+            update_dsp_chain();
+        });
+        pbp.add(&bit_perfect);
+        pbp.fixed(&bit_perfect, 140);
+        let mut bit_perfect_status = Frame::default().with_align(Align::Center);
+        bit_perfect_status.set_frame(FrameType::BorderBox);
+        pbp.add(&bit_perfect_status);
+        // show and reorder the DSP chain
+        let mut dsp_chain = Button::new(0, 0, 0, 0, "DSP chain");
+        dsp_chain.set_tooltip("Show the DSP chain, move its stages or enable/disable them");
+        dsp_chain.set_callback(|_| Self::show_dsp_chain());
+        pbp.add(&dsp_chain);
+        pbp.fixed(&dsp_chain, 100);
+        // loudness: switch the compressor of the DSP chain, without restarting the streams
+        let mut loudness_button = CheckButton::new(0, 0, 0, 0, "Loudness");
+        loudness_button.set_tooltip("Compress the dynamic range, for a noisy environment");
+        loudness_button.set(loudness(&config.dsp_chain));
+        loudness_button.set_callback(|b| {
+            let mut conf = CONFIG.write();
+            if set_loudness(&mut conf.dsp_chain, b.is_set()) {
+                let _ = conf.update_config();
+                drop(conf);
+                update_dsp_chain();
+            }
+        });
+        pbp.add(&loudness_button);
+        pbp.fixed(&loudness_button, 90);
+        // export the discovered renderers, e.g. for a support request
+        let mut export = Button::new(0, 0, 0, 0, "Export renderers");
+        export.set_tooltip("Save the discovered renderers as renderers.json in the config folder");
+        export.set_callback(|_| {
+            let path = CONFIG.read().config_dir().join(INVENTORY_FILE);
+            match export_renderers(&path) {
+                Ok(n) => ui_log(&format!("Exported {n} renderers to {}", path.display())),
+                Err(e) => ui_log(&format!(
+                    "*E*E*> Could not export the renderers to {}: {e}",
+                    path.display()
+                )),
+            }
+        });
+        pbp.add(&export);
+        pbp.fixed(&export, 140);
+        // force-disconnect a stuck streaming client, the choices are the current connections
+        let mut disconnect = MenuButton::new(0, 0, 0, 25, None).with_label("Disconnect client");
+        disconnect
+            .set_tooltip("Close a streaming connection, so that the renderer pulls a fresh stream");
+        disconnect.handle(|b, ev| {
+            if ev == Event::Push {
+                b.clear();
+                for client in client_details() {
+                    b.add_choice(
+                        &format!(
+                            "{} {} ({})",
+                            client.remote_addr, client.streaming_format, client.user_agent
+                        )
+                        .fw_slash_pipe_escape(),
+                    );
+                }
+                if b.size() == 0 {
+                    ui_log("No streaming clients to disconnect");
+                    return true;
+                }
+            }
+            false
+        });
+        disconnect.set_callback(|b| {
+            if let Some(choice) = b.choice() {
+                if let Some(remote_addr) = choice.split_whitespace().next() {
+                    disconnect_client(remote_addr);
+                }
+            }
+        });
+        pbp.add(&disconnect);
+        pbp.fixed(&disconnect, 150);
+        vpack.add(&pbp);
+
         // all other options
         let mut pconfig1 = Flex::new(0, 0, GW, 20, "");
         pconfig1.set_spacing(10);
@@ -399,6 +1026,9 @@ impl MainForm {
                 let newformat = StreamingFormat::from_str(&format).unwrap();
                 conf.streaming_format = Some(newformat);
                 let _ = conf.update_config();
+                drop(conf);
+                // switch the playing renderers to the new format
+                let _ = MSGCHANNEL.read().0.send(MessageType::FormatMessage);
                 let fmt = format!("FMT: {format}");
                 b.set_label(&fmt);
                 app::awake();
@@ -421,6 +1051,8 @@ impl MainForm {
                     conf.bits_per_sample = Some(16);
                 }
                 let _ = conf.update_config();
+                drop(conf);
+                let _ = MSGCHANNEL.read().0.send(MessageType::FormatMessage);
             }
         });
         pconfig2.add(&b24_bit);
@@ -467,6 +1099,23 @@ impl MainForm {
         pconfig2.make_resizable(true);
         vpack.add(&pconfig2);
 
+        // the estimated bitrate of the configured format, updated by show_bitrate()
+        let mut pbitrate = Flex::new(0, 0, GW, 20, "");
+        pbitrate.end();
+        pbitrate.set_type(FlexType::Row);
+        let mut bitrate = Frame::default().with_align(Align::Left | Align::Inside);
+        bitrate.set_frame(FrameType::FlatBox);
+        bitrate.set_tooltip("The estimated network bandwidth of the stream");
+        pbitrate.add(&bitrate);
+        // the resource usage, updated by show_resources() if it is monitored
+        let mut resources = Frame::default().with_align(Align::Right | Align::Inside);
+        resources.set_frame(FrameType::FlatBox);
+        resources.set_tooltip(
+            "The CPU usage of swyh-rs (and of the FLAC encoders) and its memory usage",
+        );
+        pbitrate.add(&resources);
+        vpack.add(&pbitrate);
+
         // streaming content length and chunking
         let mut pconfig3 = Flex::new(0, 0, GW, 20, "");
         pconfig3.set_spacing(10);
@@ -557,6 +1206,57 @@ impl MainForm {
         });
         pconfig3.add(&upfront_buffer_ms);
 
+        let label_preroll = Frame::default().with_label("Pre-roll (msec): ");
+        pconfig3.add(&label_preroll);
+        let mut preroll_ms = IntInput::new(0, 0, 50, 0, "");
+        preroll_ms.set_maximum_size(5);
+        let p_config = config.preroll_msec.unwrap_or_default();
+        preroll_ms.set_value(&p_config.to_string());
+        preroll_ms.set_callback({
+            let config_changed = config_changed.clone();
+            move |i| {
+                let mut p: i32 = i.value().parse().unwrap_or_default();
+                if p < 0 {
+                    i.set_value(&0i32.to_string());
+                    return;
+                }
+                if p > 5_000 {
+                    i.set_value(&5_000i32.to_string());
+                    p = 5_000;
+                }
+                let mut conf = CONFIG.write();
+                if Some(p as u32) != conf.preroll_msec {
+                    conf.preroll_msec = Some(p as u32);
+                    let _ = conf.update_config();
+                    config_changed.set(true);
+                }
+            }
+        });
+        pconfig3.add(&preroll_ms);
+
+        // the capture timeout before silence is sent, for new streams
+        let label_timeout = Frame::default().with_label("Capture timeout (msec): ");
+        pconfig3.add(&label_timeout);
+        let mut capture_timeout_ms = IntInput::new(0, 0, 50, 0, "");
+        capture_timeout_ms.set_maximum_size(5);
+        capture_timeout_ms
+            .set_tooltip("LPCM/WAV/RF64 silence starts after this time without audio");
+        let t_config = config.capture_timeout.unwrap_or(2000);
+        capture_timeout_ms.set_value(&t_config.to_string());
+        capture_timeout_ms.set_callback({
+            move |i| {
+                let t: i32 = i.value().parse().unwrap_or_default();
+                let t = t.clamp(100, 10_000);
+                i.set_value(&t.to_string());
+                let mut conf = CONFIG.write();
+                if Some(t as u32) != conf.capture_timeout {
+                    conf.capture_timeout = Some(t as u32);
+                    let _ = conf.update_config();
+                }
+            }
+        });
+        pconfig3.add(&capture_timeout_ms);
+
         //pconfig3.auto_layout();
         pconfig3.make_resizable(true);
         vpack.add(&pconfig3);
@@ -574,31 +1274,135 @@ impl MainForm {
         // rms monitor meters widgets
         let mut rms_mon_l = Progress::new(0, 0, 0, 0, "");
         let mut rms_mon_r = Progress::new(0, 0, 0, 0, "");
-        rms_mon_l.set_minimum(0.0);
-        rms_mon_l.set_maximum(16384.0);
-        rms_mon_l.set_value(0.0);
-        rms_mon_l.set_color(Color::White);
-        rms_mon_l.set_selection_color(Color::Green);
-        rms_mon_r.set_minimum(0.0);
-        rms_mon_r.set_maximum(16384.0);
-        rms_mon_r.set_value(0.0);
-        rms_mon_r.set_color(Color::White);
-        rms_mon_r.set_selection_color(Color::Green);
+        // the meters show the rms level in dBFS with a peak-hold marker
+        let meter_state = Arc::new(Mutex::new(MeterState {
+            scale: MeterSettings::from_config(config).scale,
+            ..Default::default()
+        }));
+        for (rms_mon, left) in [(&mut rms_mon_l, true), (&mut rms_mon_r, false)] {
+            rms_mon.set_minimum(0.0);
+            rms_mon.set_maximum(1.0);
+            rms_mon.set_value(0.0);
+            rms_mon.set_color(Color::White);
+            rms_mon.set_selection_color(Color::Green);
+            rms_mon.set_tooltip("RMS level and peak hold");
+            rms_mon.draw({
+                let meter_state = meter_state.clone();
+                move |p| {
+                    let state = *meter_state.lock();
+                    let peak = if left { state.peak_l } else { state.peak_r };
+                    if peak.level > 0.0 {
+                        let x = p.x()
+                            + (state.scale.fraction(peak.level) * f64::from(p.w() - 2)) as i32;
+                        draw::draw_rect_fill(x, p.y(), 2, p.h(), Color::DarkRed);
+                    }
+                }
+            });
+        }
+        // the clip indicator, click to reset
+        let mut clip_led = Button::new(0, 0, 0, 0, "Clip");
+        clip_led.set_tooltip("Clipped samples, click to reset");
+        let clip_color = clip_led.color();
+        clip_led.set_callback({
+            let meter_state = meter_state.clone();
+            move |b| {
+                meter_state.lock().clips = 0;
+                b.set_color(clip_color);
+                b.set_label("Clip");
+                b.redraw();
+            }
+        });
         // rms checkbox callback
         show_rms.set_callback({
             let mut rms_mon_l = rms_mon_l.clone();
             let mut rms_mon_r = rms_mon_r.clone();
+            let meter_state = meter_state.clone();
             move |b| {
                 rms_mon_l.set_value(0.0);
                 rms_mon_r.set_value(0.0);
+                // reset the peak hold, but keep the clip count
+                let mut state = meter_state.lock();
+                state.peak_l = PeakHold::default();
+                state.peak_r = PeakHold::default();
+                drop(state);
                 let run_rms = b.is_set();
-                RUN_RMS_MONITOR.store(run_rms, Ordering::Release);
                 let mut conf = CONFIG.write();
+                // MQTT keeps getting the RMS levels
+                RUN_RMS_MONITOR.store(run_rms || conf.mqtt.is_some(), Ordering::Release);
                 conf.monitor_rms = run_rms;
                 let _ = conf.update_config();
             }
         });
         pconfig4.add(&show_rms);
+        // the spectrum analyzer of the RMS monitor
+        let spectrum_levels = Arc::new(Mutex::new(vec![0.0f32; SPECTRUM_BANDS]));
+        let mut pspectrum = Flex::new(0, 0, GW, 40, "");
+        pspectrum.end();
+        let mut spectrum = Frame::default();
+        spectrum.set_frame(FrameType::BorderBox);
+        spectrum.set_color(Color::White);
+        spectrum.draw({
+            let spectrum_levels = spectrum_levels.clone();
+            move |f| {
+                let levels = spectrum_levels.lock();
+                let bar_w = (f.w() - 4) / SPECTRUM_BANDS as i32;
+                let max_h = f.h() - 4;
+                for (i, level) in levels.iter().enumerate() {
+                    let h = (level * max_h as f32) as i32;
+                    draw::draw_rect_fill(
+                        f.x() + 2 + i as i32 * bar_w,
+                        f.y() + 2 + max_h - h,
+                        bar_w - 1,
+                        h,
+                        Color::Green,
+                    );
+                }
+            }
+        });
+        pspectrum.add(&spectrum);
+        let mut show_spectrum = CheckButton::new(0, 0, 0, 0, "Spectrum");
+        show_spectrum.set_tooltip("Show a spectrum analyzer with the RMS monitor");
+        if config.monitor_spectrum {
+            show_spectrum.set(true);
+            RUN_SPECTRUM_ANALYZER.store(true, Ordering::Release);
+        } else {
+            pspectrum.hide();
+        }
+        show_spectrum.set_callback({
+            let mut pspectrum = pspectrum.clone();
+            let spectrum_levels = spectrum_levels.clone();
+            let mut vpack = vpack.clone();
+            move |b| {
+                let run_spectrum = b.is_set();
+                spectrum_levels.lock().fill(0.0);
+                if run_spectrum {
+                    pspectrum.show();
+                } else {
+                    pspectrum.hide();
+                }
+                vpack.redraw();
+                RUN_SPECTRUM_ANALYZER.store(run_spectrum, Ordering::Release);
+                let mut conf = CONFIG.write();
+                conf.monitor_spectrum = run_spectrum;
+                let _ = conf.update_config();
+            }
+        });
+        pconfig4.add(&show_spectrum);
+        // only capture audio while a renderer is streaming
+        let mut capture_on_demand = CheckButton::new(0, 0, 0, 0, "Capture on demand");
+        if config.capture_on_demand {
+            capture_on_demand.set(true);
+        }
+        capture_on_demand.set_callback({
+            let config_changed = config_changed.clone();
+            move |b| {
+                let mut conf = CONFIG.write();
+                conf.capture_on_demand = b.is_set();
+                let _ = conf.update_config();
+                config_changed.set(true);
+            }
+        });
+        pconfig4.add(&capture_on_demand);
         // vertical pack for the RMS meters
         let mut pconfig3_v = Flex::new(0, 0, GW, 16, "");
         pconfig3_v.set_spacing(4);
@@ -609,20 +1413,150 @@ impl MainForm {
         //pconfig3_v.auto_layout();
         pconfig3_v.make_resizable(true);
         pconfig4.add(&pconfig3_v);
+        pconfig4.add(&clip_led);
+        pconfig4.fixed(&clip_led, 50);
 
         //pconfig4.auto_layout();
         pconfig4.make_resizable(true);
         vpack.add(&pconfig4);
 
+        // RMS meter ballistics and scale
+        let mut pmeter = Flex::new(0, 0, GW, 20, "");
+        pmeter.set_spacing(10);
+        pmeter.set_type(FlexType::Row);
+        pmeter.end();
+        let meter_settings = MeterSettings::from_config(config);
+        let label_window = Frame::default().with_label("RMS window (msec): ");
+        pmeter.add(&label_window);
+        let mut rms_window_ms = IntInput::new(0, 0, 50, 0, "");
+        rms_window_ms.set_maximum_size(4);
+        rms_window_ms.set_tooltip("RMS window length in msec");
+        rms_window_ms.set_value(&meter_settings.window_msec.to_string());
+        rms_window_ms.set_callback(move |i| {
+            let w: u32 = i.value().parse().unwrap_or(DEFAULT_RMS_WINDOW_MSEC);
+            let mut conf = CONFIG.write();
+            conf.rms_window_msec = Some(w);
+            let w = MeterSettings::from_config(&conf).window_msec;
+            conf.rms_window_msec = Some(w);
+            let _ = conf.update_config();
+            i.set_value(&w.to_string());
+        });
+        pmeter.add(&rms_window_ms);
+        let label_refresh = Frame::default().with_label("Refresh (Hz): ");
+        pmeter.add(&label_refresh);
+        let mut meter_refresh = IntInput::new(0, 0, 50, 0, "");
+        meter_refresh.set_maximum_size(2);
+        meter_refresh.set_tooltip("RMS meter refresh rate in Hz");
+        meter_refresh.set_value(&meter_settings.refresh_hz.to_string());
+        meter_refresh.set_callback(move |i| {
+            let hz: u32 = i.value().parse().unwrap_or(DEFAULT_METER_REFRESH_HZ);
+            let mut conf = CONFIG.write();
+            conf.meter_refresh_hz = Some(hz);
+            let hz = MeterSettings::from_config(&conf).refresh_hz;
+            conf.meter_refresh_hz = Some(hz);
+            let _ = conf.update_config();
+            i.set_value(&hz.to_string());
+        });
+        pmeter.add(&meter_refresh);
+        let meter_scales = ["Linear", "dBFS -40", "dBFS -60", "dBFS -80", "dBFS -96"];
+        let cur_scale = if meter_settings.scale.linear {
+            "Linear".to_string()
+        } else {
+            format!("dBFS {:.0}", meter_settings.scale.floor_db)
+        };
+        let mut meter_scale = MenuButton::default().with_label(&format!("Scale: {cur_scale}"));
+        for scale in &meter_scales {
+            meter_scale.add_choice(scale);
+        }
+        meter_scale.set_callback(move |b| {
+            let i = b.value();
+            if i < 0 {
+                return;
+            }
+            let scale = meter_scales[i as usize];
+            let mut conf = CONFIG.write();
+            if let Some(floor) = scale.strip_prefix("dBFS ") {
+                conf.meter_linear = false;
+                conf.meter_floor_db = floor.parse().ok();
+            } else {
+                conf.meter_linear = true;
+            }
+            let _ = conf.update_config();
+            b.set_label(&format!("Scale: {scale}"));
+        });
+        pmeter.add(&meter_scale);
+        pmeter.make_resizable(true);
+        vpack.add(&pmeter);
+
+        vpack.add(&pspectrum);
+
         // show renderer buttons title with our local ip address
         let mut pbuttons = Flex::new(0, 0, GW, 25, "");
+        pbuttons.set_spacing(10);
+        pbuttons.set_type(FlexType::Row);
         pbuttons.end();
         let mut frame = Frame::new(0, 0, FW, 25, "").with_align(Align::Center);
         frame.set_frame(FrameType::BorderBox);
-        frame.set_label(&format!("UPNP rendering devices on network {local_addr}"));
+        // the HTTP port can differ from the configured port if that was in use
+        let port = server_port();
+        frame.set_label(&format!(
+            "UPNP rendering devices on network {local_addr}, HTTP port {port}"
+        ));
+        if Some(port) != config.server_port {
+            frame.set_label_color(Color::Red);
+        }
         frame.set_color(title_color);
         pbuttons.add(&frame);
+        // recall a session preset, or save the playing renderers as a session preset
+        let mut sessions_button = MenuButton::new(0, 0, 0, 25, None).with_label("Sessions");
+        sessions_button
+            .set_tooltip("Start the renderers of a session with their volume and format");
+        sessions_button.handle(move |b, ev| {
+            if ev == Event::Push {
+                b.clear();
+                let names: Vec<String> = CONFIG
+                    .read()
+                    .sessions
+                    .iter()
+                    .map(|s| s.name.clone())
+                    .collect();
+                for name in names {
+                    b.add(
+                        &format!("Recall/{}", name.fw_slash_pipe_escape()),
+                        Shortcut::None,
+                        MenuFlag::Normal,
+                        move |_| {
+                            let _ = MSGCHANNEL
+                                .read()
+                                .0
+                                .send(MessageType::RecallSessionMessage(name.clone()));
+                        },
+                    );
+                }
+                b.add(
+                    "Save playing renderers...",
+                    Shortcut::None,
+                    MenuFlag::Normal,
+                    |_| {
+                        if let Some(name) = dialog::input_default("Session name", "") {
+                            let name = name.trim().to_string();
+                            if !name.is_empty() {
+                                let _ = MSGCHANNEL
+                                    .read()
+                                    .0
+                                    .send(MessageType::SaveSessionMessage(name));
+                            }
+                        }
+                    },
+                );
+            }
+            false
+        });
+        pbuttons.add(&sessions_button);
+        pbuttons.fixed(&sessions_button, 130);
         vpack.add(&pbuttons);
+        // the renderer buttons are inserted below the title
+        let btn_index = vpack.children();
 
         // setup feedback textbox at the bottom
         let mut pfeedback = Flex::new(0, 0, GW, 156, "");
@@ -630,14 +1564,70 @@ impl MainForm {
         let buf = TextBuffer::default();
         let mut tb = TextDisplay::new(0, 0, 0, 150, "").with_align(Align::Left);
         tb.set_buffer(Some(buf));
+        tb.set_tooltip("Log messages");
         pfeedback.add(&tb);
         pfeedback.resizable(&tb);
         vpack.add(&pfeedback);
         vpack.resizable(&pfeedback);
 
+        // the log can be popped out into its own resizable window
+        let mut log_wind = DoubleWindow::default()
+            .with_size(WW, 300)
+            .with_label("swyh-rs log");
+        log_wind.end();
+        log_wind.make_resizable(true);
+        log_wind.size_range(WW / 2, 100, 0, 0);
+        if let Ok(icon) = SvgImage::from_data(icon_bytes) {
+            log_wind.set_icon(Some(icon));
+        }
+        let mut log_pane = LogPane {
+            wind: wind.clone(),
+            vpack: vpack.clone(),
+            pfeedback,
+            log_wind,
+            tb: tb.clone(),
+            min_w: WW,
+            min_h: WH * 2 / 3,
+        };
+        if config.log_window {
+            log_pane.pop_out(true, !config.log_window_hidden);
+        }
+        log_window.set_callback({
+            let mut log_pane = log_pane.clone();
+            let mut show_log = show_log.clone();
+            move |b| {
+                let separate = b.is_set();
+                log_pane.pop_out(separate, true);
+                if separate {
+                    show_log.activate();
+                } else {
+                    show_log.deactivate();
+                }
+                let mut conf = CONFIG.write();
+                conf.log_window = separate;
+                let _ = conf.update_config();
+            }
+        });
+        show_log.set_callback({
+            let mut log_pane = log_pane.clone();
+            move |_| log_pane.pop_out(true, true)
+        });
+
         // create a hashmap for a button for each discovered renderer
         let buttons: HashMap<String, LightButton> = HashMap::new();
 
+        // restore the window position and size if it is still on one of the screens
+        if let Some(g) = config.window_geometry {
+            let min_h = if log_pane.is_separate() {
+                WH * 2 / 3 - LogPane::HEIGHT
+            } else {
+                WH * 2 / 3
+            };
+            if g.w >= WW && g.h >= min_h && Self::on_screen(&g) {
+                wind.resize(g.x, g.y, g.w, g.h);
+            }
+        }
+
         MainForm {
             wind,
             vpack,
@@ -648,12 +1638,28 @@ impl MainForm {
             fmt_choice: ss_choice,
             b24_bit,
             show_rms,
-            rms_mon_l,
-            rms_mon_r,
+            rms_monitor: RmsMonitorWidgets {
+                rms_mon_l,
+                rms_mon_r,
+                clip_led,
+                meter_state,
+                spectrum,
+                spectrum_levels,
+            },
             choose_audio_source_but,
+            choose_monitor_but,
+            choose_mix_but,
+            audio_sources: sources,
+            bit_perfect_status,
+            loudness: loudness_button,
+            bitrate,
+            resources,
             tb,
             buttons,
-            btn_index: 10,
+            badges: Rc::new(RefCell::new(HashMap::new())),
+            rows: Rc::new(RefCell::new(HashMap::new())),
+            volume_sliders: Rc::new(RefCell::new(HashMap::new())),
+            btn_index,
             bwidth: frame.width(),
             bheight: frame.height(),
             wd: *wd,
@@ -661,6 +1667,279 @@ impl MainForm {
         }
     }
 
+    /// replace the audio source and playback monitor choices after devices were added or removed
+    pub fn update_audio_sources(&mut self, audio_sources: Vec<String>) {
+        self.choose_audio_source_but.clear();
+        self.choose_monitor_but.clear();
+        self.choose_monitor_but.add_choice("None");
+        self.choose_mix_but.clear();
+        self.choose_mix_but.add_choice("None");
+        for name in &audio_sources {
+            let name = name.fw_slash_pipe_escape();
+            self.choose_audio_source_but.add_choice(&name);
+            self.choose_monitor_but.add_choice(&name);
+            self.choose_mix_but.add_choice(&name);
+        }
+        *self.audio_sources.borrow_mut() = audio_sources;
+    }
+
+    /// show if the stream with the configured settings is bit-perfect or which conversions occur
+    pub fn show_bit_perfect(&mut self) {
+        let (bps, channel_mode) = {
+            let conf = CONFIG.read();
+            (
+                conf.bits_per_sample.unwrap_or(16),
+                conf.channel_mode.unwrap_or_default(),
+            )
+        };
+        let (label, color) = if capture_format().is_none() {
+            (
+                "Bit-perfect: no audio capture yet".to_string(),
+                Color::Light2,
+            )
+        } else {
+            let conversions = stream_conversions(bps, enforce_channel_mode(channel_mode));
+            if conversions.is_empty() {
+                (
+                    "Bit-perfect delivery".to_string(),
+                    Color::from_u32(0x0090_ee90),
+                )
+            } else {
+                (
+                    format!("Converting: {}", conversions.join(", ")),
+                    Color::from_u32(0x00ff_d580),
+                )
+            }
+        };
+        if self.bit_perfect_status.label() != label {
+            self.bit_perfect_status.set_label(&label);
+            self.bit_perfect_status.set_color(color);
+            self.bit_perfect_status.redraw();
+        }
+    }
+
+    /// show the estimated bitrate of the configured format, and warn if it may be too much for WiFi
+    pub fn show_bitrate(&mut self) {
+        let (format, bps) = {
+            let conf = CONFIG.read();
+            (
+                conf.streaming_format.unwrap_or(Flac),
+                conf.bits_per_sample.unwrap_or(16),
+            )
+        };
+        let channels = stream_channels(self.wd.channels);
+        let (advice, too_much) = bitrate_advice(format, bps, self.wd.sample_rate.0, channels);
+        let label = if too_much {
+            format!("Bitrate: {advice} (may be too much for some WiFi renderers)")
+        } else {
+            format!("Bitrate: {advice}")
+        };
+        if self.bitrate.label() != label {
+            self.bitrate.set_label(&label);
+            self.bitrate.set_label_color(if too_much {
+                Color::from_u32(0x00c0_6000)
+            } else {
+                Color::Foreground
+            });
+            self.bitrate.redraw();
+        }
+    }
+
+    /// show the last measured resource usage, and the throttled FLAC compression level
+    pub fn show_resources(&mut self) {
+        let Some(usage) = resource_usage() else {
+            return;
+        };
+        let configured = CONFIG
+            .read()
+            .flac_compression_level
+            .unwrap_or_default()
+            .min(MAX_COMPRESSION_LEVEL);
+        let throttled = usage.compression_level < configured;
+        let label = if throttled {
+            format!(
+                "{}, FLAC level {} (throttled)",
+                usage.summary(),
+                usage.compression_level
+            )
+        } else {
+            usage.summary()
+        };
+        if self.resources.label() != label {
+            self.resources.set_label(&label);
+            self.resources.set_label_color(if throttled {
+                Color::from_u32(0x00c0_6000)
+            } else {
+                Color::Foreground
+            });
+            self.resources.redraw();
+        }
+    }
+
+    /// a renderer pulled the stream in this format, for the badges next to its button
+    pub fn show_stream_started(&mut self, location: &str, format: StreamingFormat, bits: u16) {
+        if let Some(rb) = self.badges.borrow_mut().get_mut(location) {
+            rb.badge.started(format, bits, Instant::now());
+        }
+    }
+
+    /// the stream of a renderer ended, for the badges next to its button
+    pub fn show_stream_ended(&mut self, location: &str) {
+        if let Some(rb) = self.badges.borrow_mut().get_mut(location) {
+            rb.badge.ended();
+        }
+    }
+
+    /// a renderer stopped responding or responds again, for the badges next to its button
+    pub fn show_offline(&mut self, location: &str, offline: bool) {
+        if let Some(rb) = self.badges.borrow_mut().get_mut(location) {
+            rb.badge.set_offline(offline);
+        }
+    }
+
+    /// update the format, connection state, elapsed time and silence badges of the renderers
+    pub fn show_badges(&mut self) {
+        let now = Instant::now();
+        // the silence periods of the streaming clients by remote ip
+        let mut silence: HashMap<String, SilenceCount> = HashMap::new();
+        for chs in CLIENTS.read().values() {
+            let count = silence.entry(chs.remote_ip.clone()).or_default();
+            *count = *count + chs.stats.silence();
+        }
+        for (location, rb) in self.badges.borrow_mut().iter_mut() {
+            let playing = self.buttons.get(location).is_some_and(|b| b.is_set());
+            let format = rb.badge.format_label();
+            if rb.format.label() != format {
+                rb.format.set_label(&format);
+                rb.format.redraw();
+            }
+            let state = rb.badge.state(playing);
+            let tooltip = format!("Connection state: {state}");
+            if rb.state.tooltip().as_deref() != Some(tooltip.as_str()) {
+                rb.state.set_tooltip(&tooltip);
+                rb.state.set_color(Color::from_u32(state.color()));
+                rb.state.redraw();
+            }
+            let elapsed = rb.badge.elapsed_label(now);
+            if rb.elapsed.label() != elapsed {
+                rb.elapsed.set_label(&elapsed);
+                rb.elapsed.redraw();
+            }
+            rb.badge.set_silence(silence.get(&rb.remote_ip).copied());
+            let label = rb.badge.silence_label();
+            if rb.silence.label() != label {
+                rb.silence.set_label(&label);
+                rb.silence.set_tooltip(&format!(
+                    "{}: no audio was captured and silence was sent to keep the stream alive. \
+                    Many silence periods point at capture starvation, none while the music \
+                    stops points at the renderer",
+                    rb.badge.silence()
+                ));
+                rb.silence.redraw();
+            }
+        }
+    }
+
+    /// show the stream parameters a renderer negotiated in the tooltip of its button,
+    /// with the configured format and bit depth if they differ
+    pub fn show_negotiated_stream(&mut self, renderer: &Renderer) {
+        let Some(negotiated) = renderer.negotiated else {
+            return;
+        };
+        let Some(button) = self.buttons.get_mut(&renderer.location) else {
+            return;
+        };
+        let (format, bits) = {
+            let conf = CONFIG.read();
+            (
+                conf.streaming_format.unwrap_or(Flac),
+                conf.bits_per_sample.unwrap_or(16),
+            )
+        };
+        // the renderer's own format and bit depth take precedence
+        let (format, bits) = (
+            renderer.streaming_format(format),
+            renderer.bits_per_sample(bits),
+        );
+        let tooltip = button.tooltip().unwrap_or_default();
+        let help = tooltip.split("\n\n").next().unwrap_or_default();
+        let mut stream = format!("Streaming {negotiated}");
+        if negotiated.format != format || negotiated.bits_per_sample != bits {
+            stream += &format!("\n(configured: {format} {bits} bit)");
+        }
+        button.set_tooltip(&format!("{help}\n\n{stream}"));
+    }
+
+    /// show the configured streaming format and bit depth after they were changed
+    /// outside the format buttons
+    pub fn show_streaming_format(&mut self) {
+        let conf = CONFIG.read();
+        let format = conf.streaming_format.unwrap_or(Flac);
+        self.fmt_choice.set_label(&format!("FMT: {format}"));
+        self.b24_bit.set(conf.bits_per_sample.unwrap_or(16) == 24);
+    }
+
+    /// show the loudness option, e.g. after recalling a session
+    pub fn show_loudness(&mut self) {
+        self.loudness.set(loudness(&CONFIG.read().dsp_chain));
+    }
+
+    /// show the volume of a renderer that was changed outside swyh-rs on its slider
+    pub fn show_volume(&mut self, location: &str, vol: i32) {
+        if let Some(vs) = self.volume_sliders.borrow_mut().get_mut(location) {
+            vs.volume = vol.clamp(0, 100);
+            let vol = f64::from(vs.volume);
+            if (vs.slider.value() - vol).abs() >= 1.0 {
+                vs.slider.set_value(vol);
+                vs.slider.redraw();
+            }
+        }
+    }
+
+    /// move the sliders of the other renderers in the locked volume groups of a renderer
+    /// along with its slider, keeping their offsets
+    fn follow_volume_locks(sliders: &VolumeSliders, location: &str, vol: i32) {
+        let Some((dev_name, old)) = sliders
+            .borrow_mut()
+            .get_mut(location)
+            .map(|vs| (vs.dev_name.clone(), std::mem::replace(&mut vs.volume, vol)))
+        else {
+            return;
+        };
+        // the followers only remember their new volume
+        if FOLLOWING_VOLUME_LOCK.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let volumes: Vec<(String, i32)> = sliders
+            .borrow()
+            .values()
+            .map(|vs| (vs.dev_name.clone(), vs.volume))
+            .collect();
+        let followers = locked_volumes(&CONFIG.read().volume_locks, &dev_name, old, vol, &volumes);
+        for (name, vol) in followers {
+            let slider = sliders
+                .borrow()
+                .values()
+                .find(|vs| vs.dev_name == name)
+                .map(|vs| vs.slider.clone());
+            if let Some(mut sl) = slider {
+                sl.set_value(f64::from(vol));
+                sl.redraw();
+                sl.do_callback();
+            }
+        }
+        FOLLOWING_VOLUME_LOCK.store(false, Ordering::Relaxed);
+    }
+
+    /// show the elapsed time reported by a renderer next to its button, None falls back
+    /// to the time since it pulled the stream
+    pub fn show_elapsed(&mut self, location: &str, elapsed: Option<u64>) {
+        if let Some(rb) = self.badges.borrow_mut().get_mut(location) {
+            rb.badge.set_position(elapsed);
+        }
+        self.show_badges();
+    }
+
     pub fn add_log_msg(&mut self, msg: &str) {
         if let Some(mut textbuffer) = self.tb.buffer() {
             textbuffer.append(msg);
@@ -672,6 +1951,47 @@ impl MainForm {
         }
     }
 
+    /// run a play or stop action of a renderer on a worker thread, so that waiting for the
+    /// fade out or for a receiver to power on doesn't freeze the GUI, the actions of a renderer
+    /// run one after the other
+    fn renderer_action(renderer: &Renderer, action: impl FnOnce(&Renderer) + Send + 'static) {
+        let order = RENDERER_ACTIONS
+            .lock()
+            .entry(renderer.location.clone())
+            .or_default()
+            .clone();
+        let renderer = renderer.clone();
+        let _ = std::thread::Builder::new()
+            .name("renderer_action".into())
+            .spawn(move || {
+                let _in_order = order.lock();
+                action(&renderer);
+            });
+    }
+
+    /// start playing on a renderer in the background, the outcome is shown on its badge
+    pub fn start_playing(
+        renderer: &Renderer,
+        local_addr: IpAddr,
+        server_port: u16,
+        streaminfo: StreamInfo,
+    ) {
+        Self::renderer_action(renderer, move |r| {
+            let result = r.play(&local_addr, server_port, &ui_log, streaminfo);
+            let _ = MSGCHANNEL.read().0.send(MessageType::AvailabilityMessage(
+                r.location.clone(),
+                result.is_ok(),
+            ));
+            app::awake();
+            record_play_result(r, result);
+        });
+    }
+
+    /// fade out and stop playing on a renderer in the background
+    pub fn stop_playing(renderer: &Renderer) {
+        Self::renderer_action(renderer, |r| r.stop_play(&ui_log));
+    }
+
     pub fn add_renderer_button(&mut self, new_renderer: &Renderer) {
         // check if the renderer responded to GetVolume and make room for the slider if yes
         let (show_vol_slider, pbwidth, slwidth) = if new_renderer.volume >= 0 {
@@ -679,14 +1999,18 @@ impl MainForm {
         } else {
             (false, self.bwidth, 0)
         };
+        let (label, hidden) = CONFIG
+            .read()
+            .renderers
+            .get(&new_renderer.dev_name)
+            .map_or((None, false), |s| {
+                (s.label.clone(), s.hidden.unwrap_or(false))
+            });
         let mut pbut = LightButton::default() // create the button
             .with_size(pbwidth, self.bheight)
             .with_pos(0, 0)
             .with_align(Align::Center | Align::Clip)
-            .with_label(&format!(
-                "{} {}",
-                new_renderer.dev_model, new_renderer.dev_name
-            ));
+            .with_label(&label.unwrap_or_else(|| new_renderer.button_label()));
         pbut.set_callback({
             let newr_c = new_renderer.clone();
             let bi = self.buttons.len();
@@ -703,7 +2027,7 @@ impl MainForm {
                 if b.is_on() {
                     {
                         let mut conf = CONFIG.write();
-                        conf.last_renderer = Some(b.label());
+                        conf.last_renderer = Some(newr_c.button_label());
                         let _ = conf.update_config();
                     }
                     let config = CONFIG.read().clone();
@@ -712,15 +2036,56 @@ impl MainForm {
                         bits_per_sample: config.bits_per_sample.unwrap_or(16),
                         streaming_format: config.streaming_format.unwrap_or(Flac),
                     };
-                    let _ = newr_c.play(
-                        &local_addr,
-                        config.server_port.unwrap_or_default(),
-                        &ui_log,
-                        streaminfo,
-                    );
+                    Self::start_playing(&newr_c, local_addr, server_port(), streaminfo);
                 } else {
-                    newr_c.stop_play(&ui_log);
+                    Self::stop_playing(&newr_c);
+                }
+            }
+        });
+        // Alt+1 to Alt+9 start or stop the first nine renderers from the keyboard
+        let accelerator = u32::try_from(self.buttons.len() + 1)
+            .ok()
+            .and_then(|n| char::from_digit(n, 10));
+        let gestures = gestures_help(&CONFIG.read().button_gestures);
+        if let Some(digit) = accelerator {
+            pbut.set_shortcut(Shortcut::Alt | digit);
+            pbut.set_tooltip(&format!("Alt+{digit} to start or stop, {gestures}"));
+        } else {
+            pbut.set_tooltip(&format!("Click to start or stop, {gestures}"));
+        }
+        // the configured mouse gestures on the button, the context menu key for the menu
+        pbut.handle({
+            let ctx = ButtonContext {
+                renderer: new_renderer.clone(),
+                local_addr: self.local_addr,
+                sample_rate: self.wd.sample_rate.0,
+                rows: self.rows.clone(),
+                volume_sliders: self.volume_sliders.clone(),
+                muted: Rc::new(Cell::new(false)),
+            };
+            move |b, ev| {
+                let gesture = match ev {
+                    Event::KeyDown if is_context_menu_key() => {
+                        Self::renderer_menu(&ctx, b);
+                        return true;
+                    }
+                    Event::Push | Event::Released => match app::event_mouse_button() {
+                        app::MouseButton::Right => Gesture::RightClick,
+                        app::MouseButton::Middle => Gesture::MiddleClick,
+                        app::MouseButton::Left if app::event_clicks() => Gesture::DoubleClick,
+                        _ => return false,
+                    },
+                    _ => return false,
+                };
+                let action = gesture_action(&CONFIG.read().button_gestures, gesture);
+                // without an action a double click is two clicks
+                if action == ButtonAction::None && gesture == Gesture::DoubleClick {
+                    return false;
+                }
+                if ev == Event::Push {
+                    Self::button_action(&ctx, b, action);
                 }
+                true
             }
         });
         // the pack for the new button
@@ -730,6 +2095,56 @@ impl MainForm {
         pbutton.end();
         // add the renderer button to the window
         pbutton.add(&pbut);
+        if hidden {
+            pbutton.hide();
+            ui_log(&format!(
+                "Renderer {} is hidden, \"Show hidden renderers...\" in the renderer menu shows it",
+                new_renderer.dev_name
+            ));
+        }
+        self.rows
+            .borrow_mut()
+            .insert(new_renderer.dev_name.clone(), pbutton.clone());
+        // with badges showing the streaming format and bit depth used for this renderer,
+        // the connection state, the elapsed time and the silence periods
+        let (format, bits) = {
+            let conf = CONFIG.read();
+            (
+                conf.streaming_format.unwrap_or(Flac),
+                conf.bits_per_sample.unwrap_or(16),
+            )
+        };
+        let badge = RendererBadge::new(
+            new_renderer.streaming_format(format),
+            new_renderer.bits_per_sample(bits),
+        );
+        let mut format = Frame::default()
+            .with_label(&badge.format_label())
+            .with_align(Align::Center | Align::Clip);
+        format.set_frame(FrameType::RoundedBox);
+        format.set_label_size(11);
+        format.set_tooltip("The streaming format and bit depth for this renderer");
+        pbutton.add(&format);
+        pbutton.fixed(&format, 60);
+        let mut state = Frame::default();
+        state.set_frame(FrameType::OvalBox);
+        state.set_color(Color::from_u32(badge.state(false).color()));
+        state.set_tooltip(&format!("Connection state: {}", badge.state(false)));
+        pbutton.add(&state);
+        pbutton.fixed(&state, 12);
+        let mut elapsed = Frame::default().with_align(Align::Center | Align::Clip);
+        elapsed.set_label_size(11);
+        elapsed.set_tooltip(
+            "The elapsed playing time reported by the renderer, or else the time since it \
+            pulled the stream",
+        );
+        pbutton.add(&elapsed);
+        pbutton.fixed(&elapsed, 55);
+        let mut silence = Frame::default().with_align(Align::Center | Align::Clip);
+        silence.set_label_size(11);
+        silence.set_label_color(Color::from_u32(0x00c0_6000));
+        pbutton.add(&silence);
+        pbutton.fixed(&silence, 40);
         // Only if GetVolume worked: show the volume slider
         if show_vol_slider {
             let mut sl = HorNiceSlider::default()
@@ -742,21 +2157,45 @@ impl MainForm {
             sl.set_color(Color::XtermWhite);
             sl.set_value(new_renderer.volume.into());
             sl.set_trigger(fltk::enums::CallbackTrigger::Release);
+            sl.set_tooltip(&format!("Volume of {}", new_renderer.dev_name));
             // slider callback
             sl.set_callback({
                 let mut newr_c = new_renderer.clone();
+                let volume_sliders = self.volume_sliders.clone();
                 move |s| {
                     let vol: i32 = s.value() as i32; // guaranteed between 0.0 and 100.0
                     debug!("Setting new volume for {}: {vol}", newr_c.dev_name);
                     newr_c.set_volume(&ui_log, vol);
+                    Self::follow_volume_locks(&volume_sliders, &newr_c.location, vol);
                 }
             });
             pbutton.add(&sl);
+            // keep the slider in sync when the volume is changed with the vendor remote
+            poll_volume(new_renderer);
+            self.volume_sliders.borrow_mut().insert(
+                new_renderer.location.clone(),
+                VolumeSlider {
+                    dev_name: new_renderer.dev_name.clone(),
+                    slider: sl.clone(),
+                    volume: new_renderer.volume,
+                },
+            );
         }
         // and add the volume slider too if GetVolume worked
         self.vpack.insert(&pbutton, self.btn_index);
         self.buttons
             .insert(new_renderer.location.clone(), pbut.clone()); // and keep a reference to it for bookkeeping
+        self.badges.borrow_mut().insert(
+            new_renderer.location.clone(),
+            RendererBadges {
+                format,
+                state,
+                elapsed,
+                silence,
+                remote_ip: new_renderer.remote_addr.clone(),
+                badge,
+            },
+        );
         app::redraw();
         // check if autoreconnect is set for this renderer
         if self.auto_reconnect.is_set() {
@@ -772,6 +2211,411 @@ impl MainForm {
         }
     }
 
+    /// the context menu of a renderer button
+    fn renderer_menu(ctx: &ButtonContext, b: &mut LightButton) {
+        let renderer = &ctx.renderer;
+        let dev_name = &renderer.dev_name;
+        let playing = b.is_on();
+        let mut labels = vec![
+            "A/V delay...",
+            "Statistics...",
+            "Test...",
+            "Play from media server...",
+            "Volume lock...",
+            if ctx.muted.get() { "Unmute" } else { "Mute" },
+            "Rename...",
+            "Hide",
+        ];
+        let any_hidden = ctx.rows.borrow().values().any(|row| !row.visible());
+        if any_hidden {
+            labels.push("Show hidden renderers...");
+        }
+        let menu = MenuItem::new(&labels);
+        let Some(item) = menu.popup(app::event_x(), app::event_y()) else {
+            return;
+        };
+        match item.label().as_deref() {
+            Some("A/V delay...") => Self::set_renderer_delay(dev_name),
+            Some("Statistics...") => {
+                dialog::message_title(&format!("swyh-rs statistics of {dev_name}"));
+                dialog::message_default(&renderer_stats(dev_name).to_string());
+            }
+            Some("Test...") => Self::button_action(ctx, b, ButtonAction::Test),
+            Some("Play from media server...") if playing => {
+                dialog::message_title("swyh-rs media server");
+                dialog::message_default(&format!("Stop playing to {dev_name} first"));
+            }
+            Some("Play from media server...") => Self::play_from_media_server(renderer),
+            Some("Volume lock...") => Self::volume_lock_menu(dev_name),
+            Some("Mute" | "Unmute") => Self::button_action(ctx, b, ButtonAction::Mute),
+            Some("Rename...") => Self::rename_renderer(renderer, b),
+            Some("Hide") => Self::button_action(ctx, b, ButtonAction::Hide),
+            Some("Show hidden renderers...") => Self::show_hidden_renderers(&ctx.rows),
+            _ => (),
+        }
+    }
+
+    /// do the action of a mouse gesture or a menu entry on a renderer button
+    fn button_action(ctx: &ButtonContext, b: &mut LightButton, action: ButtonAction) {
+        let dev_name = &ctx.renderer.dev_name;
+        match action {
+            ButtonAction::None => (),
+            ButtonAction::Menu => Self::renderer_menu(ctx, b),
+            ButtonAction::Restart => {
+                ui_log(&format!("Restarting the stream to {dev_name}"));
+                if b.is_on() {
+                    b.set(false);
+                    b.do_callback();
+                }
+                b.set(true);
+                b.do_callback();
+            }
+            ButtonAction::Mute => {
+                let mute = !ctx.muted.get();
+                ctx.muted.set(mute);
+                ctx.renderer.set_mute(&ui_log, mute);
+                if let Some(vs) = ctx
+                    .volume_sliders
+                    .borrow_mut()
+                    .get_mut(&ctx.renderer.location)
+                {
+                    vs.slider.set_selection_color(if mute {
+                        Color::from_u32(0x00a0_a0a0)
+                    } else {
+                        Color::XtermGreen
+                    });
+                    vs.slider.redraw();
+                }
+            }
+            ButtonAction::Test if b.is_on() => {
+                dialog::message_title("swyh-rs renderer test");
+                dialog::message_default(&format!("Stop playing to {dev_name} first"));
+            }
+            ButtonAction::Test => {
+                Self::test_renderer(&ctx.renderer, ctx.local_addr, ctx.sample_rate);
+            }
+            ButtonAction::Hide => {
+                if b.is_on() {
+                    b.set(false);
+                    b.do_callback();
+                }
+                Self::set_hidden(&ctx.rows, dev_name, true);
+            }
+        }
+    }
+
+    /// change the label of a renderer button, an empty label restores the default label
+    fn rename_renderer(renderer: &Renderer, b: &mut LightButton) {
+        let dev_name = &renderer.dev_name;
+        dialog::message_title("swyh-rs rename renderer");
+        let Some(name) = dialog::input_default(
+            &format!("The label of the button of {dev_name} (empty for the default)"),
+            &b.label(),
+        ) else {
+            return;
+        };
+        let name = name.trim();
+        {
+            let mut conf = CONFIG.write();
+            conf.renderers.entry(dev_name.clone()).or_default().label =
+                (!name.is_empty()).then(|| name.to_string());
+            let _ = conf.update_config();
+        }
+        if name.is_empty() {
+            b.set_label(&renderer.button_label());
+        } else {
+            b.set_label(name);
+        }
+        b.redraw();
+    }
+
+    /// hide or show the row of a renderer button, and remember it
+    fn set_hidden(rows: &RendererRows, dev_name: &str, hidden: bool) {
+        {
+            let mut conf = CONFIG.write();
+            conf.renderers
+                .entry(dev_name.to_string())
+                .or_default()
+                .hidden = hidden.then_some(true);
+            let _ = conf.update_config();
+        }
+        if let Some(row) = rows.borrow_mut().get_mut(dev_name) {
+            if hidden {
+                row.hide();
+            } else {
+                row.show();
+            }
+        }
+        app::redraw();
+        ui_log(&format!(
+            "Renderer {dev_name} is {}",
+            if hidden { "hidden" } else { "shown again" }
+        ));
+    }
+
+    /// choose a hidden renderer to show again
+    fn show_hidden_renderers(rows: &RendererRows) {
+        let mut hidden: Vec<String> = rows
+            .borrow()
+            .iter()
+            .filter(|(_, row)| !row.visible())
+            .map(|(dev_name, _)| dev_name.clone())
+            .collect();
+        hidden.sort();
+        if let Some(n) = Self::popup_choice(&hidden) {
+            Self::set_hidden(rows, &hidden[n], false);
+        }
+    }
+
+    /// join, leave, lock or unlock the volume lock groups of a renderer
+    fn volume_lock_menu(dev_name: &str) {
+        let mut choices = lock_choices(&CONFIG.read().volume_locks, dev_name);
+        choices.push((
+            String::from("New group..."),
+            LockChange::Join(String::new()),
+        ));
+        let labels: Vec<String> = choices.iter().map(|(label, _)| label.clone()).collect();
+        let Some(n) = Self::popup_choice(&labels) else {
+            return;
+        };
+        let change = match &choices[n].1 {
+            LockChange::Join(name) if name.is_empty() => {
+                dialog::message_title("swyh-rs volume lock");
+                let Some(name) = dialog::input_default(
+                    &format!("The name of the new volume lock group with {dev_name}"),
+                    "",
+                ) else {
+                    return;
+                };
+                let name = name.trim().to_string();
+                if name.is_empty() {
+                    return;
+                }
+                LockChange::Join(name)
+            }
+            change => change.clone(),
+        };
+        let mut conf = CONFIG.write();
+        apply_lock_change(&mut conf.volume_locks, &change, dev_name);
+        let _ = conf.update_config();
+        match &change {
+            LockChange::Join(name) => ui_log(&format!("{dev_name} joined volume lock {name}")),
+            LockChange::Leave(name) => ui_log(&format!("{dev_name} left volume lock {name}")),
+            LockChange::Toggle(name) => {
+                let locked = conf
+                    .volume_locks
+                    .iter()
+                    .any(|l| l.name == *name && l.locked);
+                ui_log(&format!(
+                    "Volume lock {name} is {}",
+                    if locked { "on" } else { "off" }
+                ));
+            }
+        }
+    }
+
+    // test the connection with a renderer in the background, the main loop shows the result
+    fn test_renderer(renderer: &Renderer, local_addr: IpAddr, sample_rate: u32) {
+        ui_log(&format!(
+            "Testing the connection with {}, this takes about 10 seconds",
+            renderer.dev_name
+        ));
+        let renderer = renderer.clone();
+        let _ = std::thread::Builder::new()
+            .name("renderer_test".into())
+            .spawn(move || {
+                let config = CONFIG.read().clone();
+                let streaminfo = StreamInfo {
+                    sample_rate,
+                    bits_per_sample: config.bits_per_sample.unwrap_or(16),
+                    streaming_format: config.streaming_format.unwrap_or(Flac),
+                };
+                let steps = diagnose(&renderer, &local_addr, server_port(), streaminfo, &ui_log);
+                let _ = MSGCHANNEL.read().0.send(MessageType::DiagnosisMessage(
+                    renderer.dev_name.clone(),
+                    steps.iter().map(ToString::to_string).collect(),
+                ));
+            });
+    }
+
+    /// pop up a numbered menu of choices at the mouse, the index of the chosen one
+    fn popup_choice(choices: &[String]) -> Option<usize> {
+        let labels: Vec<String> = choices
+            .iter()
+            .enumerate()
+            .map(|(n, choice)| format!("{}. {choice}", n + 1).fw_slash_pipe_escape())
+            .collect();
+        let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+        let item = MenuItem::new(&labels).popup(app::event_x(), app::event_y())?;
+        let label = item.label()?;
+        let n = label.split_once(". ")?.0.parse::<usize>().ok()?;
+        n.checked_sub(1)
+    }
+
+    // browse a media server with popup menus and let the renderer play the chosen item,
+    // the search and the browsing run in the background and post their outcome
+    // as a MediaBrowseMessage
+    fn play_from_media_server(renderer: &Renderer) {
+        Self::search_media_servers(renderer, false);
+    }
+
+    // find the media servers, with a new search if asked or if none are known
+    fn search_media_servers(renderer: &Renderer, again: bool) {
+        let renderer = renderer.clone();
+        let _ = std::thread::Builder::new()
+            .name("media_servers".into())
+            .spawn(move || {
+                let mut servers = media_servers(again, &ui_log);
+                if servers.is_empty() && !again {
+                    servers = media_servers(true, &ui_log);
+                }
+                let _ = MSGCHANNEL.read().0.send(MessageType::MediaBrowseMessage(
+                    MediaBrowse::Servers(renderer, servers, again),
+                ));
+            });
+    }
+
+    // browse the last container of the path on the media server
+    fn browse_media_server(renderer: &Renderer, server: &MediaServer, path: Vec<String>) {
+        let (renderer, server) = (renderer.clone(), server.clone());
+        let _ = std::thread::Builder::new()
+            .name("media_browse".into())
+            .spawn(move || {
+                let objects = server.browse(&path[path.len() - 1]);
+                let _ = MSGCHANNEL.read().0.send(MessageType::MediaBrowseMessage(
+                    MediaBrowse::Objects(renderer, server, path, objects),
+                ));
+            });
+    }
+
+    /// let the user choose one of the media servers that were found, or search again
+    pub fn choose_media_server(renderer: &Renderer, servers: &[MediaServer], again: bool) {
+        let mut choices: Vec<String> = servers.iter().map(|s| s.name.clone()).collect();
+        if !again {
+            choices.push("Search again".to_string());
+        }
+        let n = match Self::popup_choice(&choices) {
+            Some(n) => n,
+            None if again && servers.is_empty() => {
+                dialog::message_title("swyh-rs media server");
+                dialog::message_default("No media servers found");
+                return;
+            }
+            None => return,
+        };
+        if n == servers.len() {
+            Self::search_media_servers(renderer, true);
+            return;
+        }
+        // the ids of the containers from the root down
+        Self::browse_media_server(renderer, &servers[n], vec!["0".to_string()]);
+    }
+
+    /// let the user choose a child of the browsed container: open a container, go up,
+    /// or let the renderer play an item
+    pub fn choose_media_object(
+        renderer: &Renderer,
+        server: &MediaServer,
+        mut path: Vec<String>,
+        objects: Option<Vec<MediaObject>>,
+    ) {
+        let Some(objects) = objects else {
+            dialog::message_title("swyh-rs media server");
+            dialog::message_default(&format!("{} did not respond", server.name));
+            return;
+        };
+        let up = usize::from(path.len() > 1);
+        let mut choices: Vec<String> = Vec::new();
+        if up == 1 {
+            choices.push("..".to_string());
+        }
+        choices.extend(objects.iter().map(|o| {
+            if o.container {
+                format!("{} >", o.title)
+            } else {
+                o.title.clone()
+            }
+        }));
+        let Some(n) = Self::popup_choice(&choices) else {
+            return;
+        };
+        if n < up {
+            path.pop();
+            Self::browse_media_server(renderer, server, path);
+            return;
+        }
+        let object = &objects[n - up];
+        if object.container {
+            path.push(object.id.clone());
+            Self::browse_media_server(renderer, server, path);
+            return;
+        }
+        let renderer = renderer.clone();
+        let (url, metadata) = (object.url.clone(), object.metadata());
+        let _ = std::thread::Builder::new()
+            .name("play_item".into())
+            .spawn(move || {
+                if let Err(e) = renderer.play_item(&url, &metadata, &ui_log) {
+                    ui_log(&format!("*E*E*> Could not play {url}: {e}"));
+                }
+            });
+    }
+
+    // connect to a streaming url of swyh-rs itself and check the stream in the background
+    fn verify_own_stream(label: &str, url: &str, sample_rate: u32) {
+        ui_log(&format!(
+            "Verifying the {label} stream for {CHECK_SECS} seconds"
+        ));
+        let name = format!("the {label} stream");
+        let url = url.to_string();
+        let _ = std::thread::Builder::new()
+            .name("stream_check".into())
+            .spawn(move || {
+                let bits = CONFIG.read().bits_per_sample.unwrap_or(16);
+                let steps = verify_stream(&url, sample_rate, bits, CHECK_SECS);
+                let _ = MSGCHANNEL.read().0.send(MessageType::DiagnosisMessage(
+                    name,
+                    steps.iter().map(ToString::to_string).collect(),
+                ));
+            });
+    }
+
+    /// show the outcome of the connection test of a renderer or a stream
+    pub fn show_diagnosis(dev_name: &str, steps: &[String]) {
+        dialog::message_title(&format!("swyh-rs test of {dev_name}"));
+        dialog::message_default(&steps.join("\n\n"));
+    }
+
+    // ask for the A/V delay of a renderer and remember it in the renderer settings
+    fn set_renderer_delay(dev_name: &str) {
+        let current = CONFIG
+            .read()
+            .renderers
+            .get(dev_name)
+            .and_then(|s| s.delay_msec)
+            .unwrap_or_default();
+        let Some(input) = dialog::input_default(
+            &format!("A/V delay in msec for {dev_name} (0 - 10000)"),
+            &current.to_string(),
+        ) else {
+            return;
+        };
+        let Ok(delay) = input.trim().parse::<u32>() else {
+            ui_log(&format!("*W*W*> Invalid A/V delay {input}"));
+            return;
+        };
+        let delay = delay.min(10_000);
+        let mut conf = CONFIG.write();
+        conf.renderers
+            .entry(dev_name.to_string())
+            .or_default()
+            .delay_msec = Some(delay);
+        let _ = conf.update_config();
+        ui_log(&format!(
+            "A/V delay for {dev_name} set to {delay} msec, effective when streaming (re)starts"
+        ));
+    }
+
     fn apply_theme(theme_index: usize) -> &'static str {
         let (theme, name) = match theme_index {
             0 => (Some(ColorTheme::new(color_themes::SHAKE_THEME)), THEMES[0]),
@@ -788,4 +2632,150 @@ impl MainForm {
         }
         name
     }
+
+    /// set the scale of all screens, None restores the scale factor of the system
+    fn apply_ui_scale(system_scales: &[f32], scale: Option<f32>) {
+        for (n, system_scale) in system_scales.iter().enumerate() {
+            let scale = scale.map_or(*system_scale, |s| s.clamp(0.5, 4.0));
+            app::set_screen_scale(n as i32, scale);
+        }
+    }
+
+    /// the streaming urls of all formats with the configured bit depth and streamsizes,
+    /// the listen page and the playlists, as (label, url)
+    fn stream_urls(local_addr: IpAddr) -> Vec<(String, String)> {
+        let config = CONFIG.read();
+        let addr = format!("{local_addr}:{}", server_port());
+        let bd = config.bits_per_sample.unwrap_or(16);
+        let mut urls: Vec<(String, String)> = [
+            (Flac, config.flac_stream_size),
+            (StreamingFormat::Wav, config.wav_stream_size),
+            (StreamingFormat::Lpcm, config.lpcm_stream_size),
+            (StreamingFormat::Rf64, config.rf64_stream_size),
+        ]
+        .into_iter()
+        .map(|(fmt, ss)| {
+            (
+                format!("{} {bd} bit", fmt.to_string().to_uppercase()),
+                stream_url(&addr, fmt, Some(bd), ss),
+            )
+        })
+        .collect();
+        urls.push(("Listen page".to_string(), format!("http://{addr}/listen")));
+        urls.push((
+            "Playlist (M3U)".to_string(),
+            format!("http://{addr}/playlist.m3u"),
+        ));
+        urls.push((
+            "Playlist (PLS)".to_string(),
+            format!("http://{addr}/playlist.pls"),
+        ));
+        urls
+    }
+
+    /// open an url in the default browser
+    fn open_in_browser(url: &str) {
+        #[cfg(target_os = "windows")]
+        let result = std::process::Command::new("explorer").arg(url).spawn();
+        #[cfg(target_os = "macos")]
+        let result = std::process::Command::new("open").arg(url).spawn();
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        let result = std::process::Command::new("xdg-open").arg(url).spawn();
+        if let Err(e) = result {
+            ui_log(&format!("*E*E*> Could not open {url} in the browser: {e}"));
+        }
+    }
+
+    /// a small panel with all streaming urls, that can be selected and copied
+    fn show_urls(urls: &[(String, String)]) {
+        let mut buf = TextBuffer::default();
+        for (label, url) in urls {
+            buf.append(&format!("{label}: {url}\n"));
+        }
+        let mut win = DoubleWindow::new(0, 0, 600, 140, "swyh-rs stream URLs").center_screen();
+        let mut td = TextDisplay::new(5, 5, 590, 130, "");
+        td.set_buffer(buf);
+        win.end();
+        win.make_resizable(true);
+        win.resizable(&td);
+        win.show();
+    }
+
+    /// show the stages of the DSP chain, they can be moved up or down and enabled or
+    /// disabled, and the changes are applied immediately
+    fn show_dsp_chain() {
+        let mut win = DoubleWindow::new(0, 0, 600, 220, "swyh-rs DSP chain").center_screen();
+        let mut stages = HoldBrowser::new(5, 5, 495, 210, "");
+        fill_dsp_chain(&mut stages);
+        // move the selected stage, or enable/disable it
+        let buttons: [(&str, fn(&mut Vec<DspStage>, usize) -> Option<usize>); 3] = [
+            ("Up", |chain, i| {
+                (i > 0).then(|| {
+                    chain.swap(i, i - 1);
+                    i - 1
+                })
+            }),
+            ("Down", |chain, i| {
+                (i + 1 < chain.len()).then(|| {
+                    chain.swap(i, i + 1);
+                    i + 1
+                })
+            }),
+            ("On/Off", |chain, i| {
+                chain[i].enabled = !chain[i].enabled;
+                Some(i)
+            }),
+        ];
+        for (n, (label, change)) in buttons.into_iter().enumerate() {
+            let mut button = Button::new(505, 5 + 30 * n as i32, 90, 25, label);
+            let mut stages = stages.clone();
+            button.set_callback(move |_| {
+                let Some(i) = usize::try_from(stages.value() - 1).ok() else {
+                    return;
+                };
+                let mut conf = CONFIG.write();
+                if i >= conf.dsp_chain.len() {
+                    return;
+                }
+                let Some(selected) = change(&mut conf.dsp_chain, i) else {
+                    return;
+                };
+                let _ = conf.update_config();
+                drop(conf);
+                update_dsp_chain();
+                fill_dsp_chain(&mut stages);
+                stages.select(selected as i32 + 1);
+            });
+        }
+        win.end();
+        win.make_resizable(true);
+        win.resizable(&stages);
+        win.show();
+    }
+
+    /// is the top left corner of the window on one of the screens
+    fn on_screen(g: &WindowGeometry) -> bool {
+        (0..app::screen_count()).any(|n| {
+            let (x, y, w, h) = app::screen_xywh(n);
+            (x..x + w).contains(&g.x) && (y..y + h).contains(&g.y)
+        })
+    }
+}
+
+/// show the stages of the DSP chain in the browser
+fn fill_dsp_chain(stages: &mut HoldBrowser) {
+    stages.clear();
+    let conf = CONFIG.read();
+    if conf.dsp_chain.is_empty() {
+        stages.add("No DSP chain, add [[configuration.dsp_chain]] stages to the config file");
+    }
+    for (n, stage) in conf.dsp_chain.iter().enumerate() {
+        stages.add(&format!("{}. {}", n + 1, describe_stage(stage)));
+    }
+}
+
+/// the keyboard alternatives for a right click: the context menu key or Shift+F10
+fn is_context_menu_key() -> bool {
+    let key = app::event_key();
+    key == Key::Menu || (key == Key::fn_key(10) && app::is_event_shift())
 }
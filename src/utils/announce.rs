@@ -0,0 +1,215 @@
+//! Announcements mixed into the live stream, e.g. a doorbell or paging from Home Assistant.
+//!
+//! A POST to /api/announce with a WAV file as body, or to /api/announce?file=doorbell.wav
+//! for a WAV file in the configured announcements directory, ducks the captured audio,
+//! mixes in the announcement at the configured gain and restores the stream when the
+//! announcement ends. When no audio is captured (nothing is playing) the announcement
+//! is sent to the clients on its own.
+
+use parking_lot::Mutex;
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+use url::form_urlencoded;
+
+use super::{
+    fallback_audio::{parse_wav, resample, stereo_stream},
+    ui_logger::ui_log,
+};
+use crate::{
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::rendercontrol::WavData,
+};
+
+/// the default ducking of the captured audio during an announcement
+const DEFAULT_DUCK_DB: f32 = -15.0;
+/// the time to duck and restore the captured audio
+const RAMP_MSEC: u32 = 200;
+/// the block size when the announcement is sent on its own
+const BLOCK_MSEC: u32 = 50;
+
+/// the announcement that is playing
+static ANNOUNCEMENT: Mutex<Option<Announcement>> = Mutex::new(None);
+/// when the `wave_reader` last received captured samples
+static LAST_CAPTURE: Mutex<Option<Instant>> = Mutex::new(None);
+/// the sample rate of the stream
+static SAMPLE_RATE: AtomicU32 = AtomicU32::new(0);
+
+/// an announcement with its ducking
+#[derive(Debug, Clone, PartialEq)]
+struct Announcement {
+    samples: Vec<f32>,
+    pos: usize,
+    gain: f32,
+    duck: f32,
+    ramp: usize,
+}
+
+impl Announcement {
+    /// mix the next part of the announcement into the interleaved stereo samples,
+    /// returns false when the announcement has ended
+    fn mix(&mut self, samples: &mut [f32]) -> bool {
+        let total = self.samples.len();
+        for s in samples.iter_mut() {
+            if self.pos >= total {
+                break;
+            }
+            // the ducking ramps in at the start and out at the end
+            let edge = self.pos.min(total - self.pos);
+            let ramp = (edge as f32 / self.ramp.max(1) as f32).min(1.0);
+            let duck = 1.0 + (self.duck - 1.0) * ramp;
+            *s = (*s * duck + self.samples[self.pos] * self.gain).clamp(-1.0, 1.0);
+            self.pos += 1;
+        }
+        self.pos < total
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// mix the playing announcement into the captured samples, called by the `wave_reader`
+pub fn mix_announcement(samples: &mut [f32]) {
+    *LAST_CAPTURE.lock() = Some(Instant::now());
+    mix_next(samples);
+}
+
+fn mix_next(samples: &mut [f32]) {
+    let mut announcement = ANNOUNCEMENT.lock();
+    if let Some(a) = announcement.as_mut() {
+        if !a.mix(samples) {
+            *announcement = None;
+            ui_log("Announcement ended");
+        }
+    }
+}
+
+/// Start the thread that sends the announcements on their own when no audio is captured.
+pub fn start_announcer(wd: &WavData) {
+    SAMPLE_RATE.store(wd.sample_rate.0, Ordering::Relaxed);
+    let block_size = (wd.sample_rate.0 * BLOCK_MSEC / 1000 * 2) as usize;
+    let _ = thread::Builder::new()
+        .name("announcer".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let block_time = Duration::from_millis(u64::from(BLOCK_MSEC));
+            let mut next = Instant::now();
+            loop {
+                next += block_time;
+                let now = Instant::now();
+                if next > now {
+                    thread::sleep(next - now);
+                } else {
+                    next = now;
+                }
+                let capturing = LAST_CAPTURE
+                    .lock()
+                    .is_some_and(|t| t.elapsed() < 4 * block_time);
+                if capturing || !stereo_stream() || ANNOUNCEMENT.lock().is_none() {
+                    continue;
+                }
+                let mut block = vec![0.0f32; block_size];
+                mix_next(&mut block);
+                CLIENTS
+                    .read()
+                    .iter()
+                    .for_each(|(_, client)| client.write(&block));
+            }
+        });
+}
+
+/// the announcement file name must be a plain file name in the announcements directory
+fn is_plain_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && Path::new(name).file_name().is_some_and(|f| f == name)
+        && !name.contains(['/', '\\'])
+}
+
+/// Start an announcement for a /api/announce request, the WAV file is the body of the
+/// request or the `file` query parameter, with an optional `gain` query parameter in dB.
+///
+/// Returns the duration of the announcement in seconds.
+pub fn announce_request(url: &str, body: &[u8]) -> Result<f64, String> {
+    let sample_rate = SAMPLE_RATE.load(Ordering::Relaxed);
+    if sample_rate == 0 {
+        return Err("the announcer is not running".to_string());
+    }
+    if !stereo_stream() {
+        return Err("announcements are not supported in multichannel mode".to_string());
+    }
+    let settings = CONFIG.read().announce.clone();
+    let mut file = None;
+    let mut gain_db = settings.gain_db.unwrap_or(0.0);
+    if let Some((_, query)) = url.split_once('?') {
+        for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+            match k.as_ref() {
+                "file" => file = Some(v.to_string()),
+                "gain" => gain_db = v.parse().map_err(|_| format!("invalid gain {v}"))?,
+                _ => (),
+            }
+        }
+    }
+    let wav = if let Some(name) = file {
+        let Some(dir) = settings.dir else {
+            return Err("no announcements directory configured".to_string());
+        };
+        if !is_plain_file_name(&name) {
+            return Err(format!("invalid file name {name}"));
+        }
+        fs::read(Path::new(&dir).join(&name)).map_err(|e| format!("{name}: {e}"))?
+    } else if body.is_empty() {
+        return Err("no WAV file in the request".to_string());
+    } else {
+        body.to_vec()
+    };
+    let (rate, samples) = parse_wav(&wav)?;
+    let samples = resample(&samples, rate, sample_rate);
+    let duration = samples.len() as f64 / 2.0 / f64::from(sample_rate);
+    *ANNOUNCEMENT.lock() = Some(Announcement {
+        samples,
+        pos: 0,
+        gain: db_to_gain(gain_db),
+        duck: db_to_gain(settings.duck_db.unwrap_or(DEFAULT_DUCK_DB)),
+        ramp: (sample_rate * RAMP_MSEC / 1000 * 2) as usize,
+    });
+    ui_log(&format!("Announcement of {duration:.1} s at {gain_db} dB"));
+    Ok(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announcement_mix() {
+        let mut a = Announcement {
+            samples: vec![0.25; 8],
+            pos: 0,
+            gain: 1.0,
+            duck: 0.0,
+            ramp: 2,
+        };
+        let mut samples = vec![0.5; 6];
+        assert!(a.mix(&mut samples));
+        // ducked in over 2 samples
+        assert_eq!(samples, vec![0.75, 0.5, 0.25, 0.25, 0.25, 0.25]);
+        let mut samples = vec![0.5; 6];
+        assert!(!a.mix(&mut samples));
+        // restored at the end, and the captured audio after the announcement
+        assert_eq!(samples, vec![0.25, 0.5, 0.5, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_plain_file_name() {
+        assert!(is_plain_file_name("doorbell.wav"));
+        assert!(!is_plain_file_name("../secret.wav"));
+        assert!(!is_plain_file_name("/etc/passwd"));
+        assert!(!is_plain_file_name(".."));
+        assert!(!is_plain_file_name(""));
+    }
+}
@@ -1,16 +1,35 @@
 use crate::{
-    globals::statics::{CLIENTS, CONFIG, RUN_RMS_MONITOR},
-    utils::ui_logger::ui_log,
+    dsp::chain::{apply_dsp_chain, update_dsp_chain},
+    globals::statics::{
+        CLIENTS, CONFIG, MONITOR_BUFFER, PREROLL, RUN_PLAYBACK_MONITOR, RUN_RECORDER,
+        RUN_RMS_MONITOR, RUN_RTP_SENDER, STREAM_PARAMS,
+    },
+    server::rtp_sender::rtp_send_samples,
+    utils::{
+        announce::mix_announcement,
+        balance::{apply_channel_gains, update_channel_gains},
+        bitperfect::set_capture_format,
+        capture_errors::{report_capture_error, CaptureError},
+        fallback_audio::{capture_lost, capture_restored},
+        hooks::hook_samples,
+        mixer::mix_secondary,
+        network_source::{open_network_source, NetworkSource},
+        recorder::record_samples,
+        scripting::script_samples,
+        track_detector::TrackDetector,
+        ui_logger::ui_log,
+        vu_trigger::vu_trigger_samples,
+    },
 };
 use cpal::{
     traits::{DeviceTrait, HostTrait},
-    DefaultStreamConfigError, Sample, SupportedStreamConfig,
+    DefaultStreamConfigError, HostId, Sample, SupportedStreamConfig,
 };
 use crossbeam_channel::Sender;
 use dasp_sample::ToSample;
 use log::debug;
 use parking_lot::Once;
-use std::sync::atomic::Ordering;
+use std::{io::Read, sync::atomic::Ordering, thread, time::Duration};
 
 /// A [`cpal::Device`] with either a default input or default output config.
 ///
@@ -156,14 +175,46 @@ fn log_stream_configs(
     };
 }
 
+/// the names of the available audio hosts (WASAPI, ALSA, JACK, CoreAudio, ...)
+#[must_use]
+pub fn get_audio_hosts() -> Vec<String> {
+    cpal::available_hosts()
+        .into_iter()
+        .map(|host_id| host_id.name().to_string())
+        .collect()
+}
+
+/// the available hosts that match the configured audio host, all available hosts if
+/// no audio host is configured or if the configured host is not available
+fn selected_hosts(audio_host: Option<&str>) -> Vec<HostId> {
+    // the device watcher enumerates the devices over and over, only warn once
+    static NOT_AVAILABLE: Once = Once::new();
+    let available_hosts = cpal::available_hosts();
+    if let Some(name) = audio_host.filter(|name| !name.is_empty()) {
+        if let Some(host_id) = available_hosts
+            .iter()
+            .find(|host_id| host_id.name().eq_ignore_ascii_case(name))
+        {
+            return vec![*host_id];
+        }
+        NOT_AVAILABLE.call_once(|| {
+            ui_log(&format!(
+                "*W*W*> Audio host {name} is not available, using all audio hosts"
+            ));
+        });
+    }
+    available_hosts
+}
+
 #[must_use]
 pub fn get_output_audio_devices() -> Vec<Device> {
     let mut result = Vec::new();
     debug!("Supported hosts:\n  {:?}", cpal::ALL_HOSTS);
     let available_hosts = cpal::available_hosts();
     debug!("Available hosts:\n  {:?}", available_hosts);
+    let audio_host = CONFIG.read().audio_host.clone();
 
-    for host_id in available_hosts {
+    for host_id in selected_hosts(audio_host.as_deref()) {
         debug!("{}", host_id.name());
         let host = cpal::host_from_id(host_id).unwrap();
 
@@ -205,6 +256,52 @@ pub fn get_default_audio_output_device() -> Option<Device> {
         .and_then(|device| DeviceKind::Output(device).try_into().ok())
 }
 
+/// the (0-based) left and right device channels that feed the stream, if a channel pair
+/// (1-based) is selected for a capture device with more than 2 channels
+#[must_use]
+pub fn channel_pair(
+    capture_channels: Option<[u16; 2]>,
+    device_channels: u16,
+) -> Option<(usize, usize)> {
+    let [left, right] = capture_channels?;
+    if device_channels <= 2
+        || left == 0
+        || right == 0
+        || left > device_channels
+        || right > device_channels
+    {
+        return None;
+    }
+    Some((usize::from(left - 1), usize::from(right - 1)))
+}
+
+/// the (0-based) left and right device channels of a stereo stream from a capture device
+/// with more than 2 channels: the selected channel pair, or else the front left and right
+/// channels, unless all channels are streamed in multichannel mode
+#[must_use]
+pub fn stereo_channels(
+    capture_channels: Option<[u16; 2]>,
+    multichannel: bool,
+    device_channels: u16,
+) -> Option<(usize, usize)> {
+    if device_channels <= 2 {
+        return None;
+    }
+    channel_pair(capture_channels, device_channels).or((!multichannel).then_some((0, 1)))
+}
+
+/// the number of channels in the stream: 2 if a stereo pair is taken from the capture
+/// device, otherwise all the channels of the capture device
+#[must_use]
+pub fn stream_channels(device_channels: u16) -> u16 {
+    let conf = CONFIG.read();
+    if stereo_channels(conf.capture_channels, conf.multichannel, device_channels).is_some() {
+        2
+    } else {
+        device_channels
+    }
+}
+
 /// `capture_audio_output` - capture the audio stream from the default audio output device
 ///
 /// sets up an input stream for the `wave_reader` in the appropriate format (f32/i16/u16)
@@ -213,23 +310,85 @@ pub fn capture_output_audio(
     rms_sender: Sender<Vec<f32>>,
 ) -> Option<cpal::Stream> {
     let device = device_wrap.as_ref();
-    ui_log(&format!(
-        "Capturing audio from: {}",
-        device
-            .name()
-            .expect("Could not get default audio device name")
-    ));
+    let device_name = device
+        .name()
+        .expect("Could not get default audio device name");
+    ui_log(&format!("Capturing audio from: {device_name}"));
     let audio_cfg = device_wrap
         .kind
         .default_config_any()
         .expect("No default stream config found");
     ui_log(&format!("Default audio {audio_cfg:?}"));
+    set_capture_format(audio_cfg.sample_format());
+    // select the configured channel pair of a multichannel device
+    let device_channels = usize::from(audio_cfg.channels());
+    let (capture_channels, multichannel) = {
+        let conf = CONFIG.read();
+        (conf.capture_channels, conf.multichannel)
+    };
+    let channel_select = stereo_channels(capture_channels, multichannel, audio_cfg.channels())
+        .map(|(left, right)| (device_channels, left, right));
+    if let Some([left, right]) = capture_channels {
+        if device_channels > 2 && channel_pair(capture_channels, audio_cfg.channels()).is_none() {
+            ui_log(&format!(
+                "*W*W*> Invalid capture channels {left} and {right} for a device with {device_channels} channels"
+            ));
+        }
+    }
+    if let Some((_, left, right)) = channel_select {
+        ui_log(&format!(
+            "Streaming channels {} and {} of {device_channels}",
+            left + 1,
+            right + 1
+        ));
+    } else if device_channels > 2 {
+        ui_log(&format!(
+            "Multichannel mode: streaming all {device_channels} channels"
+        ));
+        warn_stereo_only();
+    }
+    let channels = stream_channels(audio_cfg.channels());
+    // the renderers get the actual stream parameters in their DIDL metadata
+    *STREAM_PARAMS.write() = Some((audio_cfg.sample_rate().0, channels));
+    // the stereo-only features are bypassed for a multichannel stream
+    let stereo = channels == 2;
+    update_channel_gains();
+    update_dsp_chain();
+    start_replay(audio_cfg.sample_rate().0, channels);
+    // the number of samples kept in the pre-roll buffer for new clients
+    let preroll_msec = CONFIG.read().preroll_msec.unwrap_or_default() as usize;
+    let preroll_size =
+        (audio_cfg.sample_rate().0 as usize * preroll_msec / 1000) * usize::from(channels);
+    PREROLL.lock().clear();
+    if preroll_size > 0 {
+        ui_log(&format!(
+            "Keeping {preroll_msec} msec of pre-roll audio for new clients"
+        ));
+    }
+    // detect track boundaries from silence gaps if enabled
+    let gap_msec = CONFIG.read().track_gap_msec.unwrap_or_default();
+    let mut detector = TrackDetector::new(audio_cfg.sample_rate().0, channels, gap_msec);
+    if detector.is_some() {
+        ui_log(&format!(
+            "Detecting track boundaries from silence gaps of {gap_msec} msec"
+        ));
+    }
     let mut f32_samples: Vec<f32> = Vec::with_capacity(16384);
     match audio_cfg.sample_format() {
         cpal::SampleFormat::F32 => match device.build_input_stream(
             &audio_cfg.config(),
-            move |data, _: &_| wave_reader::<f32>(data, &mut f32_samples, &rms_sender),
-            capture_err_fn,
+            move |data, _: &_| {
+                wave_reader::<f32>(
+                    data,
+                    &mut f32_samples,
+                    channel_select,
+                    stereo,
+                    preroll_size,
+                    &mut detector,
+                    &rms_sender,
+                );
+            },
+            capture_err_fn(device_name.clone()),
             None,
         ) {
             Ok(stream) => {
@@ -237,15 +396,29 @@ pub fn capture_output_audio(
                 Some(stream)
             }
             Err(e) => {
-                ui_log(&format!("Error capturing f32 audio stream: {e}"));
+                report_capture_error(
+                    CaptureError::from_build_error(&e),
+                    &device_name,
+                    &format!("Error capturing f32 audio stream: {e}"),
+                );
                 None
             }
         },
         cpal::SampleFormat::I16 => {
             match device.build_input_stream(
                 &audio_cfg.config(),
-                move |data, _: &_| wave_reader::<i16>(data, &mut f32_samples, &rms_sender),
-                capture_err_fn,
+                move |data, _: &_| {
+                    wave_reader::<i16>(
+                        data,
+                        &mut f32_samples,
+                        channel_select,
+                        stereo,
+                        preroll_size,
+                        &mut detector,
+                        &rms_sender,
+                    );
+                },
+                capture_err_fn(device_name.clone()),
                 None,
             ) {
                 Ok(stream) => {
@@ -253,7 +426,11 @@ pub fn capture_output_audio(
                     Some(stream)
                 }
                 Err(e) => {
-                    ui_log(&format!("Error capturing i16 audio stream: {e}"));
+                    report_capture_error(
+                        CaptureError::from_build_error(&e),
+                        &device_name,
+                        &format!("Error capturing i16 audio stream: {e}"),
+                    );
                     None
                 }
             }
@@ -261,8 +438,18 @@ pub fn capture_output_audio(
         cpal::SampleFormat::U16 => {
             match device.build_input_stream(
                 &audio_cfg.config(),
-                move |data, _: &_| wave_reader::<u16>(data, &mut f32_samples, &rms_sender),
-                capture_err_fn,
+                move |data, _: &_| {
+                    wave_reader::<u16>(
+                        data,
+                        &mut f32_samples,
+                        channel_select,
+                        stereo,
+                        preroll_size,
+                        &mut detector,
+                        &rms_sender,
+                    );
+                },
+                capture_err_fn(device_name.clone()),
                 None,
             ) {
                 Ok(stream) => {
@@ -270,7 +457,11 @@ pub fn capture_output_audio(
                     Some(stream)
                 }
                 Err(e) => {
-                    ui_log(&format!("Error capturing u16 audio stream: {e}"));
+                    report_capture_error(
+                        CaptureError::from_build_error(&e),
+                        &device_name,
+                        &format!("Error capturing u16 audio stream: {e}"),
+                    );
                     None
                 }
             }
@@ -279,18 +470,147 @@ pub fn capture_output_audio(
     }
 }
 
-/// `capture_err_fn` - called whan it's impossible to build an audio input stream
-fn capture_err_fn(err: cpal::StreamError) {
-    ui_log(&format!("Error {err} building audio input stream"));
+/// the features that only handle stereo samples
+fn warn_stereo_only() {
+    let conf = CONFIG.read();
+    let stereo_only = [
+        ("RTP", conf.rtp_destination.is_some()),
+        ("recording", conf.record_dir.is_some()),
+        ("the playback monitor", conf.monitor_device.is_some()),
+        ("the RMS monitor", conf.monitor_rms),
+        ("the mix source", conf.mix_source.is_some()),
+        ("the VU trigger", conf.vu_trigger.is_some()),
+        ("the fallback audio", conf.fallback_audio.is_some()),
+        ("the announcements", conf.control_api),
+        ("balance and trim", true),
+    ];
+    for (feature, enabled) in stereo_only {
+        if enabled {
+            ui_log(&format!(
+                "*W*W*> {feature} only supports stereo, it is disabled in multichannel mode"
+            ));
+        }
+    }
+}
+
+/// `capture_network_audio` - capture the audio from a network source (another swyh-rs instance)
+///
+/// a thread reads the WAV samples for the `wave_reader`, and reconnects if the connection is lost
+pub fn capture_network_audio(source: NetworkSource, rms_sender: Sender<Vec<f32>>) {
+    ui_log(&format!(
+        "Capturing audio from network source: {}",
+        source.url
+    ));
+    let format = source.format;
+    set_capture_format(source.wav_data().sample_format);
+    *STREAM_PARAMS.write() = Some((format.sample_rate, 2));
+    update_channel_gains();
+    update_dsp_chain();
+    start_replay(format.sample_rate, 2);
+    // the number of samples kept in the pre-roll buffer for new clients
+    let preroll_msec = CONFIG.read().preroll_msec.unwrap_or_default() as usize;
+    let preroll_size = (format.sample_rate as usize * preroll_msec / 1000) * 2;
+    PREROLL.lock().clear();
+    // detect track boundaries from silence gaps if enabled
+    let gap_msec = CONFIG.read().track_gap_msec.unwrap_or_default();
+    let mut detector = TrackDetector::new(format.sample_rate, 2, gap_msec);
+    let _ = thread::Builder::new()
+        .name("network_capture".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let mut source = source;
+            let mut f32_samples: Vec<f32> = Vec::with_capacity(16384);
+            // read 10 msec of stereo samples at a time
+            let bytes_per_sample = usize::from(format.bits_per_sample / 8);
+            let mut buf = vec![0u8; (format.sample_rate as usize / 100) * 2 * bytes_per_sample];
+            loop {
+                if let Err(e) = source.reader.read_exact(&mut buf) {
+                    ui_log(&format!(
+                        "*W*W*> Lost network source {}: {e}, reconnecting",
+                        source.url
+                    ));
+                    thread::sleep(Duration::from_secs(2));
+                    match open_network_source(&source.url) {
+                        Some(new_source) if new_source.format == format => source = new_source,
+                        Some(_) => {
+                            ui_log("*E*E*> The network source format has changed, please restart");
+                            break;
+                        }
+                        None => (),
+                    }
+                    continue;
+                }
+                if bytes_per_sample == 2 {
+                    let samples = buf
+                        .chunks_exact(2)
+                        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                        .collect::<Vec<i16>>();
+                    wave_reader::<i16>(
+                        &samples,
+                        &mut f32_samples,
+                        None,
+                        true,
+                        preroll_size,
+                        &mut detector,
+                        &rms_sender,
+                    );
+                } else {
+                    // 24 bit samples in the upper bits of an i32
+                    let samples = buf
+                        .chunks_exact(3)
+                        .map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]))
+                        .collect::<Vec<i32>>();
+                    wave_reader::<i32>(
+                        &samples,
+                        &mut f32_samples,
+                        None,
+                        true,
+                        preroll_size,
+                        &mut detector,
+                        &rms_sender,
+                    );
+                }
+            }
+        });
+}
+
+/// `capture_err_fn` - the error callback of the audio input stream of a device,
+/// the error is classified to tell the user what to do about it
+fn capture_err_fn(device_name: String) -> impl FnMut(cpal::StreamError) + Send + 'static {
+    move |err| {
+        let kind = CaptureError::from_stream_error(&err);
+        if kind == CaptureError::DeviceUnplugged {
+            capture_lost();
+        }
+        report_capture_error(
+            kind,
+            &device_name,
+            &format!("Error {err} in audio input stream"),
+        );
+    }
 }
 
 /// `wave_reader` - the captured audio input stream reader
 ///
 /// writes the captured samples to all registered clients in the
 /// CLIENTS `ChannnelStream` hashmap
-/// also feeds the RMS monitor channel if the RMS option is set
-fn wave_reader<T>(samples: &[T], f32_samples: &mut Vec<f32>, rms_sender: &Sender<Vec<f32>>)
-where
+/// only the selected left and right channels are kept if a channel pair is selected,
+/// and the DSP chain is applied, and for a stereo stream the balance and trim gains,
+/// the VU trigger, the mix source and the announcements
+/// also keeps the most recent samples in the PREROLL buffer if pre-roll is enabled,
+/// and in the replay buffer if it is enabled
+/// and the track boundary detector
+/// and for a stereo stream the RMS monitor channel if the RMS option is set,
+/// the local playback monitor, the recorder and the RTP sender if they are enabled
+fn wave_reader<T>(
+    samples: &[T],
+    f32_samples: &mut Vec<f32>,
+    channel_select: Option<(usize, usize, usize)>,
+    stereo: bool,
+    preroll_size: usize,
+    detector: &mut Option<TrackDetector>,
+    rms_sender: &Sender<Vec<f32>>,
+) where
     T: Sample + ToSample<f32>,
 {
     static ONFIRSTCALL: Once = Once::new();
@@ -300,13 +620,91 @@ where
             RUN_RMS_MONITOR.store(true, Ordering::Relaxed);
         }
     });
+    capture_restored();
     f32_samples.clear();
-    f32_samples.extend(samples.iter().map(|x: &T| T::to_sample::<f32>(*x)));
-    CLIENTS
-        .read()
-        .iter()
-        .for_each(|(_, client)| client.write(f32_samples));
+    if let Some((channels, left, right)) = channel_select {
+        for frame in samples.chunks_exact(channels) {
+            f32_samples.push(T::to_sample::<f32>(frame[left]));
+            f32_samples.push(T::to_sample::<f32>(frame[right]));
+        }
+    } else {
+        f32_samples.extend(samples.iter().map(|x: &T| T::to_sample::<f32>(*x)));
+    }
+    if stereo {
+        apply_channel_gains(f32_samples);
+    }
+    apply_dsp_chain(f32_samples);
+    if stereo {
+        vu_trigger_samples(f32_samples);
+    }
+    hook_samples(f32_samples);
+    script_samples(f32_samples);
+    if stereo {
+        mix_secondary(f32_samples);
+        mix_announcement(f32_samples);
+    }
+    if preroll_size > 0 {
+        // the pre-roll stays locked while distributing the samples, so that a new client
+        // gets every sample exactly once, either from the pre-roll or live
+        let mut preroll = PREROLL.lock();
+        preroll.extend(f32_samples.iter());
+        if preroll.len() > preroll_size {
+            let excess = preroll.len() - preroll_size;
+            preroll.drain(..excess);
+        }
+        CLIENTS
+            .read()
+            .iter()
+            .for_each(|(_, client)| client.write(f32_samples));
+    } else {
+        CLIENTS
+            .read()
+            .iter()
+            .for_each(|(_, client)| client.write(f32_samples));
+    }
+    replay_samples(f32_samples);
+    let track_change = detector.as_mut().and_then(|d| d.process(f32_samples));
+    if !stereo {
+        return;
+    }
+    if RUN_RTP_SENDER.load(Ordering::Acquire) {
+        rtp_send_samples(f32_samples);
+    }
+    if RUN_RECORDER.load(Ordering::Acquire) {
+        record_samples(f32_samples, track_change);
+    }
+    if RUN_PLAYBACK_MONITOR.load(Ordering::Acquire) {
+        MONITOR_BUFFER.lock().extend(f32_samples.iter());
+    }
     if RUN_RMS_MONITOR.load(Ordering::Acquire) {
         rms_sender.send(Vec::from(f32_samples.as_slice())).unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_pair() {
+        assert_eq!(channel_pair(None, 8), None);
+        assert_eq!(channel_pair(Some([3, 4]), 8), Some((2, 3)));
+        assert_eq!(channel_pair(Some([4, 3]), 4), Some((3, 2)));
+        // not for stereo devices
+        assert_eq!(channel_pair(Some([1, 2]), 2), None);
+        // out of range
+        assert_eq!(channel_pair(Some([0, 1]), 8), None);
+        assert_eq!(channel_pair(Some([7, 9]), 8), None);
+    }
+
+    #[test]
+    fn test_stereo_channels() {
+        // the front left and right channels, unless in multichannel mode
+        assert_eq!(stereo_channels(None, false, 6), Some((0, 1)));
+        assert_eq!(stereo_channels(None, true, 6), None);
+        // a selected pair wins
+        assert_eq!(stereo_channels(Some([5, 6]), true, 6), Some((4, 5)));
+        assert_eq!(stereo_channels(Some([7, 8]), false, 6), Some((0, 1)));
+        assert_eq!(stereo_channels(None, false, 2), None);
+    }
+}
@@ -0,0 +1,82 @@
+//! Balance and independent left/right gain trim of the captured stereo samples,
+//! for a capture source or a renderer with a fixed channel imbalance.
+//!
+//! The gains are applied by the `wave_reader`, so they affect every client,
+//! and they can be changed while streaming. In bit-perfect mode they are not applied.
+
+use parking_lot::RwLock;
+
+use crate::globals::statics::CONFIG;
+
+/// the balance range, -100 is left only, 100 is right only
+pub const BALANCE_RANGE: i32 = 100;
+/// the trim range in dB
+pub const MIN_TRIM_DB: f32 = -12.0;
+pub const MAX_TRIM_DB: f32 = 6.0;
+
+/// the current left and right gains
+static CHANNEL_GAINS: RwLock<[f32; 2]> = RwLock::new([1.0, 1.0]);
+
+/// the left and right gain for a balance and the left and right trims in dB
+#[must_use]
+pub fn channel_gains(balance: i32, trim_left_db: f32, trim_right_db: f32) -> [f32; 2] {
+    let balance = balance.clamp(-BALANCE_RANGE, BALANCE_RANGE) as f32 / BALANCE_RANGE as f32;
+    // the balance only attenuates the opposite channel
+    let left = (1.0 - balance).min(1.0);
+    let right = (1.0 + balance).min(1.0);
+    let gain = |db: f32| 10f32.powf(db.clamp(MIN_TRIM_DB, MAX_TRIM_DB) / 20.0);
+    [left * gain(trim_left_db), right * gain(trim_right_db)]
+}
+
+/// (re)compute the channel gains from the configuration
+pub fn update_channel_gains() {
+    let gains = {
+        let conf = CONFIG.read();
+        // no DSP in bit-perfect mode
+        if conf.bit_perfect {
+            [1.0, 1.0]
+        } else {
+            channel_gains(
+                conf.balance.unwrap_or_default(),
+                conf.trim_left_db.unwrap_or_default(),
+                conf.trim_right_db.unwrap_or_default(),
+            )
+        }
+    };
+    *CHANNEL_GAINS.write() = gains;
+}
+
+/// true if the channel gains change the samples
+#[must_use]
+pub fn channel_gains_active() -> bool {
+    *CHANNEL_GAINS.read() != [1.0, 1.0]
+}
+
+/// apply the channel gains to interleaved stereo samples
+pub fn apply_channel_gains(samples: &mut [f32]) {
+    let [left, right] = *CHANNEL_GAINS.read();
+    if left == 1.0 && right == 1.0 {
+        return;
+    }
+    for lr in samples.chunks_exact_mut(2) {
+        lr[0] *= left;
+        lr[1] *= right;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_gains() {
+        assert_eq!(channel_gains(0, 0.0, 0.0), [1.0, 1.0]);
+        assert_eq!(channel_gains(50, 0.0, 0.0), [0.5, 1.0]);
+        assert_eq!(channel_gains(-100, 0.0, 0.0), [1.0, 0.0]);
+        assert_eq!(channel_gains(-500, 0.0, 0.0), [1.0, 0.0]);
+        let [left, right] = channel_gains(0, -6.0, 20.0);
+        assert!((left - 0.501).abs() < 0.001);
+        // limited to +6 dB
+        assert!((right - 1.995).abs() < 0.001);
+    }
+}
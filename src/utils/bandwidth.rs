@@ -0,0 +1,73 @@
+//! The estimated network bandwidth of a stream, for the format advisor of the GUI.
+//!
+//! LPCM, WAV and RF64 send the raw samples, FLAC compresses typical music to about 60%.
+//! Streams above 3 Mbps are often too much for cheap WiFi renderers with a weak signal.
+
+use crate::enums::streaming::StreamingFormat;
+
+/// the typical FLAC compression ratio of music
+const FLAC_RATIO: f64 = 0.6;
+/// the bitrate above which a WiFi renderer may drop out
+pub const WIFI_WARNING_BPS: f64 = 3_000_000.0;
+
+/// the estimated bitrate of a stream in bits per second
+#[must_use]
+pub fn estimated_bitrate(
+    format: StreamingFormat,
+    bits_per_sample: u16,
+    sample_rate: u32,
+    channels: u16,
+) -> f64 {
+    let raw = f64::from(bits_per_sample) * f64::from(sample_rate) * f64::from(channels);
+    match format {
+        StreamingFormat::Flac => raw * FLAC_RATIO,
+        StreamingFormat::Lpcm | StreamingFormat::Wav | StreamingFormat::Rf64 => raw,
+    }
+}
+
+/// the advice for the configured stream, e.g. "FLAC 16/44.1 ~0.8 Mbps, WAV ~1.4 Mbps",
+/// and if the bitrate may be too much for WiFi
+#[must_use]
+pub fn bitrate_advice(
+    format: StreamingFormat,
+    bits_per_sample: u16,
+    sample_rate: u32,
+    channels: u16,
+) -> (String, bool) {
+    let bitrate = estimated_bitrate(format, bits_per_sample, sample_rate, channels);
+    // compare with the alternative: uncompressed or FLAC
+    let other = if format == StreamingFormat::Flac {
+        StreamingFormat::Wav
+    } else {
+        StreamingFormat::Flac
+    };
+    let other_bitrate = estimated_bitrate(other, bits_per_sample, sample_rate, channels);
+    let advice = format!(
+        "{} {bits_per_sample}/{} ~{:.1} Mbps, {} ~{:.1} Mbps",
+        format.to_string().to_uppercase(),
+        f64::from(sample_rate) / 1000.0,
+        bitrate / 1e6,
+        other.to_string().to_uppercase(),
+        other_bitrate / 1e6,
+    );
+    (advice, bitrate > WIFI_WARNING_BPS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitrate_advice() {
+        assert_eq!(
+            estimated_bitrate(StreamingFormat::Wav, 16, 44100, 2),
+            1_411_200.0
+        );
+        let (advice, warn) = bitrate_advice(StreamingFormat::Flac, 16, 44100, 2);
+        assert_eq!(advice, "FLAC 16/44.1 ~0.8 Mbps, WAV ~1.4 Mbps");
+        assert!(!warn);
+        let (advice, warn) = bitrate_advice(StreamingFormat::Wav, 24, 96000, 2);
+        assert_eq!(advice, "WAV 24/96 ~4.6 Mbps, FLAC ~2.8 Mbps");
+        assert!(warn);
+    }
+}
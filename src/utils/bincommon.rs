@@ -4,9 +4,71 @@ use cpal::{
     traits::{DeviceTrait, StreamTrait},
     Sample, SampleFormat, Stream, StreamConfig,
 };
+use crossbeam_channel::Sender;
+use hashbrown::HashMap;
 use log::warn;
+use parking_lot::Mutex;
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    audiodevices::{
+        capture_output_audio, get_default_audio_output_device, get_output_audio_devices, Device,
+    },
+    capture_errors::{report_capture_error, CaptureError},
+    ui_logger::ui_log,
+};
+use crate::{
+    enums::streaming::StreamingFormat,
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::rendercontrol::{renderer_available, Renderer},
+    server::streaming_server::StreamerFeedBack,
+};
+
+/// is silence being injected, by the "Inject silence" option or for a renderer quirk
+static SILENCE_INJECTED: AtomicBool = AtomicBool::new(false);
+
+/// a renderer that drops the connection within this time does not support the format
+const FALLBACK_STREAMING_TIME: Duration = Duration::from_secs(3);
+
+/// the fallback formats that are being tried, by renderer name, until they have played
+static FALLBACK_TRIALS: LazyLock<Mutex<HashMap<String, StreamingFormat>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
 
-use super::audiodevices::Device;
+/// the fallback format that is being tried for a renderer, if any
+#[must_use]
+pub fn trial_format(dev_name: &str) -> Option<StreamingFormat> {
+    FALLBACK_TRIALS.lock().get(dev_name).copied()
+}
+
+/// Start capturing audio from the selected audio source.
+///
+/// The capture stream is returned to keep it alive, dropping it stops the capture.
+/// With the "capture on demand" option this is only done when the first client connects.
+pub fn start_audio_capture(device: &Device, rms_sender: Sender<Vec<f32>>) -> Option<Stream> {
+    let Some(stream) = capture_output_audio(device, rms_sender) else {
+        ui_log("*E*E*> Could not capture audio ...Please check configuration.");
+        return None;
+    };
+    match stream.play() {
+        Ok(()) => Some(stream),
+        Err(e) => {
+            report_capture_error(
+                CaptureError::from_play_error(&e),
+                device.name(),
+                &format!("Could not start audio capture: {e}"),
+            );
+            None
+        }
+    }
+}
 
 /// Inject silence into the audio stream to solve problems with Sonos when pausing audio.
 /// contributed by @genekellyjr, see issue #71
@@ -21,6 +83,7 @@ pub fn run_silence_injector(device: &Device) -> Stream {
         }
     }
 
+    SILENCE_INJECTED.store(true, Ordering::Release);
     let config = device.default_config();
     let sample_format = config.sample_format();
     let err_fn = |err| warn!("an error occurred on the output audio stream: {err}");
@@ -43,3 +106,211 @@ pub fn run_silence_injector(device: &Device) -> Stream {
         .expect("Unable to inject silence into the output stream");
     stream
 }
+
+/// Start injecting silence into the selected audio source for a renderer that needs it
+/// (the `inject_silence` quirk, e.g. Sonos), unless silence is already being injected.
+///
+/// The silence stream is kept alive by its own thread for the lifetime of the app.
+pub fn start_silence_injector() {
+    if SILENCE_INJECTED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let (source, source_index) = {
+        let conf = CONFIG.read();
+        (conf.sound_source.clone(), conf.sound_source_index)
+    };
+    let _ = thread::Builder::new()
+        .name("silence_injector".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            // the same audio source as the capture, see the main function of the app
+            let device = get_output_audio_devices()
+                .into_iter()
+                .enumerate()
+                .find(|(index, d)| {
+                    source.as_deref() == Some(d.name())
+                        && source_index.is_none_or(|i| i == *index as i32)
+                })
+                .map(|(_, d)| d)
+                .or_else(get_default_audio_output_device);
+            let Some(device) = device else {
+                ui_log("*W*W*> No audio device found to inject silence into");
+                return;
+            };
+            ui_log(&format!("Injecting silence into {}", device.name()));
+            let _stream = run_silence_injector(&device);
+            loop {
+                thread::park();
+            }
+        });
+}
+
+/// Select the next format of the configured fallback chain when a renderer has dropped
+/// the connection shortly after it started streaming (the classic Sonos FLAC symptom).
+/// Only call it when the stream ended by itself, not when it was stopped.
+///
+/// The new format (with 16 bit samples) is tried by `Renderer::play`, it is only
+/// remembered for the renderer in the config once it has played, see
+/// `confirm_fallback_format`.
+pub fn next_fallback_format(
+    renderer: &Renderer,
+    feedback: &StreamerFeedBack,
+) -> Option<StreamingFormat> {
+    let next = {
+        let conf = CONFIG.read();
+        let chain = &conf.format_fallback;
+        if chain.is_empty() || feedback.streaming_time >= FALLBACK_STREAMING_TIME {
+            return None;
+        }
+        match chain.iter().position(|f| *f == feedback.streaming_format) {
+            Some(i) => chain.get(i + 1).copied(),
+            None => chain.first().copied(),
+        }
+    };
+    let Some(format) = next else {
+        FALLBACK_TRIALS.lock().remove(&renderer.dev_name);
+        ui_log(&format!(
+            "*W*W*> {} dropped {} after {} msecs, no more fallback formats left",
+            renderer.dev_name,
+            feedback.streaming_format,
+            feedback.streaming_time.as_millis()
+        ));
+        return None;
+    };
+    ui_log(&format!(
+        "*W*W*> {} dropped {} after {} msecs, falling back to {format} 16 bit",
+        renderer.dev_name,
+        feedback.streaming_format,
+        feedback.streaming_time.as_millis()
+    ));
+    FALLBACK_TRIALS
+        .lock()
+        .insert(renderer.dev_name.clone(), format);
+    Some(format)
+}
+
+/// Remember the fallback format that is being tried for a renderer in the config, once
+/// its stream has lasted longer than a dropped connection. Returns true if it was remembered.
+pub fn confirm_fallback_format(renderer: &Renderer, feedback: &StreamerFeedBack) -> bool {
+    if feedback.streaming_time < FALLBACK_STREAMING_TIME {
+        return false;
+    }
+    let mut trials = FALLBACK_TRIALS.lock();
+    if trials.get(&renderer.dev_name) != Some(&feedback.streaming_format) {
+        return false;
+    }
+    trials.remove(&renderer.dev_name);
+    drop(trials);
+    ui_log(&format!(
+        "{} plays {} 16 bit, using it for {} from now on",
+        renderer.dev_name, feedback.streaming_format, renderer.dev_name
+    ));
+    let mut conf = CONFIG.write();
+    let settings = conf.renderers.entry(renderer.dev_name.clone()).or_default();
+    settings.streaming_format = Some(feedback.streaming_format);
+    settings.bits_per_sample = Some(16);
+    let _ = conf.update_config();
+    true
+}
+
+/// Remember the format that a renderer pulled when it differs from the format that swyh-rs
+/// would use for it, e.g. when the /stream/swyh.flac url was selected in the renderer's app.
+///
+/// The format is remembered for the renderer in the config, so that `Renderer::play` builds
+/// the matching url and DIDL from now on. Returns true if the format was remembered.
+pub fn remember_pulled_format(renderer: &Renderer, feedback: &StreamerFeedBack) -> bool {
+    let configured = CONFIG
+        .read()
+        .streaming_format
+        .unwrap_or(StreamingFormat::Flac);
+    let expected = renderer.streaming_format(configured);
+    if feedback.streaming_format == expected {
+        return false;
+    }
+    ui_log(&format!(
+        "{} pulled {} instead of {expected}, using {} for it from now on",
+        renderer.dev_name, feedback.streaming_format, feedback.streaming_format
+    ));
+    let mut conf = CONFIG.write();
+    let settings = conf.renderers.entry(renderer.dev_name.clone()).or_default();
+    settings.streaming_format = Some(feedback.streaming_format);
+    let _ = conf.update_config();
+    true
+}
+
+/// Wait for the next periodic SSDP discovery.
+///
+/// With the `ssdp_pause_while_streaming` option a discovery that is due while a renderer
+/// is streaming is postponed until the streaming stops, because the multicast burst can
+/// make WiFi renderers glitch. It then runs immediately.
+pub fn wait_for_ssdp_discovery(interval: Duration) {
+    const POLL: Duration = Duration::from_secs(1);
+    let due = Instant::now() + interval;
+    let mut paused = false;
+    loop {
+        let now = Instant::now();
+        if now < due {
+            thread::sleep((due - now).min(POLL));
+            continue;
+        }
+        if !CONFIG.read().ssdp_pause_while_streaming || CLIENTS.read().is_empty() {
+            break;
+        }
+        if !paused {
+            ui_log("Streaming: SSDP discovery paused until streaming stops");
+            paused = true;
+        }
+        thread::sleep(POLL);
+    }
+    if paused {
+        ui_log("Streaming stopped: resuming SSDP discovery");
+    }
+}
+
+/// The unicast refresh of the known renderers between the multicast SSDP discoveries.
+///
+/// With `ssdp_unicast_refresh = n` in the config only every n-th periodic discovery is a
+/// multicast sweep, the other ones fetch the device description of the known renderers.
+/// A renderer that stops responding forces a multicast sweep at the next discovery,
+/// in case it came back at another location.
+#[derive(Debug, Default)]
+pub struct SsdpRefresh {
+    round: u32,
+    sweep: bool,
+    offline: HashSet<String>,
+}
+
+impl SsdpRefresh {
+    /// is a multicast sweep due for this discovery, the first one always is
+    pub fn multicast_due(&mut self) -> bool {
+        let every = CONFIG.read().ssdp_unicast_refresh.unwrap_or(1).max(1);
+        let due = self.round % every == 0 || std::mem::take(&mut self.sweep);
+        self.round = self.round.wrapping_add(1);
+        due
+    }
+
+    /// check that the known renderers still respond, returns the locations of the renderers
+    /// that stopped responding (false) or respond again (true)
+    pub fn refresh<'a>(
+        &mut self,
+        renderers: impl Iterator<Item = &'a Renderer>,
+    ) -> Vec<(String, bool)> {
+        let mut changed = Vec::new();
+        for r in renderers {
+            if renderer_available(&r.location) {
+                if self.offline.remove(&r.location) {
+                    ui_log(&format!("Renderer {} is available again", r.dev_name));
+                    changed.push((r.location.clone(), true));
+                }
+            } else if self.offline.insert(r.location.clone()) {
+                ui_log(&format!(
+                    "*W*W*> Renderer {} at {} is not responding",
+                    r.dev_name, r.location
+                ));
+                self.sweep = true;
+                changed.push((r.location.clone(), false));
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,103 @@
+//! Bit-perfect mode: no DSP (balance, trim, channel mode, DSP chain) is applied to the
+//! captured samples, and the delivery to a client is checked for conversions.
+//!
+//! The stream always has the sample rate of the capture device, so delivery is bit-perfect
+//! when integer samples are captured and nothing changes them on the way to the client.
+//! The f32 samples of the `wave_reader` are exact for 16 bit integer samples, and sending them
+//! as 24 bit only pads them with zero bits.
+
+use cpal::SampleFormat;
+use parking_lot::RwLock;
+
+use crate::{
+    dsp::chain::dsp_chain_active, enums::streaming::ChannelMode, globals::statics::CONFIG,
+    utils::balance::channel_gains_active,
+};
+
+/// the sample format of the running audio capture
+static CAPTURE_FORMAT: RwLock<Option<SampleFormat>> = RwLock::new(None);
+
+/// remember the sample format of the audio capture
+pub fn set_capture_format(sample_format: SampleFormat) {
+    *CAPTURE_FORMAT.write() = Some(sample_format);
+}
+
+/// the sample format of the audio capture, if it has started
+#[must_use]
+pub fn capture_format() -> Option<SampleFormat> {
+    *CAPTURE_FORMAT.read()
+}
+
+/// the conversions between the captured samples and a client stream, none if bit-perfect
+#[must_use]
+pub fn conversions(
+    capture_format: Option<SampleFormat>,
+    bits_per_sample: u16,
+    channel_mode: ChannelMode,
+    gains_active: bool,
+) -> Vec<String> {
+    let mut conversions = Vec::new();
+    match capture_format {
+        Some(SampleFormat::I16 | SampleFormat::U16) => (),
+        Some(format) => {
+            conversions.push(format!("{format:?} samples to {bits_per_sample} bit"));
+        }
+        None => conversions.push("unknown capture format".to_string()),
+    }
+    if channel_mode != ChannelMode::Stereo {
+        conversions.push(format!("channel mode {channel_mode}"));
+    }
+    if gains_active {
+        conversions.push("balance/trim gain".to_string());
+    }
+    conversions
+}
+
+/// the conversions for a client stream with the current capture and settings
+#[must_use]
+pub fn stream_conversions(bits_per_sample: u16, channel_mode: ChannelMode) -> Vec<String> {
+    let mut conversions = conversions(
+        capture_format(),
+        bits_per_sample,
+        channel_mode,
+        channel_gains_active(),
+    );
+    if dsp_chain_active() {
+        conversions.push("DSP chain".to_string());
+    }
+    conversions
+}
+
+/// the channel mode of a client stream, always stereo in bit-perfect mode
+#[must_use]
+pub fn enforce_channel_mode(channel_mode: ChannelMode) -> ChannelMode {
+    if CONFIG.read().bit_perfect {
+        ChannelMode::Stereo
+    } else {
+        channel_mode
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let i16 = Some(SampleFormat::I16);
+        assert!(conversions(i16, 16, ChannelMode::Stereo, false).is_empty());
+        assert!(conversions(i16, 24, ChannelMode::Stereo, false).is_empty());
+        assert_eq!(
+            conversions(Some(SampleFormat::F32), 24, ChannelMode::Stereo, false),
+            vec!["F32 samples to 24 bit".to_string()]
+        );
+        assert_eq!(
+            conversions(i16, 16, ChannelMode::Mono, true),
+            vec![
+                "channel mode Mono".to_string(),
+                "balance/trim gain".to_string()
+            ]
+        );
+        assert_eq!(conversions(None, 16, ChannelMode::Stereo, false).len(), 1);
+    }
+}
@@ -0,0 +1,152 @@
+//! The mouse gestures on the renderer buttons in the GUI. A click starts or stops playing,
+//! a double click, a middle click and a right click each do an action that can be changed
+//! in the `[configuration.button_gestures]` table of the config file, e.g.
+//!
+//! ```toml
+//! [configuration.button_gestures]
+//! double_click = "None"
+//! middle_click = "Test"
+//! ```
+//!
+//! The actions are `None`, `Menu` (the renderer menu), `Restart` (restart the stream),
+//! `Mute` (mute or unmute), `Test` (the connection test) and `Hide` (hide the button). By
+//! default a double click restarts the stream, a middle click mutes and a right click pops
+//! up the renderer menu. Shift+F10 or the context menu key always pops up the menu.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::utils::configuration::ButtonGestures;
+
+/// the action of a mouse gesture on a renderer button
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ButtonAction {
+    None,
+    Menu,
+    Restart,
+    Mute,
+    Test,
+    Hide,
+}
+
+impl fmt::Display for ButtonAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl ButtonAction {
+    /// what the action does, for the button tooltip
+    fn help(self) -> &'static str {
+        match self {
+            ButtonAction::None => "",
+            ButtonAction::Menu => "for the renderer menu",
+            ButtonAction::Restart => "to restart the stream",
+            ButtonAction::Mute => "to mute or unmute",
+            ButtonAction::Test => "to test the connection",
+            ButtonAction::Hide => "to hide the renderer",
+        }
+    }
+}
+
+/// a mouse gesture on a renderer button
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Gesture {
+    DoubleClick,
+    MiddleClick,
+    RightClick,
+}
+
+impl Gesture {
+    const ALL: [Gesture; 3] = [
+        Gesture::DoubleClick,
+        Gesture::MiddleClick,
+        Gesture::RightClick,
+    ];
+
+    fn default_action(self) -> ButtonAction {
+        match self {
+            Gesture::DoubleClick => ButtonAction::Restart,
+            Gesture::MiddleClick => ButtonAction::Mute,
+            Gesture::RightClick => ButtonAction::Menu,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Gesture::DoubleClick => "double click",
+            Gesture::MiddleClick => "middle click",
+            Gesture::RightClick => "right click",
+        }
+    }
+}
+
+/// the configured action of a gesture
+#[must_use]
+pub fn gesture_action(gestures: &ButtonGestures, gesture: Gesture) -> ButtonAction {
+    let configured = match gesture {
+        Gesture::DoubleClick => gestures.double_click,
+        Gesture::MiddleClick => gestures.middle_click,
+        Gesture::RightClick => gestures.right_click,
+    };
+    configured.unwrap_or_else(|| gesture.default_action())
+}
+
+/// the gestures with an action for the button tooltip, e.g. "double click to restart the
+/// stream, middle click to mute or unmute, right click or Shift+F10 for the renderer menu"
+#[must_use]
+pub fn gestures_help(gestures: &ButtonGestures) -> String {
+    let mut help: Vec<String> = Gesture::ALL
+        .iter()
+        .map(|&g| (g, gesture_action(gestures, g)))
+        .filter(|(_, action)| *action != ButtonAction::None)
+        .map(|(g, action)| {
+            if action == ButtonAction::Menu {
+                format!("{} or Shift+F10 {}", g.name(), action.help())
+            } else {
+                format!("{} {}", g.name(), action.help())
+            }
+        })
+        .collect();
+    if !Gesture::ALL
+        .iter()
+        .any(|&g| gesture_action(gestures, g) == ButtonAction::Menu)
+    {
+        help.push(format!("Shift+F10 {}", ButtonAction::Menu.help()));
+    }
+    help.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_button_gestures() {
+        let mut gestures = ButtonGestures::default();
+        assert_eq!(
+            gesture_action(&gestures, Gesture::DoubleClick),
+            ButtonAction::Restart
+        );
+        assert_eq!(
+            gesture_action(&gestures, Gesture::RightClick),
+            ButtonAction::Menu
+        );
+        assert_eq!(
+            gestures_help(&gestures),
+            "double click to restart the stream, middle click to mute or unmute, \
+            right click or Shift+F10 for the renderer menu"
+        );
+        gestures.double_click = Some(ButtonAction::None);
+        gestures.right_click = Some(ButtonAction::Test);
+        assert_eq!(
+            gesture_action(&gestures, Gesture::DoubleClick),
+            ButtonAction::None
+        );
+        assert_eq!(
+            gestures_help(&gestures),
+            "middle click to mute or unmute, right click to test the connection, \
+            Shift+F10 for the renderer menu"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Classify the audio capture errors and tell the user what to do about them.
+//!
+//! The cpal errors are often backend specific texts like "AUDCLNT_E_DEVICE_IN_USE" or
+//! "Device or resource busy", that mean nothing to most users. The common causes are
+//! recognized, and a hint to fix them is logged and shown as a notification, instead of
+//! only the error.
+
+use cpal::{BuildStreamError, PlayStreamError, StreamError};
+
+use super::{
+    hooks::{run_hooks, HookEvent},
+    notify::{notify, NotifyEvent},
+    ui_logger::ui_log,
+};
+
+/// the cause of an audio capture error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureError {
+    /// the device was unplugged or disabled
+    DeviceUnplugged,
+    /// the format of the device was changed, or is not supported
+    FormatChanged,
+    /// the device is used in exclusive mode by another application
+    ExclusiveUse,
+    /// access to the device is not allowed
+    AccessDenied,
+    /// anything else
+    Other,
+}
+
+impl CaptureError {
+    /// classify an error that occurs while capturing
+    #[must_use]
+    pub fn from_stream_error(err: &StreamError) -> CaptureError {
+        match err {
+            StreamError::DeviceNotAvailable => CaptureError::DeviceUnplugged,
+            StreamError::BackendSpecific { err } => classify_message(&err.description),
+        }
+    }
+
+    /// classify an error building the capture stream
+    #[must_use]
+    pub fn from_build_error(err: &BuildStreamError) -> CaptureError {
+        match err {
+            BuildStreamError::DeviceNotAvailable => CaptureError::DeviceUnplugged,
+            BuildStreamError::StreamConfigNotSupported => CaptureError::FormatChanged,
+            BuildStreamError::BackendSpecific { err } => classify_message(&err.description),
+            _ => CaptureError::Other,
+        }
+    }
+
+    /// classify an error starting the capture stream
+    #[must_use]
+    pub fn from_play_error(err: &PlayStreamError) -> CaptureError {
+        match err {
+            PlayStreamError::DeviceNotAvailable => CaptureError::DeviceUnplugged,
+            PlayStreamError::BackendSpecific { err } => classify_message(&err.description),
+        }
+    }
+
+    /// what the user can do about it, None if we don't know
+    #[must_use]
+    pub fn hint(self, device: &str) -> Option<String> {
+        match self {
+            CaptureError::DeviceUnplugged => Some(format!(
+                "The audio source {device} was unplugged or disabled, reconnect or enable it, or select another audio source"
+            )),
+            CaptureError::FormatChanged => Some(format!(
+                "The format of the audio source {device} was changed or is not supported, restart swyh-rs after changing the sample rate or bit depth of the device"
+            )),
+            CaptureError::ExclusiveUse => Some(format!(
+                "The audio source {device} is in use in exclusive mode by another application (e.g. foobar2000 or a DAW with WASAPI exclusive or ASIO output), switch it to shared mode or allow only shared mode in the sound settings of the device"
+            )),
+            CaptureError::AccessDenied => Some(format!(
+                "Access to the audio source {device} is denied, allow apps to access the microphone in the privacy settings (Windows), or add your user to the audio group (Linux)"
+            )),
+            CaptureError::Other => None,
+        }
+    }
+}
+
+/// the WASAPI HRESULT names and codes
+const WASAPI_ERRORS: &[(&str, CaptureError)] = &[
+    ("audclnt_e_device_in_use", CaptureError::ExclusiveUse),
+    ("0x8889000a", CaptureError::ExclusiveUse),
+    (
+        "audclnt_e_exclusive_mode_not_allowed",
+        CaptureError::ExclusiveUse,
+    ),
+    ("0x8889000e", CaptureError::ExclusiveUse),
+    ("e_accessdenied", CaptureError::AccessDenied),
+    ("0x80070005", CaptureError::AccessDenied),
+    (
+        "audclnt_e_device_invalidated",
+        CaptureError::DeviceUnplugged,
+    ),
+    ("0x88890004", CaptureError::DeviceUnplugged),
+    ("audclnt_e_unsupported_format", CaptureError::FormatChanged),
+    ("0x88890008", CaptureError::FormatChanged),
+];
+
+/// the errno names in an "ALSA function '...' failed with error 'EBUSY: ...'" message
+const ALSA_ERRORS: &[(&str, CaptureError)] = &[
+    ("'ebusy", CaptureError::ExclusiveUse),
+    ("'eacces", CaptureError::AccessDenied),
+    ("'eperm", CaptureError::AccessDenied),
+    ("'enodev", CaptureError::DeviceUnplugged),
+    ("'enxio", CaptureError::DeviceUnplugged),
+    ("'einval", CaptureError::FormatChanged),
+];
+
+/// the CoreAudio OSStatus names and four character codes
+const COREAUDIO_ERRORS: &[(&str, CaptureError)] = &[
+    ("kaudiodevicepermissionserror", CaptureError::ExclusiveUse),
+    ("'!hog'", CaptureError::ExclusiveUse),
+    (
+        "kaudiohardwarebaddeviceerror",
+        CaptureError::DeviceUnplugged,
+    ),
+    ("'!dev'", CaptureError::DeviceUnplugged),
+    (
+        "kaudiodeviceunsupportedformaterror",
+        CaptureError::FormatChanged,
+    ),
+    ("'!dat'", CaptureError::FormatChanged),
+];
+
+/// classify a backend specific error by the WASAPI, ALSA or CoreAudio error in its text,
+/// general words like "busy" or "format" are not enough
+fn classify_message(message: &str) -> CaptureError {
+    let message = message.to_lowercase();
+    let alsa: &[(&str, CaptureError)] = if message.starts_with("alsa function") {
+        ALSA_ERRORS
+    } else {
+        &[]
+    };
+    WASAPI_ERRORS
+        .iter()
+        .chain(COREAUDIO_ERRORS)
+        .chain(alsa)
+        .find(|(id, _)| message.contains(id))
+        .map_or(CaptureError::Other, |(_, kind)| *kind)
+}
+
+/// log an audio capture error with the hint to fix it, and show it as a notification
+pub fn report_capture_error(kind: CaptureError, device: &str, error: &str) {
+    ui_log(&format!("*E*E*> {error}"));
+    let message = if let Some(hint) = kind.hint(device) {
+        ui_log(&format!("*E*E*> {hint}"));
+        hint
+    } else {
+        format!("Audio capture error: {error}")
+    };
+    notify(NotifyEvent::CaptureLost, &message);
+    run_hooks(
+        HookEvent::CaptureLost,
+        &[("device", device.to_string()), ("message", message)],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_message() {
+        assert_eq!(
+            classify_message("AUDCLNT_E_DEVICE_IN_USE (0x8889000A)"),
+            CaptureError::ExclusiveUse
+        );
+        assert_eq!(
+            classify_message(
+                "ALSA function 'snd_pcm_open' failed with error 'EBUSY: Device or resource busy'"
+            ),
+            CaptureError::ExclusiveUse
+        );
+        assert_eq!(
+            classify_message("Access is denied. (0x80070005)"),
+            CaptureError::AccessDenied
+        );
+        assert_eq!(
+            classify_message("AUDCLNT_E_DEVICE_INVALIDATED"),
+            CaptureError::DeviceUnplugged
+        );
+        assert_eq!(
+            classify_message("AUDCLNT_E_UNSUPPORTED_FORMAT"),
+            CaptureError::FormatChanged
+        );
+        assert_eq!(
+            classify_message(
+                "ALSA function 'snd_pcm_hw_params' failed with error 'EINVAL: Invalid argument'"
+            ),
+            CaptureError::FormatChanged
+        );
+        assert_eq!(classify_message("buffer underrun"), CaptureError::Other);
+        // general words don't classify an error
+        assert_eq!(
+            classify_message("the sample format is not available"),
+            CaptureError::Other
+        );
+        assert_eq!(
+            classify_message("EBUSY: the device is busy"),
+            CaptureError::Other
+        );
+        assert!(CaptureError::Other.hint("Speakers").is_none());
+        assert!(CaptureError::ExclusiveUse
+            .hint("Speakers")
+            .unwrap()
+            .contains("shared mode"));
+    }
+}
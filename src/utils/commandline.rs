@@ -2,7 +2,7 @@
 use std::net::IpAddr;
 
 use lexopt::{
-    Arg::{Long, Short},
+    Arg::{Long, Short, Value},
     Parser, ValueExt,
 };
 use log::LevelFilter;
@@ -30,6 +30,28 @@ pub struct Args {
     pub serve_only: Option<bool>,
     pub volume: Option<u8>,
     pub upfront_buffer: Option<u32>,
+    pub capture_on_demand: Option<bool>,
+    pub preroll_msec: Option<u32>,
+    pub monitor_device: Option<String>,
+    pub monitor_delay_msec: Option<u32>,
+    pub track_gap_msec: Option<u32>,
+    pub record_dir: Option<String>,
+    pub rtp_destination: Option<String>,
+    pub balance: Option<i32>,
+    pub trim_left_db: Option<f32>,
+    pub trim_right_db: Option<f32>,
+    pub bit_perfect: Option<bool>,
+    pub dither: Option<Dither>,
+    pub output: Option<String>,
+    pub network_source: Option<String>,
+    pub export_renderers: Option<String>,
+    pub import_renderers: Option<String>,
+    pub disconnect: Option<String>,
+    pub session: Option<String>,
+    pub audio_host: Option<String>,
+    pub verify_stream: Option<bool>,
+    pub selftest: Option<bool>,
+    pub config_command: Option<String>,
 }
 
 impl Default for Args {
@@ -61,6 +83,28 @@ impl Args {
             serve_only: None,
             volume: None,
             upfront_buffer: None,
+            capture_on_demand: None,
+            preroll_msec: None,
+            monitor_device: None,
+            monitor_delay_msec: None,
+            track_gap_msec: None,
+            record_dir: None,
+            rtp_destination: None,
+            balance: None,
+            trim_left_db: None,
+            trim_right_db: None,
+            bit_perfect: None,
+            dither: None,
+            output: None,
+            network_source: None,
+            export_renderers: None,
+            import_renderers: None,
+            disconnect: None,
+            session: None,
+            audio_host: None,
+            verify_stream: None,
+            selftest: None,
+            config_command: None,
         }
     }
 
@@ -68,6 +112,12 @@ impl Args {
     fn usage(&self) {
         println!(
             r#"
+Usage: swyh-rs-cli [options] | swyh-rs-cli selftest | swyh-rs-cli config show
+
+    selftest : check the audio devices, the streaming server and SSDP without a renderer,
+               exits with 0 if all checks pass, or the number of the failed check (1-4)
+    config show : print the effective config, the user config laid over the system config
+
 Recognized options:
     -h (--help) : print usage
     -n (--no_run) : dry-run, don't start streaming
@@ -87,6 +137,26 @@ Recognized options:
     -x (--serve_only) bool: only run the music server, no ssdp discovery [false]
     -v (--volume) u8 : desired player volume between 0 and 100 [unchanged]
     -u (--upfront_buffer) u32 : initial buffering in milliseconds [0]
+    -d (--capture_on_demand) bool : only capture audio while a client is streaming [false]
+    -P (--preroll) u32 : pre-roll audio sent to new clients in milliseconds [0]
+    -m (--monitor) string : name of a local output device that plays the outgoing audio [none]
+    -M (--monitor_delay) u32 : playback monitor delay in milliseconds [0]
+    -g (--track_gap) u32 : silence in milliseconds that starts a new track, 0 = no track detection [0]
+    -R (--record_dir) string : record to this directory, one file per detected track [no recording]
+    -t (--rtp) string : also send RTP to this unicast or multicast ip:port [none]
+    -B (--balance) i32 : balance between -100 (left) and 100 (right) [0]
+    -L (--trim_left) f32 : left channel gain trim in dB between -12 and 6 [0]
+    -T (--trim_right) f32 : right channel gain trim in dB between -12 and 6 [0]
+    -k (--bit_perfect) bool : bit-perfect mode, no balance, trim or channel mode [false]
+    -D (--dither) string : dither when reducing to 16 bit (off/tpdf/shaped) [off]
+    -O (--output) string : also write the stream to a named pipe or file, or stdout (-) [none]
+    -N (--network_source) string : use the WAV stream of another swyh-rs instance as audio source [none]
+    -E (--export_renderers) string : export the discovered renderers to a JSON file [none]
+    -I (--import_renderers) string : add the renderers in an exported JSON file to the discovered renderers [none]
+    -X (--disconnect) string : disconnect a streaming client (ip address) of the running swyh-rs on -e and -p, and exit [none]
+    -Y (--session) string : start the renderers of a session preset with their volume and format [none]
+    -H (--audio_host) string : only use the audio devices of this audio host (e.g. ALSA or JACK), all = all hosts [all]
+    -V (--verify_stream) bool : verify the own stream for 10 seconds after starting and log the outcome [false]
 "#
         );
         println!("{self:?}");
@@ -268,10 +338,140 @@ Recognized options:
                         self.upfront_buffer = Some(b);
                     }
                 }
+                Short('d') | Long("capture_on_demand") => {
+                    if let Ok(on_demand) = argparser.value() {
+                        self.capture_on_demand =
+                            Some(on_demand.string().unwrap().sanitize_bool().parse().unwrap());
+                    } else {
+                        self.capture_on_demand = Some(true);
+                    }
+                }
+                Short('P') | Long("preroll") => {
+                    if let Ok(preroll) = argparser.value() {
+                        let p: u32 = preroll.parse().unwrap();
+                        self.preroll_msec = Some(p);
+                    }
+                }
+                Short('m') | Long("monitor") => {
+                    if let Ok(device) = argparser.value() {
+                        self.monitor_device = Some(device.string().unwrap_or_default());
+                    }
+                }
+                Short('M') | Long("monitor_delay") => {
+                    if let Ok(delay) = argparser.value() {
+                        let d: u32 = delay.parse().unwrap();
+                        self.monitor_delay_msec = Some(d);
+                    }
+                }
+                Short('g') | Long("track_gap") => {
+                    if let Ok(gap) = argparser.value() {
+                        let g: u32 = gap.parse().unwrap();
+                        self.track_gap_msec = Some(g);
+                    }
+                }
+                Short('R') | Long("record_dir") => {
+                    if let Ok(dir) = argparser.value() {
+                        self.record_dir = Some(dir.string().unwrap_or_default());
+                    }
+                }
+                Short('t') | Long("rtp") => {
+                    if let Ok(dest) = argparser.value() {
+                        self.rtp_destination = Some(dest.string().unwrap_or_default());
+                    }
+                }
+                Short('B') | Long("balance") => {
+                    if let Ok(balance) = argparser.value() {
+                        let b: i32 = balance.parse().unwrap();
+                        self.balance = Some(b);
+                    }
+                }
+                Short('L') | Long("trim_left") => {
+                    if let Ok(trim) = argparser.value() {
+                        let t: f32 = trim.parse().unwrap();
+                        self.trim_left_db = Some(t);
+                    }
+                }
+                Short('T') | Long("trim_right") => {
+                    if let Ok(trim) = argparser.value() {
+                        let t: f32 = trim.parse().unwrap();
+                        self.trim_right_db = Some(t);
+                    }
+                }
+                Short('k') | Long("bit_perfect") => {
+                    if let Ok(bit_perfect) = argparser.value() {
+                        self.bit_perfect = Some(
+                            bit_perfect
+                                .string()
+                                .unwrap()
+                                .sanitize_bool()
+                                .parse()
+                                .unwrap(),
+                        );
+                    } else {
+                        self.bit_perfect = Some(true);
+                    }
+                }
+                Short('D') | Long("dither") => {
+                    if let Ok(dither) = argparser.value() {
+                        let d = dither.string().unwrap_or_default();
+                        self.dither = d.parse().ok();
+                    }
+                }
+                Short('O') | Long("output") => {
+                    if let Ok(output) = argparser.value() {
+                        self.output = Some(output.string().unwrap_or_default());
+                    }
+                }
+                Short('N') | Long("network_source") => {
+                    if let Ok(url) = argparser.value() {
+                        self.network_source = Some(url.string().unwrap_or_default());
+                    }
+                }
+                Short('E') | Long("export_renderers") => {
+                    if let Ok(file) = argparser.value() {
+                        self.export_renderers = Some(file.string().unwrap_or_default());
+                    }
+                }
+                Short('I') | Long("import_renderers") => {
+                    if let Ok(file) = argparser.value() {
+                        self.import_renderers = Some(file.string().unwrap_or_default());
+                    }
+                }
+                Short('X') | Long("disconnect") => {
+                    if let Ok(client) = argparser.value() {
+                        self.disconnect = Some(client.string().unwrap_or_default());
+                    }
+                }
+                Short('Y') | Long("session") => {
+                    if let Ok(name) = argparser.value() {
+                        self.session = Some(name.string().unwrap_or_default());
+                    }
+                }
+                Short('H') | Long("audio_host") => {
+                    if let Ok(host) = argparser.value() {
+                        self.audio_host = Some(host.string().unwrap_or_default());
+                    }
+                }
+                Short('V') | Long("verify_stream") => {
+                    if let Ok(verify) = argparser.value() {
+                        self.verify_stream =
+                            Some(verify.string().unwrap().sanitize_bool().parse().unwrap());
+                    } else {
+                        self.verify_stream = Some(true);
+                    }
+                }
+                Value(cmd) if cmd == "selftest" => {
+                    self.selftest = Some(true);
+                }
+                Value(cmd) if cmd == "config" => {
+                    let command = argparser.value().ok().and_then(|c| c.string().ok());
+                    self.config_command = Some(command.unwrap_or_else(|| "show".to_string()));
+                }
                 _ => (),
             }
         }
-        println!("{self:?}\n");
+        // stderr, stdout may be the stream output
+        eprintln!("{self:?}\n");
         self.clone()
     }
 }
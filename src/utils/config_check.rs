@@ -0,0 +1,197 @@
+//! Helpful errors for a config file that can't be read, instead of silently falling back
+//! to the defaults and overwriting a hand-edited config.
+//!
+//! Every problem is reported with its line, its key and, for the streaming formats and
+//! stream sizes, the valid values. The invalid lines are commented out one by one until
+//! the config can be read, and the repaired config is offered as a separate file.
+
+use serde::de::DeserializeOwned;
+
+use crate::enums::streaming::{StreamSize, StreamingFormat};
+
+/// the number of invalid lines that are commented out before giving up
+const MAX_REPAIRS: usize = 20;
+/// the prefix of a line that was commented out
+const INVALID: &str = "# invalid: ";
+
+/// a value in a config file that can't be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// the line number, starting at 1
+    pub line: Option<usize>,
+    /// the key with its table, e.g. "configuration.streaming_format"
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {line}: ")?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "{key}: ")?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(values) = self.key.as_deref().and_then(valid_values) {
+            write!(f, " (valid values: {})", values.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// the valid values of the streaming format and stream size keys
+fn valid_values(key: &str) -> Option<Vec<String>> {
+    let name = key.rsplit('.').next().unwrap_or(key).to_lowercase();
+    if name.ends_with("streamsize") || name.ends_with("stream_size") {
+        Some(
+            [
+                StreamSize::NoneChunked,
+                StreamSize::U32maxChunked,
+                StreamSize::U32maxNotChunked,
+                StreamSize::U64maxChunked,
+                StreamSize::U64maxNotChunked,
+            ]
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        )
+    } else if name.ends_with("format") && !name.starts_with("record") {
+        Some(
+            [
+                StreamingFormat::Lpcm,
+                StreamingFormat::Wav,
+                StreamingFormat::Flac,
+                StreamingFormat::Rf64,
+            ]
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        )
+    } else {
+        None
+    }
+}
+
+/// the table and the key of a line, for a value that spans lines the key it belongs to
+fn key_of_line(lines: &[&str], index: usize) -> Option<String> {
+    let key = lines[..=index]
+        .iter()
+        .rev()
+        .map(|l| l.trim())
+        .take_while(|l| !l.starts_with('['))
+        .find(|l| !l.starts_with('#') && l.contains('='))
+        .and_then(|l| l.split('=').next())
+        .map(|k| k.trim().trim_matches('"').to_string());
+    let table = lines[..=index]
+        .iter()
+        .rev()
+        .map(|l| l.trim())
+        .find(|l| l.starts_with('['))
+        .map(|l| l.trim_matches(['[', ']']).trim().to_string());
+    match (table, key) {
+        (Some(table), Some(key)) => Some(format!("{table}.{key}")),
+        (table, key) => key.or(table),
+    }
+}
+
+/// the line index of a byte offset
+fn line_index(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count()
+}
+
+/// Read a config that has errors: the problems, and if it can be repaired by commenting
+/// out the invalid lines, the repaired config and its text.
+pub fn repair_config<T: DeserializeOwned>(text: &str) -> (Vec<ConfigProblem>, Option<(T, String)>) {
+    let mut text = text.to_string();
+    let mut problems = Vec::new();
+    for _ in 0..=MAX_REPAIRS {
+        let error = match toml::from_str::<T>(&text) {
+            Ok(_) if problems.is_empty() => return (problems, None),
+            Ok(config) => return (problems, Some((config, text))),
+            Err(error) => error,
+        };
+        // a value on a single line, not a table with a missing field
+        let index = error
+            .span()
+            .filter(|span| {
+                !error.message().starts_with("missing field")
+                    && !text[span.clone()].trim_end().contains('\n')
+            })
+            .map(|span| line_index(&text, span.start));
+        let lines: Vec<&str> = text.lines().collect();
+        let key = index.and_then(|i| key_of_line(&lines, i));
+        problems.push(ConfigProblem {
+            line: index.map(|i| i + 1),
+            key,
+            message: error.message().trim().to_string(),
+        });
+        // comment out the invalid line, unless it's a table header or already commented out
+        let Some(i) = index.filter(|&i| {
+            lines
+                .get(i)
+                .is_some_and(|l| !l.trim().starts_with(['[', '#']))
+        }) else {
+            break;
+        };
+        let mut repaired: Vec<String> = lines.iter().map(|l| (*l).to_string()).collect();
+        repaired[i] = format!("{INVALID}{}", repaired[i]);
+        text = repaired.join("\n") + "\n";
+    }
+    (problems, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct TestConfig {
+        configuration: TestConfiguration,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct TestConfiguration {
+        #[serde(default)]
+        server_port: Option<u16>,
+        #[serde(default)]
+        streaming_format: Option<StreamingFormat>,
+        #[serde(default)]
+        flac_stream_size: Option<StreamSize>,
+    }
+
+    #[test]
+    fn test_repair_config() {
+        let text = "[configuration]\n\
+            server_port = 5901\n\
+            streaming_format = \"Flak\"\n\
+            flac_stream_size = 42\n";
+        let (problems, repaired) = repair_config::<TestConfig>(text);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].line, Some(3));
+        assert_eq!(
+            problems[0].key.as_deref(),
+            Some("configuration.streaming_format")
+        );
+        assert!(problems[0]
+            .to_string()
+            .contains("valid values: Lpcm, Wav, Flac, Rf64"));
+        assert_eq!(problems[1].line, Some(4));
+        assert!(problems[1]
+            .to_string()
+            .contains("NoneChunked, U32maxChunked"));
+        let (config, text) = repaired.unwrap();
+        assert_eq!(config.configuration.server_port, Some(5901));
+        assert!(config.configuration.streaming_format.is_none());
+        assert!(config.configuration.flac_stream_size.is_none());
+        assert!(text.contains("# invalid: streaming_format = \"Flak\""));
+        // a config without errors needs no repair
+        let (problems, repaired) = repair_config::<TestConfig>("[configuration]\n");
+        assert!(problems.is_empty() && repaired.is_none());
+        // a missing table can't be repaired
+        let (problems, repaired) = repair_config::<TestConfig>("server_port = 5901\n");
+        assert_eq!(problems.len(), 1);
+        assert!(repaired.is_none());
+    }
+}
@@ -0,0 +1,297 @@
+//! Override config keys with `SWYH_*` environment variables, for container and systemd
+//! deployments that configure swyh-rs without a config file in the image.
+//!
+//! The name of the variable is the key in upper case with the `SWYH_` prefix, e.g.
+//! `SWYH_SERVER_PORT=5902` or `SWYH_STREAMING_FORMAT=Flac`, and a double underscore
+//! separates the keys of a table, e.g. `SWYH_MQTT__HOST=broker.local`. The value is read as
+//! a TOML value (a number, a boolean, an array, ...) if the config key accepts it, or else as
+//! a string, so that `SWYH_MQTT__PASSWORD=1234` stays a string. An override that the config
+//! key accepts in neither way is reported and skipped, it never invalidates the config file.
+//!
+//! The overrides are applied over the config file(s), and are not saved: the config file
+//! keeps its own values for the overridden keys.
+
+use std::sync::LazyLock;
+use toml::{Table, Value};
+
+/// the prefix of the environment variables
+const ENV_PREFIX: &str = "SWYH_";
+
+/// a config key overridden by an environment variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvOverride {
+    /// the name of the environment variable
+    pub var: String,
+    /// the key path in the `[configuration]` table
+    pub path: Vec<String>,
+    /// the value read as TOML
+    pub value: Value,
+    /// the value of the environment variable
+    pub raw: String,
+}
+
+/// the overrides from the environment of this process, read once
+static ENV_OVERRIDES: LazyLock<Vec<EnvOverride>> = LazyLock::new(|| {
+    env_overrides(
+        std::env::vars_os()
+            .filter_map(|(var, value)| Some((var.into_string().ok()?, value.into_string().ok()?))),
+    )
+});
+
+/// the overrides from the environment of this process
+#[must_use]
+pub fn overrides() -> &'static [EnvOverride] {
+    &ENV_OVERRIDES
+}
+
+/// a value as TOML, or else as a string
+fn parse_value(value: &str) -> Value {
+    format!("value = {value}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+/// the config overrides of the `SWYH_*` variables
+fn env_overrides(vars: impl Iterator<Item = (String, String)>) -> Vec<EnvOverride> {
+    let mut overrides: Vec<EnvOverride> = vars
+        .filter_map(|(var, value)| {
+            let path: Vec<String> = var
+                .strip_prefix(ENV_PREFIX)?
+                .split("__")
+                .map(str::to_lowercase)
+                .collect();
+            if path.iter().any(String::is_empty) {
+                return None;
+            }
+            Some(EnvOverride {
+                value: parse_value(&value),
+                raw: value,
+                var,
+                path,
+            })
+        })
+        .collect();
+    overrides.sort_by(|a, b| a.var.cmp(&b.var));
+    overrides
+}
+
+/// the `[configuration]` table of a config, created if missing
+fn configuration(config: &mut Table) -> Option<&mut Table> {
+    config
+        .entry("configuration")
+        .or_insert_with(|| Value::Table(Table::new()))
+        .as_table_mut()
+}
+
+/// set the value of a key path, the missing tables are created
+fn set_value(table: &mut Table, path: &[String], value: Value) {
+    let Some((key, tables)) = path.split_last() else {
+        return;
+    };
+    let mut table = table;
+    for name in tables {
+        let entry = table
+            .entry(name.as_str())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+        let Some(next) = entry.as_table_mut() else {
+            return;
+        };
+        table = next;
+    }
+    table.insert(key.clone(), value);
+}
+
+/// the value of a key path
+fn get_value<'a>(table: &'a Table, path: &[String]) -> Option<&'a Value> {
+    let (key, tables) = path.split_last()?;
+    let mut table = table;
+    for name in tables {
+        table = table.get(name)?.as_table()?;
+    }
+    table.get(key)
+}
+
+/// remove the value of a key path, and the tables that are empty then
+fn remove_value(table: &mut Table, path: &[String]) {
+    match path {
+        [key] => {
+            table.remove(key);
+        }
+        [name, rest @ ..] => {
+            if let Some(next) = table.get_mut(name).and_then(Value::as_table_mut) {
+                remove_value(next, rest);
+                if next.is_empty() {
+                    table.remove(name);
+                }
+            }
+        }
+        [] => (),
+    }
+}
+
+/// apply the overrides to a config
+pub fn apply_overrides(config: &mut Table, overrides: &[EnvOverride]) {
+    let Some(configuration) = configuration(config) else {
+        return;
+    };
+    for o in overrides {
+        set_value(configuration, &o.path, o.value.clone());
+    }
+}
+
+/// put back the values of the config file for the overridden keys, before saving a config
+pub fn restore_overridden(config: &mut Table, file: &Table, overrides: &[EnvOverride]) {
+    let file = file.get("configuration").and_then(Value::as_table);
+    let Some(configuration) = configuration(config) else {
+        return;
+    };
+    for o in overrides {
+        match file.and_then(|f| get_value(f, &o.path)) {
+            Some(value) => set_value(configuration, &o.path, value.clone()),
+            None => remove_value(configuration, &o.path),
+        }
+    }
+}
+
+/// apply the overrides that `valid` accepts, each one with its TOML value or else as a string,
+/// and return the problems of the skipped overrides
+fn apply_valid_overrides(
+    config: &mut Table,
+    overrides: &[EnvOverride],
+    valid: impl Fn(&Table) -> bool,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    for o in overrides {
+        let mut values = vec![o.value.clone()];
+        if !o.value.is_str() {
+            values.push(Value::String(o.raw.clone()));
+        }
+        let applied = values.into_iter().find_map(|value| {
+            let mut candidate = config.clone();
+            apply_overrides(&mut candidate, &[EnvOverride { value, ..o.clone() }]);
+            valid(&candidate).then_some(candidate)
+        });
+        match applied {
+            Some(candidate) => *config = candidate,
+            None => problems.push(format!(
+                "Ignoring the environment variable {}: {} is not a valid value for {}",
+                o.var,
+                o.raw,
+                o.path.join(".")
+            )),
+        }
+    }
+    problems
+}
+
+/// the config text with the overrides of the environment applied that `valid` accepts, and
+/// the problems of the skipped overrides, a config text that isn't valid itself is returned as is
+#[must_use]
+pub fn with_env_overrides(text: String, valid: impl Fn(&str) -> bool) -> (String, Vec<String>) {
+    let overrides = overrides();
+    if overrides.is_empty() || !valid(&text) {
+        return (text, Vec::new());
+    }
+    let Ok(mut config) = text.parse::<Table>() else {
+        return (text, Vec::new());
+    };
+    for o in overrides {
+        eprintln!(
+            "Config override from the environment: {} => {}",
+            o.var,
+            o.path.join(".")
+        );
+    }
+    let problems = apply_valid_overrides(&mut config, overrides, |candidate| {
+        toml::to_string(candidate).is_ok_and(|t| valid(&t))
+    });
+    for problem in &problems {
+        eprintln!("{problem}");
+    }
+    (toml::to_string(&config).unwrap_or(text), problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = [
+            ("SWYH_SERVER_PORT", "5902"),
+            ("SWYH_STREAMING_FORMAT", "Flac"),
+            ("SWYH_MQTT__HOST", "broker.local"),
+            ("SWYH_ACTIVE_RENDERERS", r#"["Kitchen", "Attic"]"#),
+            ("SWYH_", "ignored"),
+            ("HOME", "/root"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let overrides = env_overrides(vars.into_iter());
+        let vars: Vec<&str> = overrides.iter().map(|o| o.var.as_str()).collect();
+        assert_eq!(
+            vars,
+            [
+                "SWYH_ACTIVE_RENDERERS",
+                "SWYH_MQTT__HOST",
+                "SWYH_SERVER_PORT",
+                "SWYH_STREAMING_FORMAT"
+            ]
+        );
+        let mut config: Table = "[configuration]\nserver_port = 5901\nstreaming_format = \"Wav\"\n"
+            .parse()
+            .unwrap();
+        let file = config.clone();
+        apply_overrides(&mut config, &overrides);
+        let conf = &config["configuration"];
+        assert_eq!(conf["server_port"].as_integer(), Some(5902));
+        assert_eq!(conf["streaming_format"].as_str(), Some("Flac"));
+        assert_eq!(conf["mqtt"]["host"].as_str(), Some("broker.local"));
+        assert_eq!(conf["active_renderers"].as_array().map(Vec::len), Some(2));
+        // the overrides are not saved
+        restore_overridden(&mut config, &file, &overrides);
+        assert_eq!(config, file);
+    }
+
+    #[test]
+    fn test_typed_env_overrides() {
+        let vars = [
+            ("SWYH_CONFIG_ID", "1"),
+            ("SWYH_MQTT__PASSWORD", "1234"),
+            ("SWYH_SERVER_PORT", "5902"),
+            ("SWYH_SSDP_INTERVAL_MINS", "often"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let overrides = env_overrides(vars.into_iter());
+        let mut config: Table = "[configuration]\nserver_port = 5901\n".parse().unwrap();
+        // a stand-in for the config types: config_id and mqtt.password are strings,
+        // server_port and ssdp_interval_mins are numbers
+        let valid = |t: &Table| {
+            let conf = &t["configuration"];
+            conf.get("config_id").is_none_or(Value::is_str)
+                && conf
+                    .get("mqtt")
+                    .and_then(|m| m.get("password"))
+                    .is_none_or(Value::is_str)
+                && conf.get("server_port").is_none_or(Value::is_integer)
+                && conf
+                    .get("ssdp_interval_mins")
+                    .is_none_or(|v| v.is_float() || v.is_integer())
+        };
+        let problems = apply_valid_overrides(&mut config, &overrides, valid);
+        let conf = &config["configuration"];
+        assert_eq!(conf["config_id"].as_str(), Some("1"));
+        assert_eq!(conf["mqtt"]["password"].as_str(), Some("1234"));
+        assert_eq!(conf["server_port"].as_integer(), Some(5902));
+        // the invalid override is skipped and reported
+        assert!(conf.get("ssdp_interval_mins").is_none());
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("Ignoring the environment variable SWYH_SSDP_INTERVAL_MINS")
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! A system-wide config with the defaults for all users, for managed multi-user machines
+//! and kiosk setups: `/etc/swyh-rs/config.toml`, or `%ProgramData%\swyh-rs\config.toml` on
+//! Windows.
+//!
+//! The per-user config is laid over the system config: a value in the user config replaces
+//! the value in the system config, tables (like `[configuration.renderers]`) are merged per
+//! key. When the settings are saved, only the values that differ from the system config are
+//! written to the user config, so that later changes of the system config still apply.
+//! `read_only = true` in the system config can't be overridden by the user config.
+
+use std::{fs, path::PathBuf, sync::LazyLock};
+use toml::{Table, Value};
+
+/// the system config, read once
+static SYSTEM_LAYER: LazyLock<Option<Table>> = LazyLock::new(read_system_layer);
+
+/// the path of the system config
+#[must_use]
+pub fn system_config_path() -> PathBuf {
+    #[cfg(target_os = "windows")]
+    let dir = std::env::var_os("ProgramData")
+        .map_or_else(|| PathBuf::from("C:\\ProgramData"), PathBuf::from);
+    #[cfg(not(target_os = "windows"))]
+    let dir = PathBuf::from("/etc");
+    dir.join(env!("CARGO_PKG_NAME")).join("config.toml")
+}
+
+fn read_system_layer() -> Option<Table> {
+    let path = system_config_path();
+    let text = fs::read_to_string(&path).ok()?;
+    match text.parse::<Table>() {
+        Ok(table) if table.get("configuration").is_some_and(Value::is_table) => {
+            eprintln!("Using the system config {}", path.display());
+            Some(table)
+        }
+        Ok(_) => {
+            eprintln!(
+                "Ignoring the system config {}: it has no [configuration] table",
+                path.display()
+            );
+            None
+        }
+        Err(e) => {
+            eprintln!("Ignoring the system config {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// the system config, if there is one
+#[must_use]
+pub fn system_layer() -> Option<&'static Table> {
+    SYSTEM_LAYER.as_ref()
+}
+
+/// lay the values of a table over a lower layer, tables are merged per key
+fn overlay(base: &mut Table, top: Table) {
+    for (key, value) in top {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(top)) => overlay(base, top),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// remove the values that are the same in a lower layer
+fn remove_layer(top: &mut Table, base: &Table) {
+    top.retain(|key, value| match (value, base.get(key)) {
+        (Value::Table(top), Some(Value::Table(base))) => {
+            remove_layer(top, base);
+            !top.is_empty()
+        }
+        (value, Some(base)) => value != base,
+        (_, None) => true,
+    });
+}
+
+/// the user config text laid over the system config, None if the user config isn't valid TOML
+#[must_use]
+pub fn merged_config(system: &Table, user: &str) -> Option<String> {
+    let user = user.parse::<Table>().ok()?;
+    let mut merged = system.clone();
+    overlay(&mut merged, user);
+    toml::to_string(&merged).ok()
+}
+
+/// the text of a config without the values that are the same in the system config
+#[must_use]
+pub fn user_layer(system: &Table, config: Table) -> String {
+    let mut config = config;
+    remove_layer(&mut config, system);
+    toml::to_string(&config).unwrap_or_default()
+}
+
+/// true if the system config makes the config read-only
+#[must_use]
+pub fn system_read_only(system: &Table) -> bool {
+    system
+        .get("configuration")
+        .and_then(|c| c.get("read_only"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_layers() {
+        let system: Table = r#"
+            [configuration]
+            server_port = 5901
+            streaming_format = "Flac"
+            read_only = true
+            [configuration.renderers.Kitchen]
+            volume = 20
+            [configuration.renderers.Attic]
+            volume = 40
+            "#
+        .parse()
+        .unwrap();
+        let user = r#"
+            [configuration]
+            streaming_format = "Wav"
+            [configuration.renderers.Kitchen]
+            volume = 30
+            "#;
+        let merged: Table = merged_config(&system, user).unwrap().parse().unwrap();
+        let conf = &merged["configuration"];
+        assert_eq!(conf["server_port"].as_integer(), Some(5901));
+        assert_eq!(conf["streaming_format"].as_str(), Some("Wav"));
+        assert_eq!(
+            conf["renderers"]["Kitchen"]["volume"].as_integer(),
+            Some(30)
+        );
+        assert_eq!(conf["renderers"]["Attic"]["volume"].as_integer(), Some(40));
+        assert!(system_read_only(&system));
+        // only the user's own values are saved
+        let saved: Table = user_layer(&system, merged).parse().unwrap();
+        let conf = &saved["configuration"];
+        assert!(conf.get("server_port").is_none());
+        assert_eq!(conf["streaming_format"].as_str(), Some("Wav"));
+        assert_eq!(
+            conf["renderers"]["Kitchen"]["volume"].as_integer(),
+            Some(30)
+        );
+        assert!(conf["renderers"].get("Attic").is_none());
+        assert!(merged_config(&system, "[configuration").is_none());
+    }
+}
@@ -1,11 +1,32 @@
 use crate::{
-    enums::streaming::{StreamSize, StreamingFormat},
+    enums::streaming::{
+        ChannelMode, Dither, FlacHeaderPreset, HeaderSize, StreamSize, StreamingFormat,
+    },
     globals::statics::{SERVER_PORT, THEMES},
+    openhome::quirks::Quirk,
+    server::ssdp_announcer::random_udn,
+    utils::{
+        button_gestures::ButtonAction,
+        config_check::repair_config,
+        config_env::{overrides, restore_overridden, with_env_overrides},
+        config_layers::{
+            merged_config, system_config_path, system_layer, system_read_only, user_layer,
+        },
+        hooks::HookEvent,
+        notify::NotifyEvent,
+        secrets::{
+            get_secret, renderer_secret, store_secret, LASTFM_PASSWORD, LASTFM_SECRET,
+            LASTFM_SESSION_KEY, LISTENBRAINZ_TOKEN, MQTT_PASSWORD, RENDERER_PASSWORD,
+            RENDERER_TOKEN,
+        },
+        swyh_import::{apply_swyh_settings, find_swyh_settings},
+    },
 };
 use lexopt::{prelude::*, Parser};
 use log::LevelFilter;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     f64, fs,
     fs::File,
     io::{BufWriter, Write},
@@ -15,6 +36,12 @@ use toml::from_str;
 
 const CONFIGFILE: &str = "config{}.toml";
 const PKGNAME: &str = env!("CARGO_PKG_NAME");
+/// the environment variable with the config directory
+const CONFIG_DIR_VAR: &str = "SWYH_CONFIG_DIR";
+/// the default FLAC capture timeout and near silence burst
+const FLAC_SILENCE_MSEC: u32 = 250;
+/// the minimum capture timeout and silence block
+const MIN_SILENCE_MSEC: u32 = 10;
 
 // default values for Serde
 struct CfgDefaults {}
@@ -41,6 +68,313 @@ impl CfgDefaults {
     fn bits_per_sample() -> Option<u16> {
         Some(16)
     }
+    fn mqtt_port() -> u16 {
+        1883
+    }
+    fn mqtt_topic_prefix() -> String {
+        "swyh".to_string()
+    }
+    fn dsp_stage_enabled() -> bool {
+        true
+    }
+}
+
+// the settings remembered for a renderer, keyed by the renderer name
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RendererSettings {
+    #[serde(alias = "StreamingFormat", default)]
+    pub streaming_format: Option<StreamingFormat>,
+    #[serde(alias = "BitsPerSample", default)]
+    pub bits_per_sample: Option<u16>,
+    #[serde(alias = "ChannelMode", default)]
+    pub channel_mode: Option<ChannelMode>,
+    #[serde(alias = "DelayMSec", default)]
+    pub delay_msec: Option<u32>,
+    #[serde(alias = "Volume", default)]
+    pub volume: Option<i32>,
+    #[serde(alias = "PowerPreset", default)]
+    pub power_preset: Option<String>,
+    #[serde(alias = "PowerHost", default)]
+    pub power_host: Option<String>,
+    #[serde(alias = "PowerInput", default)]
+    pub power_input: Option<String>,
+    #[serde(alias = "PowerOn", default)]
+    pub power_on: Option<Vec<String>>,
+    #[serde(alias = "PowerOff", default)]
+    pub power_off: Option<Vec<String>>,
+    #[serde(alias = "PowerOnDelayMSec", default)]
+    pub power_on_delay_msec: Option<u32>,
+    #[serde(alias = "HeaderSize", default)]
+    pub header_size: Option<HeaderSize>,
+    #[serde(alias = "Auth", default)]
+    pub auth: Option<RendererAuth>,
+    #[serde(alias = "Label", default)]
+    pub label: Option<String>,
+    #[serde(alias = "Hidden", default)]
+    pub hidden: Option<bool>,
+}
+
+// the credentials of a renderer that needs them on its control endpoints:
+// HTTP basic auth with the username and password, and/or a (pairing) token that is
+// sent as a bearer token, or in the header `token_header` if that is set
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RendererAuth {
+    #[serde(alias = "Username", default)]
+    pub username: Option<String>,
+    #[serde(alias = "Password", default)]
+    pub password: Option<String>,
+    #[serde(alias = "Token", default)]
+    pub token: Option<String>,
+    #[serde(alias = "TokenHeader", default)]
+    pub token_header: Option<String>,
+}
+
+impl RendererAuth {
+    /// the password from the config file, or else from the keyring
+    #[must_use]
+    pub fn password(&self, renderer: &str, config_id: &str) -> Option<String> {
+        self.password
+            .clone()
+            .or_else(|| get_secret(&renderer_secret(RENDERER_PASSWORD, renderer), config_id))
+    }
+
+    /// the token from the config file, or else from the keyring
+    #[must_use]
+    pub fn token(&self, renderer: &str, config_id: &str) -> Option<String> {
+        self.token
+            .clone()
+            .or_else(|| get_secret(&renderer_secret(RENDERER_TOKEN, renderer), config_id))
+    }
+}
+
+// the capture timeout and silence block size in msec for a streaming format,
+// missing values are computed from the global capture timeout
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SilenceTiming {
+    #[serde(alias = "CaptureTimeout", default)]
+    pub capture_timeout: Option<u32>,
+    #[serde(alias = "SilenceMSec", default)]
+    pub silence_msec: Option<u32>,
+}
+
+// the position and size of the main window, remembered when it is closed
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowGeometry {
+    #[serde(alias = "X")]
+    pub x: i32,
+    #[serde(alias = "Y")]
+    pub y: i32,
+    #[serde(alias = "W")]
+    pub w: i32,
+    #[serde(alias = "H")]
+    pub h: i32,
+}
+
+// the TCP options of the streaming connections
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TcpSettings {
+    #[serde(alias = "KeepaliveSecs", default)]
+    pub keepalive_secs: Option<u32>,
+    #[serde(alias = "KeepaliveIntervalSecs", default)]
+    pub keepalive_interval_secs: Option<u32>,
+    #[serde(alias = "KeepaliveRetries", default)]
+    pub keepalive_retries: Option<u32>,
+    #[serde(alias = "NoDelay", default)]
+    pub nodelay: bool,
+    #[serde(alias = "SendBufferSize", default)]
+    pub send_buffer_size: Option<u32>,
+}
+
+// the announcements that are mixed into the stream with /api/announce
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct AnnounceSettings {
+    #[serde(alias = "Dir", default)]
+    pub dir: Option<String>,
+    #[serde(alias = "GainDb", default)]
+    pub gain_db: Option<f32>,
+    #[serde(alias = "DuckDb", default)]
+    pub duck_db: Option<f32>,
+}
+
+// start the renderers on audio activity and stop them after a prolonged silence
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct VuTriggerSettings {
+    #[serde(alias = "Renderers", default)]
+    pub renderers: Vec<String>,
+    #[serde(alias = "ThresholdDb", default)]
+    pub threshold_db: Option<f32>,
+    #[serde(alias = "StartSecs", default)]
+    pub start_secs: Option<f32>,
+    #[serde(alias = "StopSecs", default)]
+    pub stop_secs: Option<u32>,
+}
+
+// a shell command and/or a webhook url that is run on an event, with {variables}
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct EventHook {
+    #[serde(alias = "Event")]
+    pub event: HookEvent,
+    #[serde(alias = "Command", default)]
+    pub command: Option<String>,
+    #[serde(alias = "Url", default)]
+    pub url: Option<String>,
+}
+
+// the actions of the mouse gestures on a renderer button, not set is the default action
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ButtonGestures {
+    #[serde(alias = "DoubleClick", default)]
+    pub double_click: Option<ButtonAction>,
+    #[serde(alias = "MiddleClick", default)]
+    pub middle_click: Option<ButtonAction>,
+    #[serde(alias = "RightClick", default)]
+    pub right_click: Option<ButtonAction>,
+}
+
+// a group of renderers whose volumes keep their offsets when one of their sliders moves,
+// while the group is locked
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct VolumeLock {
+    #[serde(alias = "Name")]
+    pub name: String,
+    #[serde(alias = "Renderers", default)]
+    pub renderers: Vec<String>,
+    #[serde(alias = "Locked", default)]
+    pub locked: bool,
+}
+
+// a named session: the renderers to start with their volume, the streaming format
+// and the loudness option
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct SessionPreset {
+    #[serde(alias = "Name")]
+    pub name: String,
+    #[serde(alias = "Renderers", default)]
+    pub renderers: Vec<SessionMember>,
+    #[serde(alias = "StreamingFormat", default)]
+    pub streaming_format: Option<StreamingFormat>,
+    #[serde(alias = "BitsPerSample", default)]
+    pub bits_per_sample: Option<u16>,
+    #[serde(alias = "Loudness", default)]
+    pub loudness: Option<bool>,
+}
+
+// a renderer of a session, by (part of) its name or its ip address
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SessionMember {
+    #[serde(alias = "Renderer")]
+    pub renderer: String,
+    #[serde(alias = "Volume", default)]
+    pub volume: Option<u8>,
+}
+
+// the identity that swyh-rs presents in the HTTP and UPnP headers
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct IdentitySettings {
+    #[serde(alias = "Server", default)]
+    pub server: Option<String>,
+    #[serde(alias = "IcyName", default)]
+    pub icy_name: Option<String>,
+    #[serde(alias = "UserAgent", default)]
+    pub user_agent: Option<String>,
+}
+
+// the STREAMINFO header of the FLAC stream: a preset, with the values that override it
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlacHeaderSettings {
+    #[serde(alias = "Preset", default)]
+    pub preset: Option<FlacHeaderPreset>,
+    #[serde(alias = "MinBlocksize", default)]
+    pub min_blocksize: Option<u16>,
+    #[serde(alias = "MaxBlocksize", default)]
+    pub max_blocksize: Option<u16>,
+    #[serde(alias = "MaxTotalSamples", default)]
+    pub max_total_samples: Option<bool>,
+    #[serde(alias = "DummyMd5", default)]
+    pub dummy_md5: Option<bool>,
+}
+
+// a stage of the DSP chain: the name of the processor and its parameters
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct DspStage {
+    #[serde(alias = "Processor")]
+    pub processor: String,
+    #[serde(alias = "Enabled", default = "CfgDefaults::dsp_stage_enabled")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub params: BTreeMap<String, toml::Value>,
+}
+
+// the resource usage monitor, and the FLAC compression throttle
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceMonitorSettings {
+    #[serde(alias = "IntervalSecs", default)]
+    pub interval_secs: Option<u32>,
+    #[serde(alias = "Throttle", default)]
+    pub throttle: bool,
+    #[serde(alias = "CpuLimitPercent", default)]
+    pub cpu_limit_percent: Option<u8>,
+}
+
+// the MQTT broker to publish the streaming state to and receive commands from
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct MqttSettings {
+    #[serde(alias = "Broker")]
+    pub broker: String,
+    #[serde(alias = "Port", default = "CfgDefaults::mqtt_port")]
+    pub port: u16,
+    #[serde(alias = "ClientId", default)]
+    pub client_id: Option<String>,
+    #[serde(alias = "Username", default)]
+    pub username: Option<String>,
+    #[serde(alias = "Password", default)]
+    pub password: Option<String>,
+    #[serde(alias = "TopicPrefix", default = "CfgDefaults::mqtt_topic_prefix")]
+    pub topic_prefix: String,
+}
+
+impl MqttSettings {
+    /// the password from the config file, or else from the keyring
+    #[must_use]
+    pub fn password(&self, config_id: &str) -> Option<String> {
+        self.password
+            .clone()
+            .or_else(|| get_secret(MQTT_PASSWORD, config_id))
+    }
+}
+
+// the Last.fm and ListenBrainz accounts to scrobble the now-playing tracks to
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScrobbleSettings {
+    #[serde(alias = "ListenBrainzToken", default)]
+    pub listenbrainz_token: Option<String>,
+    #[serde(alias = "LastfmApiKey", default)]
+    pub lastfm_api_key: Option<String>,
+    #[serde(alias = "LastfmSecret", default)]
+    pub lastfm_secret: Option<String>,
+    #[serde(alias = "LastfmSessionKey", default)]
+    pub lastfm_session_key: Option<String>,
+    #[serde(alias = "LastfmUsername", default)]
+    pub lastfm_username: Option<String>,
+    #[serde(alias = "LastfmPassword", default)]
+    pub lastfm_password: Option<String>,
+    #[serde(alias = "NowPlayingFile", default)]
+    pub now_playing_file: Option<String>,
+}
+
+impl ScrobbleSettings {
+    /// a credential from the config file, or else from the keyring
+    #[must_use]
+    pub fn secret(&self, name: &str, config_id: &str) -> Option<String> {
+        let value = match name {
+            LISTENBRAINZ_TOKEN => &self.listenbrainz_token,
+            LASTFM_SECRET => &self.lastfm_secret,
+            LASTFM_SESSION_KEY => &self.lastfm_session_key,
+            LASTFM_PASSWORD => &self.lastfm_password,
+            _ => return None,
+        };
+        value.clone().or_else(|| get_secret(name, config_id))
+    }
 }
 
 // the configuration struct, read from and saved in config.ini
@@ -102,6 +436,9 @@ pub struct Configuration {
     pub active_renderers: Vec<String>,
     #[serde(alias = "LastNetwork", default)]
     pub last_network: Option<String>,
+    // the network of the streaming server if it's not the SSDP network (last_network)
+    #[serde(alias = "ServeNetwork", default)]
+    pub serve_network: Option<String>,
     #[serde(alias = "ConfigDir", default)]
     config_dir: PathBuf,
     #[serde(alias = "ConfigId", default)]
@@ -110,6 +447,180 @@ pub struct Configuration {
     pub read_only: bool,
     #[serde(alias = "ColorTheme", default)]
     pub color_theme: Option<u8>,
+    #[serde(alias = "CaptureOnDemand", default)]
+    pub capture_on_demand: bool,
+    #[serde(alias = "PreRollMSec", default)]
+    pub preroll_msec: Option<u32>,
+    #[serde(alias = "Quirks", default)]
+    pub quirks: Vec<Quirk>,
+    #[serde(alias = "FormatFallback", default)]
+    pub format_fallback: Vec<StreamingFormat>,
+    #[serde(alias = "Renderers", default)]
+    pub renderers: BTreeMap<String, RendererSettings>,
+    #[serde(alias = "MonitorDevice", default)]
+    pub monitor_device: Option<String>,
+    #[serde(alias = "MonitorDelayMSec", default)]
+    pub monitor_delay_msec: Option<u32>,
+    #[serde(alias = "Mqtt", default)]
+    pub mqtt: Option<MqttSettings>,
+    #[serde(alias = "Scrobble", default)]
+    pub scrobble: Option<ScrobbleSettings>,
+    #[serde(alias = "TrackGapMSec", default)]
+    pub track_gap_msec: Option<u32>,
+    #[serde(alias = "TrackChangeHook", default)]
+    pub track_change_hook: Option<String>,
+    #[serde(alias = "RecordDir", default)]
+    pub record_dir: Option<String>,
+    #[serde(alias = "RecordFormat", default)]
+    pub record_format: Option<StreamingFormat>,
+    #[serde(alias = "RtpDestination", default)]
+    pub rtp_destination: Option<String>,
+    #[serde(alias = "RtpTtl", default)]
+    pub rtp_ttl: Option<u32>,
+    #[serde(alias = "SSDPAnnounce", default)]
+    pub ssdp_announce: bool,
+    #[serde(alias = "Slimproto", default)]
+    pub slimproto: bool,
+    #[serde(alias = "CaptureChannels", default)]
+    pub capture_channels: Option<[u16; 2]>,
+    #[serde(alias = "ChannelMode", default)]
+    pub channel_mode: Option<ChannelMode>,
+    #[serde(alias = "Balance", default)]
+    pub balance: Option<i32>,
+    #[serde(alias = "TrimLeftDb", default)]
+    pub trim_left_db: Option<f32>,
+    #[serde(alias = "TrimRightDb", default)]
+    pub trim_right_db: Option<f32>,
+    #[serde(alias = "TransportPollSecs", default)]
+    pub transport_poll_secs: Option<u32>,
+    #[serde(alias = "BitPerfect", default)]
+    pub bit_perfect: bool,
+    #[serde(alias = "Dither", default)]
+    pub dither: Option<Dither>,
+    #[serde(alias = "EncoderPriority", default)]
+    pub encoder_priority: Option<i32>,
+    #[serde(alias = "EncoderCores", default)]
+    pub encoder_cores: Option<Vec<usize>>,
+    #[serde(alias = "DeviceWatchSecs", default)]
+    pub device_watch_secs: Option<u32>,
+    #[serde(alias = "LPCMSilence", default)]
+    pub lpcm_silence: Option<SilenceTiming>,
+    #[serde(alias = "WAVSilence", default)]
+    pub wav_silence: Option<SilenceTiming>,
+    #[serde(alias = "RF64Silence", default)]
+    pub rf64_silence: Option<SilenceTiming>,
+    #[serde(alias = "FLACSilence", default)]
+    pub flac_silence: Option<SilenceTiming>,
+    #[serde(alias = "RestoreVolume", default)]
+    pub restore_volume: bool,
+    #[serde(alias = "StartVolumeCap", default)]
+    pub start_volume_cap: Option<u8>,
+    #[serde(alias = "FadeInMSec", default)]
+    pub fade_in_msec: Option<u32>,
+    #[serde(alias = "FadeOutMSec", default)]
+    pub fade_out_msec: Option<u32>,
+    #[serde(alias = "NetworkSource", default)]
+    pub network_source: Option<String>,
+    #[serde(alias = "KnownRenderers", default)]
+    pub known_renderers: Option<String>,
+    #[serde(alias = "AccessLog", default)]
+    pub access_log: bool,
+    #[serde(alias = "MonitorSpectrum", default)]
+    pub monitor_spectrum: bool,
+    #[serde(alias = "RmsWindowMSec", default)]
+    pub rms_window_msec: Option<u32>,
+    #[serde(alias = "MeterRefreshHz", default)]
+    pub meter_refresh_hz: Option<u32>,
+    #[serde(alias = "MeterLinear", default)]
+    pub meter_linear: bool,
+    #[serde(alias = "MeterFloorDb", default)]
+    pub meter_floor_db: Option<f64>,
+    #[serde(alias = "UiScale", default)]
+    pub ui_scale: Option<f32>,
+    #[serde(alias = "WindowGeometry", default)]
+    pub window_geometry: Option<WindowGeometry>,
+    #[serde(alias = "LogWindow", default)]
+    pub log_window: bool,
+    #[serde(alias = "LogWindowHidden", default)]
+    pub log_window_hidden: bool,
+    #[serde(alias = "Notify", default)]
+    pub notify: Vec<NotifyEvent>,
+    #[serde(alias = "UseKeyring", default)]
+    pub use_keyring: bool,
+    #[serde(alias = "UDN", default)]
+    pub udn: Option<String>,
+    #[serde(alias = "Tcp", default)]
+    pub tcp: Option<TcpSettings>,
+    #[serde(alias = "SsdpPauseWhileStreaming", default)]
+    pub ssdp_pause_while_streaming: bool,
+    #[serde(alias = "SsdpUnicastRefresh", default)]
+    pub ssdp_unicast_refresh: Option<u32>,
+    #[serde(alias = "FallbackAudio", default)]
+    pub fallback_audio: Option<String>,
+    #[serde(alias = "Announce", default)]
+    pub announce: AnnounceSettings,
+    #[serde(alias = "MixSource", default)]
+    pub mix_source: Option<String>,
+    #[serde(alias = "MixGainDb", default)]
+    pub mix_gain_db: Option<f32>,
+    #[serde(alias = "VuTrigger", default)]
+    pub vu_trigger: Option<VuTriggerSettings>,
+    #[serde(alias = "Sessions", default)]
+    pub sessions: Vec<SessionPreset>,
+    #[serde(alias = "VolumeLocks", default)]
+    pub volume_locks: Vec<VolumeLock>,
+    #[serde(alias = "PositionPollSecs", default)]
+    pub position_poll_secs: Option<u32>,
+    #[serde(alias = "VolumePollSecs", default)]
+    pub volume_poll_secs: Option<u32>,
+    #[serde(alias = "Identity", default)]
+    pub identity: IdentitySettings,
+    #[serde(alias = "AudioHost", default)]
+    pub audio_host: Option<String>,
+    #[serde(alias = "FlacHeader", default)]
+    pub flac_header: FlacHeaderSettings,
+    #[serde(alias = "Multichannel", default)]
+    pub multichannel: bool,
+    #[serde(alias = "IgdPortCheck", default)]
+    pub igd_port_check: bool,
+    #[serde(alias = "FlacCompressionLevel", default)]
+    pub flac_compression_level: Option<u32>,
+    #[serde(alias = "ResourceMonitor", default)]
+    pub resource_monitor: ResourceMonitorSettings,
+    #[serde(alias = "DspChain", default)]
+    pub dsp_chain: Vec<DspStage>,
+    #[serde(alias = "ReplaySecs", default)]
+    pub replay_secs: Option<u32>,
+    #[serde(alias = "Hooks", default)]
+    pub hooks: Vec<EventHook>,
+    #[serde(alias = "SilenceHookSecs", default)]
+    pub silence_hook_secs: Option<u32>,
+    #[serde(alias = "Script", default)]
+    pub script: Option<String>,
+    #[serde(alias = "CatchUpSecs", default)]
+    pub catch_up_secs: Option<u32>,
+    #[serde(alias = "CheckUpdates", default)]
+    pub check_updates: bool,
+    #[serde(alias = "LogStdout", default)]
+    pub log_stdout: bool,
+    #[serde(alias = "StaleClientSecs", default)]
+    pub stale_client_secs: Option<u32>,
+    #[serde(alias = "ButtonGestures", default)]
+    pub button_gestures: ButtonGestures,
+    #[serde(alias = "SilencePauseSecs", default)]
+    pub silence_pause_secs: Option<u32>,
+    // the HTTP endpoints that change the state of swyh-rs, off by default
+    #[serde(alias = "ControlApi", default)]
+    pub control_api: bool,
+    // the bearer token of /api/shutdown, which is disabled without it
+    #[serde(alias = "ShutdownToken", default)]
+    pub shutdown_token: Option<String>,
+    // the problems of a config file that could not be read
+    #[serde(skip)]
+    pub config_problems: Vec<String>,
+    // the messages of reading the config, logged once the logger is running
+    #[serde(skip)]
+    pub config_messages: Vec<String>,
 }
 
 impl Default for Configuration {
@@ -144,11 +655,143 @@ impl Configuration {
             last_renderer: None,
             active_renderers: Vec::new(),
             last_network: None,
+            serve_network: None,
             config_dir: Self::get_config_dir(),
             config_id: Some(Self::get_config_id()),
             read_only: false,
             color_theme: None,
+            capture_on_demand: false,
+            preroll_msec: Some(0),
+            quirks: Vec::new(),
+            format_fallback: Vec::new(),
+            renderers: BTreeMap::new(),
+            monitor_device: None,
+            monitor_delay_msec: Some(0),
+            mqtt: None,
+            scrobble: None,
+            track_gap_msec: Some(0),
+            track_change_hook: None,
+            record_dir: None,
+            record_format: None,
+            rtp_destination: None,
+            rtp_ttl: None,
+            ssdp_announce: false,
+            slimproto: false,
+            capture_channels: None,
+            channel_mode: None,
+            balance: None,
+            trim_left_db: None,
+            trim_right_db: None,
+            transport_poll_secs: None,
+            bit_perfect: false,
+            dither: None,
+            encoder_priority: None,
+            encoder_cores: None,
+            device_watch_secs: None,
+            lpcm_silence: None,
+            wav_silence: None,
+            rf64_silence: None,
+            flac_silence: None,
+            restore_volume: false,
+            start_volume_cap: None,
+            fade_in_msec: None,
+            fade_out_msec: None,
+            network_source: None,
+            known_renderers: None,
+            access_log: false,
+            monitor_spectrum: false,
+            rms_window_msec: None,
+            meter_refresh_hz: None,
+            meter_linear: false,
+            meter_floor_db: None,
+            ui_scale: None,
+            window_geometry: None,
+            log_window: false,
+            log_window_hidden: false,
+            notify: Vec::new(),
+            use_keyring: false,
+            udn: None,
+            tcp: None,
+            ssdp_pause_while_streaming: false,
+            ssdp_unicast_refresh: None,
+            fallback_audio: None,
+            announce: AnnounceSettings::default(),
+            mix_source: None,
+            mix_gain_db: None,
+            vu_trigger: None,
+            sessions: Vec::new(),
+            volume_locks: Vec::new(),
+            position_poll_secs: None,
+            volume_poll_secs: None,
+            identity: IdentitySettings::default(),
+            audio_host: None,
+            flac_header: FlacHeaderSettings::default(),
+            multichannel: false,
+            igd_port_check: false,
+            flac_compression_level: None,
+            resource_monitor: ResourceMonitorSettings::default(),
+            dsp_chain: Vec::new(),
+            replay_secs: None,
+            hooks: Vec::new(),
+            silence_hook_secs: None,
+            script: None,
+            catch_up_secs: None,
+            check_updates: false,
+            log_stdout: false,
+            stale_client_secs: None,
+            button_gestures: ButtonGestures::default(),
+            silence_pause_secs: None,
+            control_api: false,
+            shutdown_token: None,
+            config_problems: Vec::new(),
+            config_messages: Vec::new(),
+        }
+    }
+
+    /// change the streaming format and/or the bit depth if given, returns true if it changed
+    pub fn set_streaming_format(
+        &mut self,
+        format: Option<StreamingFormat>,
+        bits_per_sample: Option<u16>,
+    ) -> bool {
+        let mut changed = false;
+        if format.is_some() && format != self.streaming_format {
+            self.streaming_format = format;
+            changed = true;
+        }
+        if bits_per_sample.is_some() && bits_per_sample != self.bits_per_sample {
+            self.bits_per_sample = bits_per_sample;
+            changed = true;
+        }
+        changed
+    }
+
+    /// the capture timeout and the silence block size in msec for a streaming format
+    ///
+    /// the default for LPCM/WAV/RF64 is a quarter of the capture timeout of silence,
+    /// FLAC sends short bursts of near silence to keep the encoder latency low
+    #[must_use]
+    pub fn silence_timing(&self, format: StreamingFormat) -> (u32, u32) {
+        let timing = match format {
+            StreamingFormat::Lpcm => self.lpcm_silence,
+            StreamingFormat::Wav => self.wav_silence,
+            StreamingFormat::Rf64 => self.rf64_silence,
+            StreamingFormat::Flac => self.flac_silence,
         }
+        .unwrap_or_default();
+        let (timeout, silence) = if format == StreamingFormat::Flac {
+            (FLAC_SILENCE_MSEC, FLAC_SILENCE_MSEC)
+        } else {
+            let timeout = self.capture_timeout.unwrap_or(2000);
+            (timeout, timeout / 4)
+        };
+        (
+            timing
+                .capture_timeout
+                .unwrap_or(timeout)
+                .max(MIN_SILENCE_MSEC),
+            timing.silence_msec.unwrap_or(silence).max(MIN_SILENCE_MSEC),
+        )
     }
 
     #[allow(dead_code)]
@@ -167,31 +810,63 @@ impl Configuration {
     pub fn read_config() -> Configuration {
         let mut force_update = false;
         let configfile = Self::choose_config_path();
+        // the SWYH settings imported into a new config
+        let mut import_messages = Vec::new();
         if !Path::new(&configfile).exists() {
-            println!("Creating a new default config {}", configfile.display());
-            let config = Configuration::new();
+            eprintln!("Creating a new default config {}", configfile.display());
+            let mut config = Self::default_config();
+            // migrate from the original Stream What You Hear
+            if let Some((path, settings)) = find_swyh_settings() {
+                import_messages.push(format!(
+                    "Importing the SWYH settings from {}",
+                    path.display()
+                ));
+                import_messages.extend(apply_swyh_settings(&mut config, &settings));
+            }
             let configuration = Config {
                 configuration: config,
             };
             let f = File::create(&configfile).unwrap();
-            let s = toml::to_string(&configuration).unwrap();
+            let s = Self::config_text(&configuration, &configfile);
             let mut w = BufWriter::new(f);
-            println!("New default CONFIG: {s}");
+            eprintln!("New default CONFIG: {s}");
             w.write_all(s.as_bytes()).unwrap();
             w.flush().unwrap();
         }
-        println!("Loading config from {}", configfile.display());
+        eprintln!("Loading config from {}", configfile.display());
         let s = fs::read_to_string(&configfile).unwrap_or_else(|error| {
             eprintln!("Unable to read config file: {error}");
             String::new()
         });
-        let mut config: Config = from_str(&s).unwrap_or_else(|error| {
-            eprintln!("Unable to deserialize config: {error}");
-            let config = Configuration::new();
-            Config {
-                configuration: config,
+        // the user config laid over the system config
+        let merged = system_layer().and_then(|system| merged_config(system, &s));
+        // with the overrides of the SWYH_* environment variables
+        let (text, override_problems) =
+            with_env_overrides(merged.unwrap_or_else(|| s.clone()), |t| {
+                from_str::<Config>(t).is_ok()
+            });
+        let mut config: Config = match from_str(&text) {
+            Ok(mut config) => {
+                config.configuration.config_problems = override_problems;
+                config
             }
-        });
+            Err(_) if !s.trim().is_empty() => Self::repaired_config(&configfile, &s),
+            Err(error) => {
+                eprintln!("Unable to deserialize config: {error}");
+                Config {
+                    configuration: Self::default_config(),
+                }
+            }
+        };
+        config.configuration.config_messages = import_messages;
+        // a config from another machine or a container image has another config directory
+        if !config.configuration.config_dir.is_dir() {
+            config.configuration.config_dir = Self::get_config_dir();
+        }
+        // the system config can make the config read-only for all users
+        if system_layer().is_some_and(system_read_only) {
+            config.configuration.read_only = true;
+        }
         if config.configuration.ssdp_interval_mins > 0.0
             && config.configuration.ssdp_interval_mins < 0.5
         {
@@ -221,10 +896,27 @@ impl Configuration {
             config.configuration.buffering_delay_msec = Some(0);
             force_update = true;
         }
+        if config.configuration.preroll_msec.is_none() {
+            config.configuration.preroll_msec = Some(0);
+            force_update = true;
+        }
+        if config.configuration.monitor_delay_msec.is_none() {
+            config.configuration.monitor_delay_msec = Some(0);
+            force_update = true;
+        }
+        if config.configuration.track_gap_msec.is_none() {
+            config.configuration.track_gap_msec = Some(0);
+            force_update = true;
+        }
         if config.configuration.config_id.is_none() {
             config.configuration.config_id = Some(String::new());
             force_update = true;
         }
+        // the UPnP identity of this installation, persisted across runs
+        if config.configuration.udn.is_none() {
+            config.configuration.udn = Some(random_udn());
+            force_update = true;
+        }
         if config.configuration.sound_source_index.is_none() {
             config.configuration.sound_source_index = Some(0);
             force_update = true;
@@ -241,22 +933,218 @@ impl Configuration {
             config.configuration.color_theme = None;
             force_update = true;
         }
+        if config.configuration.use_keyring
+            && !config.configuration.read_only
+            && config.configuration.move_secrets_to_keyring()
+        {
+            force_update = true;
+        }
         if force_update && !config.configuration.read_only {
             config.configuration.update_config().unwrap();
         }
         config.configuration
     }
 
+    /// the defaults of a new config: the system config if there is one
+    fn default_config() -> Configuration {
+        system_layer()
+            .and_then(|system| toml::Value::Table(system.clone()).try_into::<Config>().ok())
+            .map_or_else(Configuration::new, |config| config.configuration)
+    }
+
+    /// the text of the config file, only the values that differ from the system config,
+    /// and the values of the config file for the keys overridden by the environment
+    fn config_text(config: &Config, configfile: &Path) -> String {
+        let overrides = overrides();
+        if system_layer().is_none() && overrides.is_empty() {
+            return toml::to_string(config).unwrap();
+        }
+        let Ok(mut table) = toml::Table::try_from(config) else {
+            return toml::to_string(config).unwrap();
+        };
+        let file = fs::read_to_string(configfile)
+            .ok()
+            .and_then(|s| s.parse::<toml::Table>().ok())
+            .unwrap_or_default();
+        restore_overridden(&mut table, &file, overrides);
+        match system_layer() {
+            Some(system) => user_layer(system, table),
+            None => toml::to_string(&table).unwrap(),
+        }
+    }
+
+    /// a copy of the config with the passwords and tokens masked, to show or log it
+    #[must_use]
+    pub fn redacted(&self) -> Configuration {
+        fn mask(secret: &mut Option<String>) {
+            if secret.is_some() {
+                *secret = Some("***".to_string());
+            }
+        }
+        let mut config = self.clone();
+        if let Some(mqtt) = &mut config.mqtt {
+            mask(&mut mqtt.password);
+        }
+        if let Some(scrobble) = &mut config.scrobble {
+            mask(&mut scrobble.listenbrainz_token);
+            mask(&mut scrobble.lastfm_secret);
+            mask(&mut scrobble.lastfm_session_key);
+            mask(&mut scrobble.lastfm_password);
+        }
+        for auth in config
+            .renderers
+            .values_mut()
+            .filter_map(|s| s.auth.as_mut())
+        {
+            mask(&mut auth.password);
+            mask(&mut auth.token);
+        }
+        mask(&mut config.shutdown_token);
+        config
+    }
+
+    /// the effective config, the user config laid over the system config with the overrides of
+    /// the environment, for `config show`, with the secrets masked
+    #[must_use]
+    pub fn show_config(&self) -> String {
+        let mut header = String::new();
+        if system_layer().is_some() {
+            header += &format!("# system config: {}\n", system_config_path().display());
+        }
+        let configfile =
+            Self::get_arg_config_path().unwrap_or_else(|| Self::get_config_path(CONFIGFILE));
+        header += &format!("# user config: {}\n", configfile.display());
+        for o in overrides() {
+            header += &format!("# environment: {}\n", o.var);
+        }
+        let conf = Config {
+            configuration: self.redacted(),
+        };
+        header + &toml::to_string(&conf).unwrap_or_default()
+    }
+
+    /// Report the problems of a config file that can't be read, and offer a repaired config
+    /// file with the invalid lines commented out. swyh-rs then runs with the repaired
+    /// config, or else the defaults, without overwriting the config file.
+    fn repaired_config(configfile: &Path, text: &str) -> Config {
+        let (problems, repaired) = repair_config::<Config>(text);
+        let mut messages: Vec<String> = problems
+            .iter()
+            .map(|p| format!("Invalid config {}: {p}", configfile.display()))
+            .collect();
+        let mut config = if let Some((config, repaired_text)) = repaired {
+            let repaired_file = configfile.with_extension("repaired.toml");
+            match fs::write(&repaired_file, repaired_text) {
+                Ok(()) => messages.push(format!(
+                    "The invalid lines are commented out in {}, review it and rename it to {} to use it",
+                    repaired_file.display(),
+                    configfile.display()
+                )),
+                Err(e) => messages.push(format!(
+                    "Unable to write the repaired config {}: {e}",
+                    repaired_file.display()
+                )),
+            }
+            config
+        } else {
+            messages.push("The config can't be repaired, using the default config".to_string());
+            Config {
+                configuration: Configuration::new(),
+            }
+        };
+        messages.push(format!(
+            "Settings are not saved until {} is valid",
+            configfile.display()
+        ));
+        for message in &messages {
+            eprintln!("{message}");
+        }
+        config.configuration.read_only = true;
+        config.configuration.config_problems = messages;
+        config
+    }
+
+    /// move the plaintext credentials to the keyring, true if any were moved
+    fn move_secrets_to_keyring(&mut self) -> bool {
+        let config_id = self.config_id.clone().unwrap_or_default();
+        let mut moved = false;
+        if let Some(mqtt) = &mut self.mqtt {
+            if let Some(password) = &mqtt.password {
+                match store_secret(MQTT_PASSWORD, &config_id, password) {
+                    Ok(()) => {
+                        self.config_messages
+                            .push("Moved the MQTT password to the keyring".to_string());
+                        mqtt.password = None;
+                        moved = true;
+                    }
+                    Err(e) => self.config_problems.push(format!(
+                        "Unable to store the MQTT password in the keyring: {e}"
+                    )),
+                }
+            }
+        }
+        if let Some(scrobble) = &mut self.scrobble {
+            for (name, secret) in [
+                (LISTENBRAINZ_TOKEN, &mut scrobble.listenbrainz_token),
+                (LASTFM_SECRET, &mut scrobble.lastfm_secret),
+                (LASTFM_SESSION_KEY, &mut scrobble.lastfm_session_key),
+                (LASTFM_PASSWORD, &mut scrobble.lastfm_password),
+            ] {
+                let Some(value) = secret else {
+                    continue;
+                };
+                match store_secret(name, &config_id, value) {
+                    Ok(()) => {
+                        self.config_messages
+                            .push(format!("Moved the {name} to the keyring"));
+                        *secret = None;
+                        moved = true;
+                    }
+                    Err(e) => self
+                        .config_problems
+                        .push(format!("Unable to store the {name} in the keyring: {e}")),
+                }
+            }
+        }
+        for (renderer, settings) in &mut self.renderers {
+            let Some(auth) = &mut settings.auth else {
+                continue;
+            };
+            for (name, secret) in [
+                (RENDERER_PASSWORD, &mut auth.password),
+                (RENDERER_TOKEN, &mut auth.token),
+            ] {
+                let Some(value) = secret else {
+                    continue;
+                };
+                match store_secret(&renderer_secret(name, renderer), &config_id, value) {
+                    Ok(()) => {
+                        self.config_messages
+                            .push(format!("Moved the {name} of {renderer} to the keyring"));
+                        *secret = None;
+                        moved = true;
+                    }
+                    Err(e) => {
+                        self.config_problems.push(format!(
+                            "Unable to store the {name} of {renderer} in the keyring: {e}"
+                        ));
+                    }
+                }
+            }
+        }
+        moved
+    }
+
     pub fn update_config(&self) -> std::io::Result<()> {
         if self.read_only {
             return Ok(());
         }
         let configfile = Self::get_config_path(CONFIGFILE);
-        let f = File::create(configfile).unwrap();
         let conf = Config {
             configuration: self.clone(),
         };
-        let s = toml::to_string(&conf).unwrap();
+        let s = Self::config_text(&conf, &configfile);
+        let f = File::create(configfile).unwrap();
         let mut w = BufWriter::new(f);
         w.write_all(s.as_bytes()).unwrap();
         w.flush().unwrap();
@@ -269,15 +1157,15 @@ impl Configuration {
         } else {
             let configfile = Self::get_config_path(CONFIGFILE);
             if !Path::new(&configfile).exists() {
-                println!("Creating a new default config {}", configfile.display());
-                let config = Configuration::new();
+                eprintln!("Creating a new default config {}", configfile.display());
+                let config = Self::default_config();
                 let configuration = Config {
                     configuration: config,
                 };
                 let f = File::create(&configfile).unwrap();
-                let s = toml::to_string(&configuration).unwrap();
+                let s = Self::config_text(&configuration, &configfile);
                 let mut w = BufWriter::new(f);
-                println!("New default CONFIG: {s}");
+                eprintln!("New default CONFIG: {s}");
                 w.write_all(s.as_bytes()).unwrap();
                 w.flush().unwrap();
             }
@@ -285,13 +1173,34 @@ impl Configuration {
         }
     }
 
+    /// the directory of the config and log files: `SWYH_CONFIG_DIR`, or `.swyh-rs` in the
+    /// home directory, or in the temp directory if there is no (writable) home directory,
+    /// like in a container with an arbitrary user
     fn get_config_dir() -> PathBuf {
-        let hd = dirs::home_dir().unwrap_or_default();
-        let config_dir = Path::new(&hd).join(".".to_string() + PKGNAME);
-        if !Path::new(&config_dir).exists() {
-            fs::create_dir_all(&config_dir).unwrap();
+        let config_dir = std::env::var_os(CONFIG_DIR_VAR).map_or_else(
+            || {
+                dirs::home_dir()
+                    .unwrap_or_else(std::env::temp_dir)
+                    .join(".".to_string() + PKGNAME)
+            },
+            PathBuf::from,
+        );
+        if Path::new(&config_dir).is_dir() {
+            return config_dir;
+        }
+        match fs::create_dir_all(&config_dir) {
+            Ok(()) => config_dir,
+            Err(e) => {
+                let temp_dir = std::env::temp_dir().join(".".to_string() + PKGNAME);
+                eprintln!(
+                    "Unable to create the config directory {}: {e}, using {}",
+                    config_dir.display(),
+                    temp_dir.display()
+                );
+                fs::create_dir_all(&temp_dir).unwrap();
+                temp_dir
+            }
         }
-        config_dir
     }
 
     fn get_config_path(filename: &str) -> PathBuf {
@@ -327,7 +1236,7 @@ impl Configuration {
                 };
             };
         }
-        println!("ARG override configfile (-C): {path:?}");
+        eprintln!("ARG override configfile (-C): {path:?}");
         path
     }
 }
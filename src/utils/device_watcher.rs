@@ -0,0 +1,91 @@
+//! Watch for audio devices that are added or removed while swyh-rs is running,
+//! by periodically enumerating the audio devices.
+//!
+//! The changes are logged and the new device list is sent to the main loop,
+//! so that the GUI can update the audio source menu without a restart.
+
+use crossbeam_channel::Sender;
+use std::{thread, time::Duration};
+
+use crate::{
+    enums::messages::MessageType,
+    globals::statics::CONFIG,
+    utils::{
+        audiodevices::get_output_audio_devices,
+        fallback_audio::capture_lost,
+        notify::{notify, NotifyEvent},
+        ui_logger::ui_log,
+    },
+};
+
+/// the names of all audio devices
+fn device_names() -> Vec<String> {
+    get_output_audio_devices()
+        .iter()
+        .map(|d| d.name().to_string())
+        .collect()
+}
+
+/// the devices that were added and removed
+#[must_use]
+pub fn device_changes(old: &[String], new: &[String]) -> (Vec<String>, Vec<String>) {
+    let added = new.iter().filter(|d| !old.contains(d)).cloned().collect();
+    let removed = old.iter().filter(|d| !new.contains(d)).cloned().collect();
+    (added, removed)
+}
+
+/// Start the thread that enumerates the audio devices every `interval_secs`.
+///
+/// A changed device list is sent to the main loop as a `MessageType::AudioDevicesMessage`.
+pub fn run_device_watcher(interval_secs: u32, msg_tx: &Sender<MessageType>) {
+    ui_log(&format!(
+        "Watching for new audio devices every {interval_secs} seconds"
+    ));
+    let msg_tx = msg_tx.clone();
+    let _ = thread::Builder::new()
+        .name("device_watcher".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let mut devices = device_names();
+            loop {
+                thread::sleep(Duration::from_secs(u64::from(interval_secs)));
+                let new_devices = device_names();
+                if new_devices == devices {
+                    continue;
+                }
+                let (added, removed) = device_changes(&devices, &new_devices);
+                for name in added {
+                    ui_log(&format!("Audio device added: {name}"));
+                }
+                let sound_source = CONFIG.read().sound_source.clone();
+                for name in removed {
+                    ui_log(&format!("*W*W*> Audio device removed: {name}"));
+                    if sound_source.as_ref() == Some(&name) {
+                        capture_lost();
+                        notify(
+                            NotifyEvent::CaptureLost,
+                            &format!("The audio source {name} was removed"),
+                        );
+                    }
+                }
+                devices.clone_from(&new_devices);
+                let _ = msg_tx.send(MessageType::AudioDevicesMessage(new_devices));
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_changes() {
+        let old = vec!["Speakers".to_string(), "HDMI".to_string()];
+        let new = vec!["Speakers".to_string(), "USB Audio".to_string()];
+        let (added, removed) = device_changes(&old, &new);
+        assert_eq!(added, vec!["USB Audio".to_string()]);
+        assert_eq!(removed, vec!["HDMI".to_string()]);
+        let (added, removed) = device_changes(&old, &old);
+        assert!(added.is_empty() && removed.is_empty());
+    }
+}
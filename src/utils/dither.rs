@@ -0,0 +1,117 @@
+//! Dithering of the f32 captured samples when they are reduced to 16 bit,
+//! instead of plain truncation.
+//!
+//! TPDF dither adds triangular noise of +/- 1 LSB before rounding, which turns the
+//! quantization distortion of low level signals into a constant noise floor.
+//! Noise shaping also feeds back the quantization error of each channel, moving
+//! the noise to the higher frequencies where it is less audible.
+
+use fastrand::Rng;
+
+use crate::{
+    dsp::chain::{dsp_chain_active, dsp_dither},
+    enums::streaming::Dither,
+    globals::statics::CONFIG,
+    utils::bitperfect::capture_format,
+};
+
+/// dithers the interleaved (stereo) samples of a stream to 16 bit
+#[derive(Clone)]
+pub struct Ditherer {
+    dither: Dither,
+    rng: Rng,
+    error: Vec<f32>,
+    channel: usize,
+}
+
+impl Ditherer {
+    #[must_use]
+    pub fn new(dither: Dither) -> Ditherer {
+        Ditherer::with_channels(dither, 2)
+    }
+
+    /// the ditherer for a stream with more (or less) than 2 channels
+    #[must_use]
+    pub fn with_channels(dither: Dither, channels: u16) -> Ditherer {
+        Ditherer {
+            dither,
+            rng: Rng::new(),
+            error: vec![0.0; usize::from(channels.max(1))],
+            channel: 0,
+        }
+    }
+
+    /// the dithered 16 bit sample, the samples must be passed in stream order
+    pub fn to_i16(&mut self, f32_sample: f32) -> i16 {
+        let ch = self.channel;
+        self.channel = (ch + 1) % self.error.len();
+        let sample = f32_sample * 32768.0;
+        let shaped = match self.dither {
+            Dither::Shaped => sample - self.error[ch],
+            _ => sample,
+        };
+        let noise = match self.dither {
+            Dither::Off => 0.0,
+            Dither::Tpdf | Dither::Shaped => self.rng.f32() - self.rng.f32(),
+        };
+        let quantized = (shaped + noise)
+            .round()
+            .clamp(f32::from(i16::MIN), f32::from(i16::MAX));
+        self.error[ch] = quantized - shaped;
+        quantized as i16
+    }
+}
+
+/// the ditherer for a new stream, if dithering is configured and the bit depth is reduced
+#[must_use]
+pub fn stream_ditherer(bits_per_sample: u16, channels: u16) -> Option<Ditherer> {
+    let conf = CONFIG.read();
+    // a dither stage of the DSP chain overrides the dither option
+    let dither = dsp_dither().unwrap_or(conf.dither.unwrap_or_default());
+    // 16 bit integer captures are not reduced unless the DSP chain changed them,
+    // and there's no processing in bit-perfect mode
+    if dither == Dither::Off
+        || bits_per_sample != 16
+        || conf.bit_perfect
+        || (!dsp_chain_active()
+            && matches!(
+                capture_format(),
+                Some(cpal::SampleFormat::I16 | cpal::SampleFormat::U16)
+            ))
+    {
+        return None;
+    }
+    Some(Ditherer::with_channels(dither, channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dither() {
+        let lsb = 1.0 / 32768.0;
+        // without dither a sample between two steps is always rounded the same way
+        let mut ditherer = Ditherer::new(Dither::Off);
+        assert!((0..100).all(|_| ditherer.to_i16(10.25 * lsb) == 10));
+        // TPDF stays within one step of the sample, and averages out to it
+        let mut ditherer = Ditherer::new(Dither::Tpdf);
+        let samples = (0..100_000)
+            .map(|_| ditherer.to_i16(10.25 * lsb))
+            .collect::<Vec<i16>>();
+        assert!(samples.iter().all(|s| (9..=11).contains(s)));
+        let average = samples.iter().map(|s| f64::from(*s)).sum::<f64>() / 100_000.0;
+        assert!((average - 10.25).abs() < 0.05);
+        // noise shaping too
+        let mut ditherer = Ditherer::new(Dither::Shaped);
+        let average = (0..100_000)
+            .map(|_| f64::from(ditherer.to_i16(-10.25 * lsb)))
+            .sum::<f64>()
+            / 100_000.0;
+        assert!((average + 10.25).abs() < 0.05);
+        // out of range samples are clamped
+        let mut ditherer = Ditherer::new(Dither::Tpdf);
+        assert_eq!(ditherer.to_i16(1.5), i16::MAX);
+        assert_eq!(ditherer.to_i16(-1.5), i16::MIN);
+    }
+}
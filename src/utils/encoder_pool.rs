@@ -0,0 +1,108 @@
+//! The pool of FLAC encoder threads.
+//!
+//! Every FLAC client needs an encoder thread for as long as it is streaming. The threads
+//! are reused for new clients instead of spawning a new thread per client, and each thread
+//! gets the configured priority and CPU core once when it is started, so that a few FLAC
+//! encoders on a small machine (e.g. a Raspberry Pi) don't starve the audio capture.
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        LazyLock,
+    },
+    thread,
+};
+
+use crate::{
+    globals::statics::CONFIG,
+    utils::{
+        priority::{pin_thread_to_core, set_thread_priority},
+        ui_logger::ui_log,
+    },
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+struct EncoderPool {
+    jobs: (Sender<Job>, Receiver<Job>),
+    // the threads waiting for a job
+    idle: AtomicUsize,
+    threads: AtomicUsize,
+}
+
+static POOL: LazyLock<EncoderPool> = LazyLock::new(|| EncoderPool {
+    jobs: unbounded(),
+    idle: AtomicUsize::new(0),
+    threads: AtomicUsize::new(0),
+});
+
+/// Run an encoder on an idle pool thread, or on a new one if they are all busy.
+pub fn run_encoder<F>(encoder: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    // claim an idle thread, each idle thread takes exactly one job
+    if POOL
+        .idle
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+        .is_err()
+    {
+        start_encoder_thread();
+    }
+    let _ = POOL.jobs.0.send(Box::new(encoder));
+}
+
+/// the CPU core for encoder thread `index` from the configured cores, if any
+#[must_use]
+pub fn encoder_core(cores: &[usize], index: usize) -> Option<usize> {
+    if cores.is_empty() {
+        None
+    } else {
+        Some(cores[index % cores.len()])
+    }
+}
+
+fn start_encoder_thread() {
+    let index = POOL.threads.fetch_add(1, Ordering::AcqRel);
+    let (priority, core) = {
+        let conf = CONFIG.read();
+        (
+            conf.encoder_priority,
+            encoder_core(conf.encoder_cores.as_deref().unwrap_or_default(), index),
+        )
+    };
+    let jobs = POOL.jobs.1.clone();
+    let _ = thread::Builder::new()
+        .name(format!("flac_encoder_{index}"))
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            if let Some(nice) = priority {
+                set_thread_priority(nice);
+            }
+            if let Some(core) = core {
+                pin_thread_to_core(core);
+            }
+            ui_log(&format!(
+                "Started FLAC encoder thread {index}{}{}",
+                priority.map_or(String::new(), |p| format!(", nice {p}")),
+                core.map_or(String::new(), |c| format!(", CPU core {c}")),
+            ));
+            while let Ok(encoder) = jobs.recv() {
+                encoder();
+                POOL.idle.fetch_add(1, Ordering::AcqRel);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoder_core() {
+        assert_eq!(encoder_core(&[], 3), None);
+        assert_eq!(encoder_core(&[2, 3], 0), Some(2));
+        assert_eq!(encoder_core(&[2, 3], 3), Some(3));
+    }
+}
@@ -0,0 +1,124 @@
+//! Fade-in when a client starts streaming and fade-out before a renderer is stopped,
+//! to avoid the pop or abrupt cut that some speakers produce.
+//!
+//! The fade is a gain ramp on the interleaved samples that the `wave_reader` writes to the
+//! `ChannelStream` of a client. After a fade-out the stream stays silent, so the renderer
+//! can be stopped without cutting off the audio. In bit-perfect mode there is no fade.
+
+use std::time::Duration;
+
+use crate::globals::statics::CONFIG;
+
+/// the gain ramp of a client stream
+#[derive(Debug)]
+pub struct Fade {
+    sample_rate: u32,
+    // the number of interleaved channels
+    channels: usize,
+    // the ramp lengths in frames
+    in_frames: u32,
+    out_frames: u32,
+    // the current position on the ramp, 0 is silent and 1.0 is full gain
+    gain: f32,
+    fading_out: bool,
+}
+
+impl Fade {
+    #[must_use]
+    pub fn new(sample_rate: u32, fade_in_msec: u32, fade_out_msec: u32) -> Fade {
+        let frames = |msec: u32| (u64::from(sample_rate) * u64::from(msec) / 1000) as u32;
+        let in_frames = frames(fade_in_msec);
+        Fade {
+            sample_rate,
+            channels: 2,
+            in_frames,
+            out_frames: frames(fade_out_msec),
+            gain: if in_frames > 0 { 0.0 } else { 1.0 },
+            fading_out: false,
+        }
+    }
+
+    /// the fade for a new client stream with the configured fade times
+    #[must_use]
+    pub fn for_stream(sample_rate: u32, channels: u16) -> Fade {
+        let conf = CONFIG.read();
+        let mut fade = if conf.bit_perfect {
+            Fade::new(sample_rate, 0, 0)
+        } else {
+            Fade::new(
+                sample_rate,
+                conf.fade_in_msec.unwrap_or_default(),
+                conf.fade_out_msec.unwrap_or_default(),
+            )
+        };
+        fade.channels = usize::from(channels.max(1));
+        fade
+    }
+
+    /// start the fade-out, returns how long it takes
+    pub fn fade_out(&mut self) -> Duration {
+        if self.out_frames == 0 {
+            return Duration::ZERO;
+        }
+        self.fading_out = true;
+        Duration::from_millis(u64::from(self.out_frames) * 1000 / u64::from(self.sample_rate))
+    }
+
+    /// apply the gain ramp to interleaved stereo samples
+    pub fn apply(&mut self, samples: &mut [f32]) {
+        if !self.fading_out && self.gain >= 1.0 {
+            return;
+        }
+        let step = if self.fading_out {
+            -1.0 / self.out_frames as f32
+        } else {
+            1.0 / self.in_frames as f32
+        };
+        for frame in samples.chunks_exact_mut(self.channels) {
+            self.gain = (self.gain + step).clamp(0.0, 1.0);
+            // a quadratic curve sounds more even than a linear one
+            let gain = self.gain * self.gain;
+            frame.iter_mut().for_each(|sample| *sample *= gain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade() {
+        // no fade: the samples are unchanged
+        let mut fade = Fade::new(1000, 0, 0);
+        let mut samples = vec![0.5f32; 20];
+        fade.apply(&mut samples);
+        assert!(samples.iter().all(|s| *s == 0.5));
+        assert_eq!(fade.fade_out(), Duration::ZERO);
+        // fade-in over 4 frames, then full gain
+        let mut fade = Fade::new(1000, 4, 8);
+        let mut samples = vec![1.0f32; 12];
+        fade.apply(&mut samples);
+        assert_eq!(samples[0], samples[1]);
+        assert!(samples[0] > 0.0 && samples[0] < samples[2] && samples[2] < samples[4]);
+        assert!(samples[6..].iter().all(|s| *s == 1.0));
+        // fade-out over 8 frames, then silence
+        assert_eq!(fade.fade_out(), Duration::from_millis(8));
+        let mut samples = vec![1.0f32; 20];
+        fade.apply(&mut samples);
+        assert!(samples[0] < 1.0 && samples[0] > samples[2]);
+        assert!(samples[14..].iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn test_fade_channels() {
+        // all channels of a 5.1 frame get the same gain
+        let mut fade = Fade::new(1000, 4, 0);
+        fade.channels = 6;
+        let mut samples = vec![1.0f32; 24];
+        fade.apply(&mut samples);
+        assert!(samples[0..6].iter().all(|s| *s == samples[0]));
+        assert!(samples[0] < samples[6] && samples[6] < samples[12]);
+        assert!(samples[18..].iter().all(|s| *s == 1.0));
+    }
+}
@@ -0,0 +1,215 @@
+//! Stream a fallback audio file when the audio capture is lost.
+//!
+//! When the captured audio device disappears the listeners would only hear silence,
+//! and assume that their speaker broke. With `fallback_audio` in the config, e.g. a
+//! recorded announcement that the source is down, the WAV file is streamed (looped)
+//! to all clients until captured samples arrive again.
+
+use std::{
+    fs,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    network_source::{read_wav_chunks, WavFormat},
+    ui_logger::ui_log,
+};
+use crate::{
+    globals::statics::{CLIENTS, CONFIG, STREAM_PARAMS},
+    openhome::rendercontrol::WavData,
+};
+
+/// the length of the fallback audio blocks that are sent to the clients
+const BLOCK_MSEC: u32 = 100;
+
+/// is the audio capture lost
+static CAPTURE_LOST: AtomicBool = AtomicBool::new(false);
+
+/// the audio capture device is gone
+pub fn capture_lost() {
+    if !CAPTURE_LOST.swap(true, Ordering::AcqRel) && CONFIG.read().fallback_audio.is_some() {
+        ui_log("*W*W*> Audio capture lost, streaming the fallback audio");
+    }
+}
+
+/// is the audio capture alive, false while it is lost
+#[must_use]
+pub fn capture_alive() -> bool {
+    !CAPTURE_LOST.load(Ordering::Relaxed)
+}
+
+/// captured samples arrive again, called by the `wave_reader`
+pub fn capture_restored() {
+    if CAPTURE_LOST.load(Ordering::Relaxed) && CAPTURE_LOST.swap(false, Ordering::AcqRel) {
+        ui_log("Audio capture restored");
+    }
+}
+
+/// true unless the capture streams more than 2 channels, the fallback audio
+/// and the announcements are stereo only
+pub fn stereo_stream() -> bool {
+    STREAM_PARAMS
+        .read()
+        .is_none_or(|(_, channels)| channels == 2)
+}
+
+/// Start the thread that streams the fallback audio while the capture is lost,
+/// if a fallback audio file is configured.
+pub fn start_fallback_audio(wd: &WavData) {
+    let Some(file) = CONFIG.read().fallback_audio.clone() else {
+        return;
+    };
+    let sample_rate = wd.sample_rate.0;
+    let samples = match fs::read(&file)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| parse_wav(&bytes))
+    {
+        Ok((rate, samples)) if !samples.is_empty() => resample(&samples, rate, sample_rate),
+        Ok(_) => {
+            ui_log(&format!("*E*E*> Fallback audio {file} has no samples"));
+            return;
+        }
+        Err(e) => {
+            ui_log(&format!(
+                "*E*E*> Could not read the fallback audio {file}: {e}"
+            ));
+            return;
+        }
+    };
+    ui_log(&format!(
+        "Fallback audio {file}: {:.1} s",
+        samples.len() as f64 / 2.0 / f64::from(sample_rate)
+    ));
+    let block_size = (sample_rate * BLOCK_MSEC / 1000 * 2) as usize;
+    let _ = thread::Builder::new()
+        .name("fallback_audio".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let block_time = Duration::from_millis(u64::from(BLOCK_MSEC));
+            loop {
+                if !CAPTURE_LOST.load(Ordering::Acquire) || !stereo_stream() {
+                    thread::sleep(block_time);
+                    continue;
+                }
+                // restart at the beginning of the file for every loss
+                let mut looped = samples.iter().copied().cycle();
+                let mut next = Instant::now();
+                while CAPTURE_LOST.load(Ordering::Acquire) {
+                    let block: Vec<f32> = looped.by_ref().take(block_size).collect();
+                    CLIENTS
+                        .read()
+                        .iter()
+                        .for_each(|(_, client)| client.write(&block));
+                    next += block_time;
+                    thread::sleep(next.saturating_duration_since(Instant::now()));
+                }
+            }
+        });
+}
+
+/// the sample rate and the interleaved stereo samples of a PCM (16, 24 or 32 bit) or
+/// 32 bit float WAV file, a mono file is played on both channels
+pub fn parse_wav(bytes: &[u8]) -> Result<(u32, Vec<f32>), String> {
+    let mut data = bytes;
+    let header = read_wav_chunks(&mut data).map_err(|e| e.to_string())?;
+    let WavFormat {
+        sample_rate,
+        channels,
+        bits_per_sample: bits,
+    } = header.format;
+    let format = header.format_tag;
+    let width = usize::from(bits / 8);
+    if channels == 0 || width == 0 {
+        return Err(format!(
+            "unsupported format: {channels} channels {bits} bits"
+        ));
+    }
+    let sample = |s: &[u8]| -> Option<f32> {
+        match (format, bits) {
+            (1, 16) => Some(f32::from(i16::from_le_bytes([s[0], s[1]])) / 32768.0),
+            (1, 24) => Some(i32::from_le_bytes([0, s[0], s[1], s[2]]) as f32 / 2_147_483_648.0),
+            (1, 32) => Some(i32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f32 / 2_147_483_648.0),
+            (3, 32) => Some(f32::from_le_bytes([s[0], s[1], s[2], s[3]])),
+            _ => None,
+        }
+    };
+    let data = &data[..(header.data_size as usize).min(data.len())];
+    let frame_size = width * usize::from(channels);
+    let mut samples = Vec::with_capacity(data.len() / frame_size * 2);
+    for frame in data.chunks_exact(frame_size) {
+        let left = sample(&frame[..width])
+            .ok_or_else(|| format!("unsupported format {format} with {bits} bits"))?;
+        let right = if channels > 1 {
+            sample(&frame[width..2 * width]).unwrap_or(left)
+        } else {
+            left
+        };
+        samples.push(left);
+        samples.push(right);
+    }
+    Ok((sample_rate, samples))
+}
+
+/// linear resampling of interleaved stereo samples
+#[must_use]
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || from == 0 || samples.len() < 4 {
+        return samples.to_vec();
+    }
+    let frames = samples.len() / 2;
+    let out_frames = (frames as u64 * u64::from(to) / u64::from(from)) as usize;
+    let step = f64::from(from) / f64::from(to);
+    let mut out = Vec::with_capacity(out_frames * 2);
+    for n in 0..out_frames {
+        let pos = n as f64 * step;
+        let i = (pos as usize).min(frames - 2);
+        let frac = ((pos - i as f64) as f32).min(1.0);
+        for ch in 0..2 {
+            let a = samples[i * 2 + ch];
+            let b = samples[(i + 1) * 2 + ch];
+            out.push(a + (b - a) * frac);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enums::streaming::HeaderSize, utils::rwstream::create_wav_hdr};
+
+    fn wav_bytes(channels: u16, sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+        let data_size = (samples.len() * 2) as u64;
+        let mut wav = create_wav_hdr(sample_rate, 16, channels, HeaderSize::Bytes(data_size));
+        for s in samples {
+            wav.extend_from_slice(&s.to_le_bytes());
+        }
+        wav
+    }
+
+    #[test]
+    fn test_parse_wav() {
+        let (rate, samples) = parse_wav(&wav_bytes(2, 44100, &[16384, -16384])).unwrap();
+        assert_eq!(rate, 44100);
+        assert_eq!(samples, vec![0.5, -0.5]);
+        // mono is played on both channels
+        let (_, samples) = parse_wav(&wav_bytes(1, 22050, &[16384, 0])).unwrap();
+        assert_eq!(samples, vec![0.5, 0.5, 0.0, 0.0]);
+        assert!(parse_wav(b"RIFF").is_err());
+        // a chunk after the data is not played
+        let mut wav = wav_bytes(2, 44100, &[16384, -16384]);
+        wav.extend_from_slice(b"LIST\0\0\0\0");
+        assert_eq!(parse_wav(&wav).unwrap().1, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn test_resample() {
+        let samples = vec![0.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, -1.0];
+        assert_eq!(resample(&samples, 44100, 44100), samples);
+        let up = resample(&samples, 22050, 44100);
+        assert_eq!(up.len(), 16);
+        assert_eq!(&up[..6], &[0.0, 0.0, 0.5, -0.5, 1.0, -1.0]);
+    }
+}
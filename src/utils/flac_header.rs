@@ -0,0 +1,236 @@
+//! The STREAMINFO header of the FLAC stream.
+//!
+//! The encoder writes the STREAMINFO header before the first frame, and a live stream
+//! can't rewrite it at the end: the block size is fixed, and the total samples and the
+//! MD5 signature are 0 (unknown). Some renderers (e.g. Sonos Gen2 amps) appear to reject
+//! these values, so the header can be patched with the values of a preset and/or the
+//! configured values while it is streamed. The header that is sent is logged.
+//! The encoder writes frames with a fixed block size (1152 samples at compression levels 0-2,
+//! 4096 at the higher levels), the configured block size range always includes it.
+
+use log::info;
+
+use crate::{enums::streaming::FlacHeaderPreset, utils::configuration::FlacHeaderSettings};
+
+/// the "fLaC" marker, the metadata block header and the 34 bytes of the STREAMINFO block
+pub const STREAMINFO_END: usize = 42;
+/// the offset of the STREAMINFO block
+const STREAMINFO: usize = 8;
+/// the smallest and largest block size of the FLAC subset
+const SUBSET_MIN_BLOCKSIZE: u16 = 16;
+const SUBSET_MAX_BLOCKSIZE: u16 = 4608;
+/// the largest number of total samples, 36 bits
+const MAX_TOTAL_SAMPLES: u64 = (1 << 36) - 1;
+/// the MD5 of nothing, not a signature of the audio but not the "unknown" 0 either
+const DUMMY_MD5: [u8; 16] = [
+    0xd4, 0x1d, 0x8c, 0xd9, 0x8f, 0x00, 0xb2, 0x04, 0xe9, 0x80, 0x09, 0x98, 0xec, 0xf8, 0x42, 0x7e,
+];
+
+/// the STREAMINFO values that replace the values of the encoder
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlacHeader {
+    pub min_blocksize: Option<u16>,
+    pub max_blocksize: Option<u16>,
+    pub max_total_samples: bool,
+    pub dummy_md5: bool,
+}
+
+impl FlacHeader {
+    /// the header values of the preset, overridden by the configured values,
+    /// for an encoder with a fixed `block_size`
+    #[must_use]
+    pub fn from_settings(settings: &FlacHeaderSettings, block_size: u16) -> FlacHeader {
+        let mut header = match settings.preset.unwrap_or_default() {
+            FlacHeaderPreset::Encoder => FlacHeader::default(),
+            FlacHeaderPreset::MaxLength => FlacHeader {
+                max_total_samples: true,
+                dummy_md5: true,
+                ..Default::default()
+            },
+        };
+        // within the FLAC subset, and min <= block size <= max
+        let block_size = block_size.clamp(SUBSET_MIN_BLOCKSIZE, SUBSET_MAX_BLOCKSIZE);
+        if let Some(min_blocksize) = settings.min_blocksize {
+            header.min_blocksize = Some(min_blocksize.clamp(SUBSET_MIN_BLOCKSIZE, block_size));
+        }
+        if let Some(max_blocksize) = settings.max_blocksize {
+            header.max_blocksize = Some(max_blocksize.clamp(block_size, SUBSET_MAX_BLOCKSIZE));
+        }
+        if let Some(max_total_samples) = settings.max_total_samples {
+            header.max_total_samples = max_total_samples;
+        }
+        if let Some(dummy_md5) = settings.dummy_md5 {
+            header.dummy_md5 = dummy_md5;
+        }
+        header
+    }
+
+    /// patch the STREAMINFO block at the start of the stream, returns false if the
+    /// stream doesn't start with a STREAMINFO block
+    pub fn patch(&self, stream: &mut [u8]) -> bool {
+        if !is_streaminfo(stream) {
+            return false;
+        }
+        let si = &mut stream[STREAMINFO..STREAMINFO_END];
+        if let Some(min_blocksize) = self.min_blocksize {
+            si[0..2].copy_from_slice(&min_blocksize.to_be_bytes());
+        }
+        if let Some(max_blocksize) = self.max_blocksize {
+            si[2..4].copy_from_slice(&max_blocksize.to_be_bytes());
+        }
+        if self.max_total_samples {
+            // the low 4 bits of byte 13 and bytes 14..18
+            si[13] |= 0x0f;
+            si[14..18].copy_from_slice(&[0xff; 4]);
+        }
+        if self.dummy_md5 {
+            si[18..34].copy_from_slice(&DUMMY_MD5);
+        }
+        true
+    }
+}
+
+fn is_streaminfo(stream: &[u8]) -> bool {
+    // the block type is in the low 7 bits, STREAMINFO is 0
+    stream.len() >= STREAMINFO_END && &stream[0..4] == b"fLaC" && stream[4] & 0x7f == 0
+}
+
+/// the STREAMINFO values for the log
+#[must_use]
+pub fn describe_streaminfo(stream: &[u8]) -> String {
+    if !is_streaminfo(stream) {
+        return "no STREAMINFO header".to_string();
+    }
+    let si = &stream[STREAMINFO..STREAMINFO_END];
+    let u24 = |b: &[u8]| (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+    let packed = u64::from_be_bytes(si[10..18].try_into().unwrap());
+    let sample_rate = packed >> 44;
+    let channels = ((packed >> 41) & 0x7) + 1;
+    let bits_per_sample = ((packed >> 36) & 0x1f) + 1;
+    let total_samples = packed & MAX_TOTAL_SAMPLES;
+    let md5 = si[18..34]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    format!(
+        "STREAMINFO: blocksize {}-{}, framesize {}-{}, {sample_rate} Hz, {channels} channels, {bits_per_sample} bits, total samples {total_samples}, MD5 {md5}",
+        u16::from_be_bytes([si[0], si[1]]),
+        u16::from_be_bytes([si[2], si[3]]),
+        u24(&si[4..7]),
+        u24(&si[7..10]),
+    )
+}
+
+/// patches and logs the STREAMINFO header of a FLAC stream as it is written
+#[derive(Debug, Clone, Default)]
+pub struct HeaderPatcher {
+    header: FlacHeader,
+    pending: Vec<u8>,
+    done: bool,
+}
+
+impl HeaderPatcher {
+    #[must_use]
+    pub fn new(header: FlacHeader) -> HeaderPatcher {
+        HeaderPatcher {
+            header,
+            pending: Vec::with_capacity(STREAMINFO_END),
+            done: false,
+        }
+    }
+
+    /// the data to send for the written data, held back until the STREAMINFO block
+    /// is complete
+    pub fn process(&mut self, buf: &[u8]) -> Option<Vec<u8>> {
+        if self.done {
+            return Some(buf.to_vec());
+        }
+        self.pending.extend_from_slice(buf);
+        if self.pending.len() < STREAMINFO_END {
+            return None;
+        }
+        self.done = true;
+        let mut data = std::mem::take(&mut self.pending);
+        if self.header != FlacHeader::default() && self.header.patch(&mut data) {
+            info!("Patched FLAC header {:?}", self.header);
+        }
+        info!("FLAC {}", describe_streaminfo(&data));
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the header of a 44.1 kHz 16 bit stereo stream as written by the encoder
+    fn encoder_header() -> Vec<u8> {
+        let mut stream = b"fLaC".to_vec();
+        stream.extend_from_slice(&[0x00, 0x00, 0x00, 0x22]);
+        stream.extend_from_slice(&[0x04, 0x80, 0x04, 0x80]);
+        stream.extend_from_slice(&[0; 6]);
+        stream.extend_from_slice(&[0x0a, 0xc4, 0x42, 0xf0, 0x00, 0x00, 0x00, 0x00]);
+        stream.extend_from_slice(&[0; 16]);
+        stream
+    }
+
+    #[test]
+    fn test_patch_streaminfo() {
+        let mut stream = encoder_header();
+        assert_eq!(
+            describe_streaminfo(&stream),
+            "STREAMINFO: blocksize 1152-1152, framesize 0-0, 44100 Hz, 2 channels, 16 bits, total samples 0, MD5 00000000000000000000000000000000"
+        );
+        let settings = FlacHeaderSettings {
+            preset: Some(FlacHeaderPreset::MaxLength),
+            min_blocksize: Some(8),
+            ..Default::default()
+        };
+        assert!(FlacHeader::from_settings(&settings, 1152).patch(&mut stream));
+        assert_eq!(
+            describe_streaminfo(&stream),
+            "STREAMINFO: blocksize 16-1152, framesize 0-0, 44100 Hz, 2 channels, 16 bits, total samples 68719476735, MD5 d41d8cd98f00b204e9800998ecf8427e"
+        );
+        assert!(!FlacHeader::default().patch(&mut b"RIFF".to_vec()));
+    }
+
+    #[test]
+    fn test_header_patcher() {
+        let settings = FlacHeaderSettings {
+            min_blocksize: Some(16),
+            max_blocksize: Some(4608),
+            ..Default::default()
+        };
+        let mut patcher = HeaderPatcher::new(FlacHeader::from_settings(&settings, 1152));
+        let stream = encoder_header();
+        assert_eq!(patcher.process(&stream[0..4]), None);
+        let data = patcher.process(&stream[4..]).unwrap();
+        assert_eq!(&data[8..12], &[0x00, 0x10, 0x12, 0x00]);
+        assert_eq!(patcher.process(&[1, 2, 3]), Some(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_blocksize_range() {
+        // the range includes the block size of the encoder
+        let settings = FlacHeaderSettings {
+            min_blocksize: Some(4608),
+            max_blocksize: Some(1024),
+            ..Default::default()
+        };
+        let header = FlacHeader::from_settings(&settings, 1152);
+        assert_eq!(header.min_blocksize, Some(1152));
+        assert_eq!(header.max_blocksize, Some(1152));
+        let header = FlacHeader::from_settings(&settings, 4096);
+        assert_eq!(header.min_blocksize, Some(4096));
+        assert_eq!(header.max_blocksize, Some(4096));
+        // and stays within the FLAC subset
+        let settings = FlacHeaderSettings {
+            min_blocksize: Some(0),
+            max_blocksize: Some(65535),
+            ..Default::default()
+        };
+        let header = FlacHeader::from_settings(&settings, 4096);
+        assert_eq!(header.min_blocksize, Some(16));
+        assert_eq!(header.max_blocksize, Some(4608));
+    }
+}
@@ -1,3 +1,11 @@
+use super::{
+    dither::Ditherer,
+    encoder_pool::run_encoder,
+    flac_header::{FlacHeader, HeaderPatcher},
+    resource_monitor::{flac_compression_level, record_thread_cpu},
+    session_stats::SessionStats,
+};
+use crate::globals::statics::CONFIG;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use dasp_sample::Sample;
 use fastrand::Rng;
@@ -12,25 +20,38 @@ use std::{
     time::Duration,
 };
 
-const NOISE_PERIOD_MS: u64 = 250; // milliseconds
+// the number of frames in a FLAC block at compression levels 0-2, and 3-8
+const FLAC_BLOCK_SIZE: usize = 1152;
+const FLAC_BLOCK_SIZE_HIGH: usize = 4096;
 
 // the flacwriter receives the data from the encoder
-// and writes them to the flac output channel
+// and writes them to the flac output channel,
+// with the STREAMINFO header patched as configured
 #[derive(Clone)]
 pub struct FlacWriter {
     flac_out: Sender<Vec<u8>>,
+    patcher: HeaderPatcher,
 }
 
 impl FlacWriter {
+    /// the header values are derived from the block size of the encoder
     #[must_use]
-    pub fn new(flac_out: Sender<Vec<u8>>) -> FlacWriter {
-        FlacWriter { flac_out }
+    pub fn new(flac_out: Sender<Vec<u8>>, block_size: usize) -> FlacWriter {
+        let block_size = u16::try_from(block_size).unwrap_or(u16::MAX);
+        let header = FlacHeader::from_settings(&CONFIG.read().flac_header, block_size);
+        FlacWriter {
+            flac_out,
+            patcher: HeaderPatcher::new(header),
+        }
     }
 }
 
 impl Write for FlacWriter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        match self.flac_out.send(buf.to_vec()) {
+        let Some(data) = self.patcher.process(buf) else {
+            return Ok(buf.len());
+        };
+        match self.flac_out.send(data) {
             Ok(()) => Ok(buf.len()),
             Err(_e) => Err(std::io::Error::new(
                 std::io::ErrorKind::ConnectionAborted,
@@ -56,6 +77,12 @@ pub struct FlacChannel {
     sample_rate: u32,
     bits_per_sample: u32,
     channels: u32,
+    ditherer: Option<Ditherer>,
+    // the capture timeout and the near silence burst in msec
+    silence_timing: (u32, u32),
+    stats: Arc<SessionStats>,
+    // the compression level, the throttle may have lowered the configured level
+    level: u32,
 }
 
 impl FlacChannel {
@@ -65,19 +92,33 @@ impl FlacChannel {
         sample_rate: u32,
         bits_per_sample: u32,
         channels: u32,
+        ditherer: Option<Ditherer>,
+        silence_timing: (u32, u32),
+        stats: Arc<SessionStats>,
     ) -> FlacChannel {
         let (flac_out, flac_in): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = unbounded();
+        let level = flac_compression_level();
         FlacChannel {
             samples_rcvr: samples_chan,
             flac_in,
             active: Arc::new(AtomicBool::new(false)),
-            writer: FlacWriter::new(flac_out),
+            writer: FlacWriter::new(flac_out, flac_block_size(level)),
             sample_rate,
             bits_per_sample,
             channels,
+            ditherer,
+            silence_timing,
+            stats,
+            level,
         }
     }
 
+    /// the duration of an encoded FLAC frame
+    #[must_use]
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(flac_block_size(self.level) as f64 / f64::from(self.sample_rate))
+    }
+
     pub fn run(&self) {
         // copy instance data for thread
         let samples_rdr = self.samples_rcvr.clone();
@@ -85,72 +126,93 @@ impl FlacChannel {
         let ch = self.channels;
         let bps = self.bits_per_sample;
         let sr = self.sample_rate;
+        let mut ditherer = self.ditherer.clone();
+        let (capture_timeout, silence_msec) = self.silence_timing;
         let l_active = self.active.clone();
-        // fire up thread
+        let stats = self.stats.clone();
+        let level = self.level;
+        if level > 0 {
+            info!("FLAC compression level {level}");
+        }
+        // run the encoder on a pool thread
         self.active.store(true, Relaxed);
-        let _thr = std::thread::Builder::new()
-            .name("flac_encoder".into())
-            .stack_size(4 * 1024 * 1024)
-            .spawn(move || {
-                // we're running
-                // setup the encoder
-                let mut outw = WriteWrapper(&mut writer);
-                let mut enc = FlacEncoder::new()
-                    .unwrap()
-                    .channels(ch)
-                    .bits_per_sample(bps)
-                    .sample_rate(sr)
-                    .compression_level(0)
-                    .set_limit_min_bitrate(true)
-                    .init_write(&mut outw)
-                    .unwrap();
-                // read captured samples and encode
-                let shift = if bps == 24 { 8u8 } else { 16u8 };
-                // create the random generator for the white noise
-                let mut rng = fastrand::Rng::with_seed(79);
-                // init NOISE feature and preallocate the noise buffer
-                const DIVISOR: u64 = 1000 / NOISE_PERIOD_MS;
-                let noise_bufsize = ((sr * 2) / DIVISOR as u32) as usize;
-                let mut noise_buf: Vec<f32> = Vec::with_capacity(noise_bufsize);
-                noise_buf.resize(noise_bufsize, 0.0);
-                // read and FLAC encode samples
-                let mut time_out = Duration::from_millis(NOISE_PERIOD_MS);
-                while l_active.load(Relaxed) {
-                    if let Ok(f32_samples) = samples_rdr.recv_timeout(time_out) {
-                        time_out = Duration::from_millis(NOISE_PERIOD_MS);
-                        let samples = f32_samples
+        run_encoder(move || {
+            // we're running
+            // setup the encoder
+            let mut outw = WriteWrapper(&mut writer);
+            let mut enc = FlacEncoder::new()
+                .unwrap()
+                .channels(ch)
+                .bits_per_sample(bps)
+                .sample_rate(sr)
+                .compression_level(level)
+                .set_limit_min_bitrate(true)
+                .init_write(&mut outw)
+                .unwrap();
+            // read captured samples and encode
+            let shift = if bps == 24 { 8u8 } else { 16u8 };
+            // create the random generator for the white noise
+            let mut rng = fastrand::Rng::with_seed(79);
+            // init NOISE feature and preallocate the noise buffer
+            let noise_bufsize =
+                noise_buffer_size(sr, silence_msec, flac_block_size(level)) / 2 * ch as usize;
+            let mut noise_buf: Vec<f32> = Vec::with_capacity(noise_bufsize);
+            noise_buf.resize(noise_bufsize, 0.0);
+            // read and FLAC encode samples
+            let capture_timeout = Duration::from_millis(u64::from(capture_timeout));
+            let mut time_out = capture_timeout;
+            while l_active.load(Relaxed) {
+                stats.record_queue_depth(samples_rdr.len());
+                if let Ok(f32_samples) = samples_rdr.recv_timeout(time_out) {
+                    time_out = capture_timeout;
+                    stats.record_audio();
+                    let samples = if let Some(ditherer) = ditherer.as_mut() {
+                        f32_samples
+                            .iter()
+                            .map(|s| i32::from(ditherer.to_i16(*s)))
+                            .collect::<Vec<i32>>()
+                    } else {
+                        f32_samples
                             .iter()
                             .map(|s| s.to_sample::<i32>() >> shift)
+                            .collect::<Vec<i32>>()
+                    };
+                    if enc
+                        .process_interleaved(
+                            samples.as_slice(),
+                            (samples.len() / ch as usize) as u32,
+                        )
+                        .is_err()
+                    {
+                        info!("Flac encoding interrupted.");
+                        break;
+                    }
+                    record_thread_cpu();
+                } else {
+                    time_out = capture_timeout * 2;
+                    // if no samples for a certain time: send very faint near silence bursts
+                    if l_active.load(Relaxed) {
+                        stats.record_silence(silence_msec);
+                        fill_noise_buffer(&mut rng, &mut noise_buf);
+                        let samples = noise_buf
+                            .iter()
+                            .map(|s| (s.to_sample::<i32>() >> shift) & 0x3)
                             .collect::<Vec<i32>>();
                         if enc
-                            .process_interleaved(samples.as_slice(), (samples.len() / 2) as u32)
+                            .process_interleaved(
+                                samples.as_slice(),
+                                (samples.len() / ch as usize) as u32,
+                            )
                             .is_err()
                         {
-                            info!("Flac encoding interrupted.");
+                            info!("Flac inject near silence interrupted.");
                             break;
                         }
-                    } else {
-                        time_out = Duration::from_millis(NOISE_PERIOD_MS * 2);
-                        // if no samples for a certain time: send very faint near silence bursts
-                        if l_active.load(Relaxed) {
-                            fill_noise_buffer(&mut rng, &mut noise_buf);
-                            let samples = noise_buf
-                                .iter()
-                                .map(|s| (s.to_sample::<i32>() >> shift) & 0x3)
-                                .collect::<Vec<i32>>();
-                            if enc
-                                .process_interleaved(samples.as_slice(), (samples.len() / 2) as u32)
-                                .is_err()
-                            {
-                                info!("Flac inject near silence interrupted.");
-                                break;
-                            }
-                        }
                     }
                 }
-                let _ = enc.finish();
-            })
-            .unwrap();
+            }
+            let _ = enc.finish();
+        });
     }
 
     pub fn stop(&self) {
@@ -166,3 +228,50 @@ fn fill_noise_buffer(rng: &mut Rng, noise_buf: &mut [f32]) {
         .iter_mut()
         .for_each(|sample| *sample = (rng.f32() * 2.0) - 1.0);
 }
+
+///
+/// the number of frames in a FLAC block at a compression level
+///
+fn flac_block_size(level: u32) -> usize {
+    if level <= 2 {
+        FLAC_BLOCK_SIZE
+    } else {
+        FLAC_BLOCK_SIZE_HIGH
+    }
+}
+
+///
+/// the number of stereo samples for a near silence burst of `msec`,
+/// in whole FLAC blocks so that the encoder doesn't hold back a partial block
+///
+fn noise_buffer_size(sample_rate: u32, msec: u32, block_size: usize) -> usize {
+    let frames = (u64::from(sample_rate) * u64::from(msec) / 1000) as usize;
+    frames.div_ceil(block_size).max(1) * block_size * 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_buffer_size() {
+        // 250 msec at 44.1 kHz is 11025 frames, rounded up to 10 blocks
+        assert_eq!(
+            noise_buffer_size(44100, 250, FLAC_BLOCK_SIZE),
+            10 * FLAC_BLOCK_SIZE * 2
+        );
+        assert_eq!(
+            noise_buffer_size(48000, 24, FLAC_BLOCK_SIZE),
+            FLAC_BLOCK_SIZE * 2
+        );
+        assert_eq!(
+            noise_buffer_size(48000, 0, FLAC_BLOCK_SIZE),
+            FLAC_BLOCK_SIZE * 2
+        );
+        // 250 msec at 44.1 kHz is 3 blocks at the higher compression levels
+        assert_eq!(
+            noise_buffer_size(44100, 250, flac_block_size(5)),
+            3 * FLAC_BLOCK_SIZE_HIGH * 2
+        );
+    }
+}
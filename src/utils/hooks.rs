@@ -0,0 +1,303 @@
+//! Shell command and webhook hooks on events, to integrate with scripts and home
+//! automation without MQTT (e.g. dim the lights when the music starts).
+//!
+//! The hooks are configured in the `hooks` list of the config file, each with an event,
+//! a shell `command` and/or a webhook `url`. The command gets the variables of the event
+//! only as `SWYH_*` environment variables, they are never pasted into the command line:
+//! a renderer name comes from the network and could contain shell syntax. The `{variables}`
+//! are replaced url encoded in the webhook url, and the webhook is a POST with the variables
+//! as a JSON object. The hooks run in the background.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    enums::streaming::StreamingState,
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::rendercontrol::Renderer,
+    server::{ssdp_announcer::http_user_agent, streaming_server::StreamerFeedBack},
+    utils::{configuration::EventHook, scripting::script_event, ui_logger::ui_log},
+};
+
+/// samples below this level (-60 dB) are silence
+const SILENCE: f32 = 0.001;
+/// the default silence before the `SilenceDetected` hooks run
+const DEFAULT_SILENCE_SECS: u32 = 60;
+
+/// the events that can run a hook
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum HookEvent {
+    StreamStarted,
+    StreamStopped,
+    CaptureLost,
+    SilenceDetected,
+}
+
+impl fmt::Display for HookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// the silence detection, if there is a `SilenceDetected` hook
+static SILENCE_DETECTOR: Mutex<Option<SilenceDetector>> = Mutex::new(None);
+
+/// detects a prolonged silence, also when no samples arrive
+#[derive(Debug, Clone)]
+struct SilenceDetector {
+    after: Duration,
+    last_sound: Instant,
+    detected: bool,
+}
+
+impl SilenceDetector {
+    fn new(secs: u32, now: Instant) -> SilenceDetector {
+        SilenceDetector {
+            after: Duration::from_secs(u64::from(secs)),
+            last_sound: now,
+            detected: false,
+        }
+    }
+
+    /// process the captured samples, true once when the silence is long enough
+    fn process(&mut self, samples: &[f32], now: Instant) -> bool {
+        if samples.iter().any(|s| s.abs() > SILENCE) {
+            self.last_sound = now;
+            self.detected = false;
+        }
+        self.idle(now)
+    }
+
+    /// true once when the silence is long enough
+    fn idle(&mut self, now: Instant) -> bool {
+        if !self.detected && now.duration_since(self.last_sound) >= self.after {
+            self.detected = true;
+            return true;
+        }
+        false
+    }
+}
+
+/// replace the {variables} in a webhook url, url encoded
+fn fill_url(template: &str, vars: &[(&str, String)]) -> String {
+    vars.iter().fold(template.to_string(), |t, (name, value)| {
+        let value: String = url::form_urlencoded::byte_serialize(value.as_bytes()).collect();
+        t.replace(&format!("{{{name}}}"), &value)
+    })
+}
+
+/// the environment variables of a hook command, e.g. `SWYH_RENDERER`
+fn command_env(vars: &[(&str, String)]) -> Vec<(String, String)> {
+    vars.iter()
+        .map(|(name, value)| (format!("SWYH_{}", name.to_uppercase()), value.clone()))
+        .collect()
+}
+
+/// run a shell command, the variables are only passed in the environment
+fn run_command(command: &str, vars: &[(&str, String)]) {
+    #[cfg(target_os = "windows")]
+    let mut cmd = {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    };
+    #[cfg(not(target_os = "windows"))]
+    let mut cmd = {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(command).envs(command_env(vars));
+    match cmd.status() {
+        Ok(status) if status.success() => (),
+        Ok(status) => ui_log(&format!("*W*W*> Hook command {command} failed: {status}")),
+        Err(e) => ui_log(&format!("*W*W*> Could not run hook command {command}: {e}")),
+    }
+}
+
+/// POST the variables as a JSON object to a webhook
+fn call_webhook(url: &str, vars: &[(&str, String)]) {
+    let url = fill_url(url, vars);
+    let body: serde_json::Map<String, serde_json::Value> = vars
+        .iter()
+        .map(|(name, value)| ((*name).to_string(), value.clone().into()))
+        .collect();
+    match attohttpc::post(&url)
+        .header("User-Agent", http_user_agent())
+        .header("Content-Type", "application/json")
+        .timeout(Duration::from_secs(5))
+        .text(serde_json::Value::Object(body).to_string())
+        .send()
+    {
+        Ok(resp) if resp.is_success() => (),
+        Ok(resp) => ui_log(&format!("*W*W*> Webhook {url} returned {}", resp.status())),
+        Err(e) => ui_log(&format!("*W*W*> Webhook {url} failed: {e}")),
+    }
+}
+
+/// run the hooks of an event in the background, with the variables of the event,
+/// and pass the event to the script
+pub fn run_hooks(event: HookEvent, vars: &[(&'static str, String)]) {
+    script_event(event, vars);
+    let hooks: Vec<EventHook> = CONFIG
+        .read()
+        .hooks
+        .iter()
+        .filter(|h| h.event == event)
+        .cloned()
+        .collect();
+    if hooks.is_empty() {
+        return;
+    }
+    let mut vars = vars.to_vec();
+    vars.insert(0, ("event", event.to_string()));
+    let _ = thread::Builder::new().name("hooks".into()).spawn(move || {
+        for hook in &hooks {
+            if let Some(command) = &hook.command {
+                run_command(command, &vars);
+            }
+            if let Some(url) = &hook.url {
+                call_webhook(url, &vars);
+            }
+        }
+    });
+}
+
+/// run a single hook in the background: an http(s) url is a webhook, anything else a shell
+/// command, e.g. the `track_change_hook`
+pub fn run_hook(hook: &str, vars: Vec<(&'static str, String)>) {
+    let hook = hook.to_string();
+    let _ = thread::Builder::new().name("hooks".into()).spawn(move || {
+        if hook.starts_with("http://") || hook.starts_with("https://") {
+            call_webhook(&hook, &vars);
+        } else {
+            run_command(&hook, &vars);
+        }
+    });
+}
+
+/// run the hooks for a renderer that started or stopped streaming
+pub fn hook_streaming_state(feedback: &StreamerFeedBack, renderers: &[Renderer]) {
+    let renderer = renderers
+        .iter()
+        .find(|r| r.remote_addr == feedback.remote_ip)
+        .map_or(feedback.remote_ip.clone(), |r| r.dev_name.clone());
+    let vars = vec![
+        ("renderer", renderer),
+        ("ip", feedback.remote_ip.clone()),
+        ("format", feedback.streaming_format.to_string()),
+    ];
+    match feedback.streaming_state {
+        StreamingState::Started => run_hooks(HookEvent::StreamStarted, &vars),
+        StreamingState::Ended => {
+            // a renderer can have more than one connection
+            let still_streaming = CLIENTS
+                .read()
+                .values()
+                .any(|chanstrm| chanstrm.remote_ip == feedback.remote_ip);
+            if !still_streaming {
+                let mut vars = vars;
+                vars.push(("secs", feedback.streaming_time.as_secs().to_string()));
+                run_hooks(HookEvent::StreamStopped, &vars);
+            }
+        }
+    }
+}
+
+fn silence_detected(secs: u32) {
+    ui_log(&format!("No audio for {secs} seconds"));
+    run_hooks(HookEvent::SilenceDetected, &[("secs", secs.to_string())]);
+}
+
+/// feed the captured samples to the silence detection, called by the `wave_reader`
+pub fn hook_samples(samples: &[f32]) {
+    let detected = SILENCE_DETECTOR.lock().as_mut().and_then(|d| {
+        d.process(samples, Instant::now())
+            .then_some(d.after.as_secs() as u32)
+    });
+    if let Some(secs) = detected {
+        silence_detected(secs);
+    }
+}
+
+/// Enable the silence detection if there is a `SilenceDetected` hook or a script, and start its
+/// watchdog thread for audio sources that deliver no samples when nothing is playing.
+pub fn start_silence_hook() {
+    let (has_hook, secs) = {
+        let conf = CONFIG.read();
+        (
+            conf.script.is_some()
+                || conf
+                    .hooks
+                    .iter()
+                    .any(|h| h.event == HookEvent::SilenceDetected),
+            conf.silence_hook_secs
+                .unwrap_or(DEFAULT_SILENCE_SECS)
+                .max(1),
+        )
+    };
+    if !has_hook {
+        return;
+    }
+    *SILENCE_DETECTOR.lock() = Some(SilenceDetector::new(secs, Instant::now()));
+    let _ = thread::Builder::new()
+        .name("silence_hook".into())
+        .spawn(move || loop {
+            thread::sleep(Duration::from_secs(1));
+            let detected = SILENCE_DETECTOR
+                .lock()
+                .as_mut()
+                .is_some_and(|d| d.idle(Instant::now()));
+            if detected {
+                silence_detected(secs);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_variables() {
+        let vars = [
+            ("event", "StreamStarted".to_string()),
+            ("renderer", "Living Room\"; rm -rf ~; \"".to_string()),
+        ];
+        assert_eq!(
+            fill_url("http://ha/hook?e={event}&r={renderer}&x={unknown}", &vars),
+            "http://ha/hook?e=StreamStarted&r=Living+Room%22%3B+rm+-rf+%7E%3B+%22&x={unknown}"
+        );
+        // the command only gets the variables in its environment
+        assert_eq!(
+            command_env(&vars),
+            [
+                ("SWYH_EVENT".to_string(), "StreamStarted".to_string()),
+                (
+                    "SWYH_RENDERER".to_string(),
+                    "Living Room\"; rm -rf ~; \"".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_silence_detector() {
+        let t0 = Instant::now();
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+        let mut d = SilenceDetector::new(10, t0);
+        assert!(!d.process(&[0.5, 0.5], at(1)));
+        assert!(!d.process(&[0.0001, 0.0], at(5)));
+        assert!(d.process(&[0.0, 0.0], at(11)));
+        // only once per silence
+        assert!(!d.idle(at(30)));
+        assert!(!d.process(&[0.5, 0.0], at(31)));
+        assert!(d.idle(at(41)));
+    }
+}
@@ -5,6 +5,8 @@ use std::net::IpAddr;
 #[cfg(feature = "gui")]
 use std::net::UdpSocket;
 
+use crate::{globals::statics::CONFIG, utils::ui_logger::ui_log};
+
 /// `get_local_address` - get the local ip address, return an `Option<String>`. when it fails, return `None`.
 #[cfg(feature = "gui")]
 pub fn get_local_addr() -> Option<IpAddr> {
@@ -38,3 +40,28 @@ pub fn get_interfaces() -> Vec<String> {
         .for_each(|iface| interfaces.push(iface.addr.ip().to_string()));
     interfaces
 }
+
+/// the address of the streaming server and its urls: the `serve_network` interface if it is
+/// configured and available, e.g. a media VLAN, otherwise the SSDP network address
+#[must_use]
+pub fn get_serve_addr(ssdp_addr: IpAddr, networks: &[String]) -> IpAddr {
+    let Some(serve_network) = CONFIG.read().serve_network.clone() else {
+        return ssdp_addr;
+    };
+    match serve_network.parse::<IpAddr>() {
+        Ok(addr) if networks.contains(&serve_network) => {
+            if addr != ssdp_addr {
+                ui_log(&format!(
+                    "Serving the audio stream on {addr}, SSDP discovery on {ssdp_addr}"
+                ));
+            }
+            addr
+        }
+        _ => {
+            ui_log(&format!(
+                "*W*W*> Serve network {serve_network} not found, serving the audio stream on {ssdp_addr}"
+            ));
+            ssdp_addr
+        }
+    }
+}
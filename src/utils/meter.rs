@@ -0,0 +1,237 @@
+//! The RMS meters of the GUI: their ballistics and scale, peak-hold and clip detection.
+//!
+//! The meters show the RMS level of each channel over a sliding window (default 100 msec),
+//! refreshed 10 times per second by default, on a dBFS scale (default floor -60 dBFS) or on
+//! a linear scale. A peak-hold marker stays at the highest sample peak for 1.5 seconds and
+//! then decays. Samples at (or within 0.01 dB of) full scale are counted as clipped.
+
+use std::collections::VecDeque;
+
+use crate::utils::configuration::Configuration;
+
+/// the default and the allowed RMS window lengths
+pub const DEFAULT_RMS_WINDOW_MSEC: u32 = 100;
+const MIN_RMS_WINDOW_MSEC: u32 = 10;
+const MAX_RMS_WINDOW_MSEC: u32 = 3000;
+/// the default and the allowed meter refresh rates
+pub const DEFAULT_METER_REFRESH_HZ: u32 = 10;
+const MIN_METER_REFRESH_HZ: u32 = 1;
+const MAX_METER_REFRESH_HZ: u32 = 30;
+/// the default lower end of the dBFS scale
+pub const DEFAULT_METER_FLOOR_DB: f64 = -60.0;
+/// the sample level that counts as clipped
+const CLIP_LEVEL: f32 = 0.999;
+/// the time a peak is held
+const PEAK_HOLD_MSEC: u32 = 1500;
+/// the decay of a peak after the hold time
+const PEAK_DECAY_DB_PER_SEC: f64 = 10.0;
+
+/// the scale of the meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterScale {
+    pub linear: bool,
+    pub floor_db: f64,
+}
+
+impl Default for MeterScale {
+    fn default() -> Self {
+        MeterScale {
+            linear: false,
+            floor_db: DEFAULT_METER_FLOOR_DB,
+        }
+    }
+}
+
+impl MeterScale {
+    /// the position of a level between 0 and 1 on the meter scale
+    #[must_use]
+    pub fn fraction(&self, level: f64) -> f64 {
+        if level <= 0.0 {
+            return 0.0;
+        }
+        if self.linear {
+            return level.min(1.0);
+        }
+        let db = 20.0 * level.log10();
+        ((db - self.floor_db) / -self.floor_db).clamp(0.0, 1.0)
+    }
+}
+
+/// the configured meter ballistics and scale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterSettings {
+    pub window_msec: u32,
+    pub refresh_hz: u32,
+    pub scale: MeterScale,
+}
+
+impl MeterSettings {
+    #[must_use]
+    pub fn from_config(conf: &Configuration) -> MeterSettings {
+        MeterSettings {
+            window_msec: conf
+                .rms_window_msec
+                .unwrap_or(DEFAULT_RMS_WINDOW_MSEC)
+                .clamp(MIN_RMS_WINDOW_MSEC, MAX_RMS_WINDOW_MSEC),
+            refresh_hz: conf
+                .meter_refresh_hz
+                .unwrap_or(DEFAULT_METER_REFRESH_HZ)
+                .clamp(MIN_METER_REFRESH_HZ, MAX_METER_REFRESH_HZ),
+            scale: MeterScale {
+                linear: conf.meter_linear,
+                floor_db: conf
+                    .meter_floor_db
+                    .unwrap_or(DEFAULT_METER_FLOOR_DB)
+                    .clamp(-120.0, -20.0),
+            },
+        }
+    }
+}
+
+/// the sliding window of the RMS meters
+pub struct RmsWindow {
+    // the sums of the squared samples (left, right) and the frames of each captured block
+    blocks: VecDeque<(f64, f64, usize)>,
+    frames: usize,
+}
+
+impl Default for RmsWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RmsWindow {
+    #[must_use]
+    pub fn new() -> RmsWindow {
+        RmsWindow {
+            blocks: VecDeque::new(),
+            frames: 0,
+        }
+    }
+
+    /// add interleaved stereo samples, keeping at least `window_frames` frames
+    pub fn push(&mut self, samples: &[f32], window_frames: usize) {
+        let (sum_l, sum_r) = samples.chunks_exact(2).fold((0.0, 0.0), |(l, r), f| {
+            (
+                l + f64::from(f[0]) * f64::from(f[0]),
+                r + f64::from(f[1]) * f64::from(f[1]),
+            )
+        });
+        let frames = samples.len() / 2;
+        self.blocks.push_back((sum_l, sum_r, frames));
+        self.frames += frames;
+        while let Some(&(_, _, oldest)) = self.blocks.front() {
+            if self.frames - oldest < window_frames {
+                break;
+            }
+            self.blocks.pop_front();
+            self.frames -= oldest;
+        }
+    }
+
+    /// the RMS levels (left, right) between 0 and 1 over the window
+    #[must_use]
+    pub fn rms(&self) -> (f64, f64) {
+        if self.frames == 0 {
+            return (0.0, 0.0);
+        }
+        let (sum_l, sum_r) = self
+            .blocks
+            .iter()
+            .fold((0.0, 0.0), |(l, r), b| (l + b.0, r + b.1));
+        let frames = self.frames as f64;
+        ((sum_l / frames).sqrt(), (sum_r / frames).sqrt())
+    }
+}
+
+/// a peak-hold level between 0 and 1
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PeakHold {
+    pub level: f64,
+    updates_left: u32,
+}
+
+impl PeakHold {
+    /// hold a new peak, or decay after the hold time
+    pub fn update(&mut self, peak: f64, refresh_hz: u32) {
+        if peak >= self.level {
+            self.level = peak;
+            self.updates_left = PEAK_HOLD_MSEC * refresh_hz / 1000;
+        } else if self.updates_left > 0 {
+            self.updates_left -= 1;
+        } else {
+            let decay = 10f64.powf(-PEAK_DECAY_DB_PER_SEC / 20.0 / f64::from(refresh_hz));
+            self.level = (self.level * decay).max(peak);
+        }
+    }
+}
+
+/// the meter state shared by the rms monitor thread and the GUI
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MeterState {
+    pub peak_l: PeakHold,
+    pub peak_r: PeakHold,
+    pub clips: u64,
+    pub scale: MeterScale,
+}
+
+/// the peak level and the number of clipped samples of some samples of a channel
+pub fn peak_and_clips<'a>(samples: impl Iterator<Item = &'a f32>) -> (f32, u64) {
+    samples.fold((0.0, 0), |(peak, clips), s| {
+        let level = s.abs();
+        (peak.max(level), clips + u64::from(level >= CLIP_LEVEL))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_scale() {
+        let scale = MeterScale::default();
+        assert_eq!(scale.fraction(0.0), 0.0);
+        assert_eq!(scale.fraction(1.0), 1.0);
+        assert!((scale.fraction(0.5) - (60.0 - 6.0206) / 60.0).abs() < 1e-3);
+        assert_eq!(scale.fraction(0.0001), 0.0);
+        let scale = MeterScale {
+            linear: false,
+            floor_db: -90.0,
+        };
+        assert!(scale.fraction(0.0001) > 0.0);
+        let scale = MeterScale {
+            linear: true,
+            ..Default::default()
+        };
+        assert_eq!(scale.fraction(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_rms_window() {
+        let mut window = RmsWindow::new();
+        assert_eq!(window.rms(), (0.0, 0.0));
+        window.push(&[0.5, 1.0, 0.5, 1.0], 4);
+        assert_eq!(window.rms(), (0.5, 1.0));
+        // the oldest block drops out of a full window
+        window.push(&[0.0; 8], 4);
+        assert_eq!(window.rms(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_peak_hold() {
+        let samples = [0.25f32, -0.5, 1.0, -1.0, 0.1];
+        assert_eq!(peak_and_clips(samples.iter()), (1.0, 2));
+        // hold, then decay
+        let mut hold = PeakHold::default();
+        hold.update(0.8, 10);
+        for _ in 0..15 {
+            hold.update(0.1, 10);
+        }
+        assert_eq!(hold.level, 0.8);
+        hold.update(0.1, 10);
+        assert!(hold.level < 0.8 && hold.level > 0.1);
+        hold.update(0.9, 10);
+        assert_eq!(hold.level, 0.9);
+    }
+}
@@ -0,0 +1,176 @@
+//! Mix a secondary audio source into the captured audio.
+//!
+//! The input stream of the mix source (e.g. a microphone for commentary) feeds the
+//! MIX_BUFFER, and the `wave_reader` mixes the buffered samples into the captured audio
+//! with the configured gain, so that both are streamed together to the renderers.
+
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    BufferSize, Sample, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig,
+};
+use dasp_sample::ToSample;
+use log::warn;
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use super::{audiodevices::get_output_audio_devices, ui_logger::ui_log};
+use crate::{globals::statics::CONFIG, openhome::rendercontrol::WavData};
+
+/// if the mix source runs ahead of the capture more than this it skips the excess
+const MAX_DRIFT_MSEC: usize = 250;
+
+/// the range of the gain of the mix source
+pub const MIN_MIX_GAIN_DB: f32 = -30.0;
+pub const MAX_MIX_GAIN_DB: f32 = 12.0;
+
+/// the stereo samples of the mix source that are waiting to be mixed
+static MIX_BUFFER: Mutex<VecDeque<f32>> = Mutex::new(VecDeque::new());
+/// is the mix source running
+static RUN_MIXER: AtomicBool = AtomicBool::new(false);
+/// the gain of the mix source (the bits of an f32)
+static MIX_GAIN: AtomicU32 = AtomicU32::new(0x3F80_0000); // 1.0
+
+/// set the gain of the mix source in dB
+pub fn set_mix_gain(db: f32) {
+    MIX_GAIN.store(10f32.powf(db / 20.0).to_bits(), Ordering::Relaxed);
+}
+
+/// Start capturing the configured mix source, if any.
+///
+/// The mix source stream is returned to keep it alive, dropping it stops the mixing.
+#[must_use]
+pub fn start_mix_source(wd: &WavData) -> Option<Stream> {
+    let (device_name, gain_db) = {
+        let conf = CONFIG.read();
+        (
+            conf.mix_source.clone()?,
+            conf.mix_gain_db
+                .unwrap_or_default()
+                .clamp(MIN_MIX_GAIN_DB, MAX_MIX_GAIN_DB),
+        )
+    };
+    let Some(device) = get_output_audio_devices()
+        .into_iter()
+        .find(|d| d.name() == device_name)
+    else {
+        ui_log(&format!("*W*W*> Mix source {device_name} not found"));
+        return None;
+    };
+    let default_config = device.default_config().clone();
+    // capture with the sample rate of the captured audio
+    let channels = default_config.channels();
+    let config = StreamConfig {
+        channels,
+        sample_rate: SampleRate(wd.sample_rate.0),
+        buffer_size: BufferSize::Default,
+    };
+    let max_size = wd.sample_rate.0 as usize * 2 * MAX_DRIFT_MSEC / 1000;
+    let device = device.as_ref();
+    let stream = match default_config.sample_format() {
+        SampleFormat::F32 => build_mix_stream::<f32>(device, &config, max_size),
+        SampleFormat::I16 => build_mix_stream::<i16>(device, &config, max_size),
+        SampleFormat::U16 => build_mix_stream::<u16>(device, &config, max_size),
+        format => {
+            ui_log(&format!(
+                "*W*W*> Unsupported mix source sample format {format:?}"
+            ));
+            return None;
+        }
+    };
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            ui_log(&format!(
+                "*W*W*> Could not open mix source {device_name} at {} Hz: {e}",
+                wd.sample_rate.0
+            ));
+            return None;
+        }
+    };
+    if let Err(e) = stream.play() {
+        ui_log(&format!(
+            "*W*W*> Could not start mix source {device_name}: {e}"
+        ));
+        return None;
+    }
+    set_mix_gain(gain_db);
+    MIX_BUFFER.lock().clear();
+    RUN_MIXER.store(true, Ordering::Release);
+    ui_log(&format!("Mixing {device_name} at {gain_db} dB"));
+    Some(stream)
+}
+
+fn build_mix_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    max_size: usize,
+) -> Result<Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + ToSample<f32>,
+{
+    let channels = usize::from(config.channels);
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &_| mix_reader(data, channels, max_size),
+        |err| warn!("an error occurred on the mix source stream: {err}"),
+        None,
+    )
+}
+
+/// `mix_reader` - buffers the samples of the mix source as stereo,
+/// a mono source is mixed into both channels
+fn mix_reader<T>(data: &[T], channels: usize, max_size: usize)
+where
+    T: SizedSample + ToSample<f32>,
+{
+    let mut buffer = MIX_BUFFER.lock();
+    for frame in data.chunks_exact(channels.max(1)) {
+        let left = frame[0].to_sample::<f32>();
+        let right = frame.get(1).map_or(left, |s| s.to_sample::<f32>());
+        buffer.push_back(left);
+        buffer.push_back(right);
+    }
+    if buffer.len() > max_size {
+        let excess = buffer.len() - max_size;
+        buffer.drain(..excess);
+    }
+}
+
+/// mix the buffered samples of the mix source into the captured samples,
+/// called by the `wave_reader`
+pub fn mix_secondary(samples: &mut [f32]) {
+    if !RUN_MIXER.load(Ordering::Acquire) {
+        return;
+    }
+    let gain = f32::from_bits(MIX_GAIN.load(Ordering::Relaxed));
+    mix_into(samples, &mut MIX_BUFFER.lock(), gain);
+}
+
+fn mix_into(samples: &mut [f32], buffer: &mut VecDeque<f32>, gain: f32) {
+    let n = samples.len().min(buffer.len());
+    for (sample, mixed) in samples.iter_mut().zip(buffer.drain(..n)) {
+        *sample = (*sample + mixed * gain).clamp(-1.0, 1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix_into() {
+        let mut buffer: VecDeque<f32> = [0.5, 0.5, 1.0].into_iter().collect();
+        let mut samples = vec![0.25, -0.25];
+        mix_into(&mut samples, &mut buffer, 0.5);
+        assert_eq!(samples, vec![0.5, 0.0]);
+        assert_eq!(buffer.len(), 1);
+        // clipped, and the captured audio alone when the buffer is empty
+        let mut samples = vec![0.75, 0.25];
+        mix_into(&mut samples, &mut buffer, 1.0);
+        assert_eq!(samples, vec![1.0, 0.25]);
+        assert!(buffer.is_empty());
+    }
+}
@@ -1,10 +1,50 @@
+pub mod announce;
 pub mod audiodevices;
+pub mod balance;
+pub mod bandwidth;
 pub mod bincommon;
+pub mod bitperfect;
+pub mod button_gestures;
+pub mod capture_errors;
 pub mod commandline;
+pub mod config_check;
+pub mod config_env;
+pub mod config_layers;
 pub mod configuration;
+pub mod device_watcher;
+pub mod dither;
+pub mod encoder_pool;
+pub mod fade;
+pub mod fallback_audio;
+pub mod flac_header;
 pub mod flacstream;
+pub mod hooks;
 pub mod local_ip_address;
+pub mod meter;
+pub mod mixer;
+pub mod mqtt;
+pub mod network_source;
+pub mod notify;
+pub mod playback_monitor;
 pub mod priority;
+pub mod recorder;
+pub mod renderer_badge;
+pub mod renderer_stats;
+pub mod replay;
+pub mod resource_monitor;
 pub mod rwstream;
+pub mod scripting;
+pub mod scrobble;
+pub mod secrets;
+pub mod selftest;
+pub mod session_stats;
+pub mod sessions;
+pub mod shutdown;
+pub mod spectrum;
+pub mod swyh_import;
+pub mod track_detector;
 pub mod traits;
 pub mod ui_logger;
+pub mod update_check;
+pub mod volume_lock;
+pub mod vu_trigger;
@@ -0,0 +1,232 @@
+//! Optional MQTT client for smart-home control (e.g. Home Assistant).
+//!
+//! Publishes to (with the default topic prefix "swyh"):
+//! - swyh/state/<renderer ip> : "started" or "ended"
+//! - swyh/clients : the comma separated ip addresses of the connected streaming clients
+//! - swyh/rms : the left and right RMS levels, once per second
+//!
+//! and subscribes to the command topics:
+//! - swyh/play : start playing to the renderer in the payload (name or ip), or the active renderers
+//! - swyh/stop : stop playing to the renderer in the payload, or to all renderers
+//! - swyh/volume/<renderer> : set the volume (0-100) of the renderer
+
+use crossbeam_channel::{Receiver, Sender};
+use dasp_sample::Sample;
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use std::{sync::OnceLock, thread, time::Duration};
+
+use super::{configuration::MqttSettings, ui_logger::ui_log};
+use crate::{
+    enums::{
+        messages::{MessageType, MqttCommand},
+        streaming::StreamingState,
+    },
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::rendercontrol::{Renderer, WavData},
+    server::streaming_server::StreamerFeedBack,
+};
+
+/// the connected MQTT client and the topic prefix
+static MQTT_CLIENT: OnceLock<(Client, String)> = OnceLock::new();
+
+/// Connect to the MQTT broker and start the thread that handles the incoming commands.
+///
+/// The commands are sent to the main loop of the app as a `MessageType::MqttMessage`.
+pub fn run_mqtt_client(settings: &MqttSettings, msg_tx: &Sender<MessageType>) {
+    let client_id = settings
+        .client_id
+        .clone()
+        .unwrap_or_else(|| "swyh-rs".to_string());
+    let mut options = MqttOptions::new(client_id, settings.broker.clone(), settings.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = &settings.username {
+        let config_id = CONFIG.read().config_id.clone().unwrap_or_default();
+        options.set_credentials(
+            username.clone(),
+            settings.password(&config_id).unwrap_or_default(),
+        );
+    }
+    let (client, mut connection) = Client::new(options, 64);
+    let prefix = settings.topic_prefix.clone();
+    if MQTT_CLIENT.set((client.clone(), prefix.clone())).is_err() {
+        return;
+    }
+    ui_log(&format!(
+        "Connecting to MQTT broker {}:{}",
+        settings.broker, settings.port
+    ));
+    let msg_tx = msg_tx.clone();
+    let _ = thread::Builder::new()
+        .name("mqtt_client".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            for notification in connection.iter() {
+                match notification {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        ui_log("Connected to the MQTT broker");
+                        // (re)subscribe to the command topics after every (re)connect
+                        for topic in ["play", "stop", "volume/+"] {
+                            let _ = client.subscribe(format!("{prefix}/{topic}"), QoS::AtLeastOnce);
+                        }
+                    }
+                    Ok(Event::Incoming(Packet::Publish(p))) => {
+                        let payload = String::from_utf8_lossy(&p.payload);
+                        if let Some(cmd) = parse_command(&prefix, &p.topic, &payload) {
+                            ui_log(&format!("MQTT command {cmd:?}"));
+                            let _ = msg_tx.send(MessageType::MqttMessage(cmd));
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(e) => {
+                        ui_log(&format!("*W*W*> MQTT connection error: {e}"));
+                        // the connection is retried on the next iteration
+                        thread::sleep(Duration::from_secs(5));
+                    }
+                }
+            }
+        });
+}
+
+/// translate a message on a command topic to a command
+#[must_use]
+pub fn parse_command(prefix: &str, topic: &str, payload: &str) -> Option<MqttCommand> {
+    let command = topic.strip_prefix(prefix)?.strip_prefix('/')?;
+    let payload = payload.trim();
+    match command {
+        "play" => Some(MqttCommand::Play(payload.to_string())),
+        "stop" => Some(MqttCommand::Stop(payload.to_string())),
+        _ => {
+            let renderer = command.strip_prefix("volume/")?;
+            let volume: u8 = payload.parse().ok()?;
+            if renderer.is_empty() || volume > 100 {
+                return None;
+            }
+            Some(MqttCommand::Volume(renderer.to_string(), volume))
+        }
+    }
+}
+
+/// check if a renderer is the one named in a command, by (part of) its name or its ip address
+#[must_use]
+pub fn renderer_matches(renderer: &Renderer, name: &str) -> bool {
+    renderer.remote_addr == name
+        || renderer
+            .dev_name
+            .to_lowercase()
+            .contains(&name.to_lowercase())
+}
+
+/// publish a message on a topic below the prefix, if the MQTT client is running
+fn publish(topic: &str, retain: bool, payload: String) {
+    if let Some((client, prefix)) = MQTT_CLIENT.get() {
+        let _ = client.try_publish(
+            format!("{prefix}/{topic}"),
+            QoS::AtMostOnce,
+            retain,
+            payload,
+        );
+    }
+}
+
+/// publish the new streaming state of a renderer and the connected clients
+pub fn publish_streaming_state(feedback: &StreamerFeedBack) {
+    if MQTT_CLIENT.get().is_none() {
+        return;
+    }
+    let state = match feedback.streaming_state {
+        StreamingState::Started => "started",
+        StreamingState::Ended => "ended",
+    };
+    publish(
+        &format!("state/{}", feedback.remote_ip),
+        true,
+        state.to_string(),
+    );
+    let clients = CLIENTS
+        .read()
+        .values()
+        .map(|c| c.remote_ip.clone())
+        .collect::<Vec<String>>()
+        .join(",");
+    publish("clients", true, clients);
+}
+
+/// the RMS levels of the captured samples, published after every second of audio
+pub struct RmsPublisher {
+    samples_per_update: usize,
+    channels: usize,
+    total_samples: usize,
+    sum_l: f64,
+    sum_r: f64,
+}
+
+impl RmsPublisher {
+    #[must_use]
+    pub fn new(wd: &WavData) -> RmsPublisher {
+        RmsPublisher {
+            samples_per_update: (wd.sample_rate.0 * u32::from(wd.channels)) as usize,
+            channels: usize::from(wd.channels.max(1)),
+            total_samples: 0,
+            sum_l: 0.0,
+            sum_r: 0.0,
+        }
+    }
+
+    /// add the captured samples, the RMS levels are published as 16 bit sample values
+    pub fn push(&mut self, samples: &[f32]) {
+        if MQTT_CLIENT.get().is_none() {
+            return;
+        }
+        self.total_samples += samples.len();
+        for (i, x) in samples.iter().enumerate() {
+            let v = f64::from(i16::from_sample(*x));
+            if i % 2 == 0 {
+                self.sum_l += v * v;
+            } else {
+                self.sum_r += v * v;
+            }
+        }
+        if self.total_samples >= self.samples_per_update {
+            let samples_per_channel = (self.total_samples / self.channels) as f64;
+            let rms_l = (self.sum_l / samples_per_channel).sqrt();
+            let rms_r = (self.sum_r / samples_per_channel).sqrt();
+            publish("rms", false, format!("{rms_l:.0},{rms_r:.0}"));
+            self.total_samples = 0;
+            self.sum_l = 0.0;
+            self.sum_r = 0.0;
+        }
+    }
+}
+
+/// publish the RMS levels of the captured samples, for the CLI that has no RMS monitor
+pub fn run_mqtt_rms_publisher(wd: WavData, rms_receiver: &Receiver<Vec<f32>>) {
+    let mut rms = RmsPublisher::new(&wd);
+    while let Ok(samples) = rms_receiver.recv() {
+        rms.push(&samples);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse_command("swyh", "swyh/play", " Kitchen\n"),
+            Some(MqttCommand::Play("Kitchen".to_string()))
+        );
+        assert_eq!(
+            parse_command("swyh", "swyh/stop", ""),
+            Some(MqttCommand::Stop(String::new()))
+        );
+        assert_eq!(
+            parse_command("swyh", "swyh/volume/Kitchen", "35"),
+            Some(MqttCommand::Volume("Kitchen".to_string(), 35))
+        );
+        assert_eq!(parse_command("swyh", "swyh/volume/Kitchen", "135"), None);
+        assert_eq!(parse_command("swyh", "swyh/volume/", "35"), None);
+        assert_eq!(parse_command("swyh", "other/play", ""), None);
+        assert_eq!(parse_command("swyh", "swyhplay", ""), None);
+    }
+}
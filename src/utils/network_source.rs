@@ -0,0 +1,214 @@
+//! Use the WAV stream of another swyh-rs instance as the audio source instead of a capture
+//! device ("chaining"), e.g. for a headless server that relays the audio captured on a desktop
+//! to many renderers.
+//!
+//! The stream has to be a 16 or 24 bit stereo WAV or RF64 stream, e.g.
+//! `http://desktop:5901/stream/swyh.wav`, its header provides the sample rate.
+
+use attohttpc::ResponseReader;
+use cpal::{SampleFormat, SampleRate};
+use std::io::{self, Read};
+
+use crate::{
+    openhome::rendercontrol::WavData, server::ssdp_announcer::http_user_agent,
+    utils::ui_logger::ui_log,
+};
+
+/// the format of a WAV stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+}
+
+/// an open network audio source, positioned at the first sample
+pub struct NetworkSource {
+    pub url: String,
+    pub format: WavFormat,
+    pub reader: ResponseReader,
+}
+
+impl NetworkSource {
+    /// the audio config data of the network source
+    #[must_use]
+    pub fn wav_data(&self) -> WavData {
+        WavData {
+            sample_format: if self.format.bits_per_sample == 16 {
+                SampleFormat::I16
+            } else {
+                SampleFormat::I32
+            },
+            sample_rate: SampleRate(self.format.sample_rate),
+            channels: self.format.channels,
+        }
+    }
+}
+
+/// the header of a WAV file or stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavHeader {
+    pub format: WavFormat,
+    /// 1 for PCM, 3 for float, the sub format of `WAVE_FORMAT_EXTENSIBLE`
+    pub format_tag: u16,
+    /// the size of the data chunk, meaningless for an "infinite" stream
+    pub data_size: u32,
+}
+
+/// the largest fmt chunk that is accepted (WAVE_FORMAT_EXTENSIBLE has 40 bytes)
+const MAX_FMT_SIZE: u32 = 64;
+
+/// read the RIFF/RF64 header up to the data chunk, positioned at the first sample
+pub fn read_wav_chunks(reader: &mut impl Read) -> io::Result<WavHeader> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff)?;
+    if !(&riff[0..4] == b"RIFF" || &riff[0..4] == b"RF64") || &riff[8..12] != b"WAVE" {
+        return Err(invalid("not a WAV stream"));
+    }
+    let mut header = None;
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        match &chunk[0..4] {
+            b"data" => {
+                return header
+                    .map(|h| WavHeader {
+                        data_size: size,
+                        ..h
+                    })
+                    .ok_or_else(|| invalid("no fmt chunk"));
+            }
+            b"fmt " => {
+                // don't allocate whatever size a broken stream claims
+                if size > MAX_FMT_SIZE {
+                    return Err(invalid("invalid fmt chunk"));
+                }
+                let mut fmt = vec![0u8; size as usize];
+                reader.read_exact(&mut fmt)?;
+                if fmt.len() < 16 {
+                    return Err(invalid("invalid fmt chunk"));
+                }
+                let mut format_tag = u16::from_le_bytes([fmt[0], fmt[1]]);
+                // WAVE_FORMAT_EXTENSIBLE: the format is in the sub format guid
+                if format_tag == 0xFFFE && fmt.len() >= 26 {
+                    format_tag = u16::from_le_bytes([fmt[24], fmt[25]]);
+                }
+                header = Some(WavHeader {
+                    format: WavFormat {
+                        channels: u16::from_le_bytes([fmt[2], fmt[3]]),
+                        sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+                        bits_per_sample: u16::from_le_bytes([fmt[14], fmt[15]]),
+                    },
+                    format_tag,
+                    data_size: 0,
+                });
+                // chunks are word aligned
+                if size & 1 == 1 {
+                    reader.read_exact(&mut [0u8; 1])?;
+                }
+            }
+            // skip the ds64 and any other chunks, chunks are word aligned
+            _ => {
+                let padded = u64::from(size) + u64::from(size & 1);
+                io::copy(&mut reader.by_ref().take(padded), &mut io::sink())?;
+            }
+        }
+    }
+}
+
+/// read the RIFF/RF64 header of a 16 or 24 bit stereo stream up to the data chunk
+pub fn read_wav_header(reader: &mut impl Read) -> io::Result<WavFormat> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    match read_wav_chunks(reader)?.format {
+        f if f.channels == 2 && (f.bits_per_sample == 16 || f.bits_per_sample == 24) => Ok(f),
+        f => Err(invalid(&format!(
+            "unsupported format: {} channels, {} bit",
+            f.channels, f.bits_per_sample
+        ))),
+    }
+}
+
+/// connect to the network source and read its WAV header
+#[must_use]
+pub fn open_network_source(url: &str) -> Option<NetworkSource> {
+    let response = match attohttpc::get(url)
+        .header("User-Agent", http_user_agent())
+        .send()
+    {
+        Ok(response) if response.is_success() => response,
+        Ok(response) => {
+            ui_log(&format!(
+                "*E*E*> Network source {url} returned {}",
+                response.status()
+            ));
+            return None;
+        }
+        Err(e) => {
+            ui_log(&format!(
+                "*E*E*> Could not connect to network source {url}: {e}"
+            ));
+            return None;
+        }
+    };
+    let (_, _, mut reader) = response.split();
+    match read_wav_header(&mut reader) {
+        Ok(format) => {
+            ui_log(&format!(
+                "Network source {url}: {} Hz, {} bit",
+                format.sample_rate, format.bits_per_sample
+            ));
+            Some(NetworkSource {
+                url: url.to_string(),
+                format,
+                reader,
+            })
+        }
+        Err(e) => {
+            ui_log(&format!("*E*E*> Network source {url}: {e}"));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_wav_header() {
+        let mut hdr = Vec::new();
+        hdr.extend_from_slice(b"RIFF");
+        hdr.extend_from_slice(&u32::MAX.to_le_bytes());
+        hdr.extend_from_slice(b"WAVE");
+        hdr.extend_from_slice(b"LIST");
+        hdr.extend_from_slice(&4u32.to_le_bytes());
+        hdr.extend_from_slice(b"INFO");
+        hdr.extend_from_slice(b"fmt ");
+        hdr.extend_from_slice(&16u32.to_le_bytes());
+        hdr.extend_from_slice(&1u16.to_le_bytes());
+        hdr.extend_from_slice(&2u16.to_le_bytes());
+        hdr.extend_from_slice(&48000u32.to_le_bytes());
+        hdr.extend_from_slice(&(48000u32 * 6).to_le_bytes());
+        hdr.extend_from_slice(&6u16.to_le_bytes());
+        hdr.extend_from_slice(&24u16.to_le_bytes());
+        hdr.extend_from_slice(b"data");
+        hdr.extend_from_slice(&u32::MAX.to_le_bytes());
+        hdr.extend_from_slice(&[1, 2, 3]);
+        let mut reader = hdr.as_slice();
+        assert_eq!(
+            read_wav_header(&mut reader).unwrap(),
+            WavFormat {
+                sample_rate: 48000,
+                channels: 2,
+                bits_per_sample: 24
+            }
+        );
+        // positioned at the first sample
+        assert_eq!(reader, &[1, 2, 3]);
+        assert!(read_wav_header(&mut &b"RIFF\0\0\0\0WAVX"[..]).is_err());
+        // a fmt chunk that claims 4 GiB
+        assert!(read_wav_header(&mut &b"RIFF\0\0\0\0WAVEfmt \xff\xff\xff\xff"[..]).is_err());
+    }
+}
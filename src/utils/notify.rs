@@ -0,0 +1,120 @@
+//! Desktop notifications for important events, so that you notice problems
+//! when the app is minimized.
+//!
+//! Each event type is enabled separately in the `notify` list of the config file,
+//! e.g. `notify = ["RendererConnected", "RendererDisconnected", "CaptureLost", "AutoResume"]`.
+//! Notifications are only shown by the GUI app, the same notification is not repeated
+//! within a few seconds.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::{
+    enums::streaming::StreamingState,
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::rendercontrol::Renderer,
+    server::streaming_server::StreamerFeedBack,
+};
+
+/// the minimum interval between identical notifications
+const REPEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// the events that can show a notification
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum NotifyEvent {
+    RendererConnected,
+    RendererDisconnected,
+    CaptureLost,
+    AutoResume,
+    UpdateAvailable,
+}
+
+/// the last notification and when it was shown
+static LAST_NOTIFICATION: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+/// show a desktop notification if notifications are enabled for the event
+pub fn notify(event: NotifyEvent, message: &str) {
+    if !CONFIG.read().notify.contains(&event) || is_repeated(message, Instant::now()) {
+        return;
+    }
+    show_notification(message);
+}
+
+/// notify that a renderer started or stopped streaming
+pub fn notify_streaming_state(feedback: &StreamerFeedBack, renderers: &[Renderer]) {
+    let name = renderers
+        .iter()
+        .find(|r| r.remote_addr == feedback.remote_ip)
+        .map_or(feedback.remote_ip.as_str(), |r| r.dev_name.as_str());
+    match feedback.streaming_state {
+        StreamingState::Started => notify(
+            NotifyEvent::RendererConnected,
+            &format!("{name} started streaming {}", feedback.streaming_format),
+        ),
+        StreamingState::Ended => {
+            // a renderer can have more than one connection
+            let still_streaming = CLIENTS
+                .read()
+                .values()
+                .any(|chanstrm| chanstrm.remote_ip == feedback.remote_ip);
+            if !still_streaming {
+                notify(
+                    NotifyEvent::RendererDisconnected,
+                    &format!("{name} stopped streaming"),
+                );
+            }
+        }
+    }
+}
+
+/// check and remember the last notification
+fn is_repeated(message: &str, now: Instant) -> bool {
+    let mut last = LAST_NOTIFICATION.lock();
+    if let Some((last_message, time)) = &*last {
+        if last_message == message && now.duration_since(*time) < REPEAT_INTERVAL {
+            return true;
+        }
+    }
+    *last = Some((message.to_string(), now));
+    false
+}
+
+#[cfg(feature = "gui")]
+fn show_notification(message: &str) {
+    use crate::utils::ui_logger::ui_log;
+    // showing a notification can block on some desktops
+    let message = message.to_string();
+    let _ = std::thread::Builder::new()
+        .name("notification".into())
+        .spawn(move || {
+            if let Err(e) = notify_rust::Notification::new()
+                .appname("swyh-rs")
+                .summary("swyh-rs")
+                .body(&message)
+                .show()
+            {
+                ui_log(&format!("*W*W*> Could not show notification: {e}"));
+            }
+        });
+}
+
+#[cfg(not(feature = "gui"))]
+fn show_notification(_message: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_repeated() {
+        let now = Instant::now();
+        assert!(!is_repeated("Kitchen stopped streaming", now));
+        assert!(is_repeated("Kitchen stopped streaming", now));
+        assert!(!is_repeated("Kitchen started streaming Flac", now));
+        assert!(!is_repeated(
+            "Kitchen started streaming Flac",
+            now + REPEAT_INTERVAL
+        ));
+    }
+}
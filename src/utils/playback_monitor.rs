@@ -0,0 +1,131 @@
+//! Play the outgoing audio on a local output device.
+//!
+//! The `wave_reader` feeds the MONITOR_BUFFER with the same samples that are sent
+//! to the streaming clients, and the output stream of the monitor device plays them
+//! with the configured delay.
+
+use cpal::{
+    traits::{DeviceTrait, StreamTrait},
+    BufferSize, FromSample, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig,
+};
+use log::warn;
+use std::sync::atomic::Ordering;
+
+use super::{audiodevices::get_output_audio_devices, ui_logger::ui_log};
+use crate::{
+    globals::statics::{CONFIG, MONITOR_BUFFER, RUN_PLAYBACK_MONITOR},
+    openhome::rendercontrol::WavData,
+};
+
+/// if the monitor lags more than this behind the capture it skips ahead to the configured delay
+const MAX_DRIFT_MSEC: usize = 500;
+
+/// Start the playback monitor on the configured monitor device, if any.
+///
+/// The monitor stream is returned to keep it alive, dropping it stops the monitor.
+#[must_use]
+pub fn start_playback_monitor(wd: &WavData) -> Option<Stream> {
+    let (device_name, delay_msec) = {
+        let conf = CONFIG.read();
+        (
+            conf.monitor_device.clone()?,
+            conf.monitor_delay_msec.unwrap_or_default(),
+        )
+    };
+    let Some(device) = get_output_audio_devices()
+        .into_iter()
+        .find(|d| d.name() == device_name)
+    else {
+        ui_log(&format!(
+            "*W*W*> Playback monitor device {device_name} not found"
+        ));
+        return None;
+    };
+    let device = device.as_ref();
+    let sample_format = match device.default_output_config() {
+        Ok(cfg) => cfg.sample_format(),
+        Err(e) => {
+            ui_log(&format!(
+                "*W*W*> {device_name} can not be used as playback monitor: {e}"
+            ));
+            return None;
+        }
+    };
+    // play with the sample rate and channels of the captured audio
+    let config = StreamConfig {
+        channels: wd.channels,
+        sample_rate: SampleRate(wd.sample_rate.0),
+        buffer_size: BufferSize::Default,
+    };
+    let frame_size = wd.sample_rate.0 as usize * usize::from(wd.channels);
+    let delay_size = frame_size * delay_msec as usize / 1000;
+    let max_size = delay_size + frame_size * MAX_DRIFT_MSEC / 1000;
+    {
+        let mut buffer = MONITOR_BUFFER.lock();
+        buffer.clear();
+        buffer.resize(delay_size, 0.0);
+    }
+    let stream = match sample_format {
+        SampleFormat::F32 => build_monitor_stream::<f32>(device, &config, delay_size, max_size),
+        SampleFormat::I16 => build_monitor_stream::<i16>(device, &config, delay_size, max_size),
+        SampleFormat::U16 => build_monitor_stream::<u16>(device, &config, delay_size, max_size),
+        format => {
+            ui_log(&format!(
+                "*W*W*> Unsupported playback monitor sample format {format:?}"
+            ));
+            return None;
+        }
+    };
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(e) => {
+            ui_log(&format!(
+                "*W*W*> Could not open playback monitor {device_name}: {e}"
+            ));
+            return None;
+        }
+    };
+    if let Err(e) = stream.play() {
+        ui_log(&format!(
+            "*W*W*> Could not start playback monitor {device_name}: {e}"
+        ));
+        return None;
+    }
+    RUN_PLAYBACK_MONITOR.store(true, Ordering::Release);
+    ui_log(&format!(
+        "Playback monitor on {device_name} with a delay of {delay_msec} msec"
+    ));
+    Some(stream)
+}
+
+fn build_monitor_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    delay_size: usize,
+    max_size: usize,
+) -> Result<Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &_| monitor_writer(data, delay_size, max_size),
+        |err| warn!("an error occurred on the playback monitor stream: {err}"),
+        None,
+    )
+}
+
+/// `monitor_writer` - plays the samples from the MONITOR_BUFFER, silence if there are none
+fn monitor_writer<T>(data: &mut [T], delay_size: usize, max_size: usize)
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let mut buffer = MONITOR_BUFFER.lock();
+    if buffer.len() > max_size {
+        let excess = buffer.len() - delay_size;
+        buffer.drain(..excess);
+    }
+    for sample in data.iter_mut() {
+        *sample = T::from_sample(buffer.pop_front().unwrap_or(0.0));
+    }
+}
@@ -42,3 +42,80 @@ pub fn raise_priority() {
 
 #[cfg(target_os = "macos")]
 pub fn raise_priority() {}
+
+/// Set the priority of the calling thread, as a nice value (-20 is highest, 19 lowest).
+#[cfg(target_os = "windows")]
+pub fn set_thread_priority(nice: i32) {
+    use windows::Win32::System::Threading::{
+        GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_ABOVE_NORMAL,
+        THREAD_PRIORITY_BELOW_NORMAL, THREAD_PRIORITY_HIGHEST, THREAD_PRIORITY_LOWEST,
+        THREAD_PRIORITY_NORMAL,
+    };
+    let priority = match nice {
+        i32::MIN..=-10 => THREAD_PRIORITY_HIGHEST,
+        -9..=-1 => THREAD_PRIORITY_ABOVE_NORMAL,
+        0 => THREAD_PRIORITY_NORMAL,
+        1..=9 => THREAD_PRIORITY_BELOW_NORMAL,
+        _ => THREAD_PRIORITY_LOWEST,
+    };
+    unsafe {
+        if let Err(e) = SetThreadPriority(GetCurrentThread(), priority) {
+            ui_log(&format!("*E*E*>Failed to set thread priority: {e}"));
+        }
+    }
+}
+
+/// Set the priority of the calling thread, as a nice value (-20 is highest, 19 lowest).
+#[cfg(target_os = "linux")]
+pub fn set_thread_priority(nice: i32) {
+    // a lower priority is always allowed, a higher one needs the same permissions
+    // as raise_priority
+    use libc::{gettid, id_t, setpriority, PRIO_PROCESS};
+    unsafe {
+        let rc = setpriority(PRIO_PROCESS, gettid() as id_t, nice);
+        if rc != 0 {
+            ui_log(&format!(
+                "*E*E*>Failed to set thread nice value {nice}, check permissions"
+            ));
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn set_thread_priority(_nice: i32) {}
+
+/// Pin the calling thread to a CPU core.
+#[cfg(target_os = "windows")]
+pub fn pin_thread_to_core(core: usize) {
+    use windows::Win32::System::Threading::{GetCurrentThread, SetThreadAffinityMask};
+    if core >= usize::BITS as usize {
+        ui_log(&format!("*E*E*>Invalid CPU core {core}"));
+        return;
+    }
+    unsafe {
+        if SetThreadAffinityMask(GetCurrentThread(), 1 << core) == 0 {
+            ui_log(&format!("*E*E*>Failed to pin thread to CPU core {core}"));
+        }
+    }
+}
+
+/// Pin the calling thread to a CPU core.
+#[cfg(target_os = "linux")]
+pub fn pin_thread_to_core(core: usize) {
+    use libc::{cpu_set_t, sched_setaffinity, CPU_SET, CPU_SETSIZE, CPU_ZERO};
+    if core >= CPU_SETSIZE as usize {
+        ui_log(&format!("*E*E*>Invalid CPU core {core}"));
+        return;
+    }
+    unsafe {
+        let mut set: cpu_set_t = std::mem::zeroed();
+        CPU_ZERO(&mut set);
+        CPU_SET(core, &mut set);
+        if sched_setaffinity(0, std::mem::size_of::<cpu_set_t>(), &set) != 0 {
+            ui_log(&format!("*E*E*>Failed to pin thread to CPU core {core}"));
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn pin_thread_to_core(_core: usize) {}
@@ -0,0 +1,279 @@
+//! Record the captured audio to disk.
+//!
+//! Every recording session gets its own directory in the configured recording directory.
+//! If track boundary detection is enabled, a new file is started at each detected track gap,
+//! with sequential numbering and a sidecar CUE file listing the tracks.
+//! A WAV file that reaches the 4 GB limit of the WAV format is continued in a new file.
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use dasp_sample::Sample;
+use flac_bound::{FlacEncoder, WriteWrapper};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, LazyLock},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{track_detector::TrackChange, ui_logger::ui_log};
+use crate::{
+    enums::streaming::{HeaderSize, StreamingFormat},
+    globals::statics::{CONFIG, RUN_RECORDER},
+    openhome::rendercontrol::WavData,
+    utils::rwstream::{create_wav_hdr, MAX_RIFF_CHUNKSIZE},
+};
+
+/// the largest data chunk in a WAV file
+const MAX_WAV_DATA_SIZE: u64 = (MAX_RIFF_CHUNKSIZE - 36) as u64;
+
+/// what the `wave_reader` sends to the recorder
+enum RecordMessage {
+    Samples(Vec<f32>),
+    NewTrack(TrackChange),
+}
+
+/// the channel from the audio capture to the recorder thread
+static RECORD_CHANNEL: LazyLock<(Sender<RecordMessage>, Receiver<RecordMessage>)> =
+    LazyLock::new(unbounded);
+
+/// send the captured samples to the recorder, a detected track change starts a new file
+pub fn record_samples(samples: &[f32], change: Option<TrackChange>) {
+    let tx = &RECORD_CHANNEL.0;
+    if let Some(change) = change {
+        let _ = tx.send(RecordMessage::NewTrack(change));
+    }
+    let _ = tx.send(RecordMessage::Samples(samples.to_vec()));
+}
+
+/// Start recording if a recording directory is configured.
+pub fn start_recorder(wd: &WavData) {
+    let (record_dir, format, bits_per_sample) = {
+        let conf = CONFIG.read();
+        let Some(dir) = conf.record_dir.clone() else {
+            return;
+        };
+        let format = if conf.record_format == Some(StreamingFormat::Flac) {
+            StreamingFormat::Flac
+        } else {
+            StreamingFormat::Wav
+        };
+        (dir, format, conf.bits_per_sample.unwrap_or(16))
+    };
+    let session = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let session_dir = Path::new(&record_dir).join(format!("swyh-rs-{session}"));
+    if let Err(e) = fs::create_dir_all(&session_dir) {
+        ui_log(&format!(
+            "*E*E*> Could not create recording directory {}: {e}",
+            session_dir.display()
+        ));
+        return;
+    }
+    ui_log(&format!(
+        "Recording {format} {bits_per_sample} bit to {}",
+        session_dir.display()
+    ));
+    let recording = Recording {
+        dir: session_dir,
+        format,
+        sample_rate: wd.sample_rate.0,
+        channels: wd.channels,
+        bits_per_sample,
+        tracks: Vec::new(),
+        overflow: None,
+    };
+    RUN_RECORDER.store(true, Ordering::Release);
+    let _ = thread::Builder::new()
+        .name("recorder".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || recording.run(&RECORD_CHANNEL.1));
+}
+
+/// a recording session
+struct Recording {
+    dir: PathBuf,
+    format: StreamingFormat,
+    sample_rate: u32,
+    channels: u16,
+    bits_per_sample: u16,
+    tracks: Vec<String>,
+    /// the samples that didn't fit in a full WAV file
+    overflow: Option<Vec<f32>>,
+}
+
+impl Recording {
+    /// record the tracks, each one to a new file
+    fn run(mut self, rx: &Receiver<RecordMessage>) {
+        loop {
+            let filename = format!(
+                "track-{:03}.{}",
+                self.tracks.len() + 1,
+                if self.format == StreamingFormat::Flac {
+                    "flac"
+                } else {
+                    "wav"
+                }
+            );
+            let path = self.dir.join(&filename);
+            let file = match File::create(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    ui_log(&format!(
+                        "*E*E*> Could not create {}: {e}, recording stopped",
+                        path.display()
+                    ));
+                    RUN_RECORDER.store(false, Ordering::Release);
+                    return;
+                }
+            };
+            ui_log(&format!("Recording to {}", path.display()));
+            self.tracks.push(filename);
+            self.write_cue_sheet();
+            let result = if self.format == StreamingFormat::Flac {
+                self.record_flac(file, rx)
+            } else {
+                self.record_wav(file, rx)
+            };
+            match result {
+                Ok(true) => (),
+                Ok(false) => return,
+                Err(e) => {
+                    ui_log(&format!(
+                        "*E*E*> Error writing {}: {e}, recording stopped",
+                        path.display()
+                    ));
+                    RUN_RECORDER.store(false, Ordering::Release);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// record a WAV file until the next track or until it is full, the header sizes are updated at the end
+    /// returns false if there's nothing more to record
+    fn record_wav(&mut self, file: File, rx: &Receiver<RecordMessage>) -> std::io::Result<bool> {
+        let mut w = BufWriter::new(file);
+        self.update_wav_header(&mut w, 0)?;
+        let mut data_size = 0u64;
+        if let Some(samples) = self.overflow.take() {
+            data_size += self.write_wav_samples(&mut w, &samples)?;
+        }
+        let mut next_track = false;
+        let mut n = 0u32;
+        while let Ok(msg) = rx.recv() {
+            match msg {
+                RecordMessage::Samples(samples) => {
+                    // keep the header up to date, in case swyh-rs is stopped while recording
+                    n += 1;
+                    if n % 500 == 0 {
+                        self.update_wav_header(&mut w, data_size)?;
+                    }
+                    // continue in a new file at the 4 GB maximum of the WAV format
+                    let size = samples.len() as u64 * u64::from(self.bits_per_sample / 8);
+                    if data_size + size > MAX_WAV_DATA_SIZE {
+                        ui_log("The WAV file is full, the recording continues in a new file");
+                        self.overflow = Some(samples);
+                        next_track = true;
+                        break;
+                    }
+                    data_size += self.write_wav_samples(&mut w, &samples)?;
+                }
+                RecordMessage::NewTrack(_) => {
+                    next_track = true;
+                    break;
+                }
+            }
+        }
+        self.update_wav_header(&mut w, data_size)?;
+        Ok(next_track)
+    }
+
+    /// write the samples as 16 or 24 bit PCM, returns the number of bytes written
+    fn write_wav_samples(&self, w: &mut BufWriter<File>, samples: &[f32]) -> std::io::Result<u64> {
+        for s in samples {
+            if self.bits_per_sample == 24 {
+                let v = s.to_sample::<i32>() >> 8;
+                w.write_all(&v.to_le_bytes()[..3])?;
+            } else {
+                w.write_all(&s.to_sample::<i16>().to_le_bytes())?;
+            }
+        }
+        Ok(samples.len() as u64 * u64::from(self.bits_per_sample / 8))
+    }
+
+    /// rewrite the WAV header with the correct sizes
+    fn update_wav_header(&self, w: &mut BufWriter<File>, data_size: u64) -> std::io::Result<()> {
+        w.flush()?;
+        let file = w.get_mut();
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&create_wav_hdr(
+            self.sample_rate,
+            self.bits_per_sample,
+            self.channels,
+            HeaderSize::Bytes(data_size),
+        ))?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    /// record a FLAC file until the next track
+    /// returns false if there's nothing more to record
+    fn record_flac(&self, file: File, rx: &Receiver<RecordMessage>) -> std::io::Result<bool> {
+        let mut w = BufWriter::new(file);
+        let mut next_track = false;
+        {
+            let mut outw = WriteWrapper(&mut w);
+            let Some(enc) = FlacEncoder::new() else {
+                return Err(std::io::Error::other("could not create FLAC encoder"));
+            };
+            let mut enc = enc
+                .channels(u32::from(self.channels))
+                .bits_per_sample(u32::from(self.bits_per_sample))
+                .sample_rate(self.sample_rate)
+                .compression_level(5)
+                .init_write(&mut outw)
+                .map_err(|e| std::io::Error::other(format!("{e:?}")))?;
+            let shift = if self.bits_per_sample == 24 { 8 } else { 16 };
+            while let Ok(msg) = rx.recv() {
+                match msg {
+                    RecordMessage::Samples(samples) => {
+                        let samples = samples
+                            .iter()
+                            .map(|s| s.to_sample::<i32>() >> shift)
+                            .collect::<Vec<i32>>();
+                        let frames = (samples.len() / usize::from(self.channels)) as u32;
+                        if enc.process_interleaved(samples.as_slice(), frames).is_err() {
+                            return Err(std::io::Error::other("FLAC encoding failed"));
+                        }
+                    }
+                    RecordMessage::NewTrack(_) => {
+                        next_track = true;
+                        break;
+                    }
+                }
+            }
+            let _ = enc.finish();
+        }
+        w.flush()?;
+        Ok(next_track)
+    }
+
+    /// (re)write the CUE sheet with all tracks recorded so far
+    fn write_cue_sheet(&self) {
+        let mut cue = String::from("REM COMMENT \"recorded by swyh-rs\"\n");
+        for (i, track) in self.tracks.iter().enumerate() {
+            cue.push_str(&format!(
+                "FILE \"{track}\" WAVE\n  TRACK {:02} AUDIO\n    INDEX 01 00:00:00\n",
+                i + 1
+            ));
+        }
+        let path = self.dir.join("tracks.cue");
+        if let Err(e) = fs::write(&path, cue) {
+            ui_log(&format!("*W*W*> Could not write {}: {e}", path.display()));
+        }
+    }
+}
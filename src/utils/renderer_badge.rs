@@ -0,0 +1,206 @@
+//! The live state of a renderer, shown in the badges next to its button in the GUI: the
+//! streaming format and bit depth, the connection state and the elapsed time.
+//!
+//! The state follows the streaming feedback of the renderer: it is buffering from the
+//! push of its button until it pulls the stream, streaming until the stream ends, and
+//! offline when starting to play failed or it doesn't respond to the SSDP refresh. The
+//! elapsed time is the playing time reported by the renderer if it is polled, and else
+//! the time since it pulled the stream.
+//!
+//! The silence badge counts the periods in which no audio was captured and silence was sent
+//! to the renderer to keep the stream alive.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{enums::streaming::StreamingFormat, utils::session_stats::SilenceCount};
+
+/// how often the elapsed time badges are updated
+pub const BADGE_REFRESH: Duration = Duration::from_secs(1);
+
+/// the connection state of a renderer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    Idle,
+    Buffering,
+    Streaming,
+    Offline,
+}
+
+impl LinkState {
+    /// the color of the state badge (RGB)
+    #[must_use]
+    pub fn color(self) -> u32 {
+        match self {
+            LinkState::Idle => 0x00a0_a0a0,
+            LinkState::Buffering => 0x00e0_a000,
+            LinkState::Streaming => 0x0000_c000,
+            LinkState::Offline => 0x00d0_0000,
+        }
+    }
+}
+
+impl fmt::Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkState::Idle => write!(f, "Idle"),
+            LinkState::Buffering => write!(f, "Buffering"),
+            LinkState::Streaming => write!(f, "Streaming"),
+            LinkState::Offline => write!(f, "Offline"),
+        }
+    }
+}
+
+/// the live state of a renderer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RendererBadge {
+    pub format: StreamingFormat,
+    pub bits_per_sample: u16,
+    /// when the renderer pulled the stream, None if it isn't streaming
+    streaming_since: Option<Instant>,
+    /// the playing time reported by the renderer
+    position: Option<u64>,
+    offline: bool,
+    /// the silence periods of the current stream
+    silence: SilenceCount,
+}
+
+impl RendererBadge {
+    #[must_use]
+    pub fn new(format: StreamingFormat, bits_per_sample: u16) -> RendererBadge {
+        RendererBadge {
+            format,
+            bits_per_sample,
+            streaming_since: None,
+            position: None,
+            offline: false,
+            silence: SilenceCount::default(),
+        }
+    }
+
+    /// the renderer pulled the stream in this format
+    pub fn started(&mut self, format: StreamingFormat, bits_per_sample: u16, now: Instant) {
+        self.format = format;
+        self.bits_per_sample = bits_per_sample;
+        self.streaming_since = Some(now);
+        self.offline = false;
+        self.silence = SilenceCount::default();
+    }
+
+    /// the stream of the renderer ended
+    pub fn ended(&mut self) {
+        self.streaming_since = None;
+        self.position = None;
+    }
+
+    /// the renderer failed to start playing or stopped responding, or responds again
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// the playing time reported by the renderer, None if it stopped or doesn't report it
+    pub fn set_position(&mut self, position: Option<u64>) {
+        self.position = position;
+    }
+
+    /// the silence periods of the stream, None keeps the count of the stream that ended
+    pub fn set_silence(&mut self, silence: Option<SilenceCount>) {
+        if let Some(silence) = silence {
+            self.silence = silence;
+        }
+    }
+
+    /// the silence periods of the current or last stream
+    #[must_use]
+    pub fn silence(&self) -> SilenceCount {
+        self.silence
+    }
+
+    /// the connection state, `playing` if its button is on
+    #[must_use]
+    pub fn state(&self, playing: bool) -> LinkState {
+        if self.offline {
+            LinkState::Offline
+        } else if self.streaming_since.is_some() {
+            LinkState::Streaming
+        } else if playing {
+            LinkState::Buffering
+        } else {
+            LinkState::Idle
+        }
+    }
+
+    /// the elapsed time in seconds
+    #[must_use]
+    pub fn elapsed(&self, now: Instant) -> Option<u64> {
+        self.position.or_else(|| {
+            self.streaming_since
+                .map(|since| now.saturating_duration_since(since).as_secs())
+        })
+    }
+
+    /// the label of the format badge, e.g. "Flac 24"
+    #[must_use]
+    pub fn format_label(&self) -> String {
+        format!("{} {}", self.format, self.bits_per_sample)
+    }
+
+    /// the label of the elapsed time badge, e.g. "0:03:25", empty when not streaming
+    #[must_use]
+    pub fn elapsed_label(&self, now: Instant) -> String {
+        self.elapsed(now).map_or_else(String::new, |secs| {
+            format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+        })
+    }
+
+    /// the label of the silence badge, e.g. "3 sil", empty without silence periods
+    #[must_use]
+    pub fn silence_label(&self) -> String {
+        if self.silence.periods == 0 {
+            String::new()
+        } else {
+            format!("{} sil", self.silence.periods)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renderer_badge() {
+        let start = Instant::now();
+        let mut badge = RendererBadge::new(StreamingFormat::Flac, 16);
+        assert_eq!(badge.state(false), LinkState::Idle);
+        assert_eq!(badge.state(true), LinkState::Buffering);
+        assert_eq!(badge.elapsed_label(start), "");
+        badge.started(StreamingFormat::Wav, 24, start);
+        assert_eq!(badge.state(true), LinkState::Streaming);
+        assert_eq!(badge.format_label(), "Wav 24");
+        let later = start + Duration::from_secs(3725);
+        assert_eq!(badge.elapsed_label(later), "1:02:05");
+        // the position reported by the renderer comes first
+        badge.set_position(Some(65));
+        assert_eq!(badge.elapsed_label(later), "0:01:05");
+        assert_eq!(badge.silence_label(), "");
+        badge.set_silence(Some(SilenceCount {
+            periods: 3,
+            msec: 1500,
+        }));
+        assert_eq!(badge.silence_label(), "3 sil");
+        badge.ended();
+        // the count of the last stream stays until the next stream starts
+        badge.set_silence(None);
+        assert_eq!(badge.silence_label(), "3 sil");
+        assert_eq!(badge.state(true), LinkState::Buffering);
+        assert_eq!(badge.elapsed(later), None);
+        badge.set_offline(true);
+        assert_eq!(badge.state(true), LinkState::Offline);
+        badge.started(StreamingFormat::Flac, 16, later);
+        assert_eq!(badge.state(true), LinkState::Streaming);
+        assert_eq!(badge.silence_label(), "");
+    }
+}
@@ -0,0 +1,201 @@
+//! Cumulative statistics per renderer, kept across sessions in `renderer_stats.json` in
+//! the config directory: the total time streamed, the number of streaming connections,
+//! the number of reconnects after the renderer dropped the stream, the periods of silence
+//! injected because no audio was captured, and the last error.
+//!
+//! They help to spot the renderer that is the chronic troublemaker on the network, and
+//! are shown with a right click on the renderer button in the GUI.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::PathBuf,
+    sync::LazyLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    enums::streaming::StreamingState, globals::statics::CONFIG, openhome::rendercontrol::Renderer,
+    server::streaming_server::StreamerFeedBack, utils::ui_logger::ui_log,
+};
+
+/// the renderer statistics file name in the config directory
+pub const STATS_FILE: &str = "renderer_stats.json";
+
+/// the statistics by renderer name, read from the file when first used
+static STATS: LazyLock<Mutex<BTreeMap<String, RendererStats>>> =
+    LazyLock::new(|| Mutex::new(read_stats()));
+
+/// the cumulative statistics of a renderer
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RendererStats {
+    pub streamed_secs: u64,
+    pub connections: u32,
+    pub reconnects: u32,
+    pub last_error: Option<String>,
+    /// the time of the last error in seconds since the unix epoch
+    pub last_error_time: Option<u64>,
+    #[serde(default)]
+    pub silence_periods: u64,
+    #[serde(default)]
+    pub silence_msec: u64,
+}
+
+impl fmt::Display for RendererStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Streamed: {:.1} hours",
+            self.streamed_secs as f64 / 3600.0
+        )?;
+        writeln!(f, "Streaming connections: {}", self.connections)?;
+        writeln!(f, "Reconnects: {}", self.reconnects)?;
+        writeln!(
+            f,
+            "Silence periods (no audio captured): {} ({:.1} s)",
+            self.silence_periods,
+            self.silence_msec as f64 / 1000.0
+        )?;
+        match (&self.last_error, self.last_error_time) {
+            (Some(error), Some(time)) => write!(
+                f,
+                "Last error: {error} ({} ago)",
+                ago(epoch_secs().saturating_sub(time))
+            ),
+            (Some(error), None) => write!(f, "Last error: {error}"),
+            _ => write!(f, "Last error: none"),
+        }
+    }
+}
+
+/// a time span as seconds, minutes, hours or days
+fn ago(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs} s"),
+        60..3600 => format!("{} min", secs / 60),
+        3600..86400 => format!("{} h", secs / 3600),
+        _ => format!("{} days", secs / 86400),
+    }
+}
+
+fn epoch_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn stats_path() -> PathBuf {
+    CONFIG.read().config_dir().join(STATS_FILE)
+}
+
+fn read_stats() -> BTreeMap<String, RendererStats> {
+    fs::read_to_string(stats_path())
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// change the statistics of a renderer and write the file
+fn update_stats(name: &str, change: impl FnOnce(&mut RendererStats)) {
+    let mut stats = STATS.lock();
+    change(stats.entry(name.to_string()).or_default());
+    let json = serde_json::to_string_pretty(&*stats).unwrap_or_default();
+    if let Err(e) = fs::write(stats_path(), json) {
+        ui_log(&format!(
+            "*W*W*> Could not write the renderer statistics: {e}"
+        ));
+    }
+}
+
+/// the renderer streaming to an ip address, if there's only one at that address
+fn feedback_renderer<'a>(
+    renderers: &'a [Renderer],
+    feedback: &StreamerFeedBack,
+) -> Option<&'a Renderer> {
+    match renderers
+        .iter()
+        .filter(|r| r.remote_addr == feedback.remote_ip)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [renderer] => Some(renderer),
+        _ => None,
+    }
+}
+
+/// count a new streaming connection, or the time streamed when it ends
+pub fn record_streaming(renderers: &[Renderer], feedback: &StreamerFeedBack) {
+    let Some(renderer) = feedback_renderer(renderers, feedback) else {
+        return;
+    };
+    match feedback.streaming_state {
+        StreamingState::Started => update_stats(&renderer.dev_name, |s| s.connections += 1),
+        StreamingState::Ended => update_stats(&renderer.dev_name, |s| {
+            s.streamed_secs += feedback.streaming_time.as_secs();
+            s.silence_periods += feedback.silence.periods;
+            s.silence_msec += feedback.silence.msec;
+        }),
+    }
+}
+
+/// count a reconnect after the renderer dropped the stream, it's also the last error
+pub fn record_reconnect(renderer: &Renderer, streaming_time: Duration) {
+    update_stats(&renderer.dev_name, |s| {
+        s.reconnects += 1;
+        s.last_error = Some(format!(
+            "dropped the stream after {}",
+            ago(streaming_time.as_secs())
+        ));
+        s.last_error_time = Some(epoch_secs());
+    });
+}
+
+/// remember the error if starting to play failed
+pub fn record_play_result(renderer: &Renderer, result: Result<(), &str>) {
+    if let Err(e) = result {
+        update_stats(&renderer.dev_name, |s| {
+            s.last_error = Some(format!("play failed: {e}"));
+            s.last_error_time = Some(epoch_secs());
+        });
+    }
+}
+
+/// the statistics of a renderer
+#[must_use]
+pub fn renderer_stats(name: &str) -> RendererStats {
+    STATS.lock().get(name).cloned().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renderer_stats() {
+        assert_eq!(ago(59), "59 s");
+        assert_eq!(ago(7200), "2 h");
+        assert_eq!(ago(3 * 86400), "3 days");
+        let stats = RendererStats {
+            streamed_secs: 5400,
+            connections: 12,
+            reconnects: 3,
+            last_error: Some("play failed: no response".to_string()),
+            last_error_time: None,
+            silence_periods: 4,
+            silence_msec: 2500,
+        };
+        assert_eq!(
+            stats.to_string(),
+            "Streamed: 1.5 hours\nStreaming connections: 12\nReconnects: 3\n\
+            Silence periods (no audio captured): 4 (2.5 s)\n\
+            Last error: play failed: no response"
+        );
+        let json = serde_json::to_string(&BTreeMap::from([("Kitchen", stats.clone())])).unwrap();
+        let read: BTreeMap<String, RendererStats> = serde_json::from_str(&json).unwrap();
+        assert_eq!(read["Kitchen"], stats);
+    }
+}
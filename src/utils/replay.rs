@@ -0,0 +1,174 @@
+//! The replay buffer: the last `replay_secs` seconds of the captured audio, after the
+//! balance, trim and DSP chain, served as a FLAC file on `/replay.flac`.
+//!
+//! A listener who just connected can pull the immediate past (e.g. the start of a song),
+//! and when a glitch is reported the replay shows exactly what was streamed. The FLAC
+//! file has the total number of samples in its STREAMINFO header, so it can be played
+//! and saved like any other FLAC file.
+//!
+//! The buffer is limited to `MAX_REPLAY_SECS`, and the encoded file is cached for
+//! `CACHE_TIME`, so that repeated requests don't encode the whole buffer every time.
+
+use dasp_sample::Sample;
+use flac_bound::{FlacEncoder, WriteWrapper};
+use log::error;
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{globals::statics::CONFIG, utils::ui_logger::ui_log};
+
+/// the rolling buffer with the most recently captured samples
+static REPLAY: LazyLock<Mutex<ReplayBuffer>> =
+    LazyLock::new(|| Mutex::new(ReplayBuffer::default()));
+/// true if the replay buffer is enabled
+static REPLAY_ACTIVE: AtomicBool = AtomicBool::new(false);
+/// the most recently encoded replay buffer, with the time and bit depth of the encoding
+static REPLAY_FLAC: Mutex<Option<(Instant, u16, Vec<u8>)>> = Mutex::new(None);
+
+/// the maximum length of the replay buffer
+const MAX_REPLAY_SECS: u32 = 60;
+/// how long an encoded replay buffer is served again
+const CACHE_TIME: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+struct ReplayBuffer {
+    samples: VecDeque<f32>,
+    size: usize,
+    sample_rate: u32,
+    channels: u16,
+}
+
+/// (re)start the replay buffer for the capture, if `replay_secs` is set
+pub fn start_replay(sample_rate: u32, channels: u16) {
+    let mut secs = CONFIG.read().replay_secs.unwrap_or_default();
+    if secs > MAX_REPLAY_SECS {
+        ui_log(&format!(
+            "*W*W*> replay_secs {secs} is too large, using {MAX_REPLAY_SECS} seconds"
+        ));
+        secs = MAX_REPLAY_SECS;
+    }
+    *REPLAY_FLAC.lock() = None;
+    let mut replay = REPLAY.lock();
+    *replay = ReplayBuffer {
+        samples: VecDeque::new(),
+        size: sample_rate as usize * secs as usize * usize::from(channels),
+        sample_rate,
+        channels,
+    };
+    REPLAY_ACTIVE.store(replay.size > 0, Ordering::Relaxed);
+    if replay.size > 0 {
+        ui_log(&format!(
+            "Keeping the last {secs} seconds of audio for /replay.flac"
+        ));
+    }
+}
+
+/// keep the captured samples in the replay buffer
+pub fn replay_samples(samples: &[f32]) {
+    if !REPLAY_ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut replay = REPLAY.lock();
+    replay.samples.extend(samples.iter());
+    if replay.samples.len() > replay.size {
+        let excess = replay.samples.len() - replay.size;
+        replay.samples.drain(..excess);
+    }
+}
+
+/// true if the url is the replay url
+#[must_use]
+pub fn is_replay_url(url: &str) -> bool {
+    url.split('?')
+        .next()
+        .is_some_and(|path| path.eq_ignore_ascii_case("/replay.flac"))
+}
+
+/// the replay buffer as a FLAC file with the configured bit depth, None if it's empty
+///
+/// a recent encoding is served from the cache, and only one request encodes at a time
+#[must_use]
+pub fn replay_flac() -> Option<Vec<u8>> {
+    let bits_per_sample = CONFIG.read().bits_per_sample.unwrap_or(16);
+    let mut cache = REPLAY_FLAC.lock();
+    if let Some((encoded, bits, flac)) = cache.as_ref() {
+        if *bits == bits_per_sample && encoded.elapsed() < CACHE_TIME {
+            return Some(flac.clone());
+        }
+    }
+    // copy the samples, so that the capture isn't blocked while encoding
+    let (samples, sample_rate, channels) = {
+        let replay = REPLAY.lock();
+        let samples: Vec<f32> = replay.samples.iter().copied().collect();
+        (samples, replay.sample_rate, replay.channels)
+    };
+    if samples.is_empty() {
+        return None;
+    }
+    let flac = encode_flac(&samples, sample_rate, channels, bits_per_sample);
+    *cache = Some((Instant::now(), bits_per_sample, flac.clone()));
+    Some(flac)
+}
+
+/// encode the interleaved samples as a FLAC file with 16 or 24 bit samples
+fn encode_flac(samples: &[f32], sample_rate: u32, channels: u16, bits_per_sample: u16) -> Vec<u8> {
+    let shift = if bits_per_sample == 24 { 8u8 } else { 16u8 };
+    let samples: Vec<i32> = samples
+        .iter()
+        .map(|s| s.to_sample::<i32>() >> shift)
+        .collect();
+    let frames = samples.len() / usize::from(channels.max(1));
+    let mut flac: Vec<u8> = Vec::new();
+    {
+        let mut outw = WriteWrapper(&mut flac);
+        let mut enc = FlacEncoder::new()
+            .unwrap()
+            .channels(u32::from(channels))
+            .bits_per_sample(u32::from(bits_per_sample))
+            .sample_rate(sample_rate)
+            .compression_level(5)
+            .total_samples_estimate(frames as u64)
+            .init_write(&mut outw)
+            .unwrap();
+        if enc
+            .process_interleaved(&samples[..frames * usize::from(channels)], frames as u32)
+            .is_err()
+        {
+            error!("Encoding the replay buffer failed");
+        }
+        let _ = enc.finish();
+    }
+    flac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_url() {
+        assert!(is_replay_url("/replay.flac"));
+        assert!(is_replay_url("/Replay.FLAC?t=1"));
+        assert!(!is_replay_url("/stream/swyh.flac"));
+    }
+
+    #[test]
+    fn test_encode_flac() {
+        let samples: Vec<f32> = (0..2 * 4410)
+            .map(|n| (n as f32 / 100.0).sin() / 2.0)
+            .collect();
+        let flac = encode_flac(&samples, 44100, 2, 24);
+        assert_eq!(&flac[0..4], b"fLaC");
+        // the bits per sample and the total samples of the STREAMINFO header
+        let info = u64::from_be_bytes(flac[18..26].try_into().unwrap());
+        assert_eq!((info >> 36) & 0x1f, 23);
+        assert_eq!(info & 0xf_ffff_ffff, 4410);
+    }
+}
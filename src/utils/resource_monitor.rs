@@ -0,0 +1,362 @@
+//! Resource usage self-monitoring, and the FLAC compression throttle.
+//!
+//! The CPU usage of swyh-rs, the CPU usage of each FLAC encoder thread and the memory
+//! usage are measured periodically, shown in the GUI and served as JSON on
+//! /stats/resources. With the throttle enabled, new FLAC streams get a lower compression
+//! level while the CPU is saturated, so that the encoders on weak hardware (e.g. a
+//! Raspberry Pi) don't cause capture glitches, and the configured level again when the
+//! CPU has recovered. The level of a running FLAC stream can't be changed.
+
+use parking_lot::{Mutex, RwLock};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering::Relaxed},
+        LazyLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{globals::statics::CONFIG, utils::ui_logger::ui_log};
+
+/// the highest FLAC compression level
+pub const MAX_COMPRESSION_LEVEL: u32 = 8;
+/// the CPU usage in % that is considered saturated if not configured
+const DEFAULT_CPU_LIMIT: u8 = 90;
+/// the compression level goes up again below this part of the limit
+const RECOVERED: f32 = 0.75;
+/// the throttled compression level, u32::MAX if not throttled
+static THROTTLED_LEVEL: AtomicU32 = AtomicU32::new(u32::MAX);
+/// the CPU time of the encoder threads, by thread name
+static THREAD_CPU: LazyLock<Mutex<HashMap<String, Duration>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// the last measurement
+static RESOURCE_USAGE: RwLock<Option<ResourceUsage>> = RwLock::new(None);
+
+/// the CPU usage of a FLAC encoder thread in % of one core
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EncoderUsage {
+    pub thread: String,
+    pub cpu_percent: f32,
+}
+
+/// the resource usage of swyh-rs during the last interval
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ResourceUsage {
+    /// the CPU usage of the process in % of all cores
+    pub cpu_percent: f32,
+    /// the CPU usage of the FLAC encoder threads
+    pub encoders: Vec<EncoderUsage>,
+    /// the resident memory, None if it can't be measured on this OS
+    pub memory_bytes: Option<u64>,
+    /// the compression level of new FLAC streams
+    pub compression_level: u32,
+}
+
+impl ResourceUsage {
+    /// a short summary for the GUI
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let mut summary = format!("CPU {:.1}%", self.cpu_percent);
+        let busy: Vec<String> = self
+            .encoders
+            .iter()
+            .filter(|e| e.cpu_percent > 0.0)
+            .map(|e| format!("{:.1}%", e.cpu_percent))
+            .collect();
+        if !busy.is_empty() {
+            summary.push_str(&format!(" (FLAC {})", busy.join(", ")));
+        }
+        if let Some(bytes) = self.memory_bytes {
+            summary.push_str(&format!(", memory {:.1} MB", bytes as f64 / 1_000_000.0));
+        }
+        summary
+    }
+}
+
+/// the last measured resource usage, None if not monitored
+#[must_use]
+pub fn resource_usage() -> Option<ResourceUsage> {
+    RESOURCE_USAGE.read().clone()
+}
+
+/// the last measured resource usage as JSON
+#[must_use]
+pub fn resources_json() -> String {
+    serde_json::to_string_pretty(&*RESOURCE_USAGE.read()).unwrap_or_default()
+}
+
+/// the compression level for a new FLAC stream
+#[must_use]
+pub fn flac_compression_level() -> u32 {
+    let configured = CONFIG.read().flac_compression_level.unwrap_or_default();
+    configured
+        .min(MAX_COMPRESSION_LEVEL)
+        .min(THROTTLED_LEVEL.load(Relaxed))
+}
+
+/// remember the CPU time of the calling encoder thread, for the monitor
+pub fn record_thread_cpu() {
+    let Some(cpu) = thread_cpu_time() else {
+        return;
+    };
+    if let Some(name) = thread::current().name() {
+        THREAD_CPU.lock().insert(name.to_string(), cpu);
+    }
+}
+
+/// the CPU usage in % of `cores` cores
+fn cpu_percent(cpu: Duration, elapsed: Duration, cores: usize) -> f32 {
+    if elapsed.is_zero() || cores == 0 {
+        return 0.0;
+    }
+    (cpu.as_secs_f64() / elapsed.as_secs_f64() / cores as f64 * 100.0) as f32
+}
+
+/// the next compression level: one lower while the CPU is saturated, one higher (up to
+/// the configured level) when the CPU has recovered
+fn next_level(level: u32, configured: u32, cpu_percent: f32, limit: f32) -> u32 {
+    if cpu_percent >= limit {
+        level.saturating_sub(1)
+    } else if cpu_percent < limit * RECOVERED {
+        (level + 1).min(configured)
+    } else {
+        level.min(configured)
+    }
+}
+
+/// Start measuring the resource usage every `interval_secs` seconds.
+pub fn run_resource_monitor(interval_secs: u32) {
+    let _ = thread::Builder::new()
+        .name("resource_monitor".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let interval = Duration::from_secs(u64::from(interval_secs));
+            let cores = thread::available_parallelism().map_or(1, usize::from);
+            ui_log(&format!(
+                "Monitoring the resource usage every {interval_secs} seconds on {cores} CPU core(s)"
+            ));
+            let mut last = Instant::now();
+            let mut last_cpu = process_cpu_time().unwrap_or_default();
+            let mut last_threads: HashMap<String, Duration> = HashMap::new();
+            loop {
+                thread::sleep(interval);
+                let elapsed = last.elapsed();
+                last = Instant::now();
+                let cpu = process_cpu_time().unwrap_or_default();
+                let cpu_percent_all = cpu_percent(cpu.saturating_sub(last_cpu), elapsed, cores);
+                last_cpu = cpu;
+                let threads = THREAD_CPU.lock().clone();
+                let mut encoders: Vec<EncoderUsage> = threads
+                    .iter()
+                    .map(|(name, cpu)| {
+                        let prev = last_threads.get(name).copied().unwrap_or_default();
+                        EncoderUsage {
+                            thread: name.clone(),
+                            cpu_percent: cpu_percent(cpu.saturating_sub(prev), elapsed, 1),
+                        }
+                    })
+                    .collect();
+                encoders.sort_by(|a, b| a.thread.cmp(&b.thread));
+                last_threads = threads;
+                let compression_level = throttle(cpu_percent_all, &encoders);
+                *RESOURCE_USAGE.write() = Some(ResourceUsage {
+                    cpu_percent: cpu_percent_all,
+                    encoders,
+                    memory_bytes: resident_memory(),
+                    compression_level,
+                });
+            }
+        })
+        .unwrap();
+}
+
+/// lower or restore the compression level of new FLAC streams if the throttle is enabled
+fn throttle(cpu_percent_all: f32, encoders: &[EncoderUsage]) -> u32 {
+    let (enabled, limit, configured) = {
+        let conf = CONFIG.read();
+        (
+            conf.resource_monitor.throttle,
+            f32::from(
+                conf.resource_monitor
+                    .cpu_limit_percent
+                    .unwrap_or(DEFAULT_CPU_LIMIT),
+            ),
+            conf.flac_compression_level
+                .unwrap_or_default()
+                .min(MAX_COMPRESSION_LEVEL),
+        )
+    };
+    let level = flac_compression_level();
+    if !enabled {
+        return level;
+    }
+    // an encoder that needs a whole core can't keep up either
+    let busiest = encoders
+        .iter()
+        .map(|e| e.cpu_percent)
+        .fold(cpu_percent_all, f32::max);
+    let next = next_level(level, configured, busiest, limit);
+    if next < level {
+        ui_log(&format!(
+            "*W*W*> CPU usage {busiest:.0}%, new FLAC streams use compression level {next}"
+        ));
+    } else if next > level {
+        ui_log(&format!(
+            "CPU usage {busiest:.0}%, new FLAC streams use compression level {next}"
+        ));
+    }
+    THROTTLED_LEVEL.store(if next == configured { u32::MAX } else { next }, Relaxed);
+    next
+}
+
+/// the CPU time used by the process
+#[cfg(target_os = "windows")]
+fn process_cpu_time() -> Option<Duration> {
+    use windows::Win32::{
+        Foundation::FILETIME,
+        System::Threading::{GetCurrentProcess, GetProcessTimes},
+    };
+    let (mut creation, mut exit, mut kernel, mut user) = (
+        FILETIME::default(),
+        FILETIME::default(),
+        FILETIME::default(),
+        FILETIME::default(),
+    );
+    unsafe {
+        GetProcessTimes(
+            GetCurrentProcess(),
+            &mut creation,
+            &mut exit,
+            &mut kernel,
+            &mut user,
+        )
+        .ok()?;
+    }
+    Some(filetime_duration(&kernel) + filetime_duration(&user))
+}
+
+/// the CPU time used by the process
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn process_cpu_time() -> Option<Duration> {
+    use libc::{getrusage, rusage, timeval, RUSAGE_SELF};
+    let to_duration = |tv: timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    unsafe {
+        let mut usage: rusage = std::mem::zeroed();
+        if getrusage(RUSAGE_SELF, &mut usage) != 0 {
+            return None;
+        }
+        Some(to_duration(usage.ru_utime) + to_duration(usage.ru_stime))
+    }
+}
+
+/// the CPU time used by the calling thread
+#[cfg(target_os = "windows")]
+fn thread_cpu_time() -> Option<Duration> {
+    use windows::Win32::{
+        Foundation::FILETIME,
+        System::Threading::{GetCurrentThread, GetThreadTimes},
+    };
+    let (mut creation, mut exit, mut kernel, mut user) = (
+        FILETIME::default(),
+        FILETIME::default(),
+        FILETIME::default(),
+        FILETIME::default(),
+    );
+    unsafe {
+        GetThreadTimes(
+            GetCurrentThread(),
+            &mut creation,
+            &mut exit,
+            &mut kernel,
+            &mut user,
+        )
+        .ok()?;
+    }
+    Some(filetime_duration(&kernel) + filetime_duration(&user))
+}
+
+/// the CPU time used by the calling thread
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn thread_cpu_time() -> Option<Duration> {
+    use libc::{clock_gettime, timespec, CLOCK_THREAD_CPUTIME_ID};
+    unsafe {
+        let mut ts: timespec = std::mem::zeroed();
+        if clock_gettime(CLOCK_THREAD_CPUTIME_ID, &mut ts) != 0 {
+            return None;
+        }
+        Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+}
+
+/// a FILETIME duration is in units of 100 nanoseconds
+#[cfg(target_os = "windows")]
+fn filetime_duration(ft: &windows::Win32::Foundation::FILETIME) -> Duration {
+    let ticks = (u64::from(ft.dwHighDateTime) << 32) | u64::from(ft.dwLowDateTime);
+    Duration::from_nanos(ticks * 100)
+}
+
+/// the resident memory (working set) of the process
+#[cfg(target_os = "windows")]
+fn resident_memory() -> Option<u64> {
+    use windows::Win32::System::{
+        ProcessStatus::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+        Threading::GetCurrentProcess,
+    };
+    let mut counters = PROCESS_MEMORY_COUNTERS::default();
+    unsafe {
+        GetProcessMemoryInfo(
+            GetCurrentProcess(),
+            &mut counters,
+            std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+        )
+        .ok()?;
+    }
+    Some(counters.WorkingSetSize as u64)
+}
+
+/// the resident memory of the process
+#[cfg(target_os = "linux")]
+fn resident_memory() -> Option<u64> {
+    // the second field of statm is the resident set size in pages
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(target_os = "macos")]
+fn resident_memory() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_percent() {
+        let sec = Duration::from_secs(1);
+        assert!((cpu_percent(sec / 2, sec, 1) - 50.0).abs() < 0.01);
+        assert!((cpu_percent(sec, sec, 4) - 25.0).abs() < 0.01);
+        assert!(cpu_percent(sec, Duration::ZERO, 4).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_next_level() {
+        // saturated: one level lower, but not below 0
+        assert_eq!(next_level(5, 5, 95.0, 90.0), 4);
+        assert_eq!(next_level(0, 5, 95.0, 90.0), 0);
+        // between the recovery threshold and the limit: unchanged
+        assert_eq!(next_level(4, 5, 80.0, 90.0), 4);
+        // recovered: one level higher, up to the configured level
+        assert_eq!(next_level(4, 5, 50.0, 90.0), 5);
+        assert_eq!(next_level(5, 5, 50.0, 90.0), 5);
+        // the configured level was lowered
+        assert_eq!(next_level(5, 3, 80.0, 90.0), 3);
+    }
+}
@@ -9,18 +9,39 @@
 /// to the media Renderer
 ///
 */
-use crate::{enums::streaming::StreamingFormat, globals::statics::CONFIG};
+use crate::{
+    enums::streaming::{ChannelMode, HeaderSize, StreamingFormat},
+    globals::statics::CONFIG,
+};
 use crossbeam_channel::{Receiver, Sender};
 use dasp_sample::Sample;
 use fastrand::Rng;
 use log::debug;
+use parking_lot::Mutex;
 use std::{
     collections::VecDeque,
-    io::{Read, Result as IoResult},
-    time::Duration,
+    io::{Error as IoError, ErrorKind, Read, Result as IoResult},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::{
+    dither::{stream_ditherer, Ditherer},
+    fade::Fade,
+    flacstream::FlacChannel,
+    session_stats::SessionStats,
+    ui_logger::ui_log,
 };
 
-use super::flacstream::FlacChannel;
+/// the largest RIFF chunksize in a WAV header
+pub(crate) const MAX_RIFF_CHUNKSIZE: u32 = 4_294_967_286;
+/// the largest RIFF size in the ds64 chunk of an RF64 header
+const MAX_DS64_RIFFSIZE: u64 = i64::MAX as u64 - 64;
+/// the latency that remains after catching up
+const NEAR_LIVE: Duration = Duration::from_secs(1);
 
 /// Channelstream - used to transport the f32 samples from the `wave_reader`
 /// to the http output stream in LPCM/WAV/FLAC format
@@ -33,12 +54,22 @@ pub struct ChannelStream {
     fifo: VecDeque<f32>,
     flac_fifo: VecDeque<u8>,
     silence: Vec<f32>,
+    silence_msec: u32,
     capture_timeout: Duration,
     sending_silence: bool,
+    samples_per_sec: usize,
+    max_latency: Duration,
     wav_hdr: Vec<u8>,
     use_wave_format: bool,
     bits_per_sample: u16,
+    channel_mode: ChannelMode,
+    ditherer: Option<Ditherer>,
+    fade: Arc<Mutex<Fade>>,
     flac_channel: Option<FlacChannel>,
+    pub stats: Arc<SessionStats>,
+    pub user_agent: String,
+    pub started: Instant,
+    disconnected: Arc<AtomicBool>,
 }
 
 impl ChannelStream {
@@ -49,40 +80,77 @@ impl ChannelStream {
         use_wave_format: bool,
         sample_rate: u32,
         bits_per_sample: u16,
+        channels: u16,
         streaming_format: StreamingFormat,
+        channel_mode: ChannelMode,
+        delay_msec: u32,
+        header_size: HeaderSize,
     ) -> ChannelStream {
+        // dither when reducing the samples to 16 bit, if configured
+        let ditherer = stream_ditherer(bits_per_sample, channels);
+        // the channel modes only apply to a stereo stream
+        let channel_mode = if channels == 2 {
+            channel_mode
+        } else {
+            ChannelMode::Stereo
+        };
+        // silence kicks in after the capture timeout of the streaming format
+        let (capture_timeout, silence_msec) = CONFIG.read().silence_timing(streaming_format);
+        // if enabled, a slow client drops whole seconds of audio when it lags too far behind
+        let catch_up_secs = CONFIG.read().catch_up_secs.unwrap_or_default();
+        let stats = Arc::new(SessionStats::default());
         let flac_channel = if streaming_format == StreamingFormat::Flac {
             Some(FlacChannel::new(
                 rx.clone(),
                 sample_rate,
                 u32::from(bits_per_sample),
-                2,
+                u32::from(channels),
+                ditherer.clone(),
+                (capture_timeout, silence_msec),
+                stats.clone(),
             ))
         } else {
             None
         };
-        let capture_timout = u64::from(CONFIG.read().capture_timeout.unwrap());
         let chs = ChannelStream {
             s: tx,
             r: rx,
             fifo: VecDeque::with_capacity(16384),
             flac_fifo: VecDeque::with_capacity(16384),
-            silence: get_silence_buffer(sample_rate, capture_timout / 4),
-            capture_timeout: Duration::from_millis(capture_timout), // silence kicks in after CAPTURE_TIMEOUT seconds
+            silence: get_silence_buffer(sample_rate, channels, u64::from(silence_msec)),
+            silence_msec,
+            capture_timeout: Duration::from_millis(u64::from(capture_timeout)),
             sending_silence: false,
+            samples_per_sec: sample_rate as usize * usize::from(channels),
+            max_latency: Duration::from_secs(u64::from(catch_up_secs)),
             remote_ip: remote_ip_addr,
             wav_hdr: if streaming_format == StreamingFormat::Wav {
-                create_wav_hdr(sample_rate, bits_per_sample)
+                create_wav_hdr(sample_rate, bits_per_sample, channels, header_size)
             } else if streaming_format == StreamingFormat::Rf64 {
-                create_rf64_hdr(sample_rate, bits_per_sample)
+                create_rf64_hdr(sample_rate, bits_per_sample, channels, header_size)
             } else {
                 Vec::new()
             },
             use_wave_format,
             bits_per_sample,
+            channel_mode,
+            ditherer,
+            fade: Arc::new(Mutex::new(Fade::for_stream(sample_rate, channels))),
             streaming_format,
             flac_channel,
+            stats,
+            user_agent: String::new(),
+            started: Instant::now(),
+            disconnected: Arc::new(AtomicBool::new(false)),
         };
+        // delay the audio for this client by starting with silence
+        if delay_msec > 0 {
+            let _ = chs.s.send(get_silence_buffer(
+                sample_rate,
+                channels,
+                u64::from(delay_msec),
+            ));
+        }
         if chs.streaming_format == StreamingFormat::Flac {
             chs.start_flac_encoder();
         }
@@ -96,6 +164,21 @@ impl ChannelStream {
         }
     }
 
+    // the bits per sample of the stream
+    pub fn bits_per_sample(&self) -> u16 {
+        self.bits_per_sample
+    }
+
+    // the number of captured blocks waiting to be converted or encoded
+    pub fn queue_depth(&self) -> usize {
+        self.r.len()
+    }
+
+    // force-disconnect the client, the HTTP response ends at the next read
+    pub fn disconnect(&self) {
+        self.disconnected.store(true, Ordering::Relaxed);
+    }
+
     // stop the flac encoder thread
     pub fn stop_flac_encoder(&self) {
         if let Some(flac_channel) = &self.flac_channel {
@@ -103,25 +186,50 @@ impl ChannelStream {
         }
     }
 
+    // start the fade-out of this stream, returns how long it takes
+    pub fn fade_out(&self) -> Duration {
+        self.fade.lock().fade_out()
+    }
+
     // called by the wave_reader to write the f32 samples to the input channel
     pub fn write(&self, samples: &[f32]) {
         // don't blow up memory if streaming stalls for some reason
         // 10_000 messages (capture buffers, not samples) is a quite a lot
         if self.s.len() < 10_000 {
-            self.s.send(samples.to_vec()).unwrap();
+            let mut samples = apply_channel_mode(self.channel_mode, samples);
+            self.fade.lock().apply(&mut samples);
+            self.s.send(samples).unwrap();
         }
     }
 
+    // log that the client skipped audio to catch up with the live audio
+    fn log_catch_up(&self, secs: f64) {
+        ui_log(&format!(
+            "{} lags behind, skipped {secs:.0} seconds of audio to catch up",
+            self.remote_ip
+        ));
+    }
+
     // fill the samples buffer with samples or with silence if no samples are coming
     #[inline(never)]
     fn get_samples(&mut self) {
         let time_out = self.capture_timeout;
+        self.stats.record_queue_depth(self.r.len());
         if let Ok(chunk) = self.r.recv_timeout(time_out) {
+            let block_duration =
+                Duration::from_secs_f64(chunk.len() as f64 / self.samples_per_sec as f64);
+            let skip = catch_up_blocks(self.r.len(), block_duration, self.max_latency);
+            if skip > 0 {
+                let dropped: usize = self.r.try_iter().take(skip).map(|b| b.len()).sum();
+                self.log_catch_up(dropped as f64 / self.samples_per_sec as f64);
+            }
             self.fifo.extend(chunk);
             self.sending_silence = false;
+            self.stats.record_audio();
         } else {
             self.fifo.extend(self.silence.clone());
             self.sending_silence = true;
+            self.stats.record_silence(self.silence_msec);
         }
     }
 }
@@ -138,6 +246,18 @@ impl ChannelStream {
 /// for transmission  
 impl Read for ChannelStream {
     fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.disconnected.load(Ordering::Relaxed) {
+            return Err(IoError::new(ErrorKind::ConnectionAborted, "disconnected"));
+        }
+        let n = self.read_stream(buf)?;
+        self.stats.record_bytes(n);
+        Ok(n)
+    }
+}
+
+impl ChannelStream {
+    // fill the HTTP response buffer with LPCM/WAV/RF64 samples or FLAC data
+    fn read_stream(&mut self, buf: &mut [u8]) -> IoResult<usize> {
         if self.flac_channel.is_none() {
             // LPCM (naked LPCM or WAV/RF64)
             if self.use_wave_format && !self.wav_hdr.is_empty() {
@@ -163,15 +283,29 @@ impl Read for ChannelStream {
             // the drain now contains the exact number of samples needed to fill the streaming buffer
             // so we can zip them
             match bytes_per_sample {
-                2 => match self.use_wave_format {
-                    true => {
+                2 => match (self.use_wave_format, self.ditherer.as_mut()) {
+                    (true, Some(ditherer)) => {
+                        buf.chunks_exact_mut(bytes_per_sample).zip(drain).for_each(
+                            |(chunk, f32_sample)| {
+                                chunk.copy_from_slice(&ditherer.to_i16(f32_sample).to_le_bytes())
+                            },
+                        );
+                    }
+                    (false, Some(ditherer)) => {
+                        buf.chunks_exact_mut(bytes_per_sample).zip(drain).for_each(
+                            |(chunk, f32_sample)| {
+                                chunk.copy_from_slice(&ditherer.to_i16(f32_sample).to_be_bytes())
+                            },
+                        );
+                    }
+                    (true, None) => {
                         buf.chunks_exact_mut(bytes_per_sample).zip(drain).for_each(
                             |(chunk, f32_sample)| {
                                 chunk.copy_from_slice(&get_le16_sample(f32_sample))
                             },
                         );
                     }
-                    false => {
+                    (false, None) => {
                         buf.chunks_exact_mut(bytes_per_sample).zip(drain).for_each(
                             |(chunk, f32_sample)| {
                                 chunk.copy_from_slice(&get_be16_sample(f32_sample))
@@ -201,7 +335,17 @@ impl Read for ChannelStream {
             Ok((buf.len() / bytes_per_sample) * bytes_per_sample)
         } else {
             // FLAC
-            let flac_in = self.flac_channel.as_ref().unwrap().flac_in.clone();
+            let flac_channel = self.flac_channel.as_ref().unwrap();
+            let flac_in = flac_channel.flac_in.clone();
+            // skip whole FLAC frames when the client lags behind, once the headers were sent
+            let frame_duration = flac_channel.frame_duration();
+            if self.stats.bytes_sent() > 0 {
+                let skip = catch_up_blocks(flac_in.len(), frame_duration, self.max_latency);
+                if skip > 0 {
+                    let dropped = flac_in.try_iter().take(skip).count();
+                    self.log_catch_up(frame_duration.as_secs_f64() * dropped as f64);
+                }
+            }
             // make sure we have enough data for this read buffer
             while self.flac_fifo.len() < buf.len() {
                 if let Ok(chunk) = flac_in.recv() {
@@ -220,6 +364,36 @@ impl Read for ChannelStream {
     }
 }
 
+/// the number of queued blocks to drop when a client lags more than the maximum latency
+/// behind the live audio: whole seconds, so that about a second remains queued
+fn catch_up_blocks(queued: usize, block_duration: Duration, max_latency: Duration) -> usize {
+    if max_latency.is_zero() || block_duration.is_zero() {
+        return 0;
+    }
+    let latency = block_duration.mul_f64(queued as f64);
+    if latency <= max_latency {
+        return 0;
+    }
+    let skip = Duration::from_secs(latency.saturating_sub(NEAR_LIVE).as_secs());
+    (skip.as_secs_f64() / block_duration.as_secs_f64()) as usize
+}
+
+// the stereo samples with the channel mode applied
+fn apply_channel_mode(channel_mode: ChannelMode, samples: &[f32]) -> Vec<f32> {
+    let mut result = samples.to_vec();
+    match channel_mode {
+        ChannelMode::Stereo => (),
+        ChannelMode::Mono => result.chunks_exact_mut(2).for_each(|lr| {
+            let m = (lr[0] + lr[1]) * 0.5;
+            lr[0] = m;
+            lr[1] = m;
+        }),
+        ChannelMode::Left => result.chunks_exact_mut(2).for_each(|lr| lr[1] = lr[0]),
+        ChannelMode::Right => result.chunks_exact_mut(2).for_each(|lr| lr[0] = lr[1]),
+    }
+    result
+}
+
 // get the next le16 sample
 #[inline(always)]
 fn get_le16_sample(f32_sample: f32) -> [u8; 2] {
@@ -272,21 +446,32 @@ cksize	        4	    Chunk size: M*Nc*Ns
 sampled data	M*Nc*Ns	Nc*Ns channel-interleaved M-byte samples
 pad byte	    0 or 1	Padding byte if M*Nc*Ns is odd
 */
-fn create_wav_hdr(sample_rate: u32, bits_per_sample: u16) -> Vec<u8> {
+pub(crate) fn create_wav_hdr(
+    sample_rate: u32,
+    bits_per_sample: u16,
+    channels: u16,
+    header_size: HeaderSize,
+) -> Vec<u8> {
     let mut hdr = [0u8; 44];
-    let channels: u16 = 2;
     let bytes_per_sample: u16 = bits_per_sample / 8;
     let block_align: u16 = channels * bytes_per_sample;
     let byte_rate: u32 = sample_rate * u32::from(block_align);
     hdr[0..4].copy_from_slice(b"RIFF"); //ChunkId, little endian WAV
-    let riffchunksize: u32 = 4_294_967_286; // RIFF chunksize
-    let datachunksize: u32 = riffchunksize - 36; // data chunksize
+
+    // data chunksize, the RIFF chunksize includes the rest of the header
+    let datachunksize = data_size(
+        header_size,
+        byte_rate,
+        block_align,
+        u64::from(MAX_RIFF_CHUNKSIZE - 36),
+    ) as u32;
+    let riffchunksize: u32 = datachunksize + 36; // RIFF chunksize
     hdr[4..8].copy_from_slice(&riffchunksize.to_le_bytes()); // RIFF ChunkSize
     hdr[8..12].copy_from_slice(b"WAVE"); // File Format
     hdr[12..16].copy_from_slice(b"fmt "); // SubChunk = Format
     hdr[16..20].copy_from_slice(&16u32.to_le_bytes()); // fmt chunksize for PCM
     hdr[20..22].copy_from_slice(&1u16.to_le_bytes()); // AudioFormat: uncompressed PCM
-    hdr[22..24].copy_from_slice(&channels.to_le_bytes()); // numchannels 2
+    hdr[22..24].copy_from_slice(&channels.to_le_bytes()); // numchannels
     hdr[24..28].copy_from_slice(&sample_rate.to_le_bytes()); // SampleRate
     hdr[28..32].copy_from_slice(&byte_rate.to_le_bytes()); // ByteRate (Bps)
     hdr[32..34].copy_from_slice(&block_align.to_le_bytes()); // BlockAlign
@@ -322,9 +507,13 @@ ckID	        4	72       Chunk ID: 'data'
 cksize	        4	76       dummy Chunk size -1 (0xffffffff)
 sampled data    ... 80
 */
-fn create_rf64_hdr(sample_rate: u32, bits_per_sample: u16) -> Vec<u8> {
+fn create_rf64_hdr(
+    sample_rate: u32,
+    bits_per_sample: u16,
+    channels: u16,
+    header_size: HeaderSize,
+) -> Vec<u8> {
     let mut hdr = [0u8; 80];
-    let channels: u16 = 2;
     let bytes_per_sample: u16 = bits_per_sample / 8;
     let block_align: u16 = channels * bytes_per_sample;
     let byte_rate: u32 = sample_rate * u32::from(block_align);
@@ -332,8 +521,8 @@ fn create_rf64_hdr(sample_rate: u32, bits_per_sample: u16) -> Vec<u8> {
     let rf64chunksize: u32 = 0xffff_ffff; // dummy RIFF chunksize
     let datachunksize: u32 = 0xffff_ffff; // dummy data chunksize
     let ds64chunksize: u32 = 28;
-    let ds64riffsize: u64 = i64::MAX as u64 - 64;
-    let ds64datasize: u64 = ds64riffsize - 8u64;
+    let ds64datasize: u64 = data_size(header_size, byte_rate, block_align, MAX_DS64_RIFFSIZE - 8);
+    let ds64riffsize: u64 = ds64datasize + 8u64;
     let ds64nsamples: u64 = ds64datasize / u64::from(bytes_per_sample);
     let ds64tablelength = 0u32;
     hdr[4..8].copy_from_slice(&rf64chunksize.to_le_bytes()); // RIFF ChunkSize
@@ -347,7 +536,7 @@ fn create_rf64_hdr(sample_rate: u32, bits_per_sample: u16) -> Vec<u8> {
     hdr[48..52].copy_from_slice(b"fmt "); // SubChunk = Format
     hdr[52..56].copy_from_slice(&16u32.to_le_bytes()); // fmt chunksize for PCM
     hdr[56..58].copy_from_slice(&1u16.to_le_bytes()); // AudioFormat: uncompressed PCM
-    hdr[58..60].copy_from_slice(&channels.to_le_bytes()); // numchannels 2
+    hdr[58..60].copy_from_slice(&channels.to_le_bytes()); // numchannels
     hdr[60..64].copy_from_slice(&sample_rate.to_le_bytes()); // SampleRate
     hdr[64..68].copy_from_slice(&byte_rate.to_le_bytes()); // ByteRate (Bps)
     hdr[68..70].copy_from_slice(&block_align.to_le_bytes()); // BlockAlign
@@ -359,10 +548,22 @@ fn create_rf64_hdr(sample_rate: u32, bits_per_sample: u16) -> Vec<u8> {
     hdr.to_vec()
 }
 
+/// the size of the audio data in a WAV or RF64 header: the maximum, a number of bytes
+/// or the size of a virtual duration, in whole sample frames and at most the maximum
+fn data_size(header_size: HeaderSize, byte_rate: u32, block_align: u16, max: u64) -> u64 {
+    let size = match header_size {
+        HeaderSize::Max => return max,
+        HeaderSize::Bytes(bytes) => bytes,
+        HeaderSize::Secs(secs) => u64::from(secs) * u64::from(byte_rate),
+    };
+    let block_align = u64::from(block_align);
+    size.min(max) / block_align * block_align
+}
+
 //#[allow(dead_code)]
-fn get_silence_buffer(sample_rate: u32, silence_period: u64) -> Vec<f32> {
-    // silence_period is in msecs (capture_timeout / 4), sample rate is per second, 2 channels for stereo
-    let size = ((sample_rate * 2 * silence_period as u32) / 1000) as usize;
+fn get_silence_buffer(sample_rate: u32, channels: u16, silence_period: u64) -> Vec<f32> {
+    // silence_period is in msecs, sample rate is per second, 2 channels for stereo
+    let size = (u64::from(sample_rate) * u64::from(channels) * silence_period / 1000) as usize;
     let mut silence = Vec::with_capacity(size);
     silence.resize(size, 0f32);
     silence
@@ -375,7 +576,7 @@ fn get_silence_buffer(sample_rate: u32, silence_period: u64) -> Vec<f32> {
 fn get_noise_buffer(sample_rate: u32, silence_period: u64) -> Vec<f32> {
     // create the random generator for the white noise
     let mut rng = Rng::with_seed(79);
-    let size = ((sample_rate * 2 * silence_period as u32) / 1000) as usize;
+    let size = (u64::from(sample_rate) * 2 * silence_period / 1000) as usize;
     let mut noise = Vec::with_capacity(size);
     noise.resize(size, 0.0);
     let amplitude: f32 = 0.001;
@@ -391,17 +592,79 @@ mod tests {
     #[test]
 
     fn test_wav_hdr() {
-        let _hdr = create_wav_hdr(44100, 24);
+        let _hdr = create_wav_hdr(44100, 24, 2, HeaderSize::Max);
         //eprintln!("WAV Header (l={}): \r\n{:02x?}", hdr.len(), hdr);
-        let _hdr = create_wav_hdr(44100, 16);
+        let _hdr = create_wav_hdr(44100, 16, 2, HeaderSize::Max);
         //eprintln!("WAV Header (l={}): \r\n{:02x?}", hdr.len(), hdr);
     }
 
+    #[test]
+    fn test_header_size() {
+        let hdr = create_wav_hdr(44100, 16, 2, HeaderSize::Max);
+        assert_eq!(hdr[4..8], 4_294_967_286u32.to_le_bytes());
+        assert_eq!(hdr[40..44], 4_294_967_250u32.to_le_bytes());
+        // one hour of 16 bit stereo at 44.1 kHz
+        let hdr = create_wav_hdr(44100, 16, 2, HeaderSize::Secs(3600));
+        assert_eq!(hdr[40..44], 635_040_000u32.to_le_bytes());
+        assert_eq!(hdr[4..8], 635_040_036u32.to_le_bytes());
+        // whole 24 bit stereo frames
+        let hdr = create_wav_hdr(48000, 24, 2, HeaderSize::Bytes(1_000_000));
+        assert_eq!(hdr[40..44], 999_996u32.to_le_bytes());
+        // a day doesn't fit
+        let hdr = create_wav_hdr(192_000, 24, 2, HeaderSize::Secs(86400));
+        assert_eq!(hdr[40..44], 4_294_967_250u32.to_le_bytes());
+        let hdr = create_rf64_hdr(44100, 16, 2, HeaderSize::Secs(86400));
+        assert_eq!(hdr[4..8], 0xffff_ffffu32.to_le_bytes());
+        assert_eq!(hdr[28..36], 15_240_960_000u64.to_le_bytes());
+        assert_eq!(hdr[20..28], 15_240_960_008u64.to_le_bytes());
+        // 5.1 channels
+        let hdr = create_wav_hdr(48000, 24, 6, HeaderSize::Secs(1));
+        assert_eq!(hdr[22..24], 6u16.to_le_bytes());
+        assert_eq!(hdr[28..32], 864_000u32.to_le_bytes());
+        assert_eq!(hdr[32..34], 18u16.to_le_bytes());
+        assert_eq!(hdr[40..44], 864_000u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_channel_mode() {
+        let samples = [0.5f32, -0.25, 0.125, 0.375];
+        assert_eq!(apply_channel_mode(ChannelMode::Stereo, &samples), samples);
+        assert_eq!(
+            apply_channel_mode(ChannelMode::Mono, &samples),
+            [0.125, 0.125, 0.25, 0.25]
+        );
+        assert_eq!(
+            apply_channel_mode(ChannelMode::Left, &samples),
+            [0.5, 0.5, 0.125, 0.125]
+        );
+        assert_eq!(
+            apply_channel_mode(ChannelMode::Right, &samples),
+            [-0.25, -0.25, 0.375, 0.375]
+        );
+    }
+
+    #[test]
+    fn test_catch_up_blocks() {
+        let block = Duration::from_millis(10);
+        let max = Duration::from_secs(10);
+        assert_eq!(catch_up_blocks(1000, block, max), 0);
+        // 12.5 seconds behind: skip 11 seconds, 1.5 seconds remain
+        assert_eq!(catch_up_blocks(1250, block, max), 1100);
+        // disabled
+        assert_eq!(catch_up_blocks(1250, block, Duration::ZERO), 0);
+        // FLAC frames of 1152 samples at 44.1 kHz
+        let frame = Duration::from_secs_f64(1152.0 / 44100.0);
+        let skip = catch_up_blocks(500, frame, max);
+        assert!(frame.mul_f64((500 - skip) as f64) < Duration::from_secs(2));
+    }
+
     #[test]
     fn test_silence() {
         const SAMPLE_RATE: u32 = 44100;
-        let sb = get_silence_buffer(SAMPLE_RATE, 250);
+        let sb = get_silence_buffer(SAMPLE_RATE, 2, 250);
         assert_eq!(sb.len(), ((SAMPLE_RATE * 2) as u64 / (1000 / 250)) as usize);
+        let sb = get_silence_buffer(SAMPLE_RATE, 6, 250);
+        assert_eq!(sb.len(), ((SAMPLE_RATE * 6) as u64 / (1000 / 250)) as usize);
     }
 
     #[test]
@@ -0,0 +1,316 @@
+//! Embedded scripting with [rhai](https://rhai.rs), for automation that the settings and the
+//! hooks can't express, e.g. "if the Kitchen disconnects 3 times in 5 minutes, switch it
+//! to WAV".
+//!
+//! The `script` file of the config (relative to the config directory) runs in its own
+//! thread. Its top level runs once at startup, then its `on_event(event, vars)` function is
+//! called for every hook event (see hooks.rs) with the variables of the event as an object
+//! map. `this` is an object map that keeps the state of the script between the events.
+//!
+//! The script has no file or network access, only this API:
+//! - `renderers()`: the discovered renderers, with `name`, `model`, `ip`, `group` and `playing`
+//! - `play(name)`, `stop(name)` and `volume(name, vol)`: like the MQTT commands, the renderers
+//!   are matched by (part of) their name or their ip address
+//! - `set_format(name, format)`: the streaming format of a renderer from the next play on
+//! - `rms_db()`: the level of the captured audio in dBFS
+//! - `config(name)`: a value of the config file, without the credentials
+//! - `log(text)` and `print(text)`: log a message
+
+use crossbeam_channel::{unbounded, Sender};
+use parking_lot::RwLock;
+use rhai::{
+    module_resolvers::DummyModuleResolver, Array, CallFnOptions, Dynamic, Engine, Map, Scope, AST,
+};
+use std::{
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        LazyLock, OnceLock,
+    },
+    thread,
+};
+
+use crate::{
+    enums::{
+        messages::{MessageType, MqttCommand},
+        streaming::StreamingFormat,
+    },
+    globals::statics::{CLIENTS, CONFIG, MSGCHANNEL},
+    openhome::rendercontrol::Renderer,
+    utils::{hooks::HookEvent, mqtt::renderer_matches, ui_logger::ui_log},
+};
+
+/// the operations a script may use per event, so that an endless loop can't hang it
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// the events for the script, when a script is running
+static SCRIPT_EVENTS: OnceLock<Sender<(HookEvent, Vec<(&'static str, String)>)>> = OnceLock::new();
+/// true while a script is running, so that the `wave_reader` measures the level
+static SCRIPTING: AtomicBool = AtomicBool::new(false);
+/// the level of the last captured samples in dBFS, as f32 bits
+static LEVEL_DB: AtomicU32 = AtomicU32::new(0);
+/// the discovered renderers, for `renderers()`
+static RENDERERS: LazyLock<RwLock<Vec<Renderer>>> = LazyLock::new(|| RwLock::new(Vec::new()));
+
+/// the level of samples in dBFS, -100 for digital silence
+fn level_db(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return -100.0;
+    }
+    let mean_square = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+    (10.0 * mean_square.log10()).max(-100.0)
+}
+
+/// remove the credentials from a config value
+fn without_secrets(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|k, _| !matches!(k.as_str(), "password" | "token" | "shutdown_token"));
+            map.values_mut().for_each(without_secrets);
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(without_secrets),
+        _ => (),
+    }
+}
+
+/// a value of the config file, () if there is no such value
+fn config_value(name: &str) -> Dynamic {
+    let Ok(mut config) = serde_json::to_value(&*CONFIG.read()) else {
+        return Dynamic::UNIT;
+    };
+    without_secrets(&mut config);
+    config
+        .get(name)
+        .and_then(|v| rhai::serde::to_dynamic(v).ok())
+        .unwrap_or(Dynamic::UNIT)
+}
+
+/// the discovered renderers as object maps
+fn renderer_maps() -> Array {
+    let clients = CLIENTS.read();
+    RENDERERS
+        .read()
+        .iter()
+        .map(|r| {
+            let mut map = Map::new();
+            map.insert("name".into(), r.dev_name.clone().into());
+            map.insert("model".into(), r.dev_model.clone().into());
+            map.insert("ip".into(), r.remote_addr.clone().into());
+            map.insert(
+                "group".into(),
+                r.group
+                    .as_ref()
+                    .map_or(Dynamic::UNIT, |g| g.name.clone().into()),
+            );
+            map.insert(
+                "playing".into(),
+                clients
+                    .values()
+                    .any(|c| c.remote_ip == r.remote_addr)
+                    .into(),
+            );
+            map.into()
+        })
+        .collect()
+}
+
+/// the streaming format of the matching renderers from the next play on
+fn set_renderer_format(name: &str, format: &str) -> bool {
+    let Some(format) = ["Lpcm", "Wav", "Flac", "Rf64"]
+        .into_iter()
+        .find(|f| f.eq_ignore_ascii_case(format))
+        .and_then(|f| StreamingFormat::from_str(f).ok())
+    else {
+        ui_log(&format!("*W*W*> Script: invalid format {format}"));
+        return false;
+    };
+    let names: Vec<String> = RENDERERS
+        .read()
+        .iter()
+        .filter(|r| renderer_matches(r, name))
+        .map(|r| r.dev_name.clone())
+        .collect();
+    if names.is_empty() {
+        return false;
+    }
+    let mut conf = CONFIG.write();
+    for name in &names {
+        ui_log(&format!("Script: using {format} for {name}"));
+        conf.renderers
+            .entry(name.clone())
+            .or_default()
+            .streaming_format = Some(format);
+    }
+    let _ = conf.update_config();
+    true
+}
+
+fn send_command(cmd: MqttCommand) {
+    let _ = MSGCHANNEL.read().0.send(MessageType::MqttMessage(cmd));
+}
+
+/// the engine with the safe API for the scripts
+fn new_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.on_print(|text| ui_log(&format!("Script: {text}")));
+    engine.on_debug(|text, _, pos| log::debug!("Script {pos}: {text}"));
+    engine.register_fn("log", |text: Dynamic| ui_log(&format!("Script: {text}")));
+    engine.register_fn("renderers", renderer_maps);
+    engine.register_fn("play", |name: &str| {
+        send_command(MqttCommand::Play(name.to_string()));
+    });
+    engine.register_fn("stop", |name: &str| {
+        send_command(MqttCommand::Stop(name.to_string()));
+    });
+    engine.register_fn("volume", |name: &str, vol: i64| {
+        let vol = u8::try_from(vol.clamp(0, 100)).unwrap_or_default();
+        send_command(MqttCommand::Volume(name.to_string(), vol));
+    });
+    engine.register_fn("set_format", set_renderer_format);
+    engine.register_fn("rms_db", || {
+        f64::from(f32::from_bits(LEVEL_DB.load(Ordering::Relaxed)))
+    });
+    engine.register_fn("config", config_value);
+    engine
+}
+
+/// the event variables as an object map
+fn vars_map(vars: &[(&'static str, String)]) -> Map {
+    vars.iter()
+        .map(|(name, value)| ((*name).into(), value.clone().into()))
+        .collect()
+}
+
+/// call `on_event` of the script, with the state of the script as `this`
+fn call_on_event(
+    engine: &Engine,
+    ast: &AST,
+    state: &mut Dynamic,
+    event: HookEvent,
+    vars: &[(&'static str, String)],
+) {
+    let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(state);
+    if let Err(e) = engine.call_fn_with_options::<Dynamic>(
+        options,
+        &mut Scope::new(),
+        ast,
+        "on_event",
+        (event.to_string(), vars_map(vars)),
+    ) {
+        ui_log(&format!("*W*W*> Script error in on_event({event}): {e}"));
+    }
+}
+
+/// remember a discovered renderer for the script
+pub fn script_renderer(renderer: &Renderer) {
+    let mut renderers = RENDERERS.write();
+    renderers.retain(|r| r.location != renderer.location);
+    renderers.push(renderer.clone());
+}
+
+/// pass an event to the script, if a script is running
+pub fn script_event(event: HookEvent, vars: &[(&'static str, String)]) {
+    if let Some(events) = SCRIPT_EVENTS.get() {
+        let _ = events.send((event, vars.to_vec()));
+    }
+}
+
+/// measure the level of the captured samples for the script, called by the `wave_reader`
+pub fn script_samples(samples: &[f32]) {
+    if SCRIPTING.load(Ordering::Relaxed) {
+        LEVEL_DB.store(level_db(samples).to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Run the configured script in its own thread: its top level once, and its `on_event`
+/// function for every event.
+pub fn start_script() {
+    let Some(script) = CONFIG.read().script.clone() else {
+        return;
+    };
+    let path: PathBuf = CONFIG.read().config_dir().join(script);
+    let engine = new_engine();
+    let ast = match engine.compile_file(path.clone()) {
+        Ok(ast) => ast,
+        Err(e) => {
+            ui_log(&format!("*E*E*> Script {} not loaded: {e}", path.display()));
+            return;
+        }
+    };
+    let (tx, rx) = unbounded();
+    if SCRIPT_EVENTS.set(tx).is_err() {
+        return;
+    }
+    SCRIPTING.store(true, Ordering::Relaxed);
+    ui_log(&format!("Running script {}", path.display()));
+    let _ = thread::Builder::new().name("script".into()).spawn(move || {
+        if let Err(e) = engine.run_ast(&ast) {
+            ui_log(&format!("*W*W*> Script error: {e}"));
+        }
+        let has_on_event = ast
+            .iter_functions()
+            .any(|f| f.name == "on_event" && f.params.len() == 2);
+        let mut state = Dynamic::from_map(Map::new());
+        while let Ok((event, vars)) = rx.recv() {
+            if has_on_event {
+                call_on_event(&engine, &ast, &mut state, event, &vars);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_state_and_limits() {
+        assert_eq!(level_db(&[]), -100.0);
+        assert!((level_db(&[0.5, -0.5]) + 6.02).abs() < 0.01);
+        let engine = new_engine();
+        // count the disconnects of the Kitchen in the state of the script
+        let ast = engine
+            .compile(
+                r#"
+                fn on_event(event, vars) {
+                    if this.drops == () { this.drops = 0; }
+                    if event == "StreamStopped" && vars.renderer == "Kitchen" {
+                        this.drops += 1;
+                    }
+                }
+                "#,
+            )
+            .unwrap();
+        let mut state = Dynamic::from_map(Map::new());
+        let vars = [("renderer", "Kitchen".to_string())];
+        for _ in 0..3 {
+            call_on_event(&engine, &ast, &mut state, HookEvent::StreamStopped, &vars);
+        }
+        call_on_event(&engine, &ast, &mut state, HookEvent::StreamStarted, &vars);
+        let drops = state.cast::<Map>()["drops"].as_int().unwrap();
+        assert_eq!(drops, 3);
+        // an endless loop stops at the operations limit
+        assert!(engine.run("loop { }").is_err());
+        // no file access
+        assert!(engine.run(r#"import "automation" as a;"#).is_err());
+    }
+
+    #[test]
+    fn test_without_secrets() {
+        let mut config = serde_json::json!({
+            "mqtt": { "host": "broker", "password": "secret" },
+            "renderers": { "Kitchen": { "auth": { "username": "me", "token": "abc" } } },
+        });
+        without_secrets(&mut config);
+        assert_eq!(
+            config,
+            serde_json::json!({
+                "mqtt": { "host": "broker" },
+                "renderers": { "Kitchen": { "auth": { "username": "me" } } },
+            })
+        );
+    }
+}
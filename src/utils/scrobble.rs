@@ -0,0 +1,508 @@
+//! Scrobble the now-playing tracks to ListenBrainz and Last.fm, for sources that don't
+//! scrobble themselves (a turntable, a radio, another computer).
+//!
+//! The captured audio has no metadata, so the now-playing track has to be pushed to swyh-rs:
+//! with a POST of a JSON object to /api/nowplaying (with `control_api` enabled), or by writing
+//! it to the `now_playing_file` of the `[configuration.scrobble]` section, e.g.
+//! `{"artist": "Miles Davis", "title": "So What", "album": "Kind of Blue", "duration_secs": 545}`.
+//! The album and the duration are optional, an object without a title ends the current track.
+//!
+//! A track is sent as "playing now" when it is pushed, and it is scrobbled when the next track
+//! is pushed (or the track is ended) if it is longer than 30 seconds and was played for at least
+//! half its duration or 4 minutes, the scrobbling rule of Last.fm and ListenBrainz. A track
+//! without a duration is scrobbled after 4 minutes.
+//!
+//! Last.fm needs the api key and the secret of an API account, and the session key of a user, or
+//! the username and password of the user to get the session key with.
+
+use parking_lot::{const_mutex, Mutex};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs, thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    globals::statics::CONFIG,
+    server::ssdp_announcer::http_user_agent,
+    utils::{
+        configuration::ScrobbleSettings,
+        secrets::{LASTFM_PASSWORD, LASTFM_SECRET, LASTFM_SESSION_KEY, LISTENBRAINZ_TOKEN},
+        ui_logger::ui_log,
+    },
+};
+
+const LISTENBRAINZ_URL: &str = "https://api.listenbrainz.org/1/submit-listens";
+const LASTFM_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+/// a track must be longer than this to be scrobbled
+const MIN_TRACK_LENGTH: Duration = Duration::from_secs(30);
+/// a track that played this long is always scrobbled
+const MAX_PLAYED: Duration = Duration::from_secs(240);
+/// the polling interval of the now-playing file
+const FILE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// a now-playing track as pushed to swyh-rs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NowPlaying {
+    #[serde(default)]
+    pub artist: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub album: Option<String>,
+    #[serde(default)]
+    pub duration_secs: Option<u64>,
+}
+
+impl NowPlaying {
+    /// false for an object without a title, that ends the current track
+    #[must_use]
+    pub fn is_track(&self) -> bool {
+        !self.title.trim().is_empty()
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.duration_secs.map(Duration::from_secs)
+    }
+}
+
+/// the track that is playing
+struct Playing {
+    track: NowPlaying,
+    /// the unix time of the start, the timestamp of the scrobble
+    started_at: u64,
+    since: Instant,
+}
+
+static PLAYING: Mutex<Option<Playing>> = const_mutex(None);
+
+/// the Last.fm session key that was requested with the username and password
+static LASTFM_SESSION: Mutex<Option<String>> = const_mutex(None);
+
+/// true if a track with this duration that played this long should be scrobbled
+#[must_use]
+pub fn should_scrobble(duration: Option<Duration>, played: Duration) -> bool {
+    match duration {
+        Some(duration) => {
+            duration > MIN_TRACK_LENGTH && (played >= duration / 2 || played >= MAX_PLAYED)
+        }
+        None => played >= MAX_PLAYED,
+    }
+}
+
+/// start watching the now-playing file, if scrobbling is configured
+pub fn start_scrobbler() {
+    let Some(settings) = CONFIG.read().scrobble.clone() else {
+        return;
+    };
+    let services = services(&settings);
+    if services.is_empty() {
+        ui_log("*W*W*> Scrobbling is configured without a ListenBrainz token or Last.fm api key");
+        return;
+    }
+    ui_log(&format!("Scrobbling to {}", services.join(" and ")));
+    let Some(file) = settings.now_playing_file else {
+        return;
+    };
+    ui_log(&format!("Watching the now-playing file {file}"));
+    let _ = thread::Builder::new()
+        .name("now_playing_file".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            let mut modified = None;
+            loop {
+                let changed = fs::metadata(&file).and_then(|m| m.modified()).ok();
+                if changed.is_some() && changed != modified {
+                    modified = changed;
+                    match fs::read(&file) {
+                        Ok(json) => {
+                            if let Err(e) = now_playing_request(&json) {
+                                ui_log(&format!("*W*W*> Now-playing file {file}: {e}"));
+                            }
+                        }
+                        Err(e) => ui_log(&format!("*W*W*> Can't read {file}: {e}")),
+                    }
+                }
+                thread::sleep(FILE_POLL_INTERVAL);
+            }
+        });
+}
+
+/// the names of the configured scrobbling services
+fn services(settings: &ScrobbleSettings) -> Vec<&'static str> {
+    let config_id = CONFIG.read().config_id.clone().unwrap_or_default();
+    let mut services = Vec::new();
+    if settings.secret(LISTENBRAINZ_TOKEN, &config_id).is_some() {
+        services.push("ListenBrainz");
+    }
+    if settings.lastfm_api_key.is_some() {
+        services.push("Last.fm");
+    }
+    services
+}
+
+/// handle a now-playing JSON object from /api/nowplaying or the now-playing file,
+/// returns the track as JSON
+pub fn now_playing_request(json: &[u8]) -> Result<String, String> {
+    if CONFIG.read().scrobble.is_none() {
+        return Err("Scrobbling is not configured".to_string());
+    }
+    let track: NowPlaying =
+        serde_json::from_slice(json).map_err(|e| format!("Invalid now-playing track: {e}"))?;
+    if track.is_track() && track.artist.trim().is_empty() {
+        return Err("A now-playing track needs an artist".to_string());
+    }
+    let response = serde_json::to_string(&track).unwrap_or_default();
+    now_playing(track);
+    Ok(response)
+}
+
+/// a new now-playing track: scrobble the previous track and send the new one as playing now
+fn now_playing(track: NowPlaying) {
+    let previous = {
+        let mut playing = PLAYING.lock();
+        // the same track pushed again, e.g. a rewritten now-playing file
+        if playing.as_ref().is_some_and(|p| p.track == track) {
+            return;
+        }
+        let previous = playing.take();
+        if track.is_track() {
+            *playing = Some(Playing {
+                track: track.clone(),
+                started_at: unix_time(),
+                since: Instant::now(),
+            });
+        }
+        previous
+    };
+    let Some(settings) = CONFIG.read().scrobble.clone() else {
+        return;
+    };
+    let _ = thread::Builder::new()
+        .name("scrobbler".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            if let Some(previous) = previous {
+                let played = previous.since.elapsed();
+                if should_scrobble(previous.track.duration(), played) {
+                    ui_log(&format!(
+                        "Scrobbling {} - {}",
+                        previous.track.artist, previous.track.title
+                    ));
+                    submit(&settings, &previous.track, Some(previous.started_at));
+                }
+            }
+            if track.is_track() {
+                ui_log(&format!("Now playing {} - {}", track.artist, track.title));
+                submit(&settings, &track, None);
+            }
+        });
+}
+
+/// send a track to the configured services, a scrobble if it has a timestamp,
+/// otherwise as playing now
+fn submit(settings: &ScrobbleSettings, track: &NowPlaying, timestamp: Option<u64>) {
+    let config_id = CONFIG.read().config_id.clone().unwrap_or_default();
+    if let Some(token) = settings.secret(LISTENBRAINZ_TOKEN, &config_id) {
+        if let Err(e) = listenbrainz_submit(&token, track, timestamp) {
+            ui_log(&format!("*W*W*> ListenBrainz: {e}"));
+        }
+    }
+    if settings.lastfm_api_key.is_some() {
+        if let Err(e) = lastfm_submit(settings, &config_id, track, timestamp) {
+            ui_log(&format!("*W*W*> Last.fm: {e}"));
+        }
+    }
+}
+
+/// the JSON payload of a ListenBrainz listen
+#[must_use]
+pub fn listenbrainz_payload(track: &NowPlaying, timestamp: Option<u64>) -> serde_json::Value {
+    let mut metadata = serde_json::json!({
+        "artist_name": track.artist,
+        "track_name": track.title,
+        "additional_info": {
+            "media_player": "swyh-rs",
+            "submission_client": "swyh-rs",
+        },
+    });
+    if let Some(album) = &track.album {
+        metadata["release_name"] = album.clone().into();
+    }
+    if let Some(duration) = track.duration_secs {
+        metadata["additional_info"]["duration"] = duration.into();
+    }
+    let mut listen = serde_json::json!({ "track_metadata": metadata });
+    if let Some(timestamp) = timestamp {
+        listen["listened_at"] = timestamp.into();
+    }
+    serde_json::json!({
+        "listen_type": if timestamp.is_some() { "single" } else { "playing_now" },
+        "payload": [listen],
+    })
+}
+
+fn listenbrainz_submit(
+    token: &str,
+    track: &NowPlaying,
+    timestamp: Option<u64>,
+) -> Result<(), String> {
+    let resp = attohttpc::post(LISTENBRAINZ_URL)
+        .header("User-Agent", http_user_agent())
+        .header("Authorization", format!("Token {token}"))
+        .header("Content-Type", "application/json")
+        .timeout(Duration::from_secs(10))
+        .text(listenbrainz_payload(track, timestamp).to_string())
+        .send()
+        .map_err(|e| e.to_string())?;
+    if resp.is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP status {}", resp.status()))
+    }
+}
+
+/// the Last.fm request parameters with the `api_sig` signature: the MD5 digest of the
+/// parameters sorted by name, each name followed by its value, and the secret
+#[must_use]
+pub fn lastfm_signed(
+    mut params: BTreeMap<&'static str, String>,
+    secret: &str,
+) -> BTreeMap<&'static str, String> {
+    let mut signature: String = params.iter().map(|(k, v)| format!("{k}{v}")).collect();
+    signature.push_str(secret);
+    params.insert("api_sig", md5_hex(signature.as_bytes()));
+    params.insert("format", "json".to_string());
+    params
+}
+
+/// a signed POST request to the Last.fm API, returns the JSON response
+fn lastfm_call(
+    params: BTreeMap<&'static str, String>,
+    secret: &str,
+) -> Result<serde_json::Value, String> {
+    let body = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(lastfm_signed(params, secret))
+        .finish();
+    let resp = attohttpc::post(LASTFM_URL)
+        .header("User-Agent", http_user_agent())
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .timeout(Duration::from_secs(10))
+        .text(body)
+        .send()
+        .map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let json: serde_json::Value = resp
+        .text()
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+    // the errors have a JSON message, sometimes with status 200
+    if let Some(message) = json.get("message").and_then(|m| m.as_str()) {
+        return Err(message.to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("HTTP status {status}"));
+    }
+    Ok(json)
+}
+
+/// the configured session key, or else one requested with the username and password
+fn lastfm_session_key(
+    settings: &ScrobbleSettings,
+    config_id: &str,
+    api_key: &str,
+    secret: &str,
+) -> Result<String, String> {
+    if let Some(session_key) = settings.secret(LASTFM_SESSION_KEY, config_id) {
+        return Ok(session_key);
+    }
+    let mut session = LASTFM_SESSION.lock();
+    if let Some(session_key) = session.as_ref() {
+        return Ok(session_key.clone());
+    }
+    let (Some(username), Some(password)) = (
+        settings.lastfm_username.clone(),
+        settings.secret(LASTFM_PASSWORD, config_id),
+    ) else {
+        return Err("a session key or a username and password are needed".to_string());
+    };
+    let params = BTreeMap::from([
+        ("method", "auth.getMobileSession".to_string()),
+        ("username", username),
+        ("password", password),
+        ("api_key", api_key.to_string()),
+    ]);
+    let json = lastfm_call(params, secret)?;
+    let session_key = json["session"]["key"]
+        .as_str()
+        .ok_or("no session key in the auth.getMobileSession response")?
+        .to_string();
+    *session = Some(session_key.clone());
+    Ok(session_key)
+}
+
+fn lastfm_submit(
+    settings: &ScrobbleSettings,
+    config_id: &str,
+    track: &NowPlaying,
+    timestamp: Option<u64>,
+) -> Result<(), String> {
+    let api_key = settings.lastfm_api_key.clone().unwrap_or_default();
+    let secret = settings
+        .secret(LASTFM_SECRET, config_id)
+        .ok_or("the secret of the api key is needed")?;
+    let session_key = lastfm_session_key(settings, config_id, &api_key, &secret)?;
+    let method = if timestamp.is_some() {
+        "track.scrobble"
+    } else {
+        "track.updateNowPlaying"
+    };
+    let mut params = BTreeMap::from([
+        ("method", method.to_string()),
+        ("artist", track.artist.clone()),
+        ("track", track.title.clone()),
+        ("api_key", api_key),
+        ("sk", session_key),
+    ]);
+    if let Some(album) = &track.album {
+        params.insert("album", album.clone());
+    }
+    if let Some(duration) = track.duration_secs {
+        params.insert("duration", duration.to_string());
+    }
+    if let Some(timestamp) = timestamp {
+        params.insert("timestamp", timestamp.to_string());
+    }
+    lastfm_call(params, &secret).map(|_| ())
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// the MD5 digest (RFC 1321) of the data in lowercase hex, for the Last.fm signature
+#[must_use]
+pub fn md5_hex(data: &[u8]) -> String {
+    const SHIFTS: [[u32; 4]; 4] = [
+        [7, 12, 17, 22],
+        [5, 9, 14, 20],
+        [4, 11, 16, 23],
+        [6, 10, 15, 21],
+    ];
+    let k: Vec<u32> = (1..=64)
+        .map(|i| (f64::from(i).sin().abs() * 4_294_967_296.0) as u32)
+        .collect();
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64).wrapping_mul(8).to_le_bytes());
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    for block in message.chunks(64) {
+        let m: Vec<u32> = block
+            .chunks(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(k[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16][i % 4]));
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+    state
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_md5_hex() {
+        assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            md5_hex(b"The quick brown fox jumps over the lazy dog"),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+        // more than one block
+        assert_eq!(
+            md5_hex(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            ),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+
+    #[test]
+    fn test_should_scrobble() {
+        let secs = Duration::from_secs;
+        // too short
+        assert!(!should_scrobble(Some(secs(30)), secs(30)));
+        // half the duration
+        assert!(should_scrobble(Some(secs(200)), secs(100)));
+        assert!(!should_scrobble(Some(secs(200)), secs(99)));
+        // 4 minutes of a long track
+        assert!(should_scrobble(Some(secs(1200)), secs(240)));
+        assert!(!should_scrobble(Some(secs(1200)), secs(239)));
+        // no duration
+        assert!(should_scrobble(None, secs(240)));
+        assert!(!should_scrobble(None, secs(200)));
+    }
+
+    #[test]
+    fn test_lastfm_signed() {
+        let params = BTreeMap::from([
+            ("method", "track.scrobble".to_string()),
+            ("artist", "Miles Davis".to_string()),
+            ("api_key", "key".to_string()),
+        ]);
+        let signed = lastfm_signed(params, "secret");
+        assert_eq!(
+            signed["api_sig"],
+            md5_hex(b"api_keykeyartistMiles Davismethodtrack.scrobblesecret")
+        );
+        assert_eq!(signed["format"], "json");
+    }
+
+    #[test]
+    fn test_listenbrainz_payload() {
+        let track: NowPlaying = serde_json::from_str(
+            r#"{"artist": "Miles Davis", "title": "So What", "duration_secs": 545}"#,
+        )
+        .unwrap();
+        let listen = listenbrainz_payload(&track, Some(1_700_000_000));
+        assert_eq!(listen["listen_type"], "single");
+        assert_eq!(listen["payload"][0]["listened_at"], 1_700_000_000);
+        assert_eq!(
+            listen["payload"][0]["track_metadata"]["track_name"],
+            "So What"
+        );
+        assert!(listen["payload"][0]["track_metadata"]["release_name"].is_null());
+        let playing_now = listenbrainz_payload(&track, None);
+        assert_eq!(playing_now["listen_type"], "playing_now");
+        assert!(playing_now["payload"][0]["listened_at"].is_null());
+        assert!(!NowPlaying::default().is_track());
+    }
+}
@@ -0,0 +1,80 @@
+//! Credentials in the OS keyring (Windows Credential Manager, macOS Keychain,
+//! Secret Service on Linux) instead of plaintext in the config file.
+//!
+//! With `use_keyring = true` in the config file, the credentials that are in the config
+//! file are moved to the keyring when the config is read, and removed from the file.
+//! A credential in the config file always has priority, so that you can change it
+//! by putting the new value in the config file.
+//! Each config id has its own keyring entries.
+//! The keyring is only asked once for each credential, the SOAP requests of a renderer
+//! with credentials would otherwise query the keyring every time.
+//! The keyring is only available in a build with the `keyring` feature (the default).
+
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use std::sync::LazyLock;
+
+/// the keyring service name of all entries
+const KEYRING_SERVICE: &str = "swyh-rs";
+
+/// the credentials that were read from the keyring, by entry name
+static SECRETS: LazyLock<Mutex<HashMap<String, Option<String>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// the names of the credentials
+pub const MQTT_PASSWORD: &str = "mqtt_password";
+pub const RENDERER_PASSWORD: &str = "renderer_password";
+pub const RENDERER_TOKEN: &str = "renderer_token";
+pub const LISTENBRAINZ_TOKEN: &str = "listenbrainz_token";
+pub const LASTFM_SECRET: &str = "lastfm_secret";
+pub const LASTFM_SESSION_KEY: &str = "lastfm_session_key";
+pub const LASTFM_PASSWORD: &str = "lastfm_password";
+
+/// the name of a credential of a renderer
+#[must_use]
+pub fn renderer_secret(name: &str, renderer: &str) -> String {
+    format!("{name}:{renderer}")
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_set(entry: &str, secret: &str) -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, entry)
+        .and_then(|entry| entry.set_password(secret))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_set(_entry: &str, _secret: &str) -> Result<(), String> {
+    Err("swyh-rs was built without the keyring feature".to_string())
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_get(entry: &str) -> Option<String> {
+    keyring::Entry::new(KEYRING_SERVICE, entry)
+        .and_then(|entry| entry.get_password())
+        .ok()
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_get(_entry: &str) -> Option<String> {
+    None
+}
+
+/// store a credential in the keyring
+pub fn store_secret(name: &str, config_id: &str, secret: &str) -> Result<(), String> {
+    let entry = format!("{name}{config_id}");
+    keyring_set(&entry, secret)?;
+    SECRETS.lock().insert(entry, Some(secret.to_string()));
+    Ok(())
+}
+
+/// get a credential from the keyring, None if it isn't there
+#[must_use]
+pub fn get_secret(name: &str, config_id: &str) -> Option<String> {
+    let entry = format!("{name}{config_id}");
+    SECRETS
+        .lock()
+        .entry(entry.clone())
+        .or_insert_with(|| keyring_get(&entry))
+        .clone()
+}
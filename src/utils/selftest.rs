@@ -0,0 +1,238 @@
+#![cfg(feature = "cli")]
+//! `swyh-rs-cli selftest`: check an installation without a renderer, for distro packagers
+//! and for users with a problem.
+//!
+//! The audio devices are enumerated, the streaming server is started on an ephemeral port
+//! of the loopback interface, a FLAC stream of a test tone is received from it and checked
+//! with the stream check (see `stream_check.rs`): the frame CRCs and numbering and the
+//! continuity, the samples are not decoded. The test tone is written to the streaming
+//! clients directly, the audio capture and the `wave_reader` are not part of the test.
+//! Finally an SSDP socket is set up and sends an M-SEARCH. The first step that fails sets
+//! the exit code.
+
+use std::{
+    f32::consts::TAU,
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    enums::streaming::StreamingFormat,
+    globals::statics::{CLIENTS, CONFIG, MSGCHANNEL},
+    openhome::{diagnosis::DiagnosisStep, rendercontrol::WavData},
+    server::{query_params::stream_url, stream_check::verify_stream, streaming_server::run_server},
+    utils::{audiodevices::get_output_audio_devices, local_ip_address::get_interfaces},
+};
+
+/// the format of the test tone
+const SAMPLE_RATE: u32 = 44100;
+const CHANNELS: u16 = 2;
+/// the duration of the stream check
+const LOOPBACK_SECS: u64 = 3;
+/// the SSDP multicast address
+const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+
+/// the outcome of a selftest step, with the exit code when it failed
+struct SelfTestStep {
+    exit_code: i32,
+    steps: Vec<DiagnosisStep>,
+}
+
+fn check_audio_devices() -> Vec<DiagnosisStep> {
+    let devices = get_output_audio_devices();
+    let step = if devices.is_empty() {
+        DiagnosisStep::new(
+            "Audio devices",
+            false,
+            "no audio devices found, is the audio system (e.g. PipeWire or PulseAudio) running \
+            and does the user have access to it?"
+                .to_string(),
+        )
+    } else {
+        let names: Vec<&str> = devices.iter().map(|d| d.name()).collect();
+        DiagnosisStep::new(
+            "Audio devices",
+            true,
+            format!("found {}: {}", devices.len(), names.join(", ")),
+        )
+    };
+    vec![step]
+}
+
+/// bind an ephemeral port on the loopback interface, the port is free again when this returns
+fn check_server_bind() -> (Vec<DiagnosisStep>, Option<u16>) {
+    match TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).and_then(|l| l.local_addr()) {
+        Ok(addr) => (
+            vec![DiagnosisStep::new(
+                "Server bind",
+                true,
+                format!("bound {addr}"),
+            )],
+            Some(addr.port()),
+        ),
+        Err(e) => (
+            vec![DiagnosisStep::new(
+                "Server bind",
+                false,
+                format!("could not bind a TCP port on the loopback interface: {e}"),
+            )],
+            None,
+        ),
+    }
+}
+
+/// write a 1 kHz test tone to the streaming clients in real time until stopped
+fn feed_test_tone(stop: Arc<AtomicBool>) {
+    let _ = thread::Builder::new()
+        .name("selftest_tone".into())
+        .spawn(move || {
+            let start = Instant::now();
+            let mut frames = 0u64;
+            while !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(10));
+                let due = (start.elapsed().as_secs_f64() * f64::from(SAMPLE_RATE)) as u64;
+                let samples: Vec<f32> = (frames..due)
+                    .flat_map(|n| {
+                        // 1000 whole periods per second
+                        let t = (n % u64::from(SAMPLE_RATE)) as f32 / SAMPLE_RATE as f32;
+                        let s = (TAU * 1000.0 * t).sin() * 0.5;
+                        [s; CHANNELS as usize]
+                    })
+                    .collect();
+                frames = due;
+                CLIENTS.read().values().for_each(|c| c.write(&samples));
+            }
+        });
+}
+
+/// run the streaming server on the port and check a FLAC stream of a test tone
+fn check_stream_loopback(port: u16) -> Vec<DiagnosisStep> {
+    // a client must get the audio immediately
+    CONFIG.write().capture_on_demand = false;
+    let local_addr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let feedback_tx = MSGCHANNEL.read().0.clone();
+    let wd = WavData {
+        sample_format: cpal::SampleFormat::F32,
+        sample_rate: cpal::SampleRate(SAMPLE_RATE),
+        channels: CHANNELS,
+    };
+    let _ = thread::Builder::new()
+        .name("selftest_server".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || run_server(&local_addr, port, wd, &feedback_tx));
+    // wait until the server listens
+    let addr = SocketAddr::new(local_addr, port);
+    let listening = (0..50).any(|_| {
+        let ok = TcpStream::connect_timeout(&addr, Duration::from_millis(100)).is_ok();
+        if !ok {
+            thread::sleep(Duration::from_millis(100));
+        }
+        ok
+    });
+    if !listening {
+        return vec![DiagnosisStep::new(
+            "Stream loopback",
+            false,
+            format!("the streaming server does not listen on {addr}"),
+        )];
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    feed_test_tone(stop.clone());
+    let url = stream_url(&addr.to_string(), StreamingFormat::Flac, Some(16), None);
+    let mut steps = vec![DiagnosisStep::new(
+        "Stream loopback",
+        true,
+        format!("receiving {url} for {LOOPBACK_SECS} seconds"),
+    )];
+    steps.extend(verify_stream(&url, SAMPLE_RATE, 16, LOOPBACK_SECS));
+    stop.store(true, Ordering::Relaxed);
+    steps
+}
+
+/// set up an SSDP socket on the configured or the first network interface and send an M-SEARCH
+fn check_ssdp() -> Vec<DiagnosisStep> {
+    let interfaces = get_interfaces();
+    let configured = CONFIG.read().last_network.clone();
+    let ip = configured.filter(|ip| interfaces.contains(ip)).or_else(|| {
+        interfaces
+            .iter()
+            .find(|ip| !ip.starts_with("127."))
+            .cloned()
+    });
+    let Some(ip) = ip.and_then(|ip| ip.parse::<Ipv4Addr>().ok()) else {
+        return vec![DiagnosisStep::new(
+            "SSDP socket",
+            false,
+            "no IPv4 network interface found".to_string(),
+        )];
+    };
+    let search = "M-SEARCH * HTTP/1.1\r\nHost: 239.255.255.250:1900\r\nMan: \"ssdp:discover\"\r\n\
+        ST: urn:schemas-upnp-org:device:MediaRenderer:1\r\nMX: 1\r\n\r\n";
+    let result = UdpSocket::bind((ip, 0)).and_then(|socket| {
+        socket.set_broadcast(true)?;
+        socket.set_multicast_ttl_v4(2)?;
+        socket.join_multicast_v4(&SSDP_ADDR, &ip)?;
+        socket.send_to(search.as_bytes(), (SSDP_ADDR, 1900))
+    });
+    let step = match result {
+        Ok(_) => DiagnosisStep::new(
+            "SSDP socket",
+            true,
+            format!("sent an M-SEARCH to {SSDP_ADDR}:1900 from {ip}"),
+        ),
+        Err(e) => DiagnosisStep::new(
+            "SSDP socket",
+            false,
+            format!("SSDP multicast on {ip} failed: {e}, check the firewall and the routes"),
+        ),
+    };
+    vec![step]
+}
+
+/// Run the selftest and print the outcome of each step. Returns 0 if all steps passed,
+/// otherwise the exit code of the failed step: 1 for the audio devices, 2 for the server
+/// bind, 3 for the stream loopback and 4 for SSDP.
+#[must_use]
+pub fn run_selftest() -> i32 {
+    let (bind_steps, port) = check_server_bind();
+    let mut outcome = vec![
+        SelfTestStep {
+            exit_code: 1,
+            steps: check_audio_devices(),
+        },
+        SelfTestStep {
+            exit_code: 2,
+            steps: bind_steps,
+        },
+    ];
+    if let Some(port) = port {
+        outcome.push(SelfTestStep {
+            exit_code: 3,
+            steps: check_stream_loopback(port),
+        });
+    }
+    outcome.push(SelfTestStep {
+        exit_code: 4,
+        steps: check_ssdp(),
+    });
+    let mut exit_code = 0;
+    for test in &outcome {
+        for step in &test.steps {
+            println!("{step}");
+        }
+        if exit_code == 0 && test.steps.iter().any(|s| !s.ok) {
+            exit_code = test.exit_code;
+        }
+    }
+    if exit_code == 0 {
+        println!("Selftest passed");
+    } else {
+        println!("Selftest FAILED (exit code {exit_code})");
+    }
+    exit_code
+}
@@ -0,0 +1,258 @@
+//! Latency and glitch analytics of the streaming sessions, to compare settings when
+//! chasing intermittent dropouts.
+//!
+//! Every `ChannelStream` keeps the depth of its sample queue (the captured blocks waiting
+//! to be converted or FLAC encoded) and the periods of injected silence. When a session
+//! ends, a summary is logged and kept with the last sessions, that the streaming server
+//! serves as JSON on /stats/sessions.
+//!
+//! The silence is injected when no audio is captured within the capture timeout. Its
+//! periods are also counted live per client (/api/clients and the renderer buttons in the
+//! GUI) and per renderer, to tell capture starvation from a renderer that stops by itself.
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
+        LazyLock,
+    },
+    time::Duration,
+};
+
+/// the number of session summaries that are kept
+const MAX_SESSIONS: usize = 50;
+
+/// the summaries of the last sessions
+static SESSIONS: Mutex<VecDeque<SessionSummary>> = Mutex::new(VecDeque::new());
+/// the number of sessions per remote ip address since the start
+static CONNECTIONS: LazyLock<Mutex<HashMap<String, u32>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// the periods of injected silence and their total duration
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SilenceCount {
+    pub periods: u64,
+    pub msec: u64,
+}
+
+impl SilenceCount {
+    #[must_use]
+    pub fn secs(&self) -> f64 {
+        self.msec as f64 / 1000.0
+    }
+}
+
+impl std::ops::Add for SilenceCount {
+    type Output = SilenceCount;
+
+    fn add(self, other: SilenceCount) -> SilenceCount {
+        SilenceCount {
+            periods: self.periods + other.periods,
+            msec: self.msec + other.msec,
+        }
+    }
+}
+
+impl fmt::Display for SilenceCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} silence periods ({:.1} s)", self.periods, self.secs())
+    }
+}
+
+/// the statistics of a streaming session, shared by the clones of its `ChannelStream`
+#[derive(Debug, Default)]
+pub struct SessionStats {
+    queue_samples: AtomicU64,
+    queue_sum: AtomicU64,
+    queue_peak: AtomicU64,
+    in_silence: AtomicBool,
+    silence_periods: AtomicU64,
+    silence_msec: AtomicU64,
+    /// the duration of the current silence period
+    silence_run_msec: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+impl SessionStats {
+    /// record the number of captured blocks waiting in the queue
+    pub fn record_queue_depth(&self, depth: usize) {
+        let depth = depth as u64;
+        self.queue_samples.fetch_add(1, Relaxed);
+        self.queue_sum.fetch_add(depth, Relaxed);
+        self.queue_peak.fetch_max(depth, Relaxed);
+    }
+
+    /// record a block of injected silence, a new period starts after captured audio
+    pub fn record_silence(&self, msec: u32) {
+        if !self.in_silence.swap(true, Relaxed) {
+            self.silence_periods.fetch_add(1, Relaxed);
+        }
+        self.silence_msec.fetch_add(u64::from(msec), Relaxed);
+        self.silence_run_msec.fetch_add(u64::from(msec), Relaxed);
+    }
+
+    /// record that captured audio is streamed again
+    pub fn record_audio(&self) {
+        self.in_silence.store(false, Relaxed);
+        self.silence_run_msec.store(0, Relaxed);
+    }
+
+    /// record the bytes read by the HTTP response
+    pub fn record_bytes(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Relaxed);
+    }
+
+    /// is silence being injected now
+    #[must_use]
+    pub fn in_silence(&self) -> bool {
+        self.in_silence.load(Relaxed)
+    }
+
+    /// the bytes sent so far
+    #[must_use]
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Relaxed)
+    }
+
+    /// the silence injected so far
+    #[must_use]
+    pub fn silence(&self) -> SilenceCount {
+        SilenceCount {
+            periods: self.silence_periods.load(Relaxed),
+            msec: self.silence_msec.load(Relaxed),
+        }
+    }
+
+    /// how long silence is being injected without a break, zero while audio is streamed
+    #[must_use]
+    pub fn silence_run(&self) -> Duration {
+        Duration::from_millis(self.silence_run_msec.load(Relaxed))
+    }
+
+    /// the summary of the session
+    #[must_use]
+    pub fn summary(
+        &self,
+        remote_ip: &str,
+        audio_format: &str,
+        duration: Duration,
+        bytes: u64,
+        reconnects: u32,
+    ) -> SessionSummary {
+        let samples = self.queue_samples.load(Relaxed);
+        SessionSummary {
+            remote_ip: remote_ip.to_string(),
+            audio_format: audio_format.to_string(),
+            duration_secs: duration.as_secs_f64(),
+            bytes,
+            avg_queue_depth: if samples > 0 {
+                self.queue_sum.load(Relaxed) as f64 / samples as f64
+            } else {
+                0.0
+            },
+            peak_queue_depth: self.queue_peak.load(Relaxed),
+            silence_periods: self.silence_periods.load(Relaxed),
+            silence_secs: self.silence_msec.load(Relaxed) as f64 / 1000.0,
+            reconnects,
+        }
+    }
+}
+
+/// the summary of a finished streaming session
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionSummary {
+    pub remote_ip: String,
+    pub audio_format: String,
+    pub duration_secs: f64,
+    pub bytes: u64,
+    pub avg_queue_depth: f64,
+    pub peak_queue_depth: u64,
+    pub silence_periods: u64,
+    pub silence_secs: f64,
+    /// the number of earlier sessions of this ip address since the start
+    pub reconnects: u32,
+}
+
+impl fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Session summary for {}: {}, {:.1} s, {} bytes, queue depth avg {:.1} peak {}, \
+            {} silence periods ({:.1} s), {} reconnects",
+            self.remote_ip,
+            self.audio_format,
+            self.duration_secs,
+            self.bytes,
+            self.avg_queue_depth,
+            self.peak_queue_depth,
+            self.silence_periods,
+            self.silence_secs,
+            self.reconnects
+        )
+    }
+}
+
+/// register a new session of an ip address, returns the number of earlier sessions
+pub fn register_session(remote_ip: &str) -> u32 {
+    let mut connections = CONNECTIONS.lock();
+    let count = connections.entry(remote_ip.to_string()).or_default();
+    *count += 1;
+    *count - 1
+}
+
+/// keep the summary of a finished session
+pub fn store_summary(summary: SessionSummary) {
+    let mut sessions = SESSIONS.lock();
+    if sessions.len() == MAX_SESSIONS {
+        sessions.pop_front();
+    }
+    sessions.push_back(summary);
+}
+
+/// the summaries of the last sessions as JSON
+#[must_use]
+pub fn sessions_json() -> String {
+    serde_json::to_string_pretty(&*SESSIONS.lock()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_stats() {
+        let stats = SessionStats::default();
+        stats.record_queue_depth(1);
+        stats.record_queue_depth(5);
+        stats.record_silence(250);
+        stats.record_silence(250);
+        stats.record_audio();
+        stats.record_silence(100);
+        assert!(stats.in_silence());
+        assert_eq!(stats.silence_run(), Duration::from_millis(100));
+        assert_eq!(stats.silence().to_string(), "2 silence periods (0.6 s)");
+        stats.record_bytes(1000);
+        assert_eq!(stats.bytes_sent(), 1000);
+        let summary = stats.summary(
+            "192.168.1.20",
+            "Flac 16 bit 44100 Hz",
+            Duration::from_secs(60),
+            1000,
+            2,
+        );
+        assert_eq!(summary.avg_queue_depth, 3.0);
+        assert_eq!(summary.peak_queue_depth, 5);
+        assert_eq!(summary.silence_periods, 2);
+        assert_eq!(summary.silence_secs, 0.6);
+        assert_eq!(
+            summary.to_string(),
+            "Session summary for 192.168.1.20: Flac 16 bit 44100 Hz, 60.0 s, 1000 bytes, \
+            queue depth avg 3.0 peak 5, 2 silence periods (0.6 s), 2 reconnects"
+        );
+        assert_eq!(register_session("10.0.0.1"), 0);
+        assert_eq!(register_session("10.0.0.1"), 1);
+    }
+}
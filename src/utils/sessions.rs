@@ -0,0 +1,125 @@
+//! Session presets: a named set of renderers with their volume, the streaming format and
+//! the loudness option, e.g. "Evening jazz" streaming 24 bit FLAC to the Living Room at
+//! volume 35 and the Kitchen at volume 25.
+//!
+//! A session is recalled from the Sessions menu of the GUI or with the `-Y` (--session)
+//! option of the CLI: the streaming format and the loudness option are applied and all
+//! renderers of the session are started with their volume in one action. The renderers
+//! are matched like the MQTT play and stop commands, by (part of) their name or their ip
+//! address. The loudness option is switched without restarting the streams.
+
+use crate::{
+    dsp::chain::set_loudness,
+    enums::messages::MqttCommand,
+    utils::configuration::{Configuration, SessionPreset},
+};
+
+/// find a session by its name, an exact match is preferred over a case insensitive one
+#[must_use]
+pub fn find_session(config: &Configuration, name: &str) -> Option<SessionPreset> {
+    config
+        .sessions
+        .iter()
+        .find(|s| s.name == name)
+        .or_else(|| {
+            config
+                .sessions
+                .iter()
+                .find(|s| s.name.eq_ignore_ascii_case(name))
+        })
+        .cloned()
+}
+
+/// apply the streaming format of a session to the config, returns true if it changed
+pub fn apply_session_format(config: &mut Configuration, session: &SessionPreset) -> bool {
+    config.set_streaming_format(session.streaming_format, session.bits_per_sample)
+}
+
+/// apply the loudness option of a session to the DSP chain, returns true if it changed
+pub fn apply_session_loudness(config: &mut Configuration, session: &SessionPreset) -> bool {
+    session
+        .loudness
+        .is_some_and(|on| set_loudness(&mut config.dsp_chain, on))
+}
+
+/// the commands that start the renderers of a session, followed by their volume
+#[must_use]
+pub fn session_commands(session: &SessionPreset) -> Vec<MqttCommand> {
+    let play = session
+        .renderers
+        .iter()
+        .filter(|m| !m.renderer.is_empty())
+        .map(|m| MqttCommand::Play(m.renderer.clone()));
+    let volume = session.renderers.iter().filter_map(|m| {
+        m.volume
+            .filter(|_| !m.renderer.is_empty())
+            .map(|vol| MqttCommand::Volume(m.renderer.clone(), vol.min(100)))
+    });
+    play.chain(volume).collect()
+}
+
+/// add a session to the config, replacing a session with the same name
+pub fn save_session(config: &mut Configuration, session: SessionPreset) {
+    if let Some(existing) = config.sessions.iter_mut().find(|s| s.name == session.name) {
+        *existing = session;
+    } else {
+        config.sessions.push(session);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{enums::streaming::StreamingFormat, utils::configuration::SessionMember};
+
+    fn evening_jazz() -> SessionPreset {
+        SessionPreset {
+            name: "Evening jazz".to_string(),
+            renderers: vec![
+                SessionMember {
+                    renderer: "Living Room".to_string(),
+                    volume: Some(35),
+                },
+                SessionMember {
+                    renderer: "Kitchen".to_string(),
+                    volume: None,
+                },
+            ],
+            streaming_format: Some(StreamingFormat::Flac),
+            bits_per_sample: Some(24),
+            loudness: Some(true),
+        }
+    }
+
+    #[test]
+    fn test_session_commands() {
+        assert_eq!(
+            session_commands(&evening_jazz()),
+            vec![
+                MqttCommand::Play("Living Room".to_string()),
+                MqttCommand::Play("Kitchen".to_string()),
+                MqttCommand::Volume("Living Room".to_string(), 35),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sessions_config() {
+        let mut config = Configuration::new();
+        config.streaming_format = Some(StreamingFormat::Lpcm);
+        config.bits_per_sample = Some(16);
+        save_session(&mut config, evening_jazz());
+        let mut replaced = evening_jazz();
+        replaced.bits_per_sample = None;
+        save_session(&mut config, replaced);
+        assert_eq!(config.sessions.len(), 1);
+        let session = find_session(&config, "evening JAZZ").unwrap();
+        assert!(apply_session_format(&mut config, &session));
+        assert_eq!(config.streaming_format, Some(StreamingFormat::Flac));
+        assert_eq!(config.bits_per_sample, Some(16));
+        assert!(!apply_session_format(&mut config, &session));
+        assert!(apply_session_loudness(&mut config, &session));
+        assert!(!apply_session_loudness(&mut config, &session));
+        assert!(find_session(&config, "Morning news").is_none());
+    }
+}
@@ -0,0 +1,151 @@
+//! The orderly shutdown of swyh-rs, shared by the GUI, the CLI and /api/shutdown.
+//!
+//! The playing renderers are faded out and stopped first, so that they don't keep
+//! retrying the stream, then they get some time to close their streaming connections.
+//! Finally the configuration is written and the log is flushed, and the exit code tells
+//! if all connections were closed.
+//!
+//! /api/shutdown is disabled unless a `shutdown_token` is configured, and a request needs it
+//! in an `Authorization: Bearer {token}` header, which a web page can't send to swyh-rs.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    globals::statics::{CLIENTS, CONFIG},
+    openhome::rendercontrol::Renderer,
+    utils::ui_logger::ui_log,
+};
+
+/// the exit code if all streaming connections were closed
+pub const EXIT_OK: i32 = 0;
+/// the exit code if streaming connections were still open after the timeout
+pub const EXIT_CLIENTS_TIMEOUT: i32 = 3;
+/// the time the renderers get to close their streaming connections
+const CLIENTS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// why a /api/shutdown request is refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownRefused {
+    /// there is no `shutdown_token`
+    Disabled,
+    /// the request has no or another token
+    Unauthorized,
+}
+
+impl ShutdownRefused {
+    /// the HTTP status code and the message of the response
+    #[must_use]
+    pub fn response(self) -> (u16, &'static str) {
+        match self {
+            ShutdownRefused::Disabled => (
+                403,
+                "Shutdown is disabled, set shutdown_token in the config file",
+            ),
+            ShutdownRefused::Unauthorized => (401, "Use Authorization: Bearer {shutdown_token}"),
+        }
+    }
+}
+
+/// check the Authorization header of a /api/shutdown request against the `shutdown_token`
+pub fn authorize_shutdown(
+    authorization: Option<&str>,
+    token: Option<&str>,
+) -> Result<(), ShutdownRefused> {
+    let token = token
+        .filter(|t| !t.is_empty())
+        .ok_or(ShutdownRefused::Disabled)?;
+    let given = authorization
+        .and_then(|a| a.trim().strip_prefix("Bearer "))
+        .map(str::trim)
+        .ok_or(ShutdownRefused::Unauthorized)?;
+    // compare all bytes, so that the time doesn't tell how much of the token is right
+    let same = given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0;
+    if same {
+        Ok(())
+    } else {
+        Err(ShutdownRefused::Unauthorized)
+    }
+}
+
+/// stop the playing renderers and wait for their streaming connections to close,
+/// flush the config and the log, and return the exit code
+pub fn graceful_shutdown(playing: &[Renderer]) -> i32 {
+    ui_log(&format!(
+        "Shutting down, stopping {} renderer(s)",
+        playing.len()
+    ));
+    // stop them all at the same time, each one fades out first
+    thread::scope(|s| {
+        for r in playing {
+            s.spawn(move || {
+                ui_log(&format!("Shutdown: stopping streaming to {}", r.dev_name));
+                r.stop_play(&ui_log);
+            });
+        }
+    });
+    let exit_code = if wait_for_clients(CLIENTS_TIMEOUT) {
+        ui_log("Shutdown: no active HTTP streaming connections");
+        EXIT_OK
+    } else {
+        ui_log(&format!(
+            "*W*W*> Shutdown: time-out waiting for {} HTTP streaming connection(s) to close",
+            CLIENTS.read().len()
+        ));
+        EXIT_CLIENTS_TIMEOUT
+    };
+    if let Err(e) = CONFIG.read().update_config() {
+        ui_log(&format!("*E*E*> Shutdown: could not write the config: {e}"));
+    }
+    log::logger().flush();
+    exit_code
+}
+
+/// wait until all streaming connections are closed, false on a time-out
+fn wait_for_clients(timeout: Duration) -> bool {
+    let start = Instant::now();
+    while !CLIENTS.read().is_empty() {
+        if start.elapsed() >= timeout {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize_shutdown() {
+        assert_eq!(
+            authorize_shutdown(Some("Bearer abc"), None),
+            Err(ShutdownRefused::Disabled)
+        );
+        assert_eq!(
+            authorize_shutdown(Some("Bearer abc"), Some("")),
+            Err(ShutdownRefused::Disabled)
+        );
+        assert_eq!(
+            authorize_shutdown(None, Some("abc")),
+            Err(ShutdownRefused::Unauthorized)
+        );
+        assert_eq!(
+            authorize_shutdown(Some("Bearer abd"), Some("abc")),
+            Err(ShutdownRefused::Unauthorized)
+        );
+        assert_eq!(
+            authorize_shutdown(Some("Basic abc"), Some("abc")),
+            Err(ShutdownRefused::Unauthorized)
+        );
+        assert_eq!(authorize_shutdown(Some("Bearer abc"), Some("abc")), Ok(()));
+    }
+}
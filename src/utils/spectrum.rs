@@ -0,0 +1,173 @@
+//! A mini spectrum analyzer for the RMS monitor, so that you can see at a glance whether the
+//! capture source is alive and has the full bandwidth.
+//!
+//! An FFT over the last captured samples (mixed to mono) is summed into logarithmically
+//! spaced bands between 30 Hz and 20 kHz, about 15 times per second. The band levels are in
+//! dBFS, scaled between 0 (-80 dBFS or lower) and 1 (0 dBFS) for the GUI.
+
+use std::f32::consts::PI;
+
+/// the number of bands of the analyzer
+pub const SPECTRUM_BANDS: usize = 20;
+/// the refresh rate of the analyzer
+const UPDATES_PER_SEC: u32 = 15;
+const FFT_SIZE: usize = 2048;
+/// the lower end of the scale in dBFS
+const FLOOR_DB: f32 = -80.0;
+/// the frequency range of the bands
+const LOWEST_HZ: f32 = 30.0;
+const HIGHEST_HZ: f32 = 20_000.0;
+
+pub struct SpectrumAnalyzer {
+    // the Hann window
+    window: Vec<f32>,
+    // the last FFT_SIZE mono samples
+    mono: Vec<f32>,
+    // the FFT bins of each band
+    bands: Vec<(usize, usize)>,
+    frames_per_update: usize,
+    frames: usize,
+}
+
+impl SpectrumAnalyzer {
+    #[must_use]
+    pub fn new(sample_rate: u32) -> SpectrumAnalyzer {
+        SpectrumAnalyzer {
+            window: (0..FFT_SIZE)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos())
+                .collect(),
+            mono: Vec::with_capacity(FFT_SIZE * 2),
+            bands: band_bins(sample_rate),
+            frames_per_update: (sample_rate / UPDATES_PER_SEC) as usize,
+            frames: 0,
+        }
+    }
+
+    /// add interleaved stereo samples, returns the band levels at the refresh rate
+    pub fn push(&mut self, samples: &[f32]) -> Option<Vec<f32>> {
+        self.mono
+            .extend(samples.chunks_exact(2).map(|f| (f[0] + f[1]) / 2.0));
+        if self.mono.len() > FFT_SIZE {
+            self.mono.drain(..self.mono.len() - FFT_SIZE);
+        }
+        self.frames += samples.len() / 2;
+        if self.frames < self.frames_per_update || self.mono.len() < FFT_SIZE {
+            return None;
+        }
+        self.frames = 0;
+        Some(self.levels())
+    }
+
+    // the scaled peak level of each band
+    fn levels(&self) -> Vec<f32> {
+        let mut re: Vec<f32> = self
+            .mono
+            .iter()
+            .zip(&self.window)
+            .map(|(s, w)| s * w)
+            .collect();
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+        // a full scale sine has a magnitude of FFT_SIZE / 4 with the Hann window
+        let scale = 4.0 / FFT_SIZE as f32;
+        self.bands
+            .iter()
+            .map(|&(lo, hi)| {
+                let peak = (lo..hi)
+                    .map(|i| (re[i] * re[i] + im[i] * im[i]).sqrt())
+                    .fold(0.0, f32::max)
+                    * scale;
+                let db = 20.0 * peak.max(1e-9).log10();
+                ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// the FFT bin range of each band
+fn band_bins(sample_rate: u32) -> Vec<(usize, usize)> {
+    let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+    let highest = (sample_rate as f32 / 2.0).min(HIGHEST_HZ);
+    let ratio = (highest / LOWEST_HZ).powf(1.0 / SPECTRUM_BANDS as f32);
+    (0..SPECTRUM_BANDS)
+        .map(|band| {
+            let lo = LOWEST_HZ * ratio.powi(band as i32);
+            let lo_bin = ((lo / bin_hz) as usize).max(1);
+            let hi_bin = (((lo * ratio) / bin_hz) as usize)
+                .max(lo_bin + 1)
+                .min(FFT_SIZE / 2);
+            (lo_bin, hi_bin)
+        })
+        .collect()
+}
+
+/// in-place radix-2 FFT, the length must be a power of 2
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    // butterflies
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spectrum() {
+        let sample_rate = 44100;
+        let mut analyzer = SpectrumAnalyzer::new(sample_rate);
+        // silence
+        let silence = vec![0.0f32; 6 * FFT_SIZE];
+        let levels = analyzer.push(&silence).unwrap();
+        assert_eq!(levels.len(), SPECTRUM_BANDS);
+        assert!(levels.iter().all(|l| *l == 0.0));
+        // a 1 kHz sine at -6 dBFS shows in its band only
+        let sine: Vec<f32> = (0..3 * FFT_SIZE)
+            .flat_map(|i| {
+                let s = 0.5 * (2.0 * PI * 1000.0 * i as f32 / sample_rate as f32).sin();
+                [s, s]
+            })
+            .collect();
+        let levels = analyzer.push(&sine).unwrap();
+        let band = analyzer
+            .bands
+            .iter()
+            .position(|&(lo, hi)| {
+                let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+                (lo as f32 * bin_hz..hi as f32 * bin_hz).contains(&1000.0)
+            })
+            .unwrap();
+        assert!((levels[band] - 74.0 / 80.0).abs() < 0.05);
+        assert!(levels[0] < 0.1 && levels[SPECTRUM_BANDS - 1] < 0.1);
+    }
+}
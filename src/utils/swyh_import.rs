@@ -0,0 +1,234 @@
+//! Import the settings of the original Stream What You Hear (SWYH, the .NET application)
+//! when swyh-rs creates a new config, to smooth the migration from the abandoned upstream
+//! project.
+//!
+//! SWYH only runs on Windows, and keeps its settings in a .NET `user.config` file in
+//! `%LOCALAPPDATA%\<company>\SWYH.exe_Url_<hash>\<version>\user.config`, there is no
+//! registry or ini file. The HTTP port, the stream format, the bit depth, the audio device and
+//! the ip address are mapped to the swyh-rs configuration, the other settings have no swyh-rs
+//! equivalent. The setting names have not been checked against every SWYH version, so the
+//! settings that are not imported are logged by name.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use stringreader::StringReader;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{enums::streaming::StreamingFormat, utils::configuration::Configuration};
+
+/// the settings of the newest SWYH `user.config` in the local application data, if any,
+/// always None on other platforms than Windows
+#[must_use]
+pub fn find_swyh_settings() -> Option<(PathBuf, Vec<(String, String)>)> {
+    if !cfg!(target_os = "windows") {
+        return None;
+    }
+    let local_data = dirs::data_local_dir()?;
+    let path = newest_user_config(&local_data)?;
+    let xml = fs::read_to_string(&path).ok()?;
+    Some((path, parse_user_config(&xml)))
+}
+
+/// the subdirectories of a directory
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// the most recently written `<company>\SWYH*\<version>\user.config`
+fn newest_user_config(local_data: &Path) -> Option<PathBuf> {
+    subdirs(local_data)
+        .iter()
+        .flat_map(|company| subdirs(company))
+        .filter(|app| {
+            app.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.to_lowercase().starts_with("swyh"))
+        })
+        .flat_map(|app| subdirs(&app))
+        .map(|version| version.join("user.config"))
+        .filter(|f| f.is_file())
+        .max_by_key(|f| {
+            f.metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH)
+        })
+}
+
+/// the `<setting name="...">` values of a .NET `user.config`
+fn parse_user_config(xml: &str) -> Vec<(String, String)> {
+    let parser = EventReader::new(StringReader::new(xml));
+    let mut settings = Vec::new();
+    let mut name: Option<String> = None;
+    let mut in_value = false;
+    for e in parser {
+        match e {
+            Ok(XmlEvent::StartElement {
+                name: element,
+                attributes,
+                ..
+            }) => match element.local_name.as_str() {
+                "setting" => {
+                    name = attributes
+                        .iter()
+                        .find(|a| a.name.local_name == "name")
+                        .map(|a| a.value.clone());
+                }
+                "value" => in_value = true,
+                _ => (),
+            },
+            Ok(XmlEvent::Characters(text)) if in_value => {
+                if let Some(name) = name.take() {
+                    settings.push((name, text.trim().to_string()));
+                }
+            }
+            Ok(XmlEvent::EndElement { name: element }) => {
+                if element.local_name == "value" {
+                    in_value = false;
+                }
+            }
+            Err(_) => break,
+            _ => (),
+        }
+    }
+    settings
+}
+
+/// the setting name without case, underscores and spaces
+fn normalized(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// the swyh-rs streaming format for a SWYH stream format, MP3 becomes FLAC
+fn streaming_format(swyh_format: &str) -> Option<StreamingFormat> {
+    let format = swyh_format.to_lowercase();
+    if format.contains("mp3") || format.contains("flac") {
+        Some(StreamingFormat::Flac)
+    } else if format.contains("wav") {
+        Some(StreamingFormat::Wav)
+    } else if format.contains("pcm") || format.contains("l16") {
+        Some(StreamingFormat::Lpcm)
+    } else {
+        None
+    }
+}
+
+/// Apply the SWYH settings to a new configuration, returns a log message for each setting
+/// that was imported, and one with the names of the settings that were not.
+pub fn apply_swyh_settings(
+    config: &mut Configuration,
+    settings: &[(String, String)],
+) -> Vec<String> {
+    let mut imported = Vec::new();
+    let mut ignored = Vec::new();
+    for (name, value) in settings {
+        if value.is_empty() {
+            continue;
+        }
+        let count = imported.len();
+        match normalized(name).as_str() {
+            "httpport" | "port" => {
+                if let Ok(port) = value.parse::<u16>() {
+                    config.server_port = Some(port);
+                    imported.push(format!("Imported SWYH server port {port}"));
+                }
+            }
+            "streamformat" | "streamingformat" | "audioformat" | "format" => {
+                if let Some(format) = streaming_format(value) {
+                    config.streaming_format = Some(format);
+                    imported.push(format!("Imported SWYH stream format {value} as {format}"));
+                }
+            }
+            "bitspersample" | "bitdepth" => {
+                if let Ok(bits @ (16 | 24)) = value.parse::<u16>() {
+                    config.bits_per_sample = Some(bits);
+                    imported.push(format!("Imported SWYH {bits} bits per sample"));
+                }
+            }
+            "audiodevice" | "capturedevice" | "recordingdevice" | "audioinput" | "device" => {
+                config.sound_source = Some(value.clone());
+                config.sound_source_index = None;
+                imported.push(format!("Imported SWYH audio device {value}"));
+            }
+            "ipaddress" | "ip" | "localip" => {
+                config.last_network = Some(value.clone());
+                imported.push(format!("Imported SWYH ip address {value}"));
+            }
+            _ => (),
+        }
+        if imported.len() == count {
+            ignored.push(name.as_str());
+        }
+    }
+    if !ignored.is_empty() {
+        imported.push(format!(
+            "SWYH settings not imported: {}",
+            ignored.join(", ")
+        ));
+    }
+    imported
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swyh_import() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+            <configuration>
+                <userSettings>
+                    <SWYH.Properties.Settings>
+                        <setting name="HTTPPort" serializeAs="String">
+                            <value>5901</value>
+                        </setting>
+                        <setting name="StreamFormat" serializeAs="String">
+                            <value>MP3</value>
+                        </setting>
+                        <setting name="BitsPerSample" serializeAs="String">
+                            <value>24</value>
+                        </setting>
+                        <setting name="Audio_Device" serializeAs="String">
+                            <value>Speakers (Realtek High Definition Audio)</value>
+                        </setting>
+                        <setting name="IPAddress" serializeAs="String">
+                            <value />
+                        </setting>
+                        <setting name="MP3Bitrate" serializeAs="String">
+                            <value>320</value>
+                        </setting>
+                    </SWYH.Properties.Settings>
+                </userSettings>
+            </configuration>"#;
+        let settings = parse_user_config(xml);
+        assert_eq!(settings.len(), 5);
+        assert_eq!(settings[0], ("HTTPPort".to_string(), "5901".to_string()));
+        let mut config = Configuration::new();
+        config.server_port = None;
+        let imported = apply_swyh_settings(&mut config, &settings);
+        assert_eq!(imported.len(), 5);
+        assert_eq!(imported[4], "SWYH settings not imported: MP3Bitrate");
+        assert_eq!(config.server_port, Some(5901));
+        assert_eq!(config.streaming_format, Some(StreamingFormat::Flac));
+        assert_eq!(config.bits_per_sample, Some(24));
+        assert_eq!(
+            config.sound_source.as_deref(),
+            Some("Speakers (Realtek High Definition Audio)")
+        );
+        assert_eq!(config.last_network, None);
+    }
+}
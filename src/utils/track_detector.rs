@@ -0,0 +1,168 @@
+//! Heuristic track boundary detection for sources without metadata (e.g. vinyl rips).
+//!
+//! A silence of at least the configured gap length followed by audio starts a new track.
+//! The `wave_reader` feeds the captured samples to the `TrackDetector`, and the track changes
+//! are handled (logged, hook called) in a separate thread so that the audio capture is never blocked.
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::{
+    sync::LazyLock,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{hooks::run_hook, ui_logger::ui_log};
+use crate::globals::statics::CONFIG;
+
+/// samples below this level (-60 dB) count as silence
+const SILENCE_LEVEL: f32 = 0.001;
+
+/// the channel from the audio capture to the track change handler
+static TRACK_CHANNEL: LazyLock<(Sender<TrackChange>, Receiver<TrackChange>)> =
+    LazyLock::new(unbounded);
+
+/// a detected track boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackChange {
+    /// the number of the new track, starting at 1
+    pub track: u32,
+    /// the time the new track started
+    pub timestamp: SystemTime,
+    /// the duration of the previous track, including the silence gap
+    pub previous_duration: Duration,
+}
+
+impl TrackChange {
+    /// the start of the track in milliseconds since the unix epoch
+    #[must_use]
+    pub fn epoch_millis(&self) -> u128 {
+        self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis()
+    }
+}
+
+/// detects track boundaries from silence gaps in the captured samples
+pub struct TrackDetector {
+    gap_samples: usize,
+    samples_per_sec: usize,
+    silent_samples: usize,
+    // the stream duration counter of the current track, 0 if no track started yet
+    track_samples: usize,
+    track: u32,
+    tx: Sender<TrackChange>,
+}
+
+impl TrackDetector {
+    /// a new detector, if track detection is enabled (a gap length > 0)
+    #[must_use]
+    pub fn new(sample_rate: u32, channels: u16, gap_msec: u32) -> Option<TrackDetector> {
+        if gap_msec == 0 {
+            return None;
+        }
+        let samples_per_sec = sample_rate as usize * usize::from(channels);
+        Some(TrackDetector {
+            gap_samples: samples_per_sec * gap_msec as usize / 1000,
+            samples_per_sec,
+            silent_samples: 0,
+            track_samples: 0,
+            track: 0,
+            tx: TRACK_CHANNEL.0.clone(),
+        })
+    }
+
+    /// process the next captured samples and report a new track when the audio resumes
+    /// after a long enough silence
+    pub fn process(&mut self, samples: &[f32]) -> Option<TrackChange> {
+        if samples.iter().all(|s| s.abs() < SILENCE_LEVEL) {
+            self.silent_samples += samples.len();
+            if self.track_samples > 0 {
+                self.track_samples += samples.len();
+            }
+            return None;
+        }
+        let new_track = self.track == 0 || self.silent_samples >= self.gap_samples;
+        self.silent_samples = 0;
+        if !new_track {
+            self.track_samples += samples.len();
+            return None;
+        }
+        let change = TrackChange {
+            track: self.track + 1,
+            timestamp: SystemTime::now(),
+            previous_duration: Duration::from_millis(
+                (self.track_samples * 1000 / self.samples_per_sec) as u64,
+            ),
+        };
+        self.track = change.track;
+        // reset the stream duration counter
+        self.track_samples = samples.len();
+        let _ = self.tx.send(change);
+        Some(change)
+    }
+}
+
+/// Start the thread that handles the detected track changes: log them
+/// and call the configured hook (an HTTP callback or a shell command).
+pub fn run_track_change_handler() {
+    let rx = TRACK_CHANNEL.1.clone();
+    let _ = thread::Builder::new()
+        .name("track_changes".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(move || {
+            while let Ok(change) = rx.recv() {
+                if change.track > 1 {
+                    ui_log(&format!(
+                        "Track {} started, the previous track lasted {} secs",
+                        change.track,
+                        change.previous_duration.as_secs()
+                    ));
+                } else {
+                    ui_log("Track 1 started");
+                }
+                let hook = CONFIG.read().track_change_hook.clone();
+                if let Some(hook) = hook {
+                    run_hook(
+                        &hook,
+                        vec![
+                            ("track", change.track.to_string()),
+                            ("timestamp", change.epoch_millis().to_string()),
+                        ],
+                    );
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_track_detector() {
+        // 1000 samples per second, 500 msec gap
+        assert!(TrackDetector::new(500, 2, 0).is_none());
+        let mut td = TrackDetector::new(500, 2, 500).unwrap();
+        let audio = vec![0.5f32; 100];
+        let silence = vec![0f32; 100];
+        // leading silence is not a track
+        assert!(td.process(&silence).is_none());
+        assert_eq!(td.process(&audio).map(|c| c.track), Some(1));
+        for _ in 0..9 {
+            assert!(td.process(&audio).is_none());
+        }
+        // a short gap is not a track boundary
+        for _ in 0..4 {
+            assert!(td.process(&silence).is_none());
+        }
+        assert!(td.process(&audio).is_none());
+        // but a long one is
+        for _ in 0..5 {
+            assert!(td.process(&silence).is_none());
+        }
+        let change = td.process(&audio).unwrap();
+        assert_eq!(change.track, 2);
+        assert_eq!(change.previous_duration, Duration::from_millis(2000));
+    }
+}
@@ -0,0 +1,161 @@
+//! Check for a newer swyh-rs release on GitHub at startup, if `check_updates = true` in the
+//! config file (off by default).
+//!
+//! A newer release is logged, and shown as a desktop notification with the
+//! `UpdateAvailable` notify event. The changelog lines of the newer releases that mention
+//! the configuration or a breaking change are logged too, so that you know what to check
+//! before upgrading.
+
+use serde::Deserialize;
+use std::{thread, time::Duration};
+
+use crate::{
+    globals::statics::{APP_VERSION, CONFIG},
+    server::ssdp_announcer::http_user_agent,
+    utils::{
+        notify::{notify, NotifyEvent},
+        ui_logger::ui_log,
+    },
+};
+
+/// the GitHub API url of the swyh-rs releases
+const RELEASES_URL: &str = "https://api.github.com/repos/dheijl/swyh-rs/releases";
+
+/// a release as returned by the GitHub API
+#[derive(Debug, Clone, Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    html_url: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+}
+
+/// the numbers of a version like "1.12.4" or "v1.12.4-beta", for comparing
+fn version_numbers(version: &str) -> Vec<u32> {
+    version
+        .trim()
+        .trim_start_matches(['v', 'V'])
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map_while(|n| n.parse().ok())
+        .collect()
+}
+
+/// the published releases that are newer than the version, newest first
+fn newer_releases(releases: &[Release], version: &str) -> Vec<Release> {
+    let current = version_numbers(version);
+    let mut newer: Vec<Release> = releases
+        .iter()
+        .filter(|r| !r.draft && !r.prerelease && version_numbers(&r.tag_name) > current)
+        .cloned()
+        .collect();
+    newer.sort_by_key(|r| std::cmp::Reverse(version_numbers(&r.tag_name)));
+    newer
+}
+
+/// the changelog lines of a release that mention the configuration or a breaking change
+fn config_changes(release: &Release) -> Vec<String> {
+    release
+        .body
+        .as_deref()
+        .unwrap_or_default()
+        .lines()
+        .map(|l| l.trim().trim_start_matches(['-', '*']).trim())
+        .filter(|l| {
+            let l = l.to_lowercase();
+            l.contains("config") || l.contains("breaking")
+        })
+        .map(String::from)
+        .collect()
+}
+
+fn get_releases() -> Result<Vec<Release>, String> {
+    let resp = attohttpc::get(RELEASES_URL)
+        .header("User-Agent", http_user_agent())
+        .header("Accept", "application/vnd.github+json")
+        .timeout(Duration::from_secs(10))
+        .send()
+        .map_err(|e| e.to_string())?;
+    if !resp.is_success() {
+        return Err(format!("HTTP status {}", resp.status()));
+    }
+    let json = resp.text().map_err(|e| e.to_string())?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+fn check_updates() {
+    let releases = match get_releases() {
+        Ok(releases) => releases,
+        Err(e) => {
+            ui_log(&format!("*W*W*> Could not check for updates: {e}"));
+            return;
+        }
+    };
+    let newer = newer_releases(&releases, APP_VERSION);
+    let Some(latest) = newer.first() else {
+        ui_log(&format!("swyh-rs {APP_VERSION} is up to date"));
+        return;
+    };
+    let msg = format!(
+        "swyh-rs {} is available (you have {APP_VERSION})",
+        latest.tag_name
+    );
+    ui_log(&format!("{msg}: {}", latest.html_url));
+    for release in &newer {
+        for change in config_changes(release) {
+            ui_log(&format!("  {}: {change}", release.tag_name));
+        }
+    }
+    notify(NotifyEvent::UpdateAvailable, &msg);
+}
+
+/// check for a newer release in the background, if enabled
+pub fn start_update_check() {
+    if !CONFIG.read().check_updates {
+        return;
+    }
+    let _ = thread::Builder::new()
+        .name("update_check".into())
+        .spawn(check_updates);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newer_releases() {
+        assert_eq!(version_numbers("v1.12.4-beta"), [1, 12, 4]);
+        assert!(version_numbers("1.12.10") > version_numbers("1.12.9"));
+        let releases: Vec<Release> = serde_json::from_str(
+            r#"[
+                {"tag_name": "1.13.0", "body": "- new feature\r\n- BREAKING: the `hooks` config moved\r\n",
+                 "html_url": "https://github.com/dheijl/swyh-rs/releases/tag/1.13.0"},
+                {"tag_name": "1.14.0-rc1", "prerelease": true},
+                {"tag_name": "1.12.5", "body": "* renamed config option `foo` to `bar`"},
+                {"tag_name": "1.12.4"},
+                {"tag_name": "1.12.3"}
+            ]"#,
+        )
+        .unwrap();
+        let newer = newer_releases(&releases, "1.12.4");
+        let tags: Vec<&str> = newer.iter().map(|r| r.tag_name.as_str()).collect();
+        assert_eq!(tags, ["1.13.0", "1.12.5"]);
+        assert_eq!(
+            config_changes(&newer[0]),
+            ["BREAKING: the `hooks` config moved"]
+        );
+        assert_eq!(
+            config_changes(&newer[1]),
+            ["renamed config option `foo` to `bar`"]
+        );
+        assert!(newer_releases(&releases, "1.13.0").is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+//! Volume lock groups: a set of renderers, e.g. the speakers of an open-plan living room
+//! and kitchen, whose volumes keep their offsets when the volume slider of one of them is
+//! moved in the GUI, while the group is locked.
+//!
+//! The groups are managed with the "Volume lock..." entry of the renderer menu and saved
+//! in the `volume_locks` of the config file. A renderer can be in more than one group. A
+//! volume that reaches 0 or 100 stays there.
+
+use crate::utils::configuration::VolumeLock;
+
+/// a change of the volume lock groups of a renderer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockChange {
+    /// join the group, it is created (locked) if it doesn't exist
+    Join(String),
+    /// leave the group, it is removed when it's empty
+    Leave(String),
+    /// switch the lock of the group on or off
+    Toggle(String),
+}
+
+/// the changes that can be made to the groups of a renderer, with their menu labels
+#[must_use]
+pub fn lock_choices(locks: &[VolumeLock], dev_name: &str) -> Vec<(String, LockChange)> {
+    let mut choices = Vec::new();
+    for lock in locks {
+        if lock.renderers.iter().any(|r| r == dev_name) {
+            let action = if lock.locked { "Unlock" } else { "Lock" };
+            choices.push((
+                format!("{action} {} ({})", lock.name, lock.renderers.join(", ")),
+                LockChange::Toggle(lock.name.clone()),
+            ));
+            choices.push((
+                format!("Leave {}", lock.name),
+                LockChange::Leave(lock.name.clone()),
+            ));
+        } else {
+            choices.push((
+                format!("Join {} ({})", lock.name, lock.renderers.join(", ")),
+                LockChange::Join(lock.name.clone()),
+            ));
+        }
+    }
+    choices
+}
+
+/// apply a change to the groups of a renderer
+pub fn apply_lock_change(locks: &mut Vec<VolumeLock>, change: &LockChange, dev_name: &str) {
+    match change {
+        LockChange::Join(name) => {
+            if let Some(lock) = locks.iter_mut().find(|l| l.name == *name) {
+                if !lock.renderers.iter().any(|r| r == dev_name) {
+                    lock.renderers.push(dev_name.to_string());
+                }
+            } else {
+                locks.push(VolumeLock {
+                    name: name.clone(),
+                    renderers: vec![dev_name.to_string()],
+                    locked: true,
+                });
+            }
+        }
+        LockChange::Leave(name) => {
+            for lock in locks.iter_mut().filter(|l| l.name == *name) {
+                lock.renderers.retain(|r| r != dev_name);
+            }
+            locks.retain(|l| !l.renderers.is_empty());
+        }
+        LockChange::Toggle(name) => {
+            for lock in locks.iter_mut().filter(|l| l.name == *name) {
+                lock.locked = !lock.locked;
+            }
+        }
+    }
+}
+
+/// the volumes that follow a volume change of a renderer from `old` to `new`: the other
+/// renderers of its locked groups keep their offset, limited to 0-100
+#[must_use]
+pub fn locked_volumes(
+    locks: &[VolumeLock],
+    dev_name: &str,
+    old: i32,
+    new: i32,
+    volumes: &[(String, i32)],
+) -> Vec<(String, i32)> {
+    let delta = new - old;
+    if delta == 0 {
+        return Vec::new();
+    }
+    let mut followers: Vec<(String, i32)> = Vec::new();
+    for lock in locks
+        .iter()
+        .filter(|l| l.locked && l.renderers.iter().any(|r| r == dev_name))
+    {
+        for member in lock.renderers.iter().filter(|r| *r != dev_name) {
+            if followers.iter().any(|(name, _)| name == member) {
+                continue;
+            }
+            if let Some((_, vol)) = volumes.iter().find(|(name, _)| name == member) {
+                followers.push((member.clone(), (vol + delta).clamp(0, 100)));
+            }
+        }
+    }
+    followers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_locks() {
+        let mut locks = Vec::new();
+        apply_lock_change(
+            &mut locks,
+            &LockChange::Join("Downstairs".into()),
+            "Kitchen",
+        );
+        apply_lock_change(&mut locks, &LockChange::Join("Downstairs".into()), "Living");
+        apply_lock_change(&mut locks, &LockChange::Join("Downstairs".into()), "Living");
+        assert_eq!(locks.len(), 1);
+        assert!(locks[0].locked);
+        assert_eq!(locks[0].renderers, ["Kitchen", "Living"]);
+        let volumes = [
+            ("Kitchen".to_string(), 30),
+            ("Living".to_string(), 95),
+            ("Attic".to_string(), 10),
+        ];
+        // the offset is kept, up to 100
+        assert_eq!(
+            locked_volumes(&locks, "Kitchen", 30, 40, &volumes),
+            [("Living".to_string(), 100)]
+        );
+        assert_eq!(
+            locked_volumes(&locks, "Living", 95, 85, &volumes),
+            [("Kitchen".to_string(), 20)]
+        );
+        assert!(locked_volumes(&locks, "Attic", 10, 20, &volumes).is_empty());
+        // unlocked groups don't follow
+        apply_lock_change(
+            &mut locks,
+            &LockChange::Toggle("Downstairs".into()),
+            "Kitchen",
+        );
+        assert!(locked_volumes(&locks, "Kitchen", 30, 40, &volumes).is_empty());
+        let choices = lock_choices(&locks, "Attic");
+        assert_eq!(choices[0].0, "Join Downstairs (Kitchen, Living)");
+        assert_eq!(
+            lock_choices(&locks, "Kitchen")[0].0,
+            "Lock Downstairs (Kitchen, Living)"
+        );
+        // an empty group is removed
+        apply_lock_change(
+            &mut locks,
+            &LockChange::Leave("Downstairs".into()),
+            "Kitchen",
+        );
+        apply_lock_change(
+            &mut locks,
+            &LockChange::Leave("Downstairs".into()),
+            "Living",
+        );
+        assert!(locks.is_empty());
+    }
+}
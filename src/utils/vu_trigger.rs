@@ -0,0 +1,209 @@
+//! Start the configured renderers when audio activity is detected on the capture source,
+//! and stop them after a prolonged silence, so that streaming just happens when you
+//! start playing music.
+//!
+//! The `wave_reader` feeds the trigger with the captured samples, and the RMS level is
+//! computed per block of 100 msec. The renderers are started when the level stays above
+//! the threshold for `start_secs`, and stopped when it stays below it for `stop_secs`.
+//! Some audio sources deliver no samples at all when nothing is playing, so a watchdog
+//! thread also stops the renderers when no audio arrives.
+//! Only the renderers that were started by the trigger are stopped, renderers that were
+//! already playing keep playing.
+
+use parking_lot::Mutex;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::ui_logger::ui_log;
+use crate::{
+    enums::messages::MessageType,
+    globals::statics::{CONFIG, MSGCHANNEL},
+    openhome::rendercontrol::WavData,
+    utils::configuration::VuTriggerSettings,
+};
+
+/// the defaults of the trigger settings
+const DEFAULT_THRESHOLD_DB: f32 = -50.0;
+const DEFAULT_START_SECS: f32 = 2.0;
+const DEFAULT_STOP_SECS: u32 = 300;
+/// the length of the RMS blocks
+const BLOCK_MSEC: u32 = 100;
+
+/// the trigger, if enabled
+static VU_TRIGGER: Mutex<Option<VuTrigger>> = Mutex::new(None);
+
+/// start or stop the renderers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VuEvent {
+    Start,
+    Stop,
+}
+
+/// the audio activity detection
+#[derive(Debug, Clone)]
+pub struct VuTrigger {
+    block_size: usize,
+    threshold: f64,
+    start_after: Duration,
+    stop_after: Duration,
+    sum: f64,
+    count: usize,
+    loud_since: Option<Instant>,
+    last_loud: Instant,
+    active: bool,
+}
+
+impl VuTrigger {
+    #[must_use]
+    pub fn new(sample_rate: u32, settings: &VuTriggerSettings, now: Instant) -> VuTrigger {
+        let threshold_db = settings.threshold_db.unwrap_or(DEFAULT_THRESHOLD_DB);
+        VuTrigger {
+            block_size: (sample_rate * BLOCK_MSEC / 1000 * 2) as usize,
+            threshold: 10f64.powf(f64::from(threshold_db) / 20.0),
+            start_after: Duration::from_secs_f32(
+                settings.start_secs.unwrap_or(DEFAULT_START_SECS).max(0.0),
+            ),
+            stop_after: Duration::from_secs(u64::from(
+                settings.stop_secs.unwrap_or(DEFAULT_STOP_SECS),
+            )),
+            sum: 0.0,
+            count: 0,
+            loud_since: None,
+            last_loud: now,
+            active: false,
+        }
+    }
+
+    /// process the captured (interleaved stereo) samples
+    pub fn process(&mut self, samples: &[f32], now: Instant) -> Option<VuEvent> {
+        for s in samples {
+            self.sum += f64::from(*s) * f64::from(*s);
+            self.count += 1;
+            if self.count < self.block_size {
+                continue;
+            }
+            let rms = (self.sum / self.count as f64).sqrt();
+            self.sum = 0.0;
+            self.count = 0;
+            if rms >= self.threshold {
+                self.loud_since.get_or_insert(now);
+                self.last_loud = now;
+            } else {
+                self.loud_since = None;
+            }
+        }
+        if !self.active
+            && self
+                .loud_since
+                .is_some_and(|t| now.duration_since(t) >= self.start_after)
+        {
+            self.active = true;
+            return Some(VuEvent::Start);
+        }
+        self.idle(now)
+    }
+
+    /// stop after a prolonged silence, also when no samples arrive
+    pub fn idle(&mut self, now: Instant) -> Option<VuEvent> {
+        if self.active && now.duration_since(self.last_loud) >= self.stop_after {
+            self.active = false;
+            self.loud_since = None;
+            return Some(VuEvent::Stop);
+        }
+        None
+    }
+}
+
+/// the names of the renderers to start, empty means the last used or active renderer(s)
+#[must_use]
+pub fn vu_trigger_renderers() -> Vec<String> {
+    CONFIG
+        .read()
+        .vu_trigger
+        .as_ref()
+        .map(|s| s.renderers.clone())
+        .unwrap_or_default()
+}
+
+/// let the event loop start the renderers, or stop the ones it started
+fn trigger(event: VuEvent) {
+    ui_log(match event {
+        VuEvent::Start => "Audio activity detected: starting the renderers",
+        VuEvent::Stop => "Prolonged silence: stopping the renderers started on audio activity",
+    });
+    let _ = MSGCHANNEL
+        .read()
+        .0
+        .send(MessageType::VuTriggerMessage(event));
+}
+
+/// feed the captured samples to the trigger, called by the `wave_reader`
+pub fn vu_trigger_samples(samples: &[f32]) {
+    let event = VU_TRIGGER
+        .lock()
+        .as_mut()
+        .and_then(|t| t.process(samples, Instant::now()));
+    if let Some(event) = event {
+        trigger(event);
+    }
+}
+
+/// Enable the trigger if it is configured, and start its watchdog thread.
+pub fn start_vu_trigger(wd: &WavData) {
+    let Some(settings) = CONFIG.read().vu_trigger.clone() else {
+        return;
+    };
+    let vu_trigger = VuTrigger::new(wd.sample_rate.0, &settings, Instant::now());
+    ui_log(&format!(
+        "Starting renderers on audio above {:.0} dB for {:.1} s, stopping after {} s of silence",
+        settings.threshold_db.unwrap_or(DEFAULT_THRESHOLD_DB),
+        vu_trigger.start_after.as_secs_f32(),
+        vu_trigger.stop_after.as_secs()
+    ));
+    *VU_TRIGGER.lock() = Some(vu_trigger);
+    let _ = thread::Builder::new()
+        .name("vu_trigger".into())
+        .stack_size(4 * 1024 * 1024)
+        .spawn(|| loop {
+            thread::sleep(Duration::from_secs(1));
+            let event = VU_TRIGGER
+                .lock()
+                .as_mut()
+                .and_then(|t| t.idle(Instant::now()));
+            if let Some(event) = event {
+                trigger(event);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vu_trigger() {
+        let settings = VuTriggerSettings {
+            renderers: Vec::new(),
+            threshold_db: Some(-40.0),
+            start_secs: Some(2.0),
+            stop_secs: Some(10),
+        };
+        let t0 = Instant::now();
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+        // 10 samples per block
+        let mut vu = VuTrigger::new(50, &settings, t0);
+        let loud = vec![0.1f32; 10];
+        let quiet = vec![0.001f32; 10];
+        assert_eq!(vu.process(&quiet, at(0)), None);
+        assert_eq!(vu.process(&loud, at(1)), None);
+        assert_eq!(vu.process(&loud, at(2)), None);
+        assert_eq!(vu.process(&loud, at(3)), Some(VuEvent::Start));
+        assert_eq!(vu.process(&loud, at(4)), None);
+        assert_eq!(vu.process(&quiet, at(10)), None);
+        // no samples at all
+        assert_eq!(vu.idle(at(14)), Some(VuEvent::Stop));
+        assert_eq!(vu.idle(at(30)), None);
+    }
+}